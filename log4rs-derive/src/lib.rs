@@ -0,0 +1,117 @@
+//! A derive macro generating log4rs config deserializers.
+//!
+//! This crate is re-exported by log4rs as `log4rs::file::Deserializer` when
+//! its `derive` feature is enabled, and should be used through it.
+#![doc(html_root_url="https://docs.rs/log4rs-derive/0.1.0")]
+#![warn(missing_docs)]
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::{DeriveInput, Error, Ident, LitStr, Path};
+
+/// Generates the deserializer of a custom component from its config struct.
+///
+/// See `log4rs::file::Deserializer` for details.
+#[proc_macro_derive(Deserializer, attributes(log4rs))]
+pub fn derive_deserializer(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct Options {
+    component: Path,
+    deserializer: Ident,
+    build: Option<Path>,
+}
+
+fn options(input: &DeriveInput) -> Result<Options, Error> {
+    let mut component = None;
+    let mut deserializer = None;
+    let mut build = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("log4rs")) {
+        attr.parse_nested_meta(|meta| {
+            let value = meta.value()?.parse::<LitStr>()?;
+            if meta.path.is_ident("component") {
+                component = Some(value.parse()?);
+            } else if meta.path.is_ident("deserializer") {
+                deserializer = Some(value.parse()?);
+            } else if meta.path.is_ident("build") {
+                build = Some(value.parse()?);
+            } else {
+                return Err(meta.error("expected `component`, `deserializer`, or `build`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let component = component.ok_or_else(|| {
+        Error::new(Span::call_site(),
+                   "missing `#[log4rs(component = \"...\")]` naming the component's trait")
+    })?;
+    // `FooAppenderConfig` is deserialized by `FooAppenderDeserializer`
+    let deserializer = deserializer.unwrap_or_else(|| {
+        let name = input.ident.to_string();
+        let base = name.strip_suffix("Config").unwrap_or(&name);
+        Ident::new(&format!("{}Deserializer", base), input.ident.span())
+    });
+
+    Ok(Options {
+        component: component,
+        deserializer: deserializer,
+        build: build,
+    })
+}
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics,
+                                      "config structs with generic parameters aren't supported"));
+    }
+    let options = options(input)?;
+
+    let config = &input.ident;
+    let vis = &input.vis;
+    let component = &options.component;
+    let deserializer = &options.deserializer;
+    let build = match options.build {
+        Some(ref build) => quote!(#build),
+        None => quote!(#config::build),
+    };
+    let doc = format!("A deserializer for the `{}` configuration.", config);
+
+    Ok(quote! {
+        #[doc = #doc]
+        #vis struct #deserializer;
+
+        // the parentheses keep `dyn` a keyword before a `::` path in the
+        // 2015 edition
+        #[allow(unused_parens)]
+        impl ::log4rs::file::Deserialize for #deserializer {
+            type Trait = dyn (#component);
+
+            type Config = #config;
+
+            fn deserialize(&self,
+                           config: #config,
+                           deserializers: &::log4rs::file::Deserializers)
+                           -> ::std::result::Result<
+                               ::std::boxed::Box<dyn (#component)>,
+                               ::std::boxed::Box<dyn (::std::error::Error)
+                                                 + ::std::marker::Sync
+                                                 + ::std::marker::Send>> {
+                let component = #build(config, deserializers)?;
+                ::std::result::Result::Ok(::std::boxed::Box::new(component))
+            }
+        }
+    })
+}
@@ -0,0 +1,211 @@
+//! The audit appender.
+//!
+//! The appender writes each log event to a file and syncs it to disk before
+//! returning, so an event which was appended successfully survives a crash
+//! of the process or the machine. Combined with `Handle::audit` or the
+//! `audit!` macro, which return the errors of the appenders instead of
+//! handling them by the configured error policy, this gives events like
+//! financial audit records guaranteed delivery.
+//!
+//! Requires the `audit_appender` feature.
+
+use antidote::Mutex;
+use log::Record;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use append::Append;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The audit appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditAppenderConfig {
+    path: String,
+    encoder: Option<EncoderConfig>,
+}
+
+/// An appender which writes each log event to a file and syncs it to disk
+/// before returning.
+pub struct AuditAppender {
+    path: PathBuf,
+    file: Mutex<File>,
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for AuditAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("AuditAppender")
+            .field("path", &self.path)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for AuditAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        // encoded up front so that a failed encoding doesn't leave a partial
+        // event in the file
+        let mut buf = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut buf), record)?;
+
+        let mut file = self.file.lock();
+        file.write_all(&buf)?;
+        file.sync_data()?;
+        self.bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+
+    fn reopen(&self) -> Result<(), Box<Error + Sync + Send>> {
+        let mut file = self.file.lock();
+        *file = open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl AuditAppender {
+    /// Creates a new `AuditAppender` builder.
+    pub fn builder() -> AuditAppenderBuilder {
+        AuditAppenderBuilder { encoder: None }
+    }
+}
+
+/// A builder for `AuditAppender`s.
+pub struct AuditAppenderBuilder {
+    encoder: Option<Box<Encode>>,
+}
+
+impl AuditAppenderBuilder {
+    /// Sets the output encoder for the `AuditAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> AuditAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `AuditAppenderBuilder`, producing an `AuditAppender`
+    /// which appends to the file at the specified path.
+    ///
+    /// The file and any missing parent directories are created.
+    pub fn build<P: AsRef<Path>>(self, path: P) -> io::Result<AuditAppender> {
+        let path = path.as_ref().to_owned();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(AuditAppender {
+            file: Mutex::new(open(&path)?),
+            path: path,
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
+        })
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    let existed = path.exists();
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+    if !existed {
+        sync_parent(path)?;
+    }
+    Ok(file)
+}
+
+// makes the creation of a file durable
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if parent != Path::new("") => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// A deserializer for the `AuditAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: audit
+///
+/// # The path of the log file. Required.
+/// path: log/audit.log
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern`.
+/// encoder:
+///   kind: json
+/// ```
+#[cfg(feature = "file")]
+pub struct AuditAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for AuditAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = AuditAppenderConfig;
+
+    fn deserialize(&self,
+                   config: AuditAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = AuditAppender::builder();
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build(deserializers.resolve_path(config.path))?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn append() {
+        let dir = TempDir::new("audit").unwrap();
+        let path = dir.path().join("audit/audit.log");
+        let appender = AuditAppender::builder()
+            .encoder(Box::new(PatternEncoder::new("{l} {m}{n}")))
+            .build(&path)
+            .unwrap();
+        appender.append(&Record::builder()
+                .level(Level::Info)
+                .args(format_args!("payment settled"))
+                .build())
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "INFO payment settled\n");
+        assert_eq!(appender.bytes_written(), Some(21));
+    }
+}
@@ -0,0 +1,198 @@
+//! Background batching shared by appenders which ship events in bulk.
+
+use log::Level;
+use std::error::Error;
+use std::io;
+use std::mem;
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use append::queue::{Bound, Queue};
+
+/// Collects items on a background thread and hands them to a flush function
+/// once `max_size` items have accumulated or `max_delay` has passed since the
+/// first item of the batch arrived.
+///
+/// Items wait for the background thread in a queue with the specified bound.
+/// Items which are still pending when the `Batcher` is dropped are flushed
+/// before the drop completes.
+pub struct Batcher<T: Send + 'static> {
+    queue: Arc<Queue<Message<T>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+enum Message<T> {
+    Item(T),
+    // flush the pending items, then reply
+    Flush(mpsc::Sender<()>),
+}
+
+impl<T: Send + 'static> Batcher<T> {
+    /// Starts the background thread, failing if it can't be spawned or the
+    /// bound has a capacity of 0.
+    pub fn new<F>(name: &str,
+                  max_size: usize,
+                  max_delay: Duration,
+                  bound: Bound,
+                  flush: F)
+                  -> io::Result<Batcher<T>>
+        where F: FnMut(Vec<T>) + Send + 'static
+    {
+        let queue = Arc::new(Queue::new(bound)?);
+        let rx = queue.clone();
+        let thread = thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || run(&rx, max_size, max_delay, flush))?;
+
+        Ok(Batcher {
+            queue: queue,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queues an item for the next batch.
+    ///
+    /// An item dropped because the queue is full isn't an error.
+    pub fn push(&self, level: Level, item: T) -> Result<(), Box<Error + Sync + Send>> {
+        match self.queue.push(level, Message::Item(item)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err("batcher is shut down".into()),
+        }
+    }
+
+    /// Hands the pending items to the flush function, returning once it has
+    /// processed them.
+    pub fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        let (tx, rx) = mpsc::channel();
+        self.queue.push_control(Message::Flush(tx)).map_err(|_| "batcher is shut down")?;
+        rx.recv().map_err(|_| "batching thread has exited".into())
+    }
+
+    /// Returns the number of items dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped()
+    }
+}
+
+impl<T: Send + 'static> Drop for Batcher<T> {
+    fn drop(&mut self) {
+        self.queue.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run<T, F>(rx: &Queue<Message<T>>, max_size: usize, max_delay: Duration, mut flush: F)
+    where F: FnMut(Vec<T>)
+{
+    let mut batch = vec![];
+    let mut deadline = None;
+
+    loop {
+        let item = rx.pop(deadline);
+
+        match item {
+            Ok(Message::Item(item)) => {
+                if batch.is_empty() {
+                    deadline = Some(Instant::now() + max_delay);
+                }
+                batch.push(item);
+                if batch.len() >= max_size {
+                    flush(mem::take(&mut batch));
+                    deadline = None;
+                }
+            }
+            Ok(Message::Flush(done)) => {
+                if !batch.is_empty() {
+                    flush(mem::take(&mut batch));
+                }
+                deadline = None;
+                let _ = done.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                flush(mem::take(&mut batch));
+                deadline = None;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                if !batch.is_empty() {
+                    flush(batch);
+                }
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use config::Backpressure;
+
+    use super::*;
+
+    #[test]
+    fn size_and_drop() {
+        let batches = Arc::new(Mutex::new(vec![]));
+        let batches2 = batches.clone();
+        let batcher = Batcher::new("test",
+                                   2,
+                                   Duration::from_secs(60),
+                                   Bound::default(),
+                                   move |b| batches2.lock().unwrap().push(b))
+            .unwrap();
+        for i in 0..5 {
+            batcher.push(Level::Info, i).unwrap();
+        }
+        drop(batcher);
+
+        assert_eq!(*batches.lock().unwrap(), vec![vec![0, 1], vec![2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn delay() {
+        let (tx, rx) = mpsc::channel();
+        let batcher = Batcher::new("test",
+                                   100,
+                                   Duration::from_millis(10),
+                                   Bound::default(),
+                                   move |b| tx.send(b).unwrap())
+            .unwrap();
+        batcher.push(Level::Info, 1).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn flush() {
+        let batches = Arc::new(Mutex::new(vec![]));
+        let batches2 = batches.clone();
+        let batcher = Batcher::new("test",
+                                   100,
+                                   Duration::from_secs(60),
+                                   Bound::default(),
+                                   move |b| batches2.lock().unwrap().push(b))
+            .unwrap();
+        batcher.flush().unwrap();
+        batcher.push(Level::Info, 1).unwrap();
+        batcher.push(Level::Info, 2).unwrap();
+        batcher.flush().unwrap();
+
+        assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        // every push would block forever
+        let bound = Bound {
+            capacity: Some(0),
+            backpressure: Some(Backpressure::Block),
+        };
+        match Batcher::new("test", 100, Duration::from_secs(60), bound, |_: Vec<u32>| {}) {
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => {}
+            _ => panic!("expected an error"),
+        }
+    }
+}
@@ -0,0 +1,702 @@
+//! The ClickHouse appender.
+//!
+//! Requires the `clickhouse_appender` feature. Connecting to `https://` URLs
+//! additionally requires the `http_tls` feature.
+
+use chrono::NaiveDateTime;
+use log::Record;
+use log_mdc;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use append::Append;
+use append::batch::Batcher;
+use append::http::{self, Client};
+use append::queue::Bound;
+use config::Backpressure;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The ClickHouse appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClickHouseAppenderConfig {
+    url: String,
+    table: String,
+    database: Option<String>,
+    user: Option<String>,
+    #[serde(deserialize_with = "::priv_serde::de_secret_opt", default)]
+    password: Option<String>,
+    format: Option<ConfigFormat>,
+    columns: Vec<ColumnConfig>,
+    batch_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    batch_timeout: Option<Duration>,
+    queue_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_backpressure", default)]
+    backpressure: Option<Backpressure>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    timeout: Option<Duration>,
+    max_retries: Option<u32>,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+enum ConfigFormat {
+    #[serde(rename = "row_binary")]
+    RowBinary,
+    #[serde(rename = "json_each_row")]
+    JsonEachRow,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnConfig {
+    name: String,
+    field: String,
+}
+
+/// The format rows are inserted in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// ClickHouse's native `RowBinary` format.
+    ///
+    /// Column types must exactly match those documented on `Field`.
+    RowBinary,
+    /// One JSON object per row.
+    JsonEachRow,
+}
+
+impl Format {
+    fn name(&self) -> &'static str {
+        match *self {
+            Format::RowBinary => "RowBinary",
+            Format::JsonEachRow => "JSONEachRow",
+        }
+    }
+}
+
+/// A log event field which can be stored in a column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    /// The time of the event, as a `DateTime64(9)` in UTC.
+    Time,
+    /// The event's level, as a `String`.
+    Level,
+    /// The event's target, as a `String`.
+    Target,
+    /// The event's module path, as a `String`.
+    ModulePath,
+    /// The source file which produced the event, as a `String`.
+    File,
+    /// The source line which produced the event, as a `UInt32`.
+    Line,
+    /// The event's message, as a `String`.
+    Message,
+    /// The name of the thread which produced the event, as a `String`.
+    Thread,
+    /// The value of an MDC key, as a `String`. Missing keys are stored as
+    /// the empty string.
+    Mdc(String),
+}
+
+impl Field {
+    /// Parses a field from its configuration name.
+    ///
+    /// The names are `time`, `level`, `target`, `module_path`, `file`,
+    /// `line`, `message`, `thread`, and `mdc.<key>`.
+    pub fn parse(name: &str) -> Result<Field, Box<Error + Sync + Send>> {
+        let field = match name {
+            "time" => Field::Time,
+            "level" => Field::Level,
+            "target" => Field::Target,
+            "module_path" => Field::ModulePath,
+            "file" => Field::File,
+            "line" => Field::Line,
+            "message" => Field::Message,
+            "thread" => Field::Thread,
+            _ if name.starts_with("mdc.") => Field::Mdc(name[4..].to_owned()),
+            _ => return Err(format!("unknown field `{}`", name).into()),
+        };
+        Ok(field)
+    }
+
+    fn value(&self, record: &Record, timestamp: u64) -> Value {
+        match *self {
+            Field::Time => Value::Time(timestamp),
+            Field::Level => Value::String(record.level().to_string()),
+            Field::Target => Value::String(record.target().to_owned()),
+            Field::ModulePath => Value::String(record.module_path().unwrap_or("").to_owned()),
+            Field::File => Value::String(record.file().unwrap_or("").to_owned()),
+            Field::Line => Value::UInt32(record.line().unwrap_or(0)),
+            Field::Message => Value::String(record.args().to_string()),
+            Field::Thread => {
+                Value::String(thread::current().name().unwrap_or("<unnamed>").to_owned())
+            }
+            Field::Mdc(ref key) => {
+                Value::String(log_mdc::get(key, |v| v.unwrap_or("").to_owned()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Time(u64),
+    UInt32(u32),
+    String(String),
+}
+
+impl Value {
+    fn write_binary(&self, buf: &mut Vec<u8>) {
+        match *self {
+            Value::Time(nanos) => write_le(buf, nanos, 8),
+            Value::UInt32(v) => write_le(buf, v as u64, 4),
+            Value::String(ref s) => {
+                let mut len = s.len() as u64;
+                loop {
+                    let byte = (len & 0x7f) as u8;
+                    len >>= 7;
+                    if len == 0 {
+                        buf.push(byte);
+                        break;
+                    }
+                    buf.push(byte | 0x80);
+                }
+                buf.extend_from_slice(s.as_bytes());
+            }
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        match *self {
+            Value::Time(nanos) => {
+                let secs = (nanos / 1_000_000_000) as i64;
+                let nanos = (nanos % 1_000_000_000) as u32;
+                let time = NaiveDateTime::from_timestamp(secs, nanos);
+                serializer.collect_str(&time.format("%Y-%m-%d %H:%M:%S%.9f"))
+            }
+            Value::UInt32(v) => serializer.serialize_u32(v),
+            Value::String(ref s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+fn write_le(buf: &mut Vec<u8>, v: u64, len: usize) {
+    for i in 0..len {
+        buf.push((v >> (i * 8)) as u8);
+    }
+}
+
+struct Row<'a> {
+    columns: &'a [(String, Field)],
+    values: &'a [Value],
+}
+
+impl<'a> Serialize for Row<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut map = serializer.serialize_map(Some(self.values.len()))?;
+        for (column, value) in self.columns.iter().zip(self.values) {
+            map.serialize_entry(&column.0, value)?;
+        }
+        map.end()
+    }
+}
+
+struct Inserter {
+    url: String,
+    headers: Vec<(String, String)>,
+    format: Format,
+    columns: Vec<(String, Field)>,
+    client: Client,
+}
+
+impl Inserter {
+    fn body(&self, rows: &[Vec<Value>]) -> Vec<u8> {
+        let mut body = vec![];
+        for values in rows {
+            match self.format {
+                Format::RowBinary => {
+                    for value in values {
+                        value.write_binary(&mut body);
+                    }
+                }
+                Format::JsonEachRow => {
+                    let row = Row {
+                        columns: &self.columns,
+                        values: values,
+                    };
+                    serde_json::to_writer(&mut body, &row).expect("serializing a row failed");
+                    body.push(b'\n');
+                }
+            }
+        }
+        body
+    }
+
+    fn insert(&self, rows: &[Vec<Value>]) -> Result<u64, Box<Error + Sync + Send>> {
+        let headers = self.headers.iter().map(|h| (&*h.0, &*h.1)).collect::<Vec<_>>();
+        let body = self.body(rows);
+        self.client.post(&self.url, &headers, &body)?;
+        Ok(body.len() as u64)
+    }
+}
+
+/// An appender which inserts log events as rows into a ClickHouse table over
+/// its HTTP interface.
+///
+/// Rows are batched on a background thread. Each configured column is filled
+/// from a field of the log event; see `Field` for the column types each field
+/// is stored as. Inserts which are rejected with `429 Too Many Requests` or a
+/// server error are retried with exponential backoff.
+pub struct ClickHouseAppender {
+    table: String,
+    columns: Vec<Field>,
+    batcher: Batcher<Vec<Value>>,
+    bytes: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for ClickHouseAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ClickHouseAppender")
+            .field("table", &self.table)
+            .field("columns", &self.columns)
+            .finish()
+    }
+}
+
+impl Append for ClickHouseAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let timestamp = timestamp();
+        let row = self.columns.iter().map(|f| f.value(record, timestamp)).collect();
+        self.batcher.push(record.level(), row)
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.batcher.flush()
+    }
+}
+
+fn timestamp() -> u64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_secs() * 1_000_000_000 + since_epoch.subsec_nanos() as u64
+}
+
+impl ClickHouseAppender {
+    /// Creates a new `ClickHouseAppender` builder.
+    pub fn builder() -> ClickHouseAppenderBuilder {
+        ClickHouseAppenderBuilder {
+            database: None,
+            user: None,
+            password: None,
+            format: Format::RowBinary,
+            columns: vec![],
+            batch_size: 1000,
+            batch_timeout: Duration::from_secs(1),
+            queue_size: None,
+            backpressure: None,
+            timeout: Duration::from_secs(10),
+            max_retries: 5,
+        }
+    }
+
+    /// Returns the number of events which were dropped because the queue of
+    /// events waiting to be batched was full.
+    pub fn dropped(&self) -> u64 {
+        self.batcher.dropped()
+    }
+}
+
+/// A builder for `ClickHouseAppender`s.
+pub struct ClickHouseAppenderBuilder {
+    database: Option<String>,
+    user: Option<String>,
+    password: Option<String>,
+    format: Format,
+    columns: Vec<(String, Field)>,
+    batch_size: usize,
+    batch_timeout: Duration,
+    queue_size: Option<usize>,
+    backpressure: Option<Backpressure>,
+    timeout: Duration,
+    max_retries: u32,
+}
+
+impl ClickHouseAppenderBuilder {
+    /// Sets the database containing the table.
+    ///
+    /// Defaults to the user's default database.
+    pub fn database(mut self, database: &str) -> ClickHouseAppenderBuilder {
+        self.database = Some(database.to_owned());
+        self
+    }
+
+    /// Sets the user to authenticate as.
+    pub fn user(mut self, user: &str) -> ClickHouseAppenderBuilder {
+        self.user = Some(user.to_owned());
+        self
+    }
+
+    /// Sets the password to authenticate with.
+    pub fn password(mut self, password: &str) -> ClickHouseAppenderBuilder {
+        self.password = Some(password.to_owned());
+        self
+    }
+
+    /// Sets the format rows are inserted in.
+    ///
+    /// Defaults to `Format::RowBinary`.
+    pub fn format(mut self, format: Format) -> ClickHouseAppenderBuilder {
+        self.format = format;
+        self
+    }
+
+    /// Adds a column which will be filled from the specified field.
+    pub fn column(mut self, name: &str, field: Field) -> ClickHouseAppenderBuilder {
+        self.columns.push((name.to_owned(), field));
+        self
+    }
+
+    /// Sets the maximum number of rows sent in a single insert.
+    ///
+    /// Defaults to 1000.
+    pub fn batch_size(mut self, batch_size: usize) -> ClickHouseAppenderBuilder {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets the maximum amount of time a row will wait to be inserted.
+    ///
+    /// Defaults to 1 second.
+    pub fn batch_timeout(mut self, batch_timeout: Duration) -> ClickHouseAppenderBuilder {
+        self.batch_timeout = batch_timeout;
+        self
+    }
+
+    /// Sets the maximum number of events which may be waiting to be batched,
+    /// after which the backpressure policy applies.
+    ///
+    /// Defaults to no limit.
+    pub fn queue_size(mut self, queue_size: usize) -> ClickHouseAppenderBuilder {
+        self.queue_size = Some(queue_size);
+        self
+    }
+
+    /// Sets the policy applied when the queue of events is full.
+    ///
+    /// Defaults to the policy of the configuration.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> ClickHouseAppenderBuilder {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    /// Sets the timeout for each insert request.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> ClickHouseAppenderBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of times a failed insert is retried before its rows
+    /// are dropped.
+    ///
+    /// Defaults to 5.
+    pub fn max_retries(mut self, max_retries: u32) -> ClickHouseAppenderBuilder {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Consumes the `ClickHouseAppenderBuilder`, producing a
+    /// `ClickHouseAppender` which inserts into the specified table of the
+    /// server at the specified URL.
+    ///
+    /// The table and column names are quoted as identifiers, so they are used
+    /// verbatim.
+    pub fn build(self,
+                 url: &str,
+                 table: &str)
+                 -> Result<ClickHouseAppender, Box<Error + Sync + Send>> {
+        if self.columns.is_empty() {
+            return Err("no columns specified".into());
+        }
+        if self.batch_size == 0 {
+            return Err("batch_size must be positive".into());
+        }
+
+        let query = insert_query(table, &self.columns, self.format);
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let insert_url = format!("{}{}query={}", url, separator, http::encode_query(&query));
+
+        let mut headers = vec![];
+        if let Some(database) = self.database {
+            headers.push(("X-ClickHouse-Database".to_owned(), database));
+        }
+        if let Some(user) = self.user {
+            headers.push(("X-ClickHouse-User".to_owned(), user));
+        }
+        if let Some(password) = self.password {
+            headers.push(("X-ClickHouse-Key".to_owned(), password));
+        }
+
+        let fields = self.columns.iter().map(|c| c.1.clone()).collect();
+        let inserter = Inserter {
+            client: Client::new(url, self.timeout, self.max_retries)?,
+            url: insert_url,
+            headers: headers,
+            format: self.format,
+            columns: self.columns,
+        };
+        let bytes = Arc::new(AtomicU64::new(0));
+        let bytes2 = bytes.clone();
+        let batcher = Batcher::new("log4rs-clickhouse",
+                                   self.batch_size,
+                                   self.batch_timeout,
+                                   Bound {
+                                       capacity: self.queue_size,
+                                       backpressure: self.backpressure,
+                                   },
+                                   move |rows: Vec<Vec<Value>>| {
+            match inserter.insert(&rows) {
+                Ok(len) => {
+                    bytes2.fetch_add(len, Ordering::Relaxed);
+                }
+                Err(e) => ::handle_error(&*e),
+            }
+        })?;
+
+        Ok(ClickHouseAppender {
+            table: table.to_owned(),
+            columns: fields,
+            batcher: batcher,
+            bytes: bytes,
+        })
+    }
+}
+
+fn insert_query(table: &str, columns: &[(String, Field)], format: Format) -> String {
+    let names = columns.iter().map(|c| identifier(&c.0)).collect::<Vec<_>>().join(", ");
+    format!("INSERT INTO {} ({}) FORMAT {}", identifier(table), names, format.name())
+}
+
+// Quotes a table or column name, so that it can't change the meaning of the
+// query.
+fn identifier(name: &str) -> String {
+    let mut quoted = String::with_capacity(name.len() + 2);
+    quoted.push('`');
+    for c in name.chars() {
+        if c == '`' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('`');
+    quoted
+}
+
+/// A deserializer for the `ClickHouseAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: clickhouse
+///
+/// # The URL of the ClickHouse HTTP interface. `https://` URLs require the
+/// # `http_tls` feature. Required.
+/// url: http://localhost:8123/
+///
+/// # The table rows are inserted into. Required. The name is quoted, so it
+/// # can't contain a database name; use `database` instead.
+/// table: logs
+///
+/// # The database containing the table. Defaults to the user's default
+/// # database.
+/// database: default
+///
+/// # Credentials to authenticate with. Optional. The password may be given
+/// # as `env:NAME` or `file:PATH` to read it from an environment variable or
+/// # a file instead.
+/// user: default
+/// password: env:CLICKHOUSE_PASSWORD
+///
+/// # The format rows are inserted in. One of `row_binary` or
+/// # `json_each_row`. Defaults to `row_binary`.
+/// format: row_binary
+///
+/// # The columns to insert, and the event field each is filled from. Fields
+/// # are `time` (`DateTime64(9)`), `line` (`UInt32`), and `level`, `target`,
+/// # `module_path`, `file`, `message`, `thread`, and `mdc.<key>` (all
+/// # `String`). Required.
+/// columns:
+///   - name: timestamp
+///     field: time
+///   - name: level
+///     field: level
+///   - name: message
+///     field: message
+///   - name: request_id
+///     field: mdc.request_id
+///
+/// # The maximum number of rows sent in a single insert. Defaults to 1000.
+/// batch_size: 1000
+///
+/// # The maximum amount of time a row will wait to be inserted. Defaults to
+/// # 1 second.
+/// batch_timeout: 1 second
+///
+/// # The maximum number of events which may be waiting to be batched, after
+/// # which `backpressure` applies. Defaults to no limit.
+/// queue_size: 10000
+///
+/// # What happens when the queue is full: `block`, `drop_newest`,
+/// # `drop_oldest`, or `drop_below(level)`. Defaults to the top level
+/// # `backpressure` of the config file.
+/// backpressure: drop_oldest
+///
+/// # The timeout for each insert request. Defaults to 10 seconds.
+/// timeout: 10 seconds
+///
+/// # The number of times a failed insert is retried. Defaults to 5.
+/// max_retries: 5
+/// ```
+#[cfg(feature = "file")]
+pub struct ClickHouseAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for ClickHouseAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = ClickHouseAppenderConfig;
+
+    fn deserialize(&self,
+                   config: ClickHouseAppenderConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = ClickHouseAppender::builder();
+        if let Some(database) = config.database {
+            appender = appender.database(&database);
+        }
+        if let Some(user) = config.user {
+            appender = appender.user(&user);
+        }
+        if let Some(password) = config.password {
+            appender = appender.password(&password);
+        }
+        if let Some(format) = config.format {
+            let format = match format {
+                ConfigFormat::RowBinary => Format::RowBinary,
+                ConfigFormat::JsonEachRow => Format::JsonEachRow,
+            };
+            appender = appender.format(format);
+        }
+        for column in config.columns {
+            appender = appender.column(&column.name, Field::parse(&column.field)?);
+        }
+        if let Some(batch_size) = config.batch_size {
+            appender = appender.batch_size(batch_size);
+        }
+        if let Some(batch_timeout) = config.batch_timeout {
+            appender = appender.batch_timeout(batch_timeout);
+        }
+        if let Some(queue_size) = config.queue_size {
+            appender = appender.queue_size(queue_size);
+        }
+        if let Some(backpressure) = config.backpressure {
+            appender = appender.backpressure(backpressure);
+        }
+        if let Some(timeout) = config.timeout {
+            appender = appender.timeout(timeout);
+        }
+        if let Some(max_retries) = config.max_retries {
+            appender = appender.max_retries(max_retries);
+        }
+        Ok(Box::new(appender.build(&config.url, &config.table)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn inserter(format: Format) -> Inserter {
+        Inserter {
+            url: "http://localhost:8123/".to_owned(),
+            headers: vec![],
+            format: format,
+            columns: vec![("ts".to_owned(), Field::Time),
+                          ("line".to_owned(), Field::Line),
+                          ("msg".to_owned(), Field::Message)],
+            client: Client::new("http://localhost:8123/", Duration::from_secs(1), 0).unwrap(),
+        }
+    }
+
+    fn rows() -> Vec<Vec<Value>> {
+        vec![vec![Value::Time(1_500_000_000_123_456_789),
+                  Value::UInt32(10),
+                  Value::String("hi".to_owned())]]
+    }
+
+    #[test]
+    fn row_binary() {
+        let body = inserter(Format::RowBinary).body(&rows());
+        let mut expected = vec![];
+        write_le(&mut expected, 1_500_000_000_123_456_789, 8);
+        expected.extend_from_slice(&[10, 0, 0, 0, 2, b'h', b'i']);
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn json_each_row() {
+        let body = inserter(Format::JsonEachRow).body(&rows());
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let expected: serde_json::Value =
+            serde_json::from_str(r#"{"ts":"2017-07-14 02:40:00.123456789","line":10,"msg":"hi"}"#)
+                .unwrap();
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn parse_fields() {
+        assert_eq!(Field::parse("mdc.request_id").unwrap(),
+                   Field::Mdc("request_id".to_owned()));
+        assert_eq!(Field::parse("line").unwrap(), Field::Line);
+        assert!(Field::parse("bogus").is_err());
+    }
+
+    #[test]
+    fn quoting() {
+        let columns = [("ts".to_owned(), Field::Time), ("my msg".to_owned(), Field::Message)];
+        assert_eq!(insert_query("logs", &columns, Format::RowBinary),
+                   "INSERT INTO `logs` (`ts`, `my msg`) FORMAT RowBinary");
+        assert_eq!(identifier("t` (a) SELECT 1; --"), "`t\\` (a) SELECT 1; --`");
+        assert_eq!(identifier("a\\b"), "`a\\\\b`");
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(http::encode_query("INSERT INTO t (a) FORMAT RowBinary"),
+                   "INSERT%20INTO%20t%20%28a%29%20FORMAT%20RowBinary");
+    }
+}
@@ -5,9 +5,11 @@
 use std::io::{self, Write};
 use std::fmt;
 use std::error::Error;
-use log::LogRecord;
+use std::sync::atomic::{AtomicU64, Ordering};
+use log::Record;
 
 use append::Append;
+use append::counting::CountingWriter;
 use encode::{self, Encode, Style};
 #[cfg(feature = "file")]
 use encode::EncoderConfig;
@@ -101,6 +103,7 @@ impl<'a> encode::Write for WriterLock<'a> {
 pub struct ConsoleAppender {
     writer: Writer,
     encoder: Box<Encode>,
+    bytes: AtomicU64,
 }
 
 impl fmt::Debug for ConsoleAppender {
@@ -112,12 +115,30 @@ impl fmt::Debug for ConsoleAppender {
 }
 
 impl Append for ConsoleAppender {
-    fn append(&self, record: &LogRecord) -> Result<(), Box<Error + Sync + Send>> {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
         let mut writer = self.writer.lock();
-        self.encoder.encode(&mut writer, record)?;
+        let result = {
+            let mut writer = CountingWriter::new(&mut writer);
+            let result = self.encoder.encode(&mut writer, record);
+            self.bytes.fetch_add(writer.count(), Ordering::Relaxed);
+            result
+        };
+        result?;
         writer.flush()?;
         Ok(())
     }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
 }
 
 impl ConsoleAppender {
@@ -171,6 +192,7 @@ impl ConsoleAppenderBuilder {
         ConsoleAppender {
             writer: writer,
             encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
         }
     }
 }
@@ -0,0 +1,58 @@
+use std::io;
+
+use encode::{self, Style};
+
+/// An `encode::Write`r which counts the bytes written through it.
+pub struct CountingWriter<W> {
+    w: W,
+    count: u64,
+}
+
+impl<W: encode::Write> CountingWriter<W> {
+    pub fn new(w: W) -> CountingWriter<W> {
+        CountingWriter {
+            w: w,
+            count: 0,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<W: encode::Write> io::Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.w.write(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+impl<W: encode::Write> encode::Write for CountingWriter<W> {
+    fn set_style(&mut self, style: &Style) -> io::Result<()> {
+        self.w.set_style(style)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "simple_writer")]
+mod test {
+    use std::io::Write;
+
+    use encode::writer::simple::SimpleWriter;
+    use super::*;
+
+    #[test]
+    fn count() {
+        let mut buf = vec![];
+        let mut w = CountingWriter::new(SimpleWriter(&mut buf));
+        w.write_all(b"hello").unwrap();
+        write!(w, " {}", 1).unwrap();
+        assert_eq!(w.count(), 7);
+    }
+}
@@ -0,0 +1,171 @@
+//! The Windows debug string appender.
+//!
+//! Requires the `debug_string_appender` feature, and is only available on
+//! Windows.
+
+use kernel32;
+use log::Record;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use append::Append;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The debug string appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DebugStringAppenderConfig {
+    encoder: Option<EncoderConfig>,
+}
+
+// A nul terminated UTF-16 string for `OutputDebugStringW`, without interior
+// nuls, which would cut it short.
+fn wide(buf: &[u8]) -> Vec<u16> {
+    let mut wide = String::from_utf8_lossy(buf)
+        .encode_utf16()
+        .filter(|&c| c != 0)
+        .collect::<Vec<u16>>();
+    wide.push(0);
+    wide
+}
+
+/// An appender which passes log events to `OutputDebugStringW`.
+///
+/// The output is shown by an attached debugger, such as Visual Studio, or by
+/// tools like DebugView, which makes this useful for GUI applications without
+/// a console.
+pub struct DebugStringAppender {
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for DebugStringAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("DebugStringAppender")
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for DebugStringAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut buf = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut buf), record)?;
+
+        let wide = wide(&buf);
+        unsafe {
+            kernel32::OutputDebugStringW(wide.as_ptr());
+        }
+        self.bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl DebugStringAppender {
+    /// Creates a new `DebugStringAppender` builder.
+    pub fn builder() -> DebugStringAppenderBuilder {
+        DebugStringAppenderBuilder { encoder: None }
+    }
+}
+
+/// A builder for `DebugStringAppender`s.
+pub struct DebugStringAppenderBuilder {
+    encoder: Option<Box<Encode>>,
+}
+
+impl DebugStringAppenderBuilder {
+    /// Sets the output encoder for the `DebugStringAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> DebugStringAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `DebugStringAppenderBuilder`, producing a
+    /// `DebugStringAppender`.
+    pub fn build(self) -> DebugStringAppender {
+        DebugStringAppender {
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A deserializer for the `DebugStringAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: debug_string
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct DebugStringAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for DebugStringAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = DebugStringAppenderConfig;
+
+    fn deserialize(&self,
+                   config: DebugStringAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = DebugStringAppender::builder();
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn wide_strings() {
+        let expected = "h\u{e9}\u{1f600}!\0".encode_utf16().collect::<Vec<_>>();
+        assert_eq!(wide("h\u{e9}\0\u{1f600}!".as_bytes()), expected);
+        assert_eq!(wide(b"\xff"), [0xfffd, 0]);
+    }
+
+    #[test]
+    fn append() {
+        let appender = DebugStringAppender::builder()
+            .encoder(Box::new(PatternEncoder::new("{l} {m}{n}")))
+            .build();
+        let record = Record::builder()
+            .level(Level::Warn)
+            .args(format_args!("low disk space"))
+            .build();
+        appender.append(&record).unwrap();
+        assert_eq!(appender.bytes_written(), Some("WARN low disk space\r\n".len() as u64));
+    }
+}
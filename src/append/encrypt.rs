@@ -0,0 +1,645 @@
+//! Encryption of log files at rest.
+//!
+//! Log files are encrypted to the X25519 public key of a recipient, so that
+//! they can only be read with the matching private key, which never needs to
+//! be available to the process writing them.
+//!
+//! Encrypted log files are written as a sequence of segments, one per opening
+//! of the file, so appending to an existing file and rolling over to a new
+//! one are both supported. Each segment starts with a header containing a
+//! fresh ephemeral X25519 public key. A key unique to the segment is derived
+//! with HKDF-SHA256 from the Diffie-Hellman shared secret of the ephemeral
+//! and recipient keys, and from the last bytes of the file preceding the
+//! segment, which chains each segment to the one before it. Data is then
+//! written as length-prefixed AES-256-GCM sealed chunks, one per flush, with
+//! nonces derived from a per-segment chunk counter. A segment ends with an
+//! empty final chunk, written when the file is closed, which is marked as
+//! such in its length prefix and authenticated data.
+//!
+//! Reordering, removing, or modifying chunks or segments causes decryption to
+//! fail, and a file cut short is reported as incomplete. Use `decrypt` to
+//! recover the plaintext. A chunk damaged by a crash in the middle of a write
+//! only loses the rest of its segment, as decryption resumes at the next
+//! segment header.
+//!
+//! Requires the `encryption` feature.
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::Path;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const MAGIC: &[u8; 8] = b"L4RSENC\x02";
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+// the number of bytes preceding a segment which it is chained to
+const CHAIN_LEN: usize = 16;
+const KEY_INFO: &[u8] = b"log4rs file key";
+// keeps a chunk length prefix from ever looking like the start of a header
+const MAX_CHUNK_LEN: usize = 64 * 1024 * 1024;
+// set in the length prefix of the final chunk of a segment
+const FINAL: u32 = 1 << 31;
+
+/// The X25519 public key to which log files are encrypted.
+#[derive(Debug, Clone)]
+pub struct EncryptionKey([u8; KEY_LEN]);
+
+impl EncryptionKey {
+    /// Creates a key from 32 raw bytes.
+    pub fn new(key: [u8; KEY_LEN]) -> EncryptionKey {
+        EncryptionKey(key)
+    }
+
+    /// Reads a key from a file.
+    ///
+    /// The file must contain either exactly 32 bytes, or 64 hexadecimal
+    /// digits optionally surrounded by whitespace.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<EncryptionKey> {
+        read_key(path.as_ref()).map(EncryptionKey)
+    }
+
+    /// Returns the key as 64 hexadecimal digits, the format accepted by
+    /// `from_file`.
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.0)
+    }
+}
+
+/// The X25519 private key with which log files are decrypted.
+#[derive(Clone)]
+pub struct DecryptionKey([u8; KEY_LEN]);
+
+impl fmt::Debug for DecryptionKey {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("DecryptionKey(..)")
+    }
+}
+
+impl DecryptionKey {
+    /// Creates a key from 32 raw bytes.
+    pub fn new(key: [u8; KEY_LEN]) -> DecryptionKey {
+        DecryptionKey(key)
+    }
+
+    /// Generates a new random key.
+    pub fn generate() -> io::Result<DecryptionKey> {
+        let mut key = [0; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| io::Error::other("unable to generate a random key"))?;
+        Ok(DecryptionKey(key))
+    }
+
+    /// Reads a key from a file, in the same format as
+    /// `EncryptionKey::from_file`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<DecryptionKey> {
+        read_key(path.as_ref()).map(DecryptionKey)
+    }
+
+    /// Returns the key as 64 hexadecimal digits, the format accepted by
+    /// `from_file`.
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.0)
+    }
+
+    /// Returns the public key to which files are encrypted so that they can
+    /// be decrypted with this key.
+    pub fn encryption_key(&self) -> EncryptionKey {
+        EncryptionKey(PublicKey::from(&StaticSecret::from(self.0)).to_bytes())
+    }
+}
+
+fn read_key(path: &Path) -> io::Result<[u8; KEY_LEN]> {
+    let mut contents = vec![];
+    File::open(path)?.read_to_end(&mut contents)?;
+    parse_key(&contents)
+}
+
+fn parse_key(contents: &[u8]) -> io::Result<[u8; KEY_LEN]> {
+    let mut key = [0; KEY_LEN];
+    if contents.len() == KEY_LEN {
+        key.copy_from_slice(contents);
+        return Ok(key);
+    }
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid encryption key");
+    let hex = String::from_utf8_lossy(contents);
+    let hex = hex.trim();
+    if hex.len() != KEY_LEN * 2 {
+        return Err(invalid());
+    }
+    for (i, byte) in key.iter_mut().enumerate() {
+        let digits = hex.get(i * 2..i * 2 + 2).ok_or_else(invalid)?;
+        *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+fn to_hex(key: &[u8]) -> String {
+    key.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// the last bytes of a file, zero-padded if it is shorter
+fn chain(tail: &[u8]) -> [u8; CHAIN_LEN] {
+    let mut chain = [0; CHAIN_LEN];
+    let len = tail.len().min(CHAIN_LEN);
+    chain[CHAIN_LEN - len..].copy_from_slice(&tail[tail.len() - len..]);
+    chain
+}
+
+fn segment_key(shared: &[u8],
+               ephemeral: &[u8],
+               recipient: &[u8],
+               chain: &[u8; CHAIN_LEN])
+               -> LessSafeKey {
+    let mut salt = [0; KEY_LEN * 2];
+    salt[..KEY_LEN].copy_from_slice(ephemeral);
+    salt[KEY_LEN..].copy_from_slice(recipient);
+    let prk = Salt::new(HKDF_SHA256, &salt).extract(shared);
+    let info = [KEY_INFO, chain];
+    let okm = prk.expand(&info, &AES_256_GCM).expect("valid HKDF output length");
+    LessSafeKey::new(UnboundKey::from(okm))
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[NONCE_LEN - 8..].copy_from_slice(&counter.to_be_bytes());
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// Returns the last bytes of the file at `path`, to which a segment appended
+/// to it is chained, or nothing if the file doesn't exist.
+pub fn file_tail(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(CHAIN_LEN as u64)))?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail)?;
+    Ok(tail)
+}
+
+/// A writer which encrypts everything written to it.
+///
+/// Data is buffered in memory and sealed into a chunk each time the writer is
+/// flushed, so the writer should be flushed at record boundaries. The
+/// segment's final chunk is written when the writer is dropped.
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    key: LessSafeKey,
+    counter: u64,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Creates a new writer starting a new file, immediately writing a
+    /// segment header to `inner`.
+    pub fn new(key: &EncryptionKey, inner: W) -> io::Result<EncryptingWriter<W>> {
+        EncryptingWriter::append(key, &[], inner)
+    }
+
+    /// Like `new`, but for appending to a file which ends with the bytes in
+    /// `tail`, as returned by `file_tail`.
+    pub fn append(key: &EncryptionKey,
+                  tail: &[u8],
+                  mut inner: W)
+                  -> io::Result<EncryptingWriter<W>> {
+        let rng = SystemRandom::new();
+        let ephemeral = EphemeralPrivateKey::generate(&X25519, &rng)
+            .map_err(|_| io::Error::other("unable to generate an ephemeral key"))?;
+        let public = ephemeral.compute_public_key()
+            .map_err(|_| io::Error::other("unable to generate an ephemeral key"))?;
+        let chain = chain(tail);
+        let segment_key = agreement::agree_ephemeral(ephemeral,
+                                                     &UnparsedPublicKey::new(&X25519, &key.0),
+                                                     |shared| {
+                segment_key(shared, public.as_ref(), &key.0, &chain)
+            })
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid encryption key"))?;
+        inner.write_all(MAGIC)?;
+        inner.write_all(public.as_ref())?;
+
+        Ok(EncryptingWriter {
+            inner: inner,
+            key: segment_key,
+            counter: 0,
+            buf: vec![],
+        })
+    }
+
+    fn seal(&mut self) -> io::Result<()> {
+        while !self.buf.is_empty() {
+            let len = self.buf.len().min(MAX_CHUNK_LEN - TAG_LEN);
+            let rest = self.buf.split_off(len);
+            let chunk = mem::replace(&mut self.buf, rest);
+            self.write_chunk(chunk, false)?;
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, mut chunk: Vec<u8>, last: bool) -> io::Result<()> {
+        self.key
+            .seal_in_place_append_tag(nonce(self.counter), Aad::from([last as u8]), &mut chunk)
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        self.counter += 1;
+
+        let prefix = chunk.len() as u32 | if last { FINAL } else { 0 };
+        self.inner.write_all(&prefix.to_le_bytes())?;
+        self.inner.write_all(&chunk)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.seal()?;
+        self.write_chunk(vec![], true)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.seal()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for EncryptingWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match r.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Decrypts an encrypted log file, writing the plaintext to `output`.
+///
+/// Returns the number of plaintext bytes written. An error of kind
+/// `InvalidData` is returned if the input was not encrypted to the key or has
+/// been tampered with, and one of kind `UnexpectedEof` if a segment ends
+/// before its final chunk, as the last segment of a file which is still being
+/// written to does, or one left by a process which exited without closing
+/// the file. Decryption continues past a damaged segment at the next segment
+/// header, and the plaintext of every segment which can be read is written to
+/// `output` before the first such error is returned.
+pub fn decrypt<R, W>(key: &DecryptionKey, input: R, output: W) -> io::Result<u64>
+    where R: Read,
+          W: Write
+{
+    let secret = StaticSecret::from(key.0);
+    let mut decryptor = Decryptor {
+        recipient: PublicKey::from(&secret),
+        secret: secret,
+        input: Input {
+            inner: input,
+            pending: vec![],
+        },
+        output: output,
+        tail: [0; CHAIN_LEN],
+        segment: None,
+        written: 0,
+        error: None,
+    };
+    decryptor.run()?;
+    match decryptor.error {
+        Some(e) => Err(e),
+        None => Ok(decryptor.written),
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+fn incomplete() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete encrypted log file")
+}
+
+// the input of `decrypt`, onto which bytes can be pushed back
+struct Input<R> {
+    inner: R,
+    // bytes to read before those of `inner`
+    pending: Vec<u8>,
+}
+
+impl<R: Read> Input<R> {
+    fn read_full(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n + read_full(&mut self.inner, &mut buf[n..])?)
+    }
+
+    // Skips to the next segment header after the start of a damaged chunk or
+    // header, given the bytes read from its start on, preceded by the
+    // `CHAIN_LEN` bytes read before it. Returns the bytes the header is
+    // chained to, or `None` at the end of the input.
+    fn resync(&mut self, mut read: Vec<u8>) -> io::Result<Option<[u8; CHAIN_LEN]>> {
+        let mut start = CHAIN_LEN + 1;
+        loop {
+            let found = read.get(start..)
+                .and_then(|r| r.windows(MAGIC.len()).position(|w| w == MAGIC));
+            if let Some(i) = found {
+                let mut rest = read.split_off(start + i);
+                rest.append(&mut self.pending);
+                self.pending = rest;
+                return Ok(Some(chain(&read)));
+            }
+
+            // keep the bytes which may start a header, along with those
+            // preceding them
+            let scanned = start.max(read.len().saturating_sub(MAGIC.len() - 1));
+            let skipped = scanned.saturating_sub(CHAIN_LEN);
+            read.drain(..skipped);
+            start = scanned - skipped;
+
+            let mut buf = vec![0; 64 * 1024];
+            let n = self.read_full(&mut buf)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            read.extend_from_slice(&buf[..n]);
+        }
+    }
+}
+
+struct Segment {
+    key: LessSafeKey,
+    // the counter of the next chunk
+    counter: u64,
+    finished: bool,
+}
+
+struct Decryptor<R, W> {
+    secret: StaticSecret,
+    recipient: PublicKey,
+    input: Input<R>,
+    output: W,
+    // the last bytes read, to which the next segment is chained
+    tail: [u8; CHAIN_LEN],
+    segment: Option<Segment>,
+    written: u64,
+    // the first error in the data, after which decryption continues
+    error: Option<io::Error>,
+}
+
+impl<R: Read, W: Write> Decryptor<R, W> {
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let mut read = self.tail.to_vec();
+            let mut prefix = [0; 8];
+            let n = self.input.read_full(&mut prefix)?;
+            read.extend_from_slice(&prefix[..n]);
+            let damaged = match n {
+                0 => break,
+                8 if prefix == *MAGIC => self.header(&mut read)?,
+                8 => self.chunk(prefix, &mut read)?,
+                _ => Some(incomplete()),
+            };
+
+            if let Some(e) = damaged {
+                self.fail(e);
+                self.segment = None;
+                match self.input.resync(read)? {
+                    Some(tail) => self.tail = tail,
+                    None => break,
+                }
+            }
+        }
+
+        self.check_finished();
+        Ok(())
+    }
+
+    fn fail(&mut self, e: io::Error) {
+        if self.error.is_none() {
+            self.error = Some(e);
+        }
+    }
+
+    // a segment which is cut short is an error even if another follows it
+    fn check_finished(&mut self) {
+        if self.segment.as_ref().is_some_and(|s| !s.finished) {
+            self.fail(incomplete());
+        }
+    }
+
+    // reads a segment header following its magic number
+    fn header(&mut self, read: &mut Vec<u8>) -> io::Result<Option<io::Error>> {
+        let mut ephemeral = [0; KEY_LEN];
+        let n = self.input.read_full(&mut ephemeral)?;
+        read.extend_from_slice(&ephemeral[..n]);
+        if n != KEY_LEN {
+            return Ok(Some(incomplete()));
+        }
+
+        let shared = self.secret.diffie_hellman(&PublicKey::from(ephemeral));
+        // a low order ephemeral key, which ring never generates
+        if !shared.was_contributory() {
+            return Ok(Some(invalid("invalid segment header")));
+        }
+
+        self.check_finished();
+        let key = segment_key(shared.as_bytes(), &ephemeral, self.recipient.as_bytes(), &self.tail);
+        self.segment = Some(Segment {
+            key: key,
+            counter: 0,
+            finished: false,
+        });
+        self.tail = chain(&ephemeral);
+        Ok(None)
+    }
+
+    // reads a chunk starting with `prefix`
+    fn chunk(&mut self, prefix: [u8; 8], read: &mut Vec<u8>) -> io::Result<Option<io::Error>> {
+        let mut len = [0; 4];
+        len.copy_from_slice(&prefix[..4]);
+        let len = u32::from_le_bytes(len);
+        let last = len & FINAL != 0;
+        let len = (len & !FINAL) as usize;
+        if !(TAG_LEN..=MAX_CHUNK_LEN).contains(&len) {
+            return Ok(Some(invalid("invalid chunk length")));
+        }
+        let mut chunk = vec![0; len];
+        chunk[..4].copy_from_slice(&prefix[4..]);
+        let n = self.input.read_full(&mut chunk[4..])?;
+        read.extend_from_slice(&chunk[4..4 + n]);
+        if n != len - 4 {
+            return Ok(Some(incomplete()));
+        }
+
+        let segment = match self.segment {
+            Some(ref mut segment) if !segment.finished => segment,
+            Some(_) => return Ok(Some(invalid("data after the final chunk of a segment"))),
+            None => return Ok(Some(invalid("not an encrypted log file"))),
+        };
+        let tail = chain(&chunk);
+        let aad = Aad::from([last as u8]);
+        let plaintext = match segment.key.open_in_place(nonce(segment.counter), aad, &mut chunk) {
+            Ok(plaintext) => plaintext,
+            Err(_) => return Ok(Some(invalid("decryption failed"))),
+        };
+        segment.counter += 1;
+        segment.finished = last;
+        self.tail = tail;
+        self.output.write_all(plaintext)?;
+        self.written += plaintext.len() as u64;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encrypt(key: &EncryptionKey, out: &mut Vec<u8>, chunks: &[&[u8]]) {
+        let tail = out.clone();
+        let mut writer = EncryptingWriter::append(key, &tail, out).unwrap();
+        for chunk in chunks {
+            writer.write_all(chunk).unwrap();
+            writer.flush().unwrap();
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = DecryptionKey::generate().unwrap();
+        let mut file = vec![];
+        encrypt(&key.encryption_key(), &mut file, &[b"one\n", b"two\n"]);
+        // a second segment, as when reopening the file in append mode
+        encrypt(&key.encryption_key(), &mut file, &[b"three\n"]);
+        assert!(!file.windows(3).any(|w| w == b"one"));
+
+        let mut plaintext = vec![];
+        assert_eq!(decrypt(&key, &file[..], &mut plaintext).unwrap(), 14);
+        assert_eq!(plaintext, b"one\ntwo\nthree\n");
+
+        let other = DecryptionKey::generate().unwrap();
+        let err = decrypt(&other, &file[..], vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tampering() {
+        let key = DecryptionKey::generate().unwrap();
+        let public = key.encryption_key();
+        let header = MAGIC.len() + KEY_LEN;
+        let chunk = 4 + 4 + TAG_LEN;
+
+        let mut file = vec![];
+        encrypt(&public, &mut file, &[b"one\n"]);
+        let mut flipped = file.clone();
+        flipped[header + 4] ^= 1;
+        let err = decrypt(&key, &flipped[..], vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // marking the first chunk as final
+        let mut marked = file.clone();
+        marked[header + 3] |= 0x80;
+        assert_eq!(decrypt(&key, &marked[..], vec![]).unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+
+        let mut file = vec![];
+        encrypt(&public, &mut file, &[b"one\n", b"two\n"]);
+        // drop the first chunk
+        let mut spliced = file[..header].to_vec();
+        spliced.extend_from_slice(&file[header + chunk..]);
+        assert!(decrypt(&key, &spliced[..], vec![]).is_err());
+
+        // drop the first segment
+        let first = file.len();
+        encrypt(&public, &mut file, &[b"three\n"]);
+        assert!(decrypt(&key, &file[..], vec![]).is_ok());
+        let err = decrypt(&key, &file[first..], vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncation() {
+        let key = DecryptionKey::generate().unwrap();
+        let mut file = vec![];
+        encrypt(&key.encryption_key(), &mut file, &[b"one\n", b"two\n"]);
+
+        // the final chunk is empty
+        let len = file.len() - 4 - TAG_LEN;
+        let mut plaintext = vec![];
+        let err = decrypt(&key, &file[..len], &mut plaintext).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(plaintext, b"one\ntwo\n");
+
+        let err = decrypt(&key, &file[..len - 1], vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        assert_eq!(decrypt(&key, &b""[..], vec![]).unwrap(), 0);
+    }
+
+    #[test]
+    fn unfinished_segment() {
+        let key = DecryptionKey::generate().unwrap();
+        let mut file = vec![];
+        encrypt(&key.encryption_key(), &mut file, &[b"one\n"]);
+        // a crash before the final chunk was written, then an appended segment
+        let len = file.len() - 4 - TAG_LEN;
+        file.truncate(len);
+        encrypt(&key.encryption_key(), &mut file, &[b"two\n"]);
+
+        let mut plaintext = vec![];
+        let err = decrypt(&key, &file[..], &mut plaintext).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(plaintext, b"one\ntwo\n");
+    }
+
+    #[test]
+    fn torn_chunk() {
+        let key = DecryptionKey::generate().unwrap();
+        let mut file = vec![];
+        encrypt(&key.encryption_key(), &mut file, &[b"one\n", b"two\n"]);
+        // a crash in the middle of writing the second chunk
+        let len = file.len() - 4 - TAG_LEN - 5;
+        file.truncate(len);
+        encrypt(&key.encryption_key(), &mut file, &[b"three\n"]);
+        let second = file.len();
+        encrypt(&key.encryption_key(), &mut file, &[b"four\n"]);
+
+        let mut plaintext = vec![];
+        assert!(decrypt(&key, &file[..], &mut plaintext).is_err());
+        assert_eq!(plaintext, b"one\nthree\nfour\n");
+
+        // the segments are still chained to the ones before them
+        let mut plaintext = vec![];
+        assert!(decrypt(&key, &file[second..], &mut plaintext).is_err());
+        assert_eq!(plaintext, b"");
+    }
+
+    #[test]
+    fn parse_key() {
+        let key = DecryptionKey::generate().unwrap();
+        let hex = format!("  {}\n", key.to_hex());
+        assert_eq!(super::parse_key(hex.as_bytes()).unwrap(), key.0);
+        assert_eq!(super::parse_key(&key.0).unwrap(), key.0);
+        assert!(super::parse_key(b"abcd").is_err());
+    }
+}
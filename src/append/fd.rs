@@ -0,0 +1,197 @@
+//! The file descriptor appender.
+//!
+//! Requires the `fd_appender` feature, and is only available on Unix.
+
+use antidote::Mutex;
+use log::Record;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use append::Append;
+use append::counting::CountingWriter;
+use append::systemd;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The file descriptor appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FdAppenderConfig {
+    fd: Option<RawFd>,
+    listen_fd: Option<String>,
+    encoder: Option<EncoderConfig>,
+}
+
+/// An appender which writes to an inherited file descriptor.
+///
+/// This is useful when a supervisor such as systemd hands the process a pipe
+/// or socket to log to.
+pub struct FdAppender {
+    fd: RawFd,
+    file: Mutex<SimpleWriter<BufWriter<File>>>,
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for FdAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("FdAppender")
+            .field("fd", &self.fd)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for FdAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut file = self.file.lock();
+        let result = {
+            let mut writer = CountingWriter::new(&mut *file);
+            let result = self.encoder.encode(&mut writer, record);
+            self.bytes.fetch_add(writer.count(), Ordering::Relaxed);
+            result
+        };
+        result?;
+        file.flush()?;
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl FdAppender {
+    /// Creates a new `FdAppender` builder.
+    pub fn builder() -> FdAppenderBuilder {
+        FdAppenderBuilder { encoder: None }
+    }
+}
+
+/// A builder for `FdAppender`s.
+pub struct FdAppenderBuilder {
+    encoder: Option<Box<Encode>>,
+}
+
+impl FdAppenderBuilder {
+    /// Sets the output encoder for the `FdAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> FdAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `FdAppenderBuilder`, producing an `FdAppender` which
+    /// writes to the specified file descriptor.
+    ///
+    /// The appender writes to a duplicate of the descriptor, so the original
+    /// remains open when the appender is dropped.
+    pub fn build(self, fd: RawFd) -> io::Result<FdAppender> {
+        let file = unsafe { File::from_raw_fd(systemd::dup(fd)?) };
+        Ok(FdAppender {
+            fd: fd,
+            file: Mutex::new(SimpleWriter(BufWriter::with_capacity(1024, file))),
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
+        })
+    }
+
+    /// Consumes the `FdAppenderBuilder`, producing an `FdAppender` which
+    /// writes to a file descriptor passed by systemd.
+    ///
+    /// The name may either be a name assigned with `FileDescriptorName=`, or
+    /// the index of the descriptor among those passed.
+    pub fn build_listen_fd(self, name: &str) -> io::Result<FdAppender> {
+        let fd = systemd::listen_fd(name)?;
+        self.build(fd)
+    }
+}
+
+/// A deserializer for the `FdAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: fd
+///
+/// # The file descriptor to write to. Exactly one of `fd` and `listen_fd` is
+/// # required.
+/// fd: 3
+///
+/// # The name or index of a file descriptor passed by systemd via
+/// # `LISTEN_FDS`.
+/// listen_fd: log
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct FdAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for FdAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = FdAppenderConfig;
+
+    fn deserialize(&self,
+                   config: FdAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = FdAppender::builder();
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        let appender = match (config.fd, config.listen_fd) {
+            (Some(fd), None) => appender.build(fd)?,
+            (None, Some(name)) => appender.build_listen_fd(&name)?,
+            _ => return Err("exactly one of `fd` and `listen_fd` must be specified".into()),
+        };
+        Ok(Box::new(appender))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn write() {
+        let dir = TempDir::new("fd_write").unwrap();
+        let path = dir.path().join("log");
+        let file = File::create(&path).unwrap();
+
+        let appender = FdAppender::builder().build(file.as_raw_fd()).unwrap();
+        drop(file);
+        appender.file.lock().write_all(b"hello").unwrap();
+        appender.file.lock().flush().unwrap();
+
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+    }
+}
@@ -3,14 +3,22 @@
 //! Requires the `file_appender` feature.
 
 use antidote::Mutex;
-use log::LogRecord;
+use log::Record;
 use std::error::Error;
 use std::fmt;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, Write, BufWriter};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
-use append::Append;
+use append::{symlink, Append};
+use append::counting::CountingWriter;
+#[cfg(feature = "encryption")]
+use append::encrypt::{self, EncryptingWriter, EncryptionKey};
+use append::mmap::MmapWriter;
+use append::thread_buffer::ThreadBuffers;
 use encode::Encode;
 #[cfg(feature = "file")]
 use encode::EncoderConfig;
@@ -27,13 +35,123 @@ pub struct FileAppenderConfig {
     path: String,
     encoder: Option<EncoderConfig>,
     append: Option<bool>,
+    symlink: Option<String>,
+    backend: Option<ConfigBackend>,
+    encryption_key_file: Option<String>,
+    #[serde(deserialize_with = "::priv_serde::de_size_opt", default)]
+    thread_buffer_size: Option<u64>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    thread_buffer_delay: Option<Duration>,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+enum ConfigBackend {
+    #[serde(rename = "buffered")]
+    Buffered,
+    #[serde(rename = "mmap")]
+    Mmap,
+}
+
+enum Writer {
+    Buffered(BufWriter<File>),
+    Mmap(MmapWriter),
+    #[cfg(feature = "encryption")]
+    Encrypted(Box<EncryptingWriter<BufWriter<File>>>),
+    // after reopening the file failed
+    Closed,
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "the log file could not be reopened")
+}
+
+impl io::Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Writer::Buffered(ref mut w) => w.write(buf),
+            Writer::Mmap(ref mut w) => w.write(buf),
+            #[cfg(feature = "encryption")]
+            Writer::Encrypted(ref mut w) => w.write(buf),
+            Writer::Closed => Err(closed()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Writer::Buffered(ref mut w) => w.flush(),
+            Writer::Mmap(ref mut w) => w.flush(),
+            #[cfg(feature = "encryption")]
+            Writer::Encrypted(ref mut w) => w.flush(),
+            Writer::Closed => Ok(()),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match *self {
+            Writer::Buffered(ref mut w) => w.write_all(buf),
+            Writer::Mmap(ref mut w) => w.write_all(buf),
+            #[cfg(feature = "encryption")]
+            Writer::Encrypted(ref mut w) => w.write_all(buf),
+            Writer::Closed => Err(closed()),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+type Key = EncryptionKey;
+#[cfg(not(feature = "encryption"))]
+type Key = ();
+
+fn open(path: &Path, append: bool, backend: &Backend, key: Option<&Key>) -> io::Result<Writer> {
+    match *backend {
+        Backend::Buffered => {
+            let file = OpenOptions::new()
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .create(true)
+                .open(path)?;
+            let file = BufWriter::with_capacity(1024, file);
+            match key {
+                #[cfg(feature = "encryption")]
+                Some(key) => {
+                    let tail = encrypt::file_tail(path)?;
+                    let writer = EncryptingWriter::append(key, &tail, file)?;
+                    Ok(Writer::Encrypted(Box::new(writer)))
+                }
+                _ => Ok(Writer::Buffered(file)),
+            }
+        }
+        Backend::Mmap => {
+            if key.is_some() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "the mmap backend does not support encryption"));
+            }
+            // mapping the file requires it to be opened for reading and
+            // not in append mode
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .truncate(!append)
+                .create(true)
+                .open(path)?;
+            let pos = file.metadata()?.len();
+            Ok(Writer::Mmap(MmapWriter::new(file, pos)?))
+        }
+    }
 }
 
 /// An appender which logs to a file.
 pub struct FileAppender {
     path: PathBuf,
-    file: Mutex<SimpleWriter<BufWriter<File>>>,
+    symlink: Option<PathBuf>,
+    file: Arc<Mutex<SimpleWriter<Writer>>>,
+    buffers: Option<ThreadBuffers<SimpleWriter<Writer>>>,
     encoder: Box<Encode>,
+    bytes: AtomicU64,
+    backend: Backend,
+    key: Option<Key>,
 }
 
 impl fmt::Debug for FileAppender {
@@ -46,10 +164,60 @@ impl fmt::Debug for FileAppender {
 }
 
 impl Append for FileAppender {
-    fn append(&self, record: &LogRecord) -> Result<(), Box<Error + Sync + Send>> {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        if let Some(ref buffers) = self.buffers {
+            return buffers.with(|buf| {
+                    let mut writer = CountingWriter::new(SimpleWriter(buf));
+                    let result = self.encoder.encode(&mut writer, record);
+                    self.bytes.fetch_add(writer.count(), Ordering::Relaxed);
+                    result
+                })?;
+        }
+
+        let mut file = self.file.lock();
+        let result = {
+            let mut writer = CountingWriter::new(&mut *file);
+            let result = self.encoder.encode(&mut writer, record);
+            self.bytes.fetch_add(writer.count(), Ordering::Relaxed);
+            result
+        };
+        result?;
+        file.flush()?;
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        if let Some(ref buffers) = self.buffers {
+            buffers.flush()?;
+        }
+        Ok(())
+    }
+
+    fn reopen(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.flush()?;
         let mut file = self.file.lock();
-        self.encoder.encode(&mut *file, record)?;
         file.flush()?;
+        // the old file, which may still be at the path, has to be closed
+        // first so that a memory map truncates it before it is reopened
+        file.0 = Writer::Closed;
+        // a file which is still in place is appended to
+        file.0 = open(&self.path, true, &self.backend, self.key.as_ref())?;
+        // the symlink may have been removed or moved along with the old file
+        if let Some(ref symlink) = self.symlink {
+            symlink::update(symlink, &self.path)?;
+        }
         Ok(())
     }
 }
@@ -60,14 +228,40 @@ impl FileAppender {
         FileAppenderBuilder {
             encoder: None,
             append: true,
+            symlink: None,
+            backend: Backend::Buffered,
+            thread_buffer: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
         }
     }
 }
 
+/// The strategy used to write to the log file.
+pub enum Backend {
+    /// Writes go through a small in-memory buffer which is flushed to the file
+    /// after every log event.
+    Buffered,
+
+    /// Writes are copied into a memory-mapped region of the file which is
+    /// grown in advance and periodically synced to disk.
+    ///
+    /// This can significantly increase throughput for small records, at the
+    /// cost of the file containing trailing NUL bytes past the logged data
+    /// until the appender is dropped. If the process exits abnormally, they
+    /// will remain.
+    Mmap,
+}
+
 /// A builder for `FileAppender`s.
 pub struct FileAppenderBuilder {
     encoder: Option<Box<Encode>>,
     append: bool,
+    symlink: Option<PathBuf>,
+    backend: Backend,
+    thread_buffer: Option<(usize, Duration)>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<EncryptionKey>,
 }
 
 impl FileAppenderBuilder {
@@ -85,23 +279,89 @@ impl FileAppenderBuilder {
         self
     }
 
+    /// Sets the path of a symlink which will be pointed at the log file when
+    /// it is opened or reopened.
+    ///
+    /// Symlinks are only supported on Unix and Windows.
+    pub fn symlink<P: AsRef<Path>>(mut self, symlink: P) -> FileAppenderBuilder {
+        self.symlink = Some(symlink.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the strategy used to write to the log file.
+    ///
+    /// Defaults to `Backend::Buffered`.
+    pub fn backend(mut self, backend: Backend) -> FileAppenderBuilder {
+        self.backend = backend;
+        self
+    }
+
+    /// Enables per-thread buffering.
+    ///
+    /// Each thread encodes events into a buffer of its own, which is written
+    /// to the file once it holds `size` bytes, at most `max_delay` after an
+    /// event was added to it, when the thread exits, and when the appender is
+    /// flushed or dropped. This removes the lock on the file from the logging
+    /// path, at the cost of events from different threads no longer being
+    /// written in order, and of buffered events being lost if the process
+    /// exits abnormally.
+    ///
+    /// Disabled by default, so that every event is written before logging
+    /// returns.
+    pub fn thread_buffer(mut self, size: usize, max_delay: Duration) -> FileAppenderBuilder {
+        self.thread_buffer = Some((size, max_delay));
+        self
+    }
+
+    /// Sets the public key to which the log file will be encrypted.
+    ///
+    /// See the `encrypt` module for details of the format; use
+    /// `encrypt::decrypt` to read the file back. Encryption is not supported
+    /// by the `Mmap` backend.
+    ///
+    /// Requires the `encryption` Cargo feature.
+    #[cfg(feature = "encryption")]
+    pub fn encryption(mut self, key: EncryptionKey) -> FileAppenderBuilder {
+        self.encryption = Some(key);
+        self
+    }
+
+    #[cfg(feature = "encryption")]
+    fn encryption_key(&self) -> Option<&Key> {
+        self.encryption.as_ref()
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn encryption_key(&self) -> Option<&Key> {
+        None
+    }
+
     /// Consumes the `FileAppenderBuilder`, producing a `FileAppender`.
     pub fn build<P: AsRef<Path>>(self, path: P) -> io::Result<FileAppender> {
         let path = path.as_ref().to_owned();
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let file = OpenOptions::new()
-            .write(true)
-            .append(self.append)
-            .truncate(!self.append)
-            .create(true)
-            .open(&path)?;
+        let writer = open(&path, self.append, &self.backend, self.encryption_key())?;
+        if let Some(ref symlink) = self.symlink {
+            symlink::update(symlink, &path)?;
+        }
+
+        let file = Arc::new(Mutex::new(SimpleWriter(writer)));
+        let buffers = match self.thread_buffer {
+            Some((size, max_delay)) => Some(ThreadBuffers::new(file.clone(), size, max_delay)?),
+            None => None,
+        };
 
         Ok(FileAppender {
+            key: self.encryption_key().cloned(),
             path: path,
-            file: Mutex::new(SimpleWriter(BufWriter::with_capacity(1024, file))),
+            symlink: self.symlink,
+            file: file,
+            buffers: buffers,
             encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
+            backend: self.backend,
         })
     }
 }
@@ -121,6 +381,29 @@ impl FileAppenderBuilder {
 /// # already exists. Defaults to `true`.
 /// append: true
 ///
+/// # If set, a symlink at this path will be maintained pointing to the log
+/// # file. Optional.
+/// symlink: log/latest.log
+///
+/// # The strategy used to write to the log file. One of `buffered` or `mmap`.
+/// # Defaults to `buffered`.
+/// backend: buffered
+///
+/// # If set, the log file is encrypted to the X25519 public key stored in this
+/// # file, either as 32 raw bytes or as 64 hexadecimal digits. Requires the
+/// # `encryption` Cargo feature and the `buffered` backend. Optional.
+/// encryption_key_file: /etc/myapp/log.key
+///
+/// # If set, each thread buffers up to this many bytes of events before they
+/// # are written to the file, removing contention between threads. Buffers
+/// # are also written when their thread exits and when the appender is
+/// # flushed. Optional.
+/// thread_buffer_size: 64 KB
+///
+/// # The longest buffered events wait before they are written. Defaults to
+/// # 1 second.
+/// thread_buffer_delay: 1 second
+///
 /// # The encoder to use to format output. Defaults to `kind: pattern`.
 /// encoder:
 ///   kind: pattern
@@ -142,16 +425,49 @@ impl Deserialize for FileAppenderDeserializer {
         if let Some(append) = config.append {
             appender = appender.append(append);
         }
+        if let Some(symlink) = config.symlink {
+            appender = appender.symlink(deserializers.resolve_path(symlink));
+        }
+        if let Some(backend) = config.backend {
+            let backend = match backend {
+                ConfigBackend::Buffered => Backend::Buffered,
+                ConfigBackend::Mmap => Backend::Mmap,
+            };
+            appender = appender.backend(backend);
+        }
+        if let Some(size) = config.thread_buffer_size {
+            let max_delay = config.thread_buffer_delay.unwrap_or_else(|| Duration::from_secs(1));
+            appender = appender.thread_buffer(size as usize, max_delay);
+        }
+        if let Some(key_file) = config.encryption_key_file {
+            appender = encryption(appender, &deserializers.resolve_path(key_file))?;
+        }
         if let Some(encoder) = config.encoder {
             appender =
                 appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
         }
-        Ok(Box::new(appender.build(&config.path)?))
+        Ok(Box::new(appender.build(deserializers.resolve_path(config.path))?))
     }
 }
 
+#[cfg(all(feature = "file", feature = "encryption"))]
+fn encryption(appender: FileAppenderBuilder,
+              key_file: &Path)
+              -> Result<FileAppenderBuilder, Box<Error + Sync + Send>> {
+    Ok(appender.encryption(EncryptionKey::from_file(key_file)?))
+}
+
+#[cfg(all(feature = "file", not(feature = "encryption")))]
+fn encryption(_: FileAppenderBuilder,
+              _: &Path)
+              -> Result<FileAppenderBuilder, Box<Error + Sync + Send>> {
+    Err("encrypted log files require the `encryption` feature".into())
+}
+
 #[cfg(test)]
 mod test {
+    use std::fs::File;
+    use std::io::{Read, Write};
     use tempdir::TempDir;
 
     use super::*;
@@ -173,4 +489,109 @@ mod test {
             .build(tempdir.path().join("foo.log"))
             .unwrap();
     }
+
+    #[test]
+    fn mmap_append() {
+        let tempdir = TempDir::new("mmap_append").unwrap();
+        let path = tempdir.path().join("foo.log");
+        File::create(&path).unwrap().write_all(b"hello").unwrap();
+
+        let appender = FileAppender::builder()
+            .backend(Backend::Mmap)
+            .build(&path)
+            .unwrap();
+        appender.file.lock().write_all(b" world").unwrap();
+        drop(appender);
+
+        let mut contents = vec![];
+        File::open(&path).unwrap().read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"hello world");
+    }
+
+    #[test]
+    fn reopen() {
+        let tempdir = TempDir::new("reopen").unwrap();
+        let path = tempdir.path().join("foo.log");
+        let rotated = tempdir.path().join("foo.log.1");
+
+        let appender = FileAppender::builder().build(&path).unwrap();
+        appender.file.lock().write_all(b"one\n").unwrap();
+        fs::rename(&path, &rotated).unwrap();
+        appender.reopen().unwrap();
+        let mut file = appender.file.lock();
+        file.write_all(b"two\n").unwrap();
+        file.flush().unwrap();
+
+        let mut contents = String::new();
+        File::open(&rotated).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "one\n");
+        contents.clear();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "two\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reopen_symlink() {
+        let tempdir = TempDir::new("reopen_symlink").unwrap();
+        let path = tempdir.path().join("foo.log");
+        let link = tempdir.path().join("latest.log");
+
+        let appender = FileAppender::builder().symlink(&link).build(&path).unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("foo.log"));
+        fs::rename(&path, tempdir.path().join("foo.log.1")).unwrap();
+        fs::remove_file(&link).unwrap();
+        appender.reopen().unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("foo.log"));
+    }
+
+    #[test]
+    fn thread_buffer() {
+        let tempdir = TempDir::new("thread_buffer").unwrap();
+        let path = tempdir.path().join("foo.log");
+        let appender = FileAppender::builder()
+            .thread_buffer(1024, Duration::from_secs(60))
+            .build(&path)
+            .unwrap();
+        let read = || {
+            let mut contents = String::new();
+            File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+            contents
+        };
+
+        appender.buffers.as_ref().unwrap().with(|buf| buf.extend_from_slice(b"one\n")).unwrap();
+        assert_eq!(read(), "");
+        Append::flush(&appender).unwrap();
+        assert_eq!(read(), "one\n");
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encryption() {
+        use append::encrypt::DecryptionKey;
+
+        let tempdir = TempDir::new("encryption").unwrap();
+        let path = tempdir.path().join("foo.log");
+        let key = DecryptionKey::generate().unwrap();
+
+        for line in &[&b"one\n"[..], b"two\n"] {
+            let appender = FileAppender::builder()
+                .encryption(key.encryption_key())
+                .build(&path)
+                .unwrap();
+            let mut file = appender.file.lock();
+            file.write_all(line).unwrap();
+            file.flush().unwrap();
+        }
+
+        let mut contents = vec![];
+        encrypt::decrypt(&key, File::open(&path).unwrap(), &mut contents).unwrap();
+        assert_eq!(contents, b"one\ntwo\n");
+
+        assert!(FileAppender::builder()
+                    .encryption(key.encryption_key())
+                    .backend(Backend::Mmap)
+                    .build(&path)
+                    .is_err());
+    }
 }
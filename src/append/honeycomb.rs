@@ -0,0 +1,409 @@
+//! The Honeycomb appender.
+//!
+//! Requires the `honeycomb_appender` feature. Honeycomb's API is only served
+//! over HTTPS, so the `http_tls` feature is also required unless events are
+//! routed through a plain HTTP proxy.
+
+use chrono::UTC;
+use log::Record;
+use log_mdc;
+use serde_json::{self, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use append::Append;
+use append::batch::Batcher;
+use append::http::Client;
+use append::queue::Bound;
+use config::Backpressure;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+const DEFAULT_URL: &str = "https://api.honeycomb.io";
+
+/// The Honeycomb appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoneycombAppenderConfig {
+    #[serde(deserialize_with = "::priv_serde::de_secret")]
+    api_key: String,
+    dataset: String,
+    url: Option<String>,
+    sample_rate_key: Option<String>,
+    batch_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    batch_timeout: Option<Duration>,
+    queue_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_backpressure", default)]
+    backpressure: Option<Backpressure>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    timeout: Option<Duration>,
+    max_retries: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct Event {
+    time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    samplerate: Option<u64>,
+    data: BTreeMap<String, Value>,
+}
+
+struct Sender {
+    url: String,
+    api_key: String,
+    client: Client,
+}
+
+impl Sender {
+    fn send(&self, events: &[Event]) -> Result<u64, Box<Error + Sync + Send>> {
+        let body = serde_json::to_vec(events)?;
+        let headers = [("Content-Type", "application/json"),
+                       ("X-Honeycomb-Team", &*self.api_key)];
+        self.client.post(&self.url, &headers, &body)?;
+        Ok(body.len() as u64)
+    }
+}
+
+/// An appender which sends log events to a Honeycomb dataset.
+///
+/// Events are batched on a background thread and sent to Honeycomb's batch
+/// API. Each event carries the record's `message`, `level`, `target`,
+/// `module_path`, `file`, `line`, and `thread`, along with every MDC entry as
+/// an attribute. If the MDC contains an integer under the sample rate key, it
+/// is sent as the event's sample rate rather than as an attribute.
+pub struct HoneycombAppender {
+    dataset: String,
+    sample_rate_key: String,
+    batcher: Batcher<Event>,
+    bytes: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for HoneycombAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("HoneycombAppender")
+            .field("dataset", &self.dataset)
+            .field("sample_rate_key", &self.sample_rate_key)
+            .finish()
+    }
+}
+
+impl Append for HoneycombAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut data = BTreeMap::new();
+        let mut samplerate = None;
+        log_mdc::iter(|k, v| {
+            if k == self.sample_rate_key {
+                if let Ok(rate) = v.parse() {
+                    samplerate = Some(rate);
+                    return;
+                }
+            }
+            data.insert(k.to_owned(), Value::String(v.to_owned()));
+        });
+
+        data.insert("message".to_owned(), Value::String(record.args().to_string()));
+        data.insert("level".to_owned(), Value::String(record.level().to_string()));
+        data.insert("target".to_owned(), Value::String(record.target().to_owned()));
+        if let Some(module_path) = record.module_path() {
+            data.insert("module_path".to_owned(), Value::String(module_path.to_owned()));
+        }
+        if let Some(file) = record.file() {
+            data.insert("file".to_owned(), Value::String(file.to_owned()));
+        }
+        if let Some(line) = record.line() {
+            data.insert("line".to_owned(), Value::from(line));
+        }
+        if let Some(name) = thread::current().name() {
+            data.insert("thread".to_owned(), Value::String(name.to_owned()));
+        }
+
+        self.batcher.push(record.level(), Event {
+            time: UTC::now().to_rfc3339(),
+            samplerate: samplerate,
+            data: data,
+        })
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.batcher.flush()
+    }
+}
+
+impl HoneycombAppender {
+    /// Creates a new `HoneycombAppender` builder.
+    pub fn builder() -> HoneycombAppenderBuilder {
+        HoneycombAppenderBuilder {
+            url: DEFAULT_URL.to_owned(),
+            sample_rate_key: "samplerate".to_owned(),
+            batch_size: 100,
+            batch_timeout: Duration::from_secs(1),
+            queue_size: None,
+            backpressure: None,
+            timeout: Duration::from_secs(10),
+            max_retries: 5,
+        }
+    }
+
+    /// Returns the number of events which were dropped because the queue of
+    /// events waiting to be batched was full.
+    pub fn dropped(&self) -> u64 {
+        self.batcher.dropped()
+    }
+}
+
+/// A builder for `HoneycombAppender`s.
+pub struct HoneycombAppenderBuilder {
+    url: String,
+    sample_rate_key: String,
+    batch_size: usize,
+    batch_timeout: Duration,
+    queue_size: Option<usize>,
+    backpressure: Option<Backpressure>,
+    timeout: Duration,
+    max_retries: u32,
+}
+
+impl HoneycombAppenderBuilder {
+    /// Sets the base URL of the Honeycomb API.
+    ///
+    /// Defaults to `https://api.honeycomb.io`.
+    pub fn url(mut self, url: &str) -> HoneycombAppenderBuilder {
+        self.url = url.trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Sets the MDC key whose value is sent as the event's sample rate.
+    ///
+    /// Defaults to `samplerate`.
+    pub fn sample_rate_key(mut self, key: &str) -> HoneycombAppenderBuilder {
+        self.sample_rate_key = key.to_owned();
+        self
+    }
+
+    /// Sets the maximum number of events sent in a single request.
+    ///
+    /// Defaults to 100.
+    pub fn batch_size(mut self, batch_size: usize) -> HoneycombAppenderBuilder {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets the maximum amount of time an event will wait to be sent.
+    ///
+    /// Defaults to 1 second.
+    pub fn batch_timeout(mut self, batch_timeout: Duration) -> HoneycombAppenderBuilder {
+        self.batch_timeout = batch_timeout;
+        self
+    }
+
+    /// Sets the maximum number of events which may be waiting to be batched,
+    /// after which the backpressure policy applies.
+    ///
+    /// Defaults to no limit.
+    pub fn queue_size(mut self, queue_size: usize) -> HoneycombAppenderBuilder {
+        self.queue_size = Some(queue_size);
+        self
+    }
+
+    /// Sets the policy applied when the queue of events is full.
+    ///
+    /// Defaults to the policy of the configuration.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> HoneycombAppenderBuilder {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    /// Sets the timeout for each request.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> HoneycombAppenderBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of times a failed request is retried before its events
+    /// are dropped.
+    ///
+    /// Defaults to 5.
+    pub fn max_retries(mut self, max_retries: u32) -> HoneycombAppenderBuilder {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Consumes the `HoneycombAppenderBuilder`, producing a
+    /// `HoneycombAppender` which sends events to the specified dataset.
+    pub fn build(self,
+                 api_key: &str,
+                 dataset: &str)
+                 -> Result<HoneycombAppender, Box<Error + Sync + Send>> {
+        if self.batch_size == 0 {
+            return Err("batch_size must be positive".into());
+        }
+
+        let url = format!("{}/1/batch/{}", self.url, dataset);
+        let sender = Sender {
+            client: Client::new(&url, self.timeout, self.max_retries)?,
+            url: url,
+            api_key: api_key.to_owned(),
+        };
+        let bytes = Arc::new(AtomicU64::new(0));
+        let bytes2 = bytes.clone();
+        let batcher = Batcher::new("log4rs-honeycomb",
+                                   self.batch_size,
+                                   self.batch_timeout,
+                                   Bound {
+                                       capacity: self.queue_size,
+                                       backpressure: self.backpressure,
+                                   },
+                                   move |events: Vec<Event>| {
+            match sender.send(&events) {
+                Ok(len) => {
+                    bytes2.fetch_add(len, Ordering::Relaxed);
+                }
+                Err(e) => ::handle_error(&*e),
+            }
+        })?;
+
+        Ok(HoneycombAppender {
+            dataset: dataset.to_owned(),
+            sample_rate_key: self.sample_rate_key,
+            batcher: batcher,
+            bytes: bytes,
+        })
+    }
+}
+
+/// A deserializer for the `HoneycombAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: honeycomb
+///
+/// # The Honeycomb API key. Required. `env:NAME` or `file:PATH` reads it from
+/// # an environment variable or a file instead.
+/// api_key: file:/run/secrets/honeycomb
+///
+/// # The dataset events are sent to. Required.
+/// dataset: my-service
+///
+/// # The base URL of the Honeycomb API. Defaults to
+/// # `https://api.honeycomb.io`.
+/// url: https://api.honeycomb.io
+///
+/// # The MDC key whose value is sent as the event's sample rate. Defaults to
+/// # `samplerate`.
+/// sample_rate_key: samplerate
+///
+/// # The maximum number of events sent in a single request. Defaults to 100.
+/// batch_size: 100
+///
+/// # The maximum amount of time an event will wait to be sent. Defaults to
+/// # 1 second.
+/// batch_timeout: 1 second
+///
+/// # The maximum number of events which may be waiting to be batched, after
+/// # which `backpressure` applies. Defaults to no limit.
+/// queue_size: 10000
+///
+/// # What happens when the queue is full: `block`, `drop_newest`,
+/// # `drop_oldest`, or `drop_below(level)`. Defaults to the top level
+/// # `backpressure` of the config file.
+/// backpressure: drop_oldest
+///
+/// # The timeout for each request. Defaults to 10 seconds.
+/// timeout: 10 seconds
+///
+/// # The number of times a failed request is retried. Defaults to 5.
+/// max_retries: 5
+/// ```
+#[cfg(feature = "file")]
+pub struct HoneycombAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for HoneycombAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = HoneycombAppenderConfig;
+
+    fn deserialize(&self,
+                   config: HoneycombAppenderConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = HoneycombAppender::builder();
+        if let Some(url) = config.url {
+            appender = appender.url(&url);
+        }
+        if let Some(sample_rate_key) = config.sample_rate_key {
+            appender = appender.sample_rate_key(&sample_rate_key);
+        }
+        if let Some(batch_size) = config.batch_size {
+            appender = appender.batch_size(batch_size);
+        }
+        if let Some(batch_timeout) = config.batch_timeout {
+            appender = appender.batch_timeout(batch_timeout);
+        }
+        if let Some(queue_size) = config.queue_size {
+            appender = appender.queue_size(queue_size);
+        }
+        if let Some(backpressure) = config.backpressure {
+            appender = appender.backpressure(backpressure);
+        }
+        if let Some(timeout) = config.timeout {
+            appender = appender.timeout(timeout);
+        }
+        if let Some(max_retries) = config.max_retries {
+            appender = appender.max_retries(max_retries);
+        }
+        Ok(Box::new(appender.build(&config.api_key, &config.dataset)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serialize() {
+        let mut data = BTreeMap::new();
+        data.insert("message".to_owned(), Value::String("hi".to_owned()));
+        let events = [Event {
+                          time: "2017-01-01T00:00:00+00:00".to_owned(),
+                          samplerate: Some(10),
+                          data: data.clone(),
+                      },
+                      Event {
+                          time: "2017-01-01T00:00:00+00:00".to_owned(),
+                          samplerate: None,
+                          data: data,
+                      }];
+        let body = serde_json::to_string(&events).unwrap();
+        assert_eq!(body,
+                   "[{\"time\":\"2017-01-01T00:00:00+00:00\",\"samplerate\":10,\
+                    \"data\":{\"message\":\"hi\"}},\
+                    {\"time\":\"2017-01-01T00:00:00+00:00\",\"data\":{\"message\":\"hi\"}}]");
+    }
+
+    #[test]
+    fn url() {
+        assert!(HoneycombAppender::builder()
+                    .url("http://localhost:1234/")
+                    .build("key", "dataset")
+                    .is_ok());
+    }
+}
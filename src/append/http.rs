@@ -0,0 +1,115 @@
+//! A minimal HTTP client shared by appenders which push to HTTP APIs.
+
+use std::cmp;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+use ureq;
+
+const INITIAL_BACKOFF_MS: u64 = 500;
+const MAX_BACKOFF_MS: u64 = 60 * 1000;
+
+/// Posts request bodies, retrying when the server is overloaded or
+/// unreachable.
+pub struct Client {
+    agent: ureq::Agent,
+    max_retries: u32,
+}
+
+impl Client {
+    pub fn new(url: &str,
+               timeout: Duration,
+               max_retries: u32)
+               -> Result<Client, Box<Error + Sync + Send>> {
+        if url.starts_with("https://") && !cfg!(feature = "http_tls") {
+            return Err("https URLs require the `http_tls` feature".into());
+        }
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!("invalid HTTP URL `{}`", url).into());
+        }
+
+        Ok(Client {
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+            max_retries: max_retries,
+        })
+    }
+
+    /// Posts a body to the URL.
+    ///
+    /// Requests which fail with a `429 Too Many Requests` or `5xx` status, or
+    /// which fail to connect, are retried with exponential backoff. A
+    /// `Retry-After` header is honored if present.
+    pub fn post(&self,
+                url: &str,
+                headers: &[(&str, &str)],
+                body: &[u8])
+                -> Result<(), Box<Error + Sync + Send>> {
+        let mut backoff = Duration::from_millis(INITIAL_BACKOFF_MS);
+        let mut attempt = 0;
+
+        loop {
+            let mut request = self.agent.post(url);
+            for &(name, value) in headers {
+                request = request.set(name, value);
+            }
+
+            let delay = match request.send_bytes(body) {
+                Ok(_) => return Ok(()),
+                Err(ureq::Error::Status(status, response)) => {
+                    if (status != 429 && status < 500) || attempt >= self.max_retries {
+                        let message = response.into_string().unwrap_or_default();
+                        return Err(format!("HTTP status {}: {}", status, message.trim()).into());
+                    }
+                    response.header("Retry-After")
+                        .and_then(|s| s.trim().parse().ok())
+                        .map(|secs| Duration::from_secs(cmp::min(secs, MAX_BACKOFF_MS / 1000)))
+                        .unwrap_or(backoff)
+                }
+                Err(e) => {
+                    if attempt >= self.max_retries {
+                        return Err(Box::new(e));
+                    }
+                    backoff
+                }
+            };
+
+            thread::sleep(delay);
+            backoff = cmp::min(backoff * 2, Duration::from_millis(MAX_BACKOFF_MS));
+            attempt += 1;
+        }
+    }
+}
+
+/// Percent-encodes all but unreserved characters for use in a URL query.
+#[allow(dead_code)]
+pub fn encode_query(s: &str) -> String {
+    let mut encoded = String::new();
+    for &b in s.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+/// Compresses a request body for use with `Content-Encoding: gzip`.
+#[cfg(feature = "gzip")]
+#[allow(dead_code)]
+pub fn gzip(body: &[u8]) -> Result<Vec<u8>, Box<Error + Sync + Send>> {
+    use flate2;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(vec![], flate2::Compression::Default);
+    encoder.write_all(body)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(not(feature = "gzip"))]
+#[allow(dead_code)]
+pub fn gzip(_: &[u8]) -> Result<Vec<u8>, Box<Error + Sync + Send>> {
+    Err("gzip compression requires the `gzip` feature".into())
+}
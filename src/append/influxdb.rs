@@ -0,0 +1,440 @@
+//! The InfluxDB appender.
+//!
+//! Requires the `influxdb_appender` feature. Writing to `https://` URLs
+//! additionally requires the `http_tls` feature.
+
+use log::Record;
+use log_mdc;
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use append::Append;
+use append::batch::Batcher;
+use append::http::{self, Client};
+use append::queue::Bound;
+use config::Backpressure;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The InfluxDB appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InfluxDbAppenderConfig {
+    url: String,
+    org: String,
+    bucket: String,
+    #[serde(deserialize_with = "::priv_serde::de_secret_opt", default)]
+    token: Option<String>,
+    measurement: Option<String>,
+    mdc_tags: Option<Vec<String>>,
+    batch_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    batch_timeout: Option<Duration>,
+    queue_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_backpressure", default)]
+    backpressure: Option<Backpressure>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    timeout: Option<Duration>,
+    max_retries: Option<u32>,
+}
+
+// escapes commas and spaces, and equals signs if `equals` is set
+fn escape(out: &mut String, s: &str, equals: bool) {
+    for c in s.chars() {
+        match c {
+            ',' | ' ' => out.push('\\'),
+            '=' if equals => out.push('\\'),
+            '\n' => {
+                out.push_str("\\n");
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+}
+
+fn escape_string_field(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+}
+
+/// An appender which writes log events as points to InfluxDB using the line
+/// protocol and the v2 HTTP API.
+///
+/// Each event becomes a point in the configured measurement, tagged with the
+/// event's `level` and `target` and the values of the configured MDC keys,
+/// and with `message`, `file`, and `line` fields. Attach filters to the
+/// appender to select which events are converted, for example only warnings
+/// and errors when deriving error rates.
+pub struct InfluxDbAppender {
+    url: String,
+    measurement: String,
+    mdc_tags: Vec<String>,
+    batcher: Batcher<String>,
+    bytes: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for InfluxDbAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("InfluxDbAppender")
+            .field("url", &self.url)
+            .field("measurement", &self.measurement)
+            .field("mdc_tags", &self.mdc_tags)
+            .finish()
+    }
+}
+
+impl Append for InfluxDbAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut tags = vec![("level".to_owned(), record.level().to_string()),
+                            ("target".to_owned(), record.target().to_owned())];
+        for key in &self.mdc_tags {
+            if let Some(value) = log_mdc::get(key, |v| v.map(|v| v.to_owned())) {
+                // empty tag values are not allowed
+                if !value.is_empty() {
+                    tags.push((key.clone(), value));
+                }
+            }
+        }
+
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let timestamp = since_epoch.as_secs() * 1_000_000_000 + since_epoch.subsec_nanos() as u64;
+        let line = self.line(&tags,
+                             &record.args().to_string(),
+                             record.file(),
+                             record.line(),
+                             timestamp);
+        self.batcher.push(record.level(), line)
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.batcher.flush()
+    }
+}
+
+impl InfluxDbAppender {
+    /// Creates a new `InfluxDbAppender` builder.
+    pub fn builder() -> InfluxDbAppenderBuilder {
+        InfluxDbAppenderBuilder {
+            token: None,
+            measurement: "log".to_owned(),
+            mdc_tags: vec![],
+            batch_size: 1000,
+            batch_timeout: Duration::from_secs(1),
+            queue_size: None,
+            backpressure: None,
+            timeout: Duration::from_secs(10),
+            max_retries: 5,
+        }
+    }
+
+    /// Returns the number of events which were dropped because the queue of
+    /// events waiting to be batched was full.
+    pub fn dropped(&self) -> u64 {
+        self.batcher.dropped()
+    }
+
+    fn line(&self,
+            tags: &[(String, String)],
+            message: &str,
+            file: Option<&str>,
+            line: Option<u32>,
+            timestamp: u64)
+            -> String {
+        let mut out = String::new();
+        escape(&mut out, &self.measurement, false);
+        let mut tags = tags.iter().collect::<Vec<_>>();
+        // InfluxDB recommends sorting tags by key
+        tags.sort_by(|a, b| a.0.cmp(&b.0));
+        for tag in tags {
+            out.push(',');
+            escape(&mut out, &tag.0, true);
+            out.push('=');
+            escape(&mut out, &tag.1, true);
+        }
+        out.push_str(" message=");
+        escape_string_field(&mut out, message);
+        if let Some(file) = file {
+            out.push_str(",file=");
+            escape_string_field(&mut out, file);
+        }
+        if let Some(line) = line {
+            let _ = write!(out, ",line={}i", line);
+        }
+        let _ = write!(out, " {}", timestamp);
+        out
+    }
+}
+
+/// A builder for `InfluxDbAppender`s.
+pub struct InfluxDbAppenderBuilder {
+    token: Option<String>,
+    measurement: String,
+    mdc_tags: Vec<String>,
+    batch_size: usize,
+    batch_timeout: Duration,
+    queue_size: Option<usize>,
+    backpressure: Option<Backpressure>,
+    timeout: Duration,
+    max_retries: u32,
+}
+
+impl InfluxDbAppenderBuilder {
+    /// Sets the API token used to authenticate.
+    pub fn token(mut self, token: &str) -> InfluxDbAppenderBuilder {
+        self.token = Some(token.to_owned());
+        self
+    }
+
+    /// Sets the measurement points are written to.
+    ///
+    /// Defaults to `log`.
+    pub fn measurement(mut self, measurement: &str) -> InfluxDbAppenderBuilder {
+        self.measurement = measurement.to_owned();
+        self
+    }
+
+    /// Adds an MDC key whose value, if present, is attached to points as a
+    /// tag of the same name.
+    pub fn mdc_tag(mut self, key: &str) -> InfluxDbAppenderBuilder {
+        self.mdc_tags.push(key.to_owned());
+        self
+    }
+
+    /// Sets the maximum number of points sent in a single write.
+    ///
+    /// Defaults to 1000.
+    pub fn batch_size(mut self, batch_size: usize) -> InfluxDbAppenderBuilder {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets the maximum amount of time a point will wait to be written.
+    ///
+    /// Defaults to 1 second.
+    pub fn batch_timeout(mut self, batch_timeout: Duration) -> InfluxDbAppenderBuilder {
+        self.batch_timeout = batch_timeout;
+        self
+    }
+
+    /// Sets the maximum number of events which may be waiting to be batched,
+    /// after which the backpressure policy applies.
+    ///
+    /// Defaults to no limit.
+    pub fn queue_size(mut self, queue_size: usize) -> InfluxDbAppenderBuilder {
+        self.queue_size = Some(queue_size);
+        self
+    }
+
+    /// Sets the policy applied when the queue of events is full.
+    ///
+    /// Defaults to the policy of the configuration.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> InfluxDbAppenderBuilder {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    /// Sets the timeout for each write request.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> InfluxDbAppenderBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of times a failed write is retried before its points
+    /// are dropped.
+    ///
+    /// Defaults to 5.
+    pub fn max_retries(mut self, max_retries: u32) -> InfluxDbAppenderBuilder {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Consumes the `InfluxDbAppenderBuilder`, producing an
+    /// `InfluxDbAppender` which writes to the specified bucket of the
+    /// organization on the server at the specified URL.
+    pub fn build(self,
+                 url: &str,
+                 org: &str,
+                 bucket: &str)
+                 -> Result<InfluxDbAppender, Box<Error + Sync + Send>> {
+        if self.batch_size == 0 {
+            return Err("batch_size must be positive".into());
+        }
+
+        let write_url = format!("{}/api/v2/write?org={}&bucket={}&precision=ns",
+                                url.trim_end_matches('/'),
+                                http::encode_query(org),
+                                http::encode_query(bucket));
+        let client = Client::new(&write_url, self.timeout, self.max_retries)?;
+        let mut headers = vec![("Content-Type".to_owned(), "text/plain; charset=utf-8".to_owned())];
+        if let Some(token) = self.token {
+            headers.push(("Authorization".to_owned(), format!("Token {}", token)));
+        }
+
+        let bytes = Arc::new(AtomicU64::new(0));
+        let bytes2 = bytes.clone();
+        let url2 = write_url.clone();
+        let batcher = Batcher::new("log4rs-influxdb",
+                                   self.batch_size,
+                                   self.batch_timeout,
+                                   Bound {
+                                       capacity: self.queue_size,
+                                       backpressure: self.backpressure,
+                                   },
+                                   move |lines: Vec<String>| {
+            let body = lines.join("\n");
+            let headers = headers.iter().map(|h| (&*h.0, &*h.1)).collect::<Vec<_>>();
+            match client.post(&url2, &headers, body.as_bytes()) {
+                Ok(()) => {
+                    bytes2.fetch_add(body.len() as u64, Ordering::Relaxed);
+                }
+                Err(e) => ::handle_error(&*e),
+            }
+        })?;
+
+        Ok(InfluxDbAppender {
+            url: write_url,
+            measurement: self.measurement,
+            mdc_tags: self.mdc_tags,
+            batcher: batcher,
+            bytes: bytes,
+        })
+    }
+}
+
+/// A deserializer for the `InfluxDbAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: influxdb
+///
+/// # The base URL of the InfluxDB server. `https://` URLs require the
+/// # `http_tls` feature. Required.
+/// url: http://localhost:8086
+///
+/// # The organization and bucket points are written to. Required.
+/// org: my-org
+/// bucket: logs
+///
+/// # The API token used to authenticate. Optional. `env:NAME` or `file:PATH`
+/// # reads it from an environment variable or a file instead.
+/// token: env:INFLUXDB_TOKEN
+///
+/// # The measurement points are written to. Defaults to `log`.
+/// measurement: log
+///
+/// # MDC keys whose values are attached to points as tags. Optional.
+/// mdc_tags:
+///   - service
+///
+/// # The maximum number of points sent in a single write. Defaults to 1000.
+/// batch_size: 1000
+///
+/// # The maximum amount of time a point will wait to be written. Defaults to
+/// # 1 second.
+/// batch_timeout: 1 second
+///
+/// # The maximum number of events which may be waiting to be batched, after
+/// # which `backpressure` applies. Defaults to no limit.
+/// queue_size: 10000
+///
+/// # What happens when the queue is full: `block`, `drop_newest`,
+/// # `drop_oldest`, or `drop_below(level)`. Defaults to the top level
+/// # `backpressure` of the config file.
+/// backpressure: drop_oldest
+///
+/// # The timeout for each write request. Defaults to 10 seconds.
+/// timeout: 10 seconds
+///
+/// # The number of times a failed write is retried. Defaults to 5.
+/// max_retries: 5
+/// ```
+#[cfg(feature = "file")]
+pub struct InfluxDbAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for InfluxDbAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = InfluxDbAppenderConfig;
+
+    fn deserialize(&self,
+                   config: InfluxDbAppenderConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = InfluxDbAppender::builder();
+        if let Some(token) = config.token {
+            appender = appender.token(&token);
+        }
+        if let Some(measurement) = config.measurement {
+            appender = appender.measurement(&measurement);
+        }
+        for key in config.mdc_tags.unwrap_or_default() {
+            appender = appender.mdc_tag(&key);
+        }
+        if let Some(batch_size) = config.batch_size {
+            appender = appender.batch_size(batch_size);
+        }
+        if let Some(batch_timeout) = config.batch_timeout {
+            appender = appender.batch_timeout(batch_timeout);
+        }
+        if let Some(queue_size) = config.queue_size {
+            appender = appender.queue_size(queue_size);
+        }
+        if let Some(backpressure) = config.backpressure {
+            appender = appender.backpressure(backpressure);
+        }
+        if let Some(timeout) = config.timeout {
+            appender = appender.timeout(timeout);
+        }
+        if let Some(max_retries) = config.max_retries {
+            appender = appender.max_retries(max_retries);
+        }
+        Ok(Box::new(appender.build(&config.url, &config.org, &config.bucket)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn line_protocol() {
+        let appender = InfluxDbAppender::builder()
+            .measurement("app log")
+            .build("http://localhost:8086/", "org", "my bucket")
+            .unwrap();
+        assert_eq!(appender.url,
+                   "http://localhost:8086/api/v2/write?org=org&bucket=my%20bucket&precision=ns");
+
+        let tags = [("target".to_owned(), "foo::bar".to_owned()),
+                    ("level".to_owned(), "ERROR".to_owned()),
+                    ("host".to_owned(), "a=b, c".to_owned())];
+        let line = appender.line(&tags, "say \"hi\"\\", Some("src/main.rs"), Some(10), 123);
+        assert_eq!(line,
+                   "app\\ log,host=a\\=b\\,\\ c,level=ERROR,target=foo::bar \
+                    message=\"say \\\"hi\\\"\\\\\",file=\"src/main.rs\",line=10i 123");
+    }
+}
@@ -0,0 +1,234 @@
+//! The Android logcat appender.
+//!
+//! Requires the `logcat_appender` feature, and is only available on Android.
+
+use log::{Level, Record};
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use append::Append;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+// older versions of Android reject longer tags
+const MAX_TAG_LEN: usize = 23;
+
+#[link(name = "log")]
+extern "C" {
+    fn __android_log_write(prio: c_int, tag: *const c_char, text: *const c_char) -> c_int;
+}
+
+/// The logcat appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogcatAppenderConfig {
+    tag: Option<String>,
+    encoder: Option<EncoderConfig>,
+}
+
+// logcat's C API doesn't allow interior nul bytes, so they're stripped
+fn c_string(mut bytes: Vec<u8>) -> CString {
+    bytes.retain(|&b| b != 0);
+    CString::new(bytes).unwrap()
+}
+
+fn tag(tag: &[u8]) -> CString {
+    c_string(tag[..tag.len().min(MAX_TAG_LEN)].to_vec())
+}
+
+fn priority(level: Level) -> c_int {
+    // values of android_LogPriority
+    match level {
+        Level::Error => 6,
+        Level::Warn => 5,
+        Level::Info => 4,
+        Level::Debug => 3,
+        Level::Trace => 2,
+    }
+}
+
+/// An appender which writes log events to the Android system log, where they
+/// can be read with `logcat`.
+///
+/// Log levels map to the corresponding logcat priorities, with `Trace`
+/// mapping to `VERBOSE`. Events are tagged with a configured tag, or with the
+/// record's target if none is configured.
+pub struct LogcatAppender {
+    tag: Option<CString>,
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for LogcatAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("LogcatAppender")
+            .field("tag", &self.tag)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for LogcatAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut buf = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut buf), record)?;
+        while buf.ends_with(b"\n") {
+            buf.pop();
+        }
+        let len = buf.len() as u64;
+        let text = c_string(buf);
+
+        let target_tag;
+        let tag = match self.tag {
+            Some(ref tag) => tag,
+            None => {
+                target_tag = tag(record.target().as_bytes());
+                &target_tag
+            }
+        };
+
+        unsafe {
+            __android_log_write(priority(record.level()), tag.as_ptr(), text.as_ptr());
+        }
+        self.bytes.fetch_add(len, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl LogcatAppender {
+    /// Creates a new `LogcatAppender` builder.
+    pub fn builder() -> LogcatAppenderBuilder {
+        LogcatAppenderBuilder {
+            tag: None,
+            encoder: None,
+        }
+    }
+}
+
+/// A builder for `LogcatAppender`s.
+pub struct LogcatAppenderBuilder {
+    tag: Option<String>,
+    encoder: Option<Box<Encode>>,
+}
+
+impl LogcatAppenderBuilder {
+    /// Sets the tag events are logged with.
+    ///
+    /// Tags longer than 23 bytes are truncated. Defaults to the record's
+    /// target.
+    pub fn tag(mut self, tag: &str) -> LogcatAppenderBuilder {
+        self.tag = Some(tag.to_owned());
+        self
+    }
+
+    /// Sets the output encoder for the `LogcatAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> LogcatAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `LogcatAppenderBuilder`, producing a `LogcatAppender`.
+    pub fn build(self) -> LogcatAppender {
+        LogcatAppender {
+            tag: self.tag.map(|t| tag(t.as_bytes())),
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::new("{m}"))),
+            bytes: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A deserializer for the `LogcatAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: logcat
+///
+/// # The tag events are logged with. Defaults to the record's target.
+/// tag: MyApp
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern` with a
+/// # pattern of `{m}`, since logcat records the level, tag, and time itself.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct LogcatAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for LogcatAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = LogcatAppenderConfig;
+
+    fn deserialize(&self,
+                   config: LogcatAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = LogcatAppender::builder();
+        if let Some(tag) = config.tag {
+            appender = appender.tag(&tag);
+        }
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tags() {
+        let appender = LogcatAppender::builder().tag("an_unusually\0long_tag_name").build();
+        assert_eq!(appender.tag.unwrap().as_bytes(), b"an_unusuallylong_tag_n");
+        assert_eq!(tag(b"app::db").as_bytes(), b"app::db");
+    }
+
+    #[test]
+    fn priorities() {
+        let priorities = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace]
+            .iter()
+            .map(|&l| priority(l))
+            .collect::<Vec<_>>();
+        // ERROR, WARN, INFO, DEBUG, and VERBOSE
+        assert_eq!(priorities, [6, 5, 4, 3, 2]);
+    }
+
+    #[test]
+    fn append() {
+        let appender = LogcatAppender::builder().build();
+        let record = Record::builder()
+            .level(Level::Info)
+            .target("log4rs::append::logcat::test")
+            .args(format_args!("hello\n"))
+            .build();
+        appender.append(&record).unwrap();
+        // without the trailing newline
+        assert_eq!(appender.bytes_written(), Some(5));
+    }
+}
@@ -0,0 +1,573 @@
+//! The Grafana Loki appender.
+//!
+//! Requires the `loki_appender` feature. Pushing to `https://` URLs
+//! additionally requires the `http_tls` feature, and compressing requests
+//! requires the `gzip` feature.
+
+use log::Record;
+use log_mdc;
+use serde_json;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use append::Append;
+use append::batch::Batcher;
+use append::http::{self, Client};
+use append::queue::Bound;
+use config::Backpressure;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The Loki appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LokiAppenderConfig {
+    url: String,
+    tenant: Option<String>,
+    labels: Option<BTreeMap<String, String>>,
+    mdc_labels: Option<Vec<String>>,
+    level_label: Option<bool>,
+    batch_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    batch_timeout: Option<Duration>,
+    queue_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_backpressure", default)]
+    backpressure: Option<Backpressure>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    timeout: Option<Duration>,
+    max_retries: Option<u32>,
+    gzip: Option<bool>,
+    encoder: Option<EncoderConfig>,
+}
+
+struct Entry {
+    labels: BTreeMap<String, String>,
+    timestamp: u64,
+    line: String,
+}
+
+#[derive(Serialize)]
+struct Push<'a> {
+    streams: Vec<Stream<'a>>,
+}
+
+#[derive(Serialize)]
+struct Stream<'a> {
+    stream: &'a BTreeMap<String, String>,
+    values: Vec<(String, &'a str)>,
+}
+
+fn push_body(entries: &[Entry]) -> Vec<u8> {
+    let mut streams: BTreeMap<&BTreeMap<String, String>, Vec<(String, &str)>> = BTreeMap::new();
+    for entry in entries {
+        streams.entry(&entry.labels)
+            .or_default()
+            .push((entry.timestamp.to_string(), &entry.line));
+    }
+
+    let push = Push {
+        streams: streams.into_iter()
+            .map(|(labels, values)| {
+                Stream {
+                    stream: labels,
+                    values: values,
+                }
+            })
+            .collect(),
+    };
+    serde_json::to_vec(&push).expect("serializing a push request failed")
+}
+
+struct Pusher {
+    url: String,
+    tenant: Option<String>,
+    gzip: bool,
+    client: Client,
+}
+
+impl Pusher {
+    fn push(&self, entries: &[Entry]) -> Result<(), Box<Error + Sync + Send>> {
+        let mut body = push_body(entries);
+        let mut headers = vec![("Content-Type", "application/json")];
+        if self.gzip {
+            body = http::gzip(&body)?;
+            headers.push(("Content-Encoding", "gzip"));
+        }
+        if let Some(ref tenant) = self.tenant {
+            headers.push(("X-Scope-OrgID", tenant));
+        }
+        self.client.post(&self.url, &headers, &body)
+    }
+}
+
+/// An appender which pushes log events to Grafana Loki.
+///
+/// Events are batched on a background thread and sent to Loki's push API.
+/// Each event is placed in a stream identified by the appender's static
+/// labels, the values of the configured MDC keys, and optionally the event's
+/// level. The encoded event, with any trailing newline removed, forms the log
+/// line.
+///
+/// Pushes which are rejected with `429 Too Many Requests` or a server error
+/// are retried with exponential backoff.
+pub struct LokiAppender {
+    url: String,
+    labels: BTreeMap<String, String>,
+    mdc_labels: Vec<String>,
+    level_label: bool,
+    encoder: Box<Encode>,
+    batcher: Batcher<Entry>,
+    bytes: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for LokiAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("LokiAppender")
+            .field("url", &self.url)
+            .field("labels", &self.labels)
+            .field("mdc_labels", &self.mdc_labels)
+            .field("level_label", &self.level_label)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for LokiAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut buf = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut buf), record)?;
+        while buf.ends_with(b"\n") || buf.ends_with(b"\r") {
+            buf.pop();
+        }
+
+        let mut labels = self.labels.clone();
+        for key in &self.mdc_labels {
+            if let Some(value) = log_mdc::get(key, |v| v.map(|v| v.to_owned())) {
+                labels.insert(key.clone(), value);
+            }
+        }
+        if self.level_label {
+            labels.insert("level".to_owned(), record.level().to_string().to_lowercase());
+        }
+
+        self.batcher.push(record.level(), Entry {
+            labels: labels,
+            timestamp: timestamp(),
+            line: String::from_utf8_lossy(&buf).into_owned(),
+        })
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.batcher.flush()
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+fn timestamp() -> u64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_secs() * 1_000_000_000 + since_epoch.subsec_nanos() as u64
+}
+
+impl LokiAppender {
+    /// Creates a new `LokiAppender` builder.
+    pub fn builder() -> LokiAppenderBuilder {
+        LokiAppenderBuilder {
+            tenant: None,
+            labels: BTreeMap::new(),
+            mdc_labels: vec![],
+            level_label: false,
+            batch_size: 100,
+            batch_timeout: Duration::from_secs(1),
+            queue_size: None,
+            backpressure: None,
+            timeout: Duration::from_secs(10),
+            max_retries: 5,
+            gzip: false,
+            encoder: None,
+        }
+    }
+
+    /// Returns the number of events which were dropped because the queue of
+    /// events waiting to be batched was full.
+    pub fn dropped(&self) -> u64 {
+        self.batcher.dropped()
+    }
+}
+
+/// A builder for `LokiAppender`s.
+pub struct LokiAppenderBuilder {
+    tenant: Option<String>,
+    labels: BTreeMap<String, String>,
+    mdc_labels: Vec<String>,
+    level_label: bool,
+    batch_size: usize,
+    batch_timeout: Duration,
+    queue_size: Option<usize>,
+    backpressure: Option<Backpressure>,
+    timeout: Duration,
+    max_retries: u32,
+    gzip: bool,
+    encoder: Option<Box<Encode>>,
+}
+
+impl LokiAppenderBuilder {
+    /// Sets the tenant ID sent in the `X-Scope-OrgID` header.
+    pub fn tenant(mut self, tenant: &str) -> LokiAppenderBuilder {
+        self.tenant = Some(tenant.to_owned());
+        self
+    }
+
+    /// Adds a label which is attached to every event.
+    pub fn label(mut self, name: &str, value: &str) -> LokiAppenderBuilder {
+        self.labels.insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Adds an MDC key whose value, if present, is attached to events as a
+    /// label of the same name.
+    pub fn mdc_label(mut self, key: &str) -> LokiAppenderBuilder {
+        self.mdc_labels.push(key.to_owned());
+        self
+    }
+
+    /// Determines if the event's level is attached as a `level` label.
+    ///
+    /// Defaults to `false`.
+    pub fn level_label(mut self, level_label: bool) -> LokiAppenderBuilder {
+        self.level_label = level_label;
+        self
+    }
+
+    /// Sets the maximum number of events sent in a single push.
+    ///
+    /// Defaults to 100.
+    pub fn batch_size(mut self, batch_size: usize) -> LokiAppenderBuilder {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets the maximum amount of time an event will wait to be pushed.
+    ///
+    /// Defaults to 1 second.
+    pub fn batch_timeout(mut self, batch_timeout: Duration) -> LokiAppenderBuilder {
+        self.batch_timeout = batch_timeout;
+        self
+    }
+
+    /// Sets the maximum number of events which may be waiting to be batched,
+    /// after which the backpressure policy applies.
+    ///
+    /// Defaults to no limit.
+    pub fn queue_size(mut self, queue_size: usize) -> LokiAppenderBuilder {
+        self.queue_size = Some(queue_size);
+        self
+    }
+
+    /// Sets the policy applied when the queue of events is full.
+    ///
+    /// Defaults to the policy of the configuration.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> LokiAppenderBuilder {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    /// Sets the timeout for each push request.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> LokiAppenderBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of times a failed push is retried before its events
+    /// are dropped.
+    ///
+    /// Defaults to 5.
+    pub fn max_retries(mut self, max_retries: u32) -> LokiAppenderBuilder {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Determines if push requests are gzip-compressed.
+    ///
+    /// Requires the `gzip` feature. Defaults to `false`.
+    pub fn gzip(mut self, gzip: bool) -> LokiAppenderBuilder {
+        self.gzip = gzip;
+        self
+    }
+
+    /// Sets the output encoder for the `LokiAppender`.
+    ///
+    /// Defaults to a `PatternEncoder` with the pattern `{m}`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> LokiAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `LokiAppenderBuilder`, producing a `LokiAppender` which
+    /// pushes to the specified URL.
+    ///
+    /// The URL should refer to Loki's push endpoint, for example
+    /// `http://localhost:3100/loki/api/v1/push`.
+    pub fn build(self, url: &str) -> Result<LokiAppender, Box<Error + Sync + Send>> {
+        if self.gzip && !cfg!(feature = "gzip") {
+            return Err("gzip compression requires the `gzip` feature".into());
+        }
+        if self.batch_size == 0 {
+            return Err("batch_size must be positive".into());
+        }
+
+        let pusher = Pusher {
+            url: url.to_owned(),
+            tenant: self.tenant,
+            gzip: self.gzip,
+            client: Client::new(url, self.timeout, self.max_retries)?,
+        };
+        let bytes = Arc::new(AtomicU64::new(0));
+        let bytes2 = bytes.clone();
+        let batcher = Batcher::new("log4rs-loki",
+                                   self.batch_size,
+                                   self.batch_timeout,
+                                   Bound {
+                                       capacity: self.queue_size,
+                                       backpressure: self.backpressure,
+                                   },
+                                   move |entries: Vec<Entry>| {
+            match pusher.push(&entries) {
+                Ok(()) => {
+                    let len = entries.iter().map(|e| e.line.len() as u64).sum::<u64>();
+                    bytes2.fetch_add(len, Ordering::Relaxed);
+                }
+                Err(e) => ::handle_error(&*e),
+            }
+        })?;
+
+        Ok(LokiAppender {
+            url: url.to_owned(),
+            labels: self.labels,
+            mdc_labels: self.mdc_labels,
+            level_label: self.level_label,
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::new("{m}"))),
+            batcher: batcher,
+            bytes: bytes,
+        })
+    }
+}
+
+/// A deserializer for the `LokiAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: loki
+///
+/// # The URL of Loki's push endpoint. `https://` URLs require the `http_tls`
+/// # feature. Required.
+/// url: http://localhost:3100/loki/api/v1/push
+///
+/// # The tenant ID sent in the `X-Scope-OrgID` header. Optional.
+/// tenant: my-team
+///
+/// # Labels attached to every event. Optional.
+/// labels:
+///   app: my-app
+///   env: production
+///
+/// # MDC keys whose values are attached to events as labels. Optional.
+/// mdc_labels:
+///   - request_id
+///
+/// # If true, the event's level is attached as a `level` label. Defaults to
+/// # `false`.
+/// level_label: false
+///
+/// # The maximum number of events sent in a single push. Defaults to 100.
+/// batch_size: 100
+///
+/// # The maximum amount of time an event will wait to be pushed. Defaults to
+/// # 1 second.
+/// batch_timeout: 1 second
+///
+/// # The maximum number of events which may be waiting to be batched, after
+/// # which `backpressure` applies. Defaults to no limit.
+/// queue_size: 10000
+///
+/// # What happens when the queue is full: `block`, `drop_newest`,
+/// # `drop_oldest`, or `drop_below(level)`. Defaults to the top level
+/// # `backpressure` of the config file.
+/// backpressure: drop_oldest
+///
+/// # The timeout for each push request. Defaults to 10 seconds.
+/// timeout: 10 seconds
+///
+/// # The number of times a failed push is retried. Defaults to 5.
+/// max_retries: 5
+///
+/// # If true, push requests are gzip-compressed. Requires the `gzip` feature.
+/// # Defaults to `false`.
+/// gzip: false
+///
+/// # The encoder used to format the log line. Defaults to `kind: pattern`
+/// # with a pattern of `{m}`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct LokiAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for LokiAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = LokiAppenderConfig;
+
+    fn deserialize(&self,
+                   config: LokiAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = LokiAppender::builder();
+        if let Some(tenant) = config.tenant {
+            appender = appender.tenant(&tenant);
+        }
+        for (name, value) in config.labels.unwrap_or_default() {
+            appender = appender.label(&name, &value);
+        }
+        for key in config.mdc_labels.unwrap_or_default() {
+            appender = appender.mdc_label(&key);
+        }
+        if let Some(level_label) = config.level_label {
+            appender = appender.level_label(level_label);
+        }
+        if let Some(batch_size) = config.batch_size {
+            appender = appender.batch_size(batch_size);
+        }
+        if let Some(batch_timeout) = config.batch_timeout {
+            appender = appender.batch_timeout(batch_timeout);
+        }
+        if let Some(queue_size) = config.queue_size {
+            appender = appender.queue_size(queue_size);
+        }
+        if let Some(backpressure) = config.backpressure {
+            appender = appender.backpressure(backpressure);
+        }
+        if let Some(timeout) = config.timeout {
+            appender = appender.timeout(timeout);
+        }
+        if let Some(max_retries) = config.max_retries {
+            appender = appender.max_retries(max_retries);
+        }
+        if let Some(gzip) = config.gzip {
+            appender = appender.gzip(gzip);
+        }
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build(&config.url)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    fn entry(app: &str, timestamp: u64, line: &str) -> Entry {
+        let mut labels = BTreeMap::new();
+        labels.insert("app".to_owned(), app.to_owned());
+        Entry {
+            labels: labels,
+            timestamp: timestamp,
+            line: line.to_owned(),
+        }
+    }
+
+    #[test]
+    fn body() {
+        let entries = [entry("a", 1, "one"), entry("b", 2, "two"), entry("a", 3, "three")];
+        let body: Value = serde_json::from_slice(&push_body(&entries)).unwrap();
+        let expected: Value = serde_json::from_str(r#"{"streams":[
+                {"stream":{"app":"a"},"values":[["1","one"],["3","three"]]},
+                {"stream":{"app":"b"},"values":[["2","two"]]}
+            ]}"#)
+            .unwrap();
+        assert_eq!(body, expected);
+    }
+
+    // reads a request, returning its headers and body
+    fn read_request(stream: &mut BufReader<::std::net::TcpStream>) -> (Vec<String>, Vec<u8>) {
+        let mut headers = vec![];
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).unwrap();
+            let line = line.trim_end().to_owned();
+            if line.is_empty() {
+                break;
+            }
+            if line.to_lowercase().starts_with("content-length:") {
+                len = line[15..].trim().parse().unwrap();
+            }
+            headers.push(line);
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body).unwrap();
+        (headers, body)
+    }
+
+    #[test]
+    fn retry() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/loki/api/v1/push", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = vec![];
+            for response in &["429 Too Many Requests\r\nRetry-After: 0", "204 No Content"] {
+                let mut stream = BufReader::new(listener.accept().unwrap().0);
+                requests.push(read_request(&mut stream));
+                write!(stream.get_mut(),
+                       "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                       response)
+                    .unwrap();
+            }
+            requests
+        });
+
+        let pusher = Pusher {
+            url: url.clone(),
+            tenant: Some("foo".to_owned()),
+            gzip: false,
+            client: Client::new(&url, Duration::from_secs(5), 1).unwrap(),
+        };
+        pusher.push(&[entry("a", 1, "one")]).unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].0.iter().any(|h| h == "X-Scope-OrgID: foo"));
+        assert_eq!(requests[1].1, push_body(&[entry("a", 1, "one")]));
+    }
+}
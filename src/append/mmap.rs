@@ -0,0 +1,108 @@
+use memmap2::{MmapMut, MmapOptions};
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+const CHUNK_SIZE: u64 = 1024 * 1024;
+const SYNC_INTERVAL_SECS: u64 = 1;
+
+/// A file writer which copies output into a memory-mapped region of the file.
+///
+/// The file is grown in fixed-size chunks ahead of the data written to it, and
+/// the mapping is asynchronously synced to disk when flushed at most once per
+/// second. The file is truncated to the length of the data written when the
+/// writer is dropped; if the process exits abnormally, the file may be left
+/// with trailing NUL bytes.
+pub struct MmapWriter {
+    file: File,
+    map: MmapMut,
+    pos: u64,
+    last_sync: Instant,
+}
+
+impl MmapWriter {
+    /// Creates a new writer which will start writing at `pos`.
+    pub fn new(file: File, pos: u64) -> io::Result<MmapWriter> {
+        let map = map(&file, pos + CHUNK_SIZE)?;
+        Ok(MmapWriter {
+            file: file,
+            map: map,
+            pos: pos,
+            last_sync: Instant::now(),
+        })
+    }
+
+    fn reserve(&mut self, len: u64) -> io::Result<()> {
+        if self.pos + len <= self.map.len() as u64 {
+            return Ok(());
+        }
+
+        self.map.flush_async()?;
+        let size = cmp::max(self.map.len() as u64 + CHUNK_SIZE, self.pos + len);
+        self.map = map(&self.file, size)?;
+        Ok(())
+    }
+}
+
+fn map(file: &File, size: u64) -> io::Result<MmapMut> {
+    file.set_len(size)?;
+    // The mapping is only valid as long as nobody else truncates the file out
+    // from underneath us, which is the same expectation logging to it already
+    // carries.
+    unsafe { MmapOptions::new().len(size as usize).map_mut(file) }
+}
+
+impl Write for MmapWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.reserve(buf.len() as u64)?;
+        let start = self.pos as usize;
+        self.map[start..start + buf.len()].copy_from_slice(buf);
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.last_sync.elapsed() >= Duration::from_secs(SYNC_INTERVAL_SECS) {
+            self.map.flush_async()?;
+            self.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MmapWriter {
+    fn drop(&mut self) {
+        let _ = self.map.flush();
+        let _ = self.file.set_len(self.pos);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Write};
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn grow_and_truncate() {
+        let dir = TempDir::new("mmap_grow_and_truncate").unwrap();
+        let path = dir.path().join("foo.log");
+        File::create(&path).unwrap().write_all(b"hello ").unwrap();
+
+        let file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let mut writer = MmapWriter::new(file, 6).unwrap();
+        writer.write_all(b"world").unwrap();
+        let big = vec![b'a'; CHUNK_SIZE as usize + 10];
+        writer.write_all(&big).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut contents = vec![];
+        File::open(&path).unwrap().read_to_end(&mut contents).unwrap();
+        assert_eq!(contents.len(), 11 + big.len());
+        assert_eq!(&contents[..11], b"hello world");
+    }
+}
@@ -2,7 +2,11 @@
 
 use std::error::Error;
 use std::fmt;
-use log::LogRecord;
+use log::Record;
+
+use encode::Encode;
+#[cfg(feature = "file")]
+use log::LevelFilter;
 #[cfg(feature = "file")]
 use serde::{de, Deserialize, Deserializer};
 #[cfg(feature = "file")]
@@ -15,20 +19,149 @@ use file::Deserializable;
 #[cfg(feature = "file")]
 use filter::FilterConfig;
 
+#[cfg(feature = "audit_appender")]
+pub mod audit;
+#[cfg(feature = "clickhouse_appender")]
+pub mod clickhouse;
+#[cfg(all(windows, feature = "debug_string_appender"))]
+pub mod debug_string;
+#[cfg(feature = "encryption")]
+pub mod encrypt;
+#[cfg(all(unix, feature = "fd_appender"))]
+pub mod fd;
 #[cfg(feature = "file_appender")]
 pub mod file;
 #[cfg(feature = "console_appender")]
 pub mod console;
+#[cfg(feature = "honeycomb_appender")]
+pub mod honeycomb;
+#[cfg(feature = "influxdb_appender")]
+pub mod influxdb;
+#[cfg(all(target_os = "android", feature = "logcat_appender"))]
+pub mod logcat;
+#[cfg(feature = "newrelic_appender")]
+pub mod newrelic;
+#[cfg(all(any(target_os = "macos", target_os = "ios"), feature = "os_log_appender"))]
+pub mod os_log;
+#[cfg(feature = "relp_appender")]
+pub mod relp;
 #[cfg(feature = "rolling_file_appender")]
 pub mod rolling_file;
+#[cfg(feature = "loki_appender")]
+pub mod loki;
+#[cfg(feature = "shadow_appender")]
+pub mod shadow;
+#[cfg(feature = "spill_queue")]
+pub mod spill;
+#[cfg(feature = "statsd_appender")]
+pub mod statsd;
+#[cfg(feature = "temp_file_appender")]
+pub mod temp_file;
+#[cfg(all(unix, feature = "unix_socket_appender"))]
+pub mod unix_socket;
+#[cfg(feature = "wal_appender")]
+pub mod wal;
+#[cfg(all(target_arch = "wasm32", feature = "wasm_console_appender"))]
+pub mod wasm_console;
+#[cfg(feature = "websocket_appender")]
+pub mod websocket;
+#[cfg(any(feature = "loki_appender",
+          feature = "clickhouse_appender",
+          feature = "honeycomb_appender",
+          feature = "newrelic_appender",
+          feature = "influxdb_appender"))]
+mod batch;
+#[cfg(any(feature = "console_appender",
+          feature = "file_appender",
+          feature = "rolling_file_appender",
+          all(unix, feature = "fd_appender")))]
+mod counting;
+#[cfg(any(feature = "loki_appender",
+          feature = "clickhouse_appender",
+          feature = "honeycomb_appender",
+          feature = "newrelic_appender",
+          feature = "influxdb_appender"))]
+mod http;
+#[cfg(feature = "file_appender")]
+mod mmap;
+#[cfg(any(all(unix, feature = "unix_socket_appender"),
+          feature = "websocket_appender",
+          feature = "relp_appender"))]
+mod net;
+#[cfg(any(feature = "loki_appender",
+          feature = "clickhouse_appender",
+          feature = "honeycomb_appender",
+          feature = "newrelic_appender",
+          feature = "influxdb_appender",
+          feature = "shadow_appender"))]
+mod queue;
+#[cfg(any(feature = "file_appender", feature = "rolling_file_appender"))]
+mod symlink;
+#[cfg(all(unix, any(feature = "unix_socket_appender", feature = "fd_appender")))]
+mod systemd;
+#[cfg(feature = "file_appender")]
+mod thread_buffer;
 
 /// A trait implemented by log4rs appenders.
 ///
 /// Appenders take a log record and processes them, for example, by writing it
 /// to a file or the console.
+///
+/// Calls into an appender are serialized by a lock of its own unless it
+/// declares itself thread-safe with `thread_safe`, in which case it is called
+/// concurrently by every thread logging to it without any lock being held.
+/// Appenders which serialize their output, like the file appender, can avoid
+/// contention with per-thread buffers or a background queue.
 pub trait Append: fmt::Debug + Send + Sync + 'static {
-    /// Processes the provided `LogRecord`.
-    fn append(&self, record: &LogRecord) -> Result<(), Box<Error + Sync + Send>>;
+    /// Processes the provided `Record`.
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>>;
+
+    /// Determines if the appender may be called from several threads at once.
+    ///
+    /// The `append`, `flush`, and `reopen` methods of an appender which isn't
+    /// thread-safe are called by one thread at a time. Other appenders aren't
+    /// affected, as each has a lock of its own. The default implementation
+    /// returns `false`.
+    fn thread_safe(&self) -> bool {
+        false
+    }
+
+    /// Returns the total number of bytes the appender has written, if it
+    /// keeps track.
+    ///
+    /// This is reported in the appender's statistics. The default
+    /// implementation returns `None`.
+    fn bytes_written(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the encoder the appender formats events with, if it has one.
+    ///
+    /// This is reported by `Handle::topology`. The default implementation
+    /// returns `None`.
+    fn encoder(&self) -> Option<&Encode> {
+        None
+    }
+
+    /// Blocks until the events the appender has accepted so far have been
+    /// written out, like those queued for a background thread.
+    ///
+    /// This is called by `Handle::flush` and `Handle::shutdown`. The default
+    /// implementation does nothing, which is correct for appenders which
+    /// write each event before `append` returns.
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        Ok(())
+    }
+
+    /// Closes and reopens any files the appender writes to, so that events
+    /// go to a new file at the same path after the old one has been moved
+    /// away, as by `logrotate`.
+    ///
+    /// This is called by `Handle::reopen`. The default implementation does
+    /// nothing.
+    fn reopen(&self) -> Result<(), Box<Error + Sync + Send>> {
+        Ok(())
+    }
 }
 
 #[cfg(feature = "file")]
@@ -44,6 +177,8 @@ impl Deserializable for Append {
 pub struct AppenderConfig {
     /// The appender kind.
     pub kind: String,
+    /// The maximum log level the appender will accept, if restricted.
+    pub level: Option<LevelFilter>,
     /// The filters attached to the appender.
     pub filters: Vec<FilterConfig>,
     /// The appender configuration.
@@ -62,6 +197,11 @@ impl<'de> Deserialize<'de> for AppenderConfig {
             None => return Err(de::Error::missing_field("kind")),
         };
 
+        let level = match map.remove(&Value::String("level".to_owned())) {
+            Some(level) => Some(::priv_serde::de_filter(level).map_err(|e| e.into_error())?),
+            None => None,
+        };
+
         let filters = match map.remove(&Value::String("filters".to_owned())) {
             Some(filters) => filters.deserialize_into().map_err(|e| e.into_error())?,
             None => vec![],
@@ -69,6 +209,7 @@ impl<'de> Deserialize<'de> for AppenderConfig {
 
         Ok(AppenderConfig {
             kind: kind,
+            level: level,
             filters: filters,
             config: Value::Map(map),
         })
@@ -0,0 +1,230 @@
+//! Connection management shared by appenders which write to sockets.
+
+use antidote::Mutex;
+use std::cmp;
+use std::io;
+use std::time::{Duration, Instant};
+
+use append::spill::SpillQueue;
+#[cfg(feature = "file")]
+use file::Deserializers;
+
+const MIN_BACKOFF_MS: u64 = 100;
+const MAX_BACKOFF_MS: u64 = 30 * 1000;
+
+/// The spill queue section of an appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpillConfig {
+    pub path: String,
+    #[serde(deserialize_with = "::priv_serde::de_size_opt", default)]
+    pub segment_size: Option<u64>,
+    pub max_segments: Option<u64>,
+}
+
+#[cfg(feature = "file")]
+impl SpillConfig {
+    pub fn open(&self, deserializers: &Deserializers) -> io::Result<SpillQueue> {
+        let mut builder = SpillQueue::builder();
+        if let Some(segment_size) = self.segment_size {
+            builder = builder.segment_size(segment_size);
+        }
+        if let Some(max_segments) = self.max_segments {
+            builder = builder.max_segments(max_segments);
+        }
+        builder.open(deserializers.resolve_path(&self.path))
+    }
+}
+
+/// A means of establishing a connection and sending events over it.
+pub trait Connect: Send + Sync + 'static {
+    type Stream: Send;
+
+    fn connect(&self) -> io::Result<Self::Stream>;
+
+    fn send(&self, stream: &mut Self::Stream, event: &[u8]) -> io::Result<()>;
+}
+
+struct State<S> {
+    stream: Option<S>,
+    retry_at: Option<Instant>,
+    backoff: Duration,
+}
+
+/// Sends events over a lazily established connection, reconnecting with
+/// exponential backoff when it fails.
+///
+/// If a spill queue is provided, events are pushed to it first and drained
+/// in order whenever the connection is available.
+pub struct Sender<C: Connect> {
+    connect: C,
+    state: Mutex<State<C::Stream>>,
+    spill: Option<SpillQueue>,
+}
+
+impl<C: Connect> Sender<C> {
+    pub fn new(connect: C, spill: Option<SpillQueue>) -> Sender<C> {
+        Sender {
+            connect: connect,
+            state: Mutex::new(State {
+                stream: None,
+                retry_at: None,
+                backoff: Duration::from_millis(MIN_BACKOFF_MS),
+            }),
+            spill: spill,
+        }
+    }
+
+    pub fn connector(&self) -> &C {
+        &self.connect
+    }
+
+    pub fn send(&self, event: &[u8]) -> io::Result<()> {
+        let mut state = self.state.lock();
+        match self.spill {
+            Some(ref spill) => {
+                spill.push(event)?;
+                // the event is safely on disk, so failing to deliver it now
+                // isn't an error
+                let _ = self.drain(&mut state, spill);
+                Ok(())
+            }
+            None => self.send_inner(&mut state, event),
+        }
+    }
+
+    /// Delivers the events in the spill queue, if any, failing if they can't
+    /// all be delivered now.
+    pub fn flush(&self) -> io::Result<()> {
+        match self.spill {
+            Some(ref spill) => {
+                let mut state = self.state.lock();
+                // don't wait out the backoff, since this is the last chance
+                // to deliver the events before the caller exits
+                state.retry_at = None;
+                self.drain(&mut state, spill)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn drain(&self, state: &mut State<C::Stream>, spill: &SpillQueue) -> io::Result<()> {
+        while let Some(event) = spill.peek()? {
+            self.send_inner(state, &event)?;
+            spill.pop()?;
+        }
+        Ok(())
+    }
+
+    fn send_inner(&self, state: &mut State<C::Stream>, event: &[u8]) -> io::Result<()> {
+        let reused = state.stream.is_some();
+        match self.try_send(state, event) {
+            // a connection which has been idle may have been closed by the
+            // peer, so retry once on a fresh one
+            Err(_) if reused => {
+                state.retry_at = None;
+                self.try_send(state, event)
+            }
+            r => r,
+        }
+    }
+
+    fn try_send(&self, state: &mut State<C::Stream>, event: &[u8]) -> io::Result<()> {
+        if state.stream.is_none() {
+            if let Some(retry_at) = state.retry_at {
+                if Instant::now() < retry_at {
+                    return Err(io::Error::new(io::ErrorKind::NotConnected,
+                                              "waiting to reconnect"));
+                }
+            }
+
+            match self.connect.connect() {
+                Ok(stream) => {
+                    state.stream = Some(stream);
+                    state.retry_at = None;
+                    state.backoff = Duration::from_millis(MIN_BACKOFF_MS);
+                }
+                Err(e) => {
+                    state.retry_at = Some(Instant::now() + state.backoff);
+                    state.backoff = cmp::min(state.backoff * 2,
+                                             Duration::from_millis(MAX_BACKOFF_MS));
+                    return Err(e);
+                }
+            }
+        }
+
+        let result = self.connect.send(state.stream.as_mut().unwrap(), event);
+        if result.is_err() {
+            state.stream = None;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tempdir::TempDir;
+
+    use super::*;
+
+    struct Mock {
+        up: AtomicBool,
+        sent: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl Connect for Mock {
+        type Stream = ();
+
+        fn connect(&self) -> io::Result<()> {
+            if self.up.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::ConnectionRefused, "down"))
+            }
+        }
+
+        fn send(&self, _: &mut (), event: &[u8]) -> io::Result<()> {
+            self.sent.lock().unwrap().push(event.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn spill_while_down() {
+        let dir = TempDir::new("net_spill_while_down").unwrap();
+        let spill = SpillQueue::builder().open(dir.path()).unwrap();
+        let mock = Mock {
+            up: AtomicBool::new(false),
+            sent: Mutex::new(vec![]),
+        };
+        let sender = Sender::new(mock, Some(spill));
+
+        sender.send(b"one").unwrap();
+        sender.send(b"two").unwrap();
+        assert!(sender.connector().sent.lock().unwrap().is_empty());
+
+        sender.connector().up.store(true, Ordering::SeqCst);
+        sender.state.lock().retry_at = None;
+        sender.send(b"three").unwrap();
+        assert_eq!(*sender.connector().sent.lock().unwrap(),
+                   vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]);
+    }
+
+    #[test]
+    fn backoff() {
+        let mock = Mock {
+            up: AtomicBool::new(false),
+            sent: Mutex::new(vec![]),
+        };
+        let sender = Sender::new(mock, None);
+
+        assert_eq!(sender.send(b"one").unwrap_err().kind(),
+                   io::ErrorKind::ConnectionRefused);
+        sender.connector().up.store(true, Ordering::SeqCst);
+        assert_eq!(sender.send(b"two").unwrap_err().kind(),
+                   io::ErrorKind::NotConnected);
+    }
+}
@@ -0,0 +1,450 @@
+//! The New Relic Logs appender.
+//!
+//! Requires the `newrelic_appender` feature. New Relic's API is only served
+//! over HTTPS, so the `http_tls` feature is also required unless events are
+//! routed through a plain HTTP proxy. Compressing requests requires the
+//! `gzip` feature.
+
+use log::Record;
+use log_mdc;
+use serde_json::{self, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use append::Append;
+use append::batch::Batcher;
+use append::http::{self, Client};
+use append::queue::Bound;
+use config::Backpressure;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+const DEFAULT_URL: &str = "https://log-api.newrelic.com/log/v1";
+
+// MDC keys which are recognized as trace context, and the attribute each is
+// reported as
+const TRACE_KEYS: &[(&str, &str)] = &[("trace.id", "trace.id"),
+                                                              ("trace_id", "trace.id"),
+                                                              ("span.id", "span.id"),
+                                                              ("span_id", "span.id")];
+
+/// The New Relic appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewRelicAppenderConfig {
+    #[serde(deserialize_with = "::priv_serde::de_secret")]
+    license_key: String,
+    url: Option<String>,
+    attributes: Option<BTreeMap<String, String>>,
+    gzip: Option<bool>,
+    batch_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    batch_timeout: Option<Duration>,
+    queue_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_backpressure", default)]
+    backpressure: Option<Backpressure>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    timeout: Option<Duration>,
+    max_retries: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    timestamp: u64,
+    message: String,
+    attributes: BTreeMap<String, Value>,
+}
+
+#[derive(Serialize)]
+struct Common<'a> {
+    attributes: &'a BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    common: Common<'a>,
+    logs: &'a [Entry],
+}
+
+struct Sender {
+    url: String,
+    license_key: String,
+    attributes: BTreeMap<String, String>,
+    gzip: bool,
+    client: Client,
+}
+
+impl Sender {
+    fn body(&self, entries: &[Entry]) -> Vec<u8> {
+        let payload = [Payload {
+                           common: Common { attributes: &self.attributes },
+                           logs: entries,
+                       }];
+        serde_json::to_vec(&payload).expect("serializing a payload failed")
+    }
+
+    fn send(&self, entries: &[Entry]) -> Result<u64, Box<Error + Sync + Send>> {
+        let mut body = self.body(entries);
+        let mut headers = vec![("Content-Type", "application/json"),
+                               ("X-License-Key", &*self.license_key)];
+        if self.gzip {
+            body = http::gzip(&body)?;
+            headers.push(("Content-Encoding", "gzip"));
+        }
+        self.client.post(&self.url, &headers, &body)?;
+        Ok(body.len() as u64)
+    }
+}
+
+/// An appender which sends log events to the New Relic Log API.
+///
+/// Events are batched on a background thread. Each event carries the
+/// record's `level`, `logger` (its target), `module_path`, `file`, `line`,
+/// and `thread`, along with every MDC entry as an attribute. MDC entries
+/// named `trace.id` or `trace_id`, and `span.id` or `span_id`, are reported
+/// as the `trace.id` and `span.id` attributes which New Relic uses to
+/// correlate logs with distributed traces.
+pub struct NewRelicAppender {
+    url: String,
+    batcher: Batcher<Entry>,
+    bytes: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for NewRelicAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("NewRelicAppender")
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+impl Append for NewRelicAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut attributes = BTreeMap::new();
+        log_mdc::iter(|k, v| {
+            let name = TRACE_KEYS.iter().find(|t| t.0 == k).map_or(k, |t| t.1);
+            attributes.insert(name.to_owned(), Value::String(v.to_owned()));
+        });
+
+        attributes.insert("level".to_owned(), Value::String(record.level().to_string()));
+        attributes.insert("logger".to_owned(), Value::String(record.target().to_owned()));
+        if let Some(module_path) = record.module_path() {
+            attributes.insert("module_path".to_owned(), Value::String(module_path.to_owned()));
+        }
+        if let Some(file) = record.file() {
+            attributes.insert("file".to_owned(), Value::String(file.to_owned()));
+        }
+        if let Some(line) = record.line() {
+            attributes.insert("line".to_owned(), Value::from(line));
+        }
+        if let Some(name) = thread::current().name() {
+            attributes.insert("thread".to_owned(), Value::String(name.to_owned()));
+        }
+
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.batcher.push(record.level(), Entry {
+            timestamp: since_epoch.as_secs() * 1000 + since_epoch.subsec_nanos() as u64 / 1_000_000,
+            message: record.args().to_string(),
+            attributes: attributes,
+        })
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.batcher.flush()
+    }
+}
+
+impl NewRelicAppender {
+    /// Creates a new `NewRelicAppender` builder.
+    pub fn builder() -> NewRelicAppenderBuilder {
+        NewRelicAppenderBuilder {
+            url: DEFAULT_URL.to_owned(),
+            attributes: BTreeMap::new(),
+            gzip: cfg!(feature = "gzip"),
+            batch_size: 100,
+            batch_timeout: Duration::from_secs(1),
+            queue_size: None,
+            backpressure: None,
+            timeout: Duration::from_secs(10),
+            max_retries: 5,
+        }
+    }
+
+    /// Returns the number of events which were dropped because the queue of
+    /// events waiting to be batched was full.
+    pub fn dropped(&self) -> u64 {
+        self.batcher.dropped()
+    }
+}
+
+/// A builder for `NewRelicAppender`s.
+pub struct NewRelicAppenderBuilder {
+    url: String,
+    attributes: BTreeMap<String, String>,
+    gzip: bool,
+    batch_size: usize,
+    batch_timeout: Duration,
+    queue_size: Option<usize>,
+    backpressure: Option<Backpressure>,
+    timeout: Duration,
+    max_retries: u32,
+}
+
+impl NewRelicAppenderBuilder {
+    /// Sets the URL of the Log API endpoint.
+    ///
+    /// Defaults to `https://log-api.newrelic.com/log/v1`. Accounts in the EU
+    /// region should use `https://log-api.eu.newrelic.com/log/v1`.
+    pub fn url(mut self, url: &str) -> NewRelicAppenderBuilder {
+        self.url = url.to_owned();
+        self
+    }
+
+    /// Adds an attribute which is attached to every event, such as a service
+    /// name.
+    pub fn attribute(mut self, name: &str, value: &str) -> NewRelicAppenderBuilder {
+        self.attributes.insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Determines if requests are gzip-compressed.
+    ///
+    /// Requires the `gzip` feature. Defaults to `true` if the `gzip` feature
+    /// is enabled.
+    pub fn gzip(mut self, gzip: bool) -> NewRelicAppenderBuilder {
+        self.gzip = gzip;
+        self
+    }
+
+    /// Sets the maximum number of events sent in a single request.
+    ///
+    /// Defaults to 100.
+    pub fn batch_size(mut self, batch_size: usize) -> NewRelicAppenderBuilder {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets the maximum amount of time an event will wait to be sent.
+    ///
+    /// Defaults to 1 second.
+    pub fn batch_timeout(mut self, batch_timeout: Duration) -> NewRelicAppenderBuilder {
+        self.batch_timeout = batch_timeout;
+        self
+    }
+
+    /// Sets the maximum number of events which may be waiting to be batched,
+    /// after which the backpressure policy applies.
+    ///
+    /// Defaults to no limit.
+    pub fn queue_size(mut self, queue_size: usize) -> NewRelicAppenderBuilder {
+        self.queue_size = Some(queue_size);
+        self
+    }
+
+    /// Sets the policy applied when the queue of events is full.
+    ///
+    /// Defaults to the policy of the configuration.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> NewRelicAppenderBuilder {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    /// Sets the timeout for each request.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> NewRelicAppenderBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of times a failed request is retried before its events
+    /// are dropped.
+    ///
+    /// Defaults to 5.
+    pub fn max_retries(mut self, max_retries: u32) -> NewRelicAppenderBuilder {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Consumes the `NewRelicAppenderBuilder`, producing a
+    /// `NewRelicAppender` which authenticates with the specified license key.
+    pub fn build(self, license_key: &str) -> Result<NewRelicAppender, Box<Error + Sync + Send>> {
+        if self.gzip && !cfg!(feature = "gzip") {
+            return Err("gzip compression requires the `gzip` feature".into());
+        }
+        if self.batch_size == 0 {
+            return Err("batch_size must be positive".into());
+        }
+
+        let sender = Sender {
+            client: Client::new(&self.url, self.timeout, self.max_retries)?,
+            url: self.url.clone(),
+            license_key: license_key.to_owned(),
+            attributes: self.attributes,
+            gzip: self.gzip,
+        };
+        let bytes = Arc::new(AtomicU64::new(0));
+        let bytes2 = bytes.clone();
+        let batcher = Batcher::new("log4rs-newrelic",
+                                   self.batch_size,
+                                   self.batch_timeout,
+                                   Bound {
+                                       capacity: self.queue_size,
+                                       backpressure: self.backpressure,
+                                   },
+                                   move |entries: Vec<Entry>| {
+            match sender.send(&entries) {
+                Ok(len) => {
+                    bytes2.fetch_add(len, Ordering::Relaxed);
+                }
+                Err(e) => ::handle_error(&*e),
+            }
+        })?;
+
+        Ok(NewRelicAppender {
+            url: self.url,
+            batcher: batcher,
+            bytes: bytes,
+        })
+    }
+}
+
+/// A deserializer for the `NewRelicAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: newrelic
+///
+/// # The New Relic license key. Required. `env:NAME` or `file:PATH` reads it
+/// # from an environment variable or a file instead.
+/// license_key: env:NEW_RELIC_LICENSE_KEY
+///
+/// # The URL of the Log API endpoint. Defaults to
+/// # `https://log-api.newrelic.com/log/v1`.
+/// url: https://log-api.eu.newrelic.com/log/v1
+///
+/// # Attributes attached to every event. Optional.
+/// attributes:
+///   service.name: my-service
+///
+/// # If true, requests are gzip-compressed. Requires the `gzip` feature.
+/// # Defaults to `true` if the `gzip` feature is enabled.
+/// gzip: true
+///
+/// # The maximum number of events sent in a single request. Defaults to 100.
+/// batch_size: 100
+///
+/// # The maximum amount of time an event will wait to be sent. Defaults to
+/// # 1 second.
+/// batch_timeout: 1 second
+///
+/// # The maximum number of events which may be waiting to be batched, after
+/// # which `backpressure` applies. Defaults to no limit.
+/// queue_size: 10000
+///
+/// # What happens when the queue is full: `block`, `drop_newest`,
+/// # `drop_oldest`, or `drop_below(level)`. Defaults to the top level
+/// # `backpressure` of the config file.
+/// backpressure: drop_oldest
+///
+/// # The timeout for each request. Defaults to 10 seconds.
+/// timeout: 10 seconds
+///
+/// # The number of times a failed request is retried. Defaults to 5.
+/// max_retries: 5
+/// ```
+#[cfg(feature = "file")]
+pub struct NewRelicAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for NewRelicAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = NewRelicAppenderConfig;
+
+    fn deserialize(&self,
+                   config: NewRelicAppenderConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = NewRelicAppender::builder();
+        if let Some(url) = config.url {
+            appender = appender.url(&url);
+        }
+        for (name, value) in config.attributes.unwrap_or_default() {
+            appender = appender.attribute(&name, &value);
+        }
+        if let Some(gzip) = config.gzip {
+            appender = appender.gzip(gzip);
+        }
+        if let Some(batch_size) = config.batch_size {
+            appender = appender.batch_size(batch_size);
+        }
+        if let Some(batch_timeout) = config.batch_timeout {
+            appender = appender.batch_timeout(batch_timeout);
+        }
+        if let Some(queue_size) = config.queue_size {
+            appender = appender.queue_size(queue_size);
+        }
+        if let Some(backpressure) = config.backpressure {
+            appender = appender.backpressure(backpressure);
+        }
+        if let Some(timeout) = config.timeout {
+            appender = appender.timeout(timeout);
+        }
+        if let Some(max_retries) = config.max_retries {
+            appender = appender.max_retries(max_retries);
+        }
+        Ok(Box::new(appender.build(&config.license_key)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn body() {
+        let mut attributes = BTreeMap::new();
+        attributes.insert("service.name".to_owned(), "svc".to_owned());
+        let sender = Sender {
+            url: "http://localhost/".to_owned(),
+            license_key: "key".to_owned(),
+            attributes: attributes,
+            gzip: false,
+            client: Client::new("http://localhost/", Duration::from_secs(1), 0).unwrap(),
+        };
+
+        let mut attributes = BTreeMap::new();
+        attributes.insert("trace.id".to_owned(), Value::String("abc".to_owned()));
+        let entries = [Entry {
+                           timestamp: 1000,
+                           message: "hi".to_owned(),
+                           attributes: attributes,
+                       }];
+
+        let body: Value = serde_json::from_slice(&sender.body(&entries)).unwrap();
+        let expected: Value = serde_json::from_str(r#"[{
+                "common": {"attributes": {"service.name": "svc"}},
+                "logs": [{"timestamp": 1000, "message": "hi", "attributes": {"trace.id": "abc"}}]
+            }]"#)
+            .unwrap();
+        assert_eq!(body, expected);
+    }
+}
@@ -0,0 +1,292 @@
+//! The Apple unified logging appender.
+//!
+//! Requires the `os_log_appender` feature, and is only available on macOS and
+//! iOS.
+
+use antidote::Mutex;
+use log::{Level, Record};
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use append::Append;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+// values of os_log_type_t
+const OS_LOG_TYPE_DEFAULT: u8 = 0x00;
+const OS_LOG_TYPE_INFO: u8 = 0x01;
+const OS_LOG_TYPE_DEBUG: u8 = 0x02;
+const OS_LOG_TYPE_ERROR: u8 = 0x10;
+
+// the format string and argument buffer the `os_log` macro would generate
+// for `os_log(log, "%{public}s", message)`
+const FORMAT: &[u8] = b"%{public}s\0";
+const BUF_SUMMARY_HAS_NON_SCALAR: u8 = 0x02;
+const ARG_STRING_PUBLIC: u8 = 0x22;
+
+extern "C" {
+    static __dso_handle: c_void;
+
+    fn os_log_create(subsystem: *const c_char, category: *const c_char) -> *mut c_void;
+    fn os_log_type_enabled(log: *mut c_void, type_: u8) -> bool;
+    fn _os_log_impl(dso: *const c_void,
+                    log: *mut c_void,
+                    type_: u8,
+                    format: *const c_char,
+                    buf: *const u8,
+                    size: u32);
+}
+
+/// The os_log appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OsLogAppenderConfig {
+    subsystem: String,
+    category: Option<String>,
+    encoder: Option<EncoderConfig>,
+}
+
+// os_log_t objects are thread safe and are never released once created
+struct Log(*mut c_void);
+
+unsafe impl Send for Log {}
+
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap()
+}
+
+fn log_type(level: Level) -> u8 {
+    match level {
+        Level::Error => OS_LOG_TYPE_ERROR,
+        Level::Warn => OS_LOG_TYPE_DEFAULT,
+        Level::Info => OS_LOG_TYPE_INFO,
+        Level::Debug | Level::Trace => OS_LOG_TYPE_DEBUG,
+    }
+}
+
+/// An appender which writes log events to Apple's unified logging system,
+/// where they can be viewed in Console.app or with the `log` command line
+/// tool.
+///
+/// Events are logged under the configured subsystem with a category of the
+/// record's target, unless a fixed category is configured. `Error` maps to
+/// the `error` log type, `Warn` to `default`, `Info` to `info`, and `Debug`
+/// and `Trace` to `debug`. Messages are logged as public.
+pub struct OsLogAppender {
+    subsystem: String,
+    category: Option<String>,
+    logs: Mutex<HashMap<String, Log>>,
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for OsLogAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("OsLogAppender")
+            .field("subsystem", &self.subsystem)
+            .field("category", &self.category)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for OsLogAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let category = match self.category {
+            Some(ref category) => category,
+            None => record.target(),
+        };
+        let log = {
+            let mut logs = self.logs.lock();
+            let log = logs.entry(category.to_owned()).or_insert_with(|| {
+                let subsystem = c_string(&self.subsystem);
+                let category = c_string(category);
+                Log(unsafe { os_log_create(subsystem.as_ptr(), category.as_ptr()) })
+            });
+            log.0
+        };
+
+        let log_type = log_type(record.level());
+        if !unsafe { os_log_type_enabled(log, log_type) } {
+            return Ok(());
+        }
+
+        let mut message = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut message), record)?;
+        while message.ends_with(b"\n") {
+            message.pop();
+        }
+        let len = message.len() as u64;
+        message.retain(|&b| b != 0);
+        let message = CString::new(message).unwrap();
+
+        let ptr = message.as_ptr() as usize;
+        let mut buf = vec![BUF_SUMMARY_HAS_NON_SCALAR,
+                           1,
+                           ARG_STRING_PUBLIC,
+                           mem::size_of::<usize>() as u8];
+        buf.extend_from_slice(&ptr.to_ne_bytes());
+        unsafe {
+            _os_log_impl(&__dso_handle,
+                         log,
+                         log_type,
+                         FORMAT.as_ptr() as *const c_char,
+                         buf.as_ptr(),
+                         buf.len() as u32);
+        }
+        self.bytes.fetch_add(len, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl OsLogAppender {
+    /// Creates a new `OsLogAppender` builder.
+    pub fn builder() -> OsLogAppenderBuilder {
+        OsLogAppenderBuilder {
+            category: None,
+            encoder: None,
+        }
+    }
+}
+
+/// A builder for `OsLogAppender`s.
+pub struct OsLogAppenderBuilder {
+    category: Option<String>,
+    encoder: Option<Box<Encode>>,
+}
+
+impl OsLogAppenderBuilder {
+    /// Sets a fixed category events are logged with.
+    ///
+    /// Defaults to the record's target.
+    pub fn category(mut self, category: &str) -> OsLogAppenderBuilder {
+        self.category = Some(category.to_owned());
+        self
+    }
+
+    /// Sets the output encoder for the `OsLogAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> OsLogAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `OsLogAppenderBuilder`, producing an `OsLogAppender`
+    /// which logs under the specified subsystem, conventionally a reverse DNS
+    /// identifier such as `com.example.app`.
+    pub fn build(self, subsystem: &str) -> OsLogAppender {
+        OsLogAppender {
+            subsystem: subsystem.to_owned(),
+            category: self.category,
+            logs: Mutex::new(HashMap::new()),
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::new("{m}"))),
+            bytes: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A deserializer for the `OsLogAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: os_log
+///
+/// # The subsystem events are logged under. Required.
+/// subsystem: com.example.app
+///
+/// # A fixed category events are logged with. Defaults to the record's
+/// # target.
+/// category: network
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern` with a
+/// # pattern of `{m}`, since the unified logging system records the level,
+/// # time, and thread itself.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct OsLogAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for OsLogAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = OsLogAppenderConfig;
+
+    fn deserialize(&self,
+                   config: OsLogAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = OsLogAppender::builder();
+        if let Some(category) = config.category {
+            appender = appender.category(&category);
+        }
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build(&config.subsystem)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn log_types() {
+        assert_eq!(log_type(Level::Error), OS_LOG_TYPE_ERROR);
+        assert_eq!(log_type(Level::Warn), OS_LOG_TYPE_DEFAULT);
+        assert_eq!(log_type(Level::Info), OS_LOG_TYPE_INFO);
+        assert_eq!(log_type(Level::Trace), OS_LOG_TYPE_DEBUG);
+    }
+
+    #[test]
+    fn categories() {
+        let record = |target| {
+            Record::builder()
+                .level(Level::Error)
+                .target(target)
+                .args(format_args!("failed\n"))
+                .build()
+        };
+
+        let appender = OsLogAppender::builder().build("org.log4rs.test");
+        appender.append(&record("app::db")).unwrap();
+        appender.append(&record("app::http")).unwrap();
+        appender.append(&record("app::db")).unwrap();
+        let mut categories = appender.logs.lock().keys().cloned().collect::<Vec<_>>();
+        categories.sort();
+        assert_eq!(categories, ["app::db", "app::http"]);
+        // errors are always enabled, and the trailing newline isn't logged
+        assert_eq!(appender.bytes_written(), Some(18));
+
+        let appender = OsLogAppender::builder().category("fixed").build("org.log4rs.test");
+        appender.append(&record("app::db")).unwrap();
+        let categories = appender.logs.lock().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(categories, ["fixed"]);
+    }
+}
@@ -0,0 +1,224 @@
+//! A bounded queue between the logging threads and the background thread of
+//! an appender, which applies a `Backpressure` policy when it is full.
+
+use antidote::{Condvar, Mutex};
+use log::Level;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Instant;
+
+use config::Backpressure;
+
+/// The bound of a queue.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bound {
+    /// The maximum number of events in the queue, or `None` for no limit.
+    ///
+    /// A queue can't be created with a capacity of 0, since no event could
+    /// ever be pushed to it.
+    pub capacity: Option<usize>,
+    /// The policy when the queue is full, or `None` for the global one.
+    pub backpressure: Option<Backpressure>,
+}
+
+pub struct Queue<T> {
+    state: Mutex<State<T>>,
+    readable: Condvar,
+    writable: Condvar,
+    bound: Bound,
+    dropped: AtomicU64,
+}
+
+struct State<T> {
+    // items pushed without a level are control messages, which don't count
+    // towards the capacity and are never dropped
+    items: VecDeque<(Option<Level>, T)>,
+    events: usize,
+    closed: bool,
+}
+
+/// The queue has been closed.
+#[derive(Debug)]
+pub struct Closed;
+
+impl<T> Queue<T> {
+    pub fn new(bound: Bound) -> io::Result<Queue<T>> {
+        if bound.capacity == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "queue capacity must be at least 1"));
+        }
+
+        Ok(Queue {
+            state: Mutex::new(State {
+                items: VecDeque::new(),
+                events: 0,
+                closed: false,
+            }),
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+            bound: bound,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Adds an event to the back of the queue, returning `false` if it was
+    /// dropped by the backpressure policy.
+    pub fn push(&self, level: Level, item: T) -> Result<bool, Closed> {
+        let mut state = self.state.lock();
+        if let Some(capacity) = self.bound.capacity {
+            let backpressure = self.bound.backpressure.unwrap_or_else(::backpressure);
+            while !state.closed && state.events >= capacity {
+                match backpressure {
+                    Backpressure::DropBelow(min) if level <= min => {
+                        state = self.writable.wait(state);
+                    }
+                    Backpressure::Block => state = self.writable.wait(state),
+                    Backpressure::DropOldest if state.events > 0 => {
+                        let oldest = state.items.iter().position(|i| i.0.is_some()).unwrap();
+                        if let Some((Some(level), _)) = state.items.remove(oldest) {
+                            ::drop_event(level);
+                        }
+                        state.events -= 1;
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    _ => {
+                        ::drop_event(level);
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        if state.closed {
+            return Err(Closed);
+        }
+        state.items.push_back((Some(level), item));
+        state.events += 1;
+        self.readable.notify_one();
+        Ok(true)
+    }
+
+    /// Adds a control message to the back of the queue, regardless of the
+    /// queue's bound.
+    pub fn push_control(&self, item: T) -> Result<(), Closed> {
+        let mut state = self.state.lock();
+        if state.closed {
+            return Err(Closed);
+        }
+        state.items.push_back((None, item));
+        self.readable.notify_one();
+        Ok(())
+    }
+
+    /// Removes the item at the front of the queue, waiting until the
+    /// deadline if there is none.
+    ///
+    /// Items pushed before the queue was closed are still returned.
+    pub fn pop(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut state = self.state.lock();
+        loop {
+            if let Some((level, item)) = state.items.pop_front() {
+                if level.is_some() {
+                    state.events -= 1;
+                    self.writable.notify_one();
+                }
+                return Ok(item);
+            }
+            if state.closed {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            state = match deadline {
+                None => self.readable.wait(state),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    self.readable.wait_timeout(state, deadline - now).0
+                }
+            };
+        }
+    }
+
+    /// Closes the queue, so that pushing fails and `pop` returns
+    /// `Disconnected` once the remaining items have been removed.
+    pub fn close(&self) {
+        self.state.lock().closed = true;
+        self.readable.notify_all();
+        self.writable.notify_all();
+    }
+
+    /// Returns the number of events dropped by the backpressure policy.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn queue(backpressure: Backpressure) -> Queue<u32> {
+        Queue::new(Bound {
+            capacity: Some(2),
+            backpressure: Some(backpressure),
+        })
+        .unwrap()
+    }
+
+    fn drain(queue: &Queue<u32>) -> Vec<u32> {
+        queue.close();
+        let mut items = vec![];
+        while let Ok(item) = queue.pop(None) {
+            items.push(item);
+        }
+        items
+    }
+
+    #[test]
+    fn dropping() {
+        let newest = queue(Backpressure::DropNewest);
+        let oldest = queue(Backpressure::DropOldest);
+        for queue in &[&newest, &oldest] {
+            queue.push(Level::Info, 1).unwrap();
+            queue.push_control(0).unwrap();
+            queue.push(Level::Info, 2).unwrap();
+            queue.push(Level::Info, 3).unwrap();
+        }
+        assert_eq!(drain(&newest), [1, 0, 2]);
+        assert_eq!(drain(&oldest), [0, 2, 3]);
+        assert_eq!(newest.dropped(), 1);
+        assert_eq!(oldest.dropped(), 1);
+        assert!(newest.push(Level::Info, 4).is_err());
+    }
+
+    #[test]
+    fn block() {
+        let queue = Arc::new(queue(Backpressure::DropBelow(Level::Warn)));
+        queue.push(Level::Info, 1).unwrap();
+        queue.push(Level::Info, 2).unwrap();
+        assert!(!queue.push(Level::Info, 3).unwrap());
+
+        let queue2 = queue.clone();
+        let pusher = thread::spawn(move || queue2.push(Level::Error, 4).unwrap());
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(queue.pop(None).unwrap(), 1);
+        assert!(pusher.join().unwrap());
+        assert_eq!(drain(&queue), [2, 4]);
+
+        let unbounded = Queue::new(Bound::default()).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(10);
+        match unbounded.pop(Some(deadline)) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => panic!("expected a timeout"),
+        }
+        unbounded.push(Level::Trace, 5).unwrap();
+        assert_eq!(unbounded.pop(Some(deadline)).unwrap(), 5);
+    }
+}
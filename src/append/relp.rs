@@ -0,0 +1,455 @@
+//! The RELP appender.
+//!
+//! Requires the `relp_appender` feature.
+
+use chrono::Local;
+use log::{Level, Record};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process;
+use std::str;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use append::Append;
+#[cfg(feature = "file")]
+use append::net::SpillConfig;
+use append::net::{Connect, Sender};
+use append::spill::SpillQueue;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+const OFFERS: &str = "relp_version=0\nrelp_software=log4rs\ncommands=syslog";
+const MAX_TXNR: u64 = 999_999_999;
+
+/// The RELP appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelpAppenderConfig {
+    addr: String,
+    facility: Option<u8>,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    timeout: Option<Duration>,
+    encoder: Option<EncoderConfig>,
+    spill: Option<SpillConfig>,
+}
+
+struct RelpConnector {
+    addr: String,
+    timeout: Duration,
+}
+
+struct Session {
+    stream: BufReader<TcpStream>,
+    txnr: u64,
+}
+
+impl Session {
+    fn transact(&mut self, command: &str, data: &[u8]) -> io::Result<()> {
+        let txnr = self.txnr;
+        self.txnr = if txnr == MAX_TXNR { 1 } else { txnr + 1 };
+
+        let mut frame = format!("{} {} {}", txnr, command, data.len()).into_bytes();
+        if !data.is_empty() {
+            frame.push(b' ');
+            frame.extend_from_slice(data);
+        }
+        frame.push(b'\n');
+        self.stream.get_mut().write_all(&frame)?;
+
+        let (rsp_txnr, command, data) = read_frame(&mut self.stream)?;
+        if command != "rsp" {
+            return Err(io::Error::new(io::ErrorKind::ConnectionAborted,
+                                      format!("server sent `{}`", command)));
+        }
+        if rsp_txnr != txnr {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "response for unexpected transaction"));
+        }
+        if !data.starts_with(b"200") {
+            let data = String::from_utf8_lossy(&data);
+            return Err(io::Error::other(format!("server rejected transaction: {}", data)));
+        }
+        Ok(())
+    }
+}
+
+fn invalid_frame() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid RELP frame")
+}
+
+fn read_field<R: BufRead>(r: &mut R, delim: u8) -> io::Result<String> {
+    let mut buf = vec![];
+    r.read_until(delim, &mut buf)?;
+    if buf.pop() != Some(delim) {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    String::from_utf8(buf).map_err(|_| invalid_frame())
+}
+
+fn read_frame<R: BufRead>(r: &mut R) -> io::Result<(u64, String, Vec<u8>)> {
+    let txnr = read_field(r, b' ')?.parse().map_err(|_| invalid_frame())?;
+    let command = read_field(r, b' ')?;
+
+    // the length is followed by a space if there is data and by the trailer
+    // otherwise
+    let mut len = String::new();
+    loop {
+        let mut byte = [0];
+        r.read_exact(&mut byte)?;
+        match byte[0] {
+            b' ' => break,
+            b'\n' => return Ok((txnr, command, vec![])),
+            b => len.push(b as char),
+        }
+    }
+    let len: usize = len.parse().map_err(|_| invalid_frame())?;
+
+    let mut data = vec![0; len];
+    r.read_exact(&mut data)?;
+    let mut trailer = [0];
+    r.read_exact(&mut trailer)?;
+    if trailer[0] != b'\n' {
+        return Err(invalid_frame());
+    }
+    Ok((txnr, command, data))
+}
+
+impl Connect for RelpConnector {
+    type Stream = Session;
+
+    fn connect(&self) -> io::Result<Session> {
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound, "address resolved to nothing");
+        for addr in self.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    let mut session = Session {
+                        stream: BufReader::new(stream),
+                        txnr: 1,
+                    };
+                    session.transact("open", OFFERS.as_bytes())?;
+                    return Ok(session);
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    fn send(&self, session: &mut Session, event: &[u8]) -> io::Result<()> {
+        session.transact("syslog", event)
+    }
+}
+
+/// An appender which sends log events as syslog messages over RELP, the
+/// Reliable Event Logging Protocol.
+///
+/// Every message is acknowledged by the receiver before `append` returns. A
+/// message which is not acknowledged, for example because the connection
+/// dropped, is reported as an error, or retained in the spill queue and
+/// retransmitted once the connection is reestablished if one is configured.
+///
+/// Messages are formatted per RFC 5424, with the encoder's output, minus any
+/// trailing newline, as the message body.
+pub struct RelpAppender {
+    facility: u8,
+    hostname: String,
+    app_name: String,
+    sender: Sender<RelpConnector>,
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for RelpAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("RelpAppender")
+            .field("addr", &self.sender.connector().addr)
+            .field("facility", &self.facility)
+            .field("hostname", &self.hostname)
+            .field("app_name", &self.app_name)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for RelpAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut body = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut body), record)?;
+        while body.ends_with(b"\n") || body.ends_with(b"\r") {
+            body.pop();
+        }
+
+        let mut message = self.header(record.level(), &Local::now().to_rfc3339()).into_bytes();
+        message.extend_from_slice(&body);
+        self.sender.send(&message)?;
+        self.bytes.fetch_add(message.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.sender.flush()?;
+        Ok(())
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl RelpAppender {
+    /// Creates a new `RelpAppender` builder.
+    pub fn builder() -> RelpAppenderBuilder {
+        RelpAppenderBuilder {
+            facility: 1,
+            hostname: None,
+            app_name: None,
+            timeout: Duration::from_secs(10),
+            encoder: None,
+            spill: None,
+        }
+    }
+
+    fn header(&self, level: Level, timestamp: &str) -> String {
+        let severity = match level {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        format!("<{}>1 {} {} {} {} - - ",
+                self.facility as u32 * 8 + severity,
+                timestamp,
+                self.hostname,
+                self.app_name,
+                process::id())
+    }
+}
+
+/// A builder for `RelpAppender`s.
+pub struct RelpAppenderBuilder {
+    facility: u8,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    timeout: Duration,
+    encoder: Option<Box<Encode>>,
+    spill: Option<SpillQueue>,
+}
+
+impl RelpAppenderBuilder {
+    /// Sets the syslog facility code messages are sent with.
+    ///
+    /// Defaults to 1 (user-level messages).
+    pub fn facility(mut self, facility: u8) -> RelpAppenderBuilder {
+        self.facility = facility;
+        self
+    }
+
+    /// Sets the hostname messages are sent with.
+    ///
+    /// Defaults to the value of the `HOSTNAME` environment variable, or `-`
+    /// if it is not set.
+    pub fn hostname(mut self, hostname: &str) -> RelpAppenderBuilder {
+        self.hostname = Some(hostname.to_owned());
+        self
+    }
+
+    /// Sets the application name messages are sent with.
+    ///
+    /// Defaults to the name of the current executable.
+    pub fn app_name(mut self, app_name: &str) -> RelpAppenderBuilder {
+        self.app_name = Some(app_name.to_owned());
+        self
+    }
+
+    /// Sets the timeout for connecting and for each acknowledgement.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> RelpAppenderBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the output encoder for the message body.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> RelpAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Sets a spill queue which messages will be buffered in until they have
+    /// been acknowledged.
+    pub fn spill(mut self, spill: SpillQueue) -> RelpAppenderBuilder {
+        self.spill = Some(spill);
+        self
+    }
+
+    /// Consumes the `RelpAppenderBuilder`, producing a `RelpAppender` which
+    /// sends to the RELP receiver at the specified address.
+    pub fn build(self, addr: &str) -> RelpAppender {
+        let hostname = self.hostname
+            .or_else(|| env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| "-".to_owned());
+        let app_name = self.app_name
+            .or_else(|| {
+                env::current_exe()
+                    .ok()
+                    .and_then(|p| p.file_stem().and_then(|s| s.to_str()).map(|s| s.to_owned()))
+            })
+            .unwrap_or_else(|| "-".to_owned());
+        let connector = RelpConnector {
+            addr: addr.to_owned(),
+            timeout: self.timeout,
+        };
+
+        RelpAppender {
+            facility: self.facility,
+            hostname: hostname,
+            app_name: app_name,
+            sender: Sender::new(connector, self.spill),
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::new("{m}"))),
+            bytes: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A deserializer for the `RelpAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: relp
+///
+/// # The address of the RELP receiver. Required.
+/// addr: rsyslog.example.com:2514
+///
+/// # The syslog facility code messages are sent with. Defaults to 1
+/// # (user-level messages).
+/// facility: 1
+///
+/// # The hostname messages are sent with. Defaults to the value of the
+/// # `HOSTNAME` environment variable.
+/// hostname: web-1
+///
+/// # The application name messages are sent with. Defaults to the name of
+/// # the current executable.
+/// app_name: my-app
+///
+/// # The timeout for connecting and for each acknowledgement. Defaults to 10
+/// # seconds.
+/// timeout: 10 seconds
+///
+/// # A directory in which messages are queued until they are acknowledged.
+/// # Queued messages survive restarts of the process. Optional.
+/// spill:
+///   path: /var/spool/myapp/relp
+///
+/// # The encoder to use to format the message body. Defaults to
+/// # `kind: pattern` with a pattern of `{m}`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct RelpAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for RelpAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = RelpAppenderConfig;
+
+    fn deserialize(&self,
+                   config: RelpAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = RelpAppender::builder();
+        if let Some(facility) = config.facility {
+            appender = appender.facility(facility);
+        }
+        if let Some(hostname) = config.hostname {
+            appender = appender.hostname(&hostname);
+        }
+        if let Some(app_name) = config.app_name {
+            appender = appender.app_name(&app_name);
+        }
+        if let Some(timeout) = config.timeout {
+            appender = appender.timeout(timeout);
+        }
+        if let Some(spill) = config.spill {
+            appender = appender.spill(spill.open(deserializers)?);
+        }
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build(&config.addr)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn frames() {
+        let mut input = &b"1 rsp 6 200 OK\n0 serverclose 0\n"[..];
+        assert_eq!(read_frame(&mut input).unwrap(),
+                   (1, "rsp".to_owned(), b"200 OK".to_vec()));
+        assert_eq!(read_frame(&mut input).unwrap(),
+                   (0, "serverclose".to_owned(), vec![]));
+        assert!(read_frame(&mut input).is_err());
+    }
+
+    #[test]
+    fn session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut stream = BufReader::new(listener.accept().unwrap().0);
+            let mut received = vec![];
+            for _ in 0..2 {
+                let (txnr, command, data) = read_frame(&mut stream).unwrap();
+                writeln!(stream.get_mut(), "{} rsp 6 200 OK", txnr).unwrap();
+                received.push((txnr, command, data));
+            }
+            received
+        });
+
+        let appender = RelpAppender::builder()
+            .hostname("host")
+            .app_name("app")
+            .build(&addr);
+        let header = appender.header(Level::Warn, "2017-01-01T00:00:00+00:00");
+        assert_eq!(header,
+                   format!("<12>1 2017-01-01T00:00:00+00:00 host app {} - - ", process::id()));
+        appender.sender.send(b"hello").unwrap();
+
+        let received = server.join().unwrap();
+        assert_eq!(received[0].0, 1);
+        assert_eq!(received[0].1, "open");
+        assert_eq!(received[1], (2, "syslog".to_owned(), b"hello".to_vec()));
+    }
+}
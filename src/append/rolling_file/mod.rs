@@ -14,10 +14,16 @@
 //! For example, you may configure an appender to roll the log over once it
 //! reaches 50 megabytes, and to preserve the last 10 log files.
 //!
+//! Several processes may log to the same file if each of their appenders is
+//! marked as shared. The processes then take turns writing to the file, and
+//! only one of them rolls it over when the policy triggers, while the others
+//! reopen the new file afterwards. Shared log files are only supported on
+//! Unix.
+//!
 //! Requires the `rolling_file_appender` feature.
 
 use antidote::Mutex;
-use log::LogRecord;
+use log::Record;
 #[cfg(feature = "file")]
 use serde;
 #[cfg(feature = "file")]
@@ -27,10 +33,13 @@ use std::fmt;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, Write, BufWriter};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 #[cfg(feature = "file")]
 use serde_value::Value;
 
-use append::Append;
+use append::{symlink, Append};
+#[cfg(feature = "encryption")]
+use append::encrypt::{self, EncryptingWriter, EncryptionKey};
 use encode::{self, Encode};
 #[cfg(feature = "file")]
 use encode::EncoderConfig;
@@ -40,6 +49,8 @@ use file::{Deserialize, Deserializers};
 
 pub mod policy;
 
+mod shared;
+
 /// Configuration for the rolling file appender.
 #[cfg(feature = "file")]
 #[derive(Deserialize)]
@@ -49,6 +60,9 @@ pub struct RollingFileAppenderConfig {
     append: Option<bool>,
     encoder: Option<EncoderConfig>,
     policy: Policy,
+    symlink: Option<String>,
+    encryption_key_file: Option<String>,
+    shared: Option<bool>,
 }
 
 #[cfg(feature = "file")]
@@ -76,21 +90,52 @@ impl<'de> serde::Deserialize<'de> for Policy {
     }
 }
 
+enum FileWriter {
+    Plain(BufWriter<File>),
+    #[cfg(feature = "encryption")]
+    Encrypted(Box<EncryptingWriter<BufWriter<File>>>),
+}
+
 struct LogWriter {
-    file: BufWriter<File>,
+    file: FileWriter,
     len: u64,
+    // the generation of a shared log file when it was opened
+    generation: u64,
+}
+
+impl LogWriter {
+    // other processes may have written to a shared log file since the last
+    // event
+    fn update_len(&mut self) -> io::Result<()> {
+        match self.file {
+            FileWriter::Plain(ref w) => self.len = w.get_ref().metadata()?.len(),
+            // shared log files aren't encrypted
+            #[cfg(feature = "encryption")]
+            FileWriter::Encrypted(_) => {}
+        }
+        Ok(())
+    }
 }
 
 impl io::Write for LogWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.file.write(buf).map(|n| {
+        let result = match self.file {
+            FileWriter::Plain(ref mut w) => w.write(buf),
+            #[cfg(feature = "encryption")]
+            FileWriter::Encrypted(ref mut w) => w.write(buf),
+        };
+        result.map(|n| {
             self.len += n as u64;
             n
         })
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.file.flush()
+        match self.file {
+            FileWriter::Plain(ref mut w) => w.flush(),
+            #[cfg(feature = "encryption")]
+            FileWriter::Encrypted(ref mut w) => w.flush(),
+        }
     }
 }
 
@@ -113,8 +158,9 @@ impl<'a> LogFile<'a> {
     ///
     /// This is calculated by taking the size of the log file when it is opened
     /// and adding the number of bytes written. It may be inaccurate if any
-    /// writes have failed or if another process has modified the file
-    /// concurrently.
+    /// writes have failed, if another process has modified the file
+    /// concurrently, or if the file is encrypted, in which case plaintext
+    /// bytes are counted.
     pub fn len(&self) -> u64 {
         self.len
     }
@@ -139,6 +185,11 @@ pub struct RollingFileAppender {
     append: bool,
     encoder: Box<Encode>,
     policy: Box<policy::Policy>,
+    symlink: Option<PathBuf>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<EncryptionKey>,
+    shared: Option<shared::LockFile>,
+    bytes: AtomicU64,
 }
 
 impl fmt::Debug for RollingFileAppender {
@@ -148,28 +199,84 @@ impl fmt::Debug for RollingFileAppender {
             .field("append", &self.append)
             .field("encoder", &self.encoder)
             .field("policy", &self.policy)
+            .field("symlink", &self.symlink)
+            .field("shared", &self.shared.is_some())
             .finish()
     }
 }
 
 impl Append for RollingFileAppender {
-    fn append(&self, record: &LogRecord) -> Result<(), Box<Error + Sync + Send>> {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
         let mut writer = self.writer.lock();
 
+        // the lock is held until the event is written and the policy has run,
+        // so that a single process rolls the log when it is due
+        let guard = match self.shared {
+            Some(ref shared) => {
+                let guard = shared.lock()?;
+                let generation = shared.generation()?;
+                if writer.as_ref().is_some_and(|w| w.generation != generation) {
+                    // another process has rolled the log, and the file this
+                    // one has open is an archive
+                    *writer = None;
+                }
+                Some((guard, generation))
+            }
+            None => None,
+        };
+
         let len = {
             let writer = self.get_writer(&mut writer)?;
-            self.encoder.encode(writer, record)?;
+            if guard.is_some() {
+                writer.update_len()?;
+            }
+            let start = writer.len;
+            let result = self.encoder.encode(writer, record);
+            self.bytes.fetch_add(writer.len - start, Ordering::Relaxed);
+            result?;
             writer.flush()?;
             writer.len
         };
 
-        let mut file = LogFile {
-            writer: &mut writer,
-            path: &self.path,
-            len: len,
+        let result = {
+            let mut file = LogFile {
+                writer: &mut writer,
+                path: &self.path,
+                len: len,
+            };
+            self.policy.process(&mut file)
         };
 
-        self.policy.process(&mut file)
+        if let Some((guard, generation)) = guard {
+            if writer.is_none() {
+                guard.bump(generation)?;
+            }
+        }
+        result
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+
+    fn reopen(&self) -> Result<(), Box<Error + Sync + Send>> {
+        let mut writer = self.writer.lock();
+        let _guard = self.lock_shared()?;
+        if let Some(mut old) = writer.take() {
+            old.flush()?;
+        }
+        // a file which is still in place is appended to even if the appender
+        // truncates files when it first opens them
+        *writer = Some(self.open(true)?);
+        Ok(())
     }
 }
 
@@ -179,37 +286,87 @@ impl RollingFileAppender {
         RollingFileAppenderBuilder {
             append: true,
             encoder: None,
+            symlink: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            shared: false,
         }
     }
 
     fn get_writer<'a>(&self, writer: &'a mut Option<LogWriter>) -> io::Result<&'a mut LogWriter> {
         if writer.is_none() {
-            let file = OpenOptions::new()
-                .write(true)
-                .append(self.append)
-                .truncate(!self.append)
-                .create(true)
-                .open(&self.path)?;
-            let len = if self.append {
-                file.metadata()?.len()
-            } else {
-                0
-            };
-            *writer = Some(LogWriter {
-                file: BufWriter::with_capacity(1024, file),
-                len: len,
-            });
+            *writer = Some(self.open(self.append)?);
         }
 
         // :( unwrap
         Ok(writer.as_mut().unwrap())
     }
+
+    // must be called with the lock held if the file is shared
+    fn open(&self, append: bool) -> io::Result<LogWriter> {
+        // other processes may be writing to a shared file, so it is never
+        // truncated
+        let append = append || self.shared.is_some();
+        let file = OpenOptions::new()
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .create(true)
+            .open(&self.path)?;
+        let len = if append {
+            file.metadata()?.len()
+        } else {
+            0
+        };
+        let file = BufWriter::with_capacity(1024, file);
+        let generation = match self.shared {
+            Some(ref shared) => shared.generation()?,
+            None => 0,
+        };
+        let writer = LogWriter {
+            file: self.file_writer(file)?,
+            len: len,
+            generation: generation,
+        };
+        if let Some(ref symlink) = self.symlink {
+            symlink::update(symlink, &self.path)?;
+        }
+        Ok(writer)
+    }
+
+    fn lock_shared(&self) -> io::Result<Option<shared::Guard<'_>>> {
+        match self.shared {
+            Some(ref shared) => shared.lock().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    fn file_writer(&self, file: BufWriter<File>) -> io::Result<FileWriter> {
+        match self.encryption {
+            Some(ref key) => {
+                let tail = encrypt::file_tail(&self.path)?;
+                let writer = EncryptingWriter::append(key, &tail, file)?;
+                Ok(FileWriter::Encrypted(Box::new(writer)))
+            }
+            None => Ok(FileWriter::Plain(file)),
+        }
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn file_writer(&self, file: BufWriter<File>) -> io::Result<FileWriter> {
+        Ok(FileWriter::Plain(file))
+    }
 }
 
 /// A builder for the `RollingFileAppender`.
 pub struct RollingFileAppenderBuilder {
     append: bool,
     encoder: Option<Box<Encode>>,
+    symlink: Option<PathBuf>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<EncryptionKey>,
+    shared: bool,
 }
 
 impl RollingFileAppenderBuilder {
@@ -229,27 +386,103 @@ impl RollingFileAppenderBuilder {
         self
     }
 
+    /// Sets the path of a symlink which will be pointed at the active log
+    /// file each time it is opened, including after a rollover.
+    ///
+    /// Symlinks are only supported on Unix and Windows.
+    pub fn symlink<P>(mut self, symlink: P) -> RollingFileAppenderBuilder
+        where P: AsRef<Path>
+    {
+        self.symlink = Some(symlink.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the public key to which log files will be encrypted.
+    ///
+    /// Every log file, including each new one opened after a rollover, is
+    /// encrypted. See the `encrypt` module for details of the format; use
+    /// `encrypt::decrypt` to read files back.
+    ///
+    /// Requires the `encryption` Cargo feature.
+    #[cfg(feature = "encryption")]
+    pub fn encryption(mut self, key: EncryptionKey) -> RollingFileAppenderBuilder {
+        self.encryption = Some(key);
+        self
+    }
+
+    /// Determines if the log file is shared with other processes.
+    ///
+    /// The appenders of a shared log file coordinate through an advisory lock
+    /// on a lock file beside it, named after the log file with a `.lock`
+    /// suffix. Each event is written while holding the lock, and the policy
+    /// runs before it is released, so only one process rolls the log when it
+    /// is due. The lock file also records a generation marker which is bumped
+    /// by each roll, and the other processes reopen the new log file when
+    /// they see it change rather than writing to the archived one. The size of
+    /// the log file is read again before each event, so triggers see the
+    /// writes of every process.
+    ///
+    /// Every process sharing the file must enable this. Shared log files are
+    /// always appended to, regardless of `append`, and can't be encrypted.
+    /// Only supported on Unix.
+    ///
+    /// Defaults to `false`.
+    pub fn shared(mut self, shared: bool) -> RollingFileAppenderBuilder {
+        self.shared = shared;
+        self
+    }
+
     /// Constructs a `RollingFileAppender`.
     pub fn build<P>(self, path: P, policy: Box<policy::Policy>) -> io::Result<RollingFileAppender>
         where P: AsRef<Path>
     {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let shared = if self.shared {
+            if self.encrypted() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "shared log files can't be encrypted"));
+            }
+            Some(shared::LockFile::open(path)?)
+        } else {
+            None
+        };
+
         let appender = RollingFileAppender {
             writer: Mutex::new(None),
-            path: path.as_ref().to_owned(),
+            path: path.to_owned(),
             append: self.append,
             encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
             policy: policy,
+            symlink: self.symlink,
+            #[cfg(feature = "encryption")]
+            encryption: self.encryption,
+            shared: shared,
+            bytes: AtomicU64::new(0),
         };
 
-        if let Some(parent) = appender.path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
         // open the log file immediately
-        appender.get_writer(&mut appender.writer.lock())?;
+        {
+            let mut writer = appender.writer.lock();
+            let _guard = appender.lock_shared()?;
+            appender.get_writer(&mut writer)?;
+        }
 
         Ok(appender)
     }
+
+    #[cfg(feature = "encryption")]
+    fn encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn encrypted(&self) -> bool {
+        false
+    }
 }
 
 /// A deserializer for the `RollingFileAppender`.
@@ -266,6 +499,21 @@ impl RollingFileAppenderBuilder {
 /// # already exists. Defaults to `true`.
 /// append: true
 ///
+/// # If set, a symlink at this path will be maintained pointing to the active
+/// # log file. Optional.
+/// symlink: log/latest.log
+///
+/// # If set, log files are encrypted to the X25519 public key stored in this
+/// # file, either as 32 raw bytes or as 64 hexadecimal digits. Requires the
+/// # `encryption` Cargo feature. Optional.
+/// encryption_key_file: /etc/myapp/log.key
+///
+/// # Specifies if the log file is shared with other processes, which then
+/// # coordinate writes and rollovers through a `.lock` file beside it. Shared
+/// # log files are always appended to. Only supported on Unix, and not with
+/// # encryption. Defaults to `false`.
+/// shared: false
+///
 /// # The encoder to use to format output. Defaults to `kind: pattern`.
 /// encoder:
 ///   kind: pattern
@@ -302,17 +550,40 @@ impl Deserialize for RollingFileAppenderDeserializer {
         if let Some(append) = config.append {
             builder = builder.append(append);
         }
+        if let Some(symlink) = config.symlink {
+            builder = builder.symlink(deserializers.resolve_path(symlink));
+        }
+        if let Some(shared) = config.shared {
+            builder = builder.shared(shared);
+        }
+        if let Some(key_file) = config.encryption_key_file {
+            builder = encryption(builder, &deserializers.resolve_path(key_file))?;
+        }
         if let Some(encoder) = config.encoder {
             let encoder = deserializers.deserialize(&encoder.kind, encoder.config)?;
             builder = builder.encoder(encoder);
         }
 
         let policy = deserializers.deserialize(&config.policy.kind, config.policy.config)?;
-        let appender = builder.build(config.path, policy)?;
+        let appender = builder.build(deserializers.resolve_path(config.path), policy)?;
         Ok(Box::new(appender))
     }
 }
 
+#[cfg(all(feature = "file", feature = "encryption"))]
+fn encryption(builder: RollingFileAppenderBuilder,
+              key_file: &Path)
+              -> Result<RollingFileAppenderBuilder, Box<Error + Sync + Send>> {
+    Ok(builder.encryption(EncryptionKey::from_file(key_file)?))
+}
+
+#[cfg(all(feature = "file", not(feature = "encryption")))]
+fn encryption(_: RollingFileAppenderBuilder,
+              _: &Path)
+              -> Result<RollingFileAppenderBuilder, Box<Error + Sync + Send>> {
+    Err("encrypted log files require the `encryption` feature".into())
+}
+
 #[cfg(test)]
 mod test {
     use std::error::Error;
@@ -410,4 +681,147 @@ appenders:
         File::open(&path).unwrap().read_to_end(&mut contents).unwrap();
         assert_eq!(contents, b"");
     }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encryption() {
+        use append::encrypt::DecryptionKey;
+        use std::fs;
+
+        let dir = TempDir::new("rolling-file-encryption").unwrap();
+        let path = dir.path().join("encrypted.log");
+        let key = DecryptionKey::generate().unwrap();
+        let appender = RollingFileAppender::builder()
+            .encryption(key.encryption_key())
+            .build(&path, Box::new(NopPolicy))
+            .unwrap();
+
+        let mut writer = appender.writer.lock();
+        for (i, line) in [&b"one\n"[..], b"two\n"].iter().enumerate() {
+            let w = appender.get_writer(&mut writer).unwrap();
+            w.write_all(line).unwrap();
+            w.flush().unwrap();
+            // roll over, as a policy would
+            *writer = None;
+            fs::rename(&path, dir.path().join(format!("encrypted.log.{}", i))).unwrap();
+        }
+
+        let mut contents = vec![];
+        let archive = File::open(dir.path().join("encrypted.log.1")).unwrap();
+        encrypt::decrypt(&key, archive, &mut contents).unwrap();
+        assert_eq!(contents, b"two\n");
+    }
+
+    #[test]
+    #[cfg(all(unix,
+              feature = "compound_policy",
+              feature = "size_trigger",
+              feature = "fixed_window_roller"))]
+    fn shared() {
+        use append::rolling_file::policy::compound::CompoundPolicy;
+        use append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+        use append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+        use log::Level;
+
+        let dir = TempDir::new("rolling-file-shared").unwrap();
+        let path = dir.path().join("shared.log");
+        let pattern = format!("{}/shared.log.{{}}", dir.path().display());
+        // stands in for an appender in each of two processes
+        let appenders = (0..2)
+            .map(|_| {
+                let roller = FixedWindowRoller::builder().build(&pattern, 3).unwrap();
+                let policy = CompoundPolicy::new(Box::new(SizeTrigger::new(10)),
+                                                 Box::new(roller));
+                RollingFileAppender::builder()
+                    .encoder(Box::new(PatternEncoder::new("{m}{n}")))
+                    .shared(true)
+                    .build(&path, Box::new(policy))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(dir.path().join("shared.log.lock").exists());
+
+        for &(i, message) in &[(0, "first line"), (1, "b"), (0, "c")] {
+            appenders[i]
+                .append(&Record::builder()
+                    .level(Level::Info)
+                    .args(format_args!("{}", message))
+                    .build())
+                .unwrap();
+        }
+
+        // the second appender reopened the log after the first one rolled it
+        assert_eq!(fs::read_to_string(dir.path().join("shared.log.0")).unwrap(),
+                   "first line\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\nc\n");
+        assert!(!dir.path().join("shared.log.1").exists());
+
+        // a shared file isn't truncated by an appender opening it
+        RollingFileAppender::builder()
+            .append(false)
+            .shared(true)
+            .build(&path, Box::new(NopPolicy))
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\nc\n");
+    }
+
+    #[test]
+    #[cfg(all(unix,
+              feature = "compound_policy",
+              feature = "size_trigger",
+              feature = "fixed_window_roller"))]
+    fn shared_concurrent() {
+        use append::rolling_file::policy::compound::CompoundPolicy;
+        use append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+        use append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+        use log::Level;
+        use std::collections::BTreeSet;
+        use std::fs;
+        use std::thread;
+
+        let dir = TempDir::new("rolling-file-shared-concurrent").unwrap();
+        let path = dir.path().join("shared.log");
+        let pattern = format!("{}/shared.log.{{}}", dir.path().display());
+        let writers = (0..4)
+            .map(|i| {
+                let path = path.clone();
+                let pattern = pattern.clone();
+                thread::spawn(move || {
+                    let roller = FixedWindowRoller::builder().build(&pattern, 1000).unwrap();
+                    let policy = CompoundPolicy::new(Box::new(SizeTrigger::new(200)),
+                                                     Box::new(roller));
+                    let appender = RollingFileAppender::builder()
+                        .encoder(Box::new(PatternEncoder::new("{m}{n}")))
+                        .shared(true)
+                        .build(&path, Box::new(policy))
+                        .unwrap();
+                    for j in 0..200 {
+                        appender.append(&Record::builder()
+                                .level(Level::Info)
+                                .args(format_args!("{} {}", i, j))
+                                .build())
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let mut lines = vec![];
+        for entry in fs::read_dir(dir.path()).unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_name() != "shared.log.lock" {
+                let contents = fs::read_to_string(entry.path()).unwrap();
+                lines.extend(contents.lines().map(|l| l.to_owned()));
+            }
+        }
+        assert!(dir.path().join("shared.log.1").exists());
+        let expected = (0..4)
+            .flat_map(|i| (0..200).map(move |j| format!("{} {}", i, j)))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(lines.len(), expected.len());
+        assert_eq!(lines.into_iter().collect::<BTreeSet<_>>(), expected);
+    }
 }
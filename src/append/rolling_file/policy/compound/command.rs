@@ -0,0 +1,162 @@
+//! Post-roll commands.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Configuration for a post-roll command.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RollCommandConfig {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    timeout: Option<Duration>,
+}
+
+#[cfg(feature = "file")]
+impl RollCommandConfig {
+    /// Creates the `RollCommand` described by this configuration.
+    pub fn build(self) -> RollCommand {
+        let mut command = RollCommand::builder();
+        for arg in self.args {
+            command = command.arg(arg);
+        }
+        if let Some(timeout) = self.timeout {
+            command = command.timeout(timeout);
+        }
+        command.build(self.command)
+    }
+}
+
+/// An external command run after each successful roll.
+///
+/// The command is invoked with its configured arguments followed by the path
+/// of the rolled log file. It runs on a background thread so it does not hold
+/// up logging; if it has not exited when the timeout elapses it is killed.
+/// Failures to run the command, nonzero exit statuses, and timeouts are
+/// reported to stderr.
+#[derive(Debug)]
+pub struct RollCommand {
+    program: OsString,
+    args: Vec<OsString>,
+    timeout: Duration,
+}
+
+impl RollCommand {
+    /// Returns a new builder for a `RollCommand`.
+    pub fn builder() -> RollCommandBuilder {
+        RollCommandBuilder {
+            args: vec![],
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Starts the command for the specified rolled file.
+    pub fn run(&self, rolled: &Path) -> JoinHandle<()> {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).arg(rolled);
+        let program = self.program.clone();
+        let timeout = self.timeout;
+
+        thread::spawn(move || {
+            if let Err(e) = run(command, timeout) {
+                let e = io::Error::new(e.kind(),
+                                       format!("post-roll command `{}` failed: {}",
+                                               program.to_string_lossy(),
+                                               e));
+                ::handle_error(&e);
+            }
+        })
+    }
+}
+
+fn run(mut command: Command, timeout: Duration) -> io::Result<()> {
+    let mut child = command.spawn()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if status.success() {
+                return Ok(());
+            }
+            return Err(io::Error::other(format!("exited with {}", status)));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// A builder for `RollCommand`s.
+pub struct RollCommandBuilder {
+    args: Vec<OsString>,
+    timeout: Duration,
+}
+
+impl RollCommandBuilder {
+    /// Adds an argument passed to the command before the rolled path.
+    pub fn arg<S: Into<OsString>>(mut self, arg: S) -> RollCommandBuilder {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Sets the amount of time the command may run before it is killed.
+    ///
+    /// Defaults to 60 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> RollCommandBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Consumes the builder, producing a `RollCommand` which runs the
+    /// specified program.
+    pub fn build<S: Into<OsString>>(self, program: S) -> RollCommand {
+        RollCommand {
+            program: program.into(),
+            args: self.args,
+            timeout: self.timeout,
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::fs::File;
+    use std::io::Read;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn run_command() {
+        let dir = TempDir::new("roll_command").unwrap();
+        let out = dir.path().join("out");
+        let command = RollCommand::builder()
+            .arg("-c")
+            .arg(format!("echo \"$0\" > '{}'", out.display()))
+            .build("sh");
+        command.run(Path::new("archive/foo.0.log")).join().unwrap();
+
+        let mut contents = String::new();
+        File::open(&out).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "archive/foo.0.log\n");
+    }
+
+    #[test]
+    fn timeout() {
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        let start = Instant::now();
+        let err = run(command, Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
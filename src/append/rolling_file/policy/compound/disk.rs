@@ -0,0 +1,66 @@
+//! Free disk space queries.
+
+use std::io;
+use std::path::Path;
+
+// the directory containing the file, since the file itself may not exist
+fn dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Returns the number of bytes available to unprivileged users on the volume
+/// containing the specified file.
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use libc;
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = CString::new(dir(path).as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    unsafe {
+        let mut stat: libc::statvfs = mem::zeroed();
+        if libc::statvfs(dir.as_ptr(), &mut stat) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+}
+
+/// Returns the number of bytes available to the current user on the volume
+/// containing the specified file.
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use kernel32;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+
+    let mut dir = dir(path).as_os_str().encode_wide().collect::<Vec<u16>>();
+    dir.push(0);
+    let mut available = 0;
+    let ok = unsafe {
+        kernel32::GetDiskFreeSpaceExW(dir.as_ptr(),
+                                      &mut available,
+                                      ptr::null_mut(),
+                                      ptr::null_mut())
+    };
+    if ok == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(available)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn current_dir() {
+        assert!(free_space(Path::new("foo.log")).unwrap() > 0);
+    }
+}
@@ -10,14 +10,21 @@ use std::collections::BTreeMap;
 use std::error::Error;
 
 use append::rolling_file::LogFile;
+#[cfg(feature = "file")]
+use append::rolling_file::policy::compound::command::RollCommandConfig;
+use append::rolling_file::policy::compound::command::RollCommand;
 use append::rolling_file::policy::compound::roll::Roll;
 use append::rolling_file::policy::Policy;
 #[cfg(feature = "file")]
 use file::{Deserialize, Deserializers};
 
+pub mod command;
 pub mod roll;
 pub mod trigger;
 
+#[cfg(feature = "free_space_trigger")]
+mod disk;
+
 /// Configuration for the compound policy.
 #[cfg(feature = "file")]
 #[derive(Deserialize)]
@@ -25,6 +32,7 @@ pub mod trigger;
 pub struct CompoundPolicyConfig {
     trigger: Trigger,
     roller: Roller,
+    post_roll: Option<RollCommandConfig>,
 }
 
 #[cfg(feature = "file")]
@@ -81,11 +89,13 @@ impl<'de> serde::Deserialize<'de> for Roller {
 ///
 /// The trigger determines if the log file should roll, for example, by checking
 /// the size of the file. The roller processes the old log file, for example,
-/// by compressing it and moving it to a different location.
+/// by compressing it and moving it to a different location. An optional
+/// command can be run after each successful roll.
 #[derive(Debug)]
 pub struct CompoundPolicy {
     trigger: Box<trigger::Trigger>,
     roller: Box<Roll>,
+    post_roll: Option<RollCommand>,
 }
 
 impl CompoundPolicy {
@@ -94,8 +104,18 @@ impl CompoundPolicy {
         CompoundPolicy {
             trigger: trigger,
             roller: roller,
+            post_roll: None,
         }
     }
+
+    /// Sets a command to run after each successful roll.
+    ///
+    /// The command receives the path of the archived log file if the roller
+    /// reports one, and the path of the active log file otherwise.
+    pub fn post_roll(mut self, command: RollCommand) -> CompoundPolicy {
+        self.post_roll = Some(command);
+        self
+    }
 }
 
 impl Policy for CompoundPolicy {
@@ -103,6 +123,10 @@ impl Policy for CompoundPolicy {
         if self.trigger.trigger(log)? {
             log.roll();
             self.roller.roll(log.path())?;
+            if let Some(ref command) = self.post_roll {
+                let rolled = self.roller.archive_path().unwrap_or_else(|| log.path().to_owned());
+                command.run(&rolled);
+            }
         }
         Ok(())
     }
@@ -133,6 +157,22 @@ impl Policy for CompoundPolicy {
 ///
 ///   # The remainder of the configuration is passed to the roller's
 ///   # deserializer, and will vary based on the kind of roller.
+///
+/// # A command to run after each successful roll. Optional.
+/// post_roll:
+///
+///   # The program to run. Required.
+///   command: /usr/local/bin/archive-log
+///
+///   # Arguments passed to the program. The path of the rolled log file is
+///   # passed after them. Defaults to none.
+///   args:
+///     - --bucket
+///     - logs
+///
+///   # The amount of time the command may run before it is killed. Defaults
+///   # to 60 seconds.
+///   timeout: 30 seconds
 /// ```
 #[cfg(feature = "file")]
 pub struct CompoundPolicyDeserializer;
@@ -149,6 +189,10 @@ impl Deserialize for CompoundPolicyDeserializer {
                    -> Result<Box<Policy>, Box<Error + Sync + Send>> {
         let trigger = deserializers.deserialize(&config.trigger.kind, config.trigger.config)?;
         let roller = deserializers.deserialize(&config.roller.kind, config.roller.config)?;
-        Ok(Box::new(CompoundPolicy::new(trigger, roller)))
+        let mut policy = CompoundPolicy::new(trigger, roller);
+        if let Some(post_roll) = config.post_roll {
+            policy = policy.post_roll(post_roll.build());
+        }
+        Ok(Box::new(policy))
     }
 }
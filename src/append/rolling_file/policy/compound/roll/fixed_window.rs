@@ -5,8 +5,10 @@
 use std::error::Error;
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+#[cfg(feature = "free_space_trigger")]
+use append::rolling_file::policy::compound::disk;
 use append::rolling_file::policy::compound::roll::Roll;
 #[cfg(feature = "file")]
 use file::{Deserialize, Deserializers};
@@ -19,6 +21,9 @@ pub struct FixedWindowRollerConfig {
     pattern: String,
     base: Option<u32>,
     count: u32,
+    manifest: Option<bool>,
+    #[serde(deserialize_with = "::priv_serde::de_size_opt", default)]
+    min_free_space: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -73,6 +78,23 @@ impl Compression {
 /// If the file extension of the pattern is `.gz` and the `gzip` Cargo feature
 /// is enabled, the archive files will be gzip-compressed.
 ///
+/// If manifests are enabled, a sidecar file named by appending `.manifest` to
+/// the archive's path is written next to each archive. It records the
+/// archive's file name, byte size, SHA-256 digest, and the time range covered
+/// by the log file, one `key: value` pair per line:
+///
+/// ```text
+/// file: foo.0.log.gz
+/// size: 4096
+/// sha256: 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+/// start: 2017-04-26T10:00:00+00:00
+/// end: 2017-04-26T11:00:00+00:00
+/// ```
+///
+/// The start time is the log file's creation time and is omitted if the
+/// filesystem does not record it. Manifests are moved along with their
+/// archives. Manifest support requires the `manifest` Cargo feature.
+///
 /// Note that this roller will have to rename every archived file every time the
 /// log rolls over. Performance may be negatively impacted by specifying a large
 /// count.
@@ -82,12 +104,74 @@ pub struct FixedWindowRoller {
     compression: Compression,
     base: u32,
     count: u32,
+    #[cfg(feature = "manifest")]
+    manifest: bool,
+    min_free_space: Option<u64>,
 }
 
 impl FixedWindowRoller {
     /// Returns a new builder for the `FixedWindowRoller`.
     pub fn builder() -> FixedWindowRollerBuilder {
-        FixedWindowRollerBuilder { base: 0 }
+        FixedWindowRollerBuilder {
+            base: 0,
+            manifest: false,
+            min_free_space: None,
+        }
+    }
+
+    // deletes archives, oldest first, until enough space is free
+    #[cfg(feature = "free_space_trigger")]
+    fn free_space(&self, min_free_space: u64) -> io::Result<()> {
+        for i in (self.base..self.base + self.count).rev() {
+            let archive = self.pattern.replace("{}", &i.to_string());
+            if disk::free_space(Path::new(&archive))? >= min_free_space {
+                break;
+            }
+            // the manifest goes first so that it can't outlive its archive
+            #[cfg(feature = "manifest")]
+            {
+                if self.manifest {
+                    manifest::take_manifest(&archive)?;
+                }
+            }
+            match fs::remove_file(&archive) {
+                Ok(()) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "free_space_trigger"))]
+    fn free_space(&self, _: u64) -> io::Result<()> {
+        unreachable!()
+    }
+
+    // Removes the manifests of the archives, returning their contents. They
+    // are only written back once every archive has been moved, so that a roll
+    // which fails partway leaves archives without manifests rather than with
+    // ones describing other files.
+    #[cfg(feature = "manifest")]
+    fn take_manifests(&self) -> io::Result<Vec<Option<String>>> {
+        if !self.manifest {
+            return Ok(vec![]);
+        }
+        (self.base..self.base + self.count)
+            .map(|i| manifest::take_manifest(&self.pattern.replace("{}", &i.to_string())))
+            .collect()
+    }
+
+    // writes the manifests taken by `take_manifests` beside the archives they
+    // were moved to, dropping that of the oldest archive
+    #[cfg(feature = "manifest")]
+    fn put_manifests(&self, manifests: Vec<Option<String>>) -> io::Result<()> {
+        for (i, contents) in (self.base + 1..self.base + self.count).zip(manifests) {
+            if let Some(contents) = contents {
+                manifest::put_manifest(&self.pattern.replace("{}", &i.to_string()), &contents)?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -110,6 +194,9 @@ impl Roll for FixedWindowRoller {
             _ => false, // Only case that can actually happen is (None, None)
         };
 
+        #[cfg(feature = "manifest")]
+        let manifests = self.take_manifests()?;
+
         for i in (self.base..self.base + self.count - 1).rev() {
             let src = self.pattern.replace("{}", &i.to_string());
             let dst = self.pattern.replace("{}", &(i + 1).to_string());
@@ -123,7 +210,141 @@ impl Roll for FixedWindowRoller {
             move_file(&src, &dst)?;
         }
 
-        self.compression.compress(file, &dst_0).map_err(Into::into)
+        #[cfg(feature = "manifest")]
+        let times = if self.manifest {
+            self.put_manifests(manifests)?;
+            Some(manifest::Times::from_path(file)?)
+        } else {
+            None
+        };
+
+        self.compression.compress(file, &dst_0)?;
+
+        #[cfg(feature = "manifest")]
+        {
+            if let Some(times) = times {
+                manifest::write_manifest(&dst_0, &times)?;
+            }
+        }
+
+        if let Some(min_free_space) = self.min_free_space {
+            self.free_space(min_free_space)?;
+        }
+        Ok(())
+    }
+
+    fn archive_path(&self) -> Option<PathBuf> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(PathBuf::from(self.pattern.replace("{}", &self.base.to_string())))
+        }
+    }
+}
+
+#[cfg(feature = "manifest")]
+mod manifest {
+    use chrono::{TimeZone, UTC};
+    use sha2::{Digest, Sha256};
+    use std::fs::{self, File};
+    use std::io::{self, Read, Write};
+    use std::path::Path;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub struct Times {
+        start: Option<SystemTime>,
+        end: SystemTime,
+    }
+
+    impl Times {
+        pub fn from_path(path: &Path) -> io::Result<Times> {
+            let metadata = fs::metadata(path)?;
+            Ok(Times {
+                start: metadata.created().ok(),
+                end: metadata.modified()?,
+            })
+        }
+    }
+
+    fn manifest_path(archive: &str) -> String {
+        format!("{}.manifest", archive)
+    }
+
+    fn file_name(archive: &str) -> String {
+        match Path::new(archive).file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => archive.to_owned(),
+        }
+    }
+
+    fn format_time(time: SystemTime) -> String {
+        let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => (-(e.duration().as_secs() as i64), 0),
+        };
+        UTC.timestamp(secs, nanos).to_rfc3339()
+    }
+
+    pub fn write_manifest(archive: &str, times: &Times) -> io::Result<()> {
+        let mut file = File::open(archive)?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        let mut buf = [0; 8 * 1024];
+        loop {
+            let len = file.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+            size += len as u64;
+        }
+
+        let mut digest = String::new();
+        for byte in hasher.finalize().iter() {
+            digest.push_str(&format!("{:02x}", byte));
+        }
+
+        let mut out = File::create(manifest_path(archive))?;
+        writeln!(out, "file: {}", file_name(archive))?;
+        writeln!(out, "size: {}", size)?;
+        writeln!(out, "sha256: {}", digest)?;
+        if let Some(start) = times.start {
+            writeln!(out, "start: {}", format_time(start))?;
+        }
+        writeln!(out, "end: {}", format_time(times.end))?;
+        out.sync_all()
+    }
+
+    /// Removes the manifest of `archive`, returning its contents, if there
+    /// is one.
+    pub fn take_manifest(archive: &str) -> io::Result<Option<String>> {
+        let path = manifest_path(archive);
+        let mut contents = String::new();
+        match File::open(&path) {
+            Ok(mut file) => file.read_to_string(&mut contents)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        fs::remove_file(path)?;
+        Ok(Some(contents))
+    }
+
+    /// Writes the manifest taken from another archive by `take_manifest` for
+    /// `archive`, rewriting the recorded file name to match.
+    pub fn put_manifest(archive: &str, contents: &str) -> io::Result<()> {
+        let mut out = String::new();
+        for line in contents.lines() {
+            if line.starts_with("file: ") {
+                out.push_str(&format!("file: {}", file_name(archive)));
+            } else {
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+
+        let mut file = File::create(manifest_path(archive))?;
+        file.write_all(out.as_bytes())?;
+        file.sync_all()
     }
 }
 
@@ -144,6 +365,8 @@ fn move_file<P>(src: P, dst: &str) -> io::Result<()>
 /// A builder for the `FixedWindowRoller`.
 pub struct FixedWindowRollerBuilder {
     base: u32,
+    manifest: bool,
+    min_free_space: Option<u64>,
 }
 
 impl FixedWindowRollerBuilder {
@@ -155,6 +378,28 @@ impl FixedWindowRollerBuilder {
         self
     }
 
+    /// Determines if a checksum manifest will be written alongside each
+    /// archived log file.
+    ///
+    /// Requires the `manifest` Cargo feature. Defaults to `false`.
+    pub fn manifest(mut self, manifest: bool) -> FixedWindowRollerBuilder {
+        self.manifest = manifest;
+        self
+    }
+
+    /// Sets an amount of free space, in bytes, to maintain on the volume
+    /// containing the archives.
+    ///
+    /// After each roll, archived log files are deleted, oldest first, until
+    /// at least this much space is free or no archives remain. This pairs
+    /// with the `FreeSpaceTrigger` to keep logs from filling the disk.
+    ///
+    /// Requires the `free_space_trigger` Cargo feature. Defaults to `None`.
+    pub fn min_free_space(mut self, min_free_space: u64) -> FixedWindowRollerBuilder {
+        self.min_free_space = Some(min_free_space);
+        self
+    }
+
     /// Constructs a new `FixedWindowRoller`.
     ///
     /// `pattern` must contain at least one instance of `{}`, all of which will
@@ -177,11 +422,22 @@ impl FixedWindowRollerBuilder {
             _ => Compression::None,
         };
 
+        if self.manifest && cfg!(not(feature = "manifest")) {
+            return Err("checksum manifests require the `manifest` feature".into());
+        }
+
+        if self.min_free_space.is_some() && cfg!(not(feature = "free_space_trigger")) {
+            return Err("a minimum free space requires the `free_space_trigger` feature".into());
+        }
+
         Ok(FixedWindowRoller {
             pattern: pattern.to_owned(),
             compression: compression,
             base: self.base,
             count: count,
+            #[cfg(feature = "manifest")]
+            manifest: self.manifest,
+            min_free_space: self.min_free_space,
         })
     }
 }
@@ -204,6 +460,16 @@ impl FixedWindowRollerBuilder {
 ///
 /// # The base value for archived log indices. Defaults to 0.
 /// base: 1
+///
+/// # If set, a checksum manifest is written alongside each archived log.
+/// # Requires the `manifest` Cargo feature. Defaults to `false`.
+/// manifest: false
+///
+/// # If set, archived logs are deleted after each roll, oldest first, until
+/// # at least this much space is free on their volume, in the same format as
+/// # the size trigger's limit. Requires the `free_space_trigger` Cargo
+/// # feature. Optional.
+/// min_free_space: 1 gb
 /// ```
 #[cfg(feature = "file")]
 pub struct FixedWindowRollerDeserializer;
@@ -216,14 +482,22 @@ impl Deserialize for FixedWindowRollerDeserializer {
 
     fn deserialize(&self,
                    config: FixedWindowRollerConfig,
-                   _: &Deserializers)
+                   deserializers: &Deserializers)
                    -> Result<Box<Roll>, Box<Error + Sync + Send>> {
         let mut builder = FixedWindowRoller::builder();
         if let Some(base) = config.base {
             builder = builder.base(base);
         }
+        if let Some(manifest) = config.manifest {
+            builder = builder.manifest(manifest);
+        }
+        if let Some(min_free_space) = config.min_free_space {
+            builder = builder.min_free_space(min_free_space);
+        }
 
-        Ok(Box::new(builder.build(&config.pattern, config.count)?))
+        let pattern = deserializers.resolve_path(config.pattern);
+        let pattern = pattern.to_str().ok_or("the roller's pattern is not valid UTF-8")?;
+        Ok(Box::new(builder.build(pattern, config.count)?))
     }
 }
 
@@ -279,6 +553,33 @@ mod test {
         assert_eq!(contents, b"file3");
     }
 
+    #[test]
+    #[cfg(feature = "free_space_trigger")]
+    fn min_free_space() {
+        let dir = TempDir::new("min_free_space").unwrap();
+
+        let base = dir.path().to_str().unwrap();
+        let roller = FixedWindowRoller::builder()
+            .min_free_space(0)
+            .build(&format!("{}/foo.log.{{}}", base), 2)
+            .unwrap();
+        let greedy = FixedWindowRoller::builder()
+            .min_free_space(u64::MAX)
+            .build(&format!("{}/foo.log.{{}}", base), 2)
+            .unwrap();
+
+        let file = dir.path().join("foo.log");
+        File::create(&file).unwrap().write_all(b"file1").unwrap();
+        roller.roll(&file).unwrap();
+        assert!(dir.path().join("foo.log.0").exists());
+
+        // no amount of deletion can free this much, so every archive goes
+        File::create(&file).unwrap().write_all(b"file2").unwrap();
+        greedy.roll(&file).unwrap();
+        assert!(!dir.path().join("foo.log.0").exists());
+        assert!(!dir.path().join("foo.log.1").exists());
+    }
+
     #[test]
     fn rotation_no_trivial_base() {
         let dir = TempDir::new("rotation_no_trivial_base").unwrap();
@@ -406,4 +707,56 @@ mod test {
 
         assert_eq!(contents, actual);
     }
+
+    #[test]
+    #[cfg_attr(not(feature = "manifest"), ignore)]
+    fn manifest() {
+        let dir = TempDir::new("manifest").unwrap();
+
+        let pattern = dir.path().join("foo.{}.log");
+        let roller = FixedWindowRoller::builder()
+            .manifest(true)
+            .build(pattern.to_str().unwrap(), 2)
+            .unwrap();
+
+        let file = dir.path().join("foo.log");
+        File::create(&file).unwrap().write_all(b"test").unwrap();
+
+        roller.roll(&file).unwrap();
+
+        let mut contents = String::new();
+        File::open(dir.path().join("foo.0.log.manifest"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(contents.starts_with("file: foo.0.log\nsize: 4\n\
+                                      sha256: 9f86d081884c7d659a2feaa0c55ad015\
+                                      a3bf4f1b2b0b822cd15d6c15b0f00a08\n"));
+        assert!(contents.contains("\nend: "));
+
+        File::create(&file).unwrap().write_all(b"test2").unwrap();
+
+        roller.roll(&file).unwrap();
+
+        contents.clear();
+        File::open(dir.path().join("foo.1.log.manifest"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(contents.starts_with("file: foo.1.log\nsize: 4\n"));
+        assert!(dir.path().join("foo.0.log.manifest").exists());
+
+        // the manifest of the oldest archive is dropped along with it
+        File::create(&file).unwrap().write_all(b"test33").unwrap();
+
+        roller.roll(&file).unwrap();
+
+        contents.clear();
+        File::open(dir.path().join("foo.1.log.manifest"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(contents.starts_with("file: foo.1.log\nsize: 5\n"));
+        assert!(!dir.path().join("foo.2.log.manifest").exists());
+    }
 }
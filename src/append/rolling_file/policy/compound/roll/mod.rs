@@ -2,7 +2,7 @@
 
 use std::error::Error;
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[cfg(feature = "file")]
 use file::Deserializable;
@@ -22,6 +22,14 @@ pub trait Roll: fmt::Debug + Send + Sync + 'static {
     /// If this method returns successfully, there *must* no longer be a file
     /// at the specified location.
     fn roll(&self, file: &Path) -> Result<(), Box<Error + Sync + Send>>;
+
+    /// Returns the path the most recently rolled log file was archived to, if
+    /// the roller keeps it.
+    ///
+    /// The default implementation returns `None`.
+    fn archive_path(&self) -> Option<PathBuf> {
+        None
+    }
 }
 
 #[cfg(feature = "file")]
@@ -0,0 +1,162 @@
+//! The free space trigger.
+//!
+//! Requires the `free_space_trigger` feature.
+
+use antidote::Mutex;
+use std::error::Error;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use append::rolling_file::LogFile;
+use append::rolling_file::policy::compound::disk;
+use append::rolling_file::policy::compound::trigger::Trigger;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// Configuration for the free space trigger.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FreeSpaceTriggerConfig {
+    #[serde(deserialize_with = "::priv_serde::de_size")]
+    min_free: u64,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    interval: Option<Duration>,
+}
+
+/// A trigger which rolls the log when free space on its volume drops below
+/// a threshold.
+///
+/// Pair it with a roller configured to free space, such as a
+/// `FixedWindowRoller` with a minimum free space, so that archives are
+/// deleted before the disk fills. Free space is checked at most once per
+/// interval, and an empty log file is never rolled.
+#[derive(Debug)]
+pub struct FreeSpaceTrigger {
+    min_free: u64,
+    interval: Duration,
+    last_check: Mutex<Option<Instant>>,
+    // returns the free space on the volume of a path
+    free_space: fn(&Path) -> io::Result<u64>,
+}
+
+impl FreeSpaceTrigger {
+    /// Returns a new trigger which rolls the log when fewer than the
+    /// specified number of bytes are free on its volume.
+    ///
+    /// Free space is checked at most once per second.
+    pub fn new(min_free: u64) -> FreeSpaceTrigger {
+        FreeSpaceTrigger::with_interval(min_free, Duration::from_secs(1))
+    }
+
+    /// Like `new`, but checks free space at most once per the specified
+    /// interval.
+    pub fn with_interval(min_free: u64, interval: Duration) -> FreeSpaceTrigger {
+        FreeSpaceTrigger {
+            min_free: min_free,
+            interval: interval,
+            last_check: Mutex::new(None),
+            free_space: disk::free_space,
+        }
+    }
+}
+
+impl Trigger for FreeSpaceTrigger {
+    fn trigger(&self, file: &LogFile) -> Result<bool, Box<Error + Sync + Send>> {
+        if file.len() == 0 {
+            return Ok(false);
+        }
+
+        {
+            let mut last_check = self.last_check.lock();
+            let now = Instant::now();
+            if let Some(last) = *last_check {
+                if now.duration_since(last) < self.interval {
+                    return Ok(false);
+                }
+            }
+            *last_check = Some(now);
+        }
+
+        Ok((self.free_space)(file.path())? < self.min_free)
+    }
+}
+
+/// A deserializer for the `FreeSpaceTrigger`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: free_space
+///
+/// # The amount of free space below which the log is rolled, in the same
+/// # format as the size trigger's limit. Required.
+/// min_free: 1 gb
+///
+/// # The minimum amount of time between free space checks. Defaults to 1
+/// # second.
+/// interval: 1 second
+/// ```
+#[cfg(feature = "file")]
+pub struct FreeSpaceTriggerDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for FreeSpaceTriggerDeserializer {
+    type Trait = Trigger;
+
+    type Config = FreeSpaceTriggerConfig;
+
+    fn deserialize(&self,
+                   config: FreeSpaceTriggerConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Trigger>, Box<Error + Sync + Send>> {
+        let interval = config.interval.unwrap_or_else(|| Duration::from_secs(1));
+        Ok(Box::new(FreeSpaceTrigger::with_interval(config.min_free, interval)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn trigger(free_space: fn(&Path) -> io::Result<u64>) -> FreeSpaceTrigger {
+        FreeSpaceTrigger {
+            free_space: free_space,
+            ..FreeSpaceTrigger::with_interval(1000, Duration::from_secs(0))
+        }
+    }
+
+    fn triggers(trigger: &FreeSpaceTrigger, len: u64) -> bool {
+        let mut writer = None;
+        let file = LogFile {
+            writer: &mut writer,
+            path: Path::new("foo.log"),
+            len: len,
+        };
+        trigger.trigger(&file).unwrap()
+    }
+
+    #[test]
+    fn below_threshold() {
+        let trigger = trigger(|_| Ok(999));
+        assert!(triggers(&trigger, 10));
+        assert!(!triggers(&trigger, 0));
+    }
+
+    #[test]
+    fn above_threshold() {
+        assert!(!triggers(&trigger(|_| Ok(1000)), 10));
+        assert!(!triggers(&trigger(|_| Ok(u64::MAX)), 10));
+    }
+
+    #[test]
+    fn interval() {
+        let trigger = FreeSpaceTrigger {
+            free_space: |_| Ok(0),
+            ..FreeSpaceTrigger::with_interval(1000, Duration::from_secs(60))
+        };
+        assert!(triggers(&trigger, 10));
+        assert!(!triggers(&trigger, 10));
+    }
+}
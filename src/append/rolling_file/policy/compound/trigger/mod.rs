@@ -7,6 +7,8 @@ use append::rolling_file::LogFile;
 #[cfg(feature = "file")]
 use file::Deserializable;
 
+#[cfg(feature = "free_space_trigger")]
+pub mod free_space;
 #[cfg(feature = "size_trigger")]
 pub mod size;
 
@@ -0,0 +1,131 @@
+//! Coordination of a rolling log file shared by several processes.
+//!
+//! Each shared log file has a lock file beside it, which is locked
+//! exclusively with `flock` while an appender writes to the log and rolls it.
+//! The lock file also holds a generation marker, a counter incremented each
+//! time the log is rolled, so that the other processes can tell that their
+//! handle refers to an archived file and reopen the new one instead.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The lock file of a shared log file.
+#[derive(Debug)]
+pub struct LockFile {
+    file: File,
+}
+
+impl LockFile {
+    /// Opens the lock file of the log file at `log`, creating it if needed.
+    pub fn open(log: &Path) -> io::Result<LockFile> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path(log))?;
+        Ok(LockFile { file: file })
+    }
+
+    /// Blocks until the lock is held by this process.
+    pub fn lock(&self) -> io::Result<Guard<'_>> {
+        lock(&self.file)?;
+        Ok(Guard { lock: self })
+    }
+
+    /// Returns the current generation of the log file.
+    pub fn generation(&self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        match read_at(&self.file, &mut buf) {
+            Ok(()) => Ok(u64::from_le_bytes(buf)),
+            // a new lock file, of a log which has never been rolled
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// An exclusive hold on a `LockFile`, released when dropped.
+pub struct Guard<'a> {
+    lock: &'a LockFile,
+}
+
+impl<'a> Drop for Guard<'a> {
+    fn drop(&mut self) {
+        let _ = unlock(&self.lock.file);
+    }
+}
+
+impl<'a> Guard<'a> {
+    /// Records that the log file has been rolled.
+    pub fn bump(&self, generation: u64) -> io::Result<()> {
+        write_at(&self.lock.file, &(generation + 1).to_le_bytes())
+    }
+}
+
+fn path(log: &Path) -> PathBuf {
+    let mut path = log.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> io::Result<()> {
+    flock(file, ::libc::LOCK_EX)
+}
+
+#[cfg(unix)]
+fn unlock(file: &File) -> io::Result<()> {
+    flock(file, ::libc::LOCK_UN)
+}
+
+#[cfg(unix)]
+fn flock(file: &File, operation: ::libc::c_int) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        if unsafe { ::libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8]) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, 0)
+}
+
+#[cfg(unix)]
+fn write_at(file: &File, buf: &[u8]) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(buf, 0)
+}
+
+#[cfg(not(unix))]
+fn lock(_: &File) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other,
+                       "shared log files are not supported on this platform"))
+}
+
+#[cfg(not(unix))]
+fn unlock(_: &File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn read_at(_: &File, _: &mut [u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other,
+                       "shared log files are not supported on this platform"))
+}
+
+#[cfg(not(unix))]
+fn write_at(_: &File, _: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other,
+                       "shared log files are not supported on this platform"))
+}
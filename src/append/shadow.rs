@@ -0,0 +1,364 @@
+//! The shadow appender.
+//!
+//! Requires the `shadow_appender` feature.
+
+use log::{Level, Record};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+#[cfg(feature = "file")]
+use append::AppenderConfig;
+use append::Append;
+use append::queue::{Bound, Queue};
+use config::Backpressure;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The shadow appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShadowAppenderConfig {
+    primary: AppenderConfig,
+    mirror: MirrorConfig,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MirrorConfig {
+    path: String,
+    encoder: Option<EncoderConfig>,
+    queue_size: Option<usize>,
+    #[serde(deserialize_with = "::priv_serde::de_backpressure", default)]
+    backpressure: Option<Backpressure>,
+}
+
+// Writes to the mirror file happen on a background thread. The file is
+// reopened after a failure so that a destination which comes back, like a
+// remounted network share, starts receiving records again.
+struct Mirror {
+    queue: Arc<Queue<Message>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Mirror {
+    fn new(path: PathBuf, bound: Bound) -> io::Result<Mirror> {
+        let queue = Arc::new(Queue::new(bound)?);
+        let receiver = queue.clone();
+        let thread = thread::Builder::new()
+            .name("log4rs-shadow".to_owned())
+            .spawn(move || {
+                let mut file: Option<File> = None;
+                while let Ok(message) = receiver.pop(None) {
+                    let buf = match message {
+                        Message::Record(buf) => buf,
+                        Message::Flush(done) => {
+                            if let Some(ref mut file) = file {
+                                let _ = file.flush();
+                            }
+                            let _ = done.send(());
+                            continue;
+                        }
+                    };
+                    if let Err(e) = write_mirror(&mut file, &path, &buf) {
+                        file = None;
+                        ::handle_error(&e);
+                    }
+                }
+                if let Some(mut file) = file {
+                    let _ = file.flush();
+                }
+            })?;
+
+        Ok(Mirror {
+            queue: queue,
+            thread: Some(thread),
+        })
+    }
+
+    // if the mirror has fallen behind, the backpressure policy applies
+    fn push(&self, level: Level, buf: Vec<u8>) {
+        let _ = self.queue.push(level, Message::Record(buf));
+    }
+
+    // waits for the records queued so far to be written
+    fn flush(&self) {
+        let (tx, rx) = mpsc::channel();
+        if self.queue.push_control(Message::Flush(tx)).is_ok() {
+            let _ = rx.recv();
+        }
+    }
+}
+
+enum Message {
+    Record(Vec<u8>),
+    Flush(mpsc::Sender<()>),
+}
+
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        self.queue.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn write_mirror(file: &mut Option<File>, path: &Path, buf: &[u8]) -> io::Result<()> {
+    if file.is_none() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        *file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+    }
+    file.as_mut().unwrap().write_all(buf)
+}
+
+/// An appender which forwards log events to a primary appender and
+/// asynchronously mirrors them to a secondary file.
+///
+/// The primary appender behaves exactly as it would on its own, and its
+/// errors are returned as normal. Mirrored events are queued and written on a
+/// background thread; failures to write the mirror are reported to stderr and
+/// never affect the primary. If the queue fills up because the mirror is slow
+/// or unavailable, the backpressure policy applies to the mirror only; by
+/// default, events are dropped from the mirror.
+pub struct ShadowAppender {
+    primary: Box<Append>,
+    path: PathBuf,
+    encoder: Box<Encode>,
+    mirror: Mirror,
+}
+
+impl fmt::Debug for ShadowAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ShadowAppender")
+            .field("primary", &self.primary)
+            .field("path", &self.path)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for ShadowAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut buf = vec![];
+        match self.encoder.encode(&mut SimpleWriter(&mut buf), record) {
+            Ok(()) => self.mirror.push(record.level(), buf),
+            Err(e) => ::handle_error(&*e),
+        }
+        self.primary.append(record)
+    }
+
+    fn thread_safe(&self) -> bool {
+        self.primary.thread_safe()
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        self.primary.bytes_written()
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.mirror.flush();
+        self.primary.flush()
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl ShadowAppender {
+    /// Creates a new `ShadowAppender` builder.
+    pub fn builder() -> ShadowAppenderBuilder {
+        ShadowAppenderBuilder {
+            encoder: None,
+            queue_size: 10000,
+            backpressure: None,
+        }
+    }
+
+    /// Returns the number of events which were not mirrored because the
+    /// mirror's queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.mirror.queue.dropped()
+    }
+}
+
+/// A builder for `ShadowAppender`s.
+pub struct ShadowAppenderBuilder {
+    encoder: Option<Box<Encode>>,
+    queue_size: usize,
+    backpressure: Option<Backpressure>,
+}
+
+impl ShadowAppenderBuilder {
+    /// Sets the output encoder for the mirror.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> ShadowAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Sets the number of events which may be waiting to be mirrored before
+    /// the backpressure policy applies.
+    ///
+    /// Defaults to 10000.
+    pub fn queue_size(mut self, queue_size: usize) -> ShadowAppenderBuilder {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// Sets the policy applied when the mirror's queue is full.
+    ///
+    /// Blocking makes the primary wait for the mirror. Defaults to the
+    /// policy of the configuration.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> ShadowAppenderBuilder {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    /// Consumes the `ShadowAppenderBuilder`, producing a `ShadowAppender`
+    /// which forwards events to the primary appender and mirrors them to the
+    /// file at the specified path.
+    ///
+    /// The mirror file is opened lazily, so a destination which is not
+    /// available yet is not an error.
+    pub fn build<P: AsRef<Path>>(self,
+                                 primary: Box<Append>,
+                                 path: P)
+                                 -> io::Result<ShadowAppender> {
+        let path = path.as_ref().to_owned();
+        Ok(ShadowAppender {
+            primary: primary,
+            mirror: Mirror::new(path.clone(),
+                                Bound {
+                                    capacity: Some(self.queue_size),
+                                    backpressure: self.backpressure,
+                                })?,
+            path: path,
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+        })
+    }
+}
+
+/// A deserializer for the `ShadowAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: shadow
+///
+/// # The primary appender, configured as any other appender. It may not have
+/// # its own level or filters; attach them to the shadow appender instead.
+/// # Required.
+/// primary:
+///   kind: file
+///   path: log/app.log
+///
+/// # The mirror. Required.
+/// mirror:
+///
+///   # The path of the file events are mirrored to. Required.
+///   path: /mnt/archive/app.log
+///
+///   # The encoder to use to format mirrored events. Defaults to
+///   # `kind: pattern`.
+///   encoder:
+///     kind: pattern
+///
+///   # The number of events which may be waiting to be mirrored before
+///   # `backpressure` applies. Defaults to 10000.
+///   queue_size: 10000
+///
+///   # What happens when the queue is full: `block`, `drop_newest`,
+///   # `drop_oldest`, or `drop_below(level)`. Defaults to the top level
+///   # `backpressure` of the config file.
+///   backpressure: drop_newest
+/// ```
+#[cfg(feature = "file")]
+pub struct ShadowAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for ShadowAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = ShadowAppenderConfig;
+
+    fn deserialize(&self,
+                   config: ShadowAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        if config.primary.level.is_some() || !config.primary.filters.is_empty() {
+            return Err("the primary appender may not have a level or filters".into());
+        }
+        let primary = deserializers.deserialize(&config.primary.kind, config.primary.config)?;
+
+        let mut appender = ShadowAppender::builder();
+        if let Some(encoder) = config.mirror.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        if let Some(queue_size) = config.mirror.queue_size {
+            appender = appender.queue_size(queue_size);
+        }
+        if let Some(backpressure) = config.mirror.backpressure {
+            appender = appender.backpressure(backpressure);
+        }
+        Ok(Box::new(appender.build(primary, deserializers.resolve_path(config.mirror.path))?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn bound(capacity: usize) -> Bound {
+        Bound {
+            capacity: Some(capacity),
+            backpressure: Some(Backpressure::DropNewest),
+        }
+    }
+
+    #[test]
+    fn mirror() {
+        let dir = TempDir::new("shadow_mirror").unwrap();
+        let path = dir.path().join("archive/mirror.log");
+
+        let mirror = Mirror::new(path.clone(), bound(10)).unwrap();
+        mirror.push(Level::Info, b"one\n".to_vec());
+        mirror.push(Level::Info, b"two\n".to_vec());
+        drop(mirror);
+
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "one\ntwo\n");
+    }
+
+    #[test]
+    fn mirror_failure() {
+        let dir = TempDir::new("shadow_mirror_failure").unwrap();
+        // a file where the mirror's parent directory should be
+        File::create(dir.path().join("blocker")).unwrap();
+
+        let mirror = Mirror::new(dir.path().join("blocker/mirror.log"), bound(1)).unwrap();
+        for _ in 0..10 {
+            mirror.push(Level::Info, b"event\n".to_vec());
+        }
+        drop(mirror);
+    }
+}
@@ -0,0 +1,528 @@
+//! A disk-backed queue for buffering encoded log events.
+//!
+//! Appenders which send log events over the network can push encoded events
+//! into a `SpillQueue` and drain it from a background thread. Events are
+//! stored in append-only segment files inside of a directory, so they survive
+//! both long outages of the remote endpoint and restarts of the process.
+//!
+//! Each event is stored as a little-endian `u32` length and a little-endian
+//! CRC-32 of the event bytes, followed by the event bytes. A record which is
+//! cut short or fails its checksum, like one torn by a crash in the middle of
+//! a write, ends its segment: when the queue is opened, the newest segment is
+//! truncated before it so that new events aren't appended after it, and
+//! reading skips the rest of an older segment.
+//!
+//! The position of the oldest unacknowledged event is recorded in a `cursor`
+//! file in the same directory. To avoid rewriting it for every event, it is
+//! only written after a number of events have been acknowledged, when a
+//! segment has been fully read, and when the queue is dropped. Delivery is
+//! at-least-once: an event that has not been acknowledged with `pop`, or whose
+//! acknowledgement had not yet been recorded when the process exited, will be
+//! read again after the queue is reopened.
+//!
+//! Requires the `spill_queue` feature.
+
+use antidote::Mutex;
+use crc32fast;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const SEGMENT_EXTENSION: &str = "seg";
+const CURSOR_FILE: &str = "cursor";
+// the length and checksum preceding each event
+const HEADER_LEN: u64 = 8;
+
+struct State {
+    dir: PathBuf,
+    segment_size: u64,
+    max_segments: Option<u64>,
+    sync: bool,
+    cursor_interval: u64,
+    // the number of events acknowledged since the cursor was last written
+    unsaved: u64,
+    write_id: u64,
+    write_file: File,
+    write_len: u64,
+    read_id: u64,
+    read_offset: u64,
+    reader: Option<BufReader<File>>,
+    peeked: Option<u64>,
+}
+
+/// A persistent FIFO queue of byte buffers backed by segment files.
+pub struct SpillQueue(Mutex<State>);
+
+impl SpillQueue {
+    /// Returns a new builder for a `SpillQueue`.
+    pub fn builder() -> SpillQueueBuilder {
+        SpillQueueBuilder {
+            segment_size: 16 * 1024 * 1024,
+            max_segments: None,
+            sync: false,
+            cursor_interval: 64,
+        }
+    }
+
+    /// Appends an event to the end of the queue.
+    pub fn push(&self, event: &[u8]) -> io::Result<()> {
+        let mut state = self.0.lock();
+        state.push(event)
+    }
+
+    /// Returns the oldest unacknowledged event without removing it.
+    ///
+    /// Returns `None` if the queue is empty.
+    pub fn peek(&self) -> io::Result<Option<Vec<u8>>> {
+        let mut state = self.0.lock();
+        state.peek()
+    }
+
+    /// Acknowledges the event most recently returned by `peek`, removing it
+    /// from the queue.
+    ///
+    /// Does nothing if no event has been peeked since the last call.
+    pub fn pop(&self) -> io::Result<()> {
+        let mut state = self.0.lock();
+        state.pop()
+    }
+
+    /// Determines if the queue contains no unacknowledged events.
+    pub fn is_empty(&self) -> io::Result<bool> {
+        let mut state = self.0.lock();
+        state.peek().map(|e| e.is_none())
+    }
+}
+
+/// A builder for `SpillQueue`s.
+pub struct SpillQueueBuilder {
+    segment_size: u64,
+    max_segments: Option<u64>,
+    sync: bool,
+    cursor_interval: u64,
+}
+
+impl SpillQueueBuilder {
+    /// Sets the size in bytes after which a new segment file is started.
+    ///
+    /// This also limits the size of an event, which is stored with an 8 byte
+    /// header. Defaults to 16 megabytes.
+    pub fn segment_size(mut self, segment_size: u64) -> SpillQueueBuilder {
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Sets the maximum number of segment files to retain.
+    ///
+    /// When a new segment would exceed this limit, the oldest segment is
+    /// deleted along with any unacknowledged events it contains. The limit
+    /// must be at least 1. Defaults to no limit.
+    pub fn max_segments(mut self, max_segments: u64) -> SpillQueueBuilder {
+        self.max_segments = Some(max_segments);
+        self
+    }
+
+    /// Determines if each event will be synced to disk as it is pushed.
+    ///
+    /// Defaults to `false`.
+    pub fn sync(mut self, sync: bool) -> SpillQueueBuilder {
+        self.sync = sync;
+        self
+    }
+
+    /// Sets the number of events acknowledged with `pop` after which the
+    /// position of the oldest unacknowledged event is written to disk.
+    ///
+    /// Events acknowledged since it was last written are read again if the
+    /// process exits without the queue being dropped. The interval must be at
+    /// least 1. Defaults to 64.
+    pub fn cursor_interval(mut self, cursor_interval: u64) -> SpillQueueBuilder {
+        self.cursor_interval = cursor_interval;
+        self
+    }
+
+    /// Opens the queue stored in the specified directory, creating it if
+    /// necessary.
+    pub fn open<P: AsRef<Path>>(self, dir: P) -> io::Result<SpillQueue> {
+        if self.max_segments == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "max_segments must be at least 1"));
+        }
+        if self.cursor_interval == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "cursor_interval must be at least 1"));
+        }
+
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+
+        let mut ids = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(SEGMENT_EXTENSION.as_ref()) {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort();
+
+        let oldest = ids.first().cloned().unwrap_or(0);
+        let write_id = ids.last().cloned().unwrap_or(0);
+        let (read_id, read_offset) = match read_cursor(&dir)? {
+            Some((id, offset)) if id >= oldest && id <= write_id => (id, offset),
+            _ => (oldest, 0),
+        };
+
+        // a torn record at the end of the newest segment is cut off
+        let write_len = valid_len(&segment_path(&dir, write_id), self.segment_size)?;
+        let write_file = open_segment(&dir, write_id)?;
+        write_file.set_len(write_len)?;
+        let read_offset = if read_id == write_id {
+            read_offset.min(write_len)
+        } else {
+            read_offset
+        };
+
+        Ok(SpillQueue(Mutex::new(State {
+            dir: dir,
+            segment_size: self.segment_size,
+            max_segments: self.max_segments,
+            sync: self.sync,
+            cursor_interval: self.cursor_interval,
+            unsaved: 0,
+            write_id: write_id,
+            write_file: write_file,
+            write_len: write_len,
+            read_id: read_id,
+            read_offset: read_offset,
+            reader: None,
+            peeked: None,
+        })))
+    }
+}
+
+impl State {
+    fn push(&mut self, event: &[u8]) -> io::Result<()> {
+        let record_len = event.len() as u64 + HEADER_LEN;
+        if record_len > self.segment_size || event.len() as u64 > 0xffff_ffff {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "event too large"));
+        }
+
+        if self.write_len > 0 && self.write_len + record_len > self.segment_size {
+            self.next_segment()?;
+        }
+
+        let mut buf = Vec::with_capacity(record_len as usize);
+        buf.extend_from_slice(&(event.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&crc32fast::hash(event).to_le_bytes());
+        buf.extend_from_slice(event);
+        self.write_file.write_all(&buf)?;
+        if self.sync {
+            self.write_file.sync_data()?;
+        }
+        self.write_len += buf.len() as u64;
+        Ok(())
+    }
+
+    fn next_segment(&mut self) -> io::Result<()> {
+        self.write_id += 1;
+        self.write_file = open_segment(&self.dir, self.write_id)?;
+        self.write_len = 0;
+
+        if let Some(max_segments) = self.max_segments {
+            while self.write_id - self.read_id + 1 > max_segments {
+                self.finish_read_segment()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish_read_segment(&mut self) -> io::Result<()> {
+        self.reader = None;
+        self.peeked = None;
+        match fs::remove_file(segment_path(&self.dir, self.read_id)) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.read_id += 1;
+        self.read_offset = 0;
+        self.save_cursor()
+    }
+
+    fn save_cursor(&mut self) -> io::Result<()> {
+        write_cursor(&self.dir, self.read_id, self.read_offset)?;
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    fn peek(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.peeked.take().is_some() {
+            // the reader has moved past the unacknowledged event
+            self.reader = None;
+        }
+
+        loop {
+            if self.reader.is_none() {
+                let mut file = File::open(segment_path(&self.dir, self.read_id))?;
+                file.seek(SeekFrom::Start(self.read_offset))?;
+                self.reader = Some(BufReader::new(file));
+            }
+
+            match read_event(self.reader.as_mut().unwrap(), self.segment_size)? {
+                Some(event) => {
+                    self.peeked = Some(self.read_offset + event.len() as u64 + HEADER_LEN);
+                    return Ok(Some(event));
+                }
+                None if self.read_id < self.write_id => self.finish_read_segment()?,
+                None => {
+                    // reposition at the next push
+                    self.reader = None;
+                    self.peeked = None;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) -> io::Result<()> {
+        if let Some(offset) = self.peeked.take() {
+            self.read_offset = offset;
+            self.unsaved += 1;
+            if self.unsaved >= self.cursor_interval {
+                self.save_cursor()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        if self.unsaved > 0 {
+            let _ = self.save_cursor();
+        }
+    }
+}
+
+// Returns `None` at the end of the segment or at a record which is torn or
+// corrupt, after which the rest of the segment is unreadable.
+fn read_event<R: Read>(r: &mut R, segment_size: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0; HEADER_LEN as usize];
+    if !read_full(r, &mut header)? {
+        return Ok(None);
+    }
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if len as u64 + HEADER_LEN > segment_size {
+        return Ok(None);
+    }
+    let mut event = vec![0; len as usize];
+    if !read_full(r, &mut event)? || crc32fast::hash(&event) != crc {
+        return Ok(None);
+    }
+    Ok(Some(event))
+}
+
+// The length of the readable records at the start of a segment.
+fn valid_len(path: &Path, segment_size: u64) -> io::Result<u64> {
+    let mut reader = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut len = 0;
+    while let Some(event) = read_event(&mut reader, segment_size)? {
+        len += event.len() as u64 + HEADER_LEN;
+    }
+    Ok(len)
+}
+
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match r.read(&mut buf[read..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", id, SEGMENT_EXTENSION))
+}
+
+fn open_segment(dir: &Path, id: u64) -> io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(segment_path(dir, id))
+}
+
+fn read_cursor(dir: &Path) -> io::Result<Option<(u64, u64)>> {
+    let mut contents = String::new();
+    match File::open(dir.join(CURSOR_FILE)) {
+        Ok(mut file) => {
+            file.read_to_string(&mut contents)?;
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let mut parts = contents.split_whitespace().map(|s| s.parse::<u64>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(id)), Some(Ok(offset))) => Ok(Some((id, offset))),
+        _ => Ok(None),
+    }
+}
+
+fn write_cursor(dir: &Path, id: u64, offset: u64) -> io::Result<()> {
+    let tmp = dir.join(format!("{}.tmp", CURSOR_FILE));
+    File::create(&tmp)?.write_all(format!("{} {}", id, offset).as_bytes())?;
+    fs::rename(tmp, dir.join(CURSOR_FILE))
+}
+
+#[cfg(test)]
+mod test {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn push_pop() {
+        let dir = TempDir::new("spill_push_pop").unwrap();
+        let queue = SpillQueue::builder().open(dir.path()).unwrap();
+        assert!(queue.is_empty().unwrap());
+
+        queue.push(b"foo").unwrap();
+        queue.push(b"bar").unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"foo".to_vec()));
+        assert_eq!(queue.peek().unwrap(), Some(b"foo".to_vec()));
+        queue.pop().unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"bar".to_vec()));
+        queue.pop().unwrap();
+        assert_eq!(queue.peek().unwrap(), None);
+
+        queue.push(b"baz").unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"baz".to_vec()));
+    }
+
+    #[test]
+    fn reopen() {
+        let dir = TempDir::new("spill_reopen").unwrap();
+        {
+            let queue = SpillQueue::builder().segment_size(14).open(dir.path()).unwrap();
+            for event in &[&b"one"[..], b"two", b"three", b"four"] {
+                queue.push(event).unwrap();
+            }
+            queue.peek().unwrap();
+            queue.pop().unwrap();
+            queue.peek().unwrap();
+        }
+
+        let queue = SpillQueue::builder().segment_size(14).open(dir.path()).unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"two".to_vec()));
+        queue.pop().unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"three".to_vec()));
+        queue.pop().unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"four".to_vec()));
+        queue.pop().unwrap();
+        assert_eq!(queue.peek().unwrap(), None);
+
+        // fully read segments are removed
+        let segments = fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension() == Some("seg".as_ref()))
+            .count();
+        assert_eq!(segments, 1);
+    }
+
+    #[test]
+    fn max_segments() {
+        let dir = TempDir::new("spill_max_segments").unwrap();
+        let queue = SpillQueue::builder()
+            .segment_size(13)
+            .max_segments(2)
+            .open(dir.path())
+            .unwrap();
+        for event in &[&b"one"[..], b"two", b"three"] {
+            queue.push(event).unwrap();
+        }
+        assert_eq!(queue.peek().unwrap(), Some(b"two".to_vec()));
+        assert!(queue.push(b"too large").is_err());
+
+        let err = SpillQueue::builder().max_segments(0).open(dir.path()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn cursor_interval() {
+        let dir = TempDir::new("spill_cursor_interval").unwrap();
+        let queue = SpillQueue::builder().cursor_interval(2).open(dir.path()).unwrap();
+        for event in &[&b"one"[..], b"two", b"three", b"four"] {
+            queue.push(event).unwrap();
+        }
+
+        queue.peek().unwrap();
+        queue.pop().unwrap();
+        assert_eq!(read_cursor(dir.path()).unwrap(), None);
+        queue.peek().unwrap();
+        queue.pop().unwrap();
+        assert_eq!(read_cursor(dir.path()).unwrap(), Some((0, 22)));
+
+        // the remaining acknowledgements are recorded when the queue is dropped
+        queue.peek().unwrap();
+        queue.pop().unwrap();
+        drop(queue);
+        let queue = SpillQueue::builder().open(dir.path()).unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"four".to_vec()));
+
+        let err = SpillQueue::builder().cursor_interval(0).open(dir.path()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn torn() {
+        let dir = TempDir::new("spill_torn").unwrap();
+        {
+            let queue = SpillQueue::builder().open(dir.path()).unwrap();
+            for event in &[&b"one"[..], b"two", b"three"] {
+                queue.push(event).unwrap();
+            }
+        }
+        // a crash in the middle of writing the last record
+        let segment = segment_path(dir.path(), 0);
+        let len = fs::metadata(&segment).unwrap().len();
+        OpenOptions::new().write(true).open(&segment).unwrap().set_len(len - 2).unwrap();
+
+        let queue = SpillQueue::builder().open(dir.path()).unwrap();
+        queue.push(b"four").unwrap();
+        let mut events = vec![];
+        while let Some(event) = queue.peek().unwrap() {
+            events.push(event);
+            queue.pop().unwrap();
+        }
+        assert_eq!(events, [&b"one"[..], b"two", b"four"]);
+
+        // a corrupt record ends an older segment
+        let dir = TempDir::new("spill_corrupt").unwrap();
+        {
+            let queue = SpillQueue::builder().segment_size(32).open(dir.path()).unwrap();
+            for event in &[&b"one"[..], b"two", b"three", b"four"] {
+                queue.push(event).unwrap();
+            }
+        }
+        let mut contents = fs::read(segment_path(dir.path(), 0)).unwrap();
+        // the length of the second record, which claims to run past the end
+        contents[11] = 0xff;
+        fs::write(segment_path(dir.path(), 0), contents).unwrap();
+
+        let queue = SpillQueue::builder().segment_size(32).open(dir.path()).unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"one".to_vec()));
+        queue.pop().unwrap();
+        assert_eq!(queue.peek().unwrap(), Some(b"three".to_vec()));
+    }
+}
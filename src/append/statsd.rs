@@ -0,0 +1,322 @@
+//! The StatsD appender.
+//!
+//! Requires the `statsd_appender` feature.
+
+use log::{Level, Record};
+use std::error::Error;
+use std::fmt;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use append::Append;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The StatsD appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsdAppenderConfig {
+    addr: String,
+    name: Option<String>,
+    tags: Option<Vec<TagConfig>>,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TagConfig {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Literal(String),
+    Level,
+    Target,
+    ModulePath,
+}
+
+/// A metric name or tag value template.
+///
+/// `{level}`, `{target}`, and `{module_path}` are replaced by the record's
+/// lowercased level, target, and module path respectively. `{{` and `}}`
+/// produce literal braces.
+#[derive(Debug)]
+struct Template(Vec<Piece>);
+
+impl Template {
+    fn parse(template: &str) -> Result<Template, Box<Error + Sync + Send>> {
+        let mut pieces = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = match rest.find('}') {
+                        Some(end) => end,
+                        None => return Err(format!("unclosed `{{` in `{}`", template).into()),
+                    };
+                    let piece = match &rest[..end] {
+                        "level" => Piece::Level,
+                        "target" => Piece::Target,
+                        "module_path" => Piece::ModulePath,
+                        name => return Err(format!("unknown placeholder `{}`", name).into()),
+                    };
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(literal.clone()));
+                        literal.clear();
+                    }
+                    pieces.push(piece);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("unmatched `}}` in `{}`", template).into()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Template(pieces))
+    }
+
+    fn render(&self, out: &mut String, level: Level, target: &str, module_path: &str) {
+        for piece in &self.0 {
+            match *piece {
+                Piece::Literal(ref s) => out.push_str(s),
+                Piece::Level => out.push_str(&level.to_string().to_lowercase()),
+                Piece::Target => out.push_str(target),
+                Piece::ModulePath => out.push_str(module_path),
+            }
+        }
+    }
+}
+
+// characters with special meaning in the StatsD wire format are replaced,
+// and Rust path separators become the dots StatsD uses for hierarchy
+fn sanitize(s: &str, tag: bool) -> String {
+    s.replace("::", ".")
+        .chars()
+        .map(|c| match c {
+            ':' if !tag => '_',
+            '|' | '@' | '#' | ',' | '\n' | '\r' => '_',
+            c if c.is_whitespace() && !tag => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// An appender which increments StatsD counters for log events rather than
+/// writing them out.
+///
+/// Each event increments a counter whose name is produced from a template,
+/// `logs.{level}` by default. DogStatsD tags may also be attached, with
+/// values produced from templates in the same way. Attach filters to the
+/// appender to select which events are counted.
+pub struct StatsdAppender {
+    socket: UdpSocket,
+    name: Template,
+    tags: Vec<(String, Template)>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for StatsdAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("StatsdAppender")
+            .field("addr", &self.socket.peer_addr().ok())
+            .field("name", &self.name)
+            .field("tags", &self.tags)
+            .finish()
+    }
+}
+
+impl Append for StatsdAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let metric = self.metric(record.level(), record.target(), record.module_path().unwrap_or(""));
+        self.socket.send(metric.as_bytes())?;
+        self.bytes.fetch_add(metric.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+}
+
+impl StatsdAppender {
+    /// Creates a new `StatsdAppender` builder.
+    pub fn builder() -> StatsdAppenderBuilder {
+        StatsdAppenderBuilder {
+            name: "logs.{level}".to_owned(),
+            tags: vec![],
+        }
+    }
+
+    fn metric(&self, level: Level, target: &str, module_path: &str) -> String {
+        let mut name = String::new();
+        self.name.render(&mut name, level, target, module_path);
+        let mut metric = sanitize(&name, false);
+        metric.push_str(":1|c");
+
+        for (i, (tag, template)) in self.tags.iter().enumerate() {
+            metric.push_str(if i == 0 { "|#" } else { "," });
+            let mut value = String::new();
+            template.render(&mut value, level, target, module_path);
+            metric.push_str(tag);
+            metric.push(':');
+            metric.push_str(&sanitize(&value, true));
+        }
+        metric
+    }
+}
+
+/// A builder for `StatsdAppender`s.
+pub struct StatsdAppenderBuilder {
+    name: String,
+    tags: Vec<(String, String)>,
+}
+
+impl StatsdAppenderBuilder {
+    /// Sets the template used to produce counter names.
+    ///
+    /// `{level}`, `{target}`, and `{module_path}` are replaced by the
+    /// record's lowercased level, target, and module path. Rust path
+    /// separators are converted to dots. The module path is empty for records
+    /// which don't have one.
+    ///
+    /// Defaults to `logs.{level}`.
+    pub fn name(mut self, name: &str) -> StatsdAppenderBuilder {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Adds a DogStatsD tag whose value is produced from a template in the
+    /// same way as counter names.
+    pub fn tag(mut self, name: &str, value: &str) -> StatsdAppenderBuilder {
+        self.tags.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Consumes the `StatsdAppenderBuilder`, producing a `StatsdAppender`
+    /// which sends to the StatsD server at the specified address.
+    pub fn build<A>(self, addr: A) -> Result<StatsdAppender, Box<Error + Sync + Send>>
+        where A: ToSocketAddrs
+    {
+        let name = Template::parse(&self.name)?;
+        let mut tags = vec![];
+        for (tag, value) in self.tags {
+            tags.push((sanitize(&tag, false), Template::parse(&value)?));
+        }
+
+        let addr = match addr.to_socket_addrs()?.next() {
+            Some(addr) => addr,
+            None => return Err("address resolved to nothing".into()),
+        };
+        let local = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+
+        Ok(StatsdAppender {
+            socket: socket,
+            name: name,
+            tags: tags,
+            bytes: AtomicU64::new(0),
+        })
+    }
+}
+
+/// A deserializer for the `StatsdAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: statsd
+///
+/// # The address of the StatsD server. Required.
+/// addr: localhost:8125
+///
+/// # The template used to produce counter names. `{level}`, `{target}`, and
+/// # `{module_path}` are replaced by those of the record. Defaults to
+/// # `logs.{level}`.
+/// name: logs.{level}
+///
+/// # DogStatsD tags attached to each counter increment, with values produced
+/// # from templates in the same way as names. Optional.
+/// tags:
+///   - name: target
+///     value: "{target}"
+/// ```
+#[cfg(feature = "file")]
+pub struct StatsdAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for StatsdAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = StatsdAppenderConfig;
+
+    fn deserialize(&self,
+                   config: StatsdAppenderConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = StatsdAppender::builder();
+        if let Some(name) = config.name {
+            appender = appender.name(&name);
+        }
+        for tag in config.tags.unwrap_or_default() {
+            appender = appender.tag(&tag.name, &tag.value);
+        }
+        Ok(Box::new(appender.build(&*config.addr)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        assert_eq!(Template::parse("a{level}{{b}}{target}").unwrap().0,
+                   vec![Piece::Literal("a".to_owned()),
+                        Piece::Level,
+                        Piece::Literal("{b}".to_owned()),
+                        Piece::Target]);
+        assert!(Template::parse("{foo}").is_err());
+        assert!(Template::parse("{level").is_err());
+        assert!(Template::parse("level}").is_err());
+    }
+
+    #[test]
+    fn send() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let appender = StatsdAppender::builder()
+            .name("app.{target}.{level}")
+            .tag("module", "{module_path}")
+            .tag("level", "{level}")
+            .build(server.local_addr().unwrap())
+            .unwrap();
+
+        let metric = appender.metric(Level::Error, "foo::bar", "foo::bar|baz");
+        assert_eq!(metric,
+                   "app.foo.bar.error:1|c|#module:foo.bar_baz,level:error");
+
+        appender.socket.send(metric.as_bytes()).unwrap();
+        let mut buf = [0; 128];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], metric.as_bytes());
+    }
+}
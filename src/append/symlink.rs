@@ -0,0 +1,101 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Atomically points the symlink at `link` to `target`.
+///
+/// The link is made relative if both paths share a parent directory, so the
+/// pair can be moved together, and a relative target elsewhere is made
+/// absolute.
+pub fn update(link: &Path, target: &Path) -> io::Result<()> {
+    let target = link_target(link, target)?;
+
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut tmp = link.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    match fs::remove_file(&tmp) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    symlink(&target, &tmp)?;
+    fs::rename(&tmp, link)
+}
+
+// The contents of a symlink are resolved relative to the directory holding
+// it, so a relative target in another directory is made absolute.
+fn link_target(link: &Path, target: &Path) -> io::Result<PathBuf> {
+    match (link.parent(), target.parent(), target.file_name()) {
+        (Some(a), Some(b), Some(name)) if a == b => Ok(PathBuf::from(name)),
+        _ if target.is_relative() => Ok(env::current_dir()?.join(target)),
+        _ => Ok(target.to_owned()),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    ::std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    ::std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_: &Path, _: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "symlinks are not supported on this platform"))
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    #[test]
+    fn relink() {
+        let dir = TempDir::new("symlink_relink").unwrap();
+        let link = dir.path().join("latest.log");
+
+        File::create(dir.path().join("a.log")).unwrap().write_all(b"a").unwrap();
+        super::update(&link, &dir.path().join("a.log")).unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("a.log"));
+
+        File::create(dir.path().join("b.log")).unwrap().write_all(b"b").unwrap();
+        super::update(&link, &dir.path().join("b.log")).unwrap();
+        let mut contents = String::new();
+        File::open(&link).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "b");
+    }
+
+    #[test]
+    fn other_dir() {
+        let dir = TempDir::new("symlink_other_dir").unwrap();
+        let link = dir.path().join("current/latest.log");
+        let target = dir.path().join("logs/app.log");
+
+        fs::create_dir(dir.path().join("logs")).unwrap();
+        File::create(&target).unwrap().write_all(b"app").unwrap();
+        super::update(&link, &target).unwrap();
+        let mut contents = String::new();
+        File::open(&link).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "app");
+
+        // a relative target is resolved from the working directory rather than
+        // from the directory of the link
+        let target = super::link_target(Path::new("current/latest.log"),
+                                        Path::new("logs/app.log"))
+            .unwrap();
+        assert_eq!(target, env::current_dir().unwrap().join("logs/app.log"));
+    }
+}
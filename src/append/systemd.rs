@@ -0,0 +1,88 @@
+//! Support for file descriptors passed in by systemd.
+
+use libc;
+use std::env;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::process;
+
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Looks up a file descriptor passed via the `sd_listen_fds` protocol.
+///
+/// The name may either be a name assigned with `FileDescriptorName=`, or the
+/// index of the descriptor among those passed.
+pub fn listen_fd(name: &str) -> io::Result<RawFd> {
+    let pid = env::var("LISTEN_PID").ok();
+    let fds = env::var("LISTEN_FDS").ok();
+    let names = env::var("LISTEN_FDNAMES").ok();
+
+    find_listen_fd(process::id(),
+                   pid.as_deref(),
+                   fds.as_deref(),
+                   names.as_deref(),
+                   name)
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound,
+                           format!("no file descriptor `{}` was passed by systemd", name))
+        })
+}
+
+fn find_listen_fd(own_pid: u32,
+                  pid: Option<&str>,
+                  fds: Option<&str>,
+                  names: Option<&str>,
+                  name: &str)
+                  -> Option<RawFd> {
+    // the variables are inherited by children, so only honor them if they
+    // were meant for this process
+    if pid.and_then(|p| p.parse::<u32>().ok()) != Some(own_pid) {
+        return None;
+    }
+    let count = fds.and_then(|f| f.parse::<RawFd>().ok())?;
+
+    let index = match names.and_then(|n| n.split(':').position(|n| n == name)) {
+        Some(index) => index as RawFd,
+        None => name.parse().ok()?,
+    };
+
+    if index < count {
+        Some(LISTEN_FDS_START + index)
+    } else {
+        None
+    }
+}
+
+/// Duplicates a file descriptor, setting close-on-exec on the copy.
+pub fn dup(fd: RawFd) -> io::Result<RawFd> {
+    let new = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, LISTEN_FDS_START) };
+    if new < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(new)
+    }
+}
+
+/// Closes a file descriptor.
+pub fn close(fd: RawFd) {
+    unsafe {
+        libc::close(fd);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find() {
+        assert_eq!(find_listen_fd(10, Some("10"), Some("2"), None, "1"), Some(4));
+        assert_eq!(find_listen_fd(10, Some("10"), Some("2"), None, "2"), None);
+        assert_eq!(find_listen_fd(10, Some("11"), Some("2"), None, "0"), None);
+        assert_eq!(find_listen_fd(10, None, Some("2"), None, "0"), None);
+        assert_eq!(find_listen_fd(10, Some("10"), Some("2"), Some("http:log"), "log"),
+                   Some(4));
+        assert_eq!(find_listen_fd(10, Some("10"), Some("2"), Some("http:log"), "foo"),
+                   None);
+    }
+}
@@ -0,0 +1,278 @@
+//! The temporary file appender.
+//!
+//! Requires the `temp_file_appender` feature.
+
+use log::Record;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use append::Append;
+use append::file::FileAppender;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The temporary file appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TempFileAppenderConfig {
+    dir: Option<String>,
+    prefix: Option<String>,
+    delete_on_drop: Option<bool>,
+    encoder: Option<EncoderConfig>,
+}
+
+/// An appender which logs to a newly created file with a unique name.
+///
+/// This is intended for tests which need real file output, since each
+/// appender gets its own file even when tests run in parallel. Use `path` to
+/// find the file.
+pub struct TempFileAppender {
+    inner: FileAppender,
+    path: PathBuf,
+    delete_on_drop: bool,
+}
+
+impl fmt::Debug for TempFileAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("TempFileAppender")
+            .field("inner", &self.inner)
+            .field("delete_on_drop", &self.delete_on_drop)
+            .finish()
+    }
+}
+
+impl Drop for TempFileAppender {
+    fn drop(&mut self) {
+        if self.delete_on_drop {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl Append for TempFileAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        self.inner.append(record)
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        self.inner.bytes_written()
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.inner.flush()
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        self.inner.encoder()
+    }
+}
+
+impl TempFileAppender {
+    /// Creates a new `TempFileAppender` builder.
+    pub fn builder() -> TempFileAppenderBuilder {
+        TempFileAppenderBuilder {
+            dir: None,
+            prefix: "log4rs-".to_owned(),
+            delete_on_drop: false,
+            encoder: None,
+        }
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// A builder for `TempFileAppender`s.
+pub struct TempFileAppenderBuilder {
+    dir: Option<PathBuf>,
+    prefix: String,
+    delete_on_drop: bool,
+    encoder: Option<Box<Encode>>,
+}
+
+impl TempFileAppenderBuilder {
+    /// Sets the directory in which the log file is created.
+    ///
+    /// Defaults to the system temporary directory.
+    pub fn dir<P: AsRef<Path>>(mut self, dir: P) -> TempFileAppenderBuilder {
+        self.dir = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the prefix of the log file's name.
+    ///
+    /// Defaults to `log4rs-`.
+    pub fn prefix(mut self, prefix: &str) -> TempFileAppenderBuilder {
+        self.prefix = prefix.to_owned();
+        self
+    }
+
+    /// Determines if the log file is deleted when the appender is dropped.
+    ///
+    /// Defaults to `false`.
+    pub fn delete_on_drop(mut self, delete_on_drop: bool) -> TempFileAppenderBuilder {
+        self.delete_on_drop = delete_on_drop;
+        self
+    }
+
+    /// Sets the output encoder for the `TempFileAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> TempFileAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `TempFileAppenderBuilder`, producing a `TempFileAppender`.
+    ///
+    /// The log file is named after the prefix, the process ID, and a value
+    /// unique within the process, and is guaranteed not to have existed
+    /// before.
+    pub fn build(self) -> io::Result<TempFileAppender> {
+        let dir = self.dir.unwrap_or_else(env::temp_dir);
+        fs::create_dir_all(&dir)?;
+
+        let path = loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.subsec_nanos())
+                .unwrap_or(0);
+            let name = format!("{}{}-{}-{:08x}.log",
+                               self.prefix,
+                               process::id(),
+                               COUNTER.fetch_add(1, Ordering::Relaxed),
+                               nanos);
+            let path = dir.join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => break path,
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        };
+
+        let mut inner = FileAppender::builder();
+        if let Some(encoder) = self.encoder {
+            inner = inner.encoder(encoder);
+        }
+        let inner = match inner.build(&path) {
+            Ok(inner) => inner,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+
+        Ok(TempFileAppender {
+            inner: inner,
+            path: path,
+            delete_on_drop: self.delete_on_drop,
+        })
+    }
+}
+
+/// A deserializer for the `TempFileAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: temp_file
+///
+/// # The directory in which the log file is created. Defaults to the system
+/// # temporary directory.
+/// dir: target/test-logs
+///
+/// # The prefix of the log file's name. Defaults to `log4rs-`.
+/// prefix: my-test-
+///
+/// # Determines if the log file is deleted when the appender is dropped.
+/// # Defaults to `false`.
+/// delete_on_drop: false
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct TempFileAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for TempFileAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = TempFileAppenderConfig;
+
+    fn deserialize(&self,
+                   config: TempFileAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = TempFileAppender::builder();
+        if let Some(dir) = config.dir {
+            appender = appender.dir(deserializers.resolve_path(dir));
+        }
+        if let Some(prefix) = config.prefix {
+            appender = appender.prefix(&prefix);
+        }
+        if let Some(delete_on_drop) = config.delete_on_drop {
+            appender = appender.delete_on_drop(delete_on_drop);
+        }
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn unique_paths() {
+        let dir = TempDir::new("temp_file").unwrap();
+        let a = TempFileAppender::builder().dir(dir.path()).prefix("test-").build().unwrap();
+        let b = TempFileAppender::builder().dir(dir.path()).prefix("test-").build().unwrap();
+        assert!(a.path() != b.path());
+        assert!(a.path().starts_with(dir.path()));
+        assert!(a.path().file_name().unwrap().to_str().unwrap().starts_with("test-"));
+        assert!(a.path().exists());
+        assert!(b.path().exists());
+    }
+
+    #[test]
+    fn delete_on_drop() {
+        let dir = TempDir::new("temp_file").unwrap();
+        let kept = TempFileAppender::builder().dir(dir.path()).build().unwrap();
+        let kept_path = kept.path().to_owned();
+        drop(kept);
+        assert!(kept_path.exists());
+
+        let deleted = TempFileAppender::builder()
+            .dir(dir.path())
+            .delete_on_drop(true)
+            .build()
+            .unwrap();
+        let deleted_path = deleted.path().to_owned();
+        drop(deleted);
+        assert!(!deleted_path.exists());
+    }
+}
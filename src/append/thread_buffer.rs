@@ -0,0 +1,229 @@
+//! Per-thread buffering of encoded events.
+//!
+//! Each thread encodes events into a buffer of its own, guarded by a lock
+//! which is only contended when the buffer is being flushed. Buffers are
+//! written to the shared writer once they reach a size, by a background
+//! thread once they get too old, and when their thread exits.
+
+use antidote::{Condvar, Mutex};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static LOCAL: RefCell<Vec<Registration>> = const { RefCell::new(Vec::new()) };
+}
+
+type Buffer = Arc<Mutex<Vec<u8>>>;
+
+/// Buffers encoded events per thread before writing them to `W`.
+pub struct ThreadBuffers<W: Write + Send + 'static> {
+    shared: Arc<Shared<W>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared<W> {
+    id: usize,
+    capacity: usize,
+    writer: Arc<Mutex<W>>,
+    buffers: Mutex<Vec<Weak<Mutex<Vec<u8>>>>>,
+    shutdown: Mutex<bool>,
+    cvar: Condvar,
+}
+
+impl<W: Write + Send + 'static> Shared<W> {
+    fn flush_all(&self) -> io::Result<()> {
+        let buffers = self.buffers.lock().iter().filter_map(Weak::upgrade).collect::<Vec<_>>();
+        let mut result = Ok(());
+        for buffer in buffers {
+            let buf = mem::take(&mut *buffer.lock());
+            if !buf.is_empty() {
+                if let Err(e) = self.write(&buf) {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+}
+
+trait Sink {
+    fn write(&self, buf: &[u8]) -> io::Result<()>;
+}
+
+impl<W: Write + Send + 'static> Sink for Shared<W> {
+    fn write(&self, buf: &[u8]) -> io::Result<()> {
+        let mut writer = self.writer.lock();
+        writer.write_all(buf)?;
+        writer.flush()
+    }
+}
+
+// A thread's buffer for one `ThreadBuffers`, flushed when the thread exits.
+struct Registration {
+    id: usize,
+    buffer: Buffer,
+    owner: Weak<Sink>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let buf = mem::take(&mut *self.buffer.lock());
+        if let (false, Some(owner)) = (buf.is_empty(), self.owner.upgrade()) {
+            let _ = owner.write(&buf);
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> ThreadBuffers<W> {
+    /// Creates buffers which are written to `writer` once they hold
+    /// `capacity` bytes or `max_delay` after they were last written.
+    pub fn new(writer: Arc<Mutex<W>>,
+               capacity: usize,
+               max_delay: Duration)
+               -> io::Result<ThreadBuffers<W>> {
+        let shared = Arc::new(Shared {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            capacity: capacity,
+            writer: writer,
+            buffers: Mutex::new(vec![]),
+            shutdown: Mutex::new(false),
+            cvar: Condvar::new(),
+        });
+
+        let weak = Arc::downgrade(&shared);
+        let thread = thread::Builder::new()
+            .name("log4rs thread buffer".to_owned())
+            .spawn(move || run(weak, max_delay))?;
+
+        Ok(ThreadBuffers {
+            shared: shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Runs `f` with the current thread's buffer, writing the buffer out if
+    /// it has reached the capacity afterwards.
+    pub fn with<F, T>(&self, f: F) -> io::Result<T>
+        where F: FnOnce(&mut Vec<u8>) -> T
+    {
+        let buffer = self.buffer();
+        let full = {
+            let mut buf = buffer.lock();
+            let value = f(&mut buf);
+            if buf.len() < self.shared.capacity {
+                return Ok(value);
+            }
+            (mem::take(&mut *buf), value)
+        };
+        self.shared.write(&full.0)?;
+        Ok(full.1)
+    }
+
+    /// Writes the buffers of all threads out.
+    pub fn flush(&self) -> io::Result<()> {
+        self.shared.flush_all()
+    }
+
+    fn buffer(&self) -> Buffer {
+        LOCAL.with(|local| {
+            let mut local = local.borrow_mut();
+            if let Some(registration) = local.iter().find(|r| r.id == self.shared.id) {
+                return registration.buffer.clone();
+            }
+
+            // drop the buffers of appenders which no longer exist
+            local.retain(|r| r.owner.strong_count() > 0);
+
+            let buffer = Arc::new(Mutex::new(Vec::with_capacity(self.shared.capacity)));
+            self.shared.buffers.lock().push(Arc::downgrade(&buffer));
+            let owner = Arc::downgrade(&self.shared);
+            local.push(Registration {
+                id: self.shared.id,
+                buffer: buffer.clone(),
+                owner: owner,
+            });
+            buffer
+        })
+    }
+}
+
+impl<W: Write + Send + 'static> Drop for ThreadBuffers<W> {
+    fn drop(&mut self) {
+        *self.shared.shutdown.lock() = true;
+        self.shared.cvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = self.shared.flush_all();
+    }
+}
+
+fn run<W: Write + Send + 'static>(shared: Weak<Shared<W>>, max_delay: Duration) {
+    loop {
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        {
+            let shutdown = shared.shutdown.lock();
+            if *shutdown {
+                return;
+            }
+            let (shutdown, _) = shared.cvar.wait_timeout(shutdown, max_delay);
+            if *shutdown {
+                return;
+            }
+        }
+        let _ = shared.flush_all();
+        // dead buffers of exited threads are pruned
+        shared.buffers.lock().retain(|b| b.strong_count() > 0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flushing() {
+        let writer = Arc::new(Mutex::new(vec![]));
+        let buffers = ThreadBuffers::new(writer.clone(), 8, Duration::from_secs(60)).unwrap();
+
+        buffers.with(|buf| buf.extend_from_slice(b"abc")).unwrap();
+        assert!(writer.lock().is_empty());
+        buffers.with(|buf| buf.extend_from_slice(b"defgh")).unwrap();
+        assert_eq!(*writer.lock(), b"abcdefgh");
+
+        let buffers = Arc::new(buffers);
+        let buffers2 = buffers.clone();
+        thread::spawn(move || buffers2.with(|buf| buf.extend_from_slice(b"1")).unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(*writer.lock(), b"abcdefgh1");
+
+        buffers.with(|buf| buf.extend_from_slice(b"2")).unwrap();
+        buffers.flush().unwrap();
+        assert_eq!(*writer.lock(), b"abcdefgh12");
+    }
+
+    #[test]
+    fn max_delay() {
+        let writer = Arc::new(Mutex::new(vec![]));
+        let buffers = ThreadBuffers::new(writer.clone(), 1024, Duration::from_millis(10)).unwrap();
+        buffers.with(|buf| buf.extend_from_slice(b"abc")).unwrap();
+        for _ in 0..500 {
+            if !writer.lock().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*writer.lock(), b"abc");
+    }
+}
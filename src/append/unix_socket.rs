@@ -0,0 +1,293 @@
+//! The Unix stream socket appender.
+//!
+//! Requires the `unix_socket_appender` feature, and is only available on Unix.
+//!
+//! Instead of connecting to a path, the appender can write to an
+//! already-connected socket passed by systemd socket activation.
+
+use log::Record;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use append::Append;
+#[cfg(feature = "file")]
+use append::net::SpillConfig;
+use append::net::{Connect, Sender};
+use append::spill::SpillQueue;
+use append::systemd;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The Unix socket appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnixSocketAppenderConfig {
+    path: Option<String>,
+    listen_fd: Option<String>,
+    encoder: Option<EncoderConfig>,
+    spill: Option<SpillConfig>,
+}
+
+#[derive(Debug)]
+enum UnixConnector {
+    Path(PathBuf),
+    // a private duplicate of an inherited socket, which is duplicated again
+    // for each connection
+    Fd(RawFd),
+}
+
+impl Drop for UnixConnector {
+    fn drop(&mut self) {
+        if let UnixConnector::Fd(fd) = *self {
+            systemd::close(fd);
+        }
+    }
+}
+
+impl Connect for UnixConnector {
+    type Stream = UnixStream;
+
+    fn connect(&self) -> io::Result<UnixStream> {
+        match *self {
+            UnixConnector::Path(ref path) => UnixStream::connect(path),
+            UnixConnector::Fd(fd) => {
+                systemd::dup(fd).map(|fd| unsafe { UnixStream::from_raw_fd(fd) })
+            }
+        }
+    }
+
+    fn send(&self, stream: &mut UnixStream, event: &[u8]) -> io::Result<()> {
+        stream.write_all(event)
+    }
+}
+
+/// An appender which writes to a Unix stream socket.
+///
+/// The connection is established when the first event is logged, and is
+/// reestablished with exponential backoff if it fails.
+pub struct UnixSocketAppender {
+    sender: Sender<UnixConnector>,
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for UnixSocketAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("UnixSocketAppender")
+            .field("target", self.sender.connector())
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for UnixSocketAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut buf = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut buf), record)?;
+        self.sender.send(&buf)?;
+        self.bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.sender.flush()?;
+        Ok(())
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl UnixSocketAppender {
+    /// Creates a new `UnixSocketAppender` builder.
+    pub fn builder() -> UnixSocketAppenderBuilder {
+        UnixSocketAppenderBuilder {
+            encoder: None,
+            spill: None,
+        }
+    }
+}
+
+/// A builder for `UnixSocketAppender`s.
+pub struct UnixSocketAppenderBuilder {
+    encoder: Option<Box<Encode>>,
+    spill: Option<SpillQueue>,
+}
+
+impl UnixSocketAppenderBuilder {
+    /// Sets the output encoder for the `UnixSocketAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> UnixSocketAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Sets a spill queue which events will be buffered in while the socket
+    /// is unavailable.
+    ///
+    /// Without a spill queue, events logged while the socket is unavailable
+    /// are dropped and reported as errors.
+    pub fn spill(mut self, spill: SpillQueue) -> UnixSocketAppenderBuilder {
+        self.spill = Some(spill);
+        self
+    }
+
+    /// Consumes the `UnixSocketAppenderBuilder`, producing a
+    /// `UnixSocketAppender` which writes to the socket at the specified path.
+    pub fn build<P: AsRef<Path>>(self, path: P) -> UnixSocketAppender {
+        self.build_inner(UnixConnector::Path(path.as_ref().to_owned()))
+    }
+
+    /// Consumes the `UnixSocketAppenderBuilder`, producing a
+    /// `UnixSocketAppender` which writes to an already-connected socket.
+    ///
+    /// The appender writes to a duplicate of the descriptor, so the original
+    /// remains open when the appender is dropped.
+    pub fn build_fd(self, fd: RawFd) -> io::Result<UnixSocketAppender> {
+        let fd = systemd::dup(fd)?;
+        Ok(self.build_inner(UnixConnector::Fd(fd)))
+    }
+
+    /// Consumes the `UnixSocketAppenderBuilder`, producing a
+    /// `UnixSocketAppender` which writes to a socket passed by systemd.
+    ///
+    /// The name may either be a name assigned with `FileDescriptorName=`, or
+    /// the index of the descriptor among those passed.
+    pub fn build_listen_fd(self, name: &str) -> io::Result<UnixSocketAppender> {
+        let fd = systemd::listen_fd(name)?;
+        self.build_fd(fd)
+    }
+
+    fn build_inner(self, connector: UnixConnector) -> UnixSocketAppender {
+        UnixSocketAppender {
+            sender: Sender::new(connector, self.spill),
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A deserializer for the `UnixSocketAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: unix_socket
+///
+/// # The path of the socket to connect to. Exactly one of `path` and
+/// # `listen_fd` is required.
+/// path: /run/vector/log.sock
+///
+/// # The name or index of a connected socket passed by systemd via
+/// # `LISTEN_FDS`.
+/// listen_fd: log
+///
+/// # A directory in which events will be queued while the socket is
+/// # unavailable. Queued events survive restarts of the process. Optional.
+/// spill:
+///   path: /var/spool/myapp/log
+///
+///   # The size after which a new segment file is started, in the same
+///   # format as the size trigger's limit. Defaults to 16 megabytes.
+///   segment_size: 16 mb
+///
+///   # The maximum number of segment files to retain. The oldest events are
+///   # discarded when this is exceeded. Defaults to no limit.
+///   max_segments: 10
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct UnixSocketAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for UnixSocketAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = UnixSocketAppenderConfig;
+
+    fn deserialize(&self,
+                   config: UnixSocketAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = UnixSocketAppender::builder();
+        if let Some(spill) = config.spill {
+            appender = appender.spill(spill.open(deserializers)?);
+        }
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        let appender = match (config.path, config.listen_fd) {
+            (Some(path), None) => appender.build(deserializers.resolve_path(path)),
+            (None, Some(name)) => appender.build_listen_fd(&name)?,
+            _ => return Err("exactly one of `path` and `listen_fd` must be specified".into()),
+        };
+        Ok(Box::new(appender))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixListener;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn reconnect() {
+        let dir = TempDir::new("unix_socket_reconnect").unwrap();
+        let path = dir.path().join("log.sock");
+        let appender = UnixSocketAppender::builder().build(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+        appender.sender.send(b"foo").unwrap();
+        let mut stream = listener.accept().unwrap().0;
+        let mut buf = [0; 3];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"foo");
+
+        // writing after the peer goes away fails, and is retried on a new
+        // connection
+        drop(stream);
+        appender.sender.send(b"bar").unwrap();
+        let mut stream = listener.accept().unwrap().0;
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"bar");
+    }
+
+    #[test]
+    fn fd() {
+        let (ours, mut theirs) = UnixStream::pair().unwrap();
+        let appender = UnixSocketAppender::builder().build_fd(ours.as_raw_fd()).unwrap();
+        drop(ours);
+
+        appender.sender.send(b"foo").unwrap();
+        let mut buf = [0; 3];
+        theirs.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"foo");
+    }
+}
@@ -0,0 +1,538 @@
+//! The write-ahead log appender.
+//!
+//! The appender writes each encoded log event as a binary record to
+//! append-only segment files inside of a directory, and `WalReader` replays
+//! them. Each record consists of a little-endian `u32` payload length, a
+//! little-endian `u32` CRC-32 checksum of the payload, and the payload.
+//!
+//! A reader's `Position` identifies the record it will read next. A process
+//! shipping the log elsewhere can persist the position after each record it
+//! has handled and resume from it later, so that no record is skipped or
+//! delivered twice.
+//!
+//! Requires the `wal_appender` feature.
+
+use antidote::Mutex;
+use crc32fast;
+use log::Record;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use append::Append;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+const SEGMENT_EXTENSION: &str = "wal";
+const HEADER_LEN: u64 = 8;
+
+/// The WAL appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WalAppenderConfig {
+    path: String,
+    #[serde(deserialize_with = "::priv_serde::de_size_opt", default)]
+    segment_size: Option<u64>,
+    max_segments: Option<u64>,
+    sync: Option<bool>,
+    encoder: Option<EncoderConfig>,
+}
+
+struct Segment {
+    id: u64,
+    file: File,
+    len: u64,
+}
+
+/// An appender which writes checksummed binary records to write-ahead log
+/// segment files.
+pub struct WalAppender {
+    dir: PathBuf,
+    segment: Mutex<Segment>,
+    segment_size: u64,
+    max_segments: Option<u64>,
+    sync: bool,
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for WalAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("WalAppender")
+            .field("dir", &self.dir)
+            .field("segment_size", &self.segment_size)
+            .field("max_segments", &self.max_segments)
+            .field("sync", &self.sync)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for WalAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut payload = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut payload), record)?;
+        self.write(&payload)?;
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl WalAppender {
+    /// Creates a new `WalAppender` builder.
+    pub fn builder() -> WalAppenderBuilder {
+        WalAppenderBuilder {
+            segment_size: 64 * 1024 * 1024,
+            max_segments: None,
+            sync: false,
+            encoder: None,
+        }
+    }
+
+    fn write(&self, payload: &[u8]) -> io::Result<()> {
+        if payload.len() as u64 > 0xffff_ffff {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "record too large"));
+        }
+
+        let mut segment = self.segment.lock();
+        if segment.len > 0 && segment.len + HEADER_LEN + payload.len() as u64 > self.segment_size {
+            let id = segment.id + 1;
+            *segment = Segment {
+                id: id,
+                file: open_segment(&self.dir, id)?,
+                len: 0,
+            };
+            if let Some(max_segments) = self.max_segments {
+                remove_segments_before(&self.dir, (id + 1).saturating_sub(max_segments))?;
+            }
+        }
+
+        let mut buf = Vec::with_capacity(payload.len() + HEADER_LEN as usize);
+        write_u32(&mut buf, payload.len() as u32);
+        write_u32(&mut buf, crc32fast::hash(payload));
+        buf.extend_from_slice(payload);
+        segment.file.write_all(&buf)?;
+        if self.sync {
+            segment.file.sync_data()?;
+        }
+        segment.len += buf.len() as u64;
+        self.bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// A builder for `WalAppender`s.
+pub struct WalAppenderBuilder {
+    segment_size: u64,
+    max_segments: Option<u64>,
+    sync: bool,
+    encoder: Option<Box<Encode>>,
+}
+
+impl WalAppenderBuilder {
+    /// Sets the size in bytes after which a new segment file is started.
+    ///
+    /// Defaults to 64 megabytes.
+    pub fn segment_size(mut self, segment_size: u64) -> WalAppenderBuilder {
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Sets the maximum number of segment files to retain.
+    ///
+    /// The oldest segments are deleted when a new one is started, whether or
+    /// not they have been replayed. Defaults to no limit.
+    pub fn max_segments(mut self, max_segments: u64) -> WalAppenderBuilder {
+        self.max_segments = Some(max_segments);
+        self
+    }
+
+    /// Determines if each record will be synced to disk as it is written.
+    ///
+    /// Defaults to `false`.
+    pub fn sync(mut self, sync: bool) -> WalAppenderBuilder {
+        self.sync = sync;
+        self
+    }
+
+    /// Sets the output encoder for the `WalAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> WalAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `WalAppenderBuilder`, producing a `WalAppender` which
+    /// writes segments to the specified directory.
+    ///
+    /// If the newest existing segment ends with an incomplete record, for
+    /// example after a crash, the incomplete record is truncated.
+    pub fn build<P: AsRef<Path>>(self, dir: P) -> io::Result<WalAppender> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+
+        let id = segment_ids(&dir)?.last().cloned().unwrap_or(0);
+        let len = recover(&segment_path(&dir, id))?;
+        let segment = Segment {
+            id: id,
+            file: open_segment(&dir, id)?,
+            len: len,
+        };
+
+        Ok(WalAppender {
+            dir: dir,
+            segment: Mutex::new(segment),
+            segment_size: self.segment_size,
+            max_segments: self.max_segments,
+            sync: self.sync,
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
+        })
+    }
+}
+
+/// The location of a record in a write-ahead log.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    /// The ID of the segment containing the record.
+    pub segment: u64,
+    /// The byte offset of the record within its segment.
+    pub offset: u64,
+}
+
+/// A reader which replays the records written by a `WalAppender`.
+pub struct WalReader {
+    dir: PathBuf,
+    position: Position,
+    reader: Option<BufReader<File>>,
+}
+
+impl WalReader {
+    /// Opens a reader positioned at the oldest record in the specified
+    /// directory.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<WalReader> {
+        let dir = dir.as_ref().to_owned();
+        let segment = segment_ids(&dir)?.first().cloned().unwrap_or(0);
+        WalReader::open_at(dir,
+                           Position {
+                               segment: segment,
+                               offset: 0,
+                           })
+    }
+
+    /// Opens a reader positioned at the specified position, typically one
+    /// previously returned by `position`.
+    pub fn open_at<P: AsRef<Path>>(dir: P, position: Position) -> io::Result<WalReader> {
+        Ok(WalReader {
+            dir: dir.as_ref().to_owned(),
+            position: position,
+            reader: None,
+        })
+    }
+
+    /// Returns the position of the next record to be read.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Reads the next record's payload.
+    ///
+    /// Returns `None` if no complete record is currently available. Calling
+    /// this again later will return records written in the meantime. An
+    /// error of kind `InvalidData` is returned if a record's checksum does
+    /// not match its payload.
+    pub fn next_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if self.reader.is_none() {
+                let path = segment_path(&self.dir, self.position.segment);
+                let mut file = match File::open(path) {
+                    Ok(file) => file,
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                        if !self.skip_to_next_segment()? {
+                            return Ok(None);
+                        }
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                file.seek(SeekFrom::Start(self.position.offset))?;
+                self.reader = Some(BufReader::new(file));
+            }
+
+            match read_record(self.reader.as_mut().unwrap())? {
+                Some(payload) => {
+                    self.position.offset += HEADER_LEN + payload.len() as u64;
+                    return Ok(Some(payload));
+                }
+                None => {
+                    // the reader may have consumed part of an incomplete
+                    // record, so reposition from scratch next time
+                    self.reader = None;
+                    if !self.skip_to_next_segment()? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    fn skip_to_next_segment(&mut self) -> io::Result<bool> {
+        let next = segment_ids(&self.dir)?.into_iter().find(|&id| id > self.position.segment);
+        match next {
+            Some(id) => {
+                self.position = Position {
+                    segment: id,
+                    offset: 0,
+                };
+                self.reader = None;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Deletes all segments which only contain records before the reader's
+    /// position.
+    pub fn remove_consumed(&self) -> io::Result<()> {
+        remove_segments_before(&self.dir, self.position.segment)
+    }
+}
+
+fn read_record<R: Read>(r: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0; HEADER_LEN as usize];
+    if !read_full(r, &mut header)? {
+        return Ok(None);
+    }
+    let len = read_u32(&header[..4]);
+    let checksum = read_u32(&header[4..]);
+    let mut payload = vec![0; len as usize];
+    if !read_full(r, &mut payload)? {
+        return Ok(None);
+    }
+    if crc32fast::hash(&payload) != checksum {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "record checksum mismatch"));
+    }
+    Ok(Some(payload))
+}
+
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match r.read(&mut buf[read..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+// truncates a trailing incomplete or corrupt record, returning the segment's
+// valid length
+fn recover(path: &Path) -> io::Result<u64> {
+    let file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut reader = BufReader::new(&file);
+    let mut len = 0;
+    loop {
+        match read_record(&mut reader) {
+            Ok(Some(payload)) => len += HEADER_LEN + payload.len() as u64,
+            Ok(None) => break,
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    if file.metadata()?.len() != len {
+        file.set_len(len)?;
+    }
+    Ok(len)
+}
+
+fn write_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+}
+
+fn read_u32(buf: &[u8]) -> u32 {
+    buf[0] as u32 | (buf[1] as u32) << 8 | (buf[2] as u32) << 16 | (buf[3] as u32) << 24
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", id, SEGMENT_EXTENSION))
+}
+
+fn open_segment(dir: &Path, id: u64) -> io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(segment_path(dir, id))
+}
+
+fn segment_ids(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut ids = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some(SEGMENT_EXTENSION.as_ref()) {
+            continue;
+        }
+        if let Some(id) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+            ids.push(id);
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+fn remove_segments_before(dir: &Path, id: u64) -> io::Result<()> {
+    for old in segment_ids(dir)?.into_iter().filter(|&old| old < id) {
+        match fs::remove_file(segment_path(dir, old)) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// A deserializer for the `WalAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: wal
+///
+/// # The directory segment files are written to. Required.
+/// path: log/wal
+///
+/// # The size after which a new segment file is started, in the same format
+/// # as the size trigger's limit. Defaults to 64 megabytes.
+/// segment_size: 64 mb
+///
+/// # The maximum number of segment files to retain. Defaults to no limit.
+/// max_segments: 16
+///
+/// # If true, each record is synced to disk as it is written. Defaults to
+/// # `false`.
+/// sync: false
+///
+/// # The encoder to use to format each record's payload. Defaults to
+/// # `kind: pattern`.
+/// encoder:
+///   kind: json
+/// ```
+#[cfg(feature = "file")]
+pub struct WalAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for WalAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = WalAppenderConfig;
+
+    fn deserialize(&self,
+                   config: WalAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = WalAppender::builder();
+        if let Some(segment_size) = config.segment_size {
+            appender = appender.segment_size(segment_size);
+        }
+        if let Some(max_segments) = config.max_segments {
+            appender = appender.max_segments(max_segments);
+        }
+        if let Some(sync) = config.sync {
+            appender = appender.sync(sync);
+        }
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build(deserializers.resolve_path(config.path))?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn replay() {
+        let dir = TempDir::new("wal_replay").unwrap();
+        let appender = WalAppender::builder().segment_size(30).build(dir.path()).unwrap();
+        appender.write(b"one").unwrap();
+        appender.write(b"two").unwrap();
+        appender.write(b"three").unwrap();
+        assert_eq!(segment_ids(dir.path()).unwrap(), vec![0, 1]);
+
+        let mut reader = WalReader::open(dir.path()).unwrap();
+        assert_eq!(reader.next_record().unwrap(), Some(b"one".to_vec()));
+        let position = reader.position();
+        assert_eq!(reader.next_record().unwrap(), Some(b"two".to_vec()));
+        assert_eq!(reader.next_record().unwrap(), Some(b"three".to_vec()));
+        assert_eq!(reader.next_record().unwrap(), None);
+
+        appender.write(b"four").unwrap();
+        assert_eq!(reader.next_record().unwrap(), Some(b"four".to_vec()));
+
+        let mut reader = WalReader::open_at(dir.path(), position).unwrap();
+        assert_eq!(reader.next_record().unwrap(), Some(b"two".to_vec()));
+        reader.next_record().unwrap();
+        reader.remove_consumed().unwrap();
+        assert_eq!(segment_ids(dir.path()).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn truncated_tail() {
+        let dir = TempDir::new("wal_truncated_tail").unwrap();
+        WalAppender::builder().build(dir.path()).unwrap().write(b"one").unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(segment_path(dir.path(), 0))
+            .unwrap()
+            .write_all(&[10, 0, 0, 0, 1, 2])
+            .unwrap();
+
+        let appender = WalAppender::builder().build(dir.path()).unwrap();
+        appender.write(b"two").unwrap();
+
+        let mut reader = WalReader::open(dir.path()).unwrap();
+        assert_eq!(reader.next_record().unwrap(), Some(b"one".to_vec()));
+        assert_eq!(reader.next_record().unwrap(), Some(b"two".to_vec()));
+        assert_eq!(reader.next_record().unwrap(), None);
+    }
+
+    #[test]
+    fn corrupt() {
+        let dir = TempDir::new("wal_corrupt").unwrap();
+        WalAppender::builder().build(dir.path()).unwrap().write(b"one").unwrap();
+        let mut file = OpenOptions::new().write(true).open(segment_path(dir.path(), 0)).unwrap();
+        file.seek(SeekFrom::Start(HEADER_LEN)).unwrap();
+        file.write_all(b"x").unwrap();
+
+        let err = WalReader::open(dir.path()).unwrap().next_record().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
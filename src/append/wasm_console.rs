@@ -0,0 +1,153 @@
+//! The browser console appender.
+//!
+//! Requires the `wasm_console_appender` feature, and is only available on
+//! `wasm32` targets.
+
+use log::{Level, Record};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use web_sys::console;
+use web_sys::wasm_bindgen::JsValue;
+
+use append::Append;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The browser console appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WasmConsoleAppenderConfig {
+    encoder: Option<EncoderConfig>,
+}
+
+// The encoded event without its line ending, which the console adds itself.
+fn message(buf: &[u8]) -> String {
+    String::from_utf8_lossy(buf).trim_end_matches(['\r', '\n']).to_owned()
+}
+
+/// An appender which logs to the browser's JavaScript console.
+///
+/// `Error` records are passed to `console.error`, `Warn` records to
+/// `console.warn`, and all others to `console.log`, so browser developer
+/// tools can filter them by severity.
+pub struct WasmConsoleAppender {
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for WasmConsoleAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("WasmConsoleAppender")
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for WasmConsoleAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut buf = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut buf), record)?;
+
+        let message = JsValue::from_str(&message(&buf));
+        match record.level() {
+            Level::Error => console::error_1(&message),
+            Level::Warn => console::warn_1(&message),
+            _ => console::log_1(&message),
+        }
+        self.bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl WasmConsoleAppender {
+    /// Creates a new `WasmConsoleAppender` builder.
+    pub fn builder() -> WasmConsoleAppenderBuilder {
+        WasmConsoleAppenderBuilder { encoder: None }
+    }
+}
+
+/// A builder for `WasmConsoleAppender`s.
+pub struct WasmConsoleAppenderBuilder {
+    encoder: Option<Box<Encode>>,
+}
+
+impl WasmConsoleAppenderBuilder {
+    /// Sets the output encoder for the `WasmConsoleAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> WasmConsoleAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Consumes the `WasmConsoleAppenderBuilder`, producing a
+    /// `WasmConsoleAppender`.
+    pub fn build(self) -> WasmConsoleAppender {
+        WasmConsoleAppender {
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A deserializer for the `WasmConsoleAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: wasm_console
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct WasmConsoleAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for WasmConsoleAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = WasmConsoleAppenderConfig;
+
+    fn deserialize(&self,
+                   config: WasmConsoleAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = WasmConsoleAppender::builder();
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        assert_eq!(message(b"disk full\r\n"), "disk full");
+        assert_eq!(message(b"first\nsecond\n\n"), "first\nsecond");
+        assert_eq!(message(b"bad \xff"), "bad \u{fffd}");
+    }
+}
@@ -0,0 +1,308 @@
+//! The WebSocket appender.
+//!
+//! Requires the `websocket_appender` feature. Connecting to `wss://` URLs
+//! additionally requires the `websocket_tls` feature.
+
+use log::Record;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::TcpStream;
+use std::str;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tungstenite::{self, Message, WebSocket};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::client::Request;
+use tungstenite::http::{HeaderName, HeaderValue};
+use tungstenite::stream::MaybeTlsStream;
+#[cfg(feature = "file")]
+use std::collections::BTreeMap;
+
+use append::Append;
+#[cfg(feature = "file")]
+use append::net::SpillConfig;
+use append::net::{Connect, Sender};
+use append::spill::SpillQueue;
+use encode::Encode;
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The WebSocket appender's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebSocketAppenderConfig {
+    url: String,
+    headers: Option<BTreeMap<String, String>>,
+    encoder: Option<EncoderConfig>,
+    spill: Option<SpillConfig>,
+}
+
+struct WebSocketConnector {
+    request: Request,
+}
+
+impl Connect for WebSocketConnector {
+    type Stream = WebSocket<MaybeTlsStream<TcpStream>>;
+
+    fn connect(&self) -> io::Result<WebSocket<MaybeTlsStream<TcpStream>>> {
+        tungstenite::connect(self.request.clone()).map(|(socket, _)| socket).map_err(to_io)
+    }
+
+    fn send(&self,
+            socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+            event: &[u8])
+            -> io::Result<()> {
+        // the message itself delimits the event, so a trailing newline would
+        // be redundant
+        let event = trim_newline(event);
+        let message = match str::from_utf8(event) {
+            Ok(event) => Message::Text(event.to_owned()),
+            Err(_) => Message::Binary(event.to_vec()),
+        };
+        socket.send(message).map_err(to_io)
+    }
+}
+
+fn trim_newline(event: &[u8]) -> &[u8] {
+    let event = if event.ends_with(b"\n") {
+        &event[..event.len() - 1]
+    } else {
+        event
+    };
+    if event.ends_with(b"\r") {
+        &event[..event.len() - 1]
+    } else {
+        event
+    }
+}
+
+fn to_io(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+/// An appender which sends each log event as a message over a WebSocket
+/// connection.
+///
+/// Events which are valid UTF-8 are sent as text messages, and all others as
+/// binary messages. A trailing newline is removed from each event. The
+/// connection is established when the first event is logged, and is
+/// reestablished with exponential backoff if it fails.
+pub struct WebSocketAppender {
+    url: String,
+    sender: Sender<WebSocketConnector>,
+    encoder: Box<Encode>,
+    bytes: AtomicU64,
+}
+
+impl fmt::Debug for WebSocketAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("WebSocketAppender")
+            .field("url", &self.url)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+impl Append for WebSocketAppender {
+    fn append(&self, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut buf = vec![];
+        self.encoder.encode(&mut SimpleWriter(&mut buf), record)?;
+        self.sender.send(&buf)?;
+        self.bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        Some(self.bytes.load(Ordering::Relaxed))
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        self.sender.flush()?;
+        Ok(())
+    }
+
+    fn encoder(&self) -> Option<&Encode> {
+        Some(&*self.encoder)
+    }
+}
+
+impl WebSocketAppender {
+    /// Creates a new `WebSocketAppender` builder.
+    pub fn builder() -> WebSocketAppenderBuilder {
+        WebSocketAppenderBuilder {
+            headers: vec![],
+            encoder: None,
+            spill: None,
+        }
+    }
+}
+
+/// A builder for `WebSocketAppender`s.
+pub struct WebSocketAppenderBuilder {
+    headers: Vec<(String, String)>,
+    encoder: Option<Box<Encode>>,
+    spill: Option<SpillQueue>,
+}
+
+impl WebSocketAppenderBuilder {
+    /// Adds a header to the opening handshake request, such as an
+    /// `Authorization` header.
+    pub fn header(mut self, name: &str, value: &str) -> WebSocketAppenderBuilder {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Sets the output encoder for the `WebSocketAppender`.
+    pub fn encoder(mut self, encoder: Box<Encode>) -> WebSocketAppenderBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Sets a spill queue which events will be buffered in while the
+    /// connection is unavailable.
+    ///
+    /// Without a spill queue, events logged while the connection is
+    /// unavailable are dropped and reported as errors.
+    pub fn spill(mut self, spill: SpillQueue) -> WebSocketAppenderBuilder {
+        self.spill = Some(spill);
+        self
+    }
+
+    /// Consumes the `WebSocketAppenderBuilder`, producing a
+    /// `WebSocketAppender` which connects to the specified `ws://` or
+    /// `wss://` URL.
+    pub fn build(self, url: &str) -> Result<WebSocketAppender, Box<Error + Sync + Send>> {
+        if url.starts_with("wss://") && !cfg!(feature = "websocket_tls") {
+            return Err("wss URLs require the `websocket_tls` feature".into());
+        }
+
+        let mut request = url.into_client_request()?;
+        for (name, value) in self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())?;
+            let value = HeaderValue::from_str(&value)?;
+            request.headers_mut().append(name, value);
+        }
+
+        Ok(WebSocketAppender {
+            url: url.to_owned(),
+            sender: Sender::new(WebSocketConnector { request: request }, self.spill),
+            encoder: self.encoder.unwrap_or_else(|| Box::new(PatternEncoder::default())),
+            bytes: AtomicU64::new(0),
+        })
+    }
+}
+
+/// A deserializer for the `WebSocketAppender`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: websocket
+///
+/// # The URL to connect to. `wss://` URLs require the `websocket_tls`
+/// # feature. Required.
+/// url: wss://logs.example.com/ingest
+///
+/// # Headers to send with the opening handshake. Optional.
+/// headers:
+///   Authorization: Bearer 0123456789abcdef
+///
+/// # A directory in which events will be queued while the connection is
+/// # unavailable. Queued events survive restarts of the process. Optional.
+/// spill:
+///   path: /var/spool/myapp/log
+///
+///   # The size after which a new segment file is started, in the same
+///   # format as the size trigger's limit. Defaults to 16 megabytes.
+///   segment_size: 16 mb
+///
+///   # The maximum number of segment files to retain. The oldest events are
+///   # discarded when this is exceeded. Defaults to no limit.
+///   max_segments: 10
+///
+/// # The encoder to use to format output. Defaults to `kind: pattern`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct WebSocketAppenderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for WebSocketAppenderDeserializer {
+    type Trait = Append;
+
+    type Config = WebSocketAppenderConfig;
+
+    fn deserialize(&self,
+                   config: WebSocketAppenderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let mut appender = WebSocketAppender::builder();
+        for (name, value) in config.headers.unwrap_or_default() {
+            appender = appender.header(&name, &value);
+        }
+        if let Some(spill) = config.spill {
+            appender = appender.spill(spill.open(deserializers)?);
+        }
+        if let Some(encoder) = config.encoder {
+            appender =
+                appender.encoder(deserializers.deserialize(&encoder.kind, encoder.config)?);
+        }
+        Ok(Box::new(appender.build(&config.url)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::thread;
+    use tungstenite::handshake::server::{Request, Response};
+
+    use super::*;
+
+    #[test]
+    fn send() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/logs", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let stream = listener.accept().unwrap().0;
+            let mut auth = None;
+            #[allow(clippy::result_large_err)]
+            let callback = |req: &Request, resp: Response| {
+                auth = req.headers().get("Authorization").cloned();
+                Ok(resp)
+            };
+            let mut socket = tungstenite::accept_hdr(stream, callback).unwrap();
+            let message = socket.read().unwrap();
+            (auth, message)
+        });
+
+        let appender = WebSocketAppender::builder()
+            .header("Authorization", "Bearer foo")
+            .build(&url)
+            .unwrap();
+        appender.sender.send(b"hello\n").unwrap();
+
+        let (auth, message) = server.join().unwrap();
+        assert_eq!(auth.unwrap(), "Bearer foo");
+        assert_eq!(message, Message::Text("hello".to_owned()));
+    }
+
+    #[test]
+    #[cfg(not(feature = "websocket_tls"))]
+    fn wss_without_tls() {
+        assert!(WebSocketAppender::builder().build("wss://localhost/").is_err());
+    }
+}
@@ -4,27 +4,38 @@ use std::collections::HashSet;
 use std::fmt;
 use std::iter::IntoIterator;
 use std::error;
-use log::LogLevelFilter;
+use std::sync::Arc;
+use log::{Level, LevelFilter};
 
 use append::Append;
 use filter::Filter;
-use {ConfigPrivateExt, PrivateConfigAppenderExt};
+use intercept::Interceptor;
+#[cfg(feature = "file")]
+pub use priv_file::{check_embedded, validate, Diagnostic, Severity};
+use {ConfigBuilderPrivateExt, ConfigPrivateExt, PrivateConfigAppenderExt};
+
+#[cfg(feature = "console_appender")]
+const DEFAULT_CONSOLE: &str = "default_console";
 
 /// Configuration for the root logger.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Root {
-    level: LogLevelFilter,
+    level: LevelFilter,
     appenders: Vec<String>,
+    appender_levels: Vec<(String, LevelFilter)>,
 }
 
 impl Root {
     /// Creates a new `RootBuilder` with no appenders.
     pub fn builder() -> RootBuilder {
-        RootBuilder { appenders: vec![] }
+        RootBuilder {
+            appenders: vec![],
+            appender_levels: vec![],
+        }
     }
 
     /// Returns the minimum level of log messages that the root logger will accept.
-    pub fn level(&self) -> LogLevelFilter {
+    pub fn level(&self) -> LevelFilter {
         self.level
     }
 
@@ -32,12 +43,21 @@ impl Root {
     pub fn appenders(&self) -> &[String] {
         &self.appenders
     }
+
+    /// Returns the maximum log level of the events the root logger passes to
+    /// the named appender.
+    ///
+    /// This is `Trace` unless the appender was attached with a level.
+    pub fn appender_level(&self, appender: &str) -> LevelFilter {
+        reference_level(&self.appender_levels, appender)
+    }
 }
 
 /// A builder for `Root`s.
 #[derive(Debug)]
 pub struct RootBuilder {
     appenders: Vec<String>,
+    appender_levels: Vec<(String, LevelFilter)>,
 }
 
 impl RootBuilder {
@@ -49,6 +69,17 @@ impl RootBuilder {
         self
     }
 
+    /// Adds an appender which only receives the events of the root logger
+    /// up to the specified level.
+    pub fn appender_with_level<T>(mut self, appender: T, level: LevelFilter) -> RootBuilder
+        where T: Into<String>
+    {
+        let appender = appender.into();
+        self.appender_levels.push((appender.clone(), level));
+        self.appenders.push(appender);
+        self
+    }
+
     /// Adds appenders.
     pub fn appenders<I>(mut self, appenders: I) -> RootBuilder
         where I: IntoIterator,
@@ -59,26 +90,39 @@ impl RootBuilder {
     }
 
     /// Consumes the `RootBuilder`, returning the `Root`.
-    pub fn build(self, level: LogLevelFilter) -> Root {
+    pub fn build(self, level: LevelFilter) -> Root {
         Root {
             level: level,
             appenders: self.appenders,
+            appender_levels: self.appender_levels,
         }
     }
 }
 
+fn reference_level(levels: &[(String, LevelFilter)], appender: &str) -> LevelFilter {
+    levels.iter().rev().find(|l| l.0 == appender).map_or(LevelFilter::Trace, |l| l.1)
+}
+
 /// Configuration for an appender.
 #[derive(Debug)]
 pub struct Appender {
     name: String,
-    appender: Box<Append>,
+    appender: Arc<Append>,
+    level: LevelFilter,
     filters: Vec<Box<Filter>>,
+    kind: Option<String>,
 }
 
 impl Appender {
     /// Creates a new `AppenderBuilder` with the specified name and `Append` trait object.
+    ///
+    /// The appender initially accepts events of all levels.
     pub fn builder() -> AppenderBuilder {
-        AppenderBuilder { filters: vec![] }
+        AppenderBuilder {
+            level: LevelFilter::Trace,
+            filters: vec![],
+            kind: None,
+        }
     }
 
     /// Returns the name of the appender.
@@ -91,26 +135,51 @@ impl Appender {
         &*self.appender
     }
 
+    /// Returns the maximum log level the appender will accept.
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+
     /// Returns the filters attached to the appender.
     pub fn filters(&self) -> &[Box<Filter>] {
         &self.filters
     }
+
+    /// Returns the kind of the appender, if known.
+    pub fn kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
 }
 
 impl PrivateConfigAppenderExt for Appender {
-    fn unpack(self) -> (String, Box<Append>, Vec<Box<Filter>>) {
-        let Appender { name, appender, filters } = self;
+    fn unpack(self) -> (String, Arc<Append>, Vec<Box<Filter>>) {
+        let Appender { name, appender, filters, .. } = self;
         (name, appender, filters)
     }
+
+    fn shared(&self) -> Arc<Append> {
+        self.appender.clone()
+    }
 }
 
 /// A builder for `Appender`s.
 #[derive(Debug)]
 pub struct AppenderBuilder {
+    level: LevelFilter,
     filters: Vec<Box<Filter>>,
+    kind: Option<String>,
 }
 
 impl AppenderBuilder {
+    /// Sets the maximum log level the appender will accept.
+    ///
+    /// Events above this level are discarded before any filters are
+    /// consulted. This is equivalent to attaching a threshold filter.
+    pub fn level(mut self, level: LevelFilter) -> AppenderBuilder {
+        self.level = level;
+        self
+    }
+
     /// Adds a filter.
     pub fn filter(mut self, filter: Box<Filter>) -> AppenderBuilder {
         self.filters.push(filter);
@@ -125,24 +194,63 @@ impl AppenderBuilder {
         self
     }
 
+    /// Sets the kind of the appender.
+    ///
+    /// The kind is only descriptive, and is reported by `Handle::topology`.
+    /// Appenders deserialized from a config file have the kind they were
+    /// configured with.
+    pub fn kind<T>(mut self, kind: T) -> AppenderBuilder
+        where T: Into<String>
+    {
+        self.kind = Some(kind.into());
+        self
+    }
+
     /// Consumes the `AppenderBuilder`, returning the `Appender`.
     pub fn build<T>(self, name: T, appender: Box<Append>) -> Appender
         where T: Into<String>
+    {
+        self.build_shared(name, Arc::from(appender))
+    }
+
+    /// Consumes the `AppenderBuilder`, returning an `Appender` which shares
+    /// an `Append` trait object.
+    ///
+    /// `Handle::set_config` keeps an appender which is shared with the active
+    /// configuration under the same name running, along with its open files,
+    /// connections, buffers, and statistics, rather than replacing it.
+    pub fn build_shared<T>(self, name: T, appender: Arc<Append>) -> Appender
+        where T: Into<String>
     {
         Appender {
             name: name.into(),
             appender: appender,
+            level: self.level,
             filters: self.filters,
+            kind: self.kind,
         }
     }
 }
 
 /// Configuration for a logger.
-#[derive(Debug)]
+///
+/// A name containing `*` is a pattern which configures every logger it
+/// matches, where `*` matches any sequence of characters including `::` and
+/// `?` matches any single character. Like a normal logger, a pattern also
+/// applies to the descendants of the loggers it matches, so
+/// `myapp::handlers::*` covers `myapp::handlers::users::get`.
+///
+/// If several loggers apply to a target, the most specific one is used. A
+/// normal logger's specificity is the length of its name, and a pattern's is
+/// the number of characters in it other than `*`. Ties are won by a normal
+/// logger, and then by the pattern which sorts first. A pattern which is
+/// additive inherits the appenders of the normal logger it overrides.
+#[derive(Debug, Clone)]
 pub struct Logger {
     name: String,
-    level: LogLevelFilter,
+    level: LevelFilter,
     appenders: Vec<String>,
+    appender_levels: Vec<(String, LevelFilter)>,
     additive: bool,
 }
 
@@ -153,6 +261,7 @@ impl Logger {
     pub fn builder() -> LoggerBuilder {
         LoggerBuilder {
             appenders: vec![],
+            appender_levels: vec![],
             additive: true,
         }
     }
@@ -163,7 +272,7 @@ impl Logger {
     }
 
     /// Returns the minimum level of log messages that the logger will accept.
-    pub fn level(&self) -> LogLevelFilter {
+    pub fn level(&self) -> LevelFilter {
         self.level
     }
 
@@ -172,6 +281,14 @@ impl Logger {
         &self.appenders
     }
 
+    /// Returns the maximum log level of the events the logger passes to the
+    /// named appender.
+    ///
+    /// This is `Trace` unless the appender was attached with a level.
+    pub fn appender_level(&self, appender: &str) -> LevelFilter {
+        reference_level(&self.appender_levels, appender)
+    }
+
     /// Determines if appenders of parent loggers will also be attached to this logger.
     pub fn additive(&self) -> bool {
         self.additive
@@ -182,6 +299,7 @@ impl Logger {
 #[derive(Debug)]
 pub struct LoggerBuilder {
     appenders: Vec<String>,
+    appender_levels: Vec<(String, LevelFilter)>,
     additive: bool,
 }
 
@@ -194,6 +312,17 @@ impl LoggerBuilder {
         self
     }
 
+    /// Adds an appender which only receives the events of the logger up to
+    /// the specified level.
+    pub fn appender_with_level<T>(mut self, appender: T, level: LevelFilter) -> LoggerBuilder
+        where T: Into<String>
+    {
+        let appender = appender.into();
+        self.appender_levels.push((appender.clone(), level));
+        self.appenders.push(appender);
+        self
+    }
+
     /// Adds appenders.
     pub fn appenders<I>(mut self, appenders: I) -> LoggerBuilder
         where I: IntoIterator,
@@ -210,24 +339,78 @@ impl LoggerBuilder {
     }
 
     /// Consumes the `LoggerBuilder`, returning the `Logger`.
-    pub fn build<T>(self, name: T, level: LogLevelFilter) -> Logger
+    pub fn build<T>(self, name: T, level: LevelFilter) -> Logger
         where T: Into<String>
     {
         Logger {
             name: name.into(),
             level: level,
             appenders: self.appenders,
+            appender_levels: self.appender_levels,
             additive: self.additive,
         }
     }
 }
 
+/// What happens when an appender fails to append an event.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// The error is discarded.
+    Ignore,
+    /// The error is written to stderr, or passed to the handler installed with
+    /// `set_error_handler`.
+    #[default]
+    Stderr,
+    /// The logging thread panics.
+    Panic,
+    /// The error is logged at the `Error` level to the appender with the
+    /// specified name, with the target `log4rs`.
+    ///
+    /// If that appender fails as well, or if it is the one which failed, the
+    /// error is handled as with `Stderr`.
+    Appender(String),
+}
+
+/// What happens when an event is handed to an appender whose queue of
+/// events waiting for a background thread is full.
+///
+/// The policy applies to the bounded queues of the batching network
+/// appenders and the mirror of the shadow appender. `Config::backpressure`
+/// sets the policy of every queue which doesn't have its own in the
+/// configuration of its appender. Control messages, like requests to flush
+/// the appender, are never dropped.
+///
+/// Other buffers aren't queues of events and have fixed behavior: the
+/// file appender's thread buffers are written out by the logging thread when
+/// full, and spill queues delete their oldest segment when they reach
+/// `max_segments`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// The logging thread waits until there is room in the queue.
+    ///
+    /// An appender which can't make progress, like one whose destination is
+    /// unreachable, blocks every thread logging to it.
+    Block,
+    /// The new event is dropped.
+    #[default]
+    DropNewest,
+    /// The oldest event in the queue is dropped to make room for the new one.
+    DropOldest,
+    /// The new event is dropped if it is less severe than the specified level,
+    /// and the logging thread waits until there is room in the queue
+    /// otherwise.
+    DropBelow(Level),
+}
+
 /// A log4rs configuration.
 #[derive(Debug)]
 pub struct Config {
     appenders: Vec<Appender>,
     root: Root,
     loggers: Vec<Logger>,
+    on_error: ErrorPolicy,
+    backpressure: Backpressure,
+    interceptors: Vec<Arc<Interceptor>>,
 }
 
 impl Config {
@@ -236,6 +419,10 @@ impl Config {
         ConfigBuilder {
             appenders: vec![],
             loggers: vec![],
+            on_error: ErrorPolicy::default(),
+            backpressure: Backpressure::default(),
+            default_console: LevelFilter::Trace,
+            interceptors: vec![],
         }
     }
 
@@ -253,12 +440,98 @@ impl Config {
     pub fn loggers(&self) -> &[Logger] {
         &self.loggers
     }
+
+    /// Returns the policy for appender errors.
+    pub fn on_error(&self) -> &ErrorPolicy {
+        &self.on_error
+    }
+
+    /// Returns the policy for full appender queues.
+    pub fn backpressure(&self) -> Backpressure {
+        self.backpressure
+    }
+
+    /// Returns the interceptors, in the order they are applied.
+    pub fn interceptors(&self) -> &[Arc<Interceptor>] {
+        &self.interceptors
+    }
+
+    /// Overrides logger levels with `env_logger` style directives, like
+    /// `warn,myapp::db=trace`.
+    ///
+    /// Directives are separated by commas. A bare level sets the root
+    /// logger's level, `name=level` sets the level of the named logger, and a
+    /// bare name sets the named logger to `trace`. Loggers which do not
+    /// already exist are added with no appenders. Later directives take
+    /// precedence over earlier ones.
+    ///
+    /// Directives which cannot be parsed are skipped and returned as errors.
+    /// `env_logger`'s `/regex` message filters are not supported.
+    pub fn override_levels(mut self, directives: &str) -> (Config, Vec<Error>) {
+        let mut errors = vec![];
+
+        for directive in directives.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            if directive.contains('/') {
+                errors.push(Error::InvalidDirective(directive.to_owned()));
+                continue;
+            }
+
+            let mut parts = directive.splitn(2, '=');
+            let (name, level) = match (parts.next().unwrap().trim(), parts.next()) {
+                (name, Some(level)) => {
+                    match level.trim().parse() {
+                        Ok(level) => (Some(name), level),
+                        Err(_) => {
+                            errors.push(Error::InvalidDirective(directive.to_owned()));
+                            continue;
+                        }
+                    }
+                }
+                (level_or_name, None) => {
+                    match level_or_name.parse() {
+                        Ok(level) => (None, level),
+                        Err(_) => (Some(level_or_name), LevelFilter::Trace),
+                    }
+                }
+            };
+
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    self.root.level = level;
+                    continue;
+                }
+            };
+            if let Err(err) = check_logger_name(name) {
+                errors.push(err);
+                continue;
+            }
+            match self.loggers.iter_mut().find(|l| l.name == name) {
+                Some(logger) => logger.level = level,
+                None => {
+                    self.loggers.push(Logger {
+                        name: name.to_owned(),
+                        level: level,
+                        appenders: vec![],
+                        appender_levels: vec![],
+                        additive: true,
+                    })
+                }
+            }
+        }
+
+        (self, errors)
+    }
 }
 
 /// A builder for `Config`s.
 pub struct ConfigBuilder {
     appenders: Vec<Appender>,
     loggers: Vec<Logger>,
+    on_error: ErrorPolicy,
+    backpressure: Backpressure,
+    default_console: LevelFilter,
+    interceptors: Vec<Box<Interceptor>>,
 }
 
 impl ConfigBuilder {
@@ -290,6 +563,53 @@ impl ConfigBuilder {
         self
     }
 
+    /// Sets the policy for appender errors.
+    ///
+    /// Defaults to `ErrorPolicy::Stderr`.
+    pub fn on_error(mut self, on_error: ErrorPolicy) -> ConfigBuilder {
+        self.on_error = on_error;
+        self
+    }
+
+    /// Sets the policy for full appender queues, which applies to the
+    /// queues of appenders not configured with a policy of their own.
+    ///
+    /// Defaults to `Backpressure::DropNewest`.
+    pub fn backpressure(mut self, backpressure: Backpressure) -> ConfigBuilder {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Adds an interceptor.
+    ///
+    /// Interceptors are applied to every event in the order they are added,
+    /// before the event reaches the loggers. See the `intercept` module.
+    pub fn interceptor(mut self, interceptor: Box<Interceptor>) -> ConfigBuilder {
+        self.interceptors.push(interceptor);
+        self
+    }
+
+    /// Adds interceptors.
+    pub fn interceptors<I>(mut self, interceptors: I) -> ConfigBuilder
+        where I: IntoIterator<Item = Box<Interceptor>>
+    {
+        self.interceptors.extend(interceptors);
+        self
+    }
+
+    /// Sets the maximum level of the console appender which is attached to
+    /// the root logger if no logger has any appenders, so that a config which
+    /// forgets them doesn't silently discard every event. The appender is
+    /// named `default_console` and writes to standard out.
+    ///
+    /// `LevelFilter::Off` disables the appender. Defaults to
+    /// `LevelFilter::Trace`, which leaves filtering to the loggers' levels.
+    /// Requires the `console_appender` feature to have an effect.
+    pub fn default_console(mut self, level: LevelFilter) -> ConfigBuilder {
+        self.default_console = level;
+        self
+    }
+
     /// Consumes the `ConfigBuilder`, returning the `Config`.
     ///
     /// Unlike `build`, this method will always return a `Config` by stripping
@@ -297,7 +617,14 @@ impl ConfigBuilder {
     pub fn build_lossy(self, mut root: Root) -> (Config, Vec<Error>) {
         let mut errors = vec![];
 
-        let ConfigBuilder { appenders, loggers } = self;
+        let ConfigBuilder {
+            appenders,
+            loggers,
+            mut on_error,
+            backpressure,
+            default_console,
+            interceptors,
+        } = self;
 
         let mut ok_appenders = vec![];
         let mut appender_names = HashSet::new();
@@ -345,10 +672,39 @@ impl ConfigBuilder {
             ok_loggers.push(logger);
         }
 
+        #[cfg(feature = "console_appender")]
+        {
+            let attached = !root.appenders.is_empty() ||
+                           ok_loggers.iter().any(|l| !l.appenders.is_empty());
+            if !attached && default_console != LevelFilter::Off &&
+               !appender_names.contains(DEFAULT_CONSOLE) {
+                let console = ::append::console::ConsoleAppender::builder().build();
+                ok_appenders.push(Appender::builder()
+                    .level(default_console)
+                    .build(DEFAULT_CONSOLE, Box::new(console)));
+                appender_names.insert(DEFAULT_CONSOLE.to_owned());
+                root.appenders.push(DEFAULT_CONSOLE.to_owned());
+            }
+        }
+        #[cfg(not(feature = "console_appender"))]
+        let _ = default_console;
+
+        if let ErrorPolicy::Appender(appender) = on_error {
+            on_error = if appender_names.contains(&appender) {
+                ErrorPolicy::Appender(appender)
+            } else {
+                errors.push(Error::NonexistentAppender(appender));
+                ErrorPolicy::default()
+            };
+        }
+
         let config = Config {
             appenders: ok_appenders,
             root: root,
             loggers: ok_loggers,
+            on_error: on_error,
+            backpressure: backpressure,
+            interceptors: interceptors.into_iter().map(Arc::from).collect(),
         };
 
         (config, errors)
@@ -367,6 +723,12 @@ impl ConfigBuilder {
     }
 }
 
+impl ConfigBuilderPrivateExt for ConfigBuilder {
+    fn unpack(self) -> (Vec<Appender>, Vec<Logger>, Vec<Box<Interceptor>>) {
+        (self.appenders, self.loggers, self.interceptors)
+    }
+}
+
 fn check_logger_name(name: &str) -> Result<(), Error> {
     if name.is_empty() {
         return Err(Error::InvalidLoggerName(name.to_owned()));
@@ -395,9 +757,9 @@ fn check_logger_name(name: &str) -> Result<(), Error> {
 }
 
 impl ConfigPrivateExt for Config {
-    fn unpack(self) -> (Vec<Appender>, Root, Vec<Logger>) {
-        let Config { appenders, root, loggers } = self;
-        (appenders, root, loggers)
+    fn unpack(self) -> (Vec<Appender>, Root, Vec<Logger>, ErrorPolicy) {
+        let Config { appenders, root, loggers, on_error, .. } = self;
+        (appenders, root, loggers, on_error)
     }
 }
 
@@ -440,6 +802,8 @@ pub enum Error {
     DuplicateLoggerName(String),
     /// A logger name was invalid.
     InvalidLoggerName(String),
+    /// A level override directive was invalid.
+    InvalidDirective(String),
     #[doc(hidden)]
     __Extensible,
 }
@@ -453,6 +817,7 @@ impl fmt::Display for Error {
             }
             Error::DuplicateLoggerName(ref n) => write!(fmt, "Duplicate logger name `{}`", n),
             Error::InvalidLoggerName(ref n) => write!(fmt, "Invalid logger name `{}`", n),
+            Error::InvalidDirective(ref d) => write!(fmt, "Invalid level directive `{}`", d),
             Error::__Extensible => unreachable!(),
         }
     }
@@ -483,4 +848,72 @@ mod test {
                     name);
         }
     }
+
+    #[test]
+    fn override_levels() {
+        use log::LevelFilter;
+
+        use super::*;
+
+        let config = Config::builder()
+            .logger(Logger::builder().additive(false).build("myapp::db".to_owned(),
+                                                            LevelFilter::Info))
+            .build(Root::builder().build(LevelFilter::Error))
+            .unwrap();
+        let (config, errors) =
+            config.override_levels("warn, myapp::db=TRACE,hyper,bad=loud,x/regex,a:::b=info");
+        assert_eq!(errors.len(), 3);
+        assert_eq!(config.root().level(), LevelFilter::Warn);
+        let loggers = config.loggers();
+        assert_eq!(loggers.len(), 2);
+        assert_eq!(loggers[0].level(), LevelFilter::Trace);
+        assert!(!loggers[0].additive());
+        assert_eq!(loggers[1].name(), "hyper");
+        assert_eq!(loggers[1].level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn on_error() {
+        use log::LevelFilter;
+
+        use super::*;
+
+        let (config, errors) = Config::builder()
+            .on_error(ErrorPolicy::Appender("missing".to_owned()))
+            .build_lossy(Root::builder().build(LevelFilter::Info));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(*config.on_error(), ErrorPolicy::Stderr);
+    }
+
+    #[test]
+    #[cfg(feature = "console_appender")]
+    fn default_console() {
+        use log::LevelFilter;
+
+        use super::*;
+
+        let config = Config::builder()
+            .default_console(LevelFilter::Info)
+            .build(Root::builder().build(LevelFilter::Warn))
+            .unwrap();
+        assert_eq!(config.appenders()[0].name(), "default_console");
+        assert_eq!(config.appenders()[0].level(), LevelFilter::Info);
+        assert_eq!(config.root().appenders(), &["default_console".to_owned()]);
+
+        let config = Config::builder()
+            .default_console(LevelFilter::Off)
+            .build(Root::builder().build(LevelFilter::Warn))
+            .unwrap();
+        assert!(config.appenders().is_empty());
+
+        // an appender attached anywhere is enough
+        let nop = ::append::console::ConsoleAppender::builder().build();
+        let config = Config::builder()
+            .appender(Appender::builder().build("nop", Box::new(nop)))
+            .logger(Logger::builder().appender("nop").build("foo", LevelFilter::Info))
+            .build(Root::builder().build(LevelFilter::Warn))
+            .unwrap();
+        assert_eq!(config.appenders().len(), 1);
+        assert!(config.root().appenders().is_empty());
+    }
 }
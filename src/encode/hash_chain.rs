@@ -0,0 +1,335 @@
+//! The hash chain encoder.
+//!
+//! Requires the `hash_chain_encoder` feature.
+
+use antidote::Mutex;
+use log::Record;
+use ring::hmac;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Read};
+use std::path::Path;
+use std::str;
+
+use encode::{Encode, Write, NEWLINE};
+#[cfg(feature = "file")]
+use encode::EncoderConfig;
+use encode::pattern::PatternEncoder;
+use encode::writer::simple::SimpleWriter;
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+const MARKER: &str = " hmac=";
+const DIGEST_LEN: usize = 32;
+const GENESIS: [u8; DIGEST_LEN] = [0; DIGEST_LEN];
+
+/// The hash chain encoder's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HashChainEncoderConfig {
+    key_file: String,
+    encoder: Option<EncoderConfig>,
+}
+
+/// Reads an HMAC key from a file.
+///
+/// The key is the contents of the file with surrounding whitespace removed.
+pub fn read_key<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let mut key = vec![];
+    File::open(path)?.read_to_end(&mut key)?;
+    while key.last().is_some_and(|b| b.is_ascii_whitespace()) {
+        key.pop();
+    }
+    let start = key.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(key.len());
+    key.drain(..start);
+    if key.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "HMAC key file is empty"));
+    }
+    Ok(key)
+}
+
+fn digest(key: &hmac::Key, previous: &[u8], record: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut ctx = hmac::Context::with_key(key);
+    ctx.update(previous);
+    ctx.update(record);
+    let mut out = [0; DIGEST_LEN];
+    out.copy_from_slice(ctx.sign().as_ref());
+    out
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<[u8; DIGEST_LEN]> {
+    if hex.len() != DIGEST_LEN * 2 {
+        return None;
+    }
+    let mut out = [0; DIGEST_LEN];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+/// An `Encode`r which makes its output tamper-evident by appending an HMAC
+/// to each record.
+///
+/// The record produced by the wrapped encoder, minus any trailing newline, is
+/// followed by ` hmac=` and the hex-encoded HMAC-SHA256 of the previous
+/// record's digest and the record itself. Each record's digest therefore
+/// depends on every record before it, so editing, removing, or reordering
+/// records breaks the chain from that point on. The chain starts from an
+/// all-zero digest each time the encoder is created.
+///
+/// Use `verify` to check a log file.
+pub struct HashChainEncoder {
+    inner: Box<Encode>,
+    key: hmac::Key,
+    previous: Mutex<[u8; DIGEST_LEN]>,
+}
+
+impl fmt::Debug for HashChainEncoder {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("HashChainEncoder")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl HashChainEncoder {
+    /// Returns a new `HashChainEncoder` which appends HMACs computed with the
+    /// specified key to the output of a `PatternEncoder` with the default
+    /// pattern.
+    pub fn new(key: &[u8]) -> HashChainEncoder {
+        HashChainEncoder::with_encoder(key, Box::new(PatternEncoder::default()))
+    }
+
+    /// Returns a new `HashChainEncoder` which appends HMACs computed with the
+    /// specified key to the output of the specified encoder.
+    pub fn with_encoder(key: &[u8], inner: Box<Encode>) -> HashChainEncoder {
+        HashChainEncoder {
+            inner: inner,
+            key: hmac::Key::new(hmac::HMAC_SHA256, key),
+            previous: Mutex::new(GENESIS),
+        }
+    }
+
+    fn chain(&self, w: &mut Write, mut record: Vec<u8>) -> io::Result<()> {
+        while record.ends_with(b"\n") || record.ends_with(b"\r") {
+            record.pop();
+        }
+
+        // the lock is held while writing so records hit the writer in chain
+        // order
+        let mut previous = self.previous.lock();
+        let digest = digest(&self.key, &*previous, &record);
+        w.write_all(&record)?;
+        w.write_all(MARKER.as_bytes())?;
+        w.write_all(to_hex(&digest).as_bytes())?;
+        w.write_all(NEWLINE.as_bytes())?;
+        *previous = digest;
+        Ok(())
+    }
+}
+
+impl Encode for HashChainEncoder {
+    fn encode(&self, w: &mut Write, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut buf = vec![];
+        self.inner.encode(&mut SimpleWriter(&mut buf), record)?;
+        self.chain(w, buf).map_err(Into::into)
+    }
+}
+
+/// The result of successfully verifying a log file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    /// The number of records verified.
+    pub records: u64,
+
+    /// The line numbers, starting at 1, of records which start a new chain,
+    /// for example because the process was restarted.
+    ///
+    /// A chain restart is indistinguishable from the removal of every record
+    /// between the restart and the end of the previous chain, so restarts
+    /// should be checked against known restarts of the application.
+    pub restarts: Vec<u64>,
+
+    /// The hex-encoded digest of the last record, which may be passed to
+    /// `verify` when checking the next file in a sequence of rolled files.
+    pub last_digest: String,
+}
+
+/// Verifies a log file written by a `HashChainEncoder`.
+///
+/// If `previous` is provided, the file is expected to continue a chain ending
+/// in that hex-encoded digest, as when the file follows another in a sequence
+/// of rolled files. Otherwise, the file must start a new chain.
+///
+/// An error of kind `InvalidData` identifying the first offending line is
+/// returned if the file has been tampered with.
+pub fn verify<R>(key: &[u8], reader: R, previous: Option<&str>) -> io::Result<Verification>
+    where R: BufRead
+{
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    let mut chain = match previous {
+        Some(previous) => {
+            from_hex(previous).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "invalid previous digest")
+            })?
+        }
+        None => GENESIS,
+    };
+
+    let mut verification = Verification {
+        records: 0,
+        restarts: vec![],
+        last_digest: to_hex(&chain),
+    };
+    // records may span lines; they end at the first line with a valid digest
+    let mut record = vec![];
+    let mut start = 1;
+    for (i, line) in reader.split(b'\n').enumerate() {
+        let mut line = line?;
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+        if i as u64 + 1 != start {
+            record.push(b'\n');
+        }
+
+        let found = String::from_utf8_lossy(&line)
+            .rfind(MARKER)
+            .and_then(|pos| {
+                let digest = from_hex(str::from_utf8(&line[pos + MARKER.len()..]).ok()?)?;
+                Some((pos, digest))
+            });
+        if let Some((pos, found)) = found {
+            let mut candidate = record.clone();
+            candidate.extend_from_slice(&line[..pos]);
+            if digest(&key, &chain, &candidate) == found {
+                chain = found;
+            } else if digest(&key, &GENESIS, &candidate) == found {
+                verification.restarts.push(start);
+                chain = found;
+            } else {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("line {}: digest mismatch", i + 1)));
+            }
+            verification.records += 1;
+            record.clear();
+            start = i as u64 + 2;
+            continue;
+        }
+
+        record.extend_from_slice(&line);
+    }
+
+    if !record.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("line {}: record without a digest", start)));
+    }
+    verification.last_digest = to_hex(&chain);
+    Ok(verification)
+}
+
+/// A deserializer for the `HashChainEncoder`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: hash_chain
+///
+/// # A file containing the HMAC key. Surrounding whitespace is removed.
+/// # Required.
+/// key_file: /etc/myapp/log-hmac.key
+///
+/// # The encoder producing the records which are chained. Defaults to
+/// # `kind: pattern`.
+/// encoder:
+///   kind: pattern
+/// ```
+#[cfg(feature = "file")]
+pub struct HashChainEncoderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for HashChainEncoderDeserializer {
+    type Trait = Encode;
+
+    type Config = HashChainEncoderConfig;
+
+    fn deserialize(&self,
+                   config: HashChainEncoderConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Encode>, Box<Error + Sync + Send>> {
+        let key = read_key(deserializers.resolve_path(config.key_file))?;
+        let encoder = match config.encoder {
+            Some(encoder) => HashChainEncoder::with_encoder(&key,
+                                 deserializers.deserialize(&encoder.kind, encoder.config)?),
+            None => HashChainEncoder::new(&key),
+        };
+        Ok(Box::new(encoder))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(encoder: &HashChainEncoder, out: &mut Vec<u8>, records: &[&str]) {
+        for record in records {
+            encoder.chain(&mut SimpleWriter(&mut *out), record.as_bytes().to_vec()).unwrap();
+        }
+    }
+
+    #[test]
+    fn chain() {
+        let encoder = HashChainEncoder::new(b"secret");
+        let mut out = vec![];
+        write(&encoder, &mut out, &["one\n", "two\nlines\n", "three"]);
+
+        let verification = verify(b"secret", &out[..], None).unwrap();
+        assert_eq!(verification.records, 3);
+        assert!(verification.restarts.is_empty());
+
+        // a restarted process starts a new chain
+        write(&HashChainEncoder::new(b"secret"), &mut out, &["four\n"]);
+        let verification = verify(b"secret", &out[..], None).unwrap();
+        assert_eq!(verification.records, 4);
+        assert_eq!(verification.restarts, vec![5]);
+
+        assert!(verify(b"other", &out[..], None).is_err());
+    }
+
+    #[test]
+    fn tampering() {
+        let encoder = HashChainEncoder::new(b"secret");
+        let mut out = vec![];
+        write(&encoder, &mut out, &["one\n", "two\n", "three\n"]);
+        let text = String::from_utf8(out).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+
+        let edited = text.replacen("two", "tw0", 1);
+        let err = verify(b"secret", edited.as_bytes(), None).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: digest mismatch");
+
+        let removed = format!("{}\n{}\n", lines[0], lines[2]);
+        assert!(verify(b"secret", removed.as_bytes(), None).is_err());
+    }
+
+    #[test]
+    fn continue_chain() {
+        let encoder = HashChainEncoder::new(b"secret");
+        let mut first = vec![];
+        write(&encoder, &mut first, &["one\n"]);
+        let mut second = vec![];
+        write(&encoder, &mut second, &["two\n"]);
+
+        let last = verify(b"secret", &first[..], None).unwrap().last_digest;
+        let verification = verify(b"secret", &second[..], Some(&last)).unwrap();
+        assert!(verification.restarts.is_empty());
+        assert!(verify(b"secret", &second[..], None).is_err());
+    }
+}
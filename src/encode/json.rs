@@ -18,15 +18,30 @@
 //!     "level": "INFO",
 //!     "target": "foo::bar",
 //!     "thread": "main",
+//!     "kv": {
+//!         "attempt": "2"
+//!     },
 //!     "mdc": {
 //!         "request_id": "123e4567-e89b-12d3-a456-426655440000"
-//!     }
+//!     },
+//!     "markers": ["AUDIT"]
 //! }
 //! ```
+//!
+//! `module_path`, `file`, and `line` are `null` if the record doesn't have
+//! them.
+//!
+//! `kv` holds the key-value pairs of the record, and `mdc` holds the entries
+//! of the MDC of the logging thread, including fields added by interceptors.
+//! Values are written as strings.
+//!
+//! `markers` lists the [markers](../../marker/index.html) active on the
+//! logging thread, outermost first.
 
 use chrono::{DateTime, Local};
 use chrono::format::{DelayedFormat, Item, Fixed};
-use log::{LogLevel, LogRecord};
+use log::kv::{self, Key, Source, Value, VisitSource};
+use log::{Level, Record};
 use log_mdc;
 use std::error::Error;
 use std::fmt;
@@ -36,6 +51,7 @@ use serde::ser::{self, Serialize, SerializeMap};
 use serde_json;
 
 use encode::{Encode, Write, NEWLINE};
+use marker;
 #[cfg(feature = "file")]
 use file::{Deserialize, Deserializers};
 
@@ -63,11 +79,12 @@ impl JsonEncoder {
     fn encode_inner(&self,
                     w: &mut Write,
                     time: DateTime<Local>,
-                    level: LogLevel,
+                    level: Level,
                     target: &str,
-                    module_path: &str,
-                    file: &str,
-                    line: u32,
+                    module_path: Option<&str>,
+                    file: Option<&str>,
+                    line: Option<u32>,
+                    key_values: &Source,
                     args: &fmt::Arguments)
                     -> Result<(), Box<Error + Sync + Send>> {
         let thread = thread::current();
@@ -80,7 +97,9 @@ impl JsonEncoder {
             line: line,
             target: target,
             thread: thread.name(),
+            kv: KeyValues(key_values),
             mdc: Mdc,
+            markers: Markers,
         };
         message.serialize(&mut serde_json::Serializer::new(&mut *w))?;
         w.write_all(NEWLINE.as_bytes())?;
@@ -89,14 +108,15 @@ impl JsonEncoder {
 }
 
 impl Encode for JsonEncoder {
-    fn encode(&self, w: &mut Write, record: &LogRecord) -> Result<(), Box<Error + Sync + Send>> {
+    fn encode(&self, w: &mut Write, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
         self.encode_inner(w,
                           Local::now(),
                           record.level(),
                           record.target(),
-                          record.location().module_path(),
-                          record.location().file(),
-                          record.location().line(),
+                          record.module_path(),
+                          record.file(),
+                          record.line(),
+                          record.key_values(),
                           record.args())
     }
 }
@@ -107,22 +127,24 @@ struct Message<'a> {
     time: DelayedFormat<option::IntoIter<Item<'a>>>,
     #[serde(serialize_with = "ser_display")]
     message: &'a fmt::Arguments<'a>,
-    module_path: &'a str,
-    file: &'a str,
-    line: u32,
+    module_path: Option<&'a str>,
+    file: Option<&'a str>,
+    line: Option<u32>,
     level: &'static str,
     target: &'a str,
     thread: Option<&'a str>,
+    kv: KeyValues<'a>,
     mdc: Mdc,
+    markers: Markers,
 }
 
-fn level_str(level: LogLevel) -> &'static str {
+fn level_str(level: Level) -> &'static str {
     match level {
-        LogLevel::Error => "ERROR",
-        LogLevel::Warn => "WARN",
-        LogLevel::Info => "INFO",
-        LogLevel::Debug => "DEBUG",
-        LogLevel::Trace => "TRACE",
+        Level::Error => "ERROR",
+        Level::Warn => "WARN",
+        Level::Info => "INFO",
+        Level::Debug => "DEBUG",
+        Level::Trace => "TRACE",
     }
 }
 
@@ -133,6 +155,39 @@ fn ser_display<T, S>(v: &T, s: S) -> Result<S::Ok, S::Error>
     s.collect_str(v)
 }
 
+struct KeyValues<'a>(&'a Source);
+
+impl<'a> ser::Serialize for KeyValues<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: ser::Serializer
+    {
+        struct Visitor<M: SerializeMap> {
+            map: M,
+            err: Option<M::Error>,
+        }
+
+        impl<'kvs, M: SerializeMap> VisitSource<'kvs> for Visitor<M> {
+            fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+                if let Err(e) = self.map.serialize_entry(key.as_str(), &value.to_string()) {
+                    self.err = Some(e);
+                    return Err(kv::Error::msg("serialization failed"));
+                }
+                Ok(())
+            }
+        }
+
+        let mut visitor = Visitor {
+            map: serializer.serialize_map(None)?,
+            err: None,
+        };
+        let _ = self.0.visit(&mut visitor);
+        if let Some(e) = visitor.err {
+            return Err(e);
+        }
+        visitor.map.end()
+    }
+}
+
 struct Mdc;
 
 impl ser::Serialize for Mdc {
@@ -154,6 +209,16 @@ impl ser::Serialize for Mdc {
     }
 }
 
+struct Markers;
+
+impl ser::Serialize for Markers {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: ser::Serializer
+    {
+        marker::get(|markers| markers.serialize(serializer))
+    }
+}
+
 /// A deserializer for the `JsonEncoder`.
 ///
 /// # Configuration
@@ -182,7 +247,7 @@ impl Deserialize for JsonEncoderDeserializer {
 #[cfg(feature = "simple_writer")]
 mod test {
     use chrono::{DateTime, Local};
-    use log::LogLevel;
+    use log::Level;
     use log_mdc;
 
     use encode::writer::simple::SimpleWriter;
@@ -193,14 +258,16 @@ mod test {
         let time = DateTime::parse_from_rfc3339("2016-03-20T14:22:20.644420340-08:00")
             .unwrap()
             .with_timezone(&Local);
-        let level = LogLevel::Debug;
+        let level = Level::Debug;
         let target = "target";
         let module_path = "module_path";
         let file = "file";
         let line = 100;
+        let key_values = [("attempt", 2)];
         let message = "message";
         let thread = "encode::json::test::default";
         log_mdc::insert("foo", "bar");
+        let _marker = marker::push("AUDIT");
 
         let encoder = JsonEncoder::new();
 
@@ -209,15 +276,18 @@ mod test {
                           time,
                           level,
                           target,
-                          module_path,
-                          file,
-                          line,
+                          Some(module_path),
+                          Some(file),
+                          Some(line),
+                          &key_values,
                           &format_args!("{}", message))
             .unwrap();
 
         let expected = format!("{{\"time\":\"{}\",\"message\":\"{}\",\"module_path\":\"{}\",\
                                 \"file\":\"{}\",\"line\":{},\"level\":\"{}\",\"target\":\"{}\",\
-                                \"thread\":\"{}\",\"mdc\":{{\"foo\":\"bar\"}}}}\n",
+                                \"thread\":\"{}\",\"kv\":{{\"attempt\":\"2\"}},\
+                                \"mdc\":{{\"foo\":\"bar\"}},\
+                                \"markers\":[\"AUDIT\"]}}\n",
                                time.to_rfc3339(),
                                message,
                                module_path,
@@ -0,0 +1,116 @@
+//! Key-value pairs in the `logfmt` style.
+//!
+//! The pairs of an event are the key-value pairs of its record, followed by
+//! the entries of the MDC of the thread logging it, as used by the key-value
+//! filter. Pairs of the record override MDC entries with the same key.
+
+use log::kv::{self, Key, Source, Value, VisitSource};
+use log_mdc;
+use std::io;
+
+struct Collect(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for Collect {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push((key.as_str().to_owned(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Returns the key-value pairs of an event with the specified record
+/// key-values, sorted by key.
+pub fn pairs(key_values: &Source) -> Vec<(String, String)> {
+    let mut collect = Collect(vec![]);
+    let _ = key_values.visit(&mut collect);
+    let mut pairs = collect.0;
+    log_mdc::iter(|k, v| {
+        if !pairs.iter().any(|p| p.0 == k) {
+            pairs.push((k.to_owned(), v.to_owned()));
+        }
+    });
+    pairs.sort();
+    pairs
+}
+
+/// Writes `key=value` pairs separated by spaces.
+pub fn write_pairs(w: &mut io::Write, pairs: &[(String, String)]) -> io::Result<()> {
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        if i > 0 {
+            w.write_all(b" ")?;
+        }
+        write_pair(w, key, value)?;
+    }
+    Ok(())
+}
+
+/// Writes a single `key=value` pair.
+pub fn write_pair(w: &mut io::Write, key: &str, value: &str) -> io::Result<()> {
+    // keys can't be quoted, so characters which would break up the pair are
+    // replaced
+    for c in key.chars() {
+        if needs_quotes(c) {
+            w.write_all(b"_")?;
+        } else {
+            write!(w, "{}", c)?;
+        }
+    }
+    w.write_all(b"=")?;
+    write_value(w, value)
+}
+
+/// Writes a value, quoting it if it is empty or contains spaces, `=`, `"`,
+/// or control characters.
+pub fn write_value(w: &mut io::Write, value: &str) -> io::Result<()> {
+    if !value.is_empty() && !value.chars().any(needs_quotes) {
+        return w.write_all(value.as_bytes());
+    }
+
+    w.write_all(b"\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => w.write_all(b"\\\"")?,
+            '\\' => w.write_all(b"\\\\")?,
+            '\n' => w.write_all(b"\\n")?,
+            '\r' => w.write_all(b"\\r")?,
+            '\t' => w.write_all(b"\\t")?,
+            c if c.is_control() => write!(w, "\\u{{{:x}}}", c as u32)?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    w.write_all(b"\"")
+}
+
+fn needs_quotes(c: char) -> bool {
+    c == ' ' || c == '=' || c == '"' || c.is_control()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record_overrides_mdc() {
+        log_mdc::clear();
+        log_mdc::insert("user", "mdc");
+        log_mdc::insert("request_id", "42");
+        let record = [("user", "record"), ("attempt", "2")];
+        let pairs = pairs(&record);
+        log_mdc::clear();
+        assert_eq!(pairs,
+                   [("attempt".to_owned(), "2".to_owned()),
+                    ("request_id".to_owned(), "42".to_owned()),
+                    ("user".to_owned(), "record".to_owned())]);
+    }
+
+    #[test]
+    fn quoting() {
+        let pairs = [("a".to_owned(), "1".to_owned()),
+                     ("b c".to_owned(), "two words".to_owned()),
+                     ("d".to_owned(), String::new()),
+                     ("e".to_owned(), "say \"hi\"\n".to_owned())];
+        let mut buf = vec![];
+        write_pairs(&mut buf, &pairs).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   r#"a=1 b_c="two words" d="" e="say \"hi\"\n""#);
+    }
+}
@@ -0,0 +1,145 @@
+//! An encoder which writes `logfmt` lines.
+//!
+//! Each log event will be written as a line of space separated `key=value`
+//! pairs, followed by the event's key-value pairs:
+//!
+//! ```text
+//! time=2016-03-20T14:22:20.644420340-08:00 level=INFO target=foo::bar msg="the log message" request_id=42
+//! ```
+//!
+//! Values are quoted if they are empty or contain spaces, `=`, `"`, or
+//! control characters.
+//!
+//! The key-value pairs of an event are those of the record, along with the
+//! entries of the [MDC](https://crates.io/crates/log-mdc) of the logging
+//! thread, which include fields added by interceptors, sorted by key.
+//!
+//! Requires the `logfmt_encoder` feature.
+
+use chrono::{DateTime, Local};
+use chrono::format::{Fixed, Item};
+use log::kv::Source;
+use log::{Level, Record};
+use std::error::Error;
+use std::fmt;
+
+use encode::{kv, Encode, Write, NEWLINE};
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+
+/// The logfmt encoder's configuration
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogfmtEncoderConfig {
+    #[serde(skip_deserializing)]
+    _p: (),
+}
+
+/// An `Encode`r which writes `logfmt` lines.
+#[derive(Debug, Default)]
+pub struct LogfmtEncoder(());
+
+impl LogfmtEncoder {
+    /// Returns a new `LogfmtEncoder` with a default configuration.
+    pub fn new() -> LogfmtEncoder {
+        LogfmtEncoder(())
+    }
+}
+
+impl LogfmtEncoder {
+    fn encode_inner(&self,
+                    w: &mut Write,
+                    time: DateTime<Local>,
+                    level: Level,
+                    target: &str,
+                    key_values: &Source,
+                    args: &fmt::Arguments)
+                    -> Result<(), Box<Error + Sync + Send>> {
+        let time = time.format_with_items(Some(Item::Fixed(Fixed::RFC3339)).into_iter());
+        write!(w, "time={} level={} ", time, level)?;
+        kv::write_pair(w, "target", target)?;
+        w.write_all(b" ")?;
+        kv::write_pair(w, "msg", &args.to_string())?;
+
+        let pairs = kv::pairs(key_values);
+        if !pairs.is_empty() {
+            w.write_all(b" ")?;
+            kv::write_pairs(w, &pairs)?;
+        }
+        w.write_all(NEWLINE.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Encode for LogfmtEncoder {
+    fn encode(&self, w: &mut Write, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
+        self.encode_inner(w,
+                          Local::now(),
+                          record.level(),
+                          record.target(),
+                          record.key_values(),
+                          record.args())
+    }
+}
+
+/// A deserializer for the `LogfmtEncoder`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: logfmt
+/// ```
+#[cfg(feature = "file")]
+pub struct LogfmtEncoderDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for LogfmtEncoderDeserializer {
+    type Trait = Encode;
+
+    type Config = LogfmtEncoderConfig;
+
+    fn deserialize(&self,
+                   _: LogfmtEncoderConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Encode>, Box<Error + Sync + Send>> {
+        Ok(Box::new(LogfmtEncoder::new()))
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "simple_writer")]
+mod test {
+    use chrono::{DateTime, Local};
+    use log::Level;
+    use log_mdc;
+
+    use encode::writer::simple::SimpleWriter;
+    use super::*;
+
+    #[test]
+    fn default() {
+        let time = DateTime::parse_from_rfc3339("2016-03-20T14:22:20.644420340-08:00")
+            .unwrap()
+            .with_timezone(&Local);
+        log_mdc::clear();
+        log_mdc::insert("user", "jane doe");
+        log_mdc::insert("request_id", "42");
+
+        let mut buf = vec![];
+        LogfmtEncoder::new()
+            .encode_inner(&mut SimpleWriter(&mut buf),
+                          time,
+                          Level::Info,
+                          "foo::bar",
+                          &[("attempt", 2)],
+                          &format_args!("the {} message", "log"))
+            .unwrap();
+        log_mdc::clear();
+
+        let expected = format!("time={} level=INFO target=foo::bar msg=\"the log message\" \
+                                attempt=2 request_id=42 user=\"jane doe\"\n",
+                               time.to_rfc3339());
+        assert_eq!(expected, String::from_utf8(buf).unwrap());
+    }
+}
@@ -1,6 +1,6 @@
 //! Encoders
 
-use log::LogRecord;
+use log::Record;
 #[cfg(feature = "file")]
 use serde::de;
 #[cfg(feature = "file")]
@@ -14,8 +14,14 @@ use std::io;
 #[cfg(feature = "file")]
 use file::Deserializable;
 
+#[cfg(feature = "hash_chain_encoder")]
+pub mod hash_chain;
 #[cfg(feature = "json_encoder")]
 pub mod json;
+#[cfg(any(feature = "pattern_encoder", feature = "logfmt_encoder"))]
+mod kv;
+#[cfg(feature = "logfmt_encoder")]
+pub mod logfmt;
 #[cfg(feature = "pattern_encoder")]
 pub mod pattern;
 pub mod writer;
@@ -27,14 +33,14 @@ const NEWLINE: &'static str = "\r\n";
 #[cfg(not(windows))]
 const NEWLINE: &'static str = "\n";
 
-/// A trait implemented by types that can serialize a `LogRecord` into a
+/// A trait implemented by types that can serialize a `Record` into a
 /// `Write`r.
 ///
 /// `Encode`rs are commonly used by `Append`ers to format a log record for
 /// output.
 pub trait Encode: fmt::Debug + Send + Sync + 'static {
-    /// Encodes the `LogRecord` into bytes and writes them.
-    fn encode(&self, w: &mut Write, record: &LogRecord) -> Result<(), Box<Error + Sync + Send>>;
+    /// Encodes the `Record` into bytes and writes them.
+    fn encode(&self, w: &mut Write, record: &Record) -> Result<(), Box<Error + Sync + Send>>;
 }
 
 #[cfg(feature = "file")]
@@ -42,6 +48,10 @@ impl Deserializable for Encode {
     fn name() -> &'static str {
         "encoder"
     }
+
+    fn default_kind() -> Option<&'static str> {
+        Some("pattern")
+    }
 }
 
 /// Configuration for an encoder.
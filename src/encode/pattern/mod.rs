@@ -46,16 +46,27 @@
 //!     * `{d}` - `2016-03-20T14:22:20.644420340-08:00`
 //!     * `{d(%Y-%m-%d %H:%M:%S)}` - `2016-03-20 14:22:20`
 //!     * `{d(%Y-%m-%d %H:%M:%S %Z)(utc)}` - `2016-03-20 22:22:20 UTC`
-//! * `f`, `file` - The source file that the log message came from.
+//! * `f`, `file` - The source file of the log message, or `???` if unknown.
 //! * `h`, `highlight` - Styles its argument according to the log level. The
 //!     style is intense red for errors, red for warnings, blue for info, and
 //!     the default style for all other levels.
 //!     * `{h(the level is {l})}` -
 //!         <code style="color: red; font-weight: bold">the level is ERROR</code>
+//! * `kv` - The key-value pairs of the log event as space separated
+//!     `key=value` pairs, sorted by key. Values are quoted if they are empty
+//!     or contain spaces, `=`, `"`, or control characters. The pairs are
+//!     those of the record, followed by the entries of the [MDC][MDC], which
+//!     include fields added by interceptors.
+//!     * `{kv}` - `request_id=42 user="jane doe"`
 //! * `l``, level` - The log level.
-//! * `L`, `line` - The line that the log message came from.
+//! * `L`, `line` - The line of the log message, or `???` if unknown.
 //! * `m`, `message` - The log message.
-//! * `M`, `module` - The module that the log message came from.
+//! * `M`, `module` - The module of the log message, or `???` if unknown.
+//! * `marker` - The [markers][markers] active on the current thread,
+//!     separated by commas. The argument, which is optional, specifies the
+//!     text used if no marker is active.
+//!     * `{marker}` - `AUDIT,SECURITY`
+//!     * `{marker(-)}` - `-`
 //! * `n` - A platform-specific newline.
 //! * `t`, `target` - The target of the log message.
 //! * `T`, `thread` - The name of the current thread.
@@ -112,9 +123,11 @@
 //! level `DEBUG` will be truncated to `DEBUG hello, wo`.
 //!
 //! [MDC]: https://crates.io/crates/log-mdc
+//! [markers]: ../../marker/index.html
 
 use chrono::{UTC, Local};
-use log::{LogRecord, LogLevel};
+use log::kv::Source;
+use log::{Record, Level};
 use log_mdc;
 use std::default::Default;
 use std::error::Error;
@@ -123,7 +136,8 @@ use std::io;
 use std::thread;
 
 use encode::pattern::parser::{Parser, Piece, Parameters, Alignment};
-use encode::{self, Encode, Style, Color, NEWLINE};
+use encode::{self, kv, Encode, Style, Color, NEWLINE};
+use marker;
 #[cfg(feature = "file")]
 use file::{Deserialize, Deserializers};
 
@@ -296,7 +310,7 @@ enum Chunk {
 impl Chunk {
     fn encode(&self,
               w: &mut encode::Write,
-              level: LogLevel,
+              level: Level,
               target: &str,
               location: &Location,
               args: &fmt::Arguments)
@@ -434,11 +448,36 @@ impl<'a> From<Piece<'a>> for Chunk {
                             params: parameters,
                         }
                     }
+                    "kv" => no_args(&formatter.args, parameters, FormattedChunk::Kv),
                     "l" | "level" => no_args(&formatter.args, parameters, FormattedChunk::Level),
                     "m" | "message" => {
                         no_args(&formatter.args, parameters, FormattedChunk::Message)
                     }
                     "M" | "module" => no_args(&formatter.args, parameters, FormattedChunk::Module),
+                    "marker" => {
+                        if formatter.args.len() > 1 {
+                            return Chunk::Error("expected at most one argument".to_owned());
+                        }
+
+                        let default = match formatter.args.first() {
+                            Some(arg) => {
+                                if arg.len() != 1 {
+                                    return Chunk::Error("invalid marker default".to_owned());
+                                }
+                                match arg[0] {
+                                    Piece::Text(text) => text.to_owned(),
+                                    Piece::Error(ref e) => return Chunk::Error(e.clone()),
+                                    _ => return Chunk::Error("invalid marker default".to_owned()),
+                                }
+                            }
+                            None => "".to_owned(),
+                        };
+
+                        Chunk::Formatted {
+                            chunk: FormattedChunk::Marker(default),
+                            params: parameters,
+                        }
+                    }
                     "n" => no_args(&formatter.args, parameters, FormattedChunk::Newline),
                     "f" | "file" => no_args(&formatter.args, parameters, FormattedChunk::File),
                     "L" | "line" => no_args(&formatter.args, parameters, FormattedChunk::Line),
@@ -535,12 +574,14 @@ enum FormattedChunk {
     Align(Vec<Chunk>),
     Highlight(Vec<Chunk>),
     Mdc(String, String),
+    Marker(String),
+    Kv,
 }
 
 impl FormattedChunk {
     fn encode(&self,
               w: &mut encode::Write,
-              level: LogLevel,
+              level: Level,
               target: &str,
               location: &Location,
               args: &fmt::Arguments)
@@ -552,9 +593,14 @@ impl FormattedChunk {
             }
             FormattedChunk::Level => write!(w, "{}", level),
             FormattedChunk::Message => w.write_fmt(*args),
-            FormattedChunk::Module => w.write_all(location.module_path.as_bytes()),
-            FormattedChunk::File => w.write_all(location.file.as_bytes()),
-            FormattedChunk::Line => write!(w, "{}", location.line),
+            FormattedChunk::Module => w.write_all(location.module_path.unwrap_or("???").as_bytes()),
+            FormattedChunk::File => w.write_all(location.file.unwrap_or("???").as_bytes()),
+            FormattedChunk::Line => {
+                match location.line {
+                    Some(line) => write!(w, "{}", line),
+                    None => w.write_all(b"???"),
+                }
+            }
             FormattedChunk::Thread => {
                 w.write_all(thread::current().name().unwrap_or("<unnamed>").as_bytes())
             }
@@ -568,18 +614,18 @@ impl FormattedChunk {
             }
             FormattedChunk::Highlight(ref chunks) => {
                 match level {
-                    LogLevel::Error => {
+                    Level::Error => {
                         w.set_style(Style::new().text(Color::Red).intense(true))?;
                     }
-                    LogLevel::Warn => w.set_style(Style::new().text(Color::Red))?,
-                    LogLevel::Info => w.set_style(Style::new().text(Color::Blue))?,
+                    Level::Warn => w.set_style(Style::new().text(Color::Red))?,
+                    Level::Info => w.set_style(Style::new().text(Color::Blue))?,
                     _ => {}
                 }
                 for chunk in chunks {
                     chunk.encode(w, level, target, location, args)?;
                 }
                 match level {
-                    LogLevel::Error | LogLevel::Warn | LogLevel::Info => {
+                    Level::Error | Level::Warn | Level::Info => {
                         w.set_style(&Style::new())?
                     }
                     _ => {}
@@ -589,6 +635,16 @@ impl FormattedChunk {
             FormattedChunk::Mdc(ref key, ref default) => {
                 log_mdc::get(key, |v| write!(w, "{}", v.unwrap_or(default)))
             }
+            FormattedChunk::Marker(ref default) => {
+                marker::get(|markers| {
+                    if markers.is_empty() {
+                        w.write_all(default.as_bytes())
+                    } else {
+                        w.write_all(markers.join(",").as_bytes())
+                    }
+                })
+            }
+            FormattedChunk::Kv => kv::write_pairs(w, &kv::pairs(location.key_values)),
         }
     }
 }
@@ -615,11 +671,12 @@ impl Default for PatternEncoder {
 }
 
 impl Encode for PatternEncoder {
-    fn encode(&self, w: &mut encode::Write, record: &LogRecord) -> Result<(), Box<Error + Sync + Send>> {
+    fn encode(&self, w: &mut encode::Write, record: &Record) -> Result<(), Box<Error + Sync + Send>> {
         let location = Location {
-            module_path: record.location().module_path(),
-            file: record.location().file(),
-            line: record.location().line(),
+            module_path: record.module_path(),
+            file: record.file(),
+            line: record.line(),
+            key_values: record.key_values(),
         };
         self.append_inner(w, record.level(), record.target(), &location, record.args())
     }
@@ -638,7 +695,7 @@ impl PatternEncoder {
 
     fn append_inner(&self,
                     w: &mut encode::Write,
-                    level: LogLevel,
+                    level: Level,
                     target: &str,
                     location: &Location,
                     args: &fmt::Arguments)
@@ -650,10 +707,12 @@ impl PatternEncoder {
     }
 }
 
+// where the event came from, along with its key-value pairs
 struct Location<'a> {
-    module_path: &'a str,
-    file: &'a str,
-    line: u32,
+    module_path: Option<&'a str>,
+    file: Option<&'a str>,
+    line: Option<u32>,
+    key_values: &'a Source,
 }
 
 /// A deserializer for the `PatternEncoder`.
@@ -693,7 +752,7 @@ mod tests {
     #[cfg(feature = "simple_writer")]
     use std::thread;
     #[cfg(feature = "simple_writer")]
-    use log::LogLevel;
+    use log::{Level, Record};
     #[cfg(feature = "simple_writer")]
     use log_mdc;
 
@@ -701,13 +760,18 @@ mod tests {
     #[cfg(feature = "simple_writer")]
     use super::Location;
     #[cfg(feature = "simple_writer")]
+    use encode::Encode;
+    #[cfg(feature = "simple_writer")]
     use encode::writer::simple::SimpleWriter;
+    #[cfg(feature = "simple_writer")]
+    use marker;
 
     #[cfg(feature = "simple_writer")]
-    static LOCATION: Location<'static> = Location {
-        module_path: "path",
-        file: "file",
-        line: 132,
+    const LOCATION: Location<'static> = Location {
+        module_path: Some("path"),
+        file: Some("file"),
+        line: Some(132),
+        key_values: &[("", ""); 0],
     };
 
     fn error_free(encoder: &PatternEncoder) -> bool {
@@ -735,7 +799,7 @@ mod tests {
         let pw = PatternEncoder::new("{l} {m} at {M} in {f}:{L}");
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Debug,
+                          Level::Debug,
                           "target",
                           &LOCATION,
                           &format_args!("the message"))
@@ -751,7 +815,7 @@ mod tests {
                 let pw = PatternEncoder::new("{T}");
                 let mut buf = vec![];
                 pw.append_inner(&mut SimpleWriter(&mut buf),
-                                  LogLevel::Debug,
+                                  Level::Debug,
                                   "target",
                                   &LOCATION,
                                   &format_args!("message"))
@@ -771,7 +835,7 @@ mod tests {
                 let pw = PatternEncoder::new("{T}");
                 let mut buf = vec![];
                 pw.append_inner(&mut SimpleWriter(&mut buf),
-                                  LogLevel::Debug,
+                                  Level::Debug,
                                   "target",
                                   &LOCATION,
                                   &format_args!("message"))
@@ -796,7 +860,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Debug,
+                          Level::Debug,
                           "",
                           &LOCATION,
                           &format_args!("foo"))
@@ -805,7 +869,7 @@ mod tests {
 
         buf.clear();
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Debug,
+                          Level::Debug,
                           "",
                           &LOCATION,
                           &format_args!("foobar!"))
@@ -820,7 +884,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Debug,
+                          Level::Debug,
                           "",
                           &LOCATION,
                           &format_args!("foo"))
@@ -829,7 +893,7 @@ mod tests {
 
         buf.clear();
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Debug,
+                          Level::Debug,
                           "",
                           &LOCATION,
                           &format_args!("foobar!"))
@@ -844,7 +908,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Info,
+                          Level::Info,
                           "",
                           &LOCATION,
                           &format_args!("foobar!"))
@@ -859,7 +923,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Info,
+                          Level::Info,
                           "",
                           &LOCATION,
                           &format_args!("foobar!"))
@@ -891,7 +955,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Info,
+                          Level::Info,
                           "",
                           &LOCATION,
                           &format_args!("foobar!"))
@@ -906,7 +970,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                          LogLevel::Info,
+                          Level::Info,
                           "",
                           &LOCATION,
                           &format_args!("foo"))
@@ -922,7 +986,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                        LogLevel::Info,
+                        Level::Info,
                         "",
                         &LOCATION,
                         &format_args!("foobar!"))
@@ -938,7 +1002,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                        LogLevel::Info,
+                        Level::Info,
                         "",
                         &LOCATION,
                         &format_args!("foobar!"))
@@ -954,7 +1018,7 @@ mod tests {
 
         let mut buf = vec![];
         pw.append_inner(&mut SimpleWriter(&mut buf),
-                        LogLevel::Info,
+                        Level::Info,
                         "",
                         &LOCATION,
                         &format_args!("foobar!"))
@@ -962,4 +1026,62 @@ mod tests {
 
         assert_eq!(buf, b"missing value");
     }
+
+    #[test]
+    #[cfg(feature = "simple_writer")]
+    fn markers() {
+        let pw = PatternEncoder::new("[{marker(none)}]");
+        let encode = || {
+            let mut buf = vec![];
+            pw.append_inner(&mut SimpleWriter(&mut buf),
+                            Level::Info,
+                            "",
+                            &LOCATION,
+                            &format_args!("foobar!"))
+                .unwrap();
+            buf
+        };
+
+        assert_eq!(encode(), b"[none]");
+        marker::with("AUDIT", || {
+            marker::with("SECURITY", || assert_eq!(encode(), b"[AUDIT,SECURITY]"));
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "simple_writer")]
+    fn kv() {
+        let pw = PatternEncoder::new("{m} [{kv}]");
+        let encode = || {
+            let mut buf = vec![];
+            pw.append_inner(&mut SimpleWriter(&mut buf),
+                            Level::Info,
+                            "",
+                            &LOCATION,
+                            &format_args!("foobar!"))
+                .unwrap();
+            buf
+        };
+
+        log_mdc::clear();
+        assert_eq!(encode(), b"foobar! []");
+        log_mdc::insert("user", "jane doe");
+        log_mdc::insert("request_id", "42");
+        assert_eq!(encode(), &b"foobar! [request_id=42 user=\"jane doe\"]"[..]);
+        log_mdc::clear();
+    }
+    #[test]
+    #[cfg(feature = "simple_writer")]
+    fn record() {
+        let pw = PatternEncoder::new("{m} at {M} in {f}:{L} [{kv}]");
+        let key_values = [("attempt", 2)];
+        let mut buf = vec![];
+        pw.encode(&mut SimpleWriter(&mut buf),
+                    &Record::builder()
+                        .args(format_args!("foobar!"))
+                        .key_values(&key_values)
+                        .build())
+            .unwrap();
+        assert_eq!(buf, &b"foobar! at ??? in ???:??? [attempt=2]"[..]);
+    }
 }
@@ -15,6 +15,59 @@
 //! # humantime crate.
 //! refresh_rate: 30 seconds
 //!
+//! # If set, `env_logger` style level directives, like `warn,myapp::db=trace`,
+//! # are read from the environment variable with this name and override the
+//! # levels configured below. See `Config::override_levels` for details. The
+//! # variable is read again whenever the configuration is reloaded.
+//! level_override_var: RUST_LOG
+//!
+//! # If set, relative paths in the configurations of appenders and other
+//! # components, like a file appender's `path`, are resolved against this
+//! # directory rather than the working directory of the process. A relative
+//! # `base_dir` is itself resolved against the directory of the config file.
+//! base_dir: /var/log/myapp
+//!
+//! # What happens when an appender fails to append an event. One of `ignore`,
+//! # `stderr`, `panic`, or `appender(name)` to log the error to the appender
+//! # with that name. See `config::ErrorPolicy` for details.
+//! #
+//! # Default: stderr
+//! on_error: appender(stdout)
+//!
+//! # What happens when an event is handed to an appender whose queue of events
+//! # waiting for a background thread is full, unless the appender is configured
+//! # with its own `backpressure`. One of `block`, `drop_newest`, `drop_oldest`,
+//! # or `drop_below(level)` to drop events less severe than the level and
+//! # block for the others. See `config::Backpressure` for details.
+//! #
+//! # Default: drop_newest
+//! backpressure: drop_below(warn)
+//!
+//! # The maximum level of the console appender attached to the root logger
+//! # if no logger has any appenders, or `off` to disable it. See
+//! # `ConfigBuilder::default_console` for details.
+//! #
+//! # Default: trace
+//! default_console: info
+//!
+//! # Interceptors modify every event before it reaches the loggers, in the
+//! # order they are listed. See the `intercept` module for details.
+//! interceptors:
+//!
+//!   -
+//!     # Like appenders, interceptors are identified by their "kind".
+//!     kind: replace
+//!
+//!     # The remainder of the configuration is passed along to the
+//!     # interceptor's builder, and will vary based on the kind of interceptor.
+//!     pattern: "password=\\S+"
+//!
+//! # Shared libraries providing additional kinds of appenders, encoders, and
+//! # filters. See the `plugin` module for details. Requires the
+//! # `dynamic_plugins` feature.
+//! plugins:
+//!   - /usr/lib/myorg/liblog4rs_kafka.so
+//!
 //! # The "appenders" map contains the set of appenders, indexed by their names.
 //! appenders:
 //!
@@ -25,6 +78,19 @@
 //!     # deserialization function.
 //!     kind: console
 //!
+//!     # A disabled appender is not built, and references to it from the root
+//!     # and loggers are ignored, so it can be switched off without removing
+//!     # its definition. It may be inherited from a template.
+//!     #
+//!     # Default: true
+//!     enabled: true
+//!
+//!     # The maximum log level the appender will accept. This is a shorthand
+//!     # for attaching a threshold filter.
+//!     #
+//!     # Default: trace
+//!     level: info
+//!
 //!     # Filters attached to an appender are specified inside the "filters"
 //!     # array.
 //!     filters:
@@ -59,11 +125,15 @@
 //!   # Default: warn
 //!   level: warn
 //!
-//!   # The list of appenders attached to the root logger.
+//!   # The list of appenders attached to the root logger. An appender may
+//!   # also be given as a map of its name and the maximum level of the events
+//!   # passed to it from this logger.
 //!   #
 //!   # Default: empty list
 //!   appenders:
 //!     - foo
+//!     - name: bar
+//!       level: error
 //!
 //! # The "loggers" map contains the set of configured loggers, indexed by their
 //! # names.
@@ -88,21 +158,157 @@
 //!     Default: true
 //!     additive: false
 //! ```
-use humantime;
-use log::LogLevelFilter;
-use serde::de::{self, Deserialize as SerdeDeserialize, DeserializeOwned};
+//!
+//! # TOML
+//!
+//! The same structure is written with tables in TOML. Logger names containing
+//! `::` must be quoted, or `loggers` may instead be an array of tables which
+//! each have a `name`, in any format:
+//!
+//! ```toml
+//! refresh_rate = "30 seconds"
+//!
+//! [appenders.stdout]
+//! kind = "console"
+//!
+//! [[appenders.stdout.filters]]
+//! kind = "threshold"
+//! level = "info"
+//!
+//! [root]
+//! level = "warn"
+//! appenders = ["stdout"]
+//!
+//! [[loggers]]
+//! name = "foo::bar::baz"
+//! level = "trace"
+//! additive = false
+//! ```
+//!
+//! # Logger patterns
+//!
+//! Logger names may contain `*` wildcards to configure every matching logger
+//! at once:
+//!
+//! ```yaml
+//! loggers:
+//!   myapp::handlers::*:
+//!     level: debug
+//!   "*::sqlx*":
+//!     level: warn
+//! ```
+//!
+//! The most specific logger applying to a target wins. See `config::Logger`
+//! for the details.
+//!
+//! # Appender templates
+//!
+//! Appenders which share most of their configuration can be based on an entry
+//! of the `appender_templates` map. An appender naming a template with
+//! `template` takes each field it doesn't set itself from the template, so a
+//! field like `policy` is either inherited or replaced as a whole:
+//!
+//! ```yaml
+//! appender_templates:
+//!   rolling_base:
+//!     kind: rolling_file
+//!     encoder:
+//!       pattern: "{d} {l} {t} - {m}{n}"
+//!     policy:
+//!       trigger:
+//!         kind: size
+//!         limit: 10 mb
+//!       roller:
+//!         kind: delete
+//!
+//! appenders:
+//!   requests:
+//!     template: rolling_base
+//!     path: log/requests.log
+//!   db:
+//!     template: rolling_base
+//!     path: log/db.log
+//! ```
+//!
+//! Templates can't themselves be based on other templates.
+//!
+//! # Includes
+//!
+//! A file loaded with `init_file` may contain an `include` list of other
+//! config files to merge underneath it. See `init_file` for details.
+//!
+//! # Profiles
+//!
+//! A `profiles` map of named partial configurations may override parts of
+//! the rest of the file, for example to raise levels in development without
+//! maintaining a separate file. The profile is selected by the
+//! `LOG4RS_PROFILE` environment variable or `init_file_with_profile`. See
+//! `init_file` for details.
+//!
+//! # Secrets
+//!
+//! Credentials in appender configurations, like an API key, need not be
+//! written in the file. A value of the form `env:NAME` is replaced with the
+//! value of the environment variable `NAME`, and a value of the form
+//! `file:PATH` with the contents of the file at `PATH`, with surrounding
+//! whitespace removed, when the appender is created. It is an error for the
+//! variable to be unset or the file to be unreadable. Each appender's
+//! documentation notes which fields accept references.
+//!
+//! # Lenient mode
+//!
+//! By default, an unknown field is an error, failing the whole config if it
+//! is at the top level, in `root`, or in a logger, or else failing the
+//! component it appears in. When a fleet runs several versions of a program,
+//! a config using fields added in a newer version of log4rs can be loaded by
+//! the older versions with `Deserializers::set_lenient`, which drops unknown
+//! fields with a warning instead.
+//!
+//! # Durations and sizes
+//!
+//! Fields holding a duration, like `refresh_rate` or a batch timeout, take a
+//! string with units such as `15 minutes`, `30s`, or `500ms`, as parsed by
+//! the humantime crate. A bare number is rejected since its unit would be
+//! ambiguous. Fields holding a size in bytes, like a size trigger's `limit`
+//! or a spill queue's `segment_size`, take a number of bytes or a string
+//! such as `250 MB`, `1GiB`, or `1.5 gb`, where every unit is a power of
+//! 1024.
+//!
+//! # Environment variables
+//!
+//! When a configuration is loaded with `init_file`, references to environment
+//! variables in string values are replaced with the variables' values. A
+//! reference has the form `${NAME}`, or `${NAME:-default}` to fall back to a
+//! default when the variable is unset or empty. Referencing an unset variable
+//! without a default is an error. `$${` produces a literal `${`.
+//!
+//! ```yaml
+//! appenders:
+//!   file:
+//!     kind: file
+//!     path: ${LOG_DIR:-/var/log/myapp}/requests.log
+//! root:
+//!   level: ${LOG_LEVEL:-info}
+//!   appenders:
+//!     - file
+//! ```
+use log::LevelFilter;
+use serde::de::{self, DeserializeOwned};
 use serde_value::Value;
 use std::borrow::ToOwned;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error;
 use std::fmt;
 use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use typemap::{Key, ShareCloneMap};
 
 use config;
-use append::AppenderConfig;
+use append::{Append, AppenderConfig};
+use intercept::{Interceptor, InterceptorConfig};
+use RawConfigPrivateExt;
 
 /// A trait implemented by traits which are deserializable.
 pub trait Deserializable: 'static {
@@ -110,9 +316,21 @@ pub trait Deserializable: 'static {
     ///
     /// For example, the `Deserializable` implementation for the `Append` trait returns "appender".
     fn name() -> &'static str;
+
+    /// Returns the kind used when a component's kind isn't specified, if
+    /// there is one.
+    ///
+    /// In lenient mode, a component of an unknown kind falls back to the
+    /// default kind with its default configuration.
+    fn default_kind() -> Option<&'static str> {
+        None
+    }
 }
 
 /// A trait for objects that can deserialize log4rs components out of a config.
+///
+/// With the `derive` feature, `#[derive(Deserializer)]` generates an
+/// implementation from a component's config struct.
 pub trait Deserialize: Send + Sync + 'static {
     /// The trait that this deserializer will create.
     type Trait: ?Sized + Deserializable;
@@ -127,6 +345,43 @@ pub trait Deserialize: Send + Sync + 'static {
                    -> Result<Box<Self::Trait>, Box<error::Error + Sync + Send>>;
 }
 
+/// Derives a deserializer for a custom component from its config struct.
+///
+/// The struct must also implement serde's `Deserialize`, and the
+/// `component` attribute names the trait of the component, like `Append`,
+/// `Encode` or `Filter`. The generated deserializer is a unit struct named
+/// after the config struct, with a `Config` suffix replaced by
+/// `Deserializer`, and builds the component with the struct's `build`
+/// method, which is passed the `Deserializers` for nested components like
+/// encoders:
+///
+/// ```ignore
+/// #[derive(Deserialize, Deserializer)]
+/// #[serde(deny_unknown_fields)]
+/// #[log4rs(component = "Append")]
+/// pub struct KafkaAppenderConfig {
+///     brokers: Vec<String>,
+///     encoder: Option<EncoderConfig>,
+/// }
+///
+/// impl KafkaAppenderConfig {
+///     fn build(self, deserializers: &Deserializers) -> Result<KafkaAppender, Box<Error + Sync + Send>> {
+///         ...
+///     }
+/// }
+///
+/// deserializers.insert("kafka", KafkaAppenderDeserializer);
+/// ```
+///
+/// The `deserializer` attribute sets a different name for the deserializer,
+/// and `build` a different path to the function building the component from
+/// the config and the `Deserializers`. The build function may return any
+/// error which converts into a `Box<Error + Sync + Send>`.
+///
+/// Requires the `derive` feature.
+#[cfg(feature = "derive")]
+pub use log4rs_derive::Deserializer;
+
 trait ErasedDeserialize: Send + Sync + 'static {
     type Trait: ?Sized;
 
@@ -134,6 +389,14 @@ trait ErasedDeserialize: Send + Sync + 'static {
                    config: Value,
                    deserializers: &Deserializers)
                    -> Result<Box<Self::Trait>, Box<error::Error + Sync + Send>>;
+
+    fn check(&self,
+             config: Value,
+             deserializers: &Deserializers)
+             -> Result<(), Box<error::Error + Sync + Send>>;
+
+    #[cfg(feature = "json_schema")]
+    fn fields(&self) -> Option<&'static [&'static str]>;
 }
 
 struct DeserializeEraser<T>(T);
@@ -147,9 +410,37 @@ impl<T> ErasedDeserialize for DeserializeEraser<T>
                    config: Value,
                    deserializers: &Deserializers)
                    -> Result<Box<Self::Trait>, Box<error::Error + Sync + Send>> {
-        let config = config.deserialize_into()?;
+        let config = self.config(config, deserializers)?;
         self.0.deserialize(config, deserializers)
     }
+
+    fn check(&self,
+             config: Value,
+             deserializers: &Deserializers)
+             -> Result<(), Box<error::Error + Sync + Send>> {
+        self.config(config, deserializers).map(|_| ())
+    }
+
+    #[cfg(feature = "json_schema")]
+    fn fields(&self) -> Option<&'static [&'static str]> {
+        schema::fields::<T::Config>()
+    }
+}
+
+impl<T> DeserializeEraser<T>
+    where T: Deserialize
+{
+    fn config(&self,
+              config: Value,
+              deserializers: &Deserializers)
+              -> Result<T::Config, Box<error::Error + Sync + Send>> {
+        let config = if deserializers.lenient() {
+            ::priv_serde::deserialize_lenient(config, &[])?
+        } else {
+            config.deserialize_into()?
+        };
+        Ok(config)
+    }
 }
 
 struct KeyAdaptor<T: ?Sized>(PhantomData<T>);
@@ -158,6 +449,38 @@ impl<T: ?Sized + 'static> Key for KeyAdaptor<T> {
     type Value = HashMap<String, Arc<ErasedDeserialize<Trait = T>>>;
 }
 
+struct BaseDir;
+
+impl Key for BaseDir {
+    type Value = PathBuf;
+}
+
+struct Lenient;
+
+impl Key for Lenient {
+    type Value = bool;
+}
+
+/// A function registering deserializers, submitted by the
+/// `register_deserializers!` macro.
+///
+/// Requires the `plugins` feature.
+#[cfg(feature = "plugins")]
+pub struct Plugin {
+    register: fn(&mut Deserializers),
+}
+
+#[cfg(feature = "plugins")]
+impl Plugin {
+    #[doc(hidden)]
+    pub const fn new(register: fn(&mut Deserializers)) -> Plugin {
+        Plugin { register: register }
+    }
+}
+
+#[cfg(feature = "plugins")]
+::inventory::collect!(Plugin);
+
 /// A container of `Deserialize`rs.
 #[derive(Clone)]
 pub struct Deserializers(ShareCloneMap);
@@ -175,6 +498,60 @@ impl Default for Deserializers {
         #[cfg(feature = "rolling_file_appender")]
         d.insert("rolling_file", ::append::rolling_file::RollingFileAppenderDeserializer);
 
+        #[cfg(all(unix, feature = "unix_socket_appender"))]
+        d.insert("unix_socket", ::append::unix_socket::UnixSocketAppenderDeserializer);
+
+        #[cfg(feature = "websocket_appender")]
+        d.insert("websocket", ::append::websocket::WebSocketAppenderDeserializer);
+
+        #[cfg(feature = "loki_appender")]
+        d.insert("loki", ::append::loki::LokiAppenderDeserializer);
+
+        #[cfg(feature = "clickhouse_appender")]
+        d.insert("clickhouse", ::append::clickhouse::ClickHouseAppenderDeserializer);
+
+        #[cfg(feature = "wal_appender")]
+        d.insert("wal", ::append::wal::WalAppenderDeserializer);
+
+        #[cfg(all(unix, feature = "fd_appender"))]
+        d.insert("fd", ::append::fd::FdAppenderDeserializer);
+
+        #[cfg(feature = "honeycomb_appender")]
+        d.insert("honeycomb", ::append::honeycomb::HoneycombAppenderDeserializer);
+
+        #[cfg(feature = "newrelic_appender")]
+        d.insert("newrelic", ::append::newrelic::NewRelicAppenderDeserializer);
+
+        #[cfg(feature = "influxdb_appender")]
+        d.insert("influxdb", ::append::influxdb::InfluxDbAppenderDeserializer);
+
+        #[cfg(feature = "relp_appender")]
+        d.insert("relp", ::append::relp::RelpAppenderDeserializer);
+
+        #[cfg(feature = "statsd_appender")]
+        d.insert("statsd", ::append::statsd::StatsdAppenderDeserializer);
+
+        #[cfg(feature = "shadow_appender")]
+        d.insert("shadow", ::append::shadow::ShadowAppenderDeserializer);
+
+        #[cfg(all(target_os = "android", feature = "logcat_appender"))]
+        d.insert("logcat", ::append::logcat::LogcatAppenderDeserializer);
+
+        #[cfg(all(any(target_os = "macos", target_os = "ios"), feature = "os_log_appender"))]
+        d.insert("os_log", ::append::os_log::OsLogAppenderDeserializer);
+
+        #[cfg(all(windows, feature = "debug_string_appender"))]
+        d.insert("debug_string", ::append::debug_string::DebugStringAppenderDeserializer);
+
+        #[cfg(all(target_arch = "wasm32", feature = "wasm_console_appender"))]
+        d.insert("wasm_console", ::append::wasm_console::WasmConsoleAppenderDeserializer);
+
+        #[cfg(feature = "temp_file_appender")]
+        d.insert("temp_file", ::append::temp_file::TempFileAppenderDeserializer);
+
+        #[cfg(feature = "audit_appender")]
+        d.insert("audit", ::append::audit::AuditAppenderDeserializer);
+
         #[cfg(feature = "compound_policy")]
         d.insert("compound", ::append::rolling_file::policy::compound::CompoundPolicyDeserializer);
 
@@ -184,18 +561,104 @@ impl Default for Deserializers {
         #[cfg(feature = "fixed_window_roller")]
         d.insert("fixed_window", ::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRollerDeserializer);
 
+        #[cfg(feature = "free_space_trigger")]
+        d.insert("free_space", ::append::rolling_file::policy::compound::trigger::free_space::FreeSpaceTriggerDeserializer);
+
         #[cfg(feature = "size_trigger")]
         d.insert("size", ::append::rolling_file::policy::compound::trigger::size::SizeTriggerDeserializer);
 
         #[cfg(feature = "json_encoder")]
         d.insert("json", ::encode::json::JsonEncoderDeserializer);
 
+        #[cfg(feature = "logfmt_encoder")]
+        d.insert("logfmt", ::encode::logfmt::LogfmtEncoderDeserializer);
+
+        #[cfg(feature = "hash_chain_encoder")]
+        d.insert("hash_chain", ::encode::hash_chain::HashChainEncoderDeserializer);
+
         #[cfg(feature = "pattern_encoder")]
         d.insert("pattern", ::encode::pattern::PatternEncoderDeserializer);
 
         #[cfg(feature = "threshold_filter")]
         d.insert("threshold", ::filter::threshold::ThresholdFilterDeserializer);
 
+        #[cfg(feature = "target_filter")]
+        d.insert("target", ::filter::target::TargetFilterDeserializer);
+
+        #[cfg(feature = "mdc_filter")]
+        d.insert("mdc", ::filter::mdc::MdcFilterDeserializer);
+
+        #[cfg(feature = "thread_filter")]
+        d.insert("thread", ::filter::thread::ThreadFilterDeserializer);
+
+        #[cfg(feature = "level_range_filter")]
+        d.insert("level_range", ::filter::level_range::LevelRangeFilterDeserializer);
+
+        #[cfg(feature = "rate_limit_filter")]
+        d.insert("rate_limit", ::filter::rate_limit::RateLimitFilterDeserializer);
+
+        #[cfg(feature = "burst_filter")]
+        d.insert("burst", ::filter::burst::BurstFilterDeserializer);
+
+        #[cfg(feature = "schedule_filter")]
+        d.insert("schedule", ::filter::schedule::ScheduleFilterDeserializer);
+
+        #[cfg(feature = "boolean_filter")]
+        d.insert("all", ::filter::boolean::AllFilterDeserializer);
+
+        #[cfg(feature = "boolean_filter")]
+        d.insert("any", ::filter::boolean::AnyFilterDeserializer);
+
+        #[cfg(feature = "boolean_filter")]
+        d.insert("not", ::filter::boolean::NotFilterDeserializer);
+
+        #[cfg(feature = "dynamic_filter")]
+        d.insert("dynamic", ::filter::dynamic::DynamicFilterDeserializer);
+
+        #[cfg(feature = "expression_filter")]
+        d.insert("expression", ::filter::expression::ExpressionFilterDeserializer);
+
+        #[cfg(feature = "deny_list_filter")]
+        d.insert("deny_list", ::filter::deny_list::DenyListFilterDeserializer);
+
+        #[cfg(feature = "location_filter")]
+        d.insert("location", ::filter::location::LocationFilterDeserializer);
+
+        #[cfg(feature = "kv_filter")]
+        d.insert("kv", ::filter::kv::KvFilterDeserializer);
+
+        #[cfg(feature = "first_n_filter")]
+        d.insert("first_n", ::filter::first_n::FirstNFilterDeserializer);
+
+        #[cfg(feature = "env_filter")]
+        d.insert("env", ::filter::env::EnvFilterDeserializer);
+
+        #[cfg(feature = "level_sample_filter")]
+        d.insert("level_sample", ::filter::level_sample::LevelSampleFilterDeserializer);
+
+        #[cfg(feature = "script_filter")]
+        d.insert("script", ::filter::script::ScriptFilterDeserializer);
+
+        #[cfg(feature = "marker_filter")]
+        d.insert("marker", ::filter::marker::MarkerFilterDeserializer);
+
+        #[cfg(feature = "size_filter")]
+        d.insert("size", ::filter::size::SizeFilterDeserializer);
+
+        #[cfg(feature = "fields_interceptor")]
+        d.insert("fields", ::intercept::fields::FieldsInterceptorDeserializer);
+
+        #[cfg(feature = "level_interceptor")]
+        d.insert("level", ::intercept::level::LevelInterceptorDeserializer);
+
+        #[cfg(feature = "replace_interceptor")]
+        d.insert("replace", ::intercept::replace::ReplaceInterceptorDeserializer);
+
+        #[cfg(feature = "plugins")]
+        for plugin in ::inventory::iter::<Plugin> {
+            (plugin.register)(&mut d);
+        }
+
         d
     }
 }
@@ -203,7 +666,8 @@ impl Default for Deserializers {
 impl Deserializers {
     /// Creates a `Deserializers` with default mappings.
     ///
-    /// All are enabled by default.
+    /// All are enabled by default, except for those noted below which pull
+    /// in large dependencies.
     ///
     /// * Appenders
     ///     * "console" -> `ConsoleAppenderDeserializer`
@@ -212,14 +676,103 @@ impl Deserializers {
     ///         * Requires the `file_appender` feature.
     ///     * "rolling_file" -> `RollingFileAppenderDeserializer`
     ///         * Requires the `rolling_file_appender` feature.
+    ///     * "unix_socket" -> `UnixSocketAppenderDeserializer`
+    ///         * Requires the `unix_socket_appender` feature and a Unix platform.
+    ///     * "websocket" -> `WebSocketAppenderDeserializer`
+    ///         * Requires the `websocket_appender` feature, which isn't enabled by default.
+    ///     * "loki" -> `LokiAppenderDeserializer`
+    ///         * Requires the `loki_appender` feature, which isn't enabled by default.
+    ///     * "clickhouse" -> `ClickHouseAppenderDeserializer`
+    ///         * Requires the `clickhouse_appender` feature, which isn't enabled by default.
+    ///     * "wal" -> `WalAppenderDeserializer`
+    ///         * Requires the `wal_appender` feature.
+    ///     * "fd" -> `FdAppenderDeserializer`
+    ///         * Requires the `fd_appender` feature and a Unix platform.
+    ///     * "honeycomb" -> `HoneycombAppenderDeserializer`
+    ///         * Requires the `honeycomb_appender` feature.
+    ///     * "newrelic" -> `NewRelicAppenderDeserializer`
+    ///         * Requires the `newrelic_appender` feature.
+    ///     * "influxdb" -> `InfluxDbAppenderDeserializer`
+    ///         * Requires the `influxdb_appender` feature.
+    ///     * "relp" -> `RelpAppenderDeserializer`
+    ///         * Requires the `relp_appender` feature.
+    ///     * "statsd" -> `StatsdAppenderDeserializer`
+    ///         * Requires the `statsd_appender` feature.
+    ///     * "shadow" -> `ShadowAppenderDeserializer`
+    ///         * Requires the `shadow_appender` feature.
+    ///     * "logcat" -> `LogcatAppenderDeserializer`
+    ///         * Requires the `logcat_appender` feature and an Android target.
+    ///     * "os_log" -> `OsLogAppenderDeserializer`
+    ///         * Requires the `os_log_appender` feature and a macOS or iOS target.
+    ///     * "debug_string" -> `DebugStringAppenderDeserializer`
+    ///         * Requires the `debug_string_appender` feature and a Windows platform.
+    ///     * "wasm_console" -> `WasmConsoleAppenderDeserializer`
+    ///         * Requires the `wasm_console_appender` feature and a `wasm32` target.
+    ///     * "temp_file" -> `TempFileAppenderDeserializer`
+    ///         * Requires the `temp_file_appender` feature.
+    ///     * "audit" -> `AuditAppenderDeserializer`
+    ///         * Requires the `audit_appender` feature.
     /// * Encoders
     ///     * "pattern" -> `PatternEncoderDeserializer`
     ///         * Requires the `pattern_encoder` feature.
     ///     * "json" -> `JsonEncoderDeserializer`
     ///         * Requires the `json_encoder` feature.
+    ///     * "logfmt" -> `LogfmtEncoderDeserializer`
+    ///         * Requires the `logfmt_encoder` feature.
+    ///     * "hash_chain" -> `HashChainEncoderDeserializer`
+    ///         * Requires the `hash_chain_encoder` feature, which isn't enabled by default.
     /// * Filters
     ///     * "threshold" -> `ThresholdFilterDeserializer`
     ///         * Requires the `threshold_filter` feature.
+    ///     * "target" -> `TargetFilterDeserializer`
+    ///         * Requires the `target_filter` feature.
+    ///     * "mdc" -> `MdcFilterDeserializer`
+    ///         * Requires the `mdc_filter` feature.
+    ///     * "thread" -> `ThreadFilterDeserializer`
+    ///         * Requires the `thread_filter` feature.
+    ///     * "level_range" -> `LevelRangeFilterDeserializer`
+    ///         * Requires the `level_range_filter` feature.
+    ///     * "rate_limit" -> `RateLimitFilterDeserializer`
+    ///         * Requires the `rate_limit_filter` feature.
+    ///     * "burst" -> `BurstFilterDeserializer`
+    ///         * Requires the `burst_filter` feature.
+    ///     * "schedule" -> `ScheduleFilterDeserializer`
+    ///         * Requires the `schedule_filter` feature.
+    ///     * "all" -> `AllFilterDeserializer`
+    ///         * Requires the `boolean_filter` feature.
+    ///     * "any" -> `AnyFilterDeserializer`
+    ///         * Requires the `boolean_filter` feature.
+    ///     * "not" -> `NotFilterDeserializer`
+    ///         * Requires the `boolean_filter` feature.
+    ///     * "dynamic" -> `DynamicFilterDeserializer`
+    ///         * Requires the `dynamic_filter` feature.
+    ///     * "expression" -> `ExpressionFilterDeserializer`
+    ///         * Requires the `expression_filter` feature.
+    ///     * "deny_list" -> `DenyListFilterDeserializer`
+    ///         * Requires the `deny_list_filter` feature.
+    ///     * "location" -> `LocationFilterDeserializer`
+    ///         * Requires the `location_filter` feature.
+    ///     * "kv" -> `KvFilterDeserializer`
+    ///         * Requires the `kv_filter` feature.
+    ///     * "first_n" -> `FirstNFilterDeserializer`
+    ///         * Requires the `first_n_filter` feature.
+    ///     * "env" -> `EnvFilterDeserializer`
+    ///         * Requires the `env_filter` feature.
+    ///     * "level_sample" -> `LevelSampleFilterDeserializer`
+    ///         * Requires the `level_sample_filter` feature.
+    ///     * "script" -> `ScriptFilterDeserializer`
+    ///         * Requires the `script_filter` feature, which isn't enabled by default.
+    ///     * "marker" -> `MarkerFilterDeserializer`
+    ///         * Requires the `marker_filter` feature.
+    ///     * "size" -> `SizeFilterDeserializer`
+    ///         * Requires the `size_filter` feature.
+    /// * Interceptors
+    ///     * "fields" -> `FieldsInterceptorDeserializer`
+    ///         * Requires the `fields_interceptor` feature.
+    ///     * "level" -> `LevelInterceptorDeserializer`
+    ///         * Requires the `level_interceptor` feature.
+    ///     * "replace" -> `ReplaceInterceptorDeserializer`
+    ///         * Requires the `replace_interceptor` feature.
     /// * Policies
     ///     *  "compound" -> `CompoundPolicyDeserializer`
     ///         * Requires the `compound_policy` feature.
@@ -229,8 +782,14 @@ impl Deserializers {
     ///     * "fixed_window" -> `FixedWindowRollerDeserializer`
     ///         * Requires the `fixed_window_roller` feature.
     /// * Triggers
+    ///     * "free_space" -> `FreeSpaceTriggerDeserializer`
+    ///         * Requires the `free_space_trigger` feature.
     ///     * "size" -> `SizeTriggerDeserializer`
     ///         * Requires the `size_trigger` feature.
+    ///
+    /// Deserializers registered by other crates with the
+    /// `register_deserializers!` macro are then added, replacing any of the
+    /// above with the same kind. This requires the `plugins` feature.
     pub fn new() -> Deserializers {
         Deserializers::default()
     }
@@ -250,6 +809,77 @@ impl Deserializers {
             .insert(kind.to_owned(), Arc::new(DeserializeEraser(deserializer)));
     }
 
+    /// Sets the directory which relative paths in component configurations,
+    /// like a file appender's `path`, are resolved against.
+    ///
+    /// By default, relative paths are resolved against the working directory
+    /// of the process. A config file's `base_dir` takes precedence over this.
+    pub fn set_base_dir<P>(&mut self, dir: P)
+        where P: Into<PathBuf>
+    {
+        self.0.insert::<BaseDir>(dir.into());
+    }
+
+    /// Returns the directory relative paths are resolved against, if set.
+    pub fn base_dir(&self) -> Option<&Path> {
+        self.0.get::<BaseDir>().map(|p| &**p)
+    }
+
+    /// Resolves a path from a component configuration against the base
+    /// directory.
+    ///
+    /// Deserializers of components which take paths should use this.
+    pub fn resolve_path<P>(&self, path: P) -> PathBuf
+        where P: AsRef<Path>
+    {
+        match self.base_dir() {
+            Some(dir) => dir.join(path),
+            None => path.as_ref().to_owned(),
+        }
+    }
+
+    /// Sets whether unrecognized parts of a configuration are tolerated.
+    ///
+    /// In lenient mode, unknown fields are dropped with a warning rather than
+    /// failing the config or component containing them, and a component of
+    /// an unknown kind falls back to the default kind of its trait, like the
+    /// `pattern` encoder, if there is one. This lets a config using features
+    /// of a newer version of log4rs still be loaded by an older one. Disabled
+    /// by default.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.0.insert::<Lenient>(lenient);
+    }
+
+    /// Returns whether unrecognized parts of a configuration are tolerated.
+    pub fn lenient(&self) -> bool {
+        self.0.get::<Lenient>().cloned().unwrap_or(false)
+    }
+
+    /// Returns a JSON Schema describing config files which can be
+    /// deserialized with these deserializers.
+    ///
+    /// The schema includes every registered appender, encoder, filter, and
+    /// rolling file policy kind along with the names of its fields, so
+    /// editors can offer completion and catch misspelled or misplaced fields.
+    /// Nested components, like an appender's encoder, are described in turn,
+    /// but the types of other fields are not.
+    ///
+    /// Requires the `json_schema` feature.
+    #[cfg(feature = "json_schema")]
+    pub fn json_schema(&self) -> ::serde_json::Value {
+        schema::schema(self)
+    }
+
+    #[cfg(feature = "json_schema")]
+    fn fields<T: ?Sized + Deserializable>(&self) -> Vec<(String, Option<&'static [&'static str]>)> {
+        let mut kinds = match self.0.get::<KeyAdaptor<T>>() {
+            Some(m) => m.iter().map(|(k, d)| (k.clone(), d.fields())).collect::<Vec<_>>(),
+            None => vec![],
+        };
+        kinds.sort_by(|a, b| a.0.cmp(&b.0));
+        kinds
+    }
+
     /// Deserializes a value of a specific type and kind.
     pub fn deserialize<T: ?Sized>(&self,
                                   kind: &str,
@@ -259,6 +889,39 @@ impl Deserializers {
     {
         match self.0.get::<KeyAdaptor<T>>().and_then(|m| m.get(kind)) {
             Some(b) => b.deserialize(config, self),
+            None if self.lenient() && T::default_kind().is_some_and(|k| k != kind) => {
+                let default = T::default_kind().unwrap();
+                ::handle_error(&*Box::<error::Error + Sync + Send>::from(
+                    format!("unknown {} kind `{}`, using `{}`", T::name(), kind, default)));
+                self.deserialize(default, Value::Map(BTreeMap::new()))
+            }
+            None => {
+                Err(format!("no {} deserializer for kind `{}` registered",
+                            T::name(),
+                            kind)
+                    .into())
+            }
+        }
+    }
+
+    /// Checks a component's configuration without creating the component.
+    ///
+    /// This returns an error if the kind isn't registered or the
+    /// configuration doesn't have the fields of the deserializer's `Config`,
+    /// but not for problems only found when creating the component, like a
+    /// file which can't be opened. Components nested in the configuration,
+    /// like an appender's encoder, aren't checked.
+    pub fn check<T>(&self,
+                    kind: &str,
+                    config: Value)
+                    -> Result<(), Box<error::Error + Sync + Send>>
+        where T: ?Sized + Deserializable
+    {
+        match self.0.get::<KeyAdaptor<T>>().and_then(|m| m.get(kind)) {
+            Some(b) => b.check(config, self),
+            None if self.lenient() && T::default_kind().is_some_and(|k| k != kind) => {
+                self.check::<T>(T::default_kind().unwrap(), Value::Map(BTreeMap::new()))
+            }
             None => {
                 Err(format!("no {} deserializer for kind `{}` registered",
                             T::name(),
@@ -277,6 +940,7 @@ pub struct Error(ErrorKind, Box<error::Error + Sync + Send>);
 enum ErrorKind {
     Appender(String),
     Filter(String),
+    Interceptor(String),
 }
 
 impl fmt::Display for Error {
@@ -288,6 +952,9 @@ impl fmt::Display for Error {
             ErrorKind::Filter(ref name) => {
                 write!(fmt, "error deserializing filter attached to appender {}: {}", name, self.1)
             }
+            ErrorKind::Interceptor(ref kind) => {
+                write!(fmt, "error deserializing {} interceptor: {}", kind, self.1)
+            }
         }
     }
 }
@@ -306,33 +973,60 @@ impl error::Error for Error {
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct RawConfig {
-    #[serde(deserialize_with = "de_duration", default)]
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
     refresh_rate: Option<Duration>,
+    level_override_var: Option<String>,
+    base_dir: Option<PathBuf>,
+    #[serde(deserialize_with = "::priv_serde::de_error_policy", default)]
+    on_error: Option<config::ErrorPolicy>,
+    #[serde(deserialize_with = "::priv_serde::de_backpressure", default)]
+    backpressure: Option<config::Backpressure>,
+    #[serde(deserialize_with = "::priv_serde::de_filter", default = "default_console_default")]
+    default_console: LevelFilter,
     #[serde(default)]
     root: Root,
     #[serde(default)]
-    appenders: HashMap<String, AppenderConfig>,
+    appender_templates: HashMap<String, BTreeMap<Value, Value>>,
     #[serde(default)]
+    appenders: HashMap<String, BTreeMap<Value, Value>>,
+    #[serde(deserialize_with = "de_loggers", default)]
     loggers: HashMap<String, Logger>,
+    #[serde(default)]
+    interceptors: Vec<InterceptorConfig>,
+    #[serde(default)]
+    plugins: Vec<PathBuf>,
 }
 
 impl RawConfig {
     /// Returns the root.
+    ///
+    /// References to disabled appenders are left out.
     pub fn root(&self) -> config::Root {
-        config::Root::builder()
-            .appenders(self.root.appenders.clone())
-            .build(self.root.level)
+        let mut root = config::Root::builder();
+        for appender in self.enabled_appenders(&self.root.appenders) {
+            root = match appender.level {
+                Some(level) => root.appender_with_level(appender.name.clone(), level),
+                None => root.appender(appender.name.clone()),
+            };
+        }
+        root.build(self.root.level)
     }
 
     /// Returns the loggers.
+    ///
+    /// References to disabled appenders are left out.
     pub fn loggers(&self) -> Vec<config::Logger> {
         self.loggers
             .iter()
             .map(|(name, logger)| {
-                config::Logger::builder()
-                    .appenders(logger.appenders.clone())
-                    .additive(logger.additive)
-                    .build(name.clone(), logger.level)
+                let mut builder = config::Logger::builder().additive(logger.additive);
+                for appender in self.enabled_appenders(&logger.appenders) {
+                    builder = match appender.level {
+                        Some(level) => builder.appender_with_level(appender.name.clone(), level),
+                        None => builder.appender(appender.name.clone()),
+                    };
+                }
+                builder.build(name.clone(), logger.level)
             }).collect()
     }
 
@@ -342,73 +1036,186 @@ impl RawConfig {
     pub fn appenders_lossy(&self,
                            deserializers: &Deserializers)
                            -> (Vec<config::Appender>, Vec<Error>) {
-        let mut appenders = vec![];
-        let mut errors = vec![];
+        self.appenders_reusing(deserializers, |_, _| None)
+    }
 
-        for (name, appender) in &self.appenders {
-            let mut builder = config::Appender::builder();
-            for filter in &appender.filters {
-                match deserializers.deserialize(&filter.kind, filter.config.clone()) {
-                    Ok(filter) => builder = builder.filter(filter),
-                    Err(e) => errors.push(Error(ErrorKind::Filter(name.clone()), e)),
-                }
-            }
-            match deserializers.deserialize(&appender.kind, appender.config.clone()) {
-                Ok(appender) => appenders.push(builder.build(name.clone(), appender)),
-                Err(e) => errors.push(Error(ErrorKind::Appender(name.clone()), e)),
+    /// Returns the interceptors, in the order they are applied.
+    ///
+    /// Any interceptors which fail to be deserialized will be ignored.
+    pub fn interceptors_lossy(&self,
+                              deserializers: &Deserializers)
+                              -> (Vec<Box<Interceptor>>, Vec<Error>) {
+        let mut interceptors = vec![];
+        let mut errors = vec![];
+        for interceptor in &self.interceptors {
+            match deserializers.deserialize(&interceptor.kind, interceptor.config.clone()) {
+                Ok(i) => interceptors.push(i),
+                Err(e) => errors.push(Error(ErrorKind::Interceptor(interceptor.kind.clone()), e)),
             }
         }
+        (interceptors, errors)
+    }
 
-        (appenders, errors)
+    /// Returns the paths of the shared libraries to load components from.
+    pub fn plugins(&self) -> &[PathBuf] {
+        &self.plugins
     }
 
     /// Returns the requested refresh rate.
     pub fn refresh_rate(&self) -> Option<Duration> {
         self.refresh_rate
     }
-}
 
-fn de_duration<'de, D>(d: D) -> Result<Option<Duration>, D::Error>
-    where D: de::Deserializer<'de>
-{
-    struct S(Duration);
+    /// Returns the policy for appender errors.
+    ///
+    /// Errors are reported to stderr instead of to a disabled appender.
+    pub fn on_error(&self) -> config::ErrorPolicy {
+        match self.on_error {
+            Some(config::ErrorPolicy::Appender(ref name)) if self.disabled(name) => {
+                config::ErrorPolicy::default()
+            }
+            ref on_error => on_error.clone().unwrap_or_default(),
+        }
+    }
 
-    impl<'de2> de::Deserialize<'de2> for S {
-        fn deserialize<D>(d: D) -> Result<S, D::Error>
-            where D: de::Deserializer<'de2>
-        {
-            struct V;
+    /// Returns the policy for full appender queues.
+    pub fn backpressure(&self) -> config::Backpressure {
+        self.backpressure.unwrap_or_default()
+    }
 
-            impl<'de3> de::Visitor<'de3> for V {
-                type Value = S;
+    /// Returns the maximum level of the console appender used if no logger
+    /// has any appenders.
+    ///
+    /// The console appender is turned off if any appender is disabled, since
+    /// disabling every appender is meant to silence the logger.
+    pub fn default_console(&self) -> LevelFilter {
+        if self.appenders.keys().any(|name| self.disabled(name)) {
+            LevelFilter::Off
+        } else {
+            self.default_console
+        }
+    }
 
-                fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-                    fmt.write_str("a duration")
-                }
+    /// Returns the directory relative paths are resolved against.
+    pub fn base_dir(&self) -> Option<&Path> {
+        self.base_dir.as_deref()
+    }
 
-                fn visit_str<E>(self, v: &str) -> Result<S, E>
-                    where E: de::Error
-                {
-                    humantime::parse_duration(v)
-                        .map(S)
-                        .map_err(|e| E::custom(e))
+    /// Returns the name of the environment variable containing level
+    /// overrides.
+    pub fn level_override_var(&self) -> Option<&str> {
+        self.level_override_var.as_deref()
+    }
+}
+
+impl RawConfig {
+    // Whether an appender is defined with `enabled: false`, directly or by
+    // its template.
+    fn disabled(&self, name: &str) -> bool {
+        let appender = match self.appenders.get(name) {
+            Some(appender) => appender,
+            None => return false,
+        };
+        let key = Value::String("enabled".to_owned());
+        let enabled = appender.get(&key).or_else(|| {
+            match appender.get(&Value::String("template".to_owned())) {
+                Some(Value::String(template)) => {
+                    self.appender_templates.get(template).and_then(|t| t.get(&key))
                 }
+                _ => None,
             }
+        });
+        enabled == Some(&Value::Bool(false))
+    }
 
-            d.deserialize_any(V)
-        }
+    fn enabled_appenders<'a>(&'a self,
+                             references: &'a [AppenderRef])
+                             -> impl Iterator<Item = &'a AppenderRef> + 'a {
+        references.iter().filter(move |r| !self.disabled(&r.name))
+    }
+
+    // Fills in the fields an appender leaves out from its template.
+    fn appender_config(&self,
+                       appender: &BTreeMap<Value, Value>)
+                       -> Result<AppenderConfig, Box<error::Error + Sync + Send>> {
+        let mut appender = appender.clone();
+        remove_enabled(&mut appender)?;
+        let template = match appender.remove(&Value::String("template".to_owned())) {
+            Some(Value::String(template)) => template,
+            Some(_) => return Err("`template` must be the name of an appender template".into()),
+            None => return Ok(Value::Map(appender).deserialize_into()?),
+        };
+        let mut config = match self.appender_templates.get(&template) {
+            Some(config) => config.clone(),
+            None => return Err(format!("appender template `{}` is not defined", template).into()),
+        };
+        remove_enabled(&mut config)?;
+        config.extend(appender);
+        Ok(Value::Map(config).deserialize_into()?)
     }
+}
 
-    Option::<S>::deserialize(d).map(|r| r.map(|s| s.0))
+// `enabled` is handled by the config rather than by the appender.
+fn remove_enabled(appender: &mut BTreeMap<Value, Value>)
+                  -> Result<(), Box<error::Error + Sync + Send>> {
+    match appender.remove(&Value::String("enabled".to_owned())) {
+        None | Some(Value::Bool(_)) => Ok(()),
+        Some(_) => Err("`enabled` must be `true` or `false`".into()),
+    }
+}
+
+impl RawConfigPrivateExt for RawConfig {
+    fn appenders_reusing<F>(&self,
+                            deserializers: &Deserializers,
+                            mut reuse: F)
+                            -> (Vec<config::Appender>, Vec<Error>)
+        where F: FnMut(&str, &AppenderConfig) -> Option<Arc<Append>>
+    {
+        let mut appenders = vec![];
+        let mut errors = vec![];
+
+        for (name, appender) in &self.appenders {
+            if self.disabled(name) {
+                continue;
+            }
+            let appender = match self.appender_config(appender) {
+                Ok(appender) => appender,
+                Err(e) => {
+                    errors.push(Error(ErrorKind::Appender(name.clone()), e));
+                    continue;
+                }
+            };
+            let mut builder = config::Appender::builder().kind(appender.kind.clone());
+            if let Some(level) = appender.level {
+                builder = builder.level(level);
+            }
+            for filter in &appender.filters {
+                match deserializers.deserialize(&filter.kind, filter.config.clone()) {
+                    Ok(filter) => builder = builder.filter(filter),
+                    Err(e) => errors.push(Error(ErrorKind::Filter(name.clone()), e)),
+                }
+            }
+            if let Some(shared) = reuse(name, &appender) {
+                appenders.push(builder.build_shared(name.clone(), shared));
+                continue;
+            }
+            match deserializers.deserialize(&appender.kind, appender.config.clone()) {
+                Ok(appender) => appenders.push(builder.build(name.clone(), appender)),
+                Err(e) => errors.push(Error(ErrorKind::Appender(name.clone()), e)),
+            }
+        }
+
+        (appenders, errors)
+    }
 }
 
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 struct Root {
     #[serde(deserialize_with = "::priv_serde::de_filter", default = "root_level_default")]
-    level: LogLevelFilter,
+    level: LevelFilter,
     #[serde(default)]
-    appenders: Vec<String>,
+    appenders: Vec<AppenderRef>,
 }
 
 impl Default for Root {
@@ -420,23 +1227,346 @@ impl Default for Root {
     }
 }
 
-fn root_level_default() -> LogLevelFilter {
-    LogLevelFilter::Debug
+fn default_console_default() -> LevelFilter {
+    LevelFilter::Trace
+}
+
+fn root_level_default() -> LevelFilter {
+    LevelFilter::Debug
 }
 
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 struct Logger {
     #[serde(deserialize_with = "::priv_serde::de_filter")]
-    level: LogLevelFilter,
+    level: LevelFilter,
     #[serde(default)]
-    appenders: Vec<String>,
+    appenders: Vec<AppenderRef>,
     #[serde(default = "logger_additive_default")]
     additive: bool,
 }
 
 fn logger_additive_default() -> bool { true }
 
+// A reference to an appender from a logger, either its name or a map of its
+// name and the maximum level of the events the logger passes to it.
+struct AppenderRef {
+    name: String,
+    level: Option<LevelFilter>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LeveledAppenderRef {
+    name: String,
+    #[serde(deserialize_with = "::priv_serde::de_filter")]
+    level: LevelFilter,
+}
+
+impl<'de> de::Deserialize<'de> for AppenderRef {
+    fn deserialize<D>(d: D) -> Result<AppenderRef, D::Error>
+        where D: de::Deserializer<'de>
+    {
+        match Value::deserialize(d)? {
+            Value::String(name) => {
+                Ok(AppenderRef {
+                    name: name,
+                    level: None,
+                })
+            }
+            value => {
+                let r = value.deserialize_into::<LeveledAppenderRef>().map_err(|e| e.to_error())?;
+                Ok(AppenderRef {
+                    name: r.name,
+                    level: Some(r.level),
+                })
+            }
+        }
+    }
+}
+
+// A logger in a list of loggers rather than a map keyed by name, like a TOML
+// array of tables.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NamedLogger {
+    name: String,
+    #[serde(deserialize_with = "::priv_serde::de_filter")]
+    level: LevelFilter,
+    #[serde(default)]
+    appenders: Vec<AppenderRef>,
+    #[serde(default = "logger_additive_default")]
+    additive: bool,
+}
+
+fn de_loggers<'de, D>(d: D) -> Result<HashMap<String, Logger>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    struct V;
+
+    impl<'de2> de::Visitor<'de2> for V {
+        type Value = HashMap<String, Logger>;
+
+        fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+            fmt.write_str("a map of loggers or a list of named loggers")
+        }
+
+        fn visit_map<A>(self, map: A) -> Result<HashMap<String, Logger>, A::Error>
+            where A: de::MapAccess<'de2>
+        {
+            de::Deserialize::deserialize(de::value::MapAccessDeserializer::new(map))
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<HashMap<String, Logger>, A::Error>
+            where A: de::SeqAccess<'de2>
+        {
+            let mut loggers = HashMap::new();
+            while let Some(logger) = seq.next_element::<NamedLogger>()? {
+                if loggers.contains_key(&logger.name) {
+                    return Err(de::Error::custom(format!("duplicate logger `{}`", logger.name)));
+                }
+                loggers.insert(logger.name,
+                               Logger {
+                                   level: logger.level,
+                                   appenders: logger.appenders,
+                                   additive: logger.additive,
+                               });
+            }
+            Ok(loggers)
+        }
+    }
+
+    d.deserialize_any(V)
+}
+
+#[cfg(feature = "json_schema")]
+mod schema {
+    use serde::de::{self, Visitor};
+    use serde_json::{Map, Value};
+
+    use append::Append;
+    use encode::Encode;
+    use filter::Filter;
+    use intercept::Interceptor;
+    use super::{Deserializable, Deserializers};
+    #[cfg(feature = "rolling_file_appender")]
+    use append::rolling_file::policy::Policy;
+    #[cfg(all(feature = "rolling_file_appender", feature = "compound_policy"))]
+    use append::rolling_file::policy::compound::roll::Roll;
+    #[cfg(all(feature = "rolling_file_appender", feature = "compound_policy"))]
+    use append::rolling_file::policy::compound::trigger::Trigger;
+
+    // A deserializer which records the fields of the struct it's asked for
+    // and then bails, since serde has no other way to list them.
+    struct FieldProbe<'a>(&'a mut Option<&'static [&'static str]>);
+
+    impl<'de, 'a> de::Deserializer<'de> for FieldProbe<'a> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V>(self, _: V) -> Result<V::Value, de::value::Error>
+            where V: Visitor<'de>
+        {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V>(self,
+                                 _: &'static str,
+                                 fields: &'static [&'static str],
+                                 _: V)
+                                 -> Result<V::Value, de::value::Error>
+            where V: Visitor<'de>
+        {
+            *self.0 = Some(fields);
+            Err(de::Error::custom("probed"))
+        }
+
+        ::serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+            enum identifier ignored_any
+        }
+    }
+
+    pub fn fields<T: de::DeserializeOwned>() -> Option<&'static [&'static str]> {
+        let mut fields = None;
+        let _ = T::deserialize(FieldProbe(&mut fields));
+        fields
+    }
+
+    fn reference(name: &str) -> Value {
+        ::serde_json::json!({ "$ref": format!("#/definitions/{}", name) })
+    }
+
+    // fields which hold nested components
+    fn field(name: &str) -> Value {
+        match name {
+            "encoder" | "filter" | "policy" | "trigger" | "roller" => reference(name),
+            "filters" => ::serde_json::json!({ "type": "array", "items": reference("filter") }),
+            _ => ::serde_json::json!({}),
+        }
+    }
+
+    fn kinds<T: ?Sized + Deserializable>(deserializers: &Deserializers,
+                                         common: &[(&str, Value)],
+                                         default_kind: Option<&str>)
+                                         -> Value {
+        let mut variants = vec![];
+        for (kind, fields) in deserializers.fields::<T>() {
+            let mut properties = Map::new();
+            properties.insert("kind".to_owned(), ::serde_json::json!({ "const": kind }));
+            for &(name, ref schema) in common {
+                properties.insert(name.to_owned(), schema.clone());
+            }
+            // configs which aren't structs can't be described
+            let additional = match fields {
+                Some(fields) => {
+                    for name in fields {
+                        properties.insert((*name).to_owned(), field(name));
+                    }
+                    false
+                }
+                None => true,
+            };
+
+            let mut variant = Map::new();
+            variant.insert("type".to_owned(), "object".into());
+            variant.insert("properties".to_owned(), Value::Object(properties));
+            variant.insert("additionalProperties".to_owned(), additional.into());
+            // the default kind may be left out
+            if default_kind != Some(&*kind) {
+                variant.insert("required".to_owned(), ::serde_json::json!(["kind"]));
+            }
+            variants.push(Value::Object(variant));
+        }
+        ::serde_json::json!({ "anyOf": variants })
+    }
+
+    pub fn schema(deserializers: &Deserializers) -> Value {
+        let level = ::serde_json::json!({
+            "enum": ["off", "error", "warn", "info", "debug", "trace",
+                     "OFF", "ERROR", "WARN", "INFO", "DEBUG", "TRACE"]
+        });
+        let names = ::serde_json::json!({ "type": "array", "items": { "type": "string" } });
+        let references = ::serde_json::json!({
+            "type": "array",
+            "items": {
+                "anyOf": [
+                    { "type": "string" },
+                    {
+                        "type": "object",
+                        "properties": { "name": { "type": "string" }, "level": reference("level") },
+                        "required": ["name"],
+                        "additionalProperties": false
+                    }
+                ]
+            }
+        });
+
+        let mut definitions = Map::new();
+        definitions.insert("level".to_owned(), level);
+        let enabled = ::serde_json::json!({ "type": "boolean" });
+        definitions.insert("appender".to_owned(),
+                           kinds::<Append>(deserializers,
+                                           &[("enabled", enabled),
+                                             ("level", reference("level")),
+                                             ("filters", field("filters"))],
+                                           None));
+        definitions.insert("encoder".to_owned(),
+                           kinds::<Encode>(deserializers, &[], Some("pattern")));
+        definitions.insert("filter".to_owned(), kinds::<Filter>(deserializers, &[], None));
+        definitions.insert("interceptor".to_owned(),
+                           kinds::<Interceptor>(deserializers, &[], None));
+        #[cfg(feature = "rolling_file_appender")]
+        definitions.insert("policy".to_owned(), kinds::<Policy>(deserializers, &[], None));
+        #[cfg(all(feature = "rolling_file_appender", feature = "compound_policy"))]
+        {
+            definitions.insert("trigger".to_owned(), kinds::<Trigger>(deserializers, &[], None));
+            definitions.insert("roller".to_owned(), kinds::<Roll>(deserializers, &[], None));
+        }
+
+        ::serde_json::json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "log4rs configuration",
+            "type": "object",
+            "properties": {
+                "refresh_rate": { "type": "string" },
+                "level_override_var": { "type": "string" },
+                "base_dir": { "type": "string" },
+                "default_console": reference("level"),
+                "on_error": {
+                    "type": "string",
+                    "pattern": "^(ignore|stderr|panic|appender\\(.+\\))$"
+                },
+                "backpressure": {
+                    "type": "string",
+                    "pattern": "^(block|drop_newest|drop_oldest|drop_below\\(.+\\))$"
+                },
+                "include": { "anyOf": [{ "type": "string" }, names.clone()] },
+                "profiles": { "type": "object", "additionalProperties": { "type": "object" } },
+                "interceptors": { "type": "array", "items": reference("interceptor") },
+                "plugins": names.clone(),
+                "root": {
+                    "type": "object",
+                    "properties": {
+                        "level": reference("level"),
+                        "appenders": references.clone()
+                    },
+                    "additionalProperties": false
+                },
+                "appender_templates": {
+                    "type": "object",
+                    "additionalProperties": { "type": "object" }
+                },
+                "appenders": {
+                    "type": "object",
+                    "additionalProperties": {
+                        "anyOf": [
+                            reference("appender"),
+                            {
+                                "type": "object",
+                                "properties": { "template": { "type": "string" } },
+                                "required": ["template"]
+                            }
+                        ]
+                    }
+                },
+                "loggers": {
+                    "anyOf": [
+                        {
+                            "type": "object",
+                            "additionalProperties": {
+                                "type": "object",
+                                "properties": {
+                                    "level": reference("level"),
+                                    "appenders": references.clone(),
+                                    "additive": { "type": "boolean" }
+                                },
+                                "additionalProperties": false
+                            }
+                        },
+                        {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "name": { "type": "string" },
+                                    "level": reference("level"),
+                                    "appenders": references,
+                                    "additive": { "type": "boolean" }
+                                },
+                                "required": ["name"],
+                                "additionalProperties": false
+                            }
+                        }
+                    ]
+                }
+            },
+            "additionalProperties": false,
+            "definitions": definitions
+        })
+    }
+}
+
 #[cfg(test)]
 #[allow(unused_imports)]
 mod test {
@@ -478,9 +1608,474 @@ loggers:
         assert!(errors.is_empty());
     }
 
+    #[cfg(all(feature = "plugins", feature = "console_appender"))]
+    ::inventory::submit! {
+        Plugin::new(|d| d.insert("plugin_console", ::append::console::ConsoleAppenderDeserializer))
+    }
+
+    #[test]
+    #[cfg(all(feature = "plugins", feature = "console_appender"))]
+    fn plugins() {
+        let config = Value::Map(Default::default());
+        assert!(Deserializers::new().deserialize::<Append>("plugin_console", config.clone()).is_ok());
+        assert!(Deserializers::empty().deserialize::<Append>("plugin_console", config).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_format")]
+    fn on_error() {
+        let parse = |s| ::serde_yaml::from_str::<RawConfig>(s).map(|c| c.on_error());
+        assert_eq!(parse("{}").unwrap(), config::ErrorPolicy::Stderr);
+        assert_eq!(parse("on_error: ignore").unwrap(), config::ErrorPolicy::Ignore);
+        assert_eq!(parse("on_error: panic").unwrap(), config::ErrorPolicy::Panic);
+        assert_eq!(parse("on_error: appender(errors)").unwrap(),
+                   config::ErrorPolicy::Appender("errors".to_owned()));
+        assert!(parse("on_error: appender()").is_err());
+        assert!(parse("on_error: loudly").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_format")]
+    fn backpressure() {
+        let parse = |s| ::serde_yaml::from_str::<RawConfig>(s).map(|c| c.backpressure());
+        assert_eq!(parse("{}").unwrap(), config::Backpressure::DropNewest);
+        assert_eq!(parse("backpressure: block").unwrap(), config::Backpressure::Block);
+        assert_eq!(parse("backpressure: drop_below(warn)").unwrap(),
+                   config::Backpressure::DropBelow(::log::Level::Warn));
+        assert!(parse("backpressure: drop_below(loud)").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "toml_format")]
+    fn toml_loggers() {
+        let cfg = r#"
+[[loggers]]
+name = "foo::bar"
+level = "debug"
+appenders = ["a"]
+
+[[loggers]]
+name = "baz"
+level = "warn"
+additive = false
+"#;
+        let config = ::toml::from_str::<RawConfig>(cfg).unwrap();
+        let mut loggers = config.loggers();
+        loggers.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(loggers[0].name(), "baz");
+        assert!(!loggers[0].additive());
+        assert_eq!(loggers[1].name(), "foo::bar");
+        assert_eq!(loggers[1].level(), LevelFilter::Debug);
+        assert_eq!(loggers[1].appenders(), &["a".to_owned()]);
+
+        let cfg = r#"
+[[loggers]]
+name = "foo"
+level = "debug"
+
+[[loggers]]
+name = "foo"
+level = "warn"
+"#;
+        assert!(::toml::from_str::<RawConfig>(cfg).is_err());
+    }
+
+    // Normalizes the differences between formats which don't affect
+    // deserialization into components.
+    #[cfg(all(feature = "yaml_format", feature = "toml_format"))]
+    fn normalize(value: Value) -> Value {
+        match value {
+            Value::I64(v) if v >= 0 => Value::U64(v as u64),
+            Value::Seq(v) => Value::Seq(v.into_iter().map(normalize).collect()),
+            Value::Map(v) => {
+                Value::Map(v.into_iter().map(|(k, v)| (normalize(k), normalize(v))).collect())
+            }
+            v => v,
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "toml_format"))]
+    fn toml_round_trip() {
+        let dir = ::tempdir::TempDir::new("toml_round_trip").unwrap();
+        let cfg = format!(r#"
+refresh_rate: 30 seconds
+on_error: stderr
+base_dir: {dir}
+appender_templates:
+  base:
+    kind: console
+    encoder:
+      pattern: "{{d}} {{l}} - {{m}}{{n}}"
+appenders:
+  console:
+    template: base
+    level: info
+    filters:
+      - kind: threshold
+        level: warn
+      - kind: target
+        allow: [a, b]
+        deny: [a::c]
+      - kind: level_range
+        min: error
+        max: info
+      - kind: all
+        filters:
+          - kind: mdc
+            key: request
+            value: abc
+          - kind: not
+            filter:
+              kind: thread
+              deny: [main]
+      - kind: any
+        filters:
+          - kind: rate_limit
+            rate: 10
+            burst: 20
+          - kind: burst
+            max_events: 5
+            period: 1 second
+          - kind: first_n
+            count: 3
+          - kind: level_sample
+            debug: 0.5
+          - kind: marker
+            marker: AUDIT
+          - kind: size
+            max_bytes: 1024
+  file:
+    kind: file
+    path: {dir}/file.log
+    append: false
+    encoder:
+      kind: json
+  rolling:
+    kind: rolling_file
+    path: {dir}/rolling.log
+    policy:
+      kind: compound
+      trigger:
+        kind: size
+        limit: 10 mb
+      roller:
+        kind: fixed_window
+        pattern: "{dir}/rolling.{{}}.log"
+        base: 1
+        count: 5
+  deleting:
+    kind: rolling_file
+    path: {dir}/deleting.log
+    policy:
+      trigger:
+        kind: size
+        limit: 1024
+      roller:
+        kind: delete
+  debug:
+    kind: debug_string
+  temp:
+    kind: temp_file
+    dir: {dir}/temp
+    prefix: log
+root:
+  level: warn
+  appenders: [console, file]
+loggers:
+  - name: foo::bar
+    level: trace
+    appenders: [rolling, deleting]
+    additive: false
+  - name: "*::sqlx*"
+    level: error
+"#,
+                          dir = dir.path().display());
+
+        let yaml = ::serde_yaml::from_str::<Value>(&cfg).unwrap();
+        let toml = ::toml::to_string(&::toml::Value::try_from(&yaml).unwrap()).unwrap();
+        let toml = ::toml::from_str::<Value>(&toml).unwrap();
+        assert_eq!(normalize(yaml.clone()), normalize(toml.clone()));
+
+        let deserializers = Deserializers::default();
+        let build = |value: Value| {
+            let config = value.deserialize_into::<RawConfig>().unwrap();
+            let (appenders, errors) = config.appenders_lossy(&deserializers);
+            let mut names = appenders.iter().map(|a| a.name().to_owned()).collect::<Vec<_>>();
+            names.sort();
+            let mut errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            errors.sort();
+            let mut loggers = config.loggers()
+                .iter()
+                .map(|l| (l.name().to_owned(), l.level(), l.additive()))
+                .collect::<Vec<_>>();
+            loggers.sort();
+            (names, errors, loggers, config.refresh_rate(), config.on_error())
+        };
+        assert_eq!(build(yaml), build(toml));
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "console_appender"))]
+    fn appender_level() {
+        let cfg = r#"
+appenders:
+  console:
+    kind: console
+    level: warn
+"#;
+        let config = ::serde_yaml::from_str::<RawConfig>(cfg).unwrap();
+        let (appenders, errors) = config.appenders_lossy(&Deserializers::new());
+        assert!(errors.is_empty());
+        assert_eq!(appenders[0].level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_format")]
+    fn reference_level() {
+        let cfg = r#"
+root:
+  appenders:
+    - name: file
+      level: debug
+    - console
+loggers:
+  foo:
+    level: trace
+    appenders: [{name: console, level: warn}]
+"#;
+        let config = ::serde_yaml::from_str::<RawConfig>(cfg).unwrap();
+        let root = config.root();
+        assert_eq!(root.appenders(), &["file".to_owned(), "console".to_owned()]);
+        assert_eq!(root.appender_level("file"), LevelFilter::Debug);
+        assert_eq!(root.appender_level("console"), LevelFilter::Trace);
+        let loggers = config.loggers();
+        assert_eq!(loggers[0].appenders(), &["console".to_owned()]);
+        assert_eq!(loggers[0].appender_level("console"), LevelFilter::Warn);
+
+        let cfg = "root: {appenders: [{name: file, level: loud}]}";
+        assert!(::serde_yaml::from_str::<RawConfig>(cfg).is_err());
+        let cfg = "root: {appenders: [{name: file, threshold: info}]}";
+        assert!(::serde_yaml::from_str::<RawConfig>(cfg).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format",
+              feature = "fields_interceptor",
+              feature = "replace_interceptor"))]
+    fn interceptors() {
+        let cfg = r#"
+interceptors:
+  - kind: fields
+    fields:
+      service: billing
+  - kind: replace
+    pattern: "("
+  - kind: replace
+    pattern: "secret"
+"#;
+        let config = ::serde_yaml::from_str::<RawConfig>(cfg).unwrap();
+        let (interceptors, errors) = config.interceptors_lossy(&Deserializers::new());
+        assert_eq!(interceptors.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().starts_with("error deserializing replace interceptor"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "console_appender"))]
+    fn appender_templates() {
+        let cfg = r#"
+appender_templates:
+  base:
+    kind: console
+    level: warn
+appenders:
+  a:
+    template: base
+  b:
+    template: base
+    level: error
+  c:
+    template: missing
+"#;
+        let config = ::serde_yaml::from_str::<RawConfig>(cfg).unwrap();
+        let (mut appenders, errors) = config.appenders_lossy(&Deserializers::new());
+        appenders.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(appenders.len(), 2);
+        assert_eq!(appenders[0].level(), LevelFilter::Warn);
+        assert_eq!(appenders[1].level(), LevelFilter::Error);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("`missing`"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "console_appender"))]
+    fn enabled() {
+        let cfg = r#"
+appender_templates:
+  off:
+    kind: console
+    enabled: false
+appenders:
+  a:
+    kind: console
+  b:
+    kind: console
+    enabled: false
+  c:
+    template: off
+  d:
+    template: off
+    enabled: true
+  e:
+    kind: console
+    enabled: maybe
+root:
+  appenders: [a, b, c, d]
+loggers:
+  foo:
+    level: info
+    appenders: [b]
+on_error: appender(b)
+"#;
+        let config = ::serde_yaml::from_str::<RawConfig>(cfg).unwrap();
+        let (mut appenders, errors) = config.appenders_lossy(&Deserializers::new());
+        appenders.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(appenders.iter().map(|a| a.name()).collect::<Vec<_>>(), ["a", "d"]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(config.root().appenders(), &["a".to_owned(), "d".to_owned()]);
+        assert!(config.loggers()[0].appenders().is_empty());
+        assert_eq!(config.on_error(), config::ErrorPolicy::default());
+        assert_eq!(config.default_console(), LevelFilter::Off);
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format",
+              feature = "console_appender",
+              feature = "boolean_filter",
+              feature = "target_filter",
+              feature = "threshold_filter"))]
+    fn boolean_filters() {
+        let cfg = r#"
+appenders:
+  console:
+    kind: console
+    filters:
+      - kind: any
+        filters:
+          - kind: threshold
+            level: warn
+          - kind: all
+            filters:
+              - kind: target
+                allow:
+                  - myapp::*
+              - kind: not
+                filter:
+                  kind: threshold
+                  level: trace
+"#;
+        let config = ::serde_yaml::from_str::<RawConfig>(cfg).unwrap();
+        let (appenders, errors) = config.appenders_lossy(&Deserializers::new());
+        assert!(errors.is_empty());
+        assert_eq!(appenders.len(), 1);
+    }
+
     #[test]
     #[cfg(feature = "yaml_format")]
     fn empty() {
         ::serde_yaml::from_str::<RawConfig>("{}").unwrap();
     }
+
+    #[test]
+    #[cfg(all(feature = "json_schema", feature = "console_appender"))]
+    fn json_schema() {
+        let schema = Deserializers::default().json_schema();
+        let console = schema["definitions"]["appender"]["anyOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|v| v["properties"]["kind"]["const"] == "console")
+            .unwrap();
+        assert_eq!(console["properties"]["encoder"]["$ref"], "#/definitions/encoder");
+        assert!(console["properties"]["target"].is_object());
+        assert_eq!(console["properties"]["filters"]["items"]["$ref"], "#/definitions/filter");
+        assert_eq!(console["additionalProperties"], false);
+        assert_eq!(console["required"][0], "kind");
+
+        let pattern = schema["definitions"]["encoder"]["anyOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|v| v["properties"]["kind"]["const"] == "pattern")
+            .unwrap();
+        assert!(pattern["properties"]["pattern"].is_object());
+        assert!(pattern.get("required").is_none());
+    }
+
+    #[cfg(feature = "derive")]
+    mod derive {
+        use log::{Level, Record};
+        use serde_value::Value;
+        use std::error::Error;
+
+        use file::{Deserializer, Deserializers};
+        use filter::{Filter, Response};
+
+        #[derive(Debug)]
+        struct TargetFilter(String);
+
+        impl Filter for TargetFilter {
+            fn filter(&self, record: &Record) -> Response {
+                if record.target() == self.0 {
+                    Response::Neutral
+                } else {
+                    Response::Reject
+                }
+            }
+        }
+
+        #[derive(Deserialize, Deserializer)]
+        #[serde(deny_unknown_fields)]
+        #[log4rs(component = "Filter")]
+        struct TargetFilterConfig {
+            target: String,
+        }
+
+        impl TargetFilterConfig {
+            fn build(self, _: &Deserializers) -> Result<TargetFilter, Box<Error + Sync + Send>> {
+                if self.target.is_empty() {
+                    return Err("empty target".into());
+                }
+                Ok(TargetFilter(self.target))
+            }
+        }
+
+        fn config(key: &str, target: &str) -> Value {
+            Value::Map(vec![(Value::String(key.to_owned()), Value::String(target.to_owned()))]
+                .into_iter()
+                .collect())
+        }
+
+        #[test]
+        fn deserializer() {
+            let mut deserializers = Deserializers::empty();
+            deserializers.insert("target", TargetFilterDeserializer);
+
+            let filter = deserializers.deserialize::<Filter>("target", config("target", "app"))
+                .unwrap();
+            let record = |target| {
+                let record = Record::builder().level(Level::Info).target(target).build();
+                filter.filter(&record)
+            };
+            match (record("app"), record("other")) {
+                (Response::Neutral, Response::Reject) => {}
+                r => panic!("unexpected responses {:?}", r),
+            }
+
+            assert!(deserializers.deserialize::<Filter>("target", config("target", "")).is_err());
+            assert!(deserializers.check::<Filter>("target", config("target", "")).is_ok());
+            assert!(deserializers.check::<Filter>("target", config("name", "app")).is_err());
+        }
+    }
 }
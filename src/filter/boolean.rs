@@ -0,0 +1,274 @@
+//! Filters combining other filters.
+//!
+//! A child filter is considered to match an event unless it rejects it. The
+//! combinators never accept events outright, so events they let through
+//! continue on to the remaining filters.
+//!
+//! Requires the `boolean_filter` feature.
+
+use log::Record;
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+#[cfg(feature = "file")]
+use filter::FilterConfig;
+
+/// The configuration of the `all` and `any` filters.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterListConfig {
+    filters: Vec<FilterConfig>,
+}
+
+/// The configuration of the `not` filter.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotFilterConfig {
+    filter: FilterConfig,
+}
+
+fn matches(filter: &Filter, record: &Record) -> bool {
+    match filter.filter(record) {
+        Response::Reject => false,
+        Response::Accept | Response::Neutral => true,
+    }
+}
+
+fn response(matched: bool) -> Response {
+    if matched {
+        Response::Neutral
+    } else {
+        Response::Reject
+    }
+}
+
+/// A filter that rejects events unless all of its child filters match them.
+///
+/// An `AllFilter` without children matches every event.
+#[derive(Debug)]
+pub struct AllFilter {
+    filters: Vec<Box<Filter>>,
+}
+
+impl AllFilter {
+    /// Creates a new `AllFilter` with the specified child filters.
+    pub fn new(filters: Vec<Box<Filter>>) -> AllFilter {
+        AllFilter { filters: filters }
+    }
+}
+
+impl Filter for AllFilter {
+    fn filter(&self, record: &Record) -> Response {
+        response(self.filters.iter().all(|f| matches(&**f, record)))
+    }
+}
+
+/// A filter that rejects events unless at least one of its child filters
+/// matches them.
+///
+/// An `AnyFilter` without children rejects every event.
+#[derive(Debug)]
+pub struct AnyFilter {
+    filters: Vec<Box<Filter>>,
+}
+
+impl AnyFilter {
+    /// Creates a new `AnyFilter` with the specified child filters.
+    pub fn new(filters: Vec<Box<Filter>>) -> AnyFilter {
+        AnyFilter { filters: filters }
+    }
+}
+
+impl Filter for AnyFilter {
+    fn filter(&self, record: &Record) -> Response {
+        response(self.filters.iter().any(|f| matches(&**f, record)))
+    }
+}
+
+/// A filter that rejects exactly the events its child filter matches.
+#[derive(Debug)]
+pub struct NotFilter {
+    filter: Box<Filter>,
+}
+
+impl NotFilter {
+    /// Creates a new `NotFilter` inverting the specified filter.
+    pub fn new(filter: Box<Filter>) -> NotFilter {
+        NotFilter { filter: filter }
+    }
+}
+
+impl Filter for NotFilter {
+    fn filter(&self, record: &Record) -> Response {
+        response(!matches(&*self.filter, record))
+    }
+}
+
+#[cfg(feature = "file")]
+fn children(config: FilterListConfig,
+            deserializers: &Deserializers)
+            -> Result<Vec<Box<Filter>>, Box<Error + Sync + Send>> {
+    config.filters
+        .into_iter()
+        .map(|f| deserializers.deserialize(&f.kind, f.config))
+        .collect()
+}
+
+/// A deserializer for the `AllFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: all
+///
+/// # The filters which must all match. Required.
+/// filters:
+///   - kind: target
+///     allow:
+///       - myapp::*
+///   - kind: threshold
+///     level: info
+/// ```
+#[cfg(feature = "file")]
+pub struct AllFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for AllFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = FilterListConfig;
+
+    fn deserialize(&self,
+                   config: FilterListConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        Ok(Box::new(AllFilter::new(children(config, deserializers)?)))
+    }
+}
+
+/// A deserializer for the `AnyFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: any
+///
+/// # The filters of which at least one must match. Required.
+/// filters:
+///   - kind: threshold
+///     level: warn
+///   - kind: target
+///     allow:
+///       - myapp::payments::*
+/// ```
+#[cfg(feature = "file")]
+pub struct AnyFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for AnyFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = FilterListConfig;
+
+    fn deserialize(&self,
+                   config: FilterListConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        Ok(Box::new(AnyFilter::new(children(config, deserializers)?)))
+    }
+}
+
+/// A deserializer for the `NotFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: not
+///
+/// # The filter to invert. Required.
+/// filter:
+///   kind: threshold
+///   level: warn
+/// ```
+#[cfg(feature = "file")]
+pub struct NotFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for NotFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = NotFilterConfig;
+
+    fn deserialize(&self,
+                   config: NotFilterConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let filter = deserializers.deserialize(&config.filter.kind, config.filter.config)?;
+        Ok(Box::new(NotFilter::new(filter)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed(Response);
+
+    impl Filter for Fixed {
+        fn filter(&self, _: &Record) -> Response {
+            self.0
+        }
+    }
+
+    fn fixed(responses: &[Response]) -> Vec<Box<Filter>> {
+        responses.iter().map(|&r| Box::new(Fixed(r)) as Box<Filter>).collect()
+    }
+
+    // whether the filter lets the event through, checking it isn't accepted
+    fn passes(filter: &Filter) -> bool {
+        match filter.filter(&Record::builder().build()) {
+            Response::Neutral => true,
+            Response::Reject => false,
+            Response::Accept => panic!("combinators don't accept events"),
+        }
+    }
+
+    #[test]
+    fn all() {
+        use filter::Response::*;
+
+        assert!(passes(&AllFilter::new(fixed(&[]))));
+        assert!(passes(&AllFilter::new(fixed(&[Accept]))));
+        assert!(passes(&AllFilter::new(fixed(&[Neutral]))));
+        assert!(passes(&AllFilter::new(fixed(&[Accept, Neutral]))));
+        assert!(!passes(&AllFilter::new(fixed(&[Reject]))));
+        assert!(!passes(&AllFilter::new(fixed(&[Accept, Reject]))));
+        assert!(!passes(&AllFilter::new(fixed(&[Neutral, Reject]))));
+    }
+
+    #[test]
+    fn any() {
+        use filter::Response::*;
+
+        assert!(!passes(&AnyFilter::new(fixed(&[]))));
+        assert!(passes(&AnyFilter::new(fixed(&[Accept]))));
+        assert!(passes(&AnyFilter::new(fixed(&[Neutral]))));
+        assert!(passes(&AnyFilter::new(fixed(&[Reject, Accept]))));
+        assert!(passes(&AnyFilter::new(fixed(&[Reject, Neutral]))));
+        assert!(!passes(&AnyFilter::new(fixed(&[Reject]))));
+        assert!(!passes(&AnyFilter::new(fixed(&[Reject, Reject]))));
+    }
+
+    #[test]
+    fn not() {
+        assert!(!passes(&NotFilter::new(Box::new(Fixed(Response::Accept)))));
+        assert!(!passes(&NotFilter::new(Box::new(Fixed(Response::Neutral)))));
+        assert!(passes(&NotFilter::new(Box::new(Fixed(Response::Reject)))));
+    }
+}
@@ -0,0 +1,203 @@
+//! The burst filter.
+//!
+//! Requires the `burst_filter` feature.
+
+use antidote::Mutex;
+use log::{Level, Record};
+use std::cell::Cell;
+#[cfg(feature = "file")]
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+thread_local! {
+    static SUMMARIZING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The burst filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BurstFilterConfig {
+    max_events: u64,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    period: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct State {
+    window_start: Instant,
+    events: u64,
+    dropped: u64,
+    first_drop: Option<Instant>,
+    last_drop: Option<Instant>,
+}
+
+/// A summary of events dropped during a burst.
+#[derive(Debug, PartialEq)]
+struct Summary {
+    dropped: u64,
+    duration: Duration,
+}
+
+/// A filter that suppresses bursts of log events, reporting how many were
+/// dropped once the burst is over.
+///
+/// At most `max_events` events are accepted per period, and the rest are
+/// rejected. The first event of a later period which is accepted is preceded
+/// by a `Warn` level event with the same target stating how many events were
+/// dropped and over what span of time, so logs do not make it seem that the
+/// system went quiet. That event is logged through the normal logging
+/// machinery, so it reaches every appender the accepted event does, and it is
+/// not itself subject to burst filters.
+#[derive(Debug)]
+pub struct BurstFilter {
+    max_events: u64,
+    period: Duration,
+    state: Mutex<Option<State>>,
+}
+
+impl BurstFilter {
+    /// Creates a new `BurstFilter` accepting at most `max_events` events per
+    /// second.
+    pub fn new(max_events: u64) -> BurstFilter {
+        BurstFilter::with_period(max_events, Duration::from_secs(1))
+    }
+
+    /// Creates a new `BurstFilter` accepting at most `max_events` events per
+    /// period.
+    pub fn with_period(max_events: u64, period: Duration) -> BurstFilter {
+        BurstFilter {
+            max_events: max_events,
+            period: period,
+            state: Mutex::new(None),
+        }
+    }
+
+    fn check(&self, now: Instant) -> (bool, Option<Summary>) {
+        let mut state = self.state.lock();
+        let state = state.get_or_insert_with(|| {
+            State {
+                window_start: now,
+                events: 0,
+                dropped: 0,
+                first_drop: None,
+                last_drop: None,
+            }
+        });
+
+        if now.duration_since(state.window_start) >= self.period {
+            state.window_start = now;
+            state.events = 0;
+        }
+
+        if state.events >= self.max_events {
+            state.dropped += 1;
+            state.first_drop.get_or_insert(now);
+            state.last_drop = Some(now);
+            return (false, None);
+        }
+
+        state.events += 1;
+        let summary = match (state.first_drop.take(), state.last_drop.take()) {
+            (Some(first), Some(last)) => {
+                let summary = Summary {
+                    dropped: state.dropped,
+                    duration: last.duration_since(first),
+                };
+                state.dropped = 0;
+                Some(summary)
+            }
+            _ => None,
+        };
+        (true, summary)
+    }
+}
+
+impl Filter for BurstFilter {
+    fn filter(&self, record: &Record) -> Response {
+        if SUMMARIZING.with(|s| s.get()) {
+            return Response::Neutral;
+        }
+
+        let (accepted, summary) = self.check(Instant::now());
+        if let Some(summary) = summary {
+            SUMMARIZING.with(|s| s.set(true));
+            ::log::log!(target: record.target(),
+                        Level::Warn,
+                        "burst filter dropped {} events over {}.{:03}s",
+                        summary.dropped,
+                        summary.duration.as_secs(),
+                        summary.duration.subsec_millis());
+            SUMMARIZING.with(|s| s.set(false));
+        }
+
+        if accepted {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+/// A deserializer for the `BurstFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: burst
+///
+/// # The maximum number of events to accept per period. Required.
+/// max_events: 100
+///
+/// # The length of a period. Defaults to 1 second.
+/// period: 1 second
+/// ```
+#[cfg(feature = "file")]
+pub struct BurstFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for BurstFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = BurstFilterConfig;
+
+    fn deserialize(&self,
+                   config: BurstFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let period = config.period.unwrap_or_else(|| Duration::from_secs(1));
+        Ok(Box::new(BurstFilter::with_period(config.max_events, period)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn burst() {
+        let filter = BurstFilter::with_period(2, Duration::from_secs(1));
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        assert_eq!(filter.check(at(0)), (true, None));
+        assert_eq!(filter.check(at(100)), (true, None));
+        assert_eq!(filter.check(at(200)), (false, None));
+        assert_eq!(filter.check(at(700)), (false, None));
+        assert_eq!(filter.check(at(900)), (false, None));
+
+        let summary = Summary {
+            dropped: 3,
+            duration: Duration::from_millis(700),
+        };
+        assert_eq!(filter.check(at(1000)), (true, Some(summary)));
+        assert_eq!(filter.check(at(1100)), (true, None));
+        assert_eq!(filter.check(at(1200)), (false, None));
+    }
+}
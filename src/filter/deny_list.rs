@@ -0,0 +1,266 @@
+//! The deny-list filter.
+//!
+//! Requires the `deny_list_filter` feature.
+
+use antidote::Mutex;
+use log::Record;
+use regex::Regex;
+#[cfg(feature = "file")]
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+use filter::name::Pattern;
+
+/// The deny-list filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DenyListFilterConfig {
+    path: String,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    refresh_rate: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct DenyList {
+    targets: Vec<Pattern>,
+    messages: Vec<Regex>,
+}
+
+impl DenyList {
+    fn parse(contents: &str) -> io::Result<DenyList> {
+        let mut list = DenyList::default();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(pattern) = line.strip_prefix("message:") {
+                let regex = Regex::new(pattern.trim()).map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("line {}: invalid regex: {}", i + 1, e))
+                })?;
+                list.messages.push(regex);
+            } else {
+                let pattern = line.strip_prefix("target:").unwrap_or(line);
+                list.targets.push(Pattern::new(pattern.trim()));
+            }
+        }
+        Ok(list)
+    }
+
+    fn denies(&self, target: &str, message: &Fn() -> String) -> bool {
+        if self.targets.iter().any(|p| p.matches(target)) {
+            return true;
+        }
+        if self.messages.is_empty() {
+            return false;
+        }
+        let message = message();
+        self.messages.iter().any(|r| r.is_match(&message))
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    list: Arc<DenyList>,
+    // the modification time and length of the file the list was read from
+    stamp: Option<(Option<SystemTime>, u64)>,
+    last_check: Instant,
+}
+
+/// A filter that rejects events matching patterns read from a file, which is
+/// reloaded when it changes.
+///
+/// This allows a noisy target or message to be muted without touching the
+/// main configuration. Each non-empty line of the file which does not start
+/// with `#` is a pattern:
+///
+/// ```text
+/// # mute a module and everything in it
+/// target: hyper::proto::*
+/// # mute messages matching a regular expression
+/// message: ^connection reset by peer
+/// ```
+///
+/// Target patterns follow the same rules as those of the `TargetFilter`, and
+/// a line without a `target:` or `message:` prefix is a target pattern. The
+/// file is checked for changes at most once per refresh period. A missing
+/// file denies nothing, and if the file cannot be read or parsed, the error
+/// is reported and the previous patterns are kept. Events which are not
+/// denied pass through to later filters.
+#[derive(Debug)]
+pub struct DenyListFilter {
+    path: PathBuf,
+    refresh_rate: Duration,
+    state: Mutex<State>,
+}
+
+impl DenyListFilter {
+    /// Creates a new `DenyListFilter` reading patterns from the specified
+    /// file, checking it for changes every 5 seconds.
+    pub fn new<P: AsRef<Path>>(path: P) -> DenyListFilter {
+        DenyListFilter::with_refresh_rate(path, Duration::from_secs(5))
+    }
+
+    /// Like `new`, but checks the file for changes at the specified rate.
+    pub fn with_refresh_rate<P: AsRef<Path>>(path: P, refresh_rate: Duration) -> DenyListFilter {
+        let filter = DenyListFilter {
+            path: path.as_ref().to_owned(),
+            refresh_rate: refresh_rate,
+            state: Mutex::new(State {
+                list: Arc::new(DenyList::default()),
+                stamp: None,
+                last_check: Instant::now(),
+            }),
+        };
+        filter.reload(&mut filter.state.lock());
+        filter
+    }
+
+    fn reload(&self, state: &mut State) {
+        state.last_check = Instant::now();
+
+        let stamp = match fs::metadata(&self.path) {
+            Ok(metadata) => Some((metadata.modified().ok(), metadata.len())),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                ::handle_error(&e);
+                return;
+            }
+        };
+        if stamp.is_some() && stamp == state.stamp {
+            return;
+        }
+
+        let list = match stamp {
+            Some(_) => {
+                let result = File::open(&self.path).and_then(|mut file| {
+                    let mut contents = String::new();
+                    file.read_to_string(&mut contents)?;
+                    DenyList::parse(&contents)
+                });
+                match result {
+                    Ok(list) => list,
+                    Err(e) => {
+                        let e = io::Error::new(e.kind(),
+                                               format!("error reading deny-list `{}`: {}",
+                                                       self.path.display(),
+                                                       e));
+                        ::handle_error(&e);
+                        // don't report the same error again until the file changes
+                        state.stamp = stamp;
+                        return;
+                    }
+                }
+            }
+            None => DenyList::default(),
+        };
+        state.list = Arc::new(list);
+        state.stamp = stamp;
+    }
+
+    fn list(&self) -> Arc<DenyList> {
+        let mut state = self.state.lock();
+        if state.last_check.elapsed() >= self.refresh_rate {
+            self.reload(&mut state);
+        }
+        state.list.clone()
+    }
+}
+
+impl Filter for DenyListFilter {
+    fn filter(&self, record: &Record) -> Response {
+        if self.list().denies(record.target(), &|| record.args().to_string()) {
+            Response::Reject
+        } else {
+            Response::Neutral
+        }
+    }
+}
+
+/// A deserializer for the `DenyListFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: deny_list
+///
+/// # The path of the file containing patterns to deny. Required.
+/// path: /etc/myapp/log-deny-list
+///
+/// # How often to check the file for changes. Defaults to 5 seconds.
+/// refresh_rate: 5 seconds
+/// ```
+#[cfg(feature = "file")]
+pub struct DenyListFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for DenyListFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = DenyListFilterConfig;
+
+    fn deserialize(&self,
+                   config: DenyListFilterConfig,
+                   deserializers: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let refresh_rate = config.refresh_rate.unwrap_or_else(|| Duration::from_secs(5));
+        Ok(Box::new(DenyListFilter::with_refresh_rate(deserializers.resolve_path(config.path), refresh_rate)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Write;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn denies(filter: &DenyListFilter, target: &str, message: &str) -> bool {
+        filter.list().denies(target, &|| message.to_owned())
+    }
+
+    #[test]
+    fn parse() {
+        let list = DenyList::parse("# comment\n\ntarget: hyper::*\nnoisy\nmessage: ^reset \\d+\n")
+            .unwrap();
+        let denies = |target, message: &'static str| list.denies(target, &|| message.to_owned());
+        assert!(denies("hyper::client", "hi"));
+        assert!(denies("noisy", "hi"));
+        assert!(!denies("noisy::child", "hi"));
+        assert!(denies("myapp", "reset 42"));
+        assert!(!denies("myapp", "connection reset 42"));
+
+        assert!(DenyList::parse("message: (").is_err());
+    }
+
+    #[test]
+    fn reload() {
+        let dir = TempDir::new("deny_list").unwrap();
+        let path = dir.path().join("deny");
+        let filter = DenyListFilter::with_refresh_rate(&path, Duration::from_secs(0));
+        assert!(!denies(&filter, "noisy", "hi"));
+
+        File::create(&path).unwrap().write_all(b"noisy\n").unwrap();
+        assert!(denies(&filter, "noisy", "hi"));
+
+        // an invalid file keeps the previous patterns
+        File::create(&path).unwrap().write_all(b"message: (\n").unwrap();
+        assert!(denies(&filter, "noisy", "hi"));
+
+        File::create(&path).unwrap().write_all(b"message: quiet\n").unwrap();
+        assert!(!denies(&filter, "noisy", "hi"));
+        assert!(denies(&filter, "other", "quiet please"));
+    }
+}
@@ -0,0 +1,218 @@
+//! The dynamic filter.
+//!
+//! Requires the `dynamic_filter` feature.
+
+use log::{Level, LevelFilter, Record};
+use std::collections::HashMap;
+#[cfg(feature = "file")]
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+static REGISTRY: Mutex<Option<HashMap<String, DynamicFilterHandle>>> = Mutex::new(None);
+
+const LEVELS: [LevelFilter; 6] = [LevelFilter::Off,
+                                     LevelFilter::Error,
+                                     LevelFilter::Warn,
+                                     LevelFilter::Info,
+                                     LevelFilter::Debug,
+                                     LevelFilter::Trace];
+
+/// The dynamic filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DynamicFilterConfig {
+    name: String,
+    #[serde(deserialize_with = "::priv_serde::de_filter")]
+    level: LevelFilter,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+#[cfg(feature = "file")]
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug)]
+struct State {
+    enabled: AtomicBool,
+    level: AtomicUsize,
+}
+
+/// A handle controlling a `DynamicFilter`.
+///
+/// Handles are cheap to clone and may be used from any thread.
+#[derive(Debug, Clone)]
+pub struct DynamicFilterHandle(Arc<State>);
+
+impl DynamicFilterHandle {
+    /// Returns the handle registered under the specified name, if any.
+    ///
+    /// Handles are registered by `DynamicFilter::named`, which is what
+    /// filters created from a config file use.
+    pub fn get(name: &str) -> Option<DynamicFilterHandle> {
+        let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.as_ref().and_then(|r| r.get(name).cloned())
+    }
+
+    /// Determines if the filter lets any events through.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns whether the filter lets any events through.
+    pub fn enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Sets the most verbose level the filter lets through.
+    pub fn set_level(&self, level: LevelFilter) {
+        self.0.level.store(level as usize, Ordering::Relaxed);
+    }
+
+    /// Returns the most verbose level the filter lets through.
+    pub fn level(&self) -> LevelFilter {
+        LEVELS[self.0.level.load(Ordering::Relaxed)]
+    }
+}
+
+/// A filter whose threshold can be changed, or which can be switched off
+/// entirely, at runtime through a `DynamicFilterHandle`.
+///
+/// While enabled, it rejects events more verbose than its level like a
+/// `ThresholdFilter`. While disabled, it rejects all events.
+#[derive(Debug)]
+pub struct DynamicFilter(DynamicFilterHandle);
+
+impl DynamicFilter {
+    /// Creates a new, enabled `DynamicFilter` with the specified level,
+    /// along with the handle controlling it.
+    pub fn new(level: LevelFilter) -> (DynamicFilter, DynamicFilterHandle) {
+        let handle = DynamicFilterHandle(Arc::new(State {
+            enabled: AtomicBool::new(true),
+            level: AtomicUsize::new(level as usize),
+        }));
+        (DynamicFilter(handle.clone()), handle)
+    }
+
+    /// Creates a `DynamicFilter` controlled by the handle registered under the
+    /// specified name, registering a new enabled handle with the specified
+    /// level if there is none.
+    ///
+    /// Since the handle outlives the filter, a filter recreated by reloading
+    /// the configuration keeps any state set at runtime.
+    pub fn named(name: &str, level: LevelFilter) -> (DynamicFilter, DynamicFilterHandle) {
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        let handle = registry.get_or_insert_with(HashMap::new)
+            .entry(name.to_owned())
+            .or_insert_with(|| DynamicFilter::new(level).1)
+            .clone();
+        (DynamicFilter(handle.clone()), handle)
+    }
+
+    fn response(&self, level: Level) -> Response {
+        if !self.0.enabled() || level > self.0.level() {
+            Response::Reject
+        } else {
+            Response::Neutral
+        }
+    }
+}
+
+impl Filter for DynamicFilter {
+    fn filter(&self, record: &Record) -> Response {
+        self.response(record.level())
+    }
+}
+
+/// A deserializer for the `DynamicFilter`.
+///
+/// The filter's handle is registered under its name, and can be retrieved
+/// with `DynamicFilterHandle::get`. The level and enabled state in the
+/// configuration only apply when the name is first registered, so changes
+/// made through the handle survive configuration reloads.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: dynamic
+///
+/// # The name under which the handle is registered. Required.
+/// name: debug_switch
+///
+/// # The initial threshold log level. Required.
+/// level: debug
+///
+/// # Whether the filter initially lets events through. Defaults to `true`.
+/// enabled: true
+/// ```
+#[cfg(feature = "file")]
+pub struct DynamicFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for DynamicFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = DynamicFilterConfig;
+
+    fn deserialize(&self,
+                   config: DynamicFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let registered = DynamicFilterHandle::get(&config.name).is_some();
+        let (filter, handle) = DynamicFilter::named(&config.name, config.level);
+        if !registered {
+            handle.set_enabled(config.enabled);
+        }
+        Ok(Box::new(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::{Level, LevelFilter};
+
+    use super::*;
+
+    use filter::rejected;
+
+    fn record(level: Level) -> Record<'static> {
+        Record::builder().level(level).build()
+    }
+
+    #[test]
+    fn handle() {
+        let (filter, handle) = DynamicFilter::new(LevelFilter::Info);
+        assert!(!rejected(&filter, &record(Level::Info)));
+        assert!(rejected(&filter, &record(Level::Debug)));
+
+        handle.set_level(LevelFilter::Trace);
+        assert_eq!(handle.level(), LevelFilter::Trace);
+        assert!(!rejected(&filter, &record(Level::Trace)));
+
+        handle.set_enabled(false);
+        assert!(rejected(&filter, &record(Level::Error)));
+        handle.clone().set_enabled(true);
+        assert!(!rejected(&filter, &record(Level::Error)));
+    }
+
+    #[test]
+    fn named() {
+        let (filter, handle) = DynamicFilter::named("dynamic_test", LevelFilter::Warn);
+        handle.set_enabled(false);
+
+        // a recreated filter shares the existing state
+        let (recreated, _) = DynamicFilter::named("dynamic_test", LevelFilter::Trace);
+        assert!(rejected(&recreated, &record(Level::Error)));
+        DynamicFilterHandle::get("dynamic_test").unwrap().set_enabled(true);
+        assert!(!rejected(&filter, &record(Level::Warn)));
+        assert!(rejected(&recreated, &record(Level::Info)));
+        assert!(DynamicFilterHandle::get("dynamic_test_missing").is_none());
+    }
+}
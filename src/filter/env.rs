@@ -0,0 +1,136 @@
+//! The environment variable filter.
+//!
+//! Requires the `env_filter` feature.
+
+use log::Record;
+use std::env;
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The environment variable filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvFilterConfig {
+    var: String,
+    value: Option<String>,
+}
+
+/// A filter that accepts or rejects all log events depending on an
+/// environment variable.
+///
+/// The variable is checked once, when the filter is created, and the result
+/// applies to every event. When created from a config file, it is checked
+/// again whenever the config is reloaded. If the check passes, events pass
+/// through to later filters, and otherwise they are rejected.
+///
+/// This allows a single shipped config to contain sections which are only
+/// enabled at runtime, like an appender for payment debugging that is only
+/// active with `DEBUG_PAYMENTS=1`.
+#[derive(Debug)]
+pub struct EnvFilter {
+    enabled: bool,
+}
+
+impl EnvFilter {
+    /// Creates a new `EnvFilter` accepting events if the specified
+    /// environment variable is set to a non-empty value.
+    pub fn set(var: &str) -> EnvFilter {
+        EnvFilter::check(env::var_os(var).map(|v| v.into_string().unwrap_or_default()),
+                         None)
+    }
+
+    /// Creates a new `EnvFilter` accepting events if the specified
+    /// environment variable is set to exactly the specified value.
+    pub fn equals(var: &str, value: &str) -> EnvFilter {
+        EnvFilter::check(env::var(var).ok(), Some(value))
+    }
+
+    fn check(actual: Option<String>, expected: Option<&str>) -> EnvFilter {
+        let enabled = match (actual, expected) {
+            (Some(actual), Some(expected)) => actual == expected,
+            (Some(actual), None) => !actual.is_empty(),
+            (None, _) => false,
+        };
+        EnvFilter { enabled: enabled }
+    }
+
+    /// Determines if the filter accepts events.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+impl Filter for EnvFilter {
+    fn filter(&self, _: &Record) -> Response {
+        if self.enabled {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+/// A deserializer for the `EnvFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: env
+///
+/// # The environment variable to check. Required.
+/// var: DEBUG_PAYMENTS
+///
+/// # The value the variable must have for events to be accepted. If not
+/// # specified, events are accepted if the variable is set to any non-empty
+/// # value.
+/// value: "1"
+/// ```
+#[cfg(feature = "file")]
+pub struct EnvFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for EnvFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = EnvFilterConfig;
+
+    fn deserialize(&self,
+                   config: EnvFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let filter = match config.value {
+            Some(value) => EnvFilter::equals(&config.var, &value),
+            None => EnvFilter::set(&config.var),
+        };
+        Ok(Box::new(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check() {
+        assert!(EnvFilter::check(Some("1".to_owned()), Some("1")).enabled());
+        assert!(!EnvFilter::check(Some("0".to_owned()), Some("1")).enabled());
+        assert!(!EnvFilter::check(None, Some("1")).enabled());
+        assert!(EnvFilter::check(Some("yes".to_owned()), None).enabled());
+        assert!(!EnvFilter::check(Some("".to_owned()), None).enabled());
+        assert!(!EnvFilter::check(None, None).enabled());
+    }
+
+    #[test]
+    fn env() {
+        env::set_var("LOG4RS_ENV_FILTER_TEST", "1");
+        assert!(EnvFilter::set("LOG4RS_ENV_FILTER_TEST").enabled());
+        assert!(EnvFilter::equals("LOG4RS_ENV_FILTER_TEST", "1").enabled());
+        assert!(!EnvFilter::equals("LOG4RS_ENV_FILTER_TEST", "2").enabled());
+        assert!(!EnvFilter::set("LOG4RS_ENV_FILTER_TEST_UNSET").enabled());
+    }
+}
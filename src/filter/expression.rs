@@ -0,0 +1,525 @@
+//! The expression filter.
+//!
+//! Requires the `expression_filter` feature.
+
+use log::{Level, Record};
+use log_mdc;
+use std::borrow::Cow;
+use std::error::Error;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use std::thread;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The expression filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpressionFilterConfig {
+    expression: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(u64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    StartsWith,
+    EndsWith,
+    Contains,
+}
+
+fn lex(expr: &str) -> Result<Vec<(usize, Token)>, Box<Error + Sync + Send>> {
+    let mut tokens = vec![];
+    let mut chars: Peekable<CharIndices> = expr.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        let two = |tokens: &mut Vec<_>, chars: &mut Peekable<CharIndices>, token| {
+            chars.next();
+            tokens.push((pos, token));
+        };
+        match (c, next) {
+            (c, _) if c.is_whitespace() => {}
+            ('&', Some('&')) => two(&mut tokens, &mut chars, Token::And),
+            ('|', Some('|')) => two(&mut tokens, &mut chars, Token::Or),
+            ('=', Some('=')) => two(&mut tokens, &mut chars, Token::Op(Op::Eq)),
+            ('!', Some('=')) => two(&mut tokens, &mut chars, Token::Op(Op::Ne)),
+            ('<', Some('=')) => two(&mut tokens, &mut chars, Token::Op(Op::Le)),
+            ('>', Some('=')) => two(&mut tokens, &mut chars, Token::Op(Op::Ge)),
+            ('^', Some('=')) => two(&mut tokens, &mut chars, Token::Op(Op::StartsWith)),
+            ('$', Some('=')) => two(&mut tokens, &mut chars, Token::Op(Op::EndsWith)),
+            ('*', Some('=')) => two(&mut tokens, &mut chars, Token::Op(Op::Contains)),
+            ('<', _) => tokens.push((pos, Token::Op(Op::Lt))),
+            ('>', _) => tokens.push((pos, Token::Op(Op::Gt))),
+            ('!', _) => tokens.push((pos, Token::Not)),
+            ('(', _) => tokens.push((pos, Token::LParen)),
+            (')', _) => tokens.push((pos, Token::RParen)),
+            ('"', _) => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => {
+                            match chars.next() {
+                                Some((_, c)) if c == '"' || c == '\\' => s.push(c),
+                                _ => return Err(format!("invalid escape at {}", pos).into()),
+                            }
+                        }
+                        Some((_, c)) => s.push(c),
+                        None => return Err(format!("unterminated string at {}", pos).into()),
+                    }
+                }
+                tokens.push((pos, Token::Str(s)));
+            }
+            (c, _) if c.is_ascii_digit() => {
+                let mut end = pos + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let num = expr[pos..end]
+                    .parse()
+                    .map_err(|_| format!("invalid number at {}", pos))?;
+                tokens.push((pos, Token::Num(num)));
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                let mut end = pos + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.' || c == '-') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push((pos, Token::Ident(expr[pos..end].to_owned())));
+            }
+            (c, _) => return Err(format!("unexpected `{}` at {}", c, pos).into()),
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Level,
+    Target,
+    Module,
+    File,
+    Line,
+    Thread,
+    Message,
+    Mdc(String),
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Level(Op, Level),
+    Line(Op, u64),
+    Str(Field, Op, String),
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map(|&(p, _)| p).unwrap_or(self.len)
+    }
+
+    fn or(&mut self) -> Result<Expr, Box<Error + Sync + Send>> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, Box<Error + Sync + Send>> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Box<Error + Sync + Send>> {
+        let offset = self.offset();
+        match self.next() {
+            Some((_, Token::Not)) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some((_, Token::LParen)) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some((_, Token::RParen)) => Ok(expr),
+                    _ => Err(format!("expected `)` at {}", offset).into()),
+                }
+            }
+            Some((_, Token::Ident(name))) => self.comparison(offset, &name),
+            _ => Err(format!("expected a comparison at {}", offset).into()),
+        }
+    }
+
+    fn comparison(&mut self, offset: usize, name: &str) -> Result<Expr, Box<Error + Sync + Send>> {
+        let field = match name {
+            "level" => Field::Level,
+            "target" => Field::Target,
+            "module" => Field::Module,
+            "file" => Field::File,
+            "line" => Field::Line,
+            "thread" => Field::Thread,
+            "message" => Field::Message,
+            _ if name.starts_with("mdc.") && name.len() > 4 => Field::Mdc(name[4..].to_owned()),
+            _ => return Err(format!("unknown field `{}` at {}", name, offset).into()),
+        };
+
+        let op = match self.next() {
+            Some((_, Token::Op(op))) => op,
+            _ => return Err(format!("expected an operator after `{}`", name).into()),
+        };
+        let ordering = match op {
+            Op::Lt | Op::Le | Op::Gt | Op::Ge => true,
+            Op::Eq | Op::Ne | Op::StartsWith | Op::EndsWith | Op::Contains => false,
+        };
+        let offset = self.offset();
+        let value = self.next().map(|(_, t)| t);
+
+        match field {
+            Field::Level => {
+                if let Op::StartsWith | Op::EndsWith | Op::Contains = op {
+                    return Err(format!("invalid operator for `level` at {}", offset).into());
+                }
+                let level = match value {
+                    Some(Token::Ident(ref s)) | Some(Token::Str(ref s)) => s.parse().ok(),
+                    _ => None,
+                };
+                match level {
+                    Some(level) => Ok(Expr::Level(op, level)),
+                    None => Err(format!("expected a level at {}", offset).into()),
+                }
+            }
+            Field::Line => {
+                match value {
+                    Some(Token::Num(n)) if ordering || op == Op::Eq || op == Op::Ne => {
+                        Ok(Expr::Line(op, n))
+                    }
+                    _ => Err(format!("expected a line number at {}", offset).into()),
+                }
+            }
+            field => {
+                if ordering {
+                    return Err(format!("invalid operator for `{}` at {}", name, offset).into());
+                }
+                match value {
+                    Some(Token::Str(s)) => Ok(Expr::Str(field, op, s)),
+                    _ => Err(format!("expected a string at {}", offset).into()),
+                }
+            }
+        }
+    }
+}
+
+fn parse(expr: &str) -> Result<Expr, Box<Error + Sync + Send>> {
+    let mut parser = Parser {
+        tokens: lex(expr)?,
+        pos: 0,
+        len: expr.len(),
+    };
+    let parsed = parser.or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("unexpected token at {}", parser.offset()).into());
+    }
+    Ok(parsed)
+}
+
+fn compare<T: PartialOrd>(op: Op, a: T, b: T) -> bool {
+    match op {
+        Op::Eq => a == b,
+        Op::Ne => a != b,
+        Op::Lt => a < b,
+        Op::Le => a <= b,
+        Op::Gt => a > b,
+        Op::Ge => a >= b,
+        Op::StartsWith | Op::EndsWith | Op::Contains => false,
+    }
+}
+
+// the parts of a record an expression can refer to
+trait Subject {
+    fn level(&self) -> Level;
+
+    fn line(&self) -> Option<u32>;
+
+    fn field(&self, field: &Field) -> Option<Cow<'_, str>>;
+}
+
+impl<'a> Subject for Record<'a> {
+    fn level(&self) -> Level {
+        Record::level(self)
+    }
+
+    fn line(&self) -> Option<u32> {
+        Record::line(self)
+    }
+
+    fn field(&self, field: &Field) -> Option<Cow<'_, str>> {
+        match *field {
+            Field::Target => Some(Cow::Borrowed(self.target())),
+            Field::Module => self.module_path().map(Cow::Borrowed),
+            Field::File => self.file().map(Cow::Borrowed),
+            Field::Thread => thread::current().name().map(|n| Cow::Owned(n.to_owned())),
+            Field::Message => Some(Cow::Owned(self.args().to_string())),
+            Field::Mdc(ref key) => log_mdc::get(key, |v| v.map(|v| Cow::Owned(v.to_owned()))),
+            Field::Level | Field::Line => None,
+        }
+    }
+}
+
+impl Expr {
+    fn eval<S: Subject + ?Sized>(&self, subject: &S) -> bool {
+        match *self {
+            Expr::And(ref a, ref b) => a.eval(subject) && b.eval(subject),
+            Expr::Or(ref a, ref b) => a.eval(subject) || b.eval(subject),
+            Expr::Not(ref e) => !e.eval(subject),
+            // levels compare by severity, the reverse of `Level`'s order
+            Expr::Level(op, level) => compare(op, level, subject.level()),
+            Expr::Line(op, line) => {
+                match subject.line() {
+                    Some(actual) => compare(op, actual as u64, line),
+                    None => op == Op::Ne,
+                }
+            }
+            Expr::Str(ref field, op, ref value) => {
+                match subject.field(field) {
+                    Some(actual) => {
+                        match op {
+                            Op::StartsWith => actual.starts_with(&**value),
+                            Op::EndsWith => actual.ends_with(&**value),
+                            Op::Contains => actual.contains(&**value),
+                            op => compare(op, &*actual, &**value),
+                        }
+                    }
+                    None => op == Op::Ne,
+                }
+            }
+        }
+    }
+}
+
+/// A filter that evaluates a boolean expression against each event.
+///
+/// Events for which the expression is false are rejected, and all others
+/// pass through to later filters.
+///
+/// Expressions compare fields of the event to literal values, and combine
+/// comparisons with `&&`, `||`, `!`, and parentheses:
+///
+/// ```text
+/// level >= WARN || (target ^= "myapp::payments" && mdc.tenant == "acme")
+/// ```
+///
+/// The fields are:
+///
+/// * `level` - compared to a level name with `==`, `!=`, `<`, `<=`, `>`, or
+///   `>=`. Levels are ordered by severity, so `level >= WARN` matches `Warn`
+///   and `Error` events.
+/// * `line` - the source line, compared to a number with the same operators.
+/// * `target`, `module`, `file`, `thread`, `message`, and `mdc.<key>` - the
+///   target, module path, source file, thread name, formatted message, and MDC
+///   value, compared to a double quoted string with `==`, `!=`, `^=` (starts
+///   with), `$=` (ends with), or `*=` (contains).
+///
+/// An unnamed thread, missing MDC entry, or module, file, or line the record
+/// doesn't have only matches `!=`.
+#[derive(Debug)]
+pub struct ExpressionFilter {
+    expr: Expr,
+}
+
+impl ExpressionFilter {
+    /// Creates a new `ExpressionFilter` from the specified expression.
+    ///
+    /// An error describing the problem is returned if the expression is
+    /// invalid.
+    pub fn new(expr: &str) -> Result<ExpressionFilter, Box<Error + Sync + Send>> {
+        parse(expr)
+            .map(|expr| ExpressionFilter { expr: expr })
+            .map_err(|e| format!("invalid filter expression: {}", e).into())
+    }
+}
+
+impl Filter for ExpressionFilter {
+    fn filter(&self, record: &Record) -> Response {
+        if self.expr.eval(record) {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+/// A deserializer for the `ExpressionFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: expression
+///
+/// # The expression events must satisfy. Required.
+/// expression: 'level >= WARN || (target ^= "myapp::payments" && mdc.tenant == "acme")'
+/// ```
+#[cfg(feature = "file")]
+pub struct ExpressionFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for ExpressionFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = ExpressionFilterConfig;
+
+    fn deserialize(&self,
+                   config: ExpressionFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        Ok(Box::new(ExpressionFilter::new(&config.expression)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+
+    use super::*;
+
+    struct Event {
+        level: Level,
+        line: u32,
+        fields: HashMap<&'static str, &'static str>,
+    }
+
+    impl Subject for Event {
+        fn level(&self) -> Level {
+            self.level
+        }
+
+        fn line(&self) -> Option<u32> {
+            Some(self.line)
+        }
+
+        fn field(&self, field: &Field) -> Option<Cow<'_, str>> {
+            let key = match *field {
+                Field::Target => "target",
+                Field::Message => "message",
+                Field::Mdc(ref key) => &**key,
+                _ => return None,
+            };
+            self.fields.get(key).map(|v| Cow::Borrowed(*v))
+        }
+    }
+
+    fn event(level: Level, fields: &[(&'static str, &'static str)]) -> Event {
+        Event {
+            level: level,
+            line: 42,
+            fields: fields.iter().cloned().collect(),
+        }
+    }
+
+    fn eval(expr: &str, event: &Event) -> bool {
+        parse(expr).unwrap().eval(event)
+    }
+
+    #[test]
+    fn levels() {
+        let warn = event(Level::Warn, &[]);
+        assert!(eval("level >= WARN", &warn));
+        assert!(eval("level >= warn", &event(Level::Error, &[])));
+        assert!(!eval("level >= WARN", &event(Level::Info, &[])));
+        assert!(eval("level < ERROR", &warn));
+        assert!(eval("level == \"warn\"", &warn));
+        assert!(eval("level != INFO", &warn));
+        assert!(eval("line >= 40 && line < 50", &warn));
+        assert!(!eval("line == 41", &warn));
+    }
+
+    #[test]
+    fn strings() {
+        let event = event(Level::Info,
+                          &[("target", "myapp::payments::stripe"),
+                            ("message", "charge \"failed\""),
+                            ("tenant", "acme")]);
+        assert!(eval(r#"target ^= "myapp::payments""#, &event));
+        assert!(eval(r#"target $= "stripe""#, &event));
+        assert!(eval(r#"message *= "\"failed\"""#, &event));
+        assert!(eval(r#"mdc.tenant == "acme""#, &event));
+        assert!(!eval(r#"mdc.region == "eu""#, &event));
+        assert!(eval(r#"mdc.region != "eu""#, &event));
+    }
+
+    #[test]
+    fn logic() {
+        let expr = r#"level >= WARN || (target ^= "payments" && mdc.tenant == "acme")"#;
+        assert!(eval(expr, &event(Level::Error, &[])));
+        assert!(eval(expr, &event(Level::Info, &[("target", "payments"), ("tenant", "acme")])));
+        assert!(!eval(expr, &event(Level::Info, &[("target", "payments")])));
+        assert!(eval("!(level >= WARN) && !level == ERROR", &event(Level::Info, &[])));
+    }
+
+    #[test]
+    fn errors() {
+        for expr in &["",
+                      "level >=",
+                      "level >= LOUD",
+                      "level ^= WARN",
+                      "target >= \"a\"",
+                      "target == a",
+                      "line == \"1\"",
+                      "color == \"red\"",
+                      "(level >= WARN",
+                      "level >= WARN)",
+                      "target == \"a",
+                      "level >= WARN &&"] {
+            assert!(ExpressionFilter::new(expr).is_err(), "{}", expr);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! The first-N occurrences filter.
+//!
+//! Requires the `first_n_filter` feature.
+
+use antidote::Mutex;
+use log::Record;
+use std::collections::HashMap;
+#[cfg(feature = "file")]
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The first-N occurrences filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FirstNFilterConfig {
+    count: u64,
+    key: Option<ConfigKey>,
+    #[serde(deserialize_with = "::priv_serde::de_duration", default)]
+    period: Option<Duration>,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+enum ConfigKey {
+    #[serde(rename = "target")]
+    Target,
+    #[serde(rename = "location")]
+    Location,
+    #[serde(rename = "message")]
+    Message,
+}
+
+/// What identifies repeated occurrences of an event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// The event's target.
+    Target,
+    /// The module, file, and line which logged the event.
+    Location,
+    /// The event's formatted message.
+    Message,
+}
+
+#[derive(Debug)]
+struct Occurrences {
+    count: u64,
+    since: Instant,
+}
+
+/// A filter that accepts only the first N events with each key.
+///
+/// Events are keyed by their target, their call site, or their message. Once
+/// `count` events with a key have been accepted, later events with that key
+/// are rejected, either for the lifetime of the filter or until the period
+/// which began with the first of them has elapsed. Setting `count` to 1 gives
+/// "warn once" behavior. Events which are accepted pass through to later
+/// filters.
+///
+/// A key is remembered for every distinct value seen, so keying by message is
+/// best suited to messages without unbounded variable content.
+#[derive(Debug)]
+pub struct FirstNFilter {
+    count: u64,
+    key: Key,
+    period: Option<Duration>,
+    seen: Mutex<HashMap<String, Occurrences>>,
+}
+
+impl FirstNFilter {
+    /// Creates a new `FirstNFilter` accepting the first `count` events from
+    /// each call site for the lifetime of the filter.
+    pub fn new(count: u64) -> FirstNFilter {
+        FirstNFilter {
+            count: count,
+            key: Key::Location,
+            period: None,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets what identifies repeated occurrences of an event.
+    ///
+    /// Defaults to `Key::Location`.
+    pub fn key(mut self, key: Key) -> FirstNFilter {
+        self.key = key;
+        self
+    }
+
+    /// Sets the period after which the count for a key starts over.
+    ///
+    /// Defaults to never.
+    pub fn period(mut self, period: Duration) -> FirstNFilter {
+        self.period = Some(period);
+        self
+    }
+
+    fn accept(&self, key: String, now: Instant) -> bool {
+        let mut seen = self.seen.lock();
+        let occurrences = seen.entry(key).or_insert_with(|| {
+            Occurrences {
+                count: 0,
+                since: now,
+            }
+        });
+
+        if let Some(period) = self.period {
+            if now.duration_since(occurrences.since) >= period {
+                occurrences.count = 0;
+                occurrences.since = now;
+            }
+        }
+
+        if occurrences.count < self.count {
+            occurrences.count += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Filter for FirstNFilter {
+    fn filter(&self, record: &Record) -> Response {
+        let key = match self.key {
+            Key::Target => record.target().to_owned(),
+            Key::Location => {
+                format!("{}:{}:{}",
+                        record.module_path().unwrap_or("???"),
+                        record.file().unwrap_or("???"),
+                        record.line().unwrap_or(0))
+            }
+            Key::Message => record.args().to_string(),
+        };
+
+        if self.accept(key, Instant::now()) {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+/// A deserializer for the `FirstNFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: first_n
+///
+/// # The number of events with each key to accept. Required.
+/// count: 1
+///
+/// # What identifies repeated events. One of `target`, `location`, or
+/// # `message`. Defaults to `location`.
+/// key: location
+///
+/// # The period after which the count for a key starts over. Defaults to
+/// # never.
+/// period: 1 hour
+/// ```
+#[cfg(feature = "file")]
+pub struct FirstNFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for FirstNFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = FirstNFilterConfig;
+
+    fn deserialize(&self,
+                   config: FirstNFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let mut filter = FirstNFilter::new(config.count);
+        match config.key {
+            Some(ConfigKey::Target) => filter = filter.key(Key::Target),
+            Some(ConfigKey::Location) | None => {}
+            Some(ConfigKey::Message) => filter = filter.key(Key::Message),
+        }
+        if let Some(period) = config.period {
+            filter = filter.period(period);
+        }
+        Ok(Box::new(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn lifetime() {
+        let filter = FirstNFilter::new(2);
+        let now = Instant::now();
+        assert!(filter.accept("a".to_owned(), now));
+        assert!(filter.accept("a".to_owned(), now));
+        assert!(!filter.accept("a".to_owned(), now));
+        assert!(filter.accept("b".to_owned(), now));
+        assert!(!filter.accept("a".to_owned(), now + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn period() {
+        let filter = FirstNFilter::new(1).period(Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(filter.accept("a".to_owned(), start));
+        assert!(!filter.accept("a".to_owned(), start + Duration::from_secs(30)));
+        assert!(filter.accept("a".to_owned(), start + Duration::from_secs(60)));
+        assert!(!filter.accept("a".to_owned(), start + Duration::from_secs(90)));
+    }
+}
@@ -0,0 +1,239 @@
+//! The key-value filter.
+//!
+//! Requires the `kv_filter` feature.
+
+use log::kv::Key;
+use log::Record;
+use log_mdc;
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The key-value filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KvFilterConfig {
+    conditions: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// longer operators first so `<=` isn't read as `<`
+const OPS: [(&str, Op); 6] = [("==", Op::Eq),
+                                      ("!=", Op::Ne),
+                                      ("<=", Op::Le),
+                                      (">=", Op::Ge),
+                                      ("<", Op::Lt),
+                                      (">", Op::Gt)];
+
+#[derive(Debug)]
+struct Condition {
+    key: String,
+    test: Option<(Op, String)>,
+}
+
+impl Condition {
+    fn parse(condition: &str) -> Result<Condition, Box<Error + Sync + Send>> {
+        let invalid = || format!("invalid condition `{}`", condition);
+
+        let found = OPS.iter()
+            .filter_map(|&(token, op)| condition.find(token).map(|pos| (pos, token, op)))
+            .min_by_key(|&(pos, token, _)| (pos, !token.len()));
+        let (key, test) = match found {
+            Some((pos, token, op)) => {
+                let value = condition[pos + token.len()..].trim();
+                let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                    &value[1..value.len() - 1]
+                } else {
+                    value
+                };
+                (condition[..pos].trim(), Some((op, value.to_owned())))
+            }
+            None => (condition.trim(), None),
+        };
+
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(invalid().into());
+        }
+        Ok(Condition {
+            key: key.to_owned(),
+            test: test,
+        })
+    }
+
+    fn matches(&self, value: Option<&str>) -> bool {
+        let (value, op, expected) = match (value, &self.test) {
+            (None, _) => return false,
+            (Some(_), None) => return true,
+            (Some(value), Some((op, expected))) => (value, *op, expected),
+        };
+
+        // compare numerically when both sides are numbers, so `500 > 99`
+        if let (Ok(a), Ok(b)) = (value.parse::<f64>(), expected.parse::<f64>()) {
+            return match op {
+                Op::Eq => a == b,
+                Op::Ne => a != b,
+                Op::Lt => a < b,
+                Op::Le => a <= b,
+                Op::Gt => a > b,
+                Op::Ge => a >= b,
+            };
+        }
+        match op {
+            Op::Eq => value == expected,
+            Op::Ne => value != expected,
+            // strings are not ordered
+            _ => false,
+        }
+    }
+}
+
+/// A filter that rejects events unless their structured key-value data
+/// satisfies a set of conditions.
+///
+/// Each condition is either a key by itself, which must be present, or a key,
+/// a comparison operator (`==`, `!=`, `<`, `<=`, `>`, or `>=`), and a value,
+/// like `status_code >= 500` or `method == "POST"`. Values which both parse as
+/// numbers are compared numerically and others as strings, which only support
+/// `==` and `!=`. All conditions must hold for an event to pass through to
+/// later filters.
+///
+/// Keys are looked up in the key-value pairs of the record, and then in the MDC
+/// of the thread logging the event.
+#[derive(Debug)]
+pub struct KvFilter {
+    conditions: Vec<Condition>,
+}
+
+impl KvFilter {
+    /// Creates a new `KvFilter` requiring all of the specified conditions.
+    ///
+    /// An error is returned if a condition cannot be parsed.
+    pub fn new<S: AsRef<str>>(conditions: &[S]) -> Result<KvFilter, Box<Error + Sync + Send>> {
+        let conditions = conditions.iter()
+            .map(|c| Condition::parse(c.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Ok(KvFilter { conditions: conditions })
+    }
+
+    fn response<F>(&self, lookup: F) -> Response
+        where F: Fn(&str, &Fn(Option<&str>) -> bool) -> bool
+    {
+        if self.conditions.iter().all(|c| lookup(&c.key, &|v| c.matches(v))) {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+impl Filter for KvFilter {
+    fn filter(&self, record: &Record) -> Response {
+        let key_values = record.key_values();
+        self.response(|key, matches| {
+            match key_values.get(Key::from_str(key)) {
+                Some(value) => matches(Some(&value.to_string())),
+                None => log_mdc::get(key, matches),
+            }
+        })
+    }
+}
+
+/// A deserializer for the `KvFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: kv
+///
+/// # The conditions which must all hold. Required.
+/// conditions:
+///   - status_code >= 500
+///   - method == "POST"
+///   - request_id
+/// ```
+#[cfg(feature = "file")]
+pub struct KvFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for KvFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = KvFilterConfig;
+
+    fn deserialize(&self,
+                   config: KvFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        Ok(Box::new(KvFilter::new(&config.conditions)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use filter;
+
+    fn rejected(filter: &KvFilter, pairs: &[(&str, &str)]) -> bool {
+        filter::rejected(filter, &Record::builder().key_values(&pairs).build())
+    }
+
+    #[test]
+    fn conditions() {
+        let filter = KvFilter::new(&["status_code >= 500", "method == \"POST\"", "request_id"])
+            .unwrap();
+        assert!(!rejected(&filter,
+                          &[("status_code", "503"), ("method", "POST"), ("request_id", "1")]));
+        assert!(rejected(&filter,
+                         &[("status_code", "404"), ("method", "POST"), ("request_id", "1")]));
+        assert!(rejected(&filter, &[("status_code", "503"), ("method", "POST")]));
+        assert!(rejected(&filter,
+                         &[("status_code", "503"), ("method", "GET"), ("request_id", "1")]));
+
+        let filter = KvFilter::new(&["latency<=0.25", "region != eu"]).unwrap();
+        assert!(!rejected(&filter, &[("latency", "0.1"), ("region", "us")]));
+        assert!(rejected(&filter, &[("latency", "0.3"), ("region", "us")]));
+        assert!(rejected(&filter, &[("latency", "0.1"), ("region", "eu")]));
+        assert!(rejected(&KvFilter::new(&["name > a"]).unwrap(), &[("name", "b")]));
+    }
+
+    #[test]
+    fn mdc() {
+        // keys missing from the event are looked up in the MDC
+        let filter = KvFilter::new(&["kv_filter_status >= 500"]).unwrap();
+        assert!(rejected(&filter, &[]));
+        log_mdc::insert("kv_filter_status", "500");
+        assert!(!rejected(&filter, &[]));
+        log_mdc::remove("kv_filter_status");
+    }
+
+    #[test]
+    fn record() {
+        let filter = KvFilter::new(&["kv_filter_code >= 500"]).unwrap();
+        log_mdc::insert("kv_filter_code", "200");
+        // the event's own value takes precedence
+        let key_values = [("kv_filter_code", 503)];
+        let record = Record::builder().key_values(&key_values).build();
+        assert!(!filter::rejected(&filter, &record));
+        log_mdc::remove("kv_filter_code");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(KvFilter::new(&[""]).is_err());
+        assert!(KvFilter::new(&["== 5"]).is_err());
+        assert!(KvFilter::new(&["status code == 5"]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! The level range filter.
+//!
+//! Requires the `level_range_filter` feature.
+
+use log::{Level, LevelFilter, Record};
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The level range filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelRangeFilterConfig {
+    #[serde(deserialize_with = "::priv_serde::de_filter")]
+    min: LevelFilter,
+    #[serde(deserialize_with = "::priv_serde::de_filter")]
+    max: LevelFilter,
+}
+
+/// A filter that rejects all events outside of a range of levels.
+///
+/// Both ends of the range are inclusive. Following the ordering of
+/// `Level`, `min` is the least verbose level accepted and `max` the most
+/// verbose, so a range from `Warn` to `Info` accepts only `Warn` and `Info`
+/// events.
+#[derive(Debug)]
+pub struct LevelRangeFilter {
+    min: LevelFilter,
+    max: LevelFilter,
+}
+
+impl LevelRangeFilter {
+    /// Creates a new `LevelRangeFilter` accepting events from `min` through
+    /// `max`.
+    pub fn new(min: LevelFilter, max: LevelFilter) -> LevelRangeFilter {
+        LevelRangeFilter { min: min, max: max }
+    }
+
+    fn response(&self, level: Level) -> Response {
+        if level < self.min || level > self.max {
+            Response::Reject
+        } else {
+            Response::Neutral
+        }
+    }
+}
+
+impl Filter for LevelRangeFilter {
+    fn filter(&self, record: &Record) -> Response {
+        self.response(record.level())
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.max
+    }
+}
+
+/// A deserializer for the `LevelRangeFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: level_range
+///
+/// # The least verbose level to accept. Required.
+/// min: warn
+///
+/// # The most verbose level to accept. Required.
+/// max: info
+/// ```
+#[cfg(feature = "file")]
+pub struct LevelRangeFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for LevelRangeFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = LevelRangeFilterConfig;
+
+    fn deserialize(&self,
+                   config: LevelRangeFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        if config.min > config.max {
+            return Err("`min` must not be more verbose than `max`".into());
+        }
+        Ok(Box::new(LevelRangeFilter::new(config.min, config.max)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::{Level, LevelFilter};
+
+    use super::*;
+
+    use filter;
+
+    #[test]
+    fn range() {
+        let filter = LevelRangeFilter::new(LevelFilter::Warn, LevelFilter::Info);
+        let rejected = |level| filter::rejected(&filter, &Record::builder().level(level).build());
+        assert!(rejected(Level::Error));
+        assert!(!rejected(Level::Warn));
+        assert!(!rejected(Level::Info));
+        assert!(rejected(Level::Debug));
+        assert!(rejected(Level::Trace));
+    }
+}
@@ -0,0 +1,187 @@
+//! The per-level sampling filter.
+//!
+//! Requires the `level_sample_filter` feature.
+
+use log::{Level, Record};
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "file")]
+use std::error::Error;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The per-level sampling filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelSampleFilterConfig {
+    error: Option<f64>,
+    warn: Option<f64>,
+    info: Option<f64>,
+    debug: Option<f64>,
+    trace: Option<f64>,
+}
+
+/// A filter that randomly accepts a fraction of log events, with a separate
+/// probability for each level.
+///
+/// This allows high volume levels like `trace` to be sampled heavily without
+/// losing events at more important levels. Levels default to a probability
+/// of 1, accepting every event. Events which are accepted pass through to
+/// later filters, and others are rejected.
+#[derive(Debug)]
+pub struct LevelSampleFilter {
+    // indexed by `level as usize - 1`
+    probabilities: [f64; 5],
+    state: AtomicU64,
+}
+
+impl LevelSampleFilter {
+    /// Creates a new `LevelSampleFilter` builder.
+    pub fn builder() -> LevelSampleFilterBuilder {
+        LevelSampleFilterBuilder { probabilities: [1.; 5] }
+    }
+
+    /// Returns the probability with which events at the specified level are
+    /// accepted.
+    pub fn probability(&self, level: Level) -> f64 {
+        self.probabilities[level as usize - 1]
+    }
+
+    // splitmix64, which stays well distributed when stepped concurrently
+    fn roll(&self) -> f64 {
+        let mut z = self.state
+            .fetch_add(0x9E3779B97F4A7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        // the top 53 bits give a uniform value in [0, 1)
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn accept(&self, level: Level) -> bool {
+        let probability = self.probability(level);
+        probability >= 1. || (probability > 0. && self.roll() < probability)
+    }
+}
+
+impl Filter for LevelSampleFilter {
+    fn filter(&self, record: &Record) -> Response {
+        if self.accept(record.level()) {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+/// A builder for `LevelSampleFilter`s.
+pub struct LevelSampleFilterBuilder {
+    probabilities: [f64; 5],
+}
+
+impl LevelSampleFilterBuilder {
+    /// Sets the probability, between 0 and 1, with which events at the
+    /// specified level are accepted.
+    ///
+    /// Defaults to 1.
+    pub fn probability(mut self, level: Level, probability: f64) -> LevelSampleFilterBuilder {
+        self.probabilities[level as usize - 1] = probability;
+        self
+    }
+
+    /// Consumes the `LevelSampleFilterBuilder`, producing a
+    /// `LevelSampleFilter`.
+    pub fn build(self) -> LevelSampleFilter {
+        LevelSampleFilter {
+            probabilities: self.probabilities,
+            state: AtomicU64::new(RandomState::new().build_hasher().finish()),
+        }
+    }
+}
+
+/// A deserializer for the `LevelSampleFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: level_sample
+///
+/// # The probability, between 0 and 1, with which events at each level are
+/// # accepted. Each defaults to 1.
+/// error: 1
+/// warn: 1
+/// info: 1
+/// debug: 0.1
+/// trace: 0.01
+/// ```
+#[cfg(feature = "file")]
+pub struct LevelSampleFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for LevelSampleFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = LevelSampleFilterConfig;
+
+    fn deserialize(&self,
+                   config: LevelSampleFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let levels = [(Level::Error, config.error),
+                      (Level::Warn, config.warn),
+                      (Level::Info, config.info),
+                      (Level::Debug, config.debug),
+                      (Level::Trace, config.trace)];
+        let mut filter = LevelSampleFilter::builder();
+        for &(level, probability) in &levels {
+            if let Some(probability) = probability {
+                if !(0. ..=1.).contains(&probability) {
+                    return Err(format!("probability for `{}` must be between 0 and 1", level)
+                        .into());
+                }
+                filter = filter.probability(level, probability);
+            }
+        }
+        Ok(Box::new(filter.build()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn probabilities() {
+        let filter = LevelSampleFilter::builder()
+            .probability(Level::Trace, 0.)
+            .probability(Level::Debug, 0.25)
+            .build();
+        assert_eq!(filter.probability(Level::Info), 1.);
+
+        let mut debug = 0;
+        for _ in 0..10000 {
+            assert!(filter.accept(Level::Info));
+            assert!(!filter.accept(Level::Trace));
+            if filter.accept(Level::Debug) {
+                debug += 1;
+            }
+        }
+        assert!(debug > 2000 && debug < 3000, "{}", debug);
+    }
+
+    #[test]
+    fn roll() {
+        let filter = LevelSampleFilter::builder().build();
+        for _ in 0..1000 {
+            let roll = filter.roll();
+            assert!((0. ..1.).contains(&roll));
+        }
+    }
+}
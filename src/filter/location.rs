@@ -0,0 +1,216 @@
+//! The caller location filter.
+//!
+//! Requires the `location_filter` feature.
+
+use log::Record;
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The caller location filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocationFilterConfig {
+    locations: Vec<LocationConfig>,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LocationConfig {
+    file: Option<String>,
+    lines: Option<String>,
+}
+
+#[derive(Debug)]
+struct Location {
+    file: Option<String>,
+    lines: Option<(u32, u32)>,
+}
+
+impl Location {
+    fn matches(&self, file: &str, line: u32) -> bool {
+        if let Some((start, end)) = self.lines {
+            if line < start || line > end {
+                return false;
+            }
+        }
+        match self.file {
+            Some(ref glob) => ::glob_matches(glob, file),
+            None => true,
+        }
+    }
+}
+
+#[cfg(feature = "file")]
+fn parse_lines(lines: &str) -> Result<(u32, u32), Box<Error + Sync + Send>> {
+    let invalid = || format!("invalid line range `{}`", lines);
+    let mut parts = lines.splitn(2, '-');
+    let start = parts.next()
+        .and_then(|s| s.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    let end = match parts.next() {
+        Some(end) => end.trim().parse().map_err(|_| invalid())?,
+        None => start,
+    };
+    if end < start {
+        return Err(invalid().into());
+    }
+    Ok((start, end))
+}
+
+/// A filter that selects log events by the source location that logged them.
+///
+/// Each location matches source files against a glob, where `*` matches any
+/// sequence of characters, including path separators, and `?` matches any
+/// single character. A location may also be restricted to an inclusive range
+/// of lines. Events from none of the locations are rejected, and all others
+/// pass through to later filters.
+///
+/// Paths are those produced by `file!()`, which are usually relative to the
+/// crate root, like `src/payments/charge.rs`, or absolute for dependencies,
+/// so patterns often need a leading `*`.
+#[derive(Debug)]
+pub struct LocationFilter {
+    locations: Vec<Location>,
+}
+
+impl LocationFilter {
+    /// Creates a new `LocationFilter` builder.
+    pub fn builder() -> LocationFilterBuilder {
+        LocationFilterBuilder { locations: vec![] }
+    }
+
+    fn response(&self, file: &str, line: u32) -> Response {
+        if self.locations.iter().any(|l| l.matches(file, line)) {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+impl Filter for LocationFilter {
+    fn filter(&self, record: &Record) -> Response {
+        match (record.file(), record.line()) {
+            (Some(file), Some(line)) => self.response(file, line),
+            _ => Response::Reject,
+        }
+    }
+}
+
+/// A builder for `LocationFilter`s.
+pub struct LocationFilterBuilder {
+    locations: Vec<Location>,
+}
+
+impl LocationFilterBuilder {
+    /// Accepts events from source files matching the specified glob.
+    pub fn file(mut self, glob: &str) -> LocationFilterBuilder {
+        self.locations.push(Location {
+            file: Some(glob.to_owned()),
+            lines: None,
+        });
+        self
+    }
+
+    /// Accepts events from the inclusive range of lines of source files
+    /// matching the specified glob.
+    pub fn lines(mut self, glob: &str, start: u32, end: u32) -> LocationFilterBuilder {
+        self.locations.push(Location {
+            file: Some(glob.to_owned()),
+            lines: Some((start, end)),
+        });
+        self
+    }
+
+    /// Consumes the `LocationFilterBuilder`, producing a `LocationFilter`.
+    pub fn build(self) -> LocationFilter {
+        LocationFilter { locations: self.locations }
+    }
+}
+
+/// A deserializer for the `LocationFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: location
+///
+/// # The locations from which events are accepted. Required.
+/// locations:
+///
+///     # A glob matching source file paths. Defaults to all files.
+///   - file: "*src/payments/charge.rs"
+///
+///     # A line, or an inclusive range of lines. Defaults to all lines.
+///     lines: 120-135
+/// ```
+#[cfg(feature = "file")]
+pub struct LocationFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for LocationFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = LocationFilterConfig;
+
+    fn deserialize(&self,
+                   config: LocationFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let mut locations = vec![];
+        for location in config.locations {
+            let lines = match location.lines {
+                Some(lines) => Some(parse_lines(&lines)?),
+                None => None,
+            };
+            locations.push(Location {
+                file: location.file,
+                lines: lines,
+            });
+        }
+        Ok(Box::new(LocationFilter { locations: locations }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use filter::rejected;
+
+    fn record(file: &str, line: u32) -> Record<'_> {
+        Record::builder().file(Some(file)).line(Some(line)).build()
+    }
+
+    #[test]
+    fn locations() {
+        let filter = LocationFilter::builder()
+            .lines("*src/payments/charge.rs", 120, 135)
+            .file("src/audit/*")
+            .build();
+        assert!(!rejected(&filter, &record("src/payments/charge.rs", 120)));
+        assert!(!rejected(&filter, &record("/build/myapp/src/payments/charge.rs", 135)));
+        assert!(rejected(&filter, &record("src/payments/charge.rs", 136)));
+        assert!(rejected(&filter, &record("src/payments/refund.rs", 125)));
+        assert!(!rejected(&filter, &record("src/audit/log.rs", 1)));
+        assert!(rejected(&LocationFilter::builder().build(), &record("src/main.rs", 1)));
+        // events without a location are rejected
+        assert!(rejected(&filter, &Record::builder().build()));
+    }
+
+    #[test]
+    #[cfg(feature = "file")]
+    fn lines() {
+        assert_eq!(parse_lines("120-135").unwrap(), (120, 135));
+        assert_eq!(parse_lines("42").unwrap(), (42, 42));
+        assert_eq!(parse_lines(" 1 - 2 ").unwrap(), (1, 2));
+        assert!(parse_lines("20-10").is_err());
+        assert!(parse_lines("a-b").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! The marker filter.
+//!
+//! Requires the `marker_filter` feature.
+
+use log::Record;
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+use marker;
+
+/// The marker filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarkerFilterConfig {
+    marker: String,
+    on_match: Option<ConfigResponse>,
+    on_mismatch: Option<ConfigResponse>,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+enum ConfigResponse {
+    #[serde(rename = "accept")]
+    Accept,
+    #[serde(rename = "neutral")]
+    Neutral,
+    #[serde(rename = "reject")]
+    Reject,
+}
+
+#[cfg(feature = "file")]
+impl ConfigResponse {
+    fn into_response(self) -> Response {
+        match self {
+            ConfigResponse::Accept => Response::Accept,
+            ConfigResponse::Neutral => Response::Neutral,
+            ConfigResponse::Reject => Response::Reject,
+        }
+    }
+}
+
+/// A filter that selects log events by their markers.
+///
+/// Events carrying the marker receive one response and all others receive
+/// another. By default, marked events pass through to later filters and
+/// others are rejected. Responding to a match with `Response::Accept` routes
+/// marked events to the appender regardless of any later level filters.
+///
+/// See the `marker` module for how markers are attached to events.
+#[derive(Debug)]
+pub struct MarkerFilter {
+    marker: String,
+    on_match: Response,
+    on_mismatch: Response,
+}
+
+impl MarkerFilter {
+    /// Creates a new `MarkerFilter` for the specified marker.
+    pub fn new(marker: &str) -> MarkerFilter {
+        MarkerFilter {
+            marker: marker.to_owned(),
+            on_match: Response::Neutral,
+            on_mismatch: Response::Reject,
+        }
+    }
+
+    /// Sets the response to events carrying the marker.
+    ///
+    /// Defaults to `Response::Neutral`.
+    pub fn on_match(mut self, response: Response) -> MarkerFilter {
+        self.on_match = response;
+        self
+    }
+
+    /// Sets the response to events not carrying the marker.
+    ///
+    /// Defaults to `Response::Reject`.
+    pub fn on_mismatch(mut self, response: Response) -> MarkerFilter {
+        self.on_mismatch = response;
+        self
+    }
+
+    fn response(&self, matched: bool) -> Response {
+        if matched {
+            self.on_match
+        } else {
+            self.on_mismatch
+        }
+    }
+}
+
+impl Filter for MarkerFilter {
+    fn filter(&self, _: &Record) -> Response {
+        self.response(marker::has(&self.marker))
+    }
+}
+
+/// A deserializer for the `MarkerFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: marker
+///
+/// # The marker to match. Required.
+/// marker: AUDIT
+///
+/// # The response to events carrying the marker. One of `accept`, `neutral`,
+/// # or `reject`. Defaults to `neutral`.
+/// on_match: accept
+///
+/// # The response to events not carrying the marker. Defaults to `reject`.
+/// on_mismatch: reject
+/// ```
+#[cfg(feature = "file")]
+pub struct MarkerFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for MarkerFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = MarkerFilterConfig;
+
+    fn deserialize(&self,
+                   config: MarkerFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let mut filter = MarkerFilter::new(&config.marker);
+        if let Some(response) = config.on_match {
+            filter = filter.on_match(response.into_response());
+        }
+        if let Some(response) = config.on_mismatch {
+            filter = filter.on_mismatch(response.into_response());
+        }
+        Ok(Box::new(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn responses() {
+        let filter = MarkerFilter::new("AUDIT");
+        match filter.response(true) {
+            Response::Neutral => {}
+            _ => panic!("expected neutral"),
+        }
+        match filter.response(false) {
+            Response::Reject => {}
+            _ => panic!("expected reject"),
+        }
+
+        let filter = MarkerFilter::new("AUDIT")
+            .on_match(Response::Accept)
+            .on_mismatch(Response::Neutral);
+        marker::with("AUDIT", || match filter.response(marker::has("AUDIT")) {
+            Response::Accept => {}
+            _ => panic!("expected accept"),
+        });
+        match filter.response(marker::has("AUDIT")) {
+            Response::Neutral => {}
+            _ => panic!("expected neutral"),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! The MDC filter.
+//!
+//! Requires the `mdc_filter` feature.
+
+use log::Record;
+use log_mdc;
+use regex::{self, Regex};
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The MDC filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MdcFilterConfig {
+    key: String,
+    value: Option<String>,
+    pattern: Option<String>,
+}
+
+#[derive(Debug)]
+enum Condition {
+    Present,
+    Equals(String),
+    Matches(Regex),
+}
+
+/// A filter that rejects all events unless an MDC entry is present, equal to
+/// a value, or matches a regular expression.
+///
+/// The MDC is read on the thread logging the event.
+#[derive(Debug)]
+pub struct MdcFilter {
+    key: String,
+    condition: Condition,
+}
+
+impl MdcFilter {
+    /// Creates a new `MdcFilter` which accepts events when the specified key
+    /// is present in the MDC.
+    pub fn present(key: &str) -> MdcFilter {
+        MdcFilter {
+            key: key.to_owned(),
+            condition: Condition::Present,
+        }
+    }
+
+    /// Creates a new `MdcFilter` which accepts events when the specified key
+    /// has the specified value in the MDC.
+    pub fn equals(key: &str, value: &str) -> MdcFilter {
+        MdcFilter {
+            key: key.to_owned(),
+            condition: Condition::Equals(value.to_owned()),
+        }
+    }
+
+    /// Creates a new `MdcFilter` which accepts events when the value of the
+    /// specified key in the MDC matches the specified regular expression.
+    ///
+    /// The expression is unanchored, so it matches anywhere in the value
+    /// unless it uses `^` and `$`.
+    pub fn matches(key: &str, pattern: &str) -> Result<MdcFilter, regex::Error> {
+        Ok(MdcFilter {
+            key: key.to_owned(),
+            condition: Condition::Matches(Regex::new(pattern)?),
+        })
+    }
+
+    fn response(&self) -> Response {
+        let accepted = log_mdc::get(&self.key, |value| {
+            match (value, &self.condition) {
+                (None, _) => false,
+                (Some(_), Condition::Present) => true,
+                (Some(value), Condition::Equals(expected)) => value == expected,
+                (Some(value), Condition::Matches(regex)) => regex.is_match(value),
+            }
+        });
+
+        if accepted {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+impl Filter for MdcFilter {
+    fn filter(&self, _: &Record) -> Response {
+        self.response()
+    }
+}
+
+/// A deserializer for the `MdcFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: mdc
+///
+/// # The MDC key to check. Required.
+/// key: tenant_id
+///
+/// # If set, the MDC value must be equal to this. Optional.
+/// value: acme
+///
+/// # If set, the MDC value must match this regular expression. Cannot be
+/// # combined with `value`. If neither is set, the key only needs to be
+/// # present. Optional.
+/// pattern: "^acme-(eu|us)$"
+/// ```
+#[cfg(feature = "file")]
+pub struct MdcFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for MdcFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = MdcFilterConfig;
+
+    fn deserialize(&self,
+                   config: MdcFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let filter = match (config.value, config.pattern) {
+            (Some(_), Some(_)) => return Err("`value` and `pattern` cannot both be set".into()),
+            (Some(value), None) => MdcFilter::equals(&config.key, &value),
+            (None, Some(pattern)) => MdcFilter::matches(&config.key, &pattern)?,
+            (None, None) => MdcFilter::present(&config.key),
+        };
+        Ok(Box::new(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use filter::rejected;
+
+    #[test]
+    fn conditions() {
+        let present = MdcFilter::present("mdc_filter_tenant");
+        let equals = MdcFilter::equals("mdc_filter_tenant", "acme");
+        let matches = MdcFilter::matches("mdc_filter_tenant", "^acme-(eu|us)$").unwrap();
+        let record = Record::builder().build();
+
+        assert!(rejected(&present, &record));
+        assert!(rejected(&equals, &record));
+        assert!(rejected(&matches, &record));
+
+        log_mdc::insert("mdc_filter_tenant", "acme");
+        assert!(!rejected(&present, &record));
+        assert!(!rejected(&equals, &record));
+        assert!(rejected(&matches, &record));
+
+        log_mdc::insert("mdc_filter_tenant", "acme-eu");
+        assert!(!rejected(&present, &record));
+        assert!(rejected(&equals, &record));
+        assert!(!rejected(&matches, &record));
+
+        log_mdc::remove("mdc_filter_tenant");
+        assert!(MdcFilter::matches("key", "(").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 //! Filters
 
 use std::fmt;
-use log::LogRecord;
+use log::{LevelFilter, Record};
 #[cfg(feature = "file")]
 use serde_value::Value;
 #[cfg(feature = "file")]
@@ -12,6 +12,46 @@ use std::collections::BTreeMap;
 #[cfg(feature = "file")]
 use file::Deserializable;
 
+#[cfg(feature = "boolean_filter")]
+pub mod boolean;
+#[cfg(feature = "burst_filter")]
+pub mod burst;
+#[cfg(feature = "deny_list_filter")]
+pub mod deny_list;
+#[cfg(feature = "dynamic_filter")]
+pub mod dynamic;
+#[cfg(feature = "env_filter")]
+pub mod env;
+#[cfg(feature = "expression_filter")]
+pub mod expression;
+#[cfg(feature = "first_n_filter")]
+pub mod first_n;
+#[cfg(feature = "kv_filter")]
+pub mod kv;
+#[cfg(feature = "level_range_filter")]
+pub mod level_range;
+#[cfg(feature = "level_sample_filter")]
+pub mod level_sample;
+#[cfg(feature = "location_filter")]
+pub mod location;
+#[cfg(feature = "marker_filter")]
+pub mod marker;
+#[cfg(feature = "mdc_filter")]
+pub mod mdc;
+#[cfg(any(feature = "target_filter", feature = "deny_list_filter"))]
+mod name;
+#[cfg(feature = "rate_limit_filter")]
+pub mod rate_limit;
+#[cfg(feature = "schedule_filter")]
+pub mod schedule;
+#[cfg(feature = "script_filter")]
+pub mod script;
+#[cfg(feature = "size_filter")]
+pub mod size;
+#[cfg(feature = "target_filter")]
+pub mod target;
+#[cfg(feature = "thread_filter")]
+pub mod thread;
 #[cfg(feature = "threshold_filter")]
 pub mod threshold;
 
@@ -21,7 +61,16 @@ pub mod threshold;
 /// sent to that appender.
 pub trait Filter: fmt::Debug + Send + Sync + 'static {
     /// Filters a log event.
-    fn filter(&self, record: &LogRecord) -> Response;
+    fn filter(&self, record: &Record) -> Response;
+
+    /// Returns the most verbose level of the events the filter may not
+    /// reject.
+    ///
+    /// log4rs uses this to skip events no appender would accept before
+    /// they are even created. The default implementation returns `Trace`.
+    fn max_level(&self) -> LevelFilter {
+        LevelFilter::Trace
+    }
 }
 
 #[cfg(feature = "file")]
@@ -32,6 +81,7 @@ impl Deserializable for Filter {
 }
 
 /// The response returned by a filter.
+#[derive(Debug, Copy, Clone)]
 pub enum Response {
     /// Accept the log event.
     ///
@@ -49,6 +99,15 @@ pub enum Response {
     Reject,
 }
 
+// whether the filter rejects the event
+#[cfg(test)]
+fn rejected(filter: &Filter, record: &Record) -> bool {
+    match filter.filter(record) {
+        Response::Reject => true,
+        Response::Accept | Response::Neutral => false,
+    }
+}
+
 /// Configuration for a filter.
 #[derive(PartialEq, Eq, Debug)]
 #[cfg(feature = "file")]
@@ -0,0 +1,38 @@
+//! Target and module path patterns.
+//!
+//! A pattern ending in `::*` matches a module and everything in it, one ending
+//! in `*` matches any name starting with the rest of the pattern, and any
+//! other pattern matches a name exactly.
+
+/// A pattern matching targets or module paths.
+#[derive(Debug)]
+pub enum Pattern {
+    Exact(String),
+    Module(String),
+    Prefix(String),
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Pattern {
+        if let Some(module) = pattern.strip_suffix("::*") {
+            Pattern::Module(module.to_owned())
+        } else if let Some(prefix) = pattern.strip_suffix('*') {
+            Pattern::Prefix(prefix.to_owned())
+        } else {
+            Pattern::Exact(pattern.to_owned())
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match *self {
+            Pattern::Exact(ref exact) => name == exact,
+            Pattern::Module(ref module) => {
+                match name.strip_prefix(&**module) {
+                    Some(rest) => rest.is_empty() || rest.starts_with("::"),
+                    None => false,
+                }
+            }
+            Pattern::Prefix(ref prefix) => name.starts_with(&**prefix),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! The rate limit filter.
+//!
+//! Requires the `rate_limit_filter` feature.
+
+use antidote::Mutex;
+use log::Record;
+use std::collections::HashMap;
+#[cfg(feature = "file")]
+use std::error::Error;
+use std::time::Instant;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The rate limit filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitFilterConfig {
+    rate: f64,
+    burst: Option<u32>,
+    #[serde(default)]
+    per_key: bool,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// A filter that limits the rate of log events with a token bucket.
+///
+/// The bucket holds up to `burst` tokens and refills at `rate` tokens per
+/// second. Each event takes a token, and events arriving when the bucket is
+/// empty are rejected. Events which are accepted pass through to later
+/// filters.
+///
+/// By default a single bucket is shared by all events. With `per_key`, each
+/// combination of target and level gets its own bucket, so a storm from one
+/// module does not starve the others.
+#[derive(Debug)]
+pub struct RateLimitFilter {
+    rate: f64,
+    burst: f64,
+    // `Level` isn't `Hash`, so levels are keyed by their discriminant
+    buckets: Mutex<HashMap<Option<(String, usize)>, Bucket>>,
+    per_key: bool,
+}
+
+impl RateLimitFilter {
+    /// Creates a new `RateLimitFilter` allowing `rate` events per second on
+    /// average and bursts of up to `burst` events.
+    pub fn new(rate: f64, burst: u32) -> RateLimitFilter {
+        RateLimitFilter {
+            rate: rate,
+            burst: burst as f64,
+            buckets: Mutex::new(HashMap::new()),
+            per_key: false,
+        }
+    }
+
+    /// Like `new`, but with a separate bucket for each target and level.
+    pub fn per_key(rate: f64, burst: u32) -> RateLimitFilter {
+        RateLimitFilter {
+            per_key: true,
+            ..RateLimitFilter::new(rate, burst)
+        }
+    }
+
+    fn take(&self, key: Option<(String, usize)>, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock();
+        let burst = self.burst;
+        let bucket = buckets.entry(key).or_insert_with(|| {
+            Bucket {
+                tokens: burst,
+                last: now,
+            }
+        });
+
+        let elapsed = now.duration_since(bucket.last);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Filter for RateLimitFilter {
+    fn filter(&self, record: &Record) -> Response {
+        let key = if self.per_key {
+            Some((record.target().to_owned(), record.level() as usize))
+        } else {
+            None
+        };
+
+        if self.take(key, Instant::now()) {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+/// A deserializer for the `RateLimitFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: rate_limit
+///
+/// # The average number of events per second to allow. Required.
+/// rate: 100
+///
+/// # The maximum number of events to allow in a burst. Defaults to `rate`,
+/// # rounded up.
+/// burst: 500
+///
+/// # If set, each combination of target and level is limited separately.
+/// # Defaults to `false`.
+/// per_key: false
+/// ```
+#[cfg(feature = "file")]
+pub struct RateLimitFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for RateLimitFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = RateLimitFilterConfig;
+
+    fn deserialize(&self,
+                   config: RateLimitFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        if config.rate.is_nan() || config.rate <= 0. {
+            return Err("`rate` must be positive".into());
+        }
+        let burst = config.burst.unwrap_or_else(|| config.rate.ceil() as u32).max(1);
+        let filter = if config.per_key {
+            RateLimitFilter::per_key(config.rate, burst)
+        } else {
+            RateLimitFilter::new(config.rate, burst)
+        };
+        Ok(Box::new(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    use filter::rejected;
+
+    #[test]
+    fn bucket() {
+        let filter = RateLimitFilter::new(10., 3);
+        let start = Instant::now();
+        assert!(filter.take(None, start));
+        assert!(filter.take(None, start));
+        assert!(filter.take(None, start));
+        assert!(!filter.take(None, start));
+
+        // refills at 10 per second, up to the burst size
+        assert!(filter.take(None, start + Duration::from_millis(100)));
+        assert!(!filter.take(None, start + Duration::from_millis(150)));
+        let later = start + Duration::from_secs(10);
+        for _ in 0..3 {
+            assert!(filter.take(None, later));
+        }
+        assert!(!filter.take(None, later));
+    }
+
+    #[test]
+    fn per_key() {
+        let filter = RateLimitFilter::per_key(1., 1);
+        let now = Instant::now();
+        let key = |target: &str, level: Level| Some((target.to_owned(), level as usize));
+        assert!(filter.take(key("a", Level::Info), now));
+        assert!(!filter.take(key("a", Level::Info), now));
+        assert!(filter.take(key("a", Level::Warn), now));
+        assert!(filter.take(key("b", Level::Info), now));
+    }
+    #[test]
+    fn records() {
+        // slow enough not to refill while the test runs
+        let filter = RateLimitFilter::per_key(0.001, 2);
+        let record = |target, level| Record::builder().target(target).level(level).build();
+        assert!(!rejected(&filter, &record("a", Level::Info)));
+        assert!(!rejected(&filter, &record("a", Level::Info)));
+        assert!(rejected(&filter, &record("a", Level::Info)));
+        assert!(!rejected(&filter, &record("a", Level::Warn)));
+        assert!(!rejected(&filter, &record("b", Level::Info)));
+
+        let filter = RateLimitFilter::new(0.001, 1);
+        assert!(!rejected(&filter, &record("a", Level::Info)));
+        assert!(rejected(&filter, &record("b", Level::Warn)));
+    }
+}
@@ -0,0 +1,278 @@
+//! The schedule filter.
+//!
+//! Requires the `schedule_filter` feature.
+
+use chrono::{Datelike, Local, Timelike, UTC};
+use log::Record;
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const ALL_DAYS: u8 = 0x7f;
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// The schedule filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleFilterConfig {
+    windows: Vec<WindowConfig>,
+    #[serde(default)]
+    utc: bool,
+}
+
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WindowConfig {
+    days: Option<String>,
+    start: String,
+    end: String,
+}
+
+fn parse_day(day: &str) -> Result<u32, Box<Error + Sync + Send>> {
+    let lower = day.trim().to_lowercase();
+    DAYS.iter()
+        .position(|d| lower.starts_with(d))
+        .map(|d| d as u32)
+        .ok_or_else(|| format!("invalid day `{}`", day).into())
+}
+
+fn parse_time(time: &str) -> Result<u32, Box<Error + Sync + Send>> {
+    let invalid = || format!("invalid time `{}`, expected `HH:MM`", time);
+    let mut parts = time.trim().splitn(2, ':');
+    let hour = parts.next().and_then(|h| h.parse::<u32>().ok()).ok_or_else(invalid)?;
+    let minute = parts.next().and_then(|m| m.parse::<u32>().ok()).ok_or_else(invalid)?;
+    // 24:00 is allowed as the end of the day
+    if minute >= 60 || hour * 60 + minute > MINUTES_PER_DAY {
+        return Err(invalid().into());
+    }
+    Ok(hour * 60 + minute)
+}
+
+/// A time window during which a `ScheduleFilter` accepts events.
+#[derive(Debug, Clone)]
+pub struct Window {
+    // bit 0 is Monday
+    days: u8,
+    start: u32,
+    end: u32,
+}
+
+impl Window {
+    /// Creates a window from a start time to an end time every day.
+    ///
+    /// Times are formatted as `HH:MM`. The start is inclusive and the end
+    /// exclusive. If the end is before the start, the window extends past
+    /// midnight, so `22:00` to `06:00` covers the night.
+    pub fn new(start: &str, end: &str) -> Result<Window, Box<Error + Sync + Send>> {
+        Ok(Window {
+            days: ALL_DAYS,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+
+    /// Restricts the window to start only on certain days of the week.
+    ///
+    /// Days are given as a comma separated list of day names or ranges of
+    /// them, like `mon-fri` or `sat,sun`. Only the first three letters of each
+    /// name are significant.
+    pub fn days(mut self, days: &str) -> Result<Window, Box<Error + Sync + Send>> {
+        let mut mask = 0;
+        for part in days.split(',') {
+            let mut range = part.splitn(2, '-');
+            let first = parse_day(range.next().unwrap_or(""))?;
+            let last = match range.next() {
+                Some(last) => parse_day(last)?,
+                None => first,
+            };
+            let mut day = first;
+            loop {
+                mask |= 1 << day;
+                if day == last {
+                    break;
+                }
+                day = (day + 1) % 7;
+            }
+        }
+        self.days = mask;
+        Ok(self)
+    }
+
+    // `day` counts from Monday and `minute` from midnight
+    fn contains(&self, day: u32, minute: u32) -> bool {
+        let on = |day: u32| self.days & (1 << day) != 0;
+        if self.start <= self.end {
+            on(day) && self.start <= minute && minute < self.end
+        } else {
+            // the part after midnight belongs to the window starting the day
+            // before
+            (on(day) && minute >= self.start) || (on((day + 6) % 7) && minute < self.end)
+        }
+    }
+}
+
+/// A filter that rejects all events outside of a set of time windows.
+///
+/// This can be used to enable verbose diagnostics only during business hours
+/// or a nightly maintenance window without changing the configuration. Events
+/// logged within a window pass through to later filters.
+#[derive(Debug)]
+pub struct ScheduleFilter {
+    windows: Vec<Window>,
+    utc: bool,
+}
+
+impl ScheduleFilter {
+    /// Creates a new `ScheduleFilter` accepting events during the specified
+    /// windows of local time.
+    pub fn new(windows: Vec<Window>) -> ScheduleFilter {
+        ScheduleFilter {
+            windows: windows,
+            utc: false,
+        }
+    }
+
+    /// Creates a new `ScheduleFilter` accepting events during the specified
+    /// windows of UTC time.
+    pub fn utc(windows: Vec<Window>) -> ScheduleFilter {
+        ScheduleFilter {
+            windows: windows,
+            utc: true,
+        }
+    }
+
+    fn response(&self, day: u32, minute: u32) -> Response {
+        if self.windows.iter().any(|w| w.contains(day, minute)) {
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+impl Filter for ScheduleFilter {
+    fn filter(&self, _: &Record) -> Response {
+        let (day, minute) = if self.utc {
+            let now = UTC::now();
+            (now.weekday().num_days_from_monday(), now.hour() * 60 + now.minute())
+        } else {
+            let now = Local::now();
+            (now.weekday().num_days_from_monday(), now.hour() * 60 + now.minute())
+        };
+        self.response(day, minute)
+    }
+}
+
+/// A deserializer for the `ScheduleFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: schedule
+///
+/// # The windows during which events are accepted. Required.
+/// windows:
+///
+///     # The days of the week on which the window starts, as a comma
+///     # separated list of names or ranges of them. Defaults to every day.
+///   - days: mon-fri
+///
+///     # The time at which the window starts, as `HH:MM`. Required.
+///     start: "09:00"
+///
+///     # The time at which the window ends, as `HH:MM`. If it is before the
+///     # start, the window ends the next day. Required.
+///     end: "17:30"
+///
+///   - start: "22:00"
+///     end: "02:00"
+///
+/// # If set, windows are in UTC rather than local time. Defaults to `false`.
+/// utc: false
+/// ```
+#[cfg(feature = "file")]
+pub struct ScheduleFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for ScheduleFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = ScheduleFilterConfig;
+
+    fn deserialize(&self,
+                   config: ScheduleFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let mut windows = vec![];
+        for window in config.windows {
+            let mut built = Window::new(&window.start, &window.end)?;
+            if let Some(days) = window.days {
+                built = built.days(&days)?;
+            }
+            windows.push(built);
+        }
+
+        if config.utc {
+            Ok(Box::new(ScheduleFilter::utc(windows)))
+        } else {
+            Ok(Box::new(ScheduleFilter::new(windows)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MON: u32 = 0;
+    const FRI: u32 = 4;
+    const SAT: u32 = 5;
+    const SUN: u32 = 6;
+
+    fn time(hour: u32, minute: u32) -> u32 {
+        hour * 60 + minute
+    }
+
+    #[test]
+    fn business_hours() {
+        let window = Window::new("09:00", "17:30").unwrap().days("mon-fri").unwrap();
+        assert!(window.contains(MON, time(9, 0)));
+        assert!(window.contains(FRI, time(17, 29)));
+        assert!(!window.contains(FRI, time(17, 30)));
+        assert!(!window.contains(MON, time(8, 59)));
+        assert!(!window.contains(SAT, time(12, 0)));
+    }
+
+    #[test]
+    fn overnight() {
+        let window = Window::new("22:00", "02:00").unwrap().days("fri").unwrap();
+        assert!(window.contains(FRI, time(23, 0)));
+        assert!(window.contains(SAT, time(1, 0)));
+        assert!(!window.contains(FRI, time(1, 0)));
+        assert!(!window.contains(SAT, time(23, 0)));
+
+        let window = Window::new("22:00", "06:00").unwrap().days("sun").unwrap();
+        assert!(window.contains(MON, time(5, 0)));
+    }
+
+    #[test]
+    fn parse() {
+        let window = Window::new("00:00", "24:00").unwrap().days("Sat, sun-mon").unwrap();
+        assert_eq!(window.days, 1 << SAT | 1 << SUN | 1 << MON);
+        assert!(Window::new("9", "17:00").is_err());
+        assert!(Window::new("09:60", "17:00").is_err());
+        assert!(Window::new("09:00", "24:01").is_err());
+        assert!(Window::new("09:00", "17:00").unwrap().days("someday").is_err());
+
+        let filter = ScheduleFilter::new(vec![window]);
+        match filter.response(FRI, time(12, 0)) {
+            Response::Reject => {}
+            _ => panic!("expected reject"),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! The script filter.
+//!
+//! Requires the `script_filter` feature.
+
+use log::Record;
+use log_mdc;
+use rhai::{AST, Dynamic, Engine, Scope};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::thread;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The script filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptFilterConfig {
+    script: String,
+    max_operations: Option<u64>,
+}
+
+/// A filter whose decision is made by a [Rhai](https://rhai.rs) script.
+///
+/// The script is run for each event with the following variables in scope:
+///
+/// * `level` - The event's level, like `"INFO"`.
+/// * `target` - The event's target.
+/// * `module` - The module which logged the event, or `()` if it isn't known.
+/// * `file` - The source file which logged the event, or `()` if it isn't
+///     known.
+/// * `line` - The source line which logged the event, or `()` if it isn't
+///     known.
+/// * `thread` - The name of the thread which logged the event, or `()` if it
+///     is unnamed.
+/// * `message` - The event's formatted message.
+///
+/// The function `mdc(key)` returns the value of the MDC entry with the
+/// specified key, or `()` if there is none.
+///
+/// The script's result determines the response. `true` lets the event pass
+/// through to later filters, and `false` rejects it. The strings `"accept"`,
+/// `"neutral"`, and `"reject"` select a response directly. If the script
+/// fails, or produces any other value, the error is reported and the event
+/// passes through.
+///
+/// Scripts are limited to a number of operations per event, 100,000 by
+/// default, to keep a bad rule from hanging the logging thread.
+pub struct ScriptFilter {
+    engine: Engine,
+    ast: AST,
+}
+
+impl fmt::Debug for ScriptFilter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ScriptFilter").finish()
+    }
+}
+
+impl ScriptFilter {
+    /// Compiles the specified script into a new `ScriptFilter`.
+    pub fn new(script: &str) -> Result<ScriptFilter, Box<Error + Sync + Send>> {
+        ScriptFilter::with_max_operations(script, 100_000)
+    }
+
+    /// Like `new`, but limits the script to the specified number of
+    /// operations per event.
+    pub fn with_max_operations(script: &str,
+                               max_operations: u64)
+                               -> Result<ScriptFilter, Box<Error + Sync + Send>> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(max_operations);
+        engine.register_fn("mdc", |key: &str| -> Dynamic {
+            log_mdc::get(key, |v| match v {
+                Some(v) => v.into(),
+                None => Dynamic::UNIT,
+            })
+        });
+        let ast = engine.compile(script)
+            .map_err(|e| format!("error compiling filter script: {}", e))?;
+        Ok(ScriptFilter {
+            engine: engine,
+            ast: ast,
+        })
+    }
+
+    fn response(&self, mut scope: Scope) -> Response {
+        let result = match self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast) {
+            Ok(result) => result,
+            Err(e) => {
+                report(format!("error running filter script: {}", e));
+                return Response::Neutral;
+            }
+        };
+
+        if let Ok(pass) = result.as_bool() {
+            return if pass {
+                Response::Neutral
+            } else {
+                Response::Reject
+            };
+        }
+        match result.into_string().as_ref().map(|s| &**s) {
+            Ok("accept") => Response::Accept,
+            Ok("neutral") => Response::Neutral,
+            Ok("reject") => Response::Reject,
+            _ => {
+                report("filter script must produce a bool, \"accept\", \"neutral\", or \
+                        \"reject\""
+                    .to_owned());
+                Response::Neutral
+            }
+        }
+    }
+}
+
+fn report(message: String) {
+    ::handle_error(&io::Error::other(message));
+}
+
+impl Filter for ScriptFilter {
+    fn filter(&self, record: &Record) -> Response {
+        let mut scope = Scope::new();
+        scope.push("level", record.level().to_string());
+        scope.push("target", record.target().to_owned());
+        match record.module_path() {
+            Some(module_path) => scope.push("module", module_path.to_owned()),
+            None => scope.push("module", ()),
+        };
+        match record.file() {
+            Some(file) => scope.push("file", file.to_owned()),
+            None => scope.push("file", ()),
+        };
+        match record.line() {
+            Some(line) => scope.push("line", line as i64),
+            None => scope.push("line", ()),
+        };
+        match thread::current().name() {
+            Some(name) => scope.push("thread", name.to_owned()),
+            None => scope.push("thread", ()),
+        };
+        scope.push("message", record.args().to_string());
+        self.response(scope)
+    }
+}
+
+/// A deserializer for the `ScriptFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: script
+///
+/// # The Rhai script deciding the response to each event. Required.
+/// script: |
+///   if target.starts_with("myapp::payments") && mdc("tenant") == "acme" {
+///     "accept"
+///   } else {
+///     level != "TRACE"
+///   }
+///
+/// # The maximum number of operations the script may perform per event.
+/// # Defaults to 100000.
+/// max_operations: 100000
+/// ```
+#[cfg(feature = "file")]
+pub struct ScriptFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for ScriptFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = ScriptFilterConfig;
+
+    fn deserialize(&self,
+                   config: ScriptFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let filter = match config.max_operations {
+            Some(max_operations) => {
+                ScriptFilter::with_max_operations(&config.script, max_operations)?
+            }
+            None => ScriptFilter::new(&config.script)?,
+        };
+        Ok(Box::new(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rhai::Scope;
+
+    use super::*;
+
+    fn response(filter: &ScriptFilter, level: &str, target: &str) -> Response {
+        let mut scope = Scope::new();
+        scope.push("level", level.to_owned());
+        scope.push("target", target.to_owned());
+        filter.response(scope)
+    }
+
+    #[test]
+    fn responses() {
+        let filter = ScriptFilter::new(r#"
+            if target.starts_with("audit") {
+                "accept"
+            } else {
+                level != "TRACE"
+            }
+        "#)
+            .unwrap();
+        match response(&filter, "TRACE", "audit::login") {
+            Response::Accept => {}
+            _ => panic!("expected accept"),
+        }
+        match response(&filter, "TRACE", "myapp") {
+            Response::Reject => {}
+            _ => panic!("expected reject"),
+        }
+        match response(&filter, "INFO", "myapp") {
+            Response::Neutral => {}
+            _ => panic!("expected neutral"),
+        }
+    }
+
+    #[test]
+    fn mdc() {
+        let filter = ScriptFilter::new(r#"mdc("script_filter_tenant") == "acme""#).unwrap();
+        log_mdc::insert("script_filter_tenant", "acme");
+        match response(&filter, "INFO", "myapp") {
+            Response::Neutral => {}
+            _ => panic!("expected neutral"),
+        }
+        log_mdc::remove("script_filter_tenant");
+        match response(&filter, "INFO", "myapp") {
+            Response::Reject => {}
+            _ => panic!("expected reject"),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(ScriptFilter::new("if {").is_err());
+
+        // runaway scripts are stopped and the event passes through
+        let filter = ScriptFilter::with_max_operations("loop {}", 1000).unwrap();
+        match response(&filter, "INFO", "myapp") {
+            Response::Neutral => {}
+            _ => panic!("expected neutral"),
+        }
+    }
+}
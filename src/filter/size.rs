@@ -0,0 +1,151 @@
+//! The message size filter.
+//!
+//! Requires the `size_filter` feature.
+
+use log::Record;
+#[cfg(feature = "file")]
+use std::convert::TryFrom;
+#[cfg(feature = "file")]
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::io;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The message size filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SizeFilterConfig {
+    #[serde(deserialize_with = "::priv_serde::de_size")]
+    max_bytes: u64,
+    #[serde(default)]
+    report_only: bool,
+}
+
+struct Counter {
+    len: usize,
+    max: usize,
+}
+
+impl fmt::Write for Counter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.len += s.len();
+        // stop formatting as soon as the limit is passed
+        if self.len > self.max {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn exceeds(args: fmt::Arguments, max: usize) -> bool {
+    let mut counter = Counter { len: 0, max: max };
+    counter.write_fmt(args).is_err()
+}
+
+/// A filter that rejects log events whose formatted message is longer than a
+/// number of bytes.
+///
+/// This guards every appender against runaway messages, like a serialized
+/// multi-megabyte payload, without truncating messages in each encoder.
+/// Messages are only formatted as far as the limit. In report-only mode,
+/// oversized events are reported as errors but still pass through. Events
+/// within the limit pass through to later filters.
+#[derive(Debug)]
+pub struct SizeFilter {
+    max_bytes: usize,
+    report_only: bool,
+}
+
+impl SizeFilter {
+    /// Creates a new `SizeFilter` rejecting messages longer than `max_bytes`.
+    pub fn new(max_bytes: usize) -> SizeFilter {
+        SizeFilter {
+            max_bytes: max_bytes,
+            report_only: false,
+        }
+    }
+
+    /// Like `new`, but reports oversized messages rather than rejecting them.
+    pub fn report_only(max_bytes: usize) -> SizeFilter {
+        SizeFilter {
+            max_bytes: max_bytes,
+            report_only: true,
+        }
+    }
+}
+
+impl Filter for SizeFilter {
+    fn filter(&self, record: &Record) -> Response {
+        if !exceeds(*record.args(), self.max_bytes) {
+            return Response::Neutral;
+        }
+
+        if self.report_only {
+            let e = io::Error::other(format!("log message from {}:{} exceeds {} bytes",
+                                             record.file().unwrap_or("???"),
+                                             record.line().unwrap_or(0),
+                                             self.max_bytes));
+            ::handle_error(&e);
+            Response::Neutral
+        } else {
+            Response::Reject
+        }
+    }
+}
+
+/// A deserializer for the `SizeFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: size
+///
+/// # The maximum length of a formatted message, in the same format as the
+/// # size trigger's limit. Required.
+/// max_bytes: 64 kb
+///
+/// # If set, oversized events are reported as errors but not rejected.
+/// # Defaults to `false`.
+/// report_only: false
+/// ```
+#[cfg(feature = "file")]
+pub struct SizeFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for SizeFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = SizeFilterConfig;
+
+    fn deserialize(&self,
+                   config: SizeFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let max_bytes = usize::try_from(config.max_bytes).unwrap_or(usize::MAX);
+        let filter = if config.report_only {
+            SizeFilter::report_only(max_bytes)
+        } else {
+            SizeFilter::new(max_bytes)
+        };
+        Ok(Box::new(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert!(!exceeds(format_args!("hello"), 5));
+        assert!(exceeds(format_args!("hello!"), 5));
+        assert!(!exceeds(format_args!("{} {}", "héllo", 42), 9));
+        assert!(exceeds(format_args!("{} {}", "héllo", 42), 8));
+        assert!(exceeds(format_args!("{}", "x".repeat(1 << 20)), 1024));
+    }
+}
@@ -0,0 +1,194 @@
+//! The target filter.
+//!
+//! Requires the `target_filter` feature.
+
+use log::Record;
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+use filter::name::Pattern;
+
+/// The target filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetFilterConfig {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+/// A filter that selects log events by their target or module path.
+///
+/// A pattern ending in `*` matches any name starting with the rest of the
+/// pattern, and `::*` additionally matches the module itself, so
+/// `myapp::payments::*` matches both `myapp::payments` and
+/// `myapp::payments::stripe`. Other patterns match the name exactly.
+///
+/// Events matching a deny pattern are rejected. If any allow patterns are
+/// configured, events matching none of them are rejected as well. All other
+/// events pass through to later filters.
+#[derive(Debug)]
+pub struct TargetFilter {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+impl TargetFilter {
+    /// Creates a new `TargetFilter` builder.
+    pub fn builder() -> TargetFilterBuilder {
+        TargetFilterBuilder {
+            allow: vec![],
+            deny: vec![],
+        }
+    }
+
+    fn response(&self, target: &str, module_path: &str) -> Response {
+        let matches = |patterns: &[Pattern]| {
+            patterns.iter().any(|p| p.matches(target) || p.matches(module_path))
+        };
+
+        if matches(&self.deny) || (!self.allow.is_empty() && !matches(&self.allow)) {
+            Response::Reject
+        } else {
+            Response::Neutral
+        }
+    }
+}
+
+impl Filter for TargetFilter {
+    fn filter(&self, record: &Record) -> Response {
+        let target = record.target();
+        self.response(target, record.module_path().unwrap_or(target))
+    }
+}
+
+/// A builder for `TargetFilter`s.
+pub struct TargetFilterBuilder {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+impl TargetFilterBuilder {
+    /// Adds a pattern to the accept-list.
+    pub fn allow(mut self, pattern: &str) -> TargetFilterBuilder {
+        self.allow.push(Pattern::new(pattern));
+        self
+    }
+
+    /// Adds a pattern to the deny-list.
+    pub fn deny(mut self, pattern: &str) -> TargetFilterBuilder {
+        self.deny.push(Pattern::new(pattern));
+        self
+    }
+
+    /// Consumes the `TargetFilterBuilder`, producing a `TargetFilter`.
+    pub fn build(self) -> TargetFilter {
+        TargetFilter {
+            allow: self.allow,
+            deny: self.deny,
+        }
+    }
+}
+
+/// A deserializer for the `TargetFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: target
+///
+/// # Patterns of targets or module paths to accept. If empty, all events not
+/// # denied are accepted. Defaults to empty.
+/// allow:
+///   - myapp::payments::*
+///
+/// # Patterns of targets or module paths to reject. Defaults to empty.
+/// deny:
+///   - myapp::payments::audit
+/// ```
+#[cfg(feature = "file")]
+pub struct TargetFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for TargetFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = TargetFilterConfig;
+
+    fn deserialize(&self,
+                   config: TargetFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let mut filter = TargetFilter::builder();
+        for pattern in &config.allow {
+            filter = filter.allow(pattern);
+        }
+        for pattern in &config.deny {
+            filter = filter.deny(pattern);
+        }
+        Ok(Box::new(filter.build()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use filter::rejected;
+
+    fn record(target: &str) -> Record<'_> {
+        Record::builder().target(target).build()
+    }
+
+    #[test]
+    fn patterns() {
+        let module = Pattern::new("myapp::payments::*");
+        assert!(module.matches("myapp::payments"));
+        assert!(module.matches("myapp::payments::stripe"));
+        assert!(!module.matches("myapp::paymentsx"));
+        assert!(!module.matches("myapp"));
+
+        let prefix = Pattern::new("myapp::pay*");
+        assert!(prefix.matches("myapp::payments"));
+        assert!(prefix.matches("myapp::payroll"));
+
+        let exact = Pattern::new("myapp");
+        assert!(exact.matches("myapp"));
+        assert!(!exact.matches("myapp::payments"));
+    }
+
+    #[test]
+    fn allow_deny() {
+        let filter = TargetFilter::builder()
+            .allow("myapp::payments::*")
+            .deny("myapp::payments::audit")
+            .build();
+        assert!(!rejected(&filter, &record("myapp::payments::stripe")));
+        assert!(rejected(&filter, &record("myapp::payments::audit")));
+        assert!(rejected(&filter, &record("myapp::users")));
+        assert!(rejected(&filter, &record("hyper")));
+
+        let filter = TargetFilter::builder().deny("hyper::*").build();
+        assert!(rejected(&filter, &record("hyper::client")));
+        assert!(!rejected(&filter, &record("myapp")));
+
+        // a target set explicitly is matched alongside the module path
+        let filter = TargetFilter::builder().allow("audit").build();
+        let record = Record::builder()
+            .target("audit")
+            .module_path(Some("myapp::payments"))
+            .build();
+        assert!(!rejected(&filter, &record));
+        let record = Record::builder()
+            .target("myapp::payments")
+            .module_path(Some("audit"))
+            .build();
+        assert!(!rejected(&filter, &record));
+        assert!(rejected(&filter, &Record::builder().target("myapp::payments").build()));
+    }
+}
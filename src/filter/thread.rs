@@ -0,0 +1,175 @@
+//! The thread name filter.
+//!
+//! Requires the `thread_filter` feature.
+
+use log::Record;
+#[cfg(feature = "file")]
+use std::error::Error;
+use std::thread;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use filter::{Filter, Response};
+
+/// The thread name filter's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThreadFilterConfig {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+/// A filter that selects log events by the name of the thread logging them.
+///
+/// Patterns are matched against the whole name, where `*` matches any
+/// sequence of characters and `?` matches any single character, so
+/// `tokio-runtime-worker*` matches all of Tokio's worker threads. A pattern
+/// without either matches the name exactly. Unnamed threads match no
+/// patterns.
+///
+/// Events matching a deny pattern are rejected. If any allow patterns are
+/// configured, events matching none of them are rejected as well. All other
+/// events pass through to later filters.
+#[derive(Debug)]
+pub struct ThreadFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl ThreadFilter {
+    /// Creates a new `ThreadFilter` builder.
+    pub fn builder() -> ThreadFilterBuilder {
+        ThreadFilterBuilder {
+            allow: vec![],
+            deny: vec![],
+        }
+    }
+
+    fn response(&self, name: Option<&str>) -> Response {
+        let matches = |patterns: &[String]| match name {
+            Some(name) => patterns.iter().any(|p| ::glob_matches(p, name)),
+            None => false,
+        };
+
+        if matches(&self.deny) || (!self.allow.is_empty() && !matches(&self.allow)) {
+            Response::Reject
+        } else {
+            Response::Neutral
+        }
+    }
+}
+
+impl Filter for ThreadFilter {
+    fn filter(&self, _: &Record) -> Response {
+        self.response(thread::current().name())
+    }
+}
+
+/// A builder for `ThreadFilter`s.
+pub struct ThreadFilterBuilder {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl ThreadFilterBuilder {
+    /// Adds a pattern to the accept-list.
+    pub fn allow(mut self, pattern: &str) -> ThreadFilterBuilder {
+        self.allow.push(pattern.to_owned());
+        self
+    }
+
+    /// Adds a pattern to the deny-list.
+    pub fn deny(mut self, pattern: &str) -> ThreadFilterBuilder {
+        self.deny.push(pattern.to_owned());
+        self
+    }
+
+    /// Consumes the `ThreadFilterBuilder`, producing a `ThreadFilter`.
+    pub fn build(self) -> ThreadFilter {
+        ThreadFilter {
+            allow: self.allow,
+            deny: self.deny,
+        }
+    }
+}
+
+/// A deserializer for the `ThreadFilter`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: thread
+///
+/// # Patterns of thread names to accept. If empty, all events not denied are
+/// # accepted. Defaults to empty.
+/// allow:
+///   - tokio-runtime-worker*
+///   - billing-job
+///
+/// # Patterns of thread names to reject. Defaults to empty.
+/// deny:
+///   - tokio-runtime-worker-?
+/// ```
+#[cfg(feature = "file")]
+pub struct ThreadFilterDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for ThreadFilterDeserializer {
+    type Trait = Filter;
+
+    type Config = ThreadFilterConfig;
+
+    fn deserialize(&self,
+                   config: ThreadFilterConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let mut filter = ThreadFilter::builder();
+        for pattern in &config.allow {
+            filter = filter.allow(pattern);
+        }
+        for pattern in &config.deny {
+            filter = filter.deny(pattern);
+        }
+        Ok(Box::new(filter.build()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use filter;
+
+    // whether the filter rejects an event logged on a thread with the name
+    fn rejected(filter: &ThreadFilter, name: Option<&str>) -> bool {
+        let builder = match name {
+            Some(name) => thread::Builder::new().name(name.to_owned()),
+            None => thread::Builder::new(),
+        };
+        thread::scope(|s| {
+            builder.spawn_scoped(s, || filter::rejected(filter, &Record::builder().build()))
+                .unwrap()
+                .join()
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn allow_deny() {
+        let filter = ThreadFilter::builder()
+            .allow("tokio-runtime-worker*")
+            .deny("tokio-runtime-worker-0")
+            .build();
+        assert!(!rejected(&filter, Some("tokio-runtime-worker-1")));
+        assert!(rejected(&filter, Some("tokio-runtime-worker-0")));
+        assert!(rejected(&filter, Some("main")));
+        assert!(rejected(&filter, None));
+
+        let filter = ThreadFilter::builder().deny("noisy*").build();
+        assert!(rejected(&filter, Some("noisy-poller")));
+        assert!(!rejected(&filter, None));
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! Requires the `threshold_filter` feature.
 
-use log::{LogLevelFilter, LogRecord};
+use log::{LevelFilter, Record};
 #[cfg(feature = "file")]
 use std::error::Error;
 
@@ -15,30 +15,34 @@ use filter::{Filter, Response};
 #[derive(Deserialize)]
 pub struct ThresholdFilterConfig {
     #[serde(deserialize_with = "::priv_serde::de_filter")]
-    level: LogLevelFilter,
+    level: LevelFilter,
 }
 
 /// A filter that rejects all events at a level below a provided threshold.
 #[derive(Debug)]
 pub struct ThresholdFilter {
-    level: LogLevelFilter,
+    level: LevelFilter,
 }
 
 impl ThresholdFilter {
     /// Creates a new `ThresholdFilter` with the specified threshold.
-    pub fn new(level: LogLevelFilter) -> ThresholdFilter {
+    pub fn new(level: LevelFilter) -> ThresholdFilter {
         ThresholdFilter { level: level }
     }
 }
 
 impl Filter for ThresholdFilter {
-    fn filter(&self, record: &LogRecord) -> Response {
+    fn filter(&self, record: &Record) -> Response {
         if record.level() > self.level {
             Response::Reject
         } else {
             Response::Neutral
         }
     }
+
+    fn max_level(&self) -> LevelFilter {
+        self.level
+    }
 }
 
 /// A deserializer for the `ThresholdFilter`.
@@ -0,0 +1,119 @@
+//! The fields interceptor.
+//!
+//! Requires the `fields_interceptor` feature.
+
+use std::collections::BTreeMap;
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use intercept::{Interceptor, Record};
+
+/// The fields interceptor's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldsInterceptorConfig {
+    fields: BTreeMap<String, String>,
+    #[serde(default = "overwrite_default")]
+    overwrite: bool,
+}
+
+#[cfg(feature = "file")]
+fn overwrite_default() -> bool {
+    true
+}
+
+/// An interceptor which adds fixed fields to every event.
+#[derive(Debug)]
+pub struct FieldsInterceptor {
+    fields: Vec<(String, String)>,
+    overwrite: bool,
+}
+
+impl FieldsInterceptor {
+    /// Creates a new `FieldsInterceptor` adding the specified fields.
+    pub fn new<I>(fields: I) -> FieldsInterceptor
+        where I: IntoIterator<Item = (String, String)>
+    {
+        FieldsInterceptor {
+            fields: fields.into_iter().collect::<BTreeMap<_, _>>().into_iter().collect(),
+            overwrite: true,
+        }
+    }
+
+    /// Sets whether the fields replace fields with the same keys added by
+    /// earlier interceptors.
+    ///
+    /// Defaults to `true`.
+    pub fn overwrite(mut self, overwrite: bool) -> FieldsInterceptor {
+        self.overwrite = overwrite;
+        self
+    }
+}
+
+impl Interceptor for FieldsInterceptor {
+    fn intercept(&self, record: &mut Record) {
+        for (key, value) in &self.fields {
+            if self.overwrite || record.field(key).is_none() {
+                record.insert_field(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// A deserializer for the `FieldsInterceptor`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: fields
+///
+/// # The fields to add. Required.
+/// fields:
+///   service: billing
+///   region: ${REGION}
+///
+/// # Whether to replace fields with the same keys added by earlier
+/// # interceptors. Defaults to true.
+/// overwrite: true
+/// ```
+#[cfg(feature = "file")]
+pub struct FieldsInterceptorDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for FieldsInterceptorDeserializer {
+    type Trait = Interceptor;
+
+    type Config = FieldsInterceptorConfig;
+
+    fn deserialize(&self,
+                   config: FieldsInterceptorConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Interceptor>, Box<Error + Sync + Send>> {
+        Ok(Box::new(FieldsInterceptor::new(config.fields).overwrite(config.overwrite)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+
+    use super::*;
+    use RecordPrivateExt;
+
+    #[test]
+    fn overwrite() {
+        let mut record = Record::new(Level::Info, "foo".to_owned(), "hello".to_owned());
+        record.insert_field("a".to_owned(), "old".to_owned());
+
+        let fields = || vec![("a".to_owned(), "new".to_owned()), ("b".to_owned(), "1".to_owned())];
+        FieldsInterceptor::new(fields()).overwrite(false).intercept(&mut record);
+        assert_eq!(record.field("a"), Some("old"));
+        assert_eq!(record.field("b"), Some("1"));
+
+        FieldsInterceptor::new(fields()).intercept(&mut record);
+        assert_eq!(record.field("a"), Some("new"));
+    }
+}
@@ -0,0 +1,145 @@
+//! The level interceptor.
+//!
+//! Requires the `level_interceptor` feature.
+
+use log::Level;
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use intercept::{Interceptor, Record};
+
+/// The level interceptor's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelInterceptorConfig {
+    target: Option<String>,
+    from: Option<String>,
+    level: String,
+}
+
+/// An interceptor which changes the level of events, like to demote the
+/// errors of a noisy dependency to warnings.
+#[derive(Debug)]
+pub struct LevelInterceptor {
+    target: Option<String>,
+    from: Option<Level>,
+    level: Level,
+}
+
+impl LevelInterceptor {
+    /// Creates a new `LevelInterceptor` which sets the level of every event
+    /// to `level`.
+    pub fn new(level: Level) -> LevelInterceptor {
+        LevelInterceptor {
+            target: None,
+            from: None,
+            level: level,
+        }
+    }
+
+    /// Only changes events whose target is the specified target or is
+    /// nested inside it, like `hyper` for `hyper::client`.
+    pub fn target(mut self, target: String) -> LevelInterceptor {
+        self.target = Some(target);
+        self
+    }
+
+    /// Only changes events at the specified level.
+    pub fn from(mut self, level: Level) -> LevelInterceptor {
+        self.from = Some(level);
+        self
+    }
+
+    fn matches(&self, record: &Record) -> bool {
+        if self.from.is_some_and(|from| from != record.level()) {
+            return false;
+        }
+        match self.target {
+            Some(ref target) => {
+                match record.target().strip_prefix(&**target) {
+                    Some(rest) => rest.is_empty() || rest.starts_with("::"),
+                    None => false,
+                }
+            }
+            None => true,
+        }
+    }
+}
+
+impl Interceptor for LevelInterceptor {
+    fn intercept(&self, record: &mut Record) {
+        if self.matches(record) {
+            record.set_level(self.level);
+        }
+    }
+}
+
+/// A deserializer for the `LevelInterceptor`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: level
+///
+/// # The level events are changed to. Required.
+/// level: warn
+///
+/// # If set, only events whose target is this target or nested inside it
+/// # are changed.
+/// target: hyper
+///
+/// # If set, only events at this level are changed.
+/// from: error
+/// ```
+#[cfg(feature = "file")]
+pub struct LevelInterceptorDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for LevelInterceptorDeserializer {
+    type Trait = Interceptor;
+
+    type Config = LevelInterceptorConfig;
+
+    fn deserialize(&self,
+                   config: LevelInterceptorConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Interceptor>, Box<Error + Sync + Send>> {
+        let parse = |level: &str| {
+            level.parse::<Level>().map_err(|_| format!("invalid level `{}`", level))
+        };
+        let mut interceptor = LevelInterceptor::new(parse(&config.level)?);
+        if let Some(target) = config.target {
+            interceptor = interceptor.target(target);
+        }
+        if let Some(from) = config.from {
+            interceptor = interceptor.from(parse(&from)?);
+        }
+        Ok(Box::new(interceptor))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use RecordPrivateExt;
+
+    #[test]
+    fn matches() {
+        let interceptor = LevelInterceptor::new(Level::Warn)
+            .target("hyper".to_owned())
+            .from(Level::Error);
+        let level = |level, target: &str| {
+            let mut record = Record::new(level, target.to_owned(), String::new());
+            interceptor.intercept(&mut record);
+            record.level()
+        };
+
+        assert_eq!(level(Level::Error, "hyper"), Level::Warn);
+        assert_eq!(level(Level::Error, "hyper::client"), Level::Warn);
+        assert_eq!(level(Level::Error, "hyperx"), Level::Error);
+        assert_eq!(level(Level::Info, "hyper"), Level::Info);
+    }
+}
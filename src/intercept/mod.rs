@@ -0,0 +1,161 @@
+//! Interceptors
+//!
+//! Interceptors modify log events before loggers, filters, and appenders
+//! see them. They are attached to the configuration as a whole rather than
+//! to an appender, so concerns like adding a request id or scrubbing
+//! sensitive data are handled once for every destination.
+//!
+//! Each event is passed through the interceptors in the order they were
+//! added. An interceptor may change the event's level or message, and may
+//! add fields, which are inserted into the MDC while the event is logged so
+//! that encoders and filters reading the MDC see them. Fields therefore have
+//! no effect unless a feature using the MDC, like `pattern_encoder`, is
+//! enabled.
+//!
+//! Interceptors only see events which pass the global maximum level, so
+//! raising the level of an event doesn't let through ones which no logger
+//! enables.
+
+use std::fmt;
+#[cfg(feature = "file")]
+use serde_value::Value;
+#[cfg(feature = "file")]
+use serde::de;
+#[cfg(feature = "file")]
+use std::collections::BTreeMap;
+use log::Level;
+
+#[cfg(feature = "file")]
+use file::Deserializable;
+use RecordPrivateExt;
+
+#[cfg(feature = "fields_interceptor")]
+pub mod fields;
+#[cfg(feature = "level_interceptor")]
+pub mod level;
+#[cfg(feature = "replace_interceptor")]
+pub mod replace;
+
+/// The trait implemented by log4rs interceptors.
+pub trait Interceptor: fmt::Debug + Send + Sync + 'static {
+    /// Modifies a log event.
+    fn intercept(&self, record: &mut Record);
+}
+
+#[cfg(feature = "file")]
+impl Deserializable for Interceptor {
+    fn name() -> &'static str {
+        "interceptor"
+    }
+}
+
+/// A log event as seen by interceptors.
+#[derive(Debug, Clone)]
+pub struct Record {
+    level: Level,
+    target: String,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Record {
+    /// Returns the level of the event.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Sets the level of the event.
+    pub fn set_level(&mut self, level: Level) {
+        self.level = level;
+    }
+
+    /// Returns the target of the event.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the message of the event.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Sets the message of the event.
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    /// Returns the fields added to the event, in the order they were added.
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// Returns the value of a field added to the event.
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|f| f.0 == key).map(|f| &*f.1)
+    }
+
+    /// Adds a field to the event, replacing any field with the same key.
+    pub fn insert_field(&mut self, key: String, value: String) {
+        match self.fields.iter_mut().find(|f| f.0 == key) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((key, value)),
+        }
+    }
+}
+
+impl RecordPrivateExt for Record {
+    fn new(level: Level, target: String, message: String) -> Record {
+        Record {
+            level: level,
+            target: target,
+            message: message,
+            fields: vec![],
+        }
+    }
+}
+
+/// Configuration for an interceptor.
+#[derive(PartialEq, Eq, Debug)]
+#[cfg(feature = "file")]
+pub struct InterceptorConfig {
+    /// The interceptor kind.
+    pub kind: String,
+    /// The interceptor configuration.
+    pub config: Value,
+}
+
+#[cfg(feature = "file")]
+impl<'de> de::Deserialize<'de> for InterceptorConfig {
+    fn deserialize<D>(d: D) -> Result<InterceptorConfig, D::Error>
+        where D: de::Deserializer<'de>
+    {
+        let mut map = BTreeMap::<Value, Value>::deserialize(d)?;
+
+        let kind = match map.remove(&Value::String("kind".to_owned())) {
+            Some(kind) => kind.deserialize_into().map_err(|e| e.to_error())?,
+            None => return Err(de::Error::missing_field("kind")),
+        };
+
+        Ok(InterceptorConfig {
+            kind: kind,
+            config: Value::Map(map),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let mut record = Record::new(Level::Info, "foo".to_owned(), "hello".to_owned());
+        record.insert_field("a".to_owned(), "1".to_owned());
+        record.insert_field("b".to_owned(), "2".to_owned());
+        record.insert_field("a".to_owned(), "3".to_owned());
+        assert_eq!(record.field("a"), Some("3"));
+        assert_eq!(record.field("c"), None);
+        assert_eq!(record.fields(),
+                   &[("a".to_owned(), "3".to_owned()), ("b".to_owned(), "2".to_owned())]);
+    }
+}
@@ -0,0 +1,110 @@
+//! The replace interceptor.
+//!
+//! Requires the `replace_interceptor` feature.
+
+use regex::Regex;
+use std::borrow::Cow;
+#[cfg(feature = "file")]
+use std::error::Error;
+
+#[cfg(feature = "file")]
+use file::{Deserialize, Deserializers};
+use intercept::{Interceptor, Record};
+
+/// The replace interceptor's configuration.
+#[cfg(feature = "file")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplaceInterceptorConfig {
+    pattern: String,
+    #[serde(default = "replacement_default")]
+    replacement: String,
+}
+
+#[cfg(feature = "file")]
+fn replacement_default() -> String {
+    "***".to_owned()
+}
+
+/// An interceptor which rewrites the parts of messages matching a regular
+/// expression, like to scrub credentials or personal data.
+#[derive(Debug)]
+pub struct ReplaceInterceptor {
+    pattern: Regex,
+    replacement: String,
+}
+
+impl ReplaceInterceptor {
+    /// Creates a new `ReplaceInterceptor` replacing every match of `pattern`
+    /// with `replacement`.
+    ///
+    /// The replacement may refer to capture groups of the pattern, as in
+    /// `Regex::replace_all`.
+    pub fn new(pattern: Regex, replacement: String) -> ReplaceInterceptor {
+        ReplaceInterceptor {
+            pattern: pattern,
+            replacement: replacement,
+        }
+    }
+}
+
+impl Interceptor for ReplaceInterceptor {
+    fn intercept(&self, record: &mut Record) {
+        let message = match self.pattern.replace_all(record.message(), &*self.replacement) {
+            Cow::Borrowed(_) => return,
+            Cow::Owned(message) => message,
+        };
+        record.set_message(message);
+    }
+}
+
+/// A deserializer for the `ReplaceInterceptor`.
+///
+/// # Configuration
+///
+/// ```yaml
+/// kind: replace
+///
+/// # The regular expression to search messages for. Required.
+/// pattern: "password=\\S+"
+///
+/// # The text matches are replaced with, which may refer to capture groups
+/// # like `$1`. Defaults to `***`.
+/// replacement: "password=***"
+/// ```
+#[cfg(feature = "file")]
+pub struct ReplaceInterceptorDeserializer;
+
+#[cfg(feature = "file")]
+impl Deserialize for ReplaceInterceptorDeserializer {
+    type Trait = Interceptor;
+
+    type Config = ReplaceInterceptorConfig;
+
+    fn deserialize(&self,
+                   config: ReplaceInterceptorConfig,
+                   _: &Deserializers)
+                   -> Result<Box<Interceptor>, Box<Error + Sync + Send>> {
+        let pattern = Regex::new(&config.pattern)?;
+        Ok(Box::new(ReplaceInterceptor::new(pattern, config.replacement)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+
+    use super::*;
+    use RecordPrivateExt;
+
+    #[test]
+    fn replace() {
+        let interceptor = ReplaceInterceptor::new(Regex::new(r"token=(\w)\w*").unwrap(),
+                                                  "token=$1***".to_owned());
+        let mut record = Record::new(Level::Info,
+                                     "foo".to_owned(),
+                                     "login token=abcdef ok".to_owned());
+        interceptor.intercept(&mut record);
+        assert_eq!(record.message(), "login token=a*** ok");
+    }
+}
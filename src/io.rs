@@ -0,0 +1,132 @@
+//! Logging through `std::io::Write`.
+//!
+//! A `LogWriter` logs each line written to it as an event, which allows
+//! output from code that only accepts a writer to go through the configured
+//! appenders:
+//!
+//! ```
+//! # extern crate log;
+//! # extern crate log4rs;
+//! # use log::Level;
+//! # use std::io::Write;
+//! # use log4rs::io::LogWriter;
+//! # fn main() {
+//! let mut writer = LogWriter::new("app::report", Level::Info);
+//! writeln!(writer, "{} rows exported", 42).unwrap();
+//! # }
+//! ```
+//!
+//! Lines may be written in any number of pieces; an event is logged once the
+//! line ending is written, and `\r\n` line endings are removed along with
+//! `\n`. Invalid UTF-8 is replaced. Flushing the writer doesn't log a
+//! partially written line, which is logged when the writer is dropped.
+
+use log::Level;
+use std::io::{self, Write};
+use std::mem;
+
+/// A writer which logs the lines written to it.
+#[derive(Debug)]
+pub struct LogWriter {
+    target: String,
+    level: Level,
+    lines: Lines,
+}
+
+impl LogWriter {
+    /// Creates a new `LogWriter` which logs lines to the specified target at
+    /// the specified level.
+    pub fn new(target: &str, level: Level) -> LogWriter {
+        LogWriter {
+            target: target.to_owned(),
+            level: level,
+            lines: Lines::default(),
+        }
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let target = &self.target;
+        let level = self.level;
+        self.lines.push(buf, |line| ::log::log!(target: target, level, "{}", line));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        let target = &self.target;
+        let level = self.level;
+        self.lines.finish(|line| ::log::log!(target: target, level, "{}", line));
+    }
+}
+
+// Splits bytes written in pieces into lines.
+#[derive(Debug, Default)]
+struct Lines {
+    partial: Vec<u8>,
+}
+
+impl Lines {
+    // Calls `f` with each line completed by `buf`, without its line ending.
+    fn push<F>(&mut self, buf: &[u8], mut f: F)
+        where F: FnMut(&str)
+    {
+        let mut rest = buf;
+        while let Some(i) = rest.iter().position(|&b| b == b'\n') {
+            if self.partial.is_empty() {
+                line(&rest[..i], &mut f);
+            } else {
+                self.partial.extend_from_slice(&rest[..i]);
+                let partial = mem::take(&mut self.partial);
+                line(&partial, &mut f);
+            }
+            rest = &rest[i + 1..];
+        }
+        self.partial.extend_from_slice(rest);
+    }
+
+    // Calls `f` with the partially written line, if there is one.
+    fn finish<F>(&mut self, mut f: F)
+        where F: FnMut(&str)
+    {
+        if !self.partial.is_empty() {
+            let partial = mem::take(&mut self.partial);
+            line(&partial, &mut f);
+        }
+    }
+}
+
+fn line<F>(line: &[u8], f: &mut F)
+    where F: FnMut(&str)
+{
+    let line = if line.ends_with(b"\r") {
+        &line[..line.len() - 1]
+    } else {
+        line
+    };
+    f(&String::from_utf8_lossy(line));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_lines() {
+        let mut out = vec![];
+        let mut lines = Lines::default();
+        lines.push(b"one\r\ntw", |l| out.push(l.to_owned()));
+        lines.push(b"o", |l| out.push(l.to_owned()));
+        lines.push(b"\n\n\xffthree", |l| out.push(l.to_owned()));
+        assert_eq!(out, ["one", "two", ""]);
+        lines.finish(|l| out.push(l.to_owned()));
+        lines.finish(|l| out.push(l.to_owned()));
+        assert_eq!(out, ["one", "two", "", "\u{fffd}three"]);
+    }
+}
@@ -59,7 +59,11 @@
 //! well as a `Deserializers` object which is responsible for instantiating the
 //! various objects specified by the config file. The `file` module
 //! documentation covers the exact configuration syntax, but an example in the
-//! YAML format is provided below.
+//! YAML format is provided below. A configuration which isn't in a file, like
+//! one embedded in the binary, can be passed to `init_from_str` or
+//! `init_from_reader` along with its `Format` instead. The `embed_config!`
+//! macro embeds a config file in the binary for `init_embedded`, and a build
+//! script can check it with `config::check_embedded`.
 //!
 //! log4rs makes heavy use of Cargo features to enable consumers to pick the
 //! functionality they wish to use. File-based configuration requires the `file`
@@ -68,10 +72,13 @@
 //! `yaml_format` feature and the console appender requires the
 //! `console_appender` feature.
 //!
-//! By default, the `all_components`, `gzip`, `file`, and `yaml_format` features
-//! are enabled.
+//! By default, the `all_components`, `gzip`, `manifest`, `file`, and
+//! `yaml_format` features are enabled.
 //!
-//! As a convenience, the `all_components` feature activates all logger components.
+//! As a convenience, the `all_components` feature activates all logger
+//! components, except for those which pull in large dependencies and must be
+//! enabled individually: the `websocket_appender`, `loki_appender`,
+//! `clickhouse_appender`, `hash_chain_encoder`, and `script_filter` features.
 //!
 //! # Examples
 //!
@@ -130,7 +137,7 @@
 //! #           feature = "file_appender",
 //! #           feature = "pattern_encoder"))]
 //! # fn f() {
-//! use log::LogLevelFilter;
+//! use log::LevelFilter;
 //! use log4rs::append::console::ConsoleAppender;
 //! use log4rs::append::file::FileAppender;
 //! use log4rs::encode::pattern::PatternEncoder;
@@ -147,12 +154,12 @@
 //!     let config = Config::builder()
 //!         .appender(Appender::builder().build("stdout", Box::new(stdout)))
 //!         .appender(Appender::builder().build("requests", Box::new(requests)))
-//!         .logger(Logger::builder().build("app::backend::db", LogLevelFilter::Info))
+//!         .logger(Logger::builder().build("app::backend::db", LevelFilter::Info))
 //!         .logger(Logger::builder()
 //!             .appender("requests")
 //!             .additive(false)
-//!             .build("app::requests", LogLevelFilter::Info))
-//!         .build(Root::builder().appender("stdout").build(LogLevelFilter::Warn))
+//!             .build("app::requests", LevelFilter::Info))
+//!         .build(Root::builder().appender("stdout").build(LevelFilter::Warn))
 //!         .unwrap();
 //!
 //!     let handle = log4rs::init_config(config).unwrap();
@@ -167,23 +174,42 @@
 
 #[cfg(feature = "antidote")]
 extern crate antidote;
-extern crate crossbeam;
+extern crate arc_swap;
 extern crate fnv;
 #[cfg(feature = "humantime")]
 extern crate humantime;
+#[cfg(feature = "inventory")]
+#[doc(hidden)]
+pub extern crate inventory;
 extern crate log;
+#[cfg(feature = "log4rs-derive")]
+extern crate log4rs_derive;
 #[cfg(feature = "log-mdc")]
 extern crate log_mdc;
+#[cfg(feature = "memmap2")]
+extern crate memmap2;
 #[cfg(feature = "typemap")]
 extern crate typemap;
 #[cfg(feature = "chrono")]
 extern crate chrono;
+#[cfg(feature = "crc32fast")]
+extern crate crc32fast;
 #[cfg(feature = "flate2")]
 extern crate flate2;
 #[cfg(all(windows, feature = "kernel32-sys"))]
 extern crate kernel32;
+#[cfg(all(target_arch = "wasm32", feature = "web-sys"))]
+extern crate web_sys;
 #[cfg(all(not(windows), feature = "libc"))]
 extern crate libc;
+#[cfg(feature = "notify")]
+extern crate notify;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "rhai")]
+extern crate rhai;
+#[cfg(feature = "ring")]
+extern crate ring;
 #[cfg(feature = "serde")]
 extern crate serde;
 #[cfg(feature = "serde_yaml")]
@@ -192,10 +218,24 @@ extern crate serde_yaml;
 extern crate serde_json;
 #[cfg(feature = "serde-value")]
 extern crate serde_value;
+#[cfg(feature = "sha2")]
+extern crate sha2;
+#[cfg(feature = "slog")]
+extern crate slog as slog_api;
+#[cfg(feature = "tungstenite")]
+extern crate tungstenite;
+#[cfg(feature = "ureq")]
+extern crate ureq;
 #[cfg(all(windows, feature = "winapi"))]
 extern crate winapi;
 #[cfg(feature = "toml")]
 extern crate toml;
+#[cfg(feature = "tracing-core")]
+extern crate tracing_core;
+#[cfg(feature = "tracing-subscriber")]
+extern crate tracing_subscriber;
+#[cfg(feature = "x25519-dalek")]
+extern crate x25519_dalek;
 
 #[cfg(feature = "serde_derive")]
 #[macro_use]
@@ -203,59 +243,216 @@ extern crate serde_derive;
 
 #[cfg(test)]
 extern crate tempdir;
+// lets tests use the paths generated by `#[derive(Deserializer)]`
+#[cfg(all(test, feature = "derive"))]
+extern crate self as log4rs;
+#[cfg(all(test, feature = "tracing_bridge"))]
+extern crate tracing as tracing_macros;
 
-use crossbeam::sync::ArcCell;
+use arc_swap::ArcSwap;
 use fnv::FnvHasher;
+use std::cell::Cell;
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error;
+use std::backtrace::Backtrace;
 use std::hash::BuildHasherDefault;
-use std::io;
 use std::io::prelude::*;
 use std::sync::Arc;
-use log::{LogLevel, LogMetadata, LogRecord, LogLevelFilter, SetLoggerError, MaxLogLevelFilter};
+#[cfg(not(target_arch = "wasm32"))]
+use std::os::raw::c_int;
+use std::panic;
+use std::ptr;
+use std::sync::{mpsc, Mutex, MutexGuard, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use log::{Level, Metadata, Record, LevelFilter, SetLoggerError};
 
 #[cfg(feature = "file")]
-pub use priv_file::{init_file, Error};
+pub use priv_env::init_from_env;
+#[cfg(feature = "file")]
+pub use priv_file::{init_embedded, init_file, init_file_with, init_file_with_profile,
+                    init_from_str, init_from_reader, EmbeddedConfig, Error, Format};
+#[cfg(feature = "remote_config")]
+pub use priv_remote::init_url;
+pub use priv_scope::{verbose_scope, VerboseScope};
 
 use append::Append;
 use config::Config;
 use filter::Filter;
+use intercept::Interceptor;
+use priv_dispatch::Dispatch;
+use priv_shard::Counter;
+use stats::{AppenderMetrics, AppenderStats, LevelCounts, Metrics, Stats, TargetVolume};
+use topology::{AppenderInfo, LoggerInfo, Topology};
 
 pub mod append;
 pub mod config;
 pub mod filter;
+pub mod intercept;
+pub mod io;
 #[cfg(feature = "file")]
 pub mod file;
 pub mod encode;
+pub mod marker;
+#[cfg(feature = "mdc")]
+pub mod mdc;
+#[cfg(feature = "dynamic_plugins")]
+pub mod plugin;
+#[cfg(feature = "process")]
+pub mod process;
+#[cfg(feature = "slog_bridge")]
+pub mod slog;
+#[cfg(feature = "tracing_bridge")]
+pub mod tracing;
+pub mod stats;
+pub mod topology;
+#[cfg(feature = "file")]
+mod priv_env;
 #[cfg(feature = "file")]
 mod priv_serde;
 #[cfg(feature = "file")]
 mod priv_file;
+#[cfg(feature = "remote_config")]
+mod priv_remote;
+mod priv_dispatch;
+mod priv_scope;
+mod priv_shard;
+mod priv_volume;
+#[cfg(all(unix, feature = "signals"))]
+pub mod signals;
 #[cfg(feature = "console_writer")]
 mod priv_io;
 
+/// Registers deserializers with every `Deserializers` created by
+/// `Deserializers::new`.
+///
+/// Crates providing components for config files can use this so that
+/// applications don't need to add each deserializer themselves. The argument
+/// is a function, or a closure which captures nothing, taking a
+/// `&mut Deserializers`:
+///
+/// ```ignore
+/// log4rs::register_deserializers!(|d| {
+///     d.insert("kafka", KafkaAppenderDeserializer);
+/// });
+/// ```
+///
+/// Registration happens when the crate is linked, so the application needs
+/// no reference to it beyond the dependency. Registered deserializers are
+/// added after the built in ones.
+///
+/// Requires the `plugins` feature.
+#[cfg(feature = "plugins")]
+#[macro_export]
+macro_rules! register_deserializers {
+    ($register:expr) => {
+        $crate::inventory::submit! {
+            $crate::file::Plugin::new($register)
+        }
+    };
+}
+
+/// Embeds a config file in the binary, evaluating to an `EmbeddedConfig`
+/// for `init_embedded`.
+///
+/// The path is relative to the root of the package invoking the macro, and
+/// its extension determines the config's format. Calling
+/// `config::check_embedded` with the same path from the package's build
+/// script checks the config when the package is built:
+///
+/// ```ignore
+/// static CONFIG: log4rs::EmbeddedConfig = embed_config!("log4rs.yaml");
+///
+/// log4rs::init_embedded(CONFIG, Default::default()).unwrap();
+/// ```
+///
+/// Requires the `file` feature (enabled by default).
+#[cfg(feature = "file")]
+#[macro_export]
+macro_rules! embed_config {
+    ($path:expr) => {
+        $crate::EmbeddedConfig::new($path,
+                                    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path)))
+    };
+}
+
+/// Logs an event with guaranteed delivery through the global logger,
+/// evaluating to a `Result` with the first error from its appenders.
+///
+/// The macro takes the same arguments as `log`'s `info!`, and the event is
+/// logged at the `Info` level. It is a shorthand for calling `Handle::audit`
+/// on the handle of the global logger, and fails if the global logger isn't
+/// a log4rs logger.
+///
+/// ```no_run
+/// # #[macro_use] extern crate log4rs;
+/// # fn main() {
+/// audit!(target: "audit::payments", "settled payment {}", 42)
+///     .expect("failed to record the payment");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! audit {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::__audit($target,
+                        format_args!($($arg)+),
+                        module_path!(),
+                        file!(),
+                        line!())
+    };
+    ($($arg:tt)+) => {
+        $crate::audit!(target: module_path!(), $($arg)+)
+    };
+}
+
+#[doc(hidden)]
+pub fn __audit(target: &str,
+               args: std::fmt::Arguments,
+               module_path: &'static str,
+               file: &'static str,
+               line: u32)
+               -> Result<(), Box<error::Error + Sync + Send>> {
+    let handle = priv_scope::global().ok_or("log4rs has not been initialized")?;
+    handle.audit(&Record::builder()
+        .level(Level::Info)
+        .target(target)
+        .args(args)
+        .module_path_static(Some(module_path))
+        .file_static(Some(file))
+        .line(Some(line))
+        .build())
+}
+
 type FnvHashMap<K, V> = HashMap<K, V, BuildHasherDefault<FnvHasher>>;
 
+// An appender attached to a logger, by its index and the maximum level of the
+// events the logger passes to it.
+type Attachment = (usize, LevelFilter);
+
+#[derive(Clone)]
 struct ConfiguredLogger {
-    level: LogLevelFilter,
-    appenders: Vec<usize>,
+    level: LevelFilter,
+    appenders: Vec<Attachment>,
+    // the length of the name of the logger this was configured by
+    specificity: usize,
     children: FnvHashMap<String, ConfiguredLogger>,
 }
 
 impl ConfiguredLogger {
     fn add(&mut self,
            path: &str,
-           mut appenders: Vec<usize>,
+           mut appenders: Vec<Attachment>,
            additive: bool,
-           level: LogLevelFilter) {
+           level: LevelFilter,
+           specificity: usize) {
         let (part, rest) = match path.find("::") {
             Some(idx) => (&path[..idx], &path[idx + 2..]),
             None => (path, ""),
         };
 
         if let Some(child) = self.children.get_mut(part) {
-            child.add(rest, appenders, additive, level);
+            child.add(rest, appenders, additive, level, specificity);
             return;
         }
 
@@ -267,22 +464,24 @@ impl ConfiguredLogger {
             ConfiguredLogger {
                 level: level,
                 appenders: appenders,
+                specificity: specificity,
                 children: FnvHashMap::default(),
             }
         } else {
             let mut child = ConfiguredLogger {
                 level: self.level,
                 appenders: self.appenders.clone(),
+                specificity: self.specificity,
                 children: FnvHashMap::default(),
             };
-            child.add(rest, appenders, additive, level);
+            child.add(rest, appenders, additive, level, specificity);
             child
         };
 
         self.children.insert(part.to_owned(), child);
     }
 
-    fn max_log_level(&self) -> LogLevelFilter {
+    fn max_log_level(&self) -> LevelFilter {
         let mut max = self.level;
         for child in self.children.values() {
             max = cmp::max(max, child.max_log_level());
@@ -302,49 +501,412 @@ impl ConfiguredLogger {
 
         node
     }
+}
 
-    fn enabled(&self, level: LogLevel) -> bool {
-        self.level >= level
+// A logger whose name contains wildcards.
+#[derive(Clone)]
+struct GlobLogger {
+    pattern: String,
+    // the number of characters which aren't wildcards
+    specificity: usize,
+    level: LevelFilter,
+    appenders: Vec<Attachment>,
+    additive: bool,
+}
+
+impl GlobLogger {
+    // Like other loggers, a pattern applies to the descendants of the targets
+    // it matches.
+    fn matches(&self, target: &str) -> bool {
+        glob_matches(&self.pattern, target) ||
+        target.match_indices("::").any(|(i, _)| glob_matches(&self.pattern, &target[..i]))
     }
+}
 
-    fn log(&self, record: &log::LogRecord, appenders: &[Appender]) {
-        if self.enabled(record.level()) {
-            for &idx in &self.appenders {
-                if let Err(err) = appenders[idx].append(record) {
-                    handle_error(&*err);
-                }
+// Matches `name` against a glob where `*` matches any sequence of characters,
+// `::` and path separators included, and `?` matches a single character.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let first = |s: &str, i: usize| s[i..].chars().next();
+    let (mut p, mut n) = (0, 0);
+    // the position in the pattern after the last `*` and the position in the
+    // name it was tried against
+    let mut backtrack = None;
+    while let Some(c) = first(name, n) {
+        match first(pattern, p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+                continue;
+            }
+            Some(pc) if pc == '?' || pc == c => {
+                p += pc.len_utf8();
+                n += c.len_utf8();
+                continue;
             }
+            _ => {}
         }
+        match backtrack {
+            // let the last `*` consume one more character
+            Some((star_p, star_n)) => {
+                p = star_p;
+                n = star_n + first(name, star_n).map_or(0, char::len_utf8);
+                backtrack = Some((star_p, n));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].chars().all(|c| c == '*')
+}
+
+// The configuration which applies to a target.
+struct Resolved<'a> {
+    level: LevelFilter,
+    appenders: &'a [Attachment],
+    inherited: &'a [Attachment],
+    attached: &'a [(usize, Vec<String>)],
+}
+
+impl<'a> Resolved<'a> {
+    fn enabled(&self, level: Level) -> bool {
+        self.level >= level
     }
+
+    fn log(&self, record: &log::Record, shared: &Arc<SharedLogger>) {
+        if !self.enabled(record.level()) {
+            FILTERED.add(record.level());
+            return;
+        }
+        priv_volume::add(record.target(), record.level());
+        for idx in self.targets(record) {
+            shared.append(idx, record);
+        }
+    }
+
+    // The indices of the appenders receiving the event.
+    fn targets<'b>(&'b self, record: &'b Record) -> impl Iterator<Item = usize> + 'b {
+        let attached = self.attached
+            .iter()
+            .filter(move |&(_, loggers)| loggers.iter().any(|l| within(record.target(), l)))
+            .map(|&(idx, _)| idx);
+        self.appenders
+            .iter()
+            .chain(self.inherited)
+            .filter(move |&&(_, level)| record.level() <= level)
+            .map(|&(idx, _)| idx)
+            .chain(attached)
+    }
+}
+
+// Whether the target is the logger's or one of its descendants.
+fn within(target: &str, logger: &str) -> bool {
+    logger.is_empty() ||
+    target.strip_prefix(logger).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
 }
 
 struct Appender {
-    appender: Box<Append>,
+    name: String,
+    appender: Arc<Append>,
+    level: LevelFilter,
     filters: Vec<Box<Filter>>,
+    kind: Option<String>,
+    counters: Arc<Counters>,
+    metrics: Arc<AppenderCounts>,
+    // serializes calls into an appender which isn't thread-safe
+    dispatch: Option<Dispatch>,
 }
 
 impl Appender {
-    fn append(&self, record: &LogRecord) -> Result<(), Box<error::Error + Sync + Send>> {
+    fn new(name: String,
+           appender: Arc<Append>,
+           level: LevelFilter,
+           filters: Vec<Box<Filter>>,
+           kind: Option<String>)
+           -> Appender {
+        let dispatch = if appender.thread_safe() {
+            None
+        } else {
+            Some(Dispatch::default())
+        };
+        Appender {
+            metrics: appender_counts(&name),
+            name: name,
+            appender: appender,
+            level: level,
+            filters: filters,
+            kind: kind,
+            counters: Arc::default(),
+            dispatch: dispatch,
+        }
+    }
+
+    fn dispatch<F, T>(&self, f: F) -> T
+        where F: FnOnce(&Append) -> T
+    {
+        match self.dispatch {
+            Some(ref dispatch) => dispatch.run(|| f(&*self.appender)),
+            None => f(&*self.appender),
+        }
+    }
+
+    fn flush(&self) -> Result<(), Box<error::Error + Sync + Send>> {
+        self.dispatch(|a| a.flush())
+    }
+
+    fn reopen(&self) -> Result<(), Box<error::Error + Sync + Send>> {
+        self.dispatch(|a| a.reopen())
+    }
+
+    fn append(&self, record: &Record) -> Result<(), Box<error::Error + Sync + Send>> {
+        self.deliver(record).map(|_| ())
+    }
+
+    // Like `append`, but also returns whether the appender's level and
+    // filters accepted the event.
+    fn deliver(&self, record: &Record) -> Result<bool, Box<error::Error + Sync + Send>> {
+        if !self.accepts(record) {
+            self.counters.filter();
+            self.metrics.filtered.add(record.level());
+            return Ok(false);
+        }
+
+        let result = {
+            let _appending = Appending::enter(self);
+            self.dispatch(|a| a.append(record))
+        };
+        match result {
+            Ok(()) => {
+                self.counters.record();
+                self.metrics.written.add(record.level());
+                Ok(true)
+            }
+            Err(err) => {
+                self.counters.error();
+                self.metrics.errored.add(record.level());
+                self.metrics.dropped.add(record.level());
+                Err(err)
+            }
+        }
+    }
+
+    fn accepts(&self, record: &Record) -> bool {
+        if record.level() > self.level {
+            return false;
+        }
+
         for filter in &self.filters {
             match filter.filter(record) {
                 filter::Response::Accept => break,
                 filter::Response::Neutral => {}
-                filter::Response::Reject => return Ok(()),
+                filter::Response::Reject => return false,
             }
         }
 
-        self.appender.append(record)
+        true
+    }
+
+    // The most verbose level of the events the appender may accept. Events
+    // more verbose than the first filter's maximum are rejected before any
+    // later filter could accept them.
+    fn max_level(&self) -> LevelFilter {
+        match self.filters.first() {
+            Some(filter) => cmp::min(self.level, filter.max_level()),
+            None => self.level,
+        }
     }
 }
 
+thread_local! {
+    // the appender whose `append` is running on this thread
+    static APPENDING: Cell<*const Appender> = const { Cell::new(ptr::null()) };
+}
+
+// Marks an appender as appending on this thread until dropped, so that the
+// events its queue discards are counted against it.
+struct Appending(*const Appender);
+
+impl Appending {
+    fn enter(appender: &Appender) -> Appending {
+        let previous = APPENDING.try_with(|a| a.replace(appender)).unwrap_or(ptr::null());
+        Appending(previous)
+    }
+}
+
+impl Drop for Appending {
+    fn drop(&mut self) {
+        let _ = APPENDING.try_with(|a| a.set(self.0));
+    }
+}
+
+// Counts an event discarded by the appender appending on this thread, for
+// example because its queue is full.
+#[cfg(any(feature = "loki_appender",
+          feature = "clickhouse_appender",
+          feature = "honeycomb_appender",
+          feature = "newrelic_appender",
+          feature = "influxdb_appender",
+          feature = "shadow_appender"))]
+fn drop_event(level: Level) {
+    let appender = APPENDING.try_with(Cell::get).unwrap_or(ptr::null());
+    // the appender is borrowed for as long as it is marked as appending
+    if let Some(appender) = unsafe { appender.as_ref() } {
+        appender.counters.drops.add(1);
+        appender.metrics.dropped.add(level);
+    }
+}
+
+// Counters are sharded, since every thread logging to an appender updates
+// them.
+#[derive(Default)]
+struct Counters {
+    records: Counter,
+    errors: Counter,
+    filtered: Counter,
+    drops: Counter,
+}
+
+impl Counters {
+    fn record(&self) {
+        self.records.add(1);
+    }
+
+    // an event which fails to be appended is lost
+    fn error(&self) {
+        self.errors.add(1);
+        self.drops.add(1);
+    }
+
+    fn filter(&self) {
+        self.filtered.add(1);
+    }
+
+    fn snapshot(&self, bytes: Option<u64>) -> AppenderStats {
+        AppenderStats::new(self.records.get(),
+                           bytes,
+                           self.errors.get(),
+                           self.filtered.get(),
+                           self.drops.get())
+    }
+}
+
+// Counts of events by level, for `metrics`.
+struct LevelCounters([Counter; 5]);
+
+impl LevelCounters {
+    const fn new() -> LevelCounters {
+        LevelCounters([const { Counter::new() }; 5])
+    }
+
+    fn add(&self, level: Level) {
+        self.0[level as usize - 1].add(1);
+    }
+
+    fn snapshot(&self) -> LevelCounts {
+        let mut counts = [0; 5];
+        for (count, counter) in counts.iter_mut().zip(&self.0) {
+            *count = counter.get();
+        }
+        LevelCounts::new(counts)
+    }
+}
+
+struct AppenderCounts {
+    written: LevelCounters,
+    filtered: LevelCounters,
+    dropped: LevelCounters,
+    errored: LevelCounters,
+}
+
+static SUBMITTED: LevelCounters = LevelCounters::new();
+static FILTERED: LevelCounters = LevelCounters::new();
+// shared by every appender with the same name, in any configuration
+static APPENDER_COUNTS: Mutex<BTreeMap<String, Arc<AppenderCounts>>> = Mutex::new(BTreeMap::new());
+
+fn appender_counts(name: &str) -> Arc<AppenderCounts> {
+    let mut counts = APPENDER_COUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    counts.entry(name.to_owned())
+        .or_insert_with(|| {
+            Arc::new(AppenderCounts {
+                written: LevelCounters::new(),
+                filtered: LevelCounters::new(),
+                dropped: LevelCounters::new(),
+                errored: LevelCounters::new(),
+            })
+        })
+        .clone()
+}
+
+/// Returns a snapshot of the process wide counts of log events.
+///
+/// Unlike `Handle::stats`, the counts are kept across configurations. See
+/// the `stats` module.
+pub fn metrics() -> Metrics {
+    let appenders = APPENDER_COUNTS.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(name, counts)| {
+            let metrics = AppenderMetrics::new(counts.written.snapshot(),
+                                               counts.filtered.snapshot(),
+                                               counts.dropped.snapshot(),
+                                               counts.errored.snapshot());
+            (name.clone(), metrics)
+        })
+        .collect();
+    Metrics::new(SUBMITTED.snapshot(), FILTERED.snapshot(), appenders)
+}
+
+#[derive(Clone, Copy)]
+enum OnError {
+    Ignore,
+    Stderr,
+    Panic,
+    Appender(usize),
+}
+
+// When the logger was configured.
+#[derive(Clone, Copy)]
+struct History {
+    initialized: SystemTime,
+    reloaded: Option<SystemTime>,
+    reloads: u64,
+}
+
 struct SharedLogger {
     root: ConfiguredLogger,
-    appenders: Vec<Appender>,
+    // sorted from most to least specific
+    globs: Vec<GlobLogger>,
+    // shared with the copies made to change levels
+    appenders: Arc<Vec<Arc<Appender>>>,
+    // appenders added through the handle, which follow those of the config,
+    // and the loggers they are attached to
+    attached: Vec<(usize, Vec<String>)>,
+    on_error: OnError,
+    interceptors: Vec<Arc<Interceptor>>,
+    // levels set through the handle, sorted from longest to shortest target
+    levels: Vec<(String, LevelFilter)>,
+    // the configuration this was built from
+    config: (config::Root, Vec<config::Logger>, config::ErrorPolicy),
+    history: History,
+    // set by `Handle::shutdown`, after which the configuration is final
+    shut_down: bool,
 }
 
 impl SharedLogger {
     fn new(config: config::Config) -> SharedLogger {
-        let (appenders, root, mut loggers) = config.unpack();
+        let interceptors = config.interceptors().to_vec();
+        let (appenders, root, mut loggers, on_error) = config.unpack();
+        let mut globs = vec![];
+        let config = (root.clone(), loggers.clone(), on_error.clone());
+
+        let on_error = match on_error {
+            config::ErrorPolicy::Ignore => OnError::Ignore,
+            config::ErrorPolicy::Stderr => OnError::Stderr,
+            config::ErrorPolicy::Panic => OnError::Panic,
+            config::ErrorPolicy::Appender(name) => {
+                // the config builder checks that the appender exists
+                let idx = appenders.iter().position(|a| a.name() == name).unwrap();
+                OnError::Appender(idx)
+            }
+        };
 
         let root = {
             let appender_map = appenders.iter()
@@ -356,8 +918,9 @@ impl SharedLogger {
                 level: root.level(),
                 appenders: root.appenders()
                     .iter()
-                    .map(|appender| appender_map[&**appender])
+                    .map(|appender| (appender_map[&**appender], root.appender_level(appender)))
                     .collect(),
+                specificity: 0,
                 children: FnvHashMap::default(),
             };
 
@@ -366,62 +929,342 @@ impl SharedLogger {
             for logger in loggers {
                 let appenders = logger.appenders()
                     .iter()
-                    .map(|appender| appender_map[&**appender])
-                    .collect();
-                root.add(logger.name(), appenders, logger.additive(), logger.level());
+                    .map(|appender| {
+                        (appender_map[&**appender], logger.appender_level(appender))
+                    })
+                    .collect::<Vec<_>>();
+                if logger.name().contains('*') {
+                    globs.push(GlobLogger {
+                        pattern: logger.name().to_owned(),
+                        specificity: logger.name().chars().filter(|&c| c != '*').count(),
+                        level: logger.level(),
+                        appenders: appenders,
+                        additive: logger.additive(),
+                    });
+                } else {
+                    root.add(logger.name(),
+                             appenders,
+                             logger.additive(),
+                             logger.level(),
+                             logger.name().len());
+                }
             }
 
             root
         };
+        globs.sort_by(|a, b| {
+            b.specificity.cmp(&a.specificity).then_with(|| a.pattern.cmp(&b.pattern))
+        });
 
         let appenders = appenders.into_iter()
             .map(|appender| {
-                let (_, appender, filters) = appender.unpack();
-                Appender {
-                    appender: appender,
-                    filters: filters,
-                }
+                let level = appender.level();
+                let kind = appender.kind().map(ToOwned::to_owned);
+                let (name, appender, filters) = appender.unpack();
+                Arc::new(Appender::new(name, appender, level, filters, kind))
             })
             .collect();
 
         SharedLogger {
             root: root,
-            appenders: appenders,
+            globs: globs,
+            appenders: Arc::new(appenders),
+            attached: vec![],
+            on_error: on_error,
+            interceptors: interceptors,
+            levels: vec![],
+            config: config,
+            history: History {
+                initialized: SystemTime::now(),
+                reloaded: None,
+                reloads: 0,
+            },
+            shut_down: false,
+        }
+    }
+
+    // The most specific logger matching the target wins, with ties going to
+    // a logger without wildcards.
+    fn find(&self, target: &str) -> Resolved<'_> {
+        let mut resolved = self.find_configured(target);
+        if let Some(level) = self.level_override(target) {
+            resolved.level = level;
+        }
+        resolved.level = priv_scope::raise(resolved.level, target);
+        resolved
+    }
+
+    fn find_configured(&self, target: &str) -> Resolved<'_> {
+        let node = self.root.find(target);
+        for glob in &self.globs {
+            if glob.specificity <= node.specificity {
+                break;
+            }
+            if glob.matches(target) {
+                return Resolved {
+                    level: glob.level,
+                    appenders: &glob.appenders,
+                    inherited: if glob.additive { &node.appenders } else { &[] },
+                    attached: &self.attached,
+                };
+            }
+        }
+
+        Resolved {
+            level: node.level,
+            appenders: &node.appenders,
+            inherited: &[],
+            attached: &self.attached,
+        }
+    }
+
+    // The level set for the target or its closest ancestor, if any.
+    fn level_override(&self, target: &str) -> Option<LevelFilter> {
+        self.levels
+            .iter()
+            .find(|&(prefix, _)| within(target, prefix))
+            .map(|&(_, level)| level)
+    }
+
+    fn max_log_level(&self) -> LevelFilter {
+        if self.shut_down {
+            return LevelFilter::Off;
+        }
+        let max = self.globs
+            .iter()
+            .fold(self.root.max_log_level(), |max, g| cmp::max(max, g.level));
+        let max = self.levels.iter().fold(max, |max, &(_, level)| cmp::max(max, level));
+        let max = cmp::max(max, priv_scope::max_level());
+
+        // interceptors can make an event more severe after it has been
+        // checked against the hint, so only without them can events no
+        // appender accepts be skipped
+        if !self.interceptors.is_empty() {
+            return max;
+        }
+        let appenders = self.appenders.iter().map(|a| a.max_level()).max();
+        cmp::min(max, appenders.unwrap_or(LevelFilter::Off))
+    }
+
+    // A copy of this logger with different level overrides.
+    fn with_levels(&self, mut levels: Vec<(String, LevelFilter)>) -> SharedLogger {
+        levels.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        SharedLogger {
+            root: self.root.clone(),
+            globs: self.globs.clone(),
+            appenders: self.appenders.clone(),
+            attached: self.attached.clone(),
+            on_error: self.on_error,
+            interceptors: self.interceptors.clone(),
+            levels: levels,
+            config: self.config.clone(),
+            history: self.history,
+            shut_down: self.shut_down,
+        }
+    }
+
+    fn flush(&self) {
+        for appender in self.appenders.iter() {
+            if let Err(e) = appender.flush() {
+                let e = format!("error flushing appender `{}`: {}", appender.name, e);
+                handle_appender_error(&*Box::<error::Error + Sync + Send>::from(e), &appender.name);
+            }
+        }
+    }
+
+    fn append(self: &Arc<Self>, idx: usize, record: &Record) {
+        let appender = &self.appenders[idx];
+        let err = match appender.append(record) {
+            Ok(()) => return,
+            Err(err) => err,
+        };
+
+        match self.on_error {
+            OnError::Ignore => {}
+            OnError::Stderr => handle_appender_error(&*err, &appender.name),
+            OnError::Panic => panic!("log4rs: appender `{}` failed: {}", appender.name, err),
+            OnError::Appender(target) if target != idx => {
+                let args = format_args!("appender `{}` failed: {}", appender.name, err);
+                let record = Record::builder()
+                    .level(Level::Error)
+                    .target("log4rs")
+                    .args(args)
+                    .module_path_static(Some(module_path!()))
+                    .file_static(Some(file!()))
+                    .line(Some(line!()))
+                    .build();
+                let target = &self.appenders[target];
+                if let Err(e) = target.append(&record) {
+                    handle_appender_error(&*e, &target.name);
+                }
+            }
+            OnError::Appender(_) => handle_appender_error(&*err, &appender.name),
+        }
+    }
+
+    // appenders carried over from the previous config keep their statistics
+    fn retain(&mut self, previous: &SharedLogger) {
+        // a new logger's appenders aren't shared yet
+        let appenders = Arc::get_mut(&mut self.appenders).unwrap();
+        for appender in appenders.iter_mut() {
+            let appender = Arc::get_mut(appender).unwrap();
+            let old = previous.appenders
+                .iter()
+                .find(|a| a.name == appender.name && Arc::ptr_eq(&a.appender, &appender.appender));
+            if let Some(old) = old {
+                appender.counters = old.counters.clone();
+            }
+        }
+
+        // as are appenders added through the handle, unless the config has
+        // one of the same name
+        for &(idx, ref loggers) in &previous.attached {
+            let appender = &previous.appenders[idx];
+            if !appenders.iter().any(|a| a.name == appender.name) {
+                self.attached.push((appenders.len(), loggers.clone()));
+                appenders.push(appender.clone());
+            }
         }
     }
 }
 
-struct Logger(Arc<ArcCell<SharedLogger>>);
+struct Logger(Arc<ArcSwap<SharedLogger>>);
 
 impl Logger {
     fn new(config: config::Config) -> Logger {
-        Logger(Arc::new(ArcCell::new(Arc::new(SharedLogger::new(config)))))
-    }
-
-    fn max_log_level(&self) -> LogLevelFilter {
-        self.0.get().root.max_log_level()
+        Logger(Arc::new(ArcSwap::from_pointee(SharedLogger::new(config))))
     }
 }
 
 impl log::Log for Logger {
-    fn enabled(&self, metadata: &LogMetadata) -> bool {
+    fn enabled(&self, metadata: &Metadata) -> bool {
         self.enabled_inner(metadata.level(), metadata.target())
     }
 
-    fn log(&self, record: &log::LogRecord) {
-        let shared = self.0.get();
-        shared.root.find(record.target()).log(record, &shared.appenders);
+    fn log(&self, record: &Record) {
+        SUBMITTED.add(record.level());
+        // a lock-free load which doesn't touch the reference count, so that
+        // threads logging concurrently don't contend with each other
+        let shared = self.0.load();
+        intercept(record, &shared, |record| shared.find(record.target()).log(record, &shared));
+    }
+
+    fn flush(&self) {
+        self.0.load_full().flush();
     }
 }
 
+// Passes an event through the interceptors and calls `f` with the result.
+fn intercept<F, T>(record: &Record, shared: &SharedLogger, f: F) -> T
+    where F: FnOnce(&Record) -> T
+{
+    if shared.interceptors.is_empty() {
+        return f(record);
+    }
+
+    let mut intercepted = intercept::Record::new(record.level(),
+                                                 record.target().to_owned(),
+                                                 record.args().to_string());
+    for interceptor in &shared.interceptors {
+        interceptor.intercept(&mut intercepted);
+    }
+
+    #[cfg(feature = "log-mdc")]
+    let previous = intercepted.fields()
+        .iter()
+        .map(|(k, v)| (k.clone(), log_mdc::insert(k.clone(), v.clone())))
+        .collect::<Vec<_>>();
+
+    let args = format_args!("{}", intercepted.message());
+    let record = Record::builder()
+        .level(intercepted.level())
+        .target(intercepted.target())
+        .args(args)
+        .module_path(record.module_path())
+        .file(record.file())
+        .line(record.line())
+        .key_values(record.key_values())
+        .build();
+    let ret = f(&record);
+
+    #[cfg(feature = "log-mdc")]
+    for (key, value) in previous {
+        match value {
+            Some(value) => {
+                log_mdc::insert(key, value);
+            }
+            None => {
+                log_mdc::remove(&key);
+            }
+        }
+    }
+
+    ret
+}
+
 impl Logger {
-    fn enabled_inner(&self, level: LogLevel, target: &str) -> bool {
-        self.0.get().root.find(target).enabled(level)
+    fn enabled_inner(&self, level: Level, target: &str) -> bool {
+        self.0.load().find(target).enabled(level)
+    }
+}
+
+type ErrorHandler = Fn(&error::Error, Option<&str>) + Sync + Send;
+
+static ERROR_HANDLER: RwLock<Option<Arc<ErrorHandler>>> = RwLock::new(None);
+
+thread_local! {
+    // set while the error handler runs, so that errors it causes by logging
+    // don't recurse into it
+    static IN_ERROR_HANDLER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Sets the function which is called with the errors log4rs encounters
+/// internally, replacing the default of printing them to stderr.
+///
+/// This covers errors from appenders whose configuration's error policy is
+/// `stderr` (the default), as well as errors loading or reloading
+/// configuration and from background threads of appenders. The second
+/// argument is the name of the appender the error came from, if any.
+///
+/// Errors raised while the handler itself is running, like when it logs an
+/// event to an appender which fails, are printed to stderr instead.
+pub fn set_error_handler<F>(handler: F)
+    where F: Fn(&error::Error, Option<&str>) + Sync + Send + 'static
+{
+    *ERROR_HANDLER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(handler));
+}
+
+fn handle_error(e: &error::Error) {
+    report_error(e, None);
+}
+
+fn handle_appender_error(e: &error::Error, appender: &str) {
+    report_error(e, Some(appender));
+}
+
+fn report_error(e: &error::Error, appender: Option<&str>) {
+    let handler = ERROR_HANDLER.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match handler {
+        Some(ref handler) if !IN_ERROR_HANDLER.with(Cell::get) => {
+            IN_ERROR_HANDLER.with(|h| h.set(true));
+            handler(e, appender);
+            IN_ERROR_HANDLER.with(|h| h.set(false));
+        }
+        _ => {
+            let _ = writeln!(::std::io::stderr(), "log4rs: {}", e);
+        }
     }
 }
 
-fn handle_error<E: error::Error + ?Sized>(e: &E) {
-    let _ = writeln!(io::stderr(), "log4rs: {}", e);
+// the policy of the queues of appenders without their own
+static BACKPRESSURE: RwLock<config::Backpressure> = RwLock::new(config::Backpressure::DropNewest);
+
+fn backpressure() -> config::Backpressure {
+    *BACKPRESSURE.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn set_backpressure(backpressure: config::Backpressure) {
+    *BACKPRESSURE.write().unwrap_or_else(|e| e.into_inner()) = backpressure;
 }
 
 /// Initializes the global logger as a log4rs logger with the provided config.
@@ -429,32 +1272,597 @@ fn handle_error<E: error::Error + ?Sized>(e: &E) {
 /// A `Handle` object is returned which can be used to adjust the logging
 /// configuration.
 pub fn init_config(config: config::Config) -> Result<Handle, SetLoggerError> {
-    let mut handle = None;
-    log::set_logger(|max_log_level| {
-            let logger = Logger::new(config);
-            max_log_level.set(logger.max_log_level());
-            handle = Some(Handle {
-                shared: logger.0.clone(),
-                max_log_level: max_log_level,
-            });
-            Box::new(logger)
-        })
-        .map(|()| handle.unwrap())
+    let backpressure = config.backpressure();
+    let logger = Logger::new(config);
+    let handle = Handle {
+        shared: logger.0.clone(),
+        update: Arc::default(),
+        reload: Arc::default(),
+    };
+    log::set_boxed_logger(Box::new(logger)).map(|()| {
+        set_backpressure(backpressure);
+        log::set_max_level(handle.shared.load_full().max_log_level());
+        priv_scope::register(handle.clone());
+        handle
+    })
+}
+
+// Flushes the logger's appenders on a background thread, returning `false`
+// if that takes longer than `timeout`.
+fn flush_within(shared: Arc<SharedLogger>, timeout: Duration) -> bool {
+    let (tx, rx) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .name("log4rs flush".to_owned())
+        .spawn(move || {
+            shared.flush();
+            drop(shared);
+            let _ = tx.send(());
+        });
+    match spawned {
+        Ok(_) => rx.recv_timeout(timeout).is_ok(),
+        Err(e) => {
+            handle_error(&e);
+            false
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+static EXIT_FLUSH: Mutex<Option<(Handle, Duration)>> = Mutex::new(None);
+
+#[cfg(not(target_arch = "wasm32"))]
+extern "C" fn flush_at_exit() {
+    let exit = EXIT_FLUSH.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some((handle, timeout)) = exit {
+        flush_within(handle.shared.load_full(), timeout);
+    }
 }
 
 /// A handle to the active logger.
+///
+/// Besides replacing the configuration, a handle can describe what the
+/// logger is currently doing. Every method reflects the configuration active
+/// at the time of the call, including any changes made through other clones
+/// of the handle or by reloading a config file.
+#[derive(Clone)]
 pub struct Handle {
-    shared: Arc<ArcCell<SharedLogger>>,
-    max_log_level: MaxLogLevelFilter,
+    shared: Arc<ArcSwap<SharedLogger>>,
+    // held while `shared` is replaced, so that concurrent changes aren't lost
+    update: Arc<Mutex<()>>,
+    // loads the configuration again from where it came from, if anywhere
+    reload: Arc<Mutex<Option<Box<Reload>>>>,
 }
 
+type Reload = FnMut() -> Result<(), Box<error::Error + Sync + Send>> + Send;
+
 impl Handle {
     /// Sets the logging configuration.
+    ///
+    /// Appenders in the new configuration which share their `Append` trait
+    /// object with an appender of the same name in the active configuration
+    /// (see `AppenderBuilder::build_shared`) are kept running along with
+    /// their statistics. Other appenders of the active configuration are
+    /// dropped once in-flight events have been processed.
+    ///
+    /// Levels set with `set_level` continue to apply to the new configuration.
+    /// The configuration's `Backpressure` policy applies to the queues of
+    /// kept appenders as well. The configuration can't be changed after
+    /// `shutdown` has been called.
     pub fn set_config(&self, config: Config) {
-        let shared = SharedLogger::new(config);
-        self.max_log_level.set(shared.root.max_log_level());
-        self.shared.set(Arc::new(shared));
+        let _update = self.lock_update();
+        let previous = self.shared.load_full();
+        if previous.shut_down {
+            return;
+        }
+        set_backpressure(config.backpressure());
+        let mut shared = SharedLogger::new(config);
+        shared.retain(&previous);
+        let mut shared = shared.with_levels(previous.levels.clone());
+        shared.history = History {
+            initialized: previous.history.initialized,
+            reloaded: Some(SystemTime::now()),
+            reloads: previous.history.reloads + 1,
+        };
+        log::set_max_level(shared.max_log_level());
+        self.shared.store(Arc::new(shared));
+    }
+
+    /// Returns the configuration of the root logger.
+    ///
+    /// Levels reflect any overrides applied when the configuration was
+    /// loaded, like those of a config file's `level_override_var`.
+    pub fn root(&self) -> config::Root {
+        self.shared.load_full().config.0.clone()
+    }
+
+    /// Returns the configurations of the loggers other than the root.
+    pub fn loggers(&self) -> Vec<config::Logger> {
+        self.shared.load_full().config.1.clone()
+    }
+
+    /// Returns how errors from appenders are handled.
+    pub fn on_error(&self) -> config::ErrorPolicy {
+        self.shared.load_full().config.2.clone()
+    }
+
+    /// Returns the names of the appenders.
+    pub fn appenders(&self) -> Vec<String> {
+        self.shared.load_full().appenders.iter().map(|a| a.name.clone()).collect()
+    }
+
+    /// Returns the maximum level of events logged to the specified target.
+    ///
+    /// Levels raised for the current thread by `verbose_scope` aren't
+    /// included.
+    pub fn level(&self, target: &str) -> LevelFilter {
+        let shared = self.shared.load_full();
+        shared.level_override(target).unwrap_or_else(|| shared.find_configured(target).level)
+    }
+
+    /// Sets the maximum level of events logged to the specified target and
+    /// its descendants, taking effect immediately.
+    ///
+    /// The level overrides that of every configured logger applying to those
+    /// targets, while events keep going to the same appenders. If levels are
+    /// set for several ancestors of a target, the closest one applies. An
+    /// empty target sets the level of every target. Levels set this way are
+    /// kept when the configuration is replaced, until they are removed with
+    /// `reset_level`.
+    pub fn set_level(&self, target: &str, level: LevelFilter) {
+        self.update_levels(|levels| {
+            levels.retain(|l| l.0 != target);
+            levels.push((target.to_owned(), level));
+        });
+    }
+
+    /// Removes the level set for the specified target by `set_level`, so the
+    /// target's configured level applies again.
+    ///
+    /// Levels set for the target's descendants or ancestors are unaffected.
+    pub fn reset_level(&self, target: &str) {
+        self.update_levels(|levels| levels.retain(|l| l.0 != target));
+    }
+
+    /// Adds an appender to the active configuration, attached to the loggers
+    /// with the specified names, taking effect immediately.
+    ///
+    /// The appender receives the events logged to those loggers' targets and
+    /// their descendants which pass the targets' levels, regardless of
+    /// additivity. An empty name stands for the root logger, so attaching to
+    /// it captures every event. Appenders added this way are kept when the
+    /// configuration is replaced, unless the new configuration has an
+    /// appender with the same name, until they are removed with
+    /// `remove_appender`.
+    ///
+    /// Returns an error if an appender with the same name already exists or
+    /// the logger has been shut down.
+    pub fn add_appender(&self,
+                        name: &str,
+                        appender: Box<Append>,
+                        attach_to: &[&str])
+                        -> Result<(), Box<error::Error + Sync + Send>> {
+        let _update = self.lock_update();
+        let previous = self.shared.load_full();
+        if previous.shut_down {
+            return Err("the logger has been shut down".into());
+        }
+        if previous.appenders.iter().any(|a| a.name == name) {
+            return Err(format!("duplicate appender name `{}`", name).into());
+        }
+
+        let mut shared = previous.with_levels(previous.levels.clone());
+        let mut appenders = (*previous.appenders).clone();
+        shared.attached.push((appenders.len(), attach_to.iter().map(|&l| l.to_owned()).collect()));
+        appenders.push(Arc::new(Appender::new(name.to_owned(),
+                                              Arc::from(appender),
+                                              LevelFilter::Trace,
+                                              vec![],
+                                              None)));
+        shared.appenders = Arc::new(appenders);
+        log::set_max_level(shared.max_log_level());
+        self.shared.store(Arc::new(shared));
+        Ok(())
+    }
+
+    /// Detaches and removes an appender added by `add_appender`, flushing
+    /// it.
+    ///
+    /// The appender is dropped once events being logged concurrently have
+    /// been processed. Returns an error if there is no such appender, or if
+    /// the appender is part of the configuration, which can only be changed
+    /// with `set_config`.
+    pub fn remove_appender(&self, name: &str) -> Result<(), Box<error::Error + Sync + Send>> {
+        let update = self.lock_update();
+        let previous = self.shared.load_full();
+        let idx = match previous.appenders.iter().position(|a| a.name == name) {
+            Some(idx) => idx,
+            None => return Err(format!("no appender named `{}`", name).into()),
+        };
+        if !previous.attached.iter().any(|a| a.0 == idx) {
+            return Err(format!("appender `{}` is part of the configuration", name).into());
+        }
+
+        let mut shared = previous.with_levels(previous.levels.clone());
+        let mut appenders = (*previous.appenders).clone();
+        let appender = appenders.remove(idx);
+        shared.attached.retain(|a| a.0 != idx);
+        for attached in &mut shared.attached {
+            if attached.0 > idx {
+                attached.0 -= 1;
+            }
+        }
+        shared.appenders = Arc::new(appenders);
+        log::set_max_level(shared.max_log_level());
+        self.shared.store(Arc::new(shared));
+        drop(update);
+
+        if let Err(e) = appender.flush() {
+            let e = format!("error flushing appender `{}`: {}", appender.name, e);
+            handle_appender_error(&*Box::<error::Error + Sync + Send>::from(e), &appender.name);
+        }
+        Ok(())
+    }
+
+    fn lock_update(&self) -> MutexGuard<'_, ()> {
+        self.update.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update_levels<F>(&self, f: F)
+        where F: FnOnce(&mut Vec<(String, LevelFilter)>)
+    {
+        let _update = self.lock_update();
+        let previous = self.shared.load_full();
+        let mut levels = previous.levels.clone();
+        f(&mut levels);
+        let shared = previous.with_levels(levels);
+        log::set_max_level(shared.max_log_level());
+        self.shared.store(Arc::new(shared));
+    }
+
+    /// Loads the configuration again from the config file it was loaded from,
+    /// whether or not the file has changed.
+    ///
+    /// Returns an error if the logger wasn't initialized by `init_file` or a
+    /// related function, or if the configuration can't be loaded, in which
+    /// case the active configuration is kept.
+    pub fn reload(&self) -> Result<(), Box<error::Error + Sync + Send>> {
+        let mut reload = self.reload.lock().unwrap_or_else(|e| e.into_inner());
+        match *reload {
+            Some(ref mut reload) => reload(),
+            None => Err("the logger was not configured by a config file".into()),
+        }
+    }
+
+    fn set_reload<F>(&self, reload: F)
+        where F: FnMut() -> Result<(), Box<error::Error + Sync + Send>> + Send + 'static
+    {
+        *self.reload.lock().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(reload));
+    }
+
+    /// Closes and reopens the files of every appender, so that a log file
+    /// which has been moved away, as by `logrotate`, is replaced by a new
+    /// one.
+    ///
+    /// Errors are reported to stderr. See `Append::reopen`.
+    pub fn reopen(&self) {
+        for appender in self.shared.load_full().appenders.iter() {
+            if let Err(e) = appender.reopen() {
+                let e = format!("error reopening appender `{}`: {}", appender.name, e);
+                handle_appender_error(&*Box::<error::Error + Sync + Send>::from(e), &appender.name);
+            }
+        }
+    }
+
+    /// Blocks until every appender has written out the events it has
+    /// accepted so far, including those buffered or batched by a background
+    /// thread.
+    ///
+    /// Errors are reported to stderr. See `Append::flush`.
+    pub fn flush(&self) {
+        self.shared.load_full().flush();
     }
+
+    /// Logs an event with guaranteed delivery, returning the first error
+    /// from the appenders instead of handling it by the error policy.
+    ///
+    /// The event goes to the appenders which would receive it if it was
+    /// logged normally, regardless of the level of the logger applying to
+    /// its target, and each appender accepting it is flushed before this
+    /// returns. Every appender is tried even if an earlier one fails. The
+    /// levels of the appenders and of the references to them, and the
+    /// appenders' filters, still apply, and an error is returned if none of
+    /// them accepts the event or the logger has been shut down.
+    ///
+    /// Delivery is only as strong as the appenders: an appender which writes
+    /// from a background thread reports success once the event is queued.
+    /// `AuditAppender` syncs each event to disk before returning. See also
+    /// the `audit!` macro.
+    pub fn audit(&self, record: &Record) -> Result<(), Box<error::Error + Sync + Send>> {
+        let shared = self.shared.load_full();
+        if shared.shut_down {
+            return Err("the logger has been shut down".into());
+        }
+        SUBMITTED.add(record.level());
+        intercept(record, &shared, |record| {
+            priv_volume::add(record.target(), record.level());
+            let mut delivered = false;
+            let mut error = None;
+            for idx in shared.find(record.target()).targets(record) {
+                let appender = &shared.appenders[idx];
+                let result = appender.deliver(record).and_then(|written| {
+                    if written {
+                        appender.flush()?;
+                    }
+                    Ok(written)
+                });
+                match result {
+                    Ok(written) => delivered |= written,
+                    Err(e) => {
+                        if error.is_none() {
+                            error = Some(format!("appender `{}` failed: {}", appender.name, e));
+                        }
+                    }
+                }
+            }
+            match error {
+                Some(e) => Err(e.into()),
+                None if delivered => Ok(()),
+                None => {
+                    Err(format!("no appender accepted the event for `{}`", record.target())
+                        .into())
+                }
+            }
+        })
+    }
+
+    /// Stops logging, flushing and then closing every appender.
+    ///
+    /// Events logged after this is called are discarded, and the logger can't
+    /// be reconfigured, so file watchers and other reloaders have no effect.
+    /// Appenders are closed once events being logged concurrently have been
+    /// processed, which for batching appenders ships any remaining events.
+    ///
+    /// Returns `false` if the appenders weren't flushed within `timeout`, in
+    /// which case they continue to be flushed on a background thread. Errors
+    /// are reported to stderr.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        let config = Config::builder()
+            .default_console(LevelFilter::Off)
+            .build(config::Root::builder().build(LevelFilter::Off))
+            .unwrap();
+        let mut shared = SharedLogger::new(config);
+        let update = self.lock_update();
+        let previous = self.shared.load_full();
+        shared.history = previous.history;
+        shared.shut_down = true;
+        log::set_max_level(LevelFilter::Off);
+        self.shared.store(Arc::new(shared));
+        drop(update);
+        if previous.shut_down {
+            return true;
+        }
+        // the appenders are closed when `previous` is dropped, unless another
+        // thread is still using them
+        flush_within(previous, timeout)
+    }
+
+    /// Logs panics through the appenders before running the previously
+    /// installed panic hook.
+    ///
+    /// Each panic is logged as an error to the `panic` target with the name
+    /// of the panicking thread, the panic message, its location, and a
+    /// backtrace, which is captured regardless of `RUST_BACKTRACE`. The
+    /// previous hook, by default the one which prints to stderr, still runs
+    /// afterwards. Call `flush_on_panic` after this to have panics written
+    /// out before they bring down the process.
+    pub fn log_panics(&self) {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let message = match info.payload().downcast_ref::<&str>() {
+                Some(message) => *message,
+                None => {
+                    match info.payload().downcast_ref::<String>() {
+                        Some(message) => &**message,
+                        None => "Box<dyn Any>",
+                    }
+                }
+            };
+            let thread = thread::current();
+            let thread = thread.name().unwrap_or("<unnamed>");
+            let backtrace = Backtrace::force_capture();
+            match info.location() {
+                Some(location) => {
+                    ::log::log!(target: "panic",
+                                Level::Error,
+                                "thread '{}' panicked at '{}', {}:{}\n{}",
+                                thread,
+                                message,
+                                location.file(),
+                                location.line(),
+                                backtrace)
+                }
+                None => {
+                    ::log::log!(target: "panic",
+                                Level::Error,
+                                "thread '{}' panicked at '{}'\n{}",
+                                thread,
+                                message,
+                                backtrace)
+                }
+            }
+            previous(info);
+        }));
+    }
+
+    /// Flushes the appenders whenever a thread panics, after running the
+    /// previously installed panic hook.
+    ///
+    /// This ensures that events logged just before a panic, or by a panic
+    /// hook which logs the panic itself, reach their destinations even if
+    /// the panic brings down the process. The panicking thread waits at most
+    /// `timeout` for the appenders to be flushed.
+    pub fn flush_on_panic(&self, timeout: Duration) {
+        let handle = self.clone();
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            flush_within(handle.shared.load_full(), timeout);
+        }));
+    }
+
+    /// Flushes the appenders when the process exits by returning from `main`
+    /// or calling `std::process::exit`, waiting at most `timeout`.
+    ///
+    /// Calling this again replaces the handle and timeout used. Not available
+    /// on WebAssembly, which has no exit handlers.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn flush_on_exit(&self, timeout: Duration) {
+        let mut exit = EXIT_FLUSH.lock().unwrap_or_else(|e| e.into_inner());
+        if exit.is_none() {
+            extern "C" {
+                fn atexit(f: extern "C" fn()) -> c_int;
+            }
+            // registering fails only if the C runtime has run out of slots
+            if unsafe { atexit(flush_at_exit) } != 0 {
+                let e = "unable to register an exit handler";
+                handle_error(&*Box::<error::Error + Sync + Send>::from(e));
+                return;
+            }
+        }
+        *exit = Some((self.clone(), timeout));
+    }
+
+    /// Returns the time at which the logger was initialized.
+    pub fn initialized_at(&self) -> SystemTime {
+        self.shared.load_full().history.initialized
+    }
+
+    /// Returns the time at which the active configuration replaced the
+    /// previous one, or `None` if the logger hasn't been reconfigured.
+    pub fn reloaded_at(&self) -> Option<SystemTime> {
+        self.shared.load_full().history.reloaded
+    }
+
+    /// Returns the number of times the logger has been reconfigured.
+    pub fn reloads(&self) -> u64 {
+        self.shared.load_full().history.reloads
+    }
+
+    /// Returns a snapshot of the statistics of the appenders in the active
+    /// configuration.
+    ///
+    /// An appender's statistics are reset when a new configuration replaces
+    /// it.
+    pub fn stats(&self) -> Stats {
+        let shared = self.shared.load_full();
+        let appenders = shared.appenders
+            .iter()
+            .map(|a| (a.name.clone(), a.counters.snapshot(a.appender.bytes_written())))
+            .collect();
+        Stats::new(appenders)
+    }
+
+    /// Starts keeping rolling counts of the events written to each target at
+    /// each level over the specified windows, like the last minute and the
+    /// last hour, discarding any previous counts.
+    ///
+    /// Events discarded by the level of their logger aren't counted; those
+    /// discarded by appenders are. Up to 10,000 targets are tracked, after
+    /// which events to other targets are counted under `<other>`. Passing no
+    /// windows stops counting. Counts are kept across configurations.
+    pub fn track_volume(&self, windows: &[Duration]) {
+        priv_volume::track(windows);
+    }
+
+    /// Returns the `n` targets and levels with the most events written over
+    /// the specified window, busiest first.
+    ///
+    /// The window must be one passed to `track_volume`; `None` is returned
+    /// otherwise. Counts are kept in 60 buckets per window, so events older
+    /// than the window may be counted for up to a sixtieth of it.
+    pub fn top_targets(&self, window: Duration, n: usize) -> Option<Vec<TargetVolume>> {
+        priv_volume::top(window, n)
+    }
+
+    /// Returns a snapshot of the loggers and appenders of the active
+    /// configuration.
+    pub fn topology(&self) -> Topology {
+        let shared = self.shared.load_full();
+        let logger = |name: &str, level, additive, appenders: &[String]| {
+            LoggerInfo::new(name.to_owned(),
+                            shared.level_override(name).unwrap_or(level),
+                            level,
+                            additive,
+                            appenders.to_vec())
+        };
+        let (ref root, ref loggers, _) = shared.config;
+        let root = logger("", root.level(), false, root.appenders());
+        let loggers = loggers.iter()
+            .map(|l| logger(l.name(), l.level(), l.additive(), l.appenders()))
+            .collect();
+        let appenders = shared.appenders
+            .iter()
+            .map(|a| {
+                AppenderInfo::new(a.name.clone(),
+                                  a.kind.clone(),
+                                  a.level,
+                                  a.appender.encoder().map(|e| format!("{:?}", e)),
+                                  a.filters.iter().map(|f| format!("{:?}", f)).collect(),
+                                  format!("{:?}", a.appender))
+            })
+            .collect();
+        Topology::new(root, loggers, appenders)
+    }
+}
+
+trait MetricsPrivateExt {
+    fn new(submitted: LevelCounts,
+           filtered: LevelCounts,
+           appenders: Vec<(String, AppenderMetrics)>)
+           -> Self;
+}
+
+trait AppenderMetricsPrivateExt {
+    fn new(written: LevelCounts,
+           filtered: LevelCounts,
+           dropped: LevelCounts,
+           errored: LevelCounts)
+           -> Self;
+}
+
+trait LevelCountsPrivateExt {
+    fn new(counts: [u64; 5]) -> Self;
+}
+
+trait TargetVolumePrivateExt {
+    fn new(target: String, level: Level, count: u64) -> Self;
+}
+
+trait RecordPrivateExt {
+    fn new(level: Level, target: String, message: String) -> Self;
+}
+
+trait TopologyPrivateExt {
+    fn new(root: LoggerInfo, loggers: Vec<LoggerInfo>, appenders: Vec<AppenderInfo>) -> Self;
+}
+
+trait LoggerInfoPrivateExt {
+    fn new(name: String,
+           level: LevelFilter,
+           configured_level: LevelFilter,
+           additive: bool,
+           appenders: Vec<String>)
+           -> Self;
+}
+
+trait AppenderInfoPrivateExt {
+    fn new(name: String,
+           kind: Option<String>,
+           level: LevelFilter,
+           encoder: Option<String>,
+           filters: Vec<String>,
+           description: String)
+           -> Self;
 }
 
 trait ErrorInternals {
@@ -462,42 +1870,768 @@ trait ErrorInternals {
 }
 
 trait ConfigPrivateExt {
-    fn unpack(self) -> (Vec<config::Appender>, config::Root, Vec<config::Logger>);
+    fn unpack(self)
+              -> (Vec<config::Appender>, config::Root, Vec<config::Logger>, config::ErrorPolicy);
+}
+
+trait ConfigBuilderPrivateExt {
+    fn unpack(self) -> (Vec<config::Appender>, Vec<config::Logger>, Vec<Box<Interceptor>>);
+}
+
+#[cfg(feature = "file")]
+trait RawConfigPrivateExt {
+    fn appenders_reusing<F>(&self,
+                            deserializers: &file::Deserializers,
+                            reuse: F)
+                            -> (Vec<config::Appender>, Vec<file::Error>)
+        where F: FnMut(&str, &append::AppenderConfig) -> Option<Arc<Append>>;
+}
+
+trait StatsPrivateExt {
+    fn new(appenders: Vec<(String, AppenderStats)>) -> Self;
+}
+
+trait AppenderStatsPrivateExt {
+    fn new(records: u64, bytes: Option<u64>, errors: u64, filtered: u64, drops: u64) -> Self;
 }
 
 trait PrivateConfigAppenderExt {
-    fn unpack(self) -> (String, Box<Append>, Vec<Box<Filter>>);
+    fn unpack(self) -> (String, Arc<Append>, Vec<Box<Filter>>);
+
+    fn shared(&self) -> Arc<Append>;
 }
 
 #[cfg(test)]
 mod test {
-    use log::{LogLevel, LogLevelFilter};
+    use log::{Level, LevelFilter};
+    use std::io;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
     use super::*;
 
     #[test]
     fn enabled() {
-        let root = config::Root::builder().build(LogLevelFilter::Debug);
+        let root = config::Root::builder().build(LevelFilter::Debug);
         let mut config = config::Config::builder();
-        let logger = config::Logger::builder().build("foo::bar", LogLevelFilter::Trace);
+        let logger = config::Logger::builder().build("foo::bar", LevelFilter::Trace);
         config = config.logger(logger);
-        let logger = config::Logger::builder().build("foo::bar::baz", LogLevelFilter::Off);
+        let logger = config::Logger::builder().build("foo::bar::baz", LevelFilter::Off);
         config = config.logger(logger);
-        let logger = config::Logger::builder().build("foo::baz::buz", LogLevelFilter::Error);
+        let logger = config::Logger::builder().build("foo::baz::buz", LevelFilter::Error);
         config = config.logger(logger);
         let config = config.build(root).unwrap();
 
         let logger = super::Logger::new(config);
 
-        assert!(logger.enabled_inner(LogLevel::Warn, "bar"));
-        assert!(!logger.enabled_inner(LogLevel::Trace, "bar"));
-        assert!(logger.enabled_inner(LogLevel::Debug, "foo"));
-        assert!(logger.enabled_inner(LogLevel::Trace, "foo::bar"));
-        assert!(!logger.enabled_inner(LogLevel::Error, "foo::bar::baz"));
-        assert!(logger.enabled_inner(LogLevel::Debug, "foo::bar::bazbuz"));
-        assert!(!logger.enabled_inner(LogLevel::Error, "foo::bar::baz::buz"));
-        assert!(!logger.enabled_inner(LogLevel::Warn, "foo::baz::buz"));
-        assert!(!logger.enabled_inner(LogLevel::Warn, "foo::baz::buz::bar"));
-        assert!(logger.enabled_inner(LogLevel::Error, "foo::baz::buz::bar"));
+        assert!(logger.enabled_inner(Level::Warn, "bar"));
+        assert!(!logger.enabled_inner(Level::Trace, "bar"));
+        assert!(logger.enabled_inner(Level::Debug, "foo"));
+        assert!(logger.enabled_inner(Level::Trace, "foo::bar"));
+        assert!(!logger.enabled_inner(Level::Error, "foo::bar::baz"));
+        assert!(logger.enabled_inner(Level::Debug, "foo::bar::bazbuz"));
+        assert!(!logger.enabled_inner(Level::Error, "foo::bar::baz::buz"));
+        assert!(!logger.enabled_inner(Level::Warn, "foo::baz::buz"));
+        assert!(!logger.enabled_inner(Level::Warn, "foo::baz::buz::bar"));
+        assert!(logger.enabled_inner(Level::Error, "foo::baz::buz::bar"));
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_matches("*::sqlx*", "app::sqlx_core"));
+        assert!(glob_matches("a*b*c", "abxbc"));
+        assert!(!glob_matches("a*b*c", "abxbcd"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("main", "main"));
+        assert!(!glob_matches("main", "mains"));
+        assert!(glob_matches("tokio-runtime-worker*", "tokio-runtime-worker"));
+        assert!(!glob_matches("tokio-runtime-worker*", "tokio-blocking"));
+        assert!(glob_matches("*-job", "billing-job"));
+        assert!(glob_matches("a*b*c", "axxbyybc"));
+        assert!(!glob_matches("a*b*c", "axxbyyb"));
+        assert!(glob_matches("worker-?", "worker-1"));
+        assert!(!glob_matches("worker-?", "worker-12"));
+        assert!(glob_matches("caf\u{e9}?*", "caf\u{e9}\u{e9}\u{e9}"));
+        assert!(glob_matches("*\u{e9}", "\u{e9}\u{e9}"));
+        assert!(!glob_matches("?", "\u{e9}\u{e9}"));
+
+        let root = config::Root::builder().build(LevelFilter::Info);
+        let config = config::Config::builder()
+            .logger(config::Logger::builder().build("app::handlers::*", LevelFilter::Debug))
+            .logger(config::Logger::builder().build("*::sqlx*", LevelFilter::Warn))
+            .logger(config::Logger::builder().build("app::sqlx", LevelFilter::Trace))
+            .logger(config::Logger::builder().build("*", LevelFilter::Error))
+            .build(root)
+            .unwrap();
+
+        let logger = super::Logger::new(config);
+
+        assert!(logger.enabled_inner(Level::Debug, "app::handlers::users"));
+        assert!(logger.enabled_inner(Level::Debug, "app::handlers::users::get"));
+        assert!(!logger.enabled_inner(Level::Debug, "app::handlers"));
+        assert!(!logger.enabled_inner(Level::Info, "db::sqlx_core::pool"));
+        // `app::sqlx` is more specific than `*::sqlx*`
+        assert!(logger.enabled_inner(Level::Trace, "app::sqlx"));
+        // but `*::sqlx*` is more specific than the root
+        assert!(!logger.enabled_inner(Level::Info, "app::sqlx_core"));
+        // and `*` isn't
+        assert!(logger.enabled_inner(Level::Info, "other"));
+        assert_eq!(logger.0.load().max_log_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn metrics() {
+        let counts = appender_counts("metrics");
+        counts.written.add(Level::Warn);
+        counts.written.add(Level::Warn);
+        counts.errored.add(Level::Debug);
+        assert!(Arc::ptr_eq(&counts, &appender_counts("metrics")));
+
+        let metrics = super::metrics();
+        let appender = metrics.appender("metrics").unwrap();
+        assert_eq!(appender.written().get(Level::Warn), 2);
+        assert_eq!(appender.written().get(Level::Error), 0);
+        assert_eq!(appender.errored().total(), 1);
+        assert_eq!(appender.dropped().total(), 0);
+    }
+
+    #[test]
+    fn counters() {
+        let appender = |name: &str, appender: Arc<Append>| {
+            Appender::new(name.to_owned(), appender, LevelFilter::Info, vec![], None)
+        };
+        let record = |level| Record::builder().level(level).args(format_args!("")).build();
+
+        let nop = appender("counters_nop", Arc::new(NopAppender));
+        nop.append(&record(Level::Info)).unwrap();
+        nop.append(&record(Level::Debug)).unwrap();
+        let stats = nop.counters.snapshot(None);
+        assert_eq!(stats.records(), 1);
+        assert_eq!(stats.filtered(), 1);
+        assert_eq!(stats.drops(), 0);
+
+        let fail = appender("counters_fail", Arc::new(FailAppender));
+        assert!(fail.append(&record(Level::Warn)).is_err());
+        let stats = fail.counters.snapshot(None);
+        assert_eq!(stats.errors(), 1);
+        assert_eq!(stats.filtered(), 0);
+        assert_eq!(stats.drops(), 1);
+
+        let metrics = super::metrics();
+        let nop = metrics.appender("counters_nop").unwrap();
+        assert_eq!(nop.filtered().get(Level::Debug), 1);
+        assert_eq!(nop.dropped().total(), 0);
+        let fail = metrics.appender("counters_fail").unwrap();
+        assert_eq!(fail.errored().get(Level::Warn), 1);
+        assert_eq!(fail.dropped().get(Level::Warn), 1);
+
+        // stands in for an appender whose queue discards the event
+        #[cfg(feature = "shadow_appender")]
+        {
+            #[derive(Debug)]
+            struct DropAppender;
+
+            impl Append for DropAppender {
+                fn append(&self, record: &Record) -> Result<(), Box<error::Error + Sync + Send>> {
+                    drop_event(record.level());
+                    Ok(())
+                }
+            }
+
+            let drop = appender("counters_drop", Arc::new(DropAppender));
+            drop.append(&record(Level::Info)).unwrap();
+            assert_eq!(drop.counters.snapshot(None).drops(), 1);
+            drop_event(Level::Info);
+            assert_eq!(drop.counters.snapshot(None).drops(), 1);
+            let metrics = super::metrics();
+            assert_eq!(metrics.appender("counters_drop").unwrap().dropped().get(Level::Info), 1);
+        }
+    }
+
+    #[test]
+    fn dispatch() {
+        #[derive(Debug, Default)]
+        struct Exclusive {
+            thread_safe: bool,
+            running: AtomicBool,
+        }
+
+        impl Append for Exclusive {
+            fn append(&self, _: &Record) -> Result<(), Box<error::Error + Sync + Send>> {
+                if self.running.swap(true, Ordering::SeqCst) {
+                    return Err("called concurrently".into());
+                }
+                thread::yield_now();
+                self.running.store(false, Ordering::SeqCst);
+                Ok(())
+            }
+
+            fn thread_safe(&self) -> bool {
+                self.thread_safe
+            }
+        }
+
+        let appender = Arc::new(Appender::new("dispatch".to_owned(),
+                                              Arc::new(Exclusive::default()),
+                                              LevelFilter::Info,
+                                              vec![],
+                                              None));
+        let threads = (0..8)
+            .map(|_| {
+                let appender = appender.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        let record = Record::builder()
+                            .level(Level::Info)
+                            .args(format_args!(""))
+                            .build();
+                        appender.append(&record).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // thread-safe appenders are called without a lock
+        let exclusive = Exclusive {
+            thread_safe: true,
+            ..Exclusive::default()
+        };
+        let appender = Appender::new("dispatch".to_owned(),
+                                     Arc::new(exclusive),
+                                     LevelFilter::Info,
+                                     vec![],
+                                     None);
+        assert!(appender.dispatch.is_none());
+    }
+
+    #[derive(Debug)]
+    struct NopAppender;
+
+    impl Append for NopAppender {
+        fn append(&self, _: &Record) -> Result<(), Box<error::Error + Sync + Send>> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn handle() {
+        let config = |level| {
+            config::Config::builder()
+                .appender(config::Appender::builder().build("nop", Box::new(NopAppender)))
+                .logger(config::Logger::builder().appender("nop").build("foo", level))
+                .build(config::Root::builder().build(LevelFilter::Warn))
+                .unwrap()
+        };
+
+        let handle = init_config(config(LevelFilter::Debug)).unwrap();
+        assert_eq!(handle.appenders(), vec!["nop".to_owned()]);
+        assert_eq!(handle.loggers()[0].name(), "foo");
+        assert_eq!(handle.level("foo::bar"), LevelFilter::Debug);
+        assert_eq!(handle.level("bar"), LevelFilter::Warn);
+        assert_eq!(handle.reloads(), 0);
+        assert!(handle.reloaded_at().is_none());
+
+        handle.clone().set_config(config(LevelFilter::Trace));
+        assert_eq!(handle.level("foo::bar"), LevelFilter::Trace);
+        assert_eq!(handle.reloads(), 1);
+        assert!(handle.reloaded_at().unwrap() >= handle.initialized_at());
+
+        handle.set_level("foo", LevelFilter::Info);
+        let topology = handle.topology();
+        assert_eq!(topology.root().level(), LevelFilter::Warn);
+        assert_eq!(topology.loggers()[0].level(), LevelFilter::Info);
+        assert_eq!(topology.loggers()[0].configured_level(), LevelFilter::Trace);
+        assert_eq!(topology.loggers()[0].appenders(), &["nop".to_owned()]);
+        let appender = topology.appender("nop").unwrap();
+        assert_eq!(appender.kind(), None);
+        assert_eq!(appender.encoder(), None);
+        assert_eq!(appender.description(), "NopAppender");
+        handle.reset_level("foo");
+
+        let setters = (0..8).map(|i| {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                handle.set_level(&format!("concurrent{}", i), LevelFilter::Error);
+                handle.set_config(config(LevelFilter::Trace));
+            })
+        }).collect::<Vec<_>>();
+        for setter in setters {
+            setter.join().unwrap();
+        }
+        for i in 0..8 {
+            assert_eq!(handle.level(&format!("concurrent{}", i)), LevelFilter::Error);
+            handle.reset_level(&format!("concurrent{}", i));
+        }
+        assert_eq!(handle.reloads(), 9);
+
+        #[derive(Debug)]
+        struct Demote;
+
+        impl Interceptor for Demote {
+            fn intercept(&self, record: &mut intercept::Record) {
+                if record.target() != "intercepted" {
+                    return;
+                }
+                record.set_level(Level::Info);
+                let message = format!("[demoted] {}", record.message());
+                record.set_message(message);
+            }
+        }
+
+        let records = Arc::new(Mutex::new(vec![]));
+        handle.set_config(config::Config::builder()
+            .appender(config::Appender::builder()
+                .build("record", Box::new(RecordAppender(records.clone()))))
+            .interceptor(Box::new(Demote))
+            .build(config::Root::builder().appender("record").build(LevelFilter::Info))
+            .unwrap());
+        ::log::log!(target: "intercepted", Level::Error, "disk {}", "full");
+        assert_eq!(*records.lock().unwrap(),
+                   vec![(Level::Info, "intercepted".to_owned(), "[demoted] disk full".to_owned())]);
+        ::log::log!(target: "scoped", Level::Debug, "hidden");
+        {
+            let _verbose = verbose_scope("scoped", LevelFilter::Debug);
+            ::log::log!(target: "scoped", Level::Debug, "shown");
+        }
+        ::log::log!(target: "scoped", Level::Debug, "hidden");
+        assert_eq!(records.lock().unwrap()[1..],
+                   [(Level::Debug, "scoped".to_owned(), "shown".to_owned())]);
+
+        // the hook is process wide, so only panics of this thread are counted
+        let this = thread::current().id();
+        let chained = Arc::new(AtomicU64::new(0));
+        let chained2 = chained.clone();
+        panic::set_hook(Box::new(move |_| {
+            if thread::current().id() == this {
+                chained2.fetch_add(1, Ordering::SeqCst);
+            }
+        }));
+        handle.log_panics();
+        let line = line!() + 1;
+        assert!(panic::catch_unwind(|| panic!("oh no")).is_err());
+        let doomed = thread::Builder::new().name("doomed".to_owned()).spawn(|| panic!("oh no"));
+        assert!(doomed.unwrap().join().is_err());
+        let _ = panic::take_hook();
+        assert_eq!(chained.load(Ordering::SeqCst), 1);
+        {
+            let records = records.lock().unwrap();
+            let (level, ref target, ref message) = records[2];
+            assert_eq!((level, &**target), (Level::Error, "panic"));
+            let expected = format!("thread '{}' panicked at 'oh no', src/lib.rs:{}\n",
+                                   thread::current().name().unwrap(),
+                                   line);
+            assert!(message.starts_with(&expected), "{}", message);
+            let (level, ref target, ref message) = records[3];
+            assert_eq!((level, &**target), (Level::Error, "panic"));
+            assert!(message.starts_with("thread 'doomed' panicked at 'oh no', src/lib.rs:"),
+                    "{}",
+                    message);
+        }
+
+        let flushes = Arc::new(AtomicU64::new(0));
+        handle.set_config(config::Config::builder()
+            .appender(config::Appender::builder()
+                .build("flush", Box::new(FlushAppender(flushes.clone()))))
+            .build(config::Root::builder().appender("flush").build(LevelFilter::Warn))
+            .unwrap());
+        handle.flush();
+        assert_eq!(flushes.load(Ordering::Relaxed), 1);
+
+        let captured = Arc::new(Mutex::new(vec![]));
+        let capture = Box::new(RecordAppender(captured.clone()));
+        handle.add_appender("capture", capture, &["app::db"]).unwrap();
+        assert!(handle.add_appender("flush", Box::new(NopAppender), &[]).is_err());
+        ::log::log!(target: "app::db::pool", Level::Warn, "slow query");
+        ::log::log!(target: "app", Level::Warn, "elsewhere");
+        assert_eq!(*captured.lock().unwrap(),
+                   vec![(Level::Warn, "app::db::pool".to_owned(), "slow query".to_owned())]);
+        assert!(handle.remove_appender("flush").is_err());
+        handle.remove_appender("capture").unwrap();
+        assert!(handle.remove_appender("capture").is_err());
+        ::log::log!(target: "app::db", Level::Warn, "detached");
+        assert_eq!(captured.lock().unwrap().len(), 1);
+
+        let names = (0..8).map(|i| format!("concurrent{}", i)).collect::<Vec<_>>();
+        let adders = names.iter().cloned().map(|name| {
+            let handle = handle.clone();
+            thread::spawn(move || handle.add_appender(&name, Box::new(NopAppender), &[]).unwrap())
+        }).collect::<Vec<_>>();
+        for adder in adders {
+            adder.join().unwrap();
+        }
+        assert!(names.iter().all(|n| handle.appenders().contains(n)));
+        let removers = names.iter().cloned().map(|name| {
+            let handle = handle.clone();
+            thread::spawn(move || handle.remove_appender(&name).unwrap())
+        }).collect::<Vec<_>>();
+        for remover in removers {
+            remover.join().unwrap();
+        }
+        assert_eq!(handle.appenders(), vec!["flush".to_owned()]);
+
+        audit!(target: "audit", "settled payment {}", 42).unwrap();
+        assert_eq!(flushes.load(Ordering::Relaxed), 2);
+        handle.add_appender("fail", Box::new(FailAppender), &["audit::failing"]).unwrap();
+        let err = audit!(target: "audit::failing", "lost").unwrap_err();
+        assert_eq!(err.to_string(), "appender `fail` failed: disk full");
+        assert_eq!(flushes.load(Ordering::Relaxed), 3);
+        handle.remove_appender("fail").unwrap();
+
+        assert!(handle.shutdown(Duration::from_secs(5)));
+        assert_eq!(flushes.load(Ordering::Relaxed), 4);
+        assert!(handle.appenders().is_empty());
+        assert!(audit!("after shutdown").is_err());
+        handle.set_config(config(LevelFilter::Debug));
+        assert!(handle.appenders().is_empty());
+    }
+
+    // A logger and a handle to it which aren't installed as the global
+    // logger. The root level is `Trace`, so that changes made through the
+    // handle don't lower the global maximum level other tests rely on.
+    fn local(records: &Records) -> (super::Logger, Handle) {
+        let config = config::Config::builder()
+            .appender(config::Appender::builder()
+                .build("record", Box::new(RecordAppender(records.clone()))))
+            .logger(config::Logger::builder()
+                .appender("record")
+                .additive(false)
+                .build("app", LevelFilter::Info))
+            .build(config::Root::builder().build(LevelFilter::Trace))
+            .unwrap();
+        unattached(config)
+    }
+
+    fn unattached(config: config::Config) -> (super::Logger, Handle) {
+        let logger = super::Logger::new(config);
+        let handle = Handle {
+            shared: logger.0.clone(),
+            update: Arc::default(),
+            reload: Arc::default(),
+        };
+        (logger, handle)
+    }
+
+    fn log_to(logger: &super::Logger, target: &str, message: &str) {
+        ::log::Log::log(logger,
+                        &Record::builder()
+                            .level(Level::Warn)
+                            .target(target)
+                            .args(format_args!("{}", message))
+                            .build());
+    }
+
+    #[test]
+    fn add_appender() {
+        let records = Arc::new(Mutex::new(vec![]));
+        let (logger, handle) = local(&records);
+        let captured = Arc::new(Mutex::new(vec![]));
+        handle.add_appender("capture", Box::new(RecordAppender(captured.clone())), &["app::db"])
+            .unwrap();
+        assert_eq!(handle.appenders(), vec!["record".to_owned(), "capture".to_owned()]);
+
+        log_to(&logger, "app::db::pool", "slow query");
+        log_to(&logger, "app", "elsewhere");
+        assert_eq!(*captured.lock().unwrap(),
+                   vec![(Level::Warn, "app::db::pool".to_owned(), "slow query".to_owned())]);
+        // the configured appenders are unaffected
+        assert_eq!(records.lock().unwrap().len(), 2);
+
+        // attached to the root logger
+        let everything = Arc::new(Mutex::new(vec![]));
+        handle.add_appender("everything", Box::new(RecordAppender(everything.clone())), &[""])
+            .unwrap();
+        log_to(&logger, "other", "unconfigured");
+        assert_eq!(*everything.lock().unwrap(),
+                   vec![(Level::Warn, "other".to_owned(), "unconfigured".to_owned())]);
+
+        let err = handle.add_appender("record", Box::new(NopAppender), &[]).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate appender name `record`");
+    }
+
+    #[test]
+    fn remove_appender() {
+        let records = Arc::new(Mutex::new(vec![]));
+        let (logger, handle) = local(&records);
+        let before = handle.shared.load_full();
+
+        // the configured appender is still referenced by the `app` logger
+        let err = handle.remove_appender("record").unwrap_err();
+        assert_eq!(err.to_string(), "appender `record` is part of the configuration");
+        let err = handle.remove_appender("missing").unwrap_err();
+        assert_eq!(err.to_string(), "no appender named `missing`");
+        assert!(Arc::ptr_eq(&before, &handle.shared.load_full()));
+        assert_eq!(handle.appenders(), vec!["record".to_owned()]);
+        assert_eq!(handle.loggers()[0].appenders(), ["record".to_owned()]);
+        log_to(&logger, "app", "kept");
+        assert_eq!(records.lock().unwrap().len(), 1);
+
+        let flushes = Arc::new(AtomicU64::new(0));
+        handle.add_appender("flush", Box::new(FlushAppender(flushes.clone())), &["app"]).unwrap();
+        handle.remove_appender("flush").unwrap();
+        assert_eq!(flushes.load(Ordering::Relaxed), 1);
+        assert_eq!(handle.appenders(), vec!["record".to_owned()]);
+        assert!(handle.shared.load().attached.is_empty());
+        log_to(&logger, "app", "after");
+        assert_eq!(records.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    #[cfg(all(feature = "console_appender", feature = "threshold_filter"))]
+    fn topology() {
+        use append::console::ConsoleAppender;
+        use encode::pattern::PatternEncoder;
+        use filter::threshold::ThresholdFilter;
+
+        let console = ConsoleAppender::builder()
+            .encoder(Box::new(PatternEncoder::new("{m}{n}")))
+            .build();
+        let config = config::Config::builder()
+            .appender(config::Appender::builder()
+                .kind("console")
+                .level(LevelFilter::Warn)
+                .filter(Box::new(ThresholdFilter::new(LevelFilter::Error)))
+                .build("console", Box::new(console)))
+            .appender(config::Appender::builder()
+                .build("record", Box::new(RecordAppender(Arc::default()))))
+            .logger(config::Logger::builder()
+                .appender("record")
+                .additive(false)
+                .build("app", LevelFilter::Info))
+            .logger(config::Logger::builder()
+                .appender("console")
+                .build("app::db", LevelFilter::Debug))
+            .build(config::Root::builder().appender("console").build(LevelFilter::Trace))
+            .unwrap();
+        let (_logger, handle) = unattached(config);
+        handle.set_level("app::db", LevelFilter::Trace);
+        handle.add_appender("added", Box::new(NopAppender), &["app"]).unwrap();
+
+        let topology = handle.topology();
+        let root = topology.root();
+        assert_eq!((root.name(), root.level(), root.additive()), ("", LevelFilter::Trace, false));
+        assert_eq!(root.appenders(), ["console".to_owned()]);
+
+        let loggers = topology.loggers()
+            .iter()
+            .map(|l| {
+                (l.name(), l.level(), l.configured_level(), l.additive(), l.appenders().to_vec())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(loggers,
+                   [("app", LevelFilter::Info, LevelFilter::Info, false, vec!["record".to_owned()]),
+                    ("app::db",
+                     LevelFilter::Trace,
+                     LevelFilter::Debug,
+                     true,
+                     vec!["console".to_owned()])]);
+
+        // sorted by name, including those added through the handle
+        let names = topology.appenders().iter().map(|a| a.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["added", "console", "record"]);
+        let console = topology.appender("console").unwrap();
+        assert_eq!((console.kind(), console.level()), (Some("console"), LevelFilter::Warn));
+        assert_eq!(console.encoder(), Some("PatternEncoder { pattern: \"{m}{n}\" }"));
+        assert_eq!(console.filters(), ["ThresholdFilter { level: Error }".to_owned()]);
+        assert!(console.description().starts_with("ConsoleAppender { encoder: PatternEncoder"));
+        let record = topology.appender("record").unwrap();
+        assert_eq!((record.kind(), record.level()), (None, LevelFilter::Trace));
+        assert_eq!(record.encoder(), None);
+        assert!(record.filters().is_empty());
+        assert!(topology.appender("missing").is_none());
+    }
+
+    #[test]
+    fn error_handler() {
+        static ERRORS: Mutex<Vec<(String, Option<String>)>> = Mutex::new(vec![]);
+        set_error_handler(|e, appender| {
+            // reported to stderr rather than recursing
+            handle_error(&io::Error::other("nested"));
+            ERRORS.lock().unwrap().push((e.to_string(), appender.map(ToOwned::to_owned)));
+        });
+
+        handle_appender_error(&io::Error::other("disk full"), "error_handler");
+        let errors = ERRORS.lock().unwrap();
+        assert!(errors.contains(&("disk full".to_owned(), Some("error_handler".to_owned()))));
+        assert!(!errors.iter().any(|e| e.0 == "nested"));
+    }
+
+    #[test]
+    fn flush_within() {
+        let flushes = Arc::new(AtomicU64::new(0));
+        let config = config::Config::builder()
+            .appender(config::Appender::builder()
+                .build("flush", Box::new(FlushAppender(flushes.clone()))))
+            .build(config::Root::builder().build(LevelFilter::Warn))
+            .unwrap();
+
+        assert!(super::flush_within(Arc::new(SharedLogger::new(config)), Duration::from_secs(5)));
+        assert_eq!(flushes.load(Ordering::Relaxed), 1);
+    }
+
+    type Records = Arc<Mutex<Vec<(Level, String, String)>>>;
+
+    #[derive(Debug)]
+    struct RecordAppender(Records);
+
+    impl Append for RecordAppender {
+        fn append(&self, record: &Record) -> Result<(), Box<error::Error + Sync + Send>> {
+            let record = (record.level(), record.target().to_owned(), record.args().to_string());
+            self.0.lock().unwrap().push(record);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct FlushAppender(Arc<AtomicU64>);
+
+    impl Append for FlushAppender {
+        fn append(&self, _: &Record) -> Result<(), Box<error::Error + Sync + Send>> {
+            Ok(())
+        }
+
+        fn flush(&self) -> Result<(), Box<error::Error + Sync + Send>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct FailAppender;
+
+    impl Append for FailAppender {
+        fn append(&self, _: &Record) -> Result<(), Box<error::Error + Sync + Send>> {
+            Err("disk full".into())
+        }
+    }
+
+    #[test]
+    fn levels() {
+        let config = config::Config::builder()
+            .logger(config::Logger::builder().build("foo::bar", LevelFilter::Trace))
+            .build(config::Root::builder().build(LevelFilter::Warn))
+            .unwrap();
+        let mut shared = SharedLogger::new(config);
+
+        shared = shared.with_levels(vec![("foo".to_owned(), LevelFilter::Debug),
+                                         ("foo::bar::baz".to_owned(), LevelFilter::Off)]);
+        assert_eq!(shared.find("foo").level, LevelFilter::Debug);
+        assert_eq!(shared.find("foo::bar").level, LevelFilter::Debug);
+        assert_eq!(shared.find("foo::bar::baz::buz").level, LevelFilter::Off);
+        assert_eq!(shared.find("foobar").level, LevelFilter::Warn);
+        assert_eq!(shared.max_log_level(), LevelFilter::Trace);
+
+        shared = shared.with_levels(vec![("".to_owned(), LevelFilter::Error)]);
+        assert_eq!(shared.find("foo::bar").level, LevelFilter::Error);
+        assert_eq!(shared.find("other").level, LevelFilter::Error);
+    }
+
+    #[test]
+    fn reference_levels() {
+        let appender = |name: &str| {
+            config::Appender::builder().build(name, Box::new(NopAppender) as Box<Append>)
+        };
+        let config = config::Config::builder()
+            .appender(appender("a"))
+            .appender(appender("b"))
+            .logger(config::Logger::builder()
+                .appender_with_level("b", LevelFilter::Error)
+                .build("foo", LevelFilter::Trace))
+            .build(config::Root::builder()
+                .appender("a")
+                .appender_with_level("b", LevelFilter::Info)
+                .build(LevelFilter::Trace))
+            .unwrap();
+        let shared = SharedLogger::new(config);
+
+        let targets = |level, target| {
+            let args = format_args!("");
+            let record = Record::builder().level(level).target(target).args(args).build();
+            shared.find(target).targets(&record).collect::<Vec<_>>()
+        };
+        assert_eq!(targets(Level::Info, "bar"), [0, 1]);
+        assert_eq!(targets(Level::Debug, "bar"), [0]);
+        // an additive logger passes events to its parent's appenders up to
+        // the parent's levels
+        assert_eq!(targets(Level::Warn, "foo"), [0, 1]);
+    }
+
+    #[derive(Debug)]
+    struct WarnFilter;
+
+    impl Filter for WarnFilter {
+        fn filter(&self, record: &Record) -> filter::Response {
+            if record.level() > Level::Warn {
+                filter::Response::Reject
+            } else {
+                filter::Response::Neutral
+            }
+        }
+
+        fn max_level(&self) -> LevelFilter {
+            LevelFilter::Warn
+        }
+    }
+
+    #[test]
+    fn max_level() {
+        #[derive(Debug)]
+        struct Tag;
+
+        impl Interceptor for Tag {
+            fn intercept(&self, _: &mut intercept::Record) {}
+        }
+
+        let config = |appenders: Vec<config::Appender>, intercept: bool| {
+            let mut config = config::Config::builder()
+                .appenders(appenders)
+                .default_console(LevelFilter::Off);
+            if intercept {
+                config = config.interceptor(Box::new(Tag));
+            }
+            config.logger(config::Logger::builder().build("foo", LevelFilter::Trace))
+                .build(config::Root::builder().build(LevelFilter::Warn))
+                .unwrap()
+        };
+        let appenders = || {
+            vec![config::Appender::builder()
+                     .level(LevelFilter::Info)
+                     .build("info", Box::new(NopAppender)),
+                 config::Appender::builder()
+                     .filter(Box::new(WarnFilter))
+                     .build("warn", Box::new(NopAppender))]
+        };
+
+        // no appender accepts `foo`'s debug and trace events
+        let shared = SharedLogger::new(config(appenders(), false));
+        assert_eq!(shared.max_log_level(), LevelFilter::Info);
+        let shared = SharedLogger::new(config(vec![], false));
+        assert_eq!(shared.max_log_level(), LevelFilter::Off);
+        let shared = SharedLogger::new(config(appenders(), true));
+        assert_eq!(shared.max_log_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn retain() {
+        let shared: Arc<Append> = Arc::new(NopAppender);
+        let config = |shared: &Arc<Append>| {
+            config::Config::builder()
+                .appender(config::Appender::builder().build_shared("shared", shared.clone()))
+                .appender(config::Appender::builder().build("new", Box::new(NopAppender)))
+                .build(config::Root::builder().build(LevelFilter::Info))
+                .unwrap()
+        };
+
+        let old = SharedLogger::new(config(&shared));
+        for appender in old.appenders.iter() {
+            appender.counters.record();
+        }
+        let mut new = SharedLogger::new(config(&shared));
+        new.retain(&old);
+
+        assert!(Arc::ptr_eq(&new.appenders[0].appender, &shared));
+        assert_eq!(new.appenders[0].counters.records.get(), 1);
+        assert_eq!(new.appenders[1].counters.records.get(), 0);
     }
 }
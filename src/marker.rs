@@ -0,0 +1,101 @@
+//! Markers.
+//!
+//! A marker names a class of log events which cuts across levels and targets,
+//! like `AUDIT` or `SECURITY`. The `log` crate has no way to attach extra data
+//! to a record, so markers are scoped to the current thread like the MDC: any
+//! event logged while a marker is active carries it.
+//!
+//! ```
+//! # #[macro_use] extern crate log;
+//! # extern crate log4rs;
+//! # fn main() {
+//! log4rs::marker::with("AUDIT", || {
+//!     info!("user {} logged in", "alice");
+//! });
+//!
+//! // or, for a single statement
+//! log4rs::marked!("AUDIT", info!("user {} logged in", "alice"));
+//! # }
+//! ```
+//!
+//! Markers are exposed to the marker filter, to the pattern encoder's
+//! `{marker}` formatter, and to the JSON encoder's `markers` field.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+/// Evaluates an expression, like a logging macro invocation, with a marker
+/// active on the current thread.
+///
+/// `marked!(marker, expr)` is a shorthand for
+/// `log4rs::marker::with(marker, || expr)`.
+#[macro_export]
+macro_rules! marked {
+    ($marker:expr, $($body:tt)+) => {
+        $crate::marker::with($marker, || $($body)+)
+    };
+}
+
+thread_local! {
+    static MARKERS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs a closure with a marker active on the current thread.
+pub fn with<F, T>(marker: &str, f: F) -> T
+    where F: FnOnce() -> T
+{
+    let _guard = push(marker);
+    f()
+}
+
+/// Activates a marker on the current thread until the returned guard is
+/// dropped.
+pub fn push(marker: &str) -> MarkerGuard {
+    MARKERS.with(|m| m.borrow_mut().push(marker.to_owned()));
+    MarkerGuard(PhantomData)
+}
+
+/// Determines if the specified marker is active on the current thread.
+pub fn has(marker: &str) -> bool {
+    MARKERS.with(|m| m.borrow().iter().any(|m| m == marker))
+}
+
+/// Invokes the provided closure with the markers active on the current
+/// thread, outermost first.
+pub fn get<F, T>(f: F) -> T
+    where F: FnOnce(&[String]) -> T
+{
+    MARKERS.with(|m| f(&m.borrow()))
+}
+
+/// A guard which deactivates a marker when dropped.
+///
+/// Guards should be dropped in the reverse order of their creation.
+pub struct MarkerGuard(PhantomData<*const ()>);
+
+impl Drop for MarkerGuard {
+    fn drop(&mut self) {
+        MARKERS.with(|m| m.borrow_mut().pop());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scopes() {
+        assert!(!has("AUDIT"));
+        with("AUDIT", || {
+            assert!(has("AUDIT"));
+            let guard = push("SECURITY");
+            assert!(has("SECURITY"));
+            get(|m| assert_eq!(m, &["AUDIT".to_owned(), "SECURITY".to_owned()]));
+            drop(guard);
+            assert!(!has("SECURITY"));
+        });
+        assert!(!has("AUDIT"));
+
+        assert!(marked!("AUDIT", has("AUDIT")));
+    }
+}
@@ -0,0 +1,241 @@
+//! Propagation of the MDC across threads and tasks.
+//!
+//! The MDC used by the pattern and JSON encoders and by the MDC filter is
+//! thread local, so entries like a request id are lost when work moves to
+//! another thread, or, for asynchronous code, when a task is polled by
+//! another worker thread of an executor. An `MdcScope` captures the entries
+//! of the current thread so that they can be re-established where the work
+//! continues:
+//!
+//! ```ignore
+//! log_mdc::insert("request_id", id);
+//!
+//! let scope = MdcScope::capture();
+//! thread::spawn(move || {
+//!     scope.in_scope(|| info!("handled in the background"));
+//! });
+//!
+//! // with the `async_mdc` feature
+//! tokio::spawn(async { info!("still has the request id") }.with_mdc());
+//! ```
+//!
+//! Requires the `mdc` feature.
+
+use log_mdc;
+#[cfg(feature = "async_mdc")]
+use std::future::Future;
+#[cfg(feature = "async_mdc")]
+use std::pin::Pin;
+#[cfg(feature = "async_mdc")]
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+
+/// A snapshot of MDC entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MdcScope {
+    entries: Vec<(String, String)>,
+}
+
+impl MdcScope {
+    /// Captures the MDC entries of the current thread.
+    pub fn capture() -> MdcScope {
+        let mut entries = vec![];
+        log_mdc::iter(|k, v| entries.push((k.to_owned(), v.to_owned())));
+        entries.sort();
+        MdcScope { entries: entries }
+    }
+
+    /// Returns the captured entries, sorted by key.
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    /// Adds the captured entries to the MDC of the current thread until the
+    /// returned guard is dropped, at which point the MDC is restored to its
+    /// previous state.
+    pub fn enter(&self) -> MdcGuard {
+        let previous = MdcScope::capture();
+        log_mdc::extend(self.entries.iter().cloned());
+        MdcGuard { previous: previous }
+    }
+
+    /// Runs a closure with the captured entries added to the MDC of the
+    /// current thread.
+    pub fn in_scope<F, T>(&self, f: F) -> T
+        where F: FnOnce() -> T
+    {
+        let _guard = self.enter();
+        f()
+    }
+
+    // Exchanges the captured entries with those of the current thread's MDC.
+    #[cfg(feature = "async_mdc")]
+    fn swap(&mut self) {
+        let previous = MdcScope::capture();
+        log_mdc::clear();
+        log_mdc::extend(self.entries.drain(..));
+        *self = previous;
+    }
+}
+
+/// A guard which restores the MDC of the current thread when dropped.
+///
+/// Created by `MdcScope::enter`.
+#[derive(Debug)]
+pub struct MdcGuard {
+    previous: MdcScope,
+}
+
+impl Drop for MdcGuard {
+    fn drop(&mut self) {
+        log_mdc::clear();
+        log_mdc::extend(self.previous.entries.drain(..));
+    }
+}
+
+/// Spawns a thread whose MDC starts with the entries of the current thread.
+///
+/// Panics if the thread can't be spawned, like `std::thread::spawn`.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    let scope = MdcScope::capture();
+    thread::spawn(move || {
+        log_mdc::extend(scope.entries);
+        f()
+    })
+}
+
+/// An extension trait for futures which carries the MDC into them.
+///
+/// Requires the `async_mdc` feature.
+#[cfg(feature = "async_mdc")]
+pub trait FutureExt: Future + Sized {
+    /// Runs the future with the MDC entries of the current thread.
+    ///
+    /// Each time the future is polled, the MDC of the polling thread is
+    /// replaced with the future's entries and restored afterwards. Entries
+    /// which the future itself inserts or removes are kept from one poll to
+    /// the next.
+    fn with_mdc(self) -> WithMdc<Self> {
+        self.with_mdc_scope(MdcScope::capture())
+    }
+
+    /// Runs the future with the MDC entries of a scope.
+    fn with_mdc_scope(self, scope: MdcScope) -> WithMdc<Self> {
+        WithMdc {
+            future: self,
+            scope: scope,
+        }
+    }
+}
+
+#[cfg(feature = "async_mdc")]
+impl<F: Future> FutureExt for F {}
+
+/// A future running with its own MDC entries.
+///
+/// Created by `FutureExt::with_mdc`.
+#[cfg(feature = "async_mdc")]
+#[derive(Debug)]
+pub struct WithMdc<F> {
+    future: F,
+    scope: MdcScope,
+}
+
+#[cfg(feature = "async_mdc")]
+impl<F: Future> Future for WithMdc<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // the future is never moved out of the pinned struct, and the scope
+        // is never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        this.scope.swap();
+        let _swapped = Swapped(&mut this.scope);
+        future.poll(cx)
+    }
+}
+
+// Swaps the entries back when dropped, even if polling panics.
+#[cfg(feature = "async_mdc")]
+struct Swapped<'a>(&'a mut MdcScope);
+
+#[cfg(feature = "async_mdc")]
+impl<'a> Drop for Swapped<'a> {
+    fn drop(&mut self) {
+        self.0.swap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn propagation() {
+        log_mdc::clear();
+        log_mdc::insert("request_id", "42");
+        let scope = MdcScope::capture();
+        assert_eq!(scope.entries(), &[("request_id".to_owned(), "42".to_owned())]);
+
+        let id = spawn(|| log_mdc::get("request_id", |v| v.map(ToOwned::to_owned)))
+            .join()
+            .unwrap();
+        assert_eq!(id.as_deref(), Some("42"));
+
+        let inner = thread::spawn(move || {
+            log_mdc::insert("user", "alice");
+            let id = scope.in_scope(|| log_mdc::get("request_id", |v| v.map(ToOwned::to_owned)));
+            (id, MdcScope::capture())
+        });
+        let (id, after) = inner.join().unwrap();
+        assert_eq!(id.as_deref(), Some("42"));
+        assert_eq!(after.entries(), &[("user".to_owned(), "alice".to_owned())]);
+        log_mdc::clear();
+    }
+
+    #[test]
+    #[cfg(feature = "async_mdc")]
+    fn future() {
+        use std::sync::Arc;
+        use std::task::{Wake, Waker};
+
+        struct NopWaker;
+
+        impl Wake for NopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        // inserts an entry on the first poll and reads it on the second
+        struct Steps(u32);
+
+        impl Future for Steps {
+            type Output = Option<String>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<String>> {
+                self.0 += 1;
+                if self.0 == 1 {
+                    log_mdc::insert("step", "one");
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Poll::Ready(log_mdc::get("step", |v| v.map(ToOwned::to_owned)))
+            }
+        }
+
+        let waker = Waker::from(Arc::new(NopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(Steps(0).with_mdc_scope(MdcScope::default()));
+
+        log_mdc::insert("outer", "1");
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert!(log_mdc::get("step", |v| v.is_none()));
+        assert!(log_mdc::get("outer", |v| v.is_some()));
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(Some("one".to_owned())));
+        log_mdc::clear();
+    }
+}
@@ -0,0 +1,660 @@
+//! Loading components from shared libraries.
+//!
+//! A config file may list shared libraries providing appenders, encoders,
+//! and filters:
+//!
+//! ```yaml
+//! plugins:
+//!   - /usr/lib/myorg/liblog4rs_kafka.so
+//!
+//! appenders:
+//!   kafka:
+//!     kind: kafka
+//!     brokers: [kafka-1:9092]
+//! ```
+//!
+//! Since Rust has no stable ABI, libraries talk to log4rs through the C ABI
+//! types of this module rather than the `Append`, `Encode` and `Filter`
+//! traits, so a library may be built by a different compiler than the
+//! application, or in another language. A library exports a function named
+//! `log4rs_plugin_register_v1` of type `RegisterFn`, which is called with a
+//! `Registry` and registers a table of functions for each kind it provides.
+//!
+//! A component is created by its table's `create` function from its
+//! configuration, serialized as JSON, and returns an opaque pointer to its
+//! state, which is passed to the table's other functions and finally to
+//! `destroy`. The functions may be called from any thread, concurrently, and
+//! must not unwind. Those which can fail return zero on success, and report
+//! the error by writing a message to the `Sink` they are passed.
+//!
+//! Relative paths are resolved like other paths in the config file.
+//! Libraries stay loaded until the process exits, including across reloads.
+//! Loading a library runs its initialization code, so only libraries which
+//! are as trusted as the application itself should be listed.
+//!
+//! Requires the `dynamic_plugins` feature; libraries can only be loaded on
+//! Unix.
+
+use log;
+use serde_value::Value;
+use std::error::Error;
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::path::Path;
+use std::slice;
+use std::str;
+
+use append::Append;
+use encode::{self, Encode};
+use file::{Deserialize, Deserializers};
+use filter::{self, Filter};
+
+/// The name of the function a library exports to register its components.
+pub const REGISTER_SYMBOL: &str = "log4rs_plugin_register_v1";
+
+/// The type of the function a library exports to register its components.
+pub type RegisterFn = unsafe extern "C" fn(registry: *const Registry);
+
+/// A borrowed UTF-8 string, which is not nul terminated.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Str {
+    /// The bytes of the string.
+    pub ptr: *const u8,
+    /// The number of bytes.
+    pub len: usize,
+}
+
+impl Str {
+    fn new(s: &str) -> Str {
+        Str {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    /// Returns the bytes of the string.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` bytes which are valid for the lifetime `'a`.
+    pub unsafe fn as_bytes<'a>(&self) -> &'a [u8] {
+        if self.len == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+}
+
+/// A log event passed to a component.
+///
+/// Missing fields are empty, and a missing line is zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    /// The level of the event, from 1 for `Error` to 5 for `Trace`.
+    pub level: u32,
+    /// The target of the event.
+    pub target: Str,
+    /// The formatted message of the event.
+    pub message: Str,
+    /// The module the event was logged from.
+    pub module_path: Str,
+    /// The source file the event was logged from.
+    pub file: Str,
+    /// The line the event was logged from.
+    pub line: u32,
+}
+
+/// A destination for bytes written by a component, like an encoder's output
+/// or an error message.
+#[repr(C)]
+pub struct Sink {
+    /// The context to pass to `write`.
+    pub ctx: *mut c_void,
+    /// Writes bytes to the sink, returning zero on success.
+    pub write: unsafe extern "C" fn(ctx: *mut c_void, data: Str) -> c_int,
+}
+
+/// The functions of an appender kind.
+#[repr(C)]
+pub struct AppenderVTable {
+    /// Creates an appender from its JSON configuration, returning null on
+    /// failure.
+    pub create: unsafe extern "C" fn(config: Str, error: *const Sink) -> *mut c_void,
+    /// Appends an event.
+    pub append: unsafe extern "C" fn(state: *mut c_void,
+                                     record: *const Record,
+                                     error: *const Sink)
+                                     -> c_int,
+    /// Flushes buffered events.
+    pub flush: unsafe extern "C" fn(state: *mut c_void, error: *const Sink) -> c_int,
+    /// Destroys the appender.
+    pub destroy: unsafe extern "C" fn(state: *mut c_void),
+}
+
+/// The functions of an encoder kind.
+#[repr(C)]
+pub struct EncoderVTable {
+    /// Creates an encoder from its JSON configuration, returning null on
+    /// failure.
+    pub create: unsafe extern "C" fn(config: Str, error: *const Sink) -> *mut c_void,
+    /// Encodes an event, writing the output to `out`.
+    pub encode: unsafe extern "C" fn(state: *mut c_void,
+                                     record: *const Record,
+                                     out: *const Sink,
+                                     error: *const Sink)
+                                     -> c_int,
+    /// Destroys the encoder.
+    pub destroy: unsafe extern "C" fn(state: *mut c_void),
+}
+
+/// The response of a filter which accepts an event.
+pub const FILTER_ACCEPT: c_int = 1;
+/// The response of a filter which takes no action on an event.
+pub const FILTER_NEUTRAL: c_int = 0;
+/// The response of a filter which rejects an event.
+pub const FILTER_REJECT: c_int = -1;
+
+/// The functions of a filter kind.
+#[repr(C)]
+pub struct FilterVTable {
+    /// Creates a filter from its JSON configuration, returning null on
+    /// failure.
+    pub create: unsafe extern "C" fn(config: Str, error: *const Sink) -> *mut c_void,
+    /// Filters an event, returning one of `FILTER_ACCEPT`, `FILTER_NEUTRAL`,
+    /// or `FILTER_REJECT`.
+    pub filter: unsafe extern "C" fn(state: *mut c_void, record: *const Record) -> c_int,
+    /// Destroys the filter.
+    pub destroy: unsafe extern "C" fn(state: *mut c_void),
+}
+
+/// The functions through which a library registers its components.
+///
+/// The tables passed to the functions must stay valid while the library is
+/// loaded.
+#[repr(C)]
+pub struct Registry {
+    /// The context to pass to the functions.
+    pub ctx: *mut c_void,
+    /// Registers an appender kind.
+    pub appender: unsafe extern "C" fn(ctx: *mut c_void,
+                                       kind: Str,
+                                       vtable: *const AppenderVTable),
+    /// Registers an encoder kind.
+    pub encoder: unsafe extern "C" fn(ctx: *mut c_void, kind: Str, vtable: *const EncoderVTable),
+    /// Registers a filter kind.
+    pub filter: unsafe extern "C" fn(ctx: *mut c_void, kind: Str, vtable: *const FilterVTable),
+}
+
+/// Loads a shared library and adds the components it provides to the
+/// deserializers.
+///
+/// # Safety
+///
+/// The library's initialization code is run, and its registration function
+/// and the functions it registers must follow the contracts of this module.
+pub unsafe fn load<P>(path: P, deserializers: &mut Deserializers) -> Result<(), Box<Error + Sync + Send>>
+    where P: AsRef<Path>
+{
+    let register = open(path.as_ref())?;
+    register_with(register, deserializers)
+}
+
+// Calls a library's registration function.
+unsafe fn register_with(register: RegisterFn,
+                        deserializers: &mut Deserializers)
+                        -> Result<(), Box<Error + Sync + Send>> {
+    let mut registrations = Registrations {
+        deserializers: deserializers,
+        error: None,
+    };
+    let registry = Registry {
+        ctx: &mut registrations as *mut Registrations as *mut c_void,
+        appender: register_appender,
+        encoder: register_encoder,
+        filter: register_filter,
+    };
+    register(&registry);
+    match registrations.error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+unsafe fn open(path: &Path) -> Result<RegisterFn, Box<Error + Sync + Send>> {
+    use libc;
+    use std::ffi::{CStr, CString};
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dlerror = || {
+        let e = libc::dlerror();
+        if e.is_null() {
+            "unknown error".to_owned()
+        } else {
+            CStr::from_ptr(e).to_string_lossy().into_owned()
+        }
+    };
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // never closed, since the library's functions are used until the process
+    // exits
+    let library = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+    if library.is_null() {
+        return Err(dlerror().into());
+    }
+    let symbol = CString::new(REGISTER_SYMBOL).unwrap();
+    let register = libc::dlsym(library, symbol.as_ptr());
+    if register.is_null() {
+        return Err(format!("the library doesn't export `{}`", REGISTER_SYMBOL).into());
+    }
+    Ok(mem::transmute::<*mut c_void, RegisterFn>(register))
+}
+
+#[cfg(not(unix))]
+unsafe fn open(_: &Path) -> Result<RegisterFn, Box<Error + Sync + Send>> {
+    Err("plugins can only be loaded on Unix".into())
+}
+
+struct Registrations<'a> {
+    deserializers: &'a mut Deserializers,
+    error: Option<String>,
+}
+
+unsafe fn registration<'a>(ctx: *mut c_void, kind: Str) -> Option<(&'a mut Registrations<'a>, String)> {
+    let registrations = &mut *(ctx as *mut Registrations);
+    match str::from_utf8(kind.as_bytes()) {
+        Ok(kind) => Some((registrations, kind.to_owned())),
+        Err(_) => {
+            registrations.error = Some("a component kind is not valid UTF-8".to_owned());
+            None
+        }
+    }
+}
+
+unsafe extern "C" fn register_appender(ctx: *mut c_void,
+                                       kind: Str,
+                                       vtable: *const AppenderVTable) {
+    if let Some((registrations, kind)) = registration(ctx, kind) {
+        registrations.deserializers.insert(&kind, AppenderDeserializer(&*vtable));
+    }
+}
+
+unsafe extern "C" fn register_encoder(ctx: *mut c_void, kind: Str, vtable: *const EncoderVTable) {
+    if let Some((registrations, kind)) = registration(ctx, kind) {
+        registrations.deserializers.insert(&kind, EncoderDeserializer(&*vtable));
+    }
+}
+
+unsafe extern "C" fn register_filter(ctx: *mut c_void, kind: Str, vtable: *const FilterVTable) {
+    if let Some((registrations, kind)) = registration(ctx, kind) {
+        registrations.deserializers.insert(&kind, FilterDeserializer(&*vtable));
+    }
+}
+
+// Collects an error message written by a component.
+#[derive(Default)]
+struct ErrorMessage(Vec<u8>);
+
+impl ErrorMessage {
+    fn sink(&mut self) -> Sink {
+        Sink {
+            ctx: self as *mut ErrorMessage as *mut c_void,
+            write: write_error,
+        }
+    }
+
+    fn into_error(self, operation: &str) -> Box<Error + Sync + Send> {
+        if self.0.is_empty() {
+            format!("{} failed", operation).into()
+        } else {
+            String::from_utf8_lossy(&self.0).into_owned().into()
+        }
+    }
+}
+
+unsafe extern "C" fn write_error(ctx: *mut c_void, data: Str) -> c_int {
+    (*(ctx as *mut ErrorMessage)).0.extend_from_slice(data.as_bytes());
+    0
+}
+
+unsafe extern "C" fn write_output(ctx: *mut c_void, data: Str) -> c_int {
+    let w = &mut *(ctx as *mut &mut encode::Write);
+    match w.write_all(data.as_bytes()) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+// Creates a component's state from its configuration.
+unsafe fn create(create: unsafe extern "C" fn(Str, *const Sink) -> *mut c_void,
+                 config: &Value)
+                 -> Result<State, Box<Error + Sync + Send>> {
+    let config = ::serde_json::to_string(config)?;
+    let mut error = ErrorMessage::default();
+    let state = create(Str::new(&config), &error.sink());
+    if state.is_null() {
+        Err(error.into_error("creating the component"))
+    } else {
+        Ok(State(state))
+    }
+}
+
+// Calls `f` with the C ABI form of a record.
+fn with_record<F, T>(record: &log::Record, f: F) -> T
+    where F: FnOnce(&Record) -> T
+{
+    let message = record.args().to_string();
+    f(&Record {
+        level: record.level() as u32,
+        target: Str::new(record.target()),
+        message: Str::new(&message),
+        module_path: Str::new(record.module_path().unwrap_or("")),
+        file: Str::new(record.file().unwrap_or("")),
+        line: record.line().unwrap_or(0),
+    })
+}
+
+// A component's state, which its library must allow to be used from any
+// thread.
+struct State(*mut c_void);
+
+unsafe impl Send for State {}
+unsafe impl Sync for State {}
+
+struct AppenderDeserializer(&'static AppenderVTable);
+
+impl Deserialize for AppenderDeserializer {
+    type Trait = Append;
+
+    type Config = Value;
+
+    fn deserialize(&self,
+                   config: Value,
+                   _: &Deserializers)
+                   -> Result<Box<Append>, Box<Error + Sync + Send>> {
+        let state = unsafe { create(self.0.create, &config)? };
+        Ok(Box::new(PluginAppender {
+            vtable: self.0,
+            state: state,
+        }))
+    }
+}
+
+struct PluginAppender {
+    vtable: &'static AppenderVTable,
+    state: State,
+}
+
+impl fmt::Debug for PluginAppender {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("PluginAppender").finish()
+    }
+}
+
+impl Append for PluginAppender {
+    fn append(&self, record: &log::Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut error = ErrorMessage::default();
+        let status = with_record(record, |record| unsafe {
+            (self.vtable.append)(self.state.0, record, &error.sink())
+        });
+        match status {
+            0 => Ok(()),
+            _ => Err(error.into_error("appending")),
+        }
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+
+    fn flush(&self) -> Result<(), Box<Error + Sync + Send>> {
+        let mut error = ErrorMessage::default();
+        match unsafe { (self.vtable.flush)(self.state.0, &error.sink()) } {
+            0 => Ok(()),
+            _ => Err(error.into_error("flushing")),
+        }
+    }
+}
+
+impl Drop for PluginAppender {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.destroy)(self.state.0) }
+    }
+}
+
+struct EncoderDeserializer(&'static EncoderVTable);
+
+impl Deserialize for EncoderDeserializer {
+    type Trait = Encode;
+
+    type Config = Value;
+
+    fn deserialize(&self,
+                   config: Value,
+                   _: &Deserializers)
+                   -> Result<Box<Encode>, Box<Error + Sync + Send>> {
+        let state = unsafe { create(self.0.create, &config)? };
+        Ok(Box::new(PluginEncoder {
+            vtable: self.0,
+            state: state,
+        }))
+    }
+}
+
+struct PluginEncoder {
+    vtable: &'static EncoderVTable,
+    state: State,
+}
+
+impl fmt::Debug for PluginEncoder {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("PluginEncoder").finish()
+    }
+}
+
+impl Encode for PluginEncoder {
+    fn encode(&self, w: &mut encode::Write, record: &log::Record) -> Result<(), Box<Error + Sync + Send>> {
+        let mut error = ErrorMessage::default();
+        let mut w = w;
+        let out = Sink {
+            ctx: &mut w as *mut &mut encode::Write as *mut c_void,
+            write: write_output,
+        };
+        let status = with_record(record, |record| unsafe {
+            (self.vtable.encode)(self.state.0, record, &out, &error.sink())
+        });
+        match status {
+            0 => Ok(()),
+            _ => Err(error.into_error("encoding")),
+        }
+    }
+}
+
+impl Drop for PluginEncoder {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.destroy)(self.state.0) }
+    }
+}
+
+struct FilterDeserializer(&'static FilterVTable);
+
+impl Deserialize for FilterDeserializer {
+    type Trait = Filter;
+
+    type Config = Value;
+
+    fn deserialize(&self,
+                   config: Value,
+                   _: &Deserializers)
+                   -> Result<Box<Filter>, Box<Error + Sync + Send>> {
+        let state = unsafe { create(self.0.create, &config)? };
+        Ok(Box::new(PluginFilter {
+            vtable: self.0,
+            state: state,
+        }))
+    }
+}
+
+struct PluginFilter {
+    vtable: &'static FilterVTable,
+    state: State,
+}
+
+impl fmt::Debug for PluginFilter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("PluginFilter").finish()
+    }
+}
+
+impl Filter for PluginFilter {
+    fn filter(&self, record: &log::Record) -> filter::Response {
+        let response = with_record(record, |record| unsafe {
+            (self.vtable.filter)(self.state.0, record)
+        });
+        match response {
+            FILTER_ACCEPT => filter::Response::Accept,
+            FILTER_REJECT => filter::Response::Reject,
+            _ => filter::Response::Neutral,
+        }
+    }
+}
+
+impl Drop for PluginFilter {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.destroy)(self.state.0) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use encode::writer::simple::SimpleWriter;
+
+    // a plugin counting the events appended by a `count` appender, and
+    // providing an `upper` encoder and an `odd` filter
+    static APPENDED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn create(config: Str, error: *const Sink) -> *mut c_void {
+        match config.as_bytes() {
+            b"{}" => Box::into_raw(Box::new(0u8)) as *mut c_void,
+            _ => {
+                ((*error).write)((*error).ctx, Str::new("unexpected config"));
+                ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe extern "C" fn append(_: *mut c_void, record: *const Record, error: *const Sink) -> c_int {
+        if (*record).message.as_bytes() == b"fail" {
+            ((*error).write)((*error).ctx, Str::new("refused"));
+            return 1;
+        }
+        APPENDED.fetch_add(1, Ordering::SeqCst);
+        0
+    }
+
+    unsafe extern "C" fn flush(_: *mut c_void, _: *const Sink) -> c_int {
+        0
+    }
+
+    unsafe extern "C" fn encode(_: *mut c_void,
+                                record: *const Record,
+                                out: *const Sink,
+                                _: *const Sink)
+                                -> c_int {
+        let message = str::from_utf8((*record).message.as_bytes()).unwrap().to_uppercase();
+        ((*out).write)((*out).ctx, Str::new(&message))
+    }
+
+    unsafe extern "C" fn filter(_: *mut c_void, record: *const Record) -> c_int {
+        if (*record).line % 2 == 1 {
+            FILTER_ACCEPT
+        } else {
+            FILTER_REJECT
+        }
+    }
+
+    unsafe extern "C" fn destroy(state: *mut c_void) {
+        drop(Box::from_raw(state as *mut u8));
+    }
+
+    static APPENDER: AppenderVTable = AppenderVTable {
+        create: create,
+        append: append,
+        flush: flush,
+        destroy: destroy,
+    };
+
+    static ENCODER: EncoderVTable = EncoderVTable {
+        create: create,
+        encode: encode,
+        destroy: destroy,
+    };
+
+    static FILTER: FilterVTable = FilterVTable {
+        create: create,
+        filter: filter,
+        destroy: destroy,
+    };
+
+    unsafe extern "C" fn register(registry: *const Registry) {
+        let registry = &*registry;
+        (registry.appender)(registry.ctx, Str::new("count"), &APPENDER);
+        (registry.encoder)(registry.ctx, Str::new("upper"), &ENCODER);
+        (registry.filter)(registry.ctx, Str::new("odd"), &FILTER);
+    }
+
+    fn empty() -> Value {
+        Value::Map(Default::default())
+    }
+
+    #[test]
+    fn components() {
+        let mut deserializers = Deserializers::empty();
+        unsafe { register_with(register, &mut deserializers).unwrap() };
+
+        let appender = deserializers.deserialize::<Append>("count", empty()).unwrap();
+        let append = |message| {
+            appender.append(&log::Record::builder()
+                .level(Level::Info)
+                .args(format_args!("{}", message))
+                .build())
+        };
+        append("hi").unwrap();
+        let e = append("fail").unwrap_err();
+        assert_eq!(e.to_string(), "refused");
+        appender.flush().unwrap();
+        assert_eq!(APPENDED.load(Ordering::SeqCst), 1);
+
+        let encoder = deserializers.deserialize::<Encode>("upper", empty()).unwrap();
+        let mut buf = vec![];
+        encoder.encode(&mut SimpleWriter(&mut buf),
+                    &log::Record::builder().args(format_args!("hello")).build())
+            .unwrap();
+        assert_eq!(buf, b"HELLO");
+
+        let filter = deserializers.deserialize::<Filter>("odd", empty()).unwrap();
+        let response = |line| filter.filter(&log::Record::builder().line(Some(line)).build());
+        match (response(3), response(4)) {
+            (filter::Response::Accept, filter::Response::Reject) => {}
+            r => panic!("unexpected responses {:?}", r),
+        }
+
+        let config = Value::Map(vec![(Value::String("a".to_owned()), Value::Bool(true))]
+            .into_iter()
+            .collect());
+        let e = deserializers.deserialize::<Filter>("odd", config).unwrap_err();
+        assert_eq!(e.to_string(), "unexpected config");
+
+        let missing = unsafe { load("/nonexistent/liblog4rs_plugin.so", &mut deserializers) };
+        assert!(missing.is_err());
+    }
+}
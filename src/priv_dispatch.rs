@@ -0,0 +1,95 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+thread_local! {
+    // its address identifies the thread
+    static THREAD: u8 = const { 0 };
+}
+
+fn thread() -> usize {
+    THREAD.try_with(|t| t as *const u8 as usize).unwrap_or(0)
+}
+
+/// Serializes calls into an appender which isn't thread-safe.
+///
+/// Each such appender has its own lock, so that threads logging to different
+/// appenders don't wait on each other. The lock is reentrant, since an
+/// appender may log events which are dispatched back to it.
+#[derive(Default)]
+pub struct Dispatch {
+    lock: Mutex<()>,
+    // the thread holding the lock, or 0
+    owner: AtomicUsize,
+}
+
+impl Dispatch {
+    pub fn run<F, T>(&self, f: F) -> T
+        where F: FnOnce() -> T
+    {
+        let thread = thread();
+        // only this thread stores its own address
+        if thread != 0 && self.owner.load(Ordering::Relaxed) == thread {
+            return f();
+        }
+
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let _owner = Owner::enter(&self.owner, thread);
+        f()
+    }
+}
+
+struct Owner<'a>(&'a AtomicUsize);
+
+impl<'a> Owner<'a> {
+    fn enter(owner: &'a AtomicUsize, thread: usize) -> Owner<'a> {
+        owner.store(thread, Ordering::Relaxed);
+        Owner(owner)
+    }
+}
+
+impl<'a> Drop for Owner<'a> {
+    fn drop(&mut self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn serialized() {
+        let dispatch = Arc::new(Dispatch::default());
+        let running = Arc::new(AtomicBool::new(false));
+        let threads = (0..8)
+            .map(|_| {
+                let dispatch = dispatch.clone();
+                let running = running.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        dispatch.run(|| {
+                            assert!(!running.swap(true, Ordering::SeqCst));
+                            thread::yield_now();
+                            running.store(false, Ordering::SeqCst);
+                        });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn reentrant() {
+        let dispatch = Dispatch::default();
+        assert_eq!(dispatch.run(|| dispatch.run(|| 1)), 1);
+        // and released afterwards
+        thread::scope(|s| s.spawn(|| dispatch.run(|| {})).join().unwrap());
+    }
+}
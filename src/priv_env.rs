@@ -0,0 +1,250 @@
+use serde_value::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::error;
+
+use {init_config, Handle};
+use file::{Deserializers, RawConfig};
+use priv_file::{self, Error};
+
+const PREFIX: &str = "LOG4RS_";
+const SECTIONS: [&str; 5] =
+    ["root", "appender_templates", "appenders", "loggers", "interceptors"];
+
+/// Initializes the global logger as a log4rs logger configured via
+/// environment variables.
+///
+/// The configuration has the same structure as a config file, with each
+/// value in its own variable. A variable's name is `LOG4RS_` followed by the
+/// path to the value, with its components separated by `__`. Names are case
+/// insensitive, and numeric components index into lists:
+///
+/// ```text
+/// LOG4RS_ROOT__LEVEL=info
+/// LOG4RS_ROOT__APPENDERS=stdout,requests
+///
+/// LOG4RS_APPENDERS__STDOUT__KIND=console
+///
+/// LOG4RS_APPENDERS__REQUESTS__KIND=file
+/// LOG4RS_APPENDERS__REQUESTS__PATH=/var/log/requests.log
+/// LOG4RS_APPENDERS__REQUESTS__ENCODER__PATTERN={d} - {m}{n}
+/// LOG4RS_APPENDERS__REQUESTS__FILTERS__0__KIND=threshold
+/// LOG4RS_APPENDERS__REQUESTS__FILTERS__0__LEVEL=warn
+///
+/// LOG4RS_LOGGERS__0__NAME=myapp::db
+/// LOG4RS_LOGGERS__0__LEVEL=trace
+/// LOG4RS_LOGGERS__0__APPENDERS=requests
+/// LOG4RS_LOGGERS__0__ADDITIVE=false
+/// ```
+///
+/// Since logger names can't appear in variable names, loggers are a list of
+/// entries with a `NAME`. `APPENDERS` values are comma separated lists. Other
+/// values of `true` and `false` are booleans and numeric values are numbers;
+/// to use one as a string instead, wrap it in double quotes. Variables with
+/// the prefix which are not under `ROOT`, `APPENDER_TEMPLATES`, `APPENDERS`,
+/// `LOGGERS`, or `INTERCEPTORS` are ignored.
+///
+/// Any nonfatal errors encountered when processing the configuration are
+/// reported to stderr.
+///
+/// Requires the `file` feature (enabled by default).
+pub fn init_from_env(deserializers: Deserializers) -> Result<Handle, Error> {
+    let vars = env::vars_os().filter_map(|(k, v)| match (k.into_string(), v.into_string()) {
+        (Ok(k), Ok(v)) => Some((k, v)),
+        _ => None,
+    });
+    let config = raw_config(vars, deserializers.lenient())?;
+    let config = priv_file::deserialize(&config, &deserializers, &|b| b);
+    init_config(config).map_err(Into::into)
+}
+
+enum Node {
+    Leaf(String),
+    Branch(BTreeMap<String, Node>),
+}
+
+fn raw_config<I>(vars: I, lenient: bool) -> Result<RawConfig, Box<error::Error + Sync + Send>>
+    where I: IntoIterator<Item = (String, String)>
+{
+    let mut tree = BTreeMap::new();
+    for (name, value) in vars {
+        let path = match name.strip_prefix(PREFIX) {
+            Some(path) => path.to_lowercase(),
+            None => continue,
+        };
+        let segments = path.split("__").collect::<Vec<_>>();
+        if segments.len() < 2 || !SECTIONS.contains(&segments[0]) {
+            continue;
+        }
+        if segments.iter().any(|s| s.is_empty()) {
+            return Err(format!("invalid config variable `{}`", name).into());
+        }
+        insert(&mut tree, &segments, value)
+            .map_err(|()| format!("config variable `{}` conflicts with another", name))?;
+    }
+
+    let mut config = BTreeMap::new();
+    for (key, node) in tree {
+        let mut value = to_value(&key, node);
+        if key == "loggers" {
+            value = loggers(value)?;
+        }
+        config.insert(Value::String(key), value);
+    }
+    priv_file::deserialize_raw(Value::Map(config), lenient)
+}
+
+fn insert(tree: &mut BTreeMap<String, Node>, path: &[&str], value: String) -> Result<(), ()> {
+    if path.len() == 1 {
+        if tree.contains_key(path[0]) {
+            return Err(());
+        }
+        tree.insert(path[0].to_owned(), Node::Leaf(value));
+        return Ok(());
+    }
+
+    let node = tree.entry(path[0].to_owned()).or_insert_with(|| Node::Branch(BTreeMap::new()));
+    match *node {
+        Node::Branch(ref mut tree) => insert(tree, &path[1..], value),
+        Node::Leaf(_) => Err(()),
+    }
+}
+
+fn to_value(key: &str, node: Node) -> Value {
+    match node {
+        Node::Leaf(value) => {
+            if key == "appenders" {
+                Value::Seq(value.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(|s| Value::String(s.to_owned()))
+                    .collect())
+            } else {
+                scalar(value)
+            }
+        }
+        Node::Branch(tree) => {
+            if tree.keys().all(|k| k.parse::<usize>().is_ok()) {
+                let mut items = tree.into_iter()
+                    .map(|(k, v)| (k.parse::<usize>().unwrap(), to_value(&k, v)))
+                    .collect::<Vec<_>>();
+                items.sort_by_key(|&(i, _)| i);
+                Value::Seq(items.into_iter().map(|(_, v)| v).collect())
+            } else {
+                Value::Map(tree.into_iter()
+                    .map(|(k, v)| {
+                        let v = to_value(&k, v);
+                        (Value::String(k), v)
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+fn scalar(value: String) -> Value {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return Value::String(value[1..value.len() - 1].to_owned());
+    }
+    match &*value {
+        "true" => return Value::Bool(true),
+        "false" => return Value::Bool(false),
+        _ => {}
+    }
+    if let Ok(v) = value.parse::<u64>() {
+        return Value::U64(v);
+    }
+    if let Ok(v) = value.parse::<i64>() {
+        return Value::I64(v);
+    }
+    if let Ok(v) = value.parse::<f64>() {
+        if v.is_finite() {
+            return Value::F64(v);
+        }
+    }
+    Value::String(value)
+}
+
+// converts the list of loggers into a map keyed by name
+fn loggers(value: Value) -> Result<Value, Box<error::Error + Sync + Send>> {
+    let entries = match value {
+        Value::Seq(entries) => entries,
+        _ => {
+            return Err("loggers must be configured by index, like `LOG4RS_LOGGERS__0__NAME`"
+                .into())
+        }
+    };
+
+    let mut loggers = BTreeMap::new();
+    for entry in entries {
+        let mut entry = match entry {
+            Value::Map(entry) => entry,
+            _ => return Err("invalid logger configuration".into()),
+        };
+        let name = match entry.remove(&Value::String("name".to_owned())) {
+            Some(Value::String(name)) => name,
+            _ => return Err("each logger must have a `NAME`".into()),
+        };
+        loggers.insert(Value::String(name), Value::Map(entry));
+    }
+    Ok(Value::Map(loggers))
+}
+
+#[cfg(test)]
+mod test {
+    use log::LevelFilter;
+
+    use super::*;
+
+    fn config(vars: &[(&str, &str)]) -> Result<RawConfig, Box<error::Error + Sync + Send>> {
+        raw_config(vars.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())), false)
+    }
+
+    #[test]
+    fn env() {
+        let config = config(&[("LOG4RS_ROOT__LEVEL", "info"),
+                              ("LOG4RS_ROOT__APPENDERS", "stdout, requests"),
+                              ("LOG4RS_APPENDERS__STDOUT__KIND", "console"),
+                              ("LOG4RS_APPENDERS__REQUESTS__KIND", "file"),
+                              ("LOG4RS_APPENDERS__REQUESTS__PATH", "/tmp/requests.log"),
+                              ("LOG4RS_APPENDERS__REQUESTS__FILTERS__0__KIND", "threshold"),
+                              ("LOG4RS_APPENDERS__REQUESTS__FILTERS__0__LEVEL", "warn"),
+                              ("LOG4RS_LOGGERS__0__NAME", "myapp::db"),
+                              ("LOG4RS_LOGGERS__0__LEVEL", "trace"),
+                              ("LOG4RS_LOGGERS__0__ADDITIVE", "false"),
+                              ("LOG4RS_CONFIG", "ignored"),
+                              ("PATH", "/bin")])
+            .unwrap();
+
+        assert_eq!(config.root().level(), LevelFilter::Info);
+        assert_eq!(config.root().appenders(), &["stdout".to_owned(), "requests".to_owned()]);
+        let loggers = config.loggers();
+        assert_eq!(loggers.len(), 1);
+        assert_eq!(loggers[0].name(), "myapp::db");
+        assert_eq!(loggers[0].level(), LevelFilter::Trace);
+        assert!(!loggers[0].additive());
+
+        let (appenders, errors) = config.appenders_lossy(&Deserializers::default());
+        assert!(errors.is_empty());
+        assert_eq!(appenders.len(), 2);
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(scalar("true".to_owned()), Value::Bool(true));
+        assert_eq!(scalar("10".to_owned()), Value::U64(10));
+        assert_eq!(scalar("-1".to_owned()), Value::I64(-1));
+        assert_eq!(scalar("0.5".to_owned()), Value::F64(0.5));
+        assert_eq!(scalar("\"10\"".to_owned()), Value::String("10".to_owned()));
+        assert_eq!(scalar("inf".to_owned()), Value::String("inf".to_owned()));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(config(&[("LOG4RS_ROOT__LEVEL", "info"), ("LOG4RS_ROOT__LEVEL__X", "info")])
+            .is_err());
+        assert!(config(&[("LOG4RS_LOGGERS__0__LEVEL", "info")]).is_err());
+        assert!(config(&[("LOG4RS_LOGGERS__FOO__LEVEL", "info")]).is_err());
+        assert!(config(&[("LOG4RS_ROOT__BOGUS", "info")]).is_err());
+    }
+}
@@ -1,15 +1,25 @@
 use log::SetLoggerError;
+use serde_value::Value;
+use std::collections::HashMap;
+use std::env;
 use std::error;
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
 use std::io::Read;
 use std::thread;
 use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, SystemTime};
+#[cfg(feature = "file_watch")]
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "file_watch")]
+use notify::{self, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 
-use {init_config, Handle, handle_error};
-use file::{Deserializers, RawConfig};
-use config::Config;
+use {init_config, ConfigBuilderPrivateExt, Handle, handle_error, PrivateConfigAppenderExt,
+     RawConfigPrivateExt};
+use append::Append;
+use file::{self, Deserializers, RawConfig};
+use config::{self, Config, ConfigBuilder};
 
 /// Initializes the global logger as a log4rs logger configured via a file.
 ///
@@ -19,37 +29,507 @@ use config::Config;
 /// Any nonfatal errors encountered when processing the configuration are
 /// reported to stderr.
 ///
+/// A `Handle` object is returned which can be used to inspect or adjust the
+/// logging configuration, including after the file is reloaded.
+///
+/// A file may list other files to include, relative to its own directory,
+/// which are merged underneath it:
+///
+/// ```yaml
+/// include:
+///   - base.yaml
+///
+/// root:
+///   level: debug
+/// ```
+///
+/// Included files are merged in order, and the including file is merged
+/// last, so later files override earlier ones. `refresh_rate` and each field
+/// of `root` are overridden individually, and appenders and loggers are
+/// replaced as a whole by name. When refreshing, every included file is
+/// checked for changes.
+///
+/// A file may also define named profiles which are merged on top of the rest
+/// of the configuration in the same way:
+///
+/// ```yaml
+/// root:
+///   level: warn
+///
+/// profiles:
+///   dev:
+///     root:
+///       level: debug
+/// ```
+///
+/// The profile named by the `LOG4RS_PROFILE` environment variable, if set, is
+/// applied, and it is an error for the file not to define it. See
+/// `init_file_with_profile` to select a profile in code.
+///
+/// If `refresh_rate` is set and the `file_watch` feature is enabled, the
+/// files are reloaded as soon as the operating system reports that they have
+/// changed rather than by polling, which is only used if notifications are
+/// unavailable.
+///
+/// Whether or not `refresh_rate` is set, `Handle::reload` reloads the files
+/// on demand.
+///
+/// The path may also be a directory of config files, like `/etc/myapp/log.d`,
+/// which are merged like includes in the lexical order of their names, so
+/// later files override earlier ones. Files with a `yaml`, `yml`, `json`, or
+/// `toml` extension are read and others are ignored, as are hidden files.
+/// When refreshing, files added to or removed from the directory are noticed
+/// as well. A relative `base_dir` is resolved against the directory itself.
+///
+/// When the configuration is reloaded, appenders whose kind and
+/// configuration are unchanged keep running, along with their open files,
+/// connections, and buffers. Changing only an appender's level or filters
+/// does not recreate it.
+///
 /// Requires the `file` feature (enabled by default).
-pub fn init_file<P>(path: P, deserializers: Deserializers) -> Result<(), Error>
+pub fn init_file<P>(path: P, deserializers: Deserializers) -> Result<Handle, Error>
     where P: AsRef<Path>
 {
-    let path = path.as_ref().to_path_buf();
-    let format = Format::from_path(&path)?;
-    let source = read_config(&path)?;
-    // An Err here could come because mtime isn't available, so don't bail
-    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
-    let config = format.parse(&source)?;
+    init_file_with(path, deserializers, |b| b)
+}
 
-    let refresh_rate = config.refresh_rate();
-    let config = deserialize(&config, &deserializers);
+/// Like `init_file`, but combines the file's configuration with appenders and
+/// loggers built in code.
+///
+/// Each time the file is loaded, including when it is reloaded, `base` is
+/// called with an empty `ConfigBuilder` and adds the appenders and loggers
+/// to it, like ones which depend on runtime data. The file's root and
+/// loggers may refer to those appenders by name. If the file and `base`
+/// both define an appender or logger with the same name, the file's is used.
+/// Interceptors added by `base` are applied after the file's.
+///
+/// Requires the `file` feature (enabled by default).
+pub fn init_file_with<P, F>(path: P,
+                            deserializers: Deserializers,
+                            base: F)
+                            -> Result<Handle, Error>
+    where P: AsRef<Path>,
+          F: Fn(ConfigBuilder) -> ConfigBuilder + Send + 'static
+{
+    init(path.as_ref(), deserializers, base, env::var(PROFILE_VAR).ok())
+}
+
+/// Like `init_file`, but applies the named profile from the file's
+/// `profiles` section rather than the one selected by the `LOG4RS_PROFILE`
+/// environment variable.
+///
+/// It is an error for the file not to define the profile.
+///
+/// Requires the `file` feature (enabled by default).
+pub fn init_file_with_profile<P>(path: P,
+                                 deserializers: Deserializers,
+                                 profile: &str)
+                                 -> Result<Handle, Error>
+    where P: AsRef<Path>
+{
+    init(path.as_ref(), deserializers, |b| b, Some(profile.to_owned()))
+}
+
+fn init<F>(path: &Path,
+           deserializers: Deserializers,
+           base: F,
+           profile: Option<String>)
+           -> Result<Handle, Error>
+    where F: Fn(ConfigBuilder) -> ConfigBuilder + Send + 'static
+{
+    let path = path.to_path_buf();
+    let sources = Sources::load(&path, profile.as_deref(), deserializers.lenient())?;
+
+    let refresh_rate = sources.config.refresh_rate();
+    let mut running = Running::default();
+    let config = deserialize_reusing(&sources.config, &deserializers, &base, &mut running);
 
     match init_config(config) {
         Ok(handle) => {
+            let reloader = Arc::new(Mutex::new(ConfigReloader {
+                path: path,
+                files: sources.files,
+                source: sources.contents,
+                deserializers: deserializers,
+                base: Box::new(base),
+                profile: profile,
+                running: running,
+                handle: handle.clone(),
+            }));
+            let shared = reloader.clone();
+            handle.set_reload(move || lock(&shared).reload());
             if let Some(refresh_rate) = refresh_rate {
-                ConfigReloader::start(path,
-                                      format,
-                                      refresh_rate,
-                                      source,
-                                      modified,
-                                      deserializers,
-                                      handle);
+                ConfigReloader::start(reloader, refresh_rate);
             }
-            Ok(())
+            Ok(handle)
         }
         Err(e) => Err(e.into()),
     }
 }
 
+/// Initializes the global logger as a log4rs logger configured by a string
+/// in the specified format.
+///
+/// The string has the same structure as a config file, like one embedded in
+/// the binary or generated at runtime. Environment variable references and
+/// the profile named by the `LOG4RS_PROFILE` environment variable are
+/// applied as with `init_file`, but there is nothing to reload, so
+/// `refresh_rate` is ignored, and includes are not supported. A relative
+/// `base_dir` is resolved against the working directory.
+///
+/// Any nonfatal errors encountered when processing the configuration are
+/// reported to stderr.
+///
+/// Requires the `file` feature (enabled by default).
+pub fn init_from_str(source: &str,
+                     format: Format,
+                     deserializers: Deserializers)
+                     -> Result<Handle, Error> {
+    let config = parse_config(source,
+                              &format,
+                              env::var(PROFILE_VAR).ok().as_deref(),
+                              deserializers.lenient())?;
+    let config = deserialize(&config, &deserializers, &|b| b);
+    init_config(config).map_err(Into::into)
+}
+
+/// Like `init_from_str`, but reads the configuration from a reader.
+///
+/// Requires the `file` feature (enabled by default).
+pub fn init_from_reader<R>(mut reader: R,
+                           format: Format,
+                           deserializers: Deserializers)
+                           -> Result<Handle, Error>
+    where R: Read
+{
+    let mut source = String::new();
+    reader.read_to_string(&mut source)
+        .map_err(|e| Box::new(e) as Box<error::Error + Sync + Send>)?;
+    init_from_str(&source, format, deserializers)
+}
+
+fn parse_config(source: &str,
+                format: &Format,
+                profile: Option<&str>,
+                lenient: bool)
+                -> Result<RawConfig, Box<error::Error + Sync + Send>> {
+    let value = format.parse(source)?;
+    if let Value::Map(ref m) = value {
+        if m.contains_key(&Value::String("include".to_owned())) {
+            return Err("`include` is only supported in config files".into());
+        }
+    }
+    let value = select_profile(value, profile)?;
+    deserialize_raw(value, lenient)
+}
+
+// Deserializes a whole config, dropping unknown fields in lenient mode. The
+// fields of components are checked when the components are created.
+pub fn deserialize_raw(value: Value,
+                       lenient: bool)
+                       -> Result<RawConfig, Box<error::Error + Sync + Send>> {
+    let config = if lenient {
+        ::priv_serde::deserialize_lenient(value, &["appenders", "appender_templates", "interceptors"])
+    } else {
+        value.deserialize_into()
+    };
+    config.map_err(|e| Box::new(e) as _)
+}
+
+/// Checks a config file for problems without installing it.
+///
+/// The file, and any files it includes, are loaded and each component is
+/// created from the provided `Deserializers` just as `init_file` would, which
+/// verifies that every kind is known, that component configurations are
+/// valid, and that files can be opened, but the components are then dropped.
+/// Note that creating components may have side effects like creating log
+/// files. References to appenders and logger names are checked, and
+/// appenders which are never used or loggers which discard every event are
+/// reported as warnings.
+///
+/// An empty list means that no problems were found.
+///
+/// Requires the `file` feature (enabled by default).
+pub fn validate<P>(path: P, deserializers: &Deserializers) -> Vec<Diagnostic>
+    where P: AsRef<Path>
+{
+    let mut diagnostics = vec![];
+    let profile = env::var(PROFILE_VAR).ok();
+    let config = match Sources::load(path.as_ref(), profile.as_deref(), deserializers.lenient()) {
+        Ok(sources) => sources.config,
+        Err(e) => {
+            diagnostics.push(Diagnostic::error(e.to_string()));
+            return diagnostics;
+        }
+    };
+
+    let mut deserializers = deserializers.clone();
+    for error in load_plugins(&config, &mut deserializers) {
+        diagnostics.push(Diagnostic::error(error.to_string()));
+    }
+    diagnose(&config, config.appenders_lossy(&deserializers), &deserializers, &mut diagnostics);
+    diagnostics
+}
+
+// Checks a config given its appenders, or placeholders for them, and the
+// errors creating them.
+fn diagnose(config: &RawConfig,
+            appenders: (Vec<config::Appender>, Vec<file::Error>),
+            deserializers: &Deserializers,
+            diagnostics: &mut Vec<Diagnostic>) {
+    let (appenders, errors) = appenders;
+    diagnostics.extend(errors.iter().map(|e| Diagnostic::error(e.to_string())));
+    let (interceptors, errors) = config.interceptors_lossy(deserializers);
+    diagnostics.extend(errors.iter().map(|e| Diagnostic::error(e.to_string())));
+    let (built, errors) = Config::builder()
+        .appenders(appenders)
+        .loggers(config.loggers())
+        .interceptors(interceptors)
+        .on_error(config.on_error())
+        .backpressure(config.backpressure())
+        .default_console(config.default_console())
+        .build_lossy(config.root());
+    diagnostics.extend(errors.iter().map(|e| Diagnostic::error(e.to_string())));
+
+    let built = match config.level_override_var().and_then(|var| env::var(var).ok()) {
+        Some(directives) => {
+            let (built, errors) = built.override_levels(&directives);
+            diagnostics.extend(errors.iter().map(|e| Diagnostic::warning(e.to_string())));
+            built
+        }
+        None => built,
+    };
+
+    for appender in built.appenders() {
+        let name = appender.name();
+        let used = built.root().appenders().iter().any(|a| a == name) ||
+                   built.loggers().iter().any(|l| l.appenders().iter().any(|a| a == name));
+        if !used {
+            diagnostics.push(Diagnostic::warning(format!("appender `{}` is not used by any logger",
+                                                         appender.name())));
+        }
+    }
+    for logger in built.loggers() {
+        if logger.appenders().is_empty() && !logger.additive() {
+            diagnostics.push(Diagnostic::warning(format!("logger `{}` has no appenders and is \
+                                                          not additive, so it discards every \
+                                                          event",
+                                                         logger.name())));
+        }
+    }
+}
+
+/// A config file embedded in the binary by `embed_config!`.
+///
+/// Requires the `file` feature (enabled by default).
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedConfig {
+    path: &'static str,
+    source: &'static str,
+}
+
+impl EmbeddedConfig {
+    #[doc(hidden)]
+    pub const fn new(path: &'static str, source: &'static str) -> EmbeddedConfig {
+        EmbeddedConfig {
+            path: path,
+            source: source,
+        }
+    }
+
+    /// Returns the path the config was embedded from, relative to the
+    /// package root.
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    /// Returns the contents of the config.
+    pub fn source(&self) -> &'static str {
+        self.source
+    }
+}
+
+/// Initializes the global logger as a log4rs logger configured by a config
+/// embedded in the binary by `embed_config!`.
+///
+/// The config is used like one passed to `init_from_str`, in the format
+/// indicated by the extension of the path it was embedded from, so no file
+/// is read.
+///
+/// Requires the `file` feature (enabled by default).
+pub fn init_embedded(config: EmbeddedConfig, deserializers: Deserializers) -> Result<Handle, Error> {
+    let format = Format::from_path(Path::new(config.path))?;
+    init_from_str(config.source, format, deserializers)
+}
+
+/// Checks a config file to be embedded by `embed_config!`, failing the build
+/// if it has errors.
+///
+/// This is meant to be called from a build script, with log4rs as a build
+/// dependency, so that a broken config is caught when the binary is built
+/// rather than when it starts:
+///
+/// ```no_run
+/// // build.rs
+/// extern crate log4rs;
+///
+/// fn main() {
+///     log4rs::config::check_embedded("log4rs.yaml", &Default::default());
+/// }
+/// ```
+///
+/// The path is relative to the package root, like the one passed to
+/// `embed_config!`. The config and each of its profiles are checked like
+/// `validate` checks a file, except that appenders aren't created, so their
+/// kinds and fields are checked but the build doesn't create log files or
+/// open connections. Environment variable references are substituted from
+/// the build's environment, and plugins are loaded from the build's machine.
+/// Warnings are reported through cargo, and errors cause a panic listing
+/// them.
+///
+/// Requires the `file` feature (enabled by default).
+pub fn check_embedded<P>(path: P, deserializers: &Deserializers)
+    where P: AsRef<Path>
+{
+    let path = path.as_ref();
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let diagnostics = match Format::from_path(path).and_then(|f| read_config(path).map(|s| (s, f))) {
+        Ok((source, format)) => check(&source, format, deserializers),
+        Err(e) => vec![Diagnostic::error(e.to_string())],
+    };
+    let mut errors = vec![];
+    for diagnostic in diagnostics {
+        match diagnostic.severity() {
+            Severity::Error => errors.push(diagnostic.message),
+            Severity::Warning => println!("cargo:warning={}: {}", path.display(), diagnostic),
+        }
+    }
+    if !errors.is_empty() {
+        panic!("invalid log4rs config `{}`:\n{}", path.display(), errors.join("\n"));
+    }
+}
+
+// Checks a config and each of its profiles without creating its appenders.
+fn check(source: &str, format: Format, deserializers: &Deserializers) -> Vec<Diagnostic> {
+    let mut profiles = vec![None];
+    if let Ok(Value::Map(ref m)) = format.parse(source) {
+        if let Some(Value::Map(defined)) = m.get(&Value::String("profiles".to_owned())) {
+            profiles.extend(defined.keys().filter_map(|k| match *k {
+                Value::String(ref k) => Some(Some(k.clone())),
+                _ => None,
+            }));
+        }
+    }
+
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    for profile in profiles {
+        let mut found = vec![];
+        match parse_config(source, &format, profile.as_deref(), deserializers.lenient()) {
+            Ok(config) => {
+                let mut deserializers = deserializers.clone();
+                let mut errors = load_plugins(&config, &mut deserializers)
+                    .iter()
+                    .map(|e| Diagnostic::error(e.to_string()))
+                    .collect::<Vec<_>>();
+                let deserializers = &deserializers;
+                let appenders = config.appenders_reusing(deserializers, |name, appender| {
+                    if let Err(e) = deserializers.check::<Append>(&appender.kind,
+                                                                  appender.config.clone()) {
+                        errors.push(Diagnostic::error(format!("error deserializing appender \
+                                                               {}: {}",
+                                                              name,
+                                                              e)));
+                    }
+                    Some(Arc::new(Placeholder) as Arc<Append>)
+                });
+                found.extend(errors);
+                diagnose(&config, appenders, deserializers, &mut found);
+            }
+            Err(e) => found.push(Diagnostic::error(e.to_string())),
+        }
+
+        // problems of the base config are reported once rather than again
+        // for every profile
+        for mut diagnostic in found {
+            if let Some(ref profile) = profile {
+                if diagnostics.iter().any(|d| d.message == diagnostic.message) {
+                    continue;
+                }
+                diagnostic.message = format!("profile `{}`: {}", profile, diagnostic.message);
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+// Stands in for an appender which has been checked but not created.
+#[derive(Debug)]
+struct Placeholder;
+
+impl Append for Placeholder {
+    fn append(&self, _: &::log::Record) -> Result<(), Box<error::Error + Sync + Send>> {
+        Ok(())
+    }
+
+    fn thread_safe(&self) -> bool {
+        true
+    }
+}
+
+/// The severity of a problem found by `validate`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    /// A problem which will cause part of the configuration to be ignored.
+    Error,
+    /// A likely mistake which does not prevent the configuration from being
+    /// used.
+    Warning,
+}
+
+/// A problem found by `validate`.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    severity: Severity,
+    message: String,
+}
+
+impl Diagnostic {
+    fn error(message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message,
+        }
+    }
+
+    fn warning(message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            message: message,
+        }
+    }
+
+    /// Returns the severity of the problem.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(fmt, "error: {}", self.message),
+            Severity::Warning => write!(fmt, "warning: {}", self.message),
+        }
+    }
+}
+
 /// An error initializing the logging framework from a file.
 #[derive(Debug)]
 pub enum Error {
@@ -96,18 +576,29 @@ impl From<Box<error::Error + Sync + Send>> for Error {
     }
 }
 
-enum Format {
+/// A config file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// YAML, requiring the `yaml_format` feature.
     #[cfg(feature = "yaml_format")]
     Yaml,
+    /// JSON, requiring the `json_format` feature.
     #[cfg(feature = "json_format")]
     Json,
+    /// TOML, requiring the `toml_format` feature.
     #[cfg(feature = "toml_format")]
     Toml,
 }
 
 impl Format {
     fn from_path(path: &Path) -> Result<Format, Box<error::Error + Sync + Send>> {
-        match path.extension().and_then(|s| s.to_str()) {
+        Format::from_extension(path.extension().and_then(|s| s.to_str()))
+    }
+
+    /// Returns the format of files with the specified extension, like `yml`.
+    pub fn from_extension(extension: Option<&str>)
+                          -> Result<Format, Box<error::Error + Sync + Send>> {
+        match extension {
             #[cfg(feature = "yaml_format")]
             Some("yaml") | Some("yml") => Ok(Format::Yaml),
             #[cfg(not(feature = "yaml_format"))]
@@ -127,16 +618,263 @@ impl Format {
         }
     }
 
-    fn parse(&self, source: &str) -> Result<RawConfig, Box<error::Error + Sync + Send>> {
-        match *self {
+    #[doc(hidden)]
+    pub fn parse(&self, source: &str) -> Result<Value, Box<error::Error + Sync + Send>> {
+        let value: Value = match *self {
             #[cfg(feature = "yaml_format")]
-            Format::Yaml => ::serde_yaml::from_str(source).map_err(Into::into),
+            Format::Yaml => ::serde_yaml::from_str(source)?,
             #[cfg(feature = "json_format")]
-            Format::Json => ::serde_json::from_str(source).map_err(Into::into),
+            Format::Json => ::serde_json::from_str(source)?,
             #[cfg(feature = "toml_format")]
-            Format::Toml => ::toml::from_str(source).map_err(Into::into),
+            Format::Toml => ::toml::from_str(source)?,
+        };
+        substitute_value(value, &|name| env::var(name).ok())
+    }
+}
+
+pub const PROFILE_VAR: &str = "LOG4RS_PROFILE";
+
+// A config merged from a file and everything it includes.
+struct Sources {
+    config: RawConfig,
+    // the modification time of each file read, if available
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    contents: String,
+}
+
+impl Sources {
+    fn load(path: &Path,
+            profile: Option<&str>,
+            lenient: bool)
+            -> Result<Sources, Box<error::Error + Sync + Send>> {
+        let mut files = vec![];
+        let mut contents = String::new();
+        let (value, dir) = if path.is_dir() {
+            (load_dir(path, &mut files, &mut contents)?, path)
+        } else {
+            (load_file(path, &mut files, &mut contents, &mut vec![])?,
+             path.parent().unwrap_or_else(|| Path::new("")))
+        };
+        let value = select_profile(value, profile)?;
+        let value = anchor_base_dir(value, dir);
+        Ok(Sources {
+            config: deserialize_raw(value, lenient)?,
+            files: files,
+            contents: contents,
+        })
+    }
+}
+
+// Merges the config files in a directory in order of their names. The
+// directory itself is tracked so that added and removed files are noticed.
+fn load_dir(dir: &Path,
+            files: &mut Vec<(PathBuf, Option<SystemTime>)>,
+            contents: &mut String)
+            -> Result<Value, Box<error::Error + Sync + Send>> {
+    let modified = fs::metadata(dir).and_then(|m| m.modified()).ok();
+    files.push((dir.to_owned(), modified));
+
+    let mut fragments = vec![];
+    let entries = fs::read_dir(dir).map_err(|e| format!("error reading `{}`: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry?.path();
+        // skip hidden files, like editor swap files
+        if path.file_name().and_then(|n| n.to_str()).is_none_or(|n| n.starts_with('.')) {
+            continue;
+        }
+        let extension = path.extension().and_then(|e| e.to_str());
+        if FRAGMENT_EXTENSIONS.contains(&extension.unwrap_or("")) && path.is_file() {
+            fragments.push(path);
         }
     }
+    fragments.sort();
+
+    let mut merged = Value::Map(Default::default());
+    for fragment in fragments {
+        let value = load_file(&fragment, files, contents, &mut vec![])?;
+        merged = merge(merged, value, 0);
+    }
+    Ok(merged)
+}
+
+const FRAGMENT_EXTENSIONS: [&str; 4] = ["yaml", "yml", "json", "toml"];
+
+fn load_file(path: &Path,
+             files: &mut Vec<(PathBuf, Option<SystemTime>)>,
+             contents: &mut String,
+             stack: &mut Vec<PathBuf>)
+             -> Result<Value, Box<error::Error + Sync + Send>> {
+    let format = Format::from_path(path)?;
+    let source = read_config(path)
+        .map_err(|e| format!("error reading `{}`: {}", path.display(), e))?;
+    // An Err here could come because mtime isn't available, so don't bail
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    files.push((path.to_owned(), modified));
+    contents.push_str(&source);
+    let mut value = format.parse(&source)?;
+
+    let includes = match value {
+        Value::Map(ref mut m) => m.remove(&Value::String("include".to_owned())),
+        _ => None,
+    };
+    let includes = match includes {
+        None | Some(Value::Unit) | Some(Value::Option(None)) => return Ok(value),
+        Some(Value::String(include)) => vec![include],
+        Some(Value::Seq(includes)) => {
+            includes.into_iter()
+                .map(|i| match i {
+                    Value::String(i) => Ok(i),
+                    _ => Err("`include` must contain file paths"),
+                })
+                .collect::<Result<_, _>>()?
+        }
+        Some(_) => return Err("`include` must be a file path or a list of them".into()),
+    };
+
+    // an unresolvable path can't be part of a cycle, so fall back to it as is
+    stack.push(path.canonicalize().unwrap_or_else(|_| path.to_owned()));
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut merged = Value::Unit;
+    for include in includes {
+        let include = dir.join(include);
+        let canonical = include.canonicalize().unwrap_or_else(|_| include.clone());
+        if stack.contains(&canonical) {
+            return Err(format!("`{}` is included recursively", include.display()).into());
+        }
+        let included = load_file(&include, files, contents, stack)?;
+        merged = merge(merged, included, 0);
+    }
+    stack.pop();
+
+    Ok(merge(merged, value, 0))
+}
+
+// Removes the `profiles` section, merging the selected profile on top.
+pub fn select_profile(mut value: Value,
+                      profile: Option<&str>)
+                      -> Result<Value, Box<error::Error + Sync + Send>> {
+    let profiles = match value {
+        Value::Map(ref mut m) => m.remove(&Value::String("profiles".to_owned())),
+        _ => None,
+    };
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return Ok(value),
+    };
+
+    let over = match profiles {
+        Some(Value::Map(mut profiles)) => profiles.remove(&Value::String(profile.to_owned())),
+        None | Some(Value::Unit) | Some(Value::Option(None)) => None,
+        Some(_) => return Err("`profiles` must be a map of profile names".into()),
+    };
+    match over {
+        Some(over) => Ok(merge(value, over, 0)),
+        None => Err(format!("profile `{}` is not defined", profile).into()),
+    }
+}
+
+// Resolves a relative `base_dir` against the config file's directory.
+fn anchor_base_dir(mut value: Value, dir: &Path) -> Value {
+    if let Value::Map(ref mut m) = value {
+        if let Some(&mut Value::String(ref mut base_dir)) =
+               m.get_mut(&Value::String("base_dir".to_owned())) {
+            if Path::new(base_dir).is_relative() {
+                *base_dir = dir.join(&*base_dir).to_string_lossy().into_owned();
+            }
+        }
+    }
+    value
+}
+
+// Merges `over` on top of `base`. The top level map and the maps directly
+// under it are merged key by key, and anything deeper is replaced outright,
+// so an appender is never a mix of two definitions.
+fn merge(base: Value, over: Value, depth: usize) -> Value {
+    match (base, over) {
+        (Value::Map(mut base), Value::Map(over)) if depth < 2 => {
+            for (k, v) in over {
+                let v = match base.remove(&k) {
+                    Some(b) => merge(b, v, depth + 1),
+                    None => v,
+                };
+                base.insert(k, v);
+            }
+            Value::Map(base)
+        }
+        (base, Value::Unit) => base,
+        (_, over) => over,
+    }
+}
+
+// Replaces `${NAME}` and `${NAME:-default}` in every string in the config.
+fn substitute_value(value: Value,
+                    lookup: &Fn(&str) -> Option<String>)
+                    -> Result<Value, Box<error::Error + Sync + Send>> {
+    let value = match value {
+        Value::String(s) => Value::String(substitute(&s, lookup)?),
+        Value::Option(Some(v)) => Value::Option(Some(Box::new(substitute_value(*v, lookup)?))),
+        Value::Newtype(v) => Value::Newtype(Box::new(substitute_value(*v, lookup)?)),
+        Value::Seq(vs) => {
+            Value::Seq(vs.into_iter()
+                .map(|v| substitute_value(v, lookup))
+                .collect::<Result<_, _>>()?)
+        }
+        Value::Map(m) => {
+            Value::Map(m.into_iter()
+                .map(|(k, v)| substitute_value(v, lookup).map(|v| (k, v)))
+                .collect::<Result<_, _>>()?)
+        }
+        v => v,
+    };
+    Ok(value)
+}
+
+fn substitute(s: &str,
+              lookup: &Fn(&str) -> Option<String>)
+              -> Result<String, Box<error::Error + Sync + Send>> {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // `$${` escapes a literal `${`
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let body = match rest.strip_prefix("${") {
+            Some(body) => body,
+            None => {
+                out.push('$');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let end = body.find('}')
+            .ok_or_else(|| format!("unterminated variable reference in `{}`", s))?;
+
+        let reference = &body[..end];
+        let (name, default) = match reference.find(":-") {
+            Some(i) => (&reference[..i], Some(&reference[i + 2..])),
+            None => (reference, None),
+        };
+        if name.is_empty() {
+            return Err(format!("empty variable reference in `{}`", s).into());
+        }
+        match (lookup(name), default) {
+            (Some(ref value), Some(default)) if value.is_empty() => out.push_str(default),
+            (Some(value), _) => out.push_str(&value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => {
+                return Err(format!("environment variable `{}` is not set", name).into())
+            }
+        }
+        rest = &body[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
 }
 
 fn read_config(path: &Path) -> Result<String, Box<error::Error + Sync + Send>> {
@@ -146,60 +884,165 @@ fn read_config(path: &Path) -> Result<String, Box<error::Error + Sync + Send>> {
     Ok(s)
 }
 
-fn deserialize(config: &RawConfig, deserializers: &Deserializers) -> Config {
-    let (appenders, errors) = config.appenders_lossy(deserializers);
+// The appenders created from the file, along with their kind and
+// configuration, and the base directory their paths were resolved against.
+#[derive(Default)]
+pub struct Running {
+    base_dir: Option<PathBuf>,
+    appenders: HashMap<String, (String, Value, Arc<Append>)>,
+}
+
+pub fn deserialize(config: &RawConfig,
+                   deserializers: &Deserializers,
+                   base: &Fn(ConfigBuilder) -> ConfigBuilder)
+                   -> Config {
+    deserialize_reusing(config, deserializers, base, &mut Running::default())
+}
+
+// Adds the components of the config's plugins to the deserializers,
+// returning the errors loading them.
+fn load_plugins(config: &RawConfig,
+                deserializers: &mut Deserializers)
+                -> Vec<Box<error::Error + Sync + Send>> {
+    let mut errors = vec![];
+    for path in config.plugins() {
+        let path = deserializers.resolve_path(path);
+        #[cfg(feature = "dynamic_plugins")]
+        let result = unsafe { ::plugin::load(&path, deserializers) };
+        #[cfg(not(feature = "dynamic_plugins"))]
+        let result = Err::<(), Box<error::Error + Sync + Send>>(
+            "the `dynamic_plugins` feature is required to load plugins".into());
+        if let Err(e) = result {
+            errors.push(format!("error loading plugin `{}`: {}", path.display(), e).into());
+        }
+    }
+    errors
+}
+
+// Like `deserialize`, but reuses appenders which are unchanged since they
+// were recorded in `running`, which is updated.
+pub fn deserialize_reusing(config: &RawConfig,
+                           deserializers: &Deserializers,
+                           base: &Fn(ConfigBuilder) -> ConfigBuilder,
+                           running: &mut Running)
+                           -> Config {
+    let extended;
+    let deserializers = if config.base_dir().is_some() || !config.plugins().is_empty() {
+        extended = {
+            let mut deserializers = deserializers.clone();
+            if let Some(dir) = config.base_dir() {
+                deserializers.set_base_dir(dir);
+            }
+            for error in load_plugins(config, &mut deserializers) {
+                handle_error(&*error);
+            }
+            deserializers
+        };
+        &extended
+    } else {
+        deserializers
+    };
+    // paths may resolve differently
+    let base_dir = deserializers.base_dir().map(Path::to_path_buf);
+    if running.base_dir != base_dir {
+        running.base_dir = base_dir;
+        running.appenders.clear();
+    }
+
+    let mut seen = HashMap::new();
+    let (mut appenders, errors) = config.appenders_reusing(deserializers, |name, appender| {
+        seen.insert(name.to_owned(), (appender.kind.clone(), appender.config.clone()));
+        match running.appenders.get(name) {
+            Some((kind, config, shared)) if *kind == appender.kind &&
+                                            *config == appender.config => Some(shared.clone()),
+            _ => None,
+        }
+    });
+    running.appenders = appenders.iter()
+        .filter_map(|a| {
+            seen.remove(a.name())
+                .map(|(kind, config)| (a.name().to_owned(), (kind, config, a.shared())))
+        })
+        .collect();
+    for error in &errors {
+        handle_error(error);
+    }
+    let mut loggers = config.loggers();
+
+    let (interceptors, errors) = config.interceptors_lossy(deserializers);
     for error in &errors {
         handle_error(error);
     }
 
-    let (config, errors) = Config::builder()
+    // the file takes precedence over components built in code
+    let (base_appenders, base_loggers, base_interceptors) = base(Config::builder()).unpack();
+    for appender in base_appenders {
+        if !appenders.iter().any(|a| a.name() == appender.name()) {
+            appenders.push(appender);
+        }
+    }
+    for logger in base_loggers {
+        if !loggers.iter().any(|l| l.name() == logger.name()) {
+            loggers.push(logger);
+        }
+    }
+
+    let (built, errors) = Config::builder()
         .appenders(appenders)
-        .loggers(config.loggers())
+        .loggers(loggers)
+        .interceptors(interceptors)
+        .interceptors(base_interceptors)
+        .on_error(config.on_error())
+        .backpressure(config.backpressure())
+        .default_console(config.default_console())
         .build_lossy(config.root());
     for error in &errors {
         handle_error(error);
     }
 
-    config
+    let directives = config.level_override_var().and_then(|var| env::var(var).ok());
+    match directives {
+        Some(directives) => {
+            let (built, errors) = built.override_levels(&directives);
+            for error in &errors {
+                handle_error(error);
+            }
+            built
+        }
+        None => built,
+    }
 }
 
 struct ConfigReloader {
     path: PathBuf,
-    format: Format,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
     source: String,
-    modified: Option<SystemTime>,
     deserializers: Deserializers,
+    base: Box<Fn(ConfigBuilder) -> ConfigBuilder + Send>,
+    profile: Option<String>,
+    running: Running,
     handle: Handle,
 }
 
-impl ConfigReloader {
-    fn start(path: PathBuf,
-             format: Format,
-             rate: Duration,
-             source: String,
-             modified: Option<SystemTime>,
-             deserializers: Deserializers,
-             handle: Handle) {
-        let mut reloader = ConfigReloader {
-            path: path,
-            format: format,
-            source: source,
-            modified: modified,
-            deserializers: deserializers,
-            handle: handle,
-        };
+// The reloader is shared by the refresh thread and `Handle::reload`.
+fn lock(reloader: &Mutex<ConfigReloader>) -> MutexGuard<'_, ConfigReloader> {
+    reloader.lock().unwrap_or_else(|e| e.into_inner())
+}
 
+impl ConfigReloader {
+    fn start(reloader: Arc<Mutex<ConfigReloader>>, rate: Duration) {
         thread::Builder::new()
             .name("log4rs refresh".to_owned())
-            .spawn(move || reloader.run(rate))
+            .spawn(move || ConfigReloader::run(&reloader, rate))
             .unwrap();
     }
 
-    fn run(&mut self, mut rate: Duration) {
+    #[cfg(not(feature = "file_watch"))]
+    fn run(reloader: &Mutex<ConfigReloader>, mut rate: Duration) {
         loop {
             thread::sleep(rate);
 
-            match self.run_once(rate) {
+            match lock(reloader).run_once(rate) {
                 Ok(Some(r)) => rate = r,
                 Ok(None) => break,
                 Err(e) => handle_error(&*e),
@@ -207,30 +1050,470 @@ impl ConfigReloader {
         }
     }
 
-    fn run_once(&mut self, rate: Duration) -> Result<Option<Duration>, Box<error::Error + Sync + Send>> {
-        if let Some(last_modified) = self.modified {
-            let modified = fs::metadata(&self.path).and_then(|m| m.modified())?;
-            if last_modified == modified {
-                return Ok(Some(rate));
+    #[cfg(feature = "file_watch")]
+    fn run(reloader: &Mutex<ConfigReloader>, mut rate: Duration) {
+        let mut watcher = FileWatcher::new(&lock(reloader).files);
+        loop {
+            // fall back to polling if notifications aren't available
+            let files = lock(reloader).files.clone();
+            let notified = match watcher {
+                Some(ref watcher) => watcher.wait(&files),
+                None => false,
+            };
+            if !notified {
+                watcher = None;
+                thread::sleep(rate);
             }
 
-            self.modified = Some(modified);
+            let mut reloader = lock(reloader);
+            match reloader.run_once(rate) {
+                Ok(Some(r)) => rate = r,
+                Ok(None) => break,
+                Err(e) => handle_error(&*e),
+            }
+
+            // the set of included files may have changed
+            if let Some(ref mut w) = watcher {
+                if let Err(e) = w.watch(&reloader.files) {
+                    handle_error(&e);
+                    watcher = None;
+                }
+            }
         }
+    }
 
-        let source = read_config(&self.path)?;
+    fn reload(&mut self) -> Result<(), Box<error::Error + Sync + Send>> {
+        let sources =
+            Sources::load(&self.path, self.profile.as_deref(), self.deserializers.lenient())?;
+        self.apply(sources);
+        Ok(())
+    }
 
-        if source == self.source {
+    fn run_once(&mut self, rate: Duration) -> Result<Option<Duration>, Box<error::Error + Sync + Send>> {
+        if self.files.iter().all(|&(_, modified)| modified.is_some()) {
+            let mut changed = false;
+            for &mut (ref path, ref mut last_modified) in &mut self.files {
+                let modified = fs::metadata(path).and_then(|m| m.modified())?;
+                if *last_modified != Some(modified) {
+                    *last_modified = Some(modified);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return Ok(Some(rate));
+            }
+        }
+
+        let sources =
+            Sources::load(&self.path, self.profile.as_deref(), self.deserializers.lenient())?;
+        if sources.contents == self.source {
+            self.files = sources.files;
             return Ok(Some(rate));
         }
 
-        self.source = source;
+        Ok(self.apply(sources))
+    }
+
+    // Returns the new refresh rate.
+    fn apply(&mut self, sources: Sources) -> Option<Duration> {
+        self.files = sources.files;
+        self.source = sources.contents;
 
-        let config = self.format.parse(&self.source)?;
-        let rate = config.refresh_rate();
-        let config = deserialize(&config, &self.deserializers);
+        let rate = sources.config.refresh_rate();
+        let config = deserialize_reusing(&sources.config,
+                                         &self.deserializers,
+                                         &*self.base,
+                                         &mut self.running);
 
         self.handle.set_config(config);
 
-        Ok(rate)
+        rate
+    }
+}
+
+#[cfg(feature = "file_watch")]
+struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    dirs: Vec<PathBuf>,
+}
+
+#[cfg(feature = "file_watch")]
+impl FileWatcher {
+    fn new(files: &[(PathBuf, Option<SystemTime>)]) -> Option<FileWatcher> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(tx) {
+            Ok(watcher) => FileWatcher {
+                watcher: watcher,
+                events: rx,
+                dirs: vec![],
+            },
+            Err(e) => {
+                handle_error(&e);
+                return None;
+            }
+        };
+        match watcher.watch(files) {
+            Ok(()) => Some(watcher),
+            Err(e) => {
+                handle_error(&e);
+                None
+            }
+        }
+    }
+
+    // Watches the directories containing the files rather than the files
+    // themselves, since editors often replace a file instead of writing to it.
+    // A directory of config files is watched itself.
+    fn watch(&mut self, files: &[(PathBuf, Option<SystemTime>)]) -> notify::Result<()> {
+        let mut dirs = vec![];
+        for (file, _) in files {
+            let dir = match file.parent() {
+                _ if file.is_dir() => file.to_owned(),
+                Some(dir) if dir != Path::new("") => dir.to_owned(),
+                _ => PathBuf::from("."),
+            };
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+
+        for dir in &self.dirs {
+            if !dirs.contains(dir) {
+                self.watcher.unwatch(dir)?;
+            }
+        }
+        for dir in &dirs {
+            if !self.dirs.contains(dir) {
+                self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            }
+        }
+        self.dirs = dirs;
+        Ok(())
+    }
+
+    // Blocks until one of the files may have changed, returning `false` if
+    // notifications have stopped working.
+    fn wait(&self, files: &[(PathBuf, Option<SystemTime>)]) -> bool {
+        loop {
+            let event = match self.events.recv() {
+                Ok(Ok(event)) => event,
+                Ok(Err(e)) => {
+                    handle_error(&e);
+                    return false;
+                }
+                Err(_) => return false,
+            };
+            if let EventKind::Access(_) = event.kind {
+                continue;
+            }
+            let relevant = event.paths.iter().any(|p| {
+                let dir = p.parent().and_then(|d| d.file_name());
+                files.iter().any(|(f, _)| {
+                    p.file_name() == f.file_name() || (f.is_dir() && dir == f.file_name())
+                })
+            });
+            if relevant {
+                break;
+            }
+        }
+
+        // let a multi-step write settle before reading the file
+        thread::sleep(Duration::from_millis(50));
+        while self.events.try_recv().is_ok() {}
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("example.com".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn substitution() {
+        let sub = |s| substitute(s, &lookup).unwrap();
+        assert_eq!(sub("tcp://${HOST}:514"), "tcp://example.com:514");
+        assert_eq!(sub("${LEVEL:-info}"), "info");
+        assert_eq!(sub("${HOST:-localhost}"), "example.com");
+        assert_eq!(sub("${EMPTY:-fallback}"), "fallback");
+        assert_eq!(sub("${EMPTY}"), "");
+        assert_eq!(sub("$${HOST} costs $5"), "${HOST} costs $5");
+        assert_eq!(sub("${HOST}${HOST}"), "example.comexample.com");
+
+        assert!(substitute("${MISSING}", &lookup).is_err());
+        assert!(substitute("${HOST", &lookup).is_err());
+        assert!(substitute("${:-x}", &lookup).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_format")]
+    fn parse() {
+        env::set_var("LOG4RS_PRIV_FILE_TEST_LEVEL", "debug");
+        let source = "root:\n  level: ${LOG4RS_PRIV_FILE_TEST_LEVEL}\n  appenders: \
+                      [\"${APPENDER:-a}\"]\n";
+        let config = Format::Yaml.parse(source).unwrap().deserialize_into::<RawConfig>().unwrap();
+        let root = config.root();
+        assert_eq!(root.level(), ::log::LevelFilter::Debug);
+        assert_eq!(root.appenders(), &["a".to_owned()]);
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_format")]
+    fn from_str() {
+        let source = "root:\n  level: info\nprofiles:\n  dev:\n    root:\n      level: debug\n";
+        let config = parse_config(source, &Format::Yaml, None, false).unwrap();
+        assert_eq!(config.root().level(), ::log::LevelFilter::Info);
+        let config = parse_config(source, &Format::Yaml, Some("dev"), false).unwrap();
+        assert_eq!(config.root().level(), ::log::LevelFilter::Debug);
+        assert!(parse_config("include: base.yaml", &Format::Yaml, None, false).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "console_appender"))]
+    fn lenient() {
+        let source = "future: 1\nroot:\n  level: info\n  colour: red\nloggers:\n  foo:\n    \
+                      level: debug\n    sampled: true\nappenders:\n  console:\n    \
+                      kind: console\n    fancy: true\n    encoder:\n      kind: hologram\n";
+        assert!(parse_config(source, &Format::Yaml, None, false).is_err());
+
+        let config = parse_config(source, &Format::Yaml, None, true).unwrap();
+        assert_eq!(config.root().level(), ::log::LevelFilter::Info);
+        assert_eq!(config.loggers()[0].level(), ::log::LevelFilter::Debug);
+
+        let mut deserializers = Deserializers::default();
+        let (_, errors) = config.appenders_lossy(&deserializers);
+        assert_eq!(errors.len(), 1);
+        deserializers.set_lenient(true);
+        let (appenders, errors) = config.appenders_lossy(&deserializers);
+        assert!(errors.is_empty());
+        assert_eq!(appenders.len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_format")]
+    fn include() {
+        let dir = TempDir::new("include").unwrap();
+        let write = |name: &str, contents: &str| {
+            File::create(dir.path().join(name)).unwrap().write_all(contents.as_bytes()).unwrap();
+        };
+        write("base.yaml",
+              "refresh_rate: 30 seconds\nroot:\n  level: warn\n  appenders: [a]\n\
+               loggers:\n  foo:\n    level: info\n    additive: false\n");
+        write("main.yaml",
+              "include: base.yaml\nroot:\n  level: debug\nloggers:\n  foo:\n    level: trace\n");
+
+        let sources = Sources::load(&dir.path().join("main.yaml"), None, false).unwrap();
+        assert_eq!(sources.files.len(), 2);
+        let config = sources.config;
+        assert_eq!(config.refresh_rate(), Some(Duration::from_secs(30)));
+        assert_eq!(config.root().level(), ::log::LevelFilter::Debug);
+        assert_eq!(config.root().appenders(), &["a".to_owned()]);
+        // loggers are replaced as a whole
+        let loggers = config.loggers();
+        assert_eq!(loggers[0].level(), ::log::LevelFilter::Trace);
+        assert!(loggers[0].additive());
+
+        write("a.yaml", "include: [b.yaml]\n");
+        write("b.yaml", "include: [a.yaml]\n");
+        assert!(Sources::load(&dir.path().join("a.yaml"), None, false).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_format")]
+    fn fragments() {
+        let dir = TempDir::new("fragments").unwrap();
+        let write = |name: &str, contents: &str| {
+            File::create(dir.path().join(name)).unwrap().write_all(contents.as_bytes()).unwrap();
+        };
+        write("10-base.yaml", "root:\n  level: warn\n  appenders: [a]\nbase_dir: logs\n");
+        write("20-site.yaml", "root:\n  level: debug\n");
+        write(".20-site.yaml.swp", "garbage");
+        write("notes.txt", "garbage");
+
+        let sources = Sources::load(dir.path(), None, false).unwrap();
+        // the directory and each fragment
+        assert_eq!(sources.files.len(), 3);
+        let config = sources.config;
+        assert_eq!(config.root().level(), ::log::LevelFilter::Debug);
+        assert_eq!(config.root().appenders(), &["a".to_owned()]);
+        assert_eq!(config.base_dir(), Some(&*dir.path().join("logs")));
+
+        write("30-broken.yaml", "root: [");
+        assert!(Sources::load(dir.path(), None, false).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "yaml_format")]
+    fn profiles() {
+        let source = "root:\n  level: warn\n  appenders: [a]\nprofiles:\n  dev:\n    root:\n      \
+                      level: debug\n  prod:\n";
+        let load = |profile| {
+            let value = Format::Yaml.parse(source).unwrap();
+            select_profile(value, profile).map(|v| v.deserialize_into::<RawConfig>().unwrap())
+        };
+
+        let root = load(None).unwrap().root();
+        assert_eq!(root.level(), ::log::LevelFilter::Warn);
+        let root = load(Some("dev")).unwrap().root();
+        assert_eq!(root.level(), ::log::LevelFilter::Debug);
+        assert_eq!(root.appenders(), &["a".to_owned()]);
+        let root = load(Some("prod")).unwrap().root();
+        assert_eq!(root.level(), ::log::LevelFilter::Warn);
+        assert!(load(Some("staging")).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "file_appender"))]
+    fn base_dir() {
+        let dir = TempDir::new("base_dir").unwrap();
+        let path = dir.path().join("log4rs.yaml");
+        File::create(&path)
+            .unwrap()
+            .write_all(b"base_dir: logs\nappenders:\n  file:\n    kind: file\n    path: app.log\n")
+            .unwrap();
+
+        let sources = Sources::load(&path, None, false).unwrap();
+        assert_eq!(sources.config.base_dir(), Some(&*dir.path().join("logs")));
+        deserialize(&sources.config, &Deserializers::default(), &|b| b);
+        assert!(dir.path().join("logs/app.log").exists());
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "console_appender"))]
+    fn reuse() {
+        let load = |source: &str| {
+            Format::Yaml.parse(source).unwrap().deserialize_into::<RawConfig>().unwrap()
+        };
+        let shared = |config: &Config, name: &str| {
+            config.appenders().iter().find(|a| a.name() == name).unwrap().shared()
+        };
+        let deserializers = Deserializers::default();
+        let mut running = Running::default();
+
+        let source = "appenders:\n  a:\n    kind: console\n  b:\n    kind: console\n";
+        let first = deserialize_reusing(&load(source), &deserializers, &|b| b, &mut running);
+        let source = "appenders:\n  a:\n    kind: console\n    level: warn\n  b:\n    \
+                      kind: console\n    target: stderr\n";
+        let second = deserialize_reusing(&load(source), &deserializers, &|b| b, &mut running);
+
+        assert!(Arc::ptr_eq(&shared(&first, "a"), &shared(&second, "a")));
+        assert!(!Arc::ptr_eq(&shared(&first, "b"), &shared(&second, "b")));
+        assert_eq!(running.appenders.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "file_watch")]
+    fn watch() {
+        let dir = TempDir::new("watch").unwrap();
+        let path = dir.path().join("log4rs.yaml");
+        File::create(&path).unwrap();
+        let files = vec![(path.clone(), None)];
+        // notifications may be unavailable in a sandbox
+        let watcher = match FileWatcher::new(&files) {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            File::create(dir.path().join("unrelated")).unwrap();
+            File::create(&path).unwrap().write_all(b"root: {}\n").unwrap();
+            dir
+        });
+        assert!(watcher.wait(&files));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "console_appender"))]
+    fn base() {
+        use append::console::ConsoleAppender;
+        use config::{Appender, Logger};
+        use log::LevelFilter;
+
+        let source = "appenders:\n  shared:\n    kind: console\n\
+                      root:\n  appenders: [code, shared]\n\
+                      loggers:\n  a:\n    level: debug\n";
+        let config = Format::Yaml.parse(source).unwrap().deserialize_into::<RawConfig>().unwrap();
+        let config = deserialize(&config, &Deserializers::default(), &|b| {
+            b.appender(Appender::builder().build("code".to_owned(),
+                                                 Box::new(ConsoleAppender::builder().build())))
+                .appender(Appender::builder().build("shared".to_owned(),
+                                                    Box::new(ConsoleAppender::builder().build())))
+                .logger(Logger::builder().build("a".to_owned(), LevelFilter::Info))
+                .logger(Logger::builder().build("b".to_owned(), LevelFilter::Info))
+        });
+
+        assert_eq!(config.appenders().len(), 2);
+        assert_eq!(config.root().appenders().len(), 2);
+        assert_eq!(config.loggers().len(), 2);
+        let a = config.loggers().iter().find(|l| l.name() == "a").unwrap();
+        assert_eq!(a.level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "console_appender"))]
+    fn validate() {
+        let dir = TempDir::new("validate").unwrap();
+        let path = dir.path().join("log4rs.yaml");
+        File::create(&path)
+            .unwrap()
+            .write_all(b"appenders:\n  stdout:\n    kind: console\n  unused:\n    kind: console\n\
+                         \x20 bogus:\n    kind: bogus\n\
+                         root:\n  appenders: [stdout, missing]\n\
+                         loggers:\n  quiet:\n    level: info\n    additive: false\n")
+            .unwrap();
+
+        let diagnostics = super::validate(&path, &Deserializers::default());
+        let errors = diagnostics.iter().filter(|d| d.severity() == Severity::Error).count();
+        let warnings = diagnostics.iter().filter(|d| d.severity() == Severity::Warning).count();
+        assert_eq!((errors, warnings), (2, 2), "{:?}", diagnostics);
+
+        let missing = super::validate(dir.path().join("missing.yaml"), &Deserializers::default());
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].severity(), Severity::Error);
+    }
+
+    #[test]
+    #[cfg(all(feature = "yaml_format", feature = "console_appender", feature = "file_appender"))]
+    fn check() {
+        let embedded = ::embed_config!("test/log.yml");
+        assert_eq!(embedded.path(), "test/log.yml");
+        let format = Format::from_path(Path::new(embedded.path())).unwrap();
+        let diagnostics = super::check(embedded.source(), format, &Deserializers::default());
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let source = "appenders:\n  stdout:\n    kind: console\n    colour: true\n\
+                      \x20 file:\n    kind: file\n    path: /nonexistent/log4rs/check.log\n\
+                      root:\n  appenders: [stdout, file]\n\
+                      profiles:\n  dev:\n    appenders:\n      extra:\n        kind: bogus\n";
+        let messages = super::check(source, Format::Yaml, &Deserializers::default())
+            .iter()
+            .map(|d| d.message().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        assert!(messages[0].starts_with("error deserializing appender stdout: Unknown field colour"));
+        assert_eq!(messages[1],
+                   "profile `dev`: error deserializing appender extra: no appender \
+                    deserializer for kind `bogus` registered");
+        assert_eq!(messages[2], "profile `dev`: appender `extra` is not used by any logger");
+
+        let plugins = super::check("plugins: [/nonexistent/liblog4rs_plugin.so]\n",
+                                   Format::Yaml,
+                                   &Deserializers::default());
+        assert_eq!(plugins.len(), 1);
+        assert!(plugins[0].message().starts_with("error loading plugin"));
     }
 }
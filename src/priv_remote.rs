@@ -0,0 +1,195 @@
+use std::env;
+use std::error;
+use std::thread;
+use std::time::Duration;
+use ureq;
+
+use {init_config, handle_error, Handle};
+use file::{Deserializers, RawConfig};
+use priv_file::{self, Error, Format, Running, PROFILE_VAR};
+
+const TIMEOUT_SECS: u64 = 30;
+
+/// Initializes the global logger as a log4rs logger configured by a document
+/// fetched from an HTTP or HTTPS URL.
+///
+/// The document has the same structure as a config file, and `${NAME}`
+/// references are replaced by the local host's environment variables. Its
+/// format is determined by the response's `Content-Type`, falling back to the
+/// extension of the URL's path. HTTPS URLs require the `http_tls` feature.
+///
+/// If `refresh_rate` is set, the URL is polled at that rate with the
+/// `If-None-Match` and `If-Modified-Since` headers from the last response, and
+/// a changed document is applied like a reloaded config file. If a request
+/// fails or returns an invalid document, the error is reported to stderr and
+/// the active configuration is kept. The profile named by the
+/// `LOG4RS_PROFILE` environment variable is applied as with `init_file`, but
+/// includes are not supported.
+///
+/// Any nonfatal errors encountered when processing the configuration are
+/// reported to stderr.
+///
+/// Requires the `remote_config` feature.
+pub fn init_url(url: &str, deserializers: Deserializers) -> Result<Handle, Error> {
+    let mut source = Source::new(url)?;
+    let config = match source.fetch(deserializers.lenient())? {
+        Some(config) => config,
+        None => return Err(Error::Log4rs(format!("no config document at `{}`", url).into())),
+    };
+
+    let refresh_rate = config.refresh_rate();
+    let mut running = Running::default();
+    let config = priv_file::deserialize_reusing(&config, &deserializers, &|b| b, &mut running);
+
+    match init_config(config) {
+        Ok(handle) => {
+            if let Some(refresh_rate) = refresh_rate {
+                let mut reloader = RemoteReloader {
+                    source: source,
+                    deserializers: deserializers,
+                    running: running,
+                    handle: handle.clone(),
+                };
+                thread::Builder::new()
+                    .name("log4rs remote refresh".to_owned())
+                    .spawn(move || reloader.run(refresh_rate))
+                    .unwrap();
+            }
+            Ok(handle)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+struct Source {
+    agent: ureq::Agent,
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    contents: String,
+}
+
+impl Source {
+    fn new(url: &str) -> Result<Source, Box<error::Error + Sync + Send>> {
+        if url.starts_with("https://") && !cfg!(feature = "http_tls") {
+            return Err("https URLs require the `http_tls` feature".into());
+        }
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!("invalid HTTP URL `{}`", url).into());
+        }
+
+        Ok(Source {
+            agent: ureq::AgentBuilder::new().timeout(Duration::from_secs(TIMEOUT_SECS)).build(),
+            url: url.to_owned(),
+            etag: None,
+            last_modified: None,
+            contents: String::new(),
+        })
+    }
+
+    // Returns `None` if the document hasn't changed since the last fetch.
+    fn fetch(&mut self, lenient: bool) -> Result<Option<RawConfig>, Box<error::Error + Sync + Send>> {
+        let mut request = self.agent.get(&self.url);
+        if let Some(ref etag) = self.etag {
+            request = request.set("If-None-Match", etag);
+        }
+        if let Some(ref last_modified) = self.last_modified {
+            request = request.set("If-Modified-Since", last_modified);
+        }
+
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, _)) => {
+                return Err(format!("error fetching `{}`: HTTP status {}", self.url, status).into())
+            }
+            Err(e) => return Err(format!("error fetching `{}`: {}", self.url, e).into()),
+        };
+        if response.status() == 304 {
+            return Ok(None);
+        }
+
+        self.etag = response.header("ETag").map(ToOwned::to_owned);
+        self.last_modified = response.header("Last-Modified").map(ToOwned::to_owned);
+        let format = Format::from_extension(extension(response.content_type(), &self.url))?;
+        let contents = response.into_string()?;
+        if contents == self.contents {
+            return Ok(None);
+        }
+
+        // an invalid document isn't fetched again until it changes
+        self.contents = contents;
+        let value = format.parse(&self.contents)?;
+        let value = priv_file::select_profile(value, env::var(PROFILE_VAR).ok().as_deref())?;
+        Ok(Some(priv_file::deserialize_raw(value, lenient)?))
+    }
+}
+
+// The format extension matching a content type, or else the URL's.
+fn extension<'a>(content_type: &str, url: &'a str) -> Option<&'a str> {
+    match content_type {
+        "application/json" => return Some("json"),
+        "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+            return Some("yaml")
+        }
+        "application/toml" | "text/toml" => return Some("toml"),
+        _ => {}
+    }
+
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match name.rfind('.') {
+        Some(i) if i > 0 => Some(&name[i + 1..]),
+        _ => None,
+    }
+}
+
+struct RemoteReloader {
+    source: Source,
+    deserializers: Deserializers,
+    running: Running,
+    handle: Handle,
+}
+
+impl RemoteReloader {
+    fn run(&mut self, mut rate: Duration) {
+        loop {
+            thread::sleep(rate);
+
+            let config = match self.source.fetch(self.deserializers.lenient()) {
+                Ok(Some(config)) => config,
+                Ok(None) => continue,
+                Err(e) => {
+                    handle_error(&*e);
+                    continue;
+                }
+            };
+
+            let refresh_rate = config.refresh_rate();
+            let config = priv_file::deserialize_reusing(&config,
+                                                        &self.deserializers,
+                                                        &|b| b,
+                                                        &mut self.running);
+            self.handle.set_config(config);
+
+            match refresh_rate {
+                Some(r) => rate = r,
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extensions() {
+        assert_eq!(extension("application/json", "http://example.com/log4rs"), Some("json"));
+        assert_eq!(extension("text/plain", "http://example.com/log4rs.yml?v=2#top"),
+                   Some("yml"));
+        assert_eq!(extension("text/plain", "http://example.com/config/"), None);
+        assert_eq!(extension("text/plain", "http://example.com/.hidden"), None);
+        assert!(Source::new("ftp://example.com/log4rs.yaml").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use log::LevelFilter;
+use std::cell::RefCell;
+use std::cmp;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use Handle;
+
+// the number of active scopes on all threads, by level
+static ACTIVE: [AtomicUsize; 6] = [AtomicUsize::new(0),
+                                   AtomicUsize::new(0),
+                                   AtomicUsize::new(0),
+                                   AtomicUsize::new(0),
+                                   AtomicUsize::new(0),
+                                   AtomicUsize::new(0)];
+static ANY_ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// the handle of the global logger, whose maximum level is kept high enough
+// for the active scopes
+static GLOBAL: Mutex<Option<Handle>> = Mutex::new(None);
+
+const LEVELS: [LevelFilter; 6] = [LevelFilter::Off,
+                                     LevelFilter::Error,
+                                     LevelFilter::Warn,
+                                     LevelFilter::Info,
+                                     LevelFilter::Debug,
+                                     LevelFilter::Trace];
+
+struct Scope {
+    id: usize,
+    target: String,
+    level: LevelFilter,
+}
+
+thread_local! {
+    static SCOPES: RefCell<Vec<Scope>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Raises the maximum level of events logged by the current thread to the
+/// specified target and its descendants until the returned guard is dropped.
+///
+/// This allows tracing a single request or operation without raising the
+/// level for the whole process:
+///
+/// ```
+/// # #[macro_use] extern crate log;
+/// # extern crate log4rs;
+/// # use log::LevelFilter;
+/// # fn main() {
+/// let _verbose = log4rs::verbose_scope("app::db", LevelFilter::Trace);
+/// trace!(target: "app::db::pool", "acquired a connection");
+/// # }
+/// ```
+///
+/// The level only applies to events logged by the current thread; work the
+/// scope hands to other threads or tasks is logged at the configured levels.
+/// Levels are only raised, never lowered, and an empty target covers every
+/// target. Events still go to the appenders of the loggers applying to their
+/// targets, and are subject to the appenders' levels and filters.
+pub fn verbose_scope(target: &str, level: LevelFilter) -> VerboseScope {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    SCOPES.with(|s| {
+        s.borrow_mut().push(Scope {
+            id: id,
+            target: target.to_owned(),
+            level: level,
+        })
+    });
+    ACTIVE[level as usize].fetch_add(1, Ordering::SeqCst);
+    ANY_ACTIVE.fetch_add(1, Ordering::SeqCst);
+    refresh();
+
+    VerboseScope {
+        id: id,
+        level: level,
+        _p: PhantomData,
+    }
+}
+
+/// A guard returned by `verbose_scope` which restores the levels of the
+/// current thread when dropped.
+#[must_use = "the levels are restored when the guard is dropped"]
+pub struct VerboseScope {
+    id: usize,
+    level: LevelFilter,
+    // the scope belongs to the thread which created it
+    _p: PhantomData<*const ()>,
+}
+
+impl Drop for VerboseScope {
+    fn drop(&mut self) {
+        let id = self.id;
+        let _ = SCOPES.try_with(|s| s.borrow_mut().retain(|s| s.id != id));
+        ACTIVE[self.level as usize].fetch_sub(1, Ordering::SeqCst);
+        ANY_ACTIVE.fetch_sub(1, Ordering::SeqCst);
+        refresh();
+    }
+}
+
+/// Registers the handle of the global logger.
+pub fn register(handle: Handle) {
+    *GLOBAL.lock().unwrap_or_else(|e| e.into_inner()) = Some(handle);
+}
+
+/// Returns the handle of the global logger, if it has been initialized.
+pub fn global() -> Option<Handle> {
+    GLOBAL.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// The level the current thread's scopes raise the target to, if any apply.
+fn level(target: &str) -> Option<LevelFilter> {
+    if ANY_ACTIVE.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    SCOPES.try_with(|s| {
+            s.borrow()
+                .iter()
+                .filter(|s| ::within(target, &s.target))
+                .map(|s| s.level)
+                .max()
+        })
+        .ok()
+        .and_then(|l| l)
+}
+
+/// Returns the highest level of the scopes active on any thread.
+pub fn max_level() -> LevelFilter {
+    LEVELS.iter()
+        .rev()
+        .zip(ACTIVE.iter().rev())
+        .find(|&(_, count)| count.load(Ordering::SeqCst) > 0)
+        .map_or(LevelFilter::Off, |(&level, _)| level)
+}
+
+fn refresh() {
+    if let Some(handle) = global() {
+        // without the update lock, a level computed from the configuration
+        // before an update could be stored after it
+        let _update = handle.lock_update();
+        ::log::set_max_level(handle.shared.load().max_log_level());
+    }
+}
+
+/// Raises a configured level to that of the current thread's scopes applying
+/// to the target.
+pub fn raise(level: LevelFilter, target: &str) -> LevelFilter {
+    match self::level(target) {
+        Some(scope) => cmp::max(level, scope),
+        None => level,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scopes() {
+        assert_eq!(raise(LevelFilter::Info, "app::db"), LevelFilter::Info);
+        {
+            let _outer = verbose_scope("app", LevelFilter::Debug);
+            let _inner = verbose_scope("app::db", LevelFilter::Trace);
+            assert!(max_level() >= LevelFilter::Trace);
+            assert_eq!(raise(LevelFilter::Info, "app::db::pool"), LevelFilter::Trace);
+            assert_eq!(raise(LevelFilter::Info, "app::http"), LevelFilter::Debug);
+            assert_eq!(raise(LevelFilter::Info, "apparent"), LevelFilter::Info);
+            assert_eq!(raise(LevelFilter::Trace, "app"), LevelFilter::Trace);
+
+            let other = ::std::thread::spawn(|| raise(LevelFilter::Info, "app::db"));
+            assert_eq!(other.join().unwrap(), LevelFilter::Info);
+        }
+        assert_eq!(raise(LevelFilter::Info, "app::db"), LevelFilter::Info);
+    }
+}
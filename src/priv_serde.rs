@@ -1,11 +1,58 @@
-use log::LogLevelFilter;
-use serde::de::{self, Deserialize};
+use humantime;
+use log::{Level, LevelFilter};
+use config::{Backpressure, ErrorPolicy};
+use serde::de::{self, Deserialize, DeserializeOwned};
+use serde_value::{DeserializerError, Value};
+use std::env;
 use std::fmt;
+use std::fs;
+use std::time::Duration;
 
-pub fn de_filter<'de, D>(d: D) -> Result<LogLevelFilter, D::Error>
+// Deserializes a value, dropping fields which aren't recognized with a
+// warning. Fields of the top level map are preferred over nested ones, and
+// the values under the `skip` keys of the top level map are left alone.
+pub fn deserialize_lenient<T>(mut value: Value, skip: &[&str]) -> Result<T, DeserializerError>
+    where T: DeserializeOwned
+{
+    loop {
+        match value.clone().deserialize_into() {
+            Err(DeserializerError::UnknownField(field, expected)) => {
+                if !(0..4).any(|depth| remove_field(&mut value, &field, depth, skip)) {
+                    return Err(DeserializerError::UnknownField(field, expected));
+                }
+                ::handle_error(&*Box::<::std::error::Error + Sync + Send>::from(
+                    format!("ignoring unknown field `{}`", field)));
+            }
+            r => return r,
+        }
+    }
+}
+
+// Removes a field from a map `depth` levels down.
+fn remove_field(value: &mut Value, field: &str, depth: usize, skip: &[&str]) -> bool {
+    match *value {
+        Value::Map(ref mut map) if depth == 0 => {
+            map.remove(&Value::String(field.to_owned())).is_some()
+        }
+        Value::Map(ref mut map) => {
+            map.iter_mut()
+                .filter(|&(k, _)| match *k {
+                    Value::String(ref k) => !skip.contains(&&**k),
+                    _ => true,
+                })
+                .any(|(_, v)| remove_field(v, field, depth - 1, &[]))
+        }
+        Value::Seq(ref mut seq) if depth > 0 => {
+            seq.iter_mut().any(|v| remove_field(v, field, depth - 1, &[]))
+        }
+        _ => false,
+    }
+}
+
+pub fn de_filter<'de, D>(d: D) -> Result<LevelFilter, D::Error>
     where D: de::Deserializer<'de>
 {
-    struct S(LogLevelFilter);
+    struct S(LevelFilter);
 
     impl<'de2> de::Deserialize<'de2> for S {
         fn deserialize<D>(d: D) -> Result<S, D::Error>
@@ -33,3 +80,240 @@ pub fn de_filter<'de, D>(d: D) -> Result<LogLevelFilter, D::Error>
 
     S::deserialize(d).map(|s| s.0)
 }
+
+pub fn de_duration<'de, D>(d: D) -> Result<Option<Duration>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    struct S(Duration);
+
+    impl<'de2> de::Deserialize<'de2> for S {
+        fn deserialize<D>(d: D) -> Result<S, D::Error>
+            where D: de::Deserializer<'de2>
+        {
+            struct V;
+
+            impl<'de3> de::Visitor<'de3> for V {
+                type Value = S;
+
+                fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                    fmt.write_str("a duration with units, like `30 seconds` or `500ms`")
+                }
+
+                fn visit_str<E>(self, v: &str) -> Result<S, E>
+                    where E: de::Error
+                {
+                    humantime::parse_duration(v)
+                        .map(S)
+                        .map_err(|e| E::custom(e))
+                }
+            }
+
+            d.deserialize_any(V)
+        }
+    }
+
+    Option::<S>::deserialize(d).map(|r| r.map(|s| s.0))
+}
+
+pub fn de_size<'de, D>(d: D) -> Result<u64, D::Error>
+    where D: de::Deserializer<'de>
+{
+    struct V;
+
+    impl<'de2> de::Visitor<'de2> for V {
+        type Value = u64;
+
+        fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+            fmt.write_str("a size, like `250 MB` or `1GiB`")
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<u64, E>
+            where E: de::Error
+        {
+            Ok(v)
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<u64, E>
+            where E: de::Error
+        {
+            if v < 0 {
+                return Err(E::invalid_value(de::Unexpected::Signed(v), &"a non-negative number"));
+            }
+
+            Ok(v as u64)
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<u64, E>
+            where E: de::Error
+        {
+            let v = v.trim();
+            let (number, unit) = match v.find(|c: char| !c.is_ascii_digit() && c != '.') {
+                Some(n) => (&v[..n], v[n..].trim()),
+                None => (v, ""),
+            };
+
+            let multiplier: u64 = match &*unit.to_ascii_lowercase() {
+                "" | "b" | "byte" | "bytes" => 1,
+                "k" | "kb" | "kib" => 1024,
+                "m" | "mb" | "mib" => 1024 * 1024,
+                "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+                "t" | "tb" | "tib" => 1024 * 1024 * 1024 * 1024,
+                _ => return Err(E::invalid_value(de::Unexpected::Str(unit), &"a valid unit")),
+            };
+
+            // allow fractions like `1.5 GB`, rounding down to a whole byte
+            let number = if number.contains('.') {
+                match number.parse::<f64>() {
+                    Ok(n) if (n * multiplier as f64) < u64::MAX as f64 => {
+                        Some((n * multiplier as f64) as u64)
+                    }
+                    Ok(_) => None,
+                    Err(_) => {
+                        return Err(E::invalid_value(de::Unexpected::Str(number), &"a number"))
+                    }
+                }
+            } else {
+                match number.parse::<u64>() {
+                    Ok(n) => n.checked_mul(multiplier),
+                    Err(_) => {
+                        return Err(E::invalid_value(de::Unexpected::Str(number), &"a number"))
+                    }
+                }
+            };
+
+            match number {
+                Some(n) => Ok(n),
+                None => Err(E::invalid_value(de::Unexpected::Str(v), &"a byte size")),
+            }
+        }
+    }
+
+    d.deserialize_any(V)
+}
+
+pub fn de_size_opt<'de, D>(d: D) -> Result<Option<u64>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    de_size(d).map(Some)
+}
+
+pub fn de_error_policy<'de, D>(d: D) -> Result<Option<ErrorPolicy>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    let value = String::deserialize(d)?;
+    let policy = match &*value {
+        "ignore" => ErrorPolicy::Ignore,
+        "stderr" => ErrorPolicy::Stderr,
+        "panic" => ErrorPolicy::Panic,
+        _ => {
+            let name = value.strip_prefix("appender(").and_then(|s| s.strip_suffix(')'));
+            match name.map(str::trim) {
+                Some(name) if !name.is_empty() => ErrorPolicy::Appender(name.to_owned()),
+                _ => {
+                    return Err(de::Error::invalid_value(de::Unexpected::Str(&value),
+                                                        &"ignore, stderr, panic, or appender(name)"))
+                }
+            }
+        }
+    };
+    Ok(Some(policy))
+}
+
+pub fn de_backpressure<'de, D>(d: D) -> Result<Option<Backpressure>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    let value = String::deserialize(d)?;
+    let backpressure = match &*value {
+        "block" => Backpressure::Block,
+        "drop_newest" => Backpressure::DropNewest,
+        "drop_oldest" => Backpressure::DropOldest,
+        _ => {
+            let level = value.strip_prefix("drop_below(").and_then(|s| s.strip_suffix(')'));
+            match level.and_then(|l| l.trim().parse::<Level>().ok()) {
+                Some(level) => Backpressure::DropBelow(level),
+                None => {
+                    return Err(de::Error::invalid_value(de::Unexpected::Str(&value),
+                                                        &"block, drop_newest, drop_oldest, or \
+                                                          drop_below(level)"))
+                }
+            }
+        }
+    };
+    Ok(Some(backpressure))
+}
+
+pub fn de_secret<'de, D>(d: D) -> Result<String, D::Error>
+    where D: de::Deserializer<'de>
+{
+    let value = String::deserialize(d)?;
+    secret(&value).map_err(de::Error::custom)
+}
+
+pub fn de_secret_opt<'de, D>(d: D) -> Result<Option<String>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    de_secret(d).map(Some)
+}
+
+// Resolves `env:NAME` and `file:PATH` references. The value itself is never
+// part of an error message.
+fn secret(value: &str) -> Result<String, String> {
+    if let Some(name) = value.strip_prefix("env:") {
+        env::var(name).map_err(|_| format!("environment variable `{}` is not set", name))
+    } else if let Some(path) = value.strip_prefix("file:") {
+        fs::read_to_string(path)
+            .map(|s| s.trim().to_owned())
+            .map_err(|e| format!("error reading secret `{}`: {}", path, e))
+    } else {
+        Ok(value.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_value::Value;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        let size = |s: &str| de_size(Value::String(s.to_owned()));
+        assert_eq!(size("1024").unwrap(), 1024);
+        assert_eq!(size("250 MB").unwrap(), 250 * 1024 * 1024);
+        assert_eq!(size("1GiB").unwrap(), 1024 * 1024 * 1024);
+        assert_eq!(size("64k").unwrap(), 64 * 1024);
+        assert_eq!(size("1.5 kb").unwrap(), 1536);
+        assert_eq!(size(" 10 bytes ").unwrap(), 10);
+        assert_eq!(de_size(Value::U64(10)).unwrap(), 10);
+        assert!(size("10 parsecs").is_err());
+        assert!(size("1.2.3 mb").is_err());
+        assert!(size("100000000 tb").is_err());
+        assert!(de_size(Value::I64(-1)).is_err());
+    }
+
+    #[test]
+    fn durations() {
+        let duration = |s: &str| de_duration(Value::String(s.to_owned()));
+        assert_eq!(duration("15 minutes").unwrap(), Some(Duration::from_secs(15 * 60)));
+        assert_eq!(duration("500ms").unwrap(), Some(Duration::from_millis(500)));
+        assert!(duration("15").is_err());
+        assert!(de_duration(Value::U64(15)).is_err());
+    }
+
+    #[test]
+    fn secrets() {
+        env::set_var("LOG4RS_PRIV_SERDE_TEST_SECRET", "hunter2");
+        assert_eq!(secret("env:LOG4RS_PRIV_SERDE_TEST_SECRET").unwrap(), "hunter2");
+        assert!(secret("env:LOG4RS_PRIV_SERDE_TEST_MISSING").is_err());
+
+        let dir = TempDir::new("secrets").unwrap();
+        let path = dir.path().join("key");
+        fs::File::create(&path).unwrap().write_all(b"0123456789abcdef\n").unwrap();
+        assert_eq!(secret(&format!("file:{}", path.display())).unwrap(), "0123456789abcdef");
+        assert!(secret("file:/nonexistent/log4rs-secret").is_err());
+
+        assert_eq!(secret("plaintext").unwrap(), "plaintext");
+    }
+}
@@ -0,0 +1,77 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+// the number of shards of each counter, a power of two
+const SHARDS: usize = 16;
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: Cell<usize> = const { Cell::new(usize::MAX) };
+}
+
+// The shard of the current thread, assigned round robin so that threads
+// incrementing a counter concurrently usually update different cache lines.
+fn shard() -> usize {
+    SHARD.try_with(|s| {
+            if s.get() == usize::MAX {
+                s.set(NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % SHARDS);
+            }
+            s.get()
+        })
+        .unwrap_or(0)
+}
+
+#[repr(align(64))]
+struct Slot(AtomicU64);
+
+/// A counter which is cheap to increment from many threads at once, at the
+/// cost of reading it.
+pub struct Counter([Slot; SHARDS]);
+
+impl Default for Counter {
+    fn default() -> Counter {
+        Counter::new()
+    }
+}
+
+impl Counter {
+    pub const fn new() -> Counter {
+        Counter([const { Slot(AtomicU64::new(0)) }; SHARDS])
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0[shard()].0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.iter().map(|s| s.0.load(Ordering::Relaxed)).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn counter() {
+        let counter = Arc::new(Counter::new());
+        let threads = (0..SHARDS + 3)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        counter.add(2);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(counter.get(), (SHARDS as u64 + 3) * 200);
+    }
+}
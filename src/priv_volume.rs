@@ -0,0 +1,215 @@
+use log::Level;
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use stats::TargetVolume;
+use TargetVolumePrivateExt;
+
+// the number of buckets each window is divided into
+const BUCKETS: usize = 60;
+
+// the maximum number of targets tracked, after which events to other targets
+// are counted under `OTHER`
+const MAX_TARGETS: usize = 10_000;
+
+const OTHER: &str = "<other>";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TRACKER: RwLock<Option<Arc<Tracker>>> = RwLock::new(None);
+
+/// Starts counting events over the specified windows, discarding previous
+/// counts. No events are counted if there are no windows.
+pub fn track(windows: &[Duration]) {
+    let mut tracker = TRACKER.write().unwrap_or_else(|e| e.into_inner());
+    if windows.is_empty() {
+        ENABLED.store(false, Ordering::SeqCst);
+        *tracker = None;
+    } else {
+        *tracker = Some(Arc::new(Tracker::new(windows)));
+        ENABLED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Counts an event written by the logger.
+pub fn add(target: &str, level: Level) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let tracker = TRACKER.read().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(tracker) = tracker {
+        tracker.add(target, level, tracker.now());
+    }
+}
+
+/// Returns the `n` targets and levels with the most events over the
+/// specified window, or `None` if the window isn't tracked.
+pub fn top(window: Duration, n: usize) -> Option<Vec<TargetVolume>> {
+    let tracker = TRACKER.read().unwrap_or_else(|e| e.into_inner()).clone();
+    tracker.and_then(|t| t.top(window, n, t.now()))
+}
+
+struct Tracker {
+    start: Instant,
+    windows: Vec<Duration>,
+    targets: RwLock<HashMap<String, Arc<Mutex<TargetCounts>>>>,
+}
+
+// the windows of each level, created by the level's first event
+struct TargetCounts([Vec<Window>; 5]);
+
+impl Tracker {
+    fn new(windows: &[Duration]) -> Tracker {
+        Tracker {
+            start: Instant::now(),
+            windows: windows.to_vec(),
+            targets: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // nanoseconds since the tracker was created
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    fn add(&self, target: &str, level: Level, now: u64) {
+        let counts = self.targets.read().unwrap_or_else(|e| e.into_inner()).get(target).cloned();
+        let counts = match counts {
+            Some(counts) => counts,
+            None => {
+                let mut targets = self.targets.write().unwrap_or_else(|e| e.into_inner());
+                let target = if targets.len() < MAX_TARGETS || targets.contains_key(target) {
+                    target
+                } else {
+                    OTHER
+                };
+                targets.entry(target.to_owned())
+                    .or_insert_with(|| Arc::new(Mutex::new(TargetCounts(Default::default()))))
+                    .clone()
+            }
+        };
+
+        let mut counts = counts.lock().unwrap_or_else(|e| e.into_inner());
+        let windows = &mut counts.0[level as usize - 1];
+        if windows.is_empty() {
+            *windows = self.windows.iter().map(|&w| Window::new(w)).collect();
+        }
+        for window in windows {
+            window.add(now);
+        }
+    }
+
+    fn top(&self, window: Duration, n: usize, now: u64) -> Option<Vec<TargetVolume>> {
+        let idx = self.windows.iter().position(|&w| w == window)?;
+
+        let mut volumes = vec![];
+        for (target, counts) in self.targets.read().unwrap_or_else(|e| e.into_inner()).iter() {
+            let mut counts = counts.lock().unwrap_or_else(|e| e.into_inner());
+            for (level, windows) in LEVELS.iter().zip(counts.0.iter_mut()) {
+                let count = windows.get_mut(idx).map_or(0, |w| w.count(now));
+                if count > 0 {
+                    volumes.push(TargetVolume::new(target.clone(), *level, count));
+                }
+            }
+        }
+        volumes.sort_by(|a, b| {
+            b.count().cmp(&a.count()).then_with(|| a.target().cmp(b.target()))
+        });
+        volumes.truncate(n);
+        Some(volumes)
+    }
+}
+
+const LEVELS: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+// Counts events over a window, in buckets which expire as the window moves.
+struct Window {
+    // nanoseconds per bucket
+    width: u64,
+    // the number of the newest bucket since the tracker was created
+    newest: u64,
+    buckets: [u64; BUCKETS],
+}
+
+impl Window {
+    fn new(window: Duration) -> Window {
+        Window {
+            width: cmp::max(window.as_nanos() as u64 / BUCKETS as u64, 1),
+            newest: 0,
+            buckets: [0; BUCKETS],
+        }
+    }
+
+    fn advance(&mut self, now: u64) {
+        let bucket = now / self.width;
+        if bucket <= self.newest {
+            return;
+        }
+        let expired = cmp::min(bucket - self.newest, BUCKETS as u64);
+        for i in 1..=expired {
+            self.buckets[((self.newest + i) % BUCKETS as u64) as usize] = 0;
+        }
+        self.newest = bucket;
+    }
+
+    fn add(&mut self, now: u64) {
+        self.advance(now);
+        self.buckets[(self.newest % BUCKETS as u64) as usize] += 1;
+    }
+
+    fn count(&mut self, now: u64) -> u64 {
+        self.advance(now);
+        self.buckets.iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn window() {
+        let mut window = Window::new(Duration::from_secs(60));
+        window.add(0);
+        window.add(30 * SECOND);
+        assert_eq!(window.count(59 * SECOND), 2);
+        assert_eq!(window.count(61 * SECOND), 1);
+        assert_eq!(window.count(1000 * SECOND), 0);
+        window.add(1000 * SECOND);
+        assert_eq!(window.count(1000 * SECOND), 1);
+    }
+
+    #[test]
+    fn top() {
+        let minute = Duration::from_secs(60);
+        let hour = Duration::from_secs(3600);
+        let tracker = Tracker::new(&[minute, hour]);
+        for _ in 0..3 {
+            tracker.add("app::db", Level::Debug, 0);
+        }
+        tracker.add("app::http", Level::Info, 0);
+        for _ in 0..2 {
+            tracker.add("app::http", Level::Info, 120 * SECOND);
+        }
+        tracker.add("app::db", Level::Error, 120 * SECOND);
+
+        let top = |window, n| {
+            tracker.top(window, n, 120 * SECOND)
+                .unwrap()
+                .iter()
+                .map(|v| (v.target().to_owned(), v.level(), v.count()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(top(minute, 10),
+                   [("app::http".to_owned(), Level::Info, 2),
+                    ("app::db".to_owned(), Level::Error, 1)]);
+        assert_eq!(top(hour, 2),
+                   [("app::db".to_owned(), Level::Debug, 3),
+                    ("app::http".to_owned(), Level::Info, 3)]);
+        assert!(tracker.top(Duration::from_secs(5), 10, 0).is_none());
+    }
+}
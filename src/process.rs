@@ -0,0 +1,209 @@
+//! Logging the output of child processes.
+//!
+//! A `LoggedCommand` runs a `std::process::Command` with its stdout and
+//! stderr piped back to the parent, and logs each line the child writes as
+//! an event:
+//!
+//! ```no_run
+//! # extern crate log;
+//! # extern crate log4rs;
+//! # use log::Level;
+//! # use std::process::Command;
+//! # use log4rs::process::LoggedCommand;
+//! # fn main() {
+//! let mut command = Command::new("pg_dump");
+//! command.arg("billing");
+//! let status = LoggedCommand::new(command)
+//!     .target("app::backup::pg_dump")
+//!     .stderr_level(Level::Error)
+//!     .status()
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! Lines are read on a background thread per stream, and invalid UTF-8 is
+//! replaced. With the `mdc` feature, the lines are logged with the MDC
+//! entries of the thread which spawned the child.
+//!
+//! Requires the `process` feature.
+
+use log::Level;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+
+#[cfg(feature = "mdc")]
+use mdc::MdcScope;
+
+/// A command whose output is logged.
+#[derive(Debug)]
+pub struct LoggedCommand {
+    command: Command,
+    target: Option<String>,
+    stdout_level: Level,
+    stderr_level: Level,
+}
+
+impl LoggedCommand {
+    /// Creates a new `LoggedCommand`.
+    ///
+    /// The command's stdout and stderr are replaced by pipes when it is
+    /// spawned; its stdin is left as configured.
+    pub fn new(command: Command) -> LoggedCommand {
+        LoggedCommand {
+            command: command,
+            target: None,
+            stdout_level: Level::Info,
+            stderr_level: Level::Warn,
+        }
+    }
+
+    /// Sets the target of the logged lines.
+    ///
+    /// Defaults to the name of the program, prefixed by `process::`.
+    pub fn target(mut self, target: &str) -> LoggedCommand {
+        self.target = Some(target.to_owned());
+        self
+    }
+
+    /// Sets the level lines written to stdout are logged at.
+    ///
+    /// Defaults to `Info`.
+    pub fn stdout_level(mut self, level: Level) -> LoggedCommand {
+        self.stdout_level = level;
+        self
+    }
+
+    /// Sets the level lines written to stderr are logged at.
+    ///
+    /// Defaults to `Warn`.
+    pub fn stderr_level(mut self, level: Level) -> LoggedCommand {
+        self.stderr_level = level;
+        self
+    }
+
+    /// Spawns the command.
+    pub fn spawn(mut self) -> io::Result<LoggedChild> {
+        let target = match self.target {
+            Some(target) => target,
+            None => format!("process::{}", self.command.get_program().to_string_lossy()),
+        };
+
+        let mut child = self.command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let mut threads = vec![];
+        if let Some(stdout) = child.stdout.take() {
+            threads.push(forward(stdout, target.clone(), self.stdout_level)?);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            threads.push(forward(stderr, target, self.stderr_level)?);
+        }
+
+        Ok(LoggedChild {
+            child: child,
+            threads: threads,
+        })
+    }
+
+    /// Spawns the command and waits for it to exit and for its output to be
+    /// logged.
+    pub fn status(self) -> io::Result<ExitStatus> {
+        self.spawn()?.wait()
+    }
+}
+
+/// A child process whose output is being logged.
+///
+/// Created by `LoggedCommand::spawn`.
+#[derive(Debug)]
+pub struct LoggedChild {
+    child: Child,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl LoggedChild {
+    /// Returns the OS-assigned process identifier of the child.
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Forces the child to exit.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()
+    }
+
+    /// Waits for the child to exit and for the rest of its output to be
+    /// logged.
+    ///
+    /// Output is logged until the child and any processes it started which
+    /// inherited its stdout or stderr have closed them.
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait()?;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        Ok(status)
+    }
+}
+
+fn forward<R>(stream: R, target: String, level: Level) -> io::Result<JoinHandle<()>>
+    where R: Read + Send + 'static
+{
+    #[cfg(feature = "mdc")]
+    let scope = MdcScope::capture();
+    thread::Builder::new().name(format!("log4rs {}", target)).spawn(move || {
+        #[cfg(feature = "mdc")]
+        let _guard = scope.enter();
+        let result = lines(stream, |line| ::log::log!(target: &target, level, "{}", line));
+        if let Err(e) = result {
+            let e = format!("error reading the output of `{}`: {}", target, e);
+            ::handle_error(&*Box::<::std::error::Error + Sync + Send>::from(e));
+        }
+    })
+}
+
+// Calls `f` with each line of the stream, without its line ending.
+fn lines<R, F>(stream: R, mut f: F) -> io::Result<()>
+    where R: Read,
+          F: FnMut(&str)
+{
+    let mut reader = BufReader::new(stream);
+    let mut buf = vec![];
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(());
+        }
+        if buf.ends_with(b"\n") {
+            buf.pop();
+            if buf.ends_with(b"\r") {
+                buf.pop();
+            }
+        }
+        f(&String::from_utf8_lossy(&buf));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_lines() {
+        let mut out = vec![];
+        lines(&b"one\r\ntwo\n\n\xffthree"[..], |l| out.push(l.to_owned())).unwrap();
+        assert_eq!(out, ["one", "two", "", "\u{fffd}three"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn status() {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo out; echo err >&2; exit 3");
+        let status = LoggedCommand::new(command).status().unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+}
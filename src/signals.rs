@@ -0,0 +1,259 @@
+//! Unix signal handling.
+//!
+//! `install` maps the conventional signals to logger management actions:
+//!
+//! * `SIGHUP` reloads the config file, as with `Handle::reload`.
+//! * `SIGUSR1` reopens the appenders' files, as with `Handle::reopen`, which
+//!     is what `logrotate` expects after it has moved a log file away.
+//! * `SIGUSR2` writes the status of the logger to stderr.
+//!
+//! Signals are delivered to a background thread, so actions run outside of
+//! the signal handler and may take locks and perform I/O. Errors are reported
+//! to stderr.
+//!
+//! Requires the `signals` feature; only available on Unix.
+
+use libc::{self, c_int, c_void};
+use std::io::{self, Write};
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+
+use {handle_error, Handle};
+use stats::Stats;
+use topology::Topology;
+
+/// An action taken when a signal is received.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Reload the config file.
+    Reload,
+    /// Reopen the appenders' files.
+    Reopen,
+    /// Write the status of the logger to stderr.
+    Dump,
+}
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// Installs handlers for `SIGHUP`, `SIGUSR1`, and `SIGUSR2` which act on the
+/// logger of `handle`.
+///
+/// Returns an error if signal handlers have already been installed.
+pub fn install(handle: &Handle) -> io::Result<()> {
+    install_with(handle,
+                 &[(libc::SIGHUP, Action::Reload),
+                   (libc::SIGUSR1, Action::Reopen),
+                   (libc::SIGUSR2, Action::Dump)])
+}
+
+/// Installs handlers taking the action paired with each signal.
+///
+/// Signals whose default action is to terminate the process, like `SIGTERM`,
+/// no longer do so once they are mapped.
+///
+/// Returns an error if signal handlers have already been installed.
+pub fn install_with(handle: &Handle, actions: &[(c_int, Action)]) -> io::Result<()> {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                  "log4rs signal handlers are already installed"));
+    }
+    match start(handle, actions) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            INSTALLED.store(false, Ordering::SeqCst);
+            Err(e)
+        }
+    }
+}
+
+fn start(handle: &Handle, actions: &[(c_int, Action)]) -> io::Result<()> {
+    if let Some(&(signal, _)) = actions.iter().find(|&&(s, _)| s <= 0 || s > 255) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("invalid signal {}", signal)));
+    }
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let (read, write) = (fds[0], fds[1]);
+    unsafe {
+        libc::fcntl(read, libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(write, libc::F_SETFD, libc::FD_CLOEXEC);
+        // the handler must never block
+        libc::fcntl(write, libc::F_SETFL, libc::O_NONBLOCK);
+    }
+    PIPE.store(write, Ordering::SeqCst);
+
+    let handle = handle.clone();
+    let actions = actions.to_vec();
+    let signals = actions.iter().map(|&(s, _)| s).collect::<Vec<_>>();
+    thread::Builder::new()
+        .name("log4rs signals".to_owned())
+        .spawn(move || run(read, &handle, &actions))?;
+
+    for signal in signals {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+extern "C" fn handler(signal: c_int) {
+    let errno = io::Error::last_os_error().raw_os_error();
+    let byte = signal as u8;
+    // if the pipe is full, the signal is dropped, as it would be by the
+    // kernel if it were already pending
+    unsafe {
+        libc::write(PIPE.load(Ordering::Relaxed), &byte as *const u8 as *const c_void, 1);
+    }
+    if let Some(errno) = errno {
+        set_errno(errno);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+fn set_errno(errno: c_int) {
+    unsafe { *libc::__errno_location() = errno }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn set_errno(errno: c_int) {
+    unsafe { *libc::__error() = errno }
+}
+
+#[cfg(not(any(target_os = "linux",
+              target_os = "android",
+              target_os = "emscripten",
+              target_os = "macos",
+              target_os = "ios",
+              target_os = "freebsd")))]
+fn set_errno(_: c_int) {}
+
+fn run(read: c_int, handle: &Handle, actions: &[(c_int, Action)]) {
+    loop {
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(read, &mut byte as *mut u8 as *mut c_void, 1) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            handle_error(&e);
+            return;
+        }
+        if n == 0 {
+            return;
+        }
+
+        if let Some(&(_, action)) = actions.iter().find(|&&(s, _)| s == byte as c_int) {
+            dispatch(handle, action);
+        }
+    }
+}
+
+fn dispatch(handle: &Handle, action: Action) {
+    match action {
+        Action::Reload => {
+            if let Err(e) = handle.reload() {
+                handle_error(&*e);
+            }
+        }
+        Action::Reopen => handle.reopen(),
+        Action::Dump => {
+            let stderr = io::stderr();
+            let mut stderr = stderr.lock();
+            let status = dump(&mut stderr, handle.reloads(), &handle.topology(), &handle.stats());
+            if let Err(e) = status {
+                handle_error(&e);
+            }
+        }
+    }
+}
+
+fn dump(w: &mut Write, reloads: u64, topology: &Topology, stats: &Stats) -> io::Result<()> {
+    writeln!(w, "log4rs status:")?;
+    writeln!(w, "  reloads: {}", reloads)?;
+
+    let root = topology.root();
+    writeln!(w, "  root: {} -> {}", root.level(), root.appenders().join(", "))?;
+    for logger in topology.loggers() {
+        writeln!(w,
+                 "  logger `{}`: {}{} -> {}",
+                 logger.name(),
+                 logger.level(),
+                 if logger.additive() { "" } else { " (not additive)" },
+                 logger.appenders().join(", "))?;
+    }
+
+    for appender in topology.appenders() {
+        write!(w,
+               "  appender `{}` ({}): level {}",
+               appender.name(),
+               appender.kind().unwrap_or("custom"),
+               appender.level())?;
+        if let Some(stats) = stats.appender(appender.name()) {
+            write!(w,
+                   ", {} records, {} errors, {} filtered, {} drops",
+                   stats.records(),
+                   stats.errors(),
+                   stats.filtered(),
+                   stats.drops())?;
+            if let Some(bytes) = stats.bytes() {
+                write!(w, ", {} bytes", bytes)?;
+            }
+        }
+        writeln!(w)?;
+    }
+    w.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use log::LevelFilter;
+
+    use super::*;
+    use stats::AppenderStats;
+    use topology::{AppenderInfo, LoggerInfo};
+    use {AppenderInfoPrivateExt, AppenderStatsPrivateExt, LoggerInfoPrivateExt,
+         StatsPrivateExt, TopologyPrivateExt};
+
+    #[test]
+    fn dump_status() {
+        let root = LoggerInfo::new(String::new(),
+                                   LevelFilter::Info,
+                                   LevelFilter::Info,
+                                   false,
+                                   vec!["stdout".to_owned()]);
+        let logger = LoggerInfo::new("foo".to_owned(),
+                                     LevelFilter::Debug,
+                                     LevelFilter::Warn,
+                                     false,
+                                     vec![]);
+        let appender = AppenderInfo::new("stdout".to_owned(),
+                                         Some("console".to_owned()),
+                                         LevelFilter::Trace,
+                                         None,
+                                         vec![],
+                                         String::new());
+        let topology = Topology::new(root, vec![logger], vec![appender]);
+        let stats = Stats::new(vec![("stdout".to_owned(), AppenderStats::new(3, Some(20), 1, 4, 1))]);
+
+        let mut out = vec![];
+        dump(&mut out, 2, &topology, &stats).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "log4rs status:\n  reloads: 2\n  root: INFO -> stdout\n  logger `foo`: DEBUG \
+                    (not additive) -> \n  appender `stdout` (console): level TRACE, 3 records, 1 \
+                    errors, 4 filtered, 1 drops, 20 bytes\n");
+    }
+}
@@ -0,0 +1,179 @@
+//! A bridge from `slog` to log4rs.
+//!
+//! `Log4rsDrain` is a `slog::Drain` which logs records through the global
+//! log4rs logger, so that code still using `slog` is filtered, formatted and
+//! written by the same configuration as events from the `log` crate:
+//!
+//! ```ignore
+//! log4rs::init_file("log4rs.yml", Default::default()).unwrap();
+//! let root = slog::Logger::root(log4rs::slog::Log4rsDrain::new(), slog::o!());
+//! ```
+//!
+//! The key-value pairs of a record and of the loggers it was logged through
+//! are added to the MDC of the logging thread while the record is logged,
+//! where filters and encoders pick them up. Pairs of child loggers
+//! override those of their parents, and the record's own pairs override both.
+//!
+//! The target of a record is its tag if it has one, and its module otherwise.
+//! `Critical` records are logged at the `Error` level. Requires the
+//! `slog_bridge` feature.
+
+use log::{self, Level as LogLevel};
+use log_mdc;
+use slog_api::{self, Drain, Key, Level, Never, OwnedKVList, Record, Serializer, KV};
+use std::fmt;
+
+/// A `Drain` which logs `slog` records through log4rs.
+#[derive(Debug, Default)]
+pub struct Log4rsDrain(());
+
+impl Log4rsDrain {
+    /// Creates a new `Log4rsDrain`.
+    pub fn new() -> Log4rsDrain {
+        Log4rsDrain(())
+    }
+}
+
+#[derive(Default)]
+struct Pairs(Vec<(String, String)>);
+
+impl Serializer for Pairs {
+    fn emit_arguments(&mut self, key: Key, value: &fmt::Arguments) -> slog_api::Result {
+        let key = key.to_string();
+        // pairs are serialized from the most specific to the least
+        if !self.0.iter().any(|p| p.0 == key) {
+            self.0.push((key, value.to_string()));
+        }
+        Ok(())
+    }
+}
+
+// A record converted to a `log` record.
+struct Converted<'a> {
+    level: LogLevel,
+    target: &'a str,
+    module_path: &'static str,
+    file: &'static str,
+    line: u32,
+    fields: Vec<(String, String)>,
+}
+
+fn level(level: Level) -> LogLevel {
+    match level {
+        Level::Critical | Level::Error => LogLevel::Error,
+        Level::Warning => LogLevel::Warn,
+        Level::Info => LogLevel::Info,
+        Level::Debug => LogLevel::Debug,
+        Level::Trace => LogLevel::Trace,
+    }
+}
+
+fn convert<'a>(record: &'a Record, values: &OwnedKVList) -> Converted<'a> {
+    let mut pairs = Pairs::default();
+    let _ = record.kv().serialize(record, &mut pairs);
+    let _ = values.serialize(record, &mut pairs);
+
+    Converted {
+        level: level(record.level()),
+        target: if record.tag().is_empty() {
+            record.module()
+        } else {
+            record.tag()
+        },
+        module_path: record.module(),
+        file: record.file(),
+        line: record.line(),
+        fields: pairs.0,
+    }
+}
+
+impl Drain for Log4rsDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), Never> {
+        if !self.is_enabled(record.level()) {
+            return Ok(());
+        }
+        let converted = convert(record, values);
+
+        let previous = converted.fields
+            .iter()
+            .map(|(k, v)| (k.clone(), log_mdc::insert(k.clone(), v.clone())))
+            .collect::<Vec<_>>();
+
+        log::logger().log(&log::Record::builder()
+            .level(converted.level)
+            .target(converted.target)
+            .args(*record.msg())
+            .module_path_static(Some(converted.module_path))
+            .file_static(Some(converted.file))
+            .line(Some(converted.line))
+            .build());
+
+        for (key, value) in previous {
+            match value {
+                Some(value) => {
+                    log_mdc::insert(key, value);
+                }
+                None => {
+                    log_mdc::remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_enabled(&self, level: Level) -> bool {
+        self::level(level) <= log::max_level()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog_api::Logger;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    type Records = Arc<Mutex<Vec<(LogLevel, String, String, Vec<(String, String)>)>>>;
+
+    // records the conversions of the records
+    struct Capture(Records);
+
+    impl Drain for Capture {
+        type Ok = ();
+        type Err = Never;
+
+        fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), Never> {
+            let mut converted = convert(record, values);
+            converted.fields.sort();
+            self.0.lock().unwrap().push((converted.level,
+                                         converted.target.to_owned(),
+                                         record.msg().to_string(),
+                                         converted.fields));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn conversion() {
+        let records = Arc::new(Mutex::new(vec![]));
+        let root = Logger::root(Capture(records.clone()),
+                                slog_api::o!("service" => "billing", "user" => "alice"));
+        let child = root.new(slog_api::o!("user" => "bob", "id" => 42));
+        slog_api::crit!(child, #"audit", "denied {}", "access"; "user" => "carol");
+        slog_api::info!(child, "done");
+
+        let pair = |k: &str, v: &str| (k.to_owned(), v.to_owned());
+        assert_eq!(*records.lock().unwrap(),
+                   vec![(LogLevel::Error,
+                         "audit".to_owned(),
+                         "denied access".to_owned(),
+                         vec![pair("id", "42"), pair("service", "billing"), pair("user", "carol")]),
+                        (LogLevel::Info,
+                         module_path!().to_owned(),
+                         "done".to_owned(),
+                         vec![pair("id", "42"), pair("service", "billing"), pair("user", "bob")])]);
+    }
+}
@@ -0,0 +1,261 @@
+//! Appender statistics.
+//!
+//! Counters are tracked for every appender in the active configuration and
+//! can be retrieved through `Handle::stats`. They are reset when a new
+//! configuration is installed.
+//!
+//! Process wide counts broken down by level are retrieved through
+//! `log4rs::metrics`. They are never reset, so they can be compared over time
+//! to detect when the delivery of events degrades.
+//!
+//! When enabled with `Handle::track_volume`, rolling counts of the events
+//! written to each target are kept, and `Handle::top_targets` retrieves the
+//! targets logging the most.
+
+use log::Level;
+
+use {StatsPrivateExt, AppenderStatsPrivateExt, MetricsPrivateExt, AppenderMetricsPrivateExt,
+     LevelCountsPrivateExt, TargetVolumePrivateExt};
+
+/// A snapshot of the statistics of every configured appender.
+#[derive(Debug, Clone)]
+pub struct Stats {
+    appenders: Vec<(String, AppenderStats)>,
+}
+
+impl Stats {
+    /// Returns the statistics of each appender, paired with its name.
+    pub fn appenders(&self) -> &[(String, AppenderStats)] {
+        &self.appenders
+    }
+
+    /// Returns the statistics of the appender with the specified name.
+    pub fn appender(&self, name: &str) -> Option<&AppenderStats> {
+        self.appenders.iter().find(|a| a.0 == name).map(|a| &a.1)
+    }
+}
+
+impl StatsPrivateExt for Stats {
+    fn new(appenders: Vec<(String, AppenderStats)>) -> Stats {
+        Stats { appenders: appenders }
+    }
+}
+
+/// A snapshot of the statistics of a single appender.
+#[derive(Debug, Clone, Default)]
+pub struct AppenderStats {
+    records: u64,
+    bytes: Option<u64>,
+    errors: u64,
+    filtered: u64,
+    drops: u64,
+}
+
+impl AppenderStats {
+    /// Returns the number of log events successfully appended.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Returns the number of bytes written by the appender, if it reports
+    /// them.
+    ///
+    /// See `Append::bytes_written`.
+    pub fn bytes(&self) -> Option<u64> {
+        self.bytes
+    }
+
+    /// Returns the number of log events the appender failed to append.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Returns the number of log events discarded by the appender's level
+    /// threshold or filters.
+    pub fn filtered(&self) -> u64 {
+        self.filtered
+    }
+
+    /// Returns the number of log events which were lost, either because the
+    /// appender failed to append them or because its queue was full.
+    ///
+    /// Failed events are also counted by `errors`.
+    pub fn drops(&self) -> u64 {
+        self.drops
+    }
+}
+
+impl AppenderStatsPrivateExt for AppenderStats {
+    fn new(records: u64,
+           bytes: Option<u64>,
+           errors: u64,
+           filtered: u64,
+           drops: u64)
+           -> AppenderStats {
+        AppenderStats {
+            records: records,
+            bytes: bytes,
+            errors: errors,
+            filtered: filtered,
+            drops: drops,
+        }
+    }
+}
+
+/// A snapshot of the process wide counts of log events.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    submitted: LevelCounts,
+    filtered: LevelCounts,
+    appenders: Vec<(String, AppenderMetrics)>,
+}
+
+impl Metrics {
+    /// Returns the number of events submitted to the logger.
+    ///
+    /// Events which the `log` crate discards because of its maximum level
+    /// never reach the logger and aren't counted.
+    pub fn submitted(&self) -> &LevelCounts {
+        &self.submitted
+    }
+
+    /// Returns the number of submitted events discarded because their levels
+    /// exceed those of the loggers they were logged to.
+    pub fn filtered(&self) -> &LevelCounts {
+        &self.filtered
+    }
+
+    /// Returns the counts of each appender which has been configured, paired
+    /// with its name and sorted by name.
+    ///
+    /// An appender's counts accumulate across configurations using its name,
+    /// and are kept after it is removed.
+    pub fn appenders(&self) -> &[(String, AppenderMetrics)] {
+        &self.appenders
+    }
+
+    /// Returns the counts of the appender with the specified name.
+    pub fn appender(&self, name: &str) -> Option<&AppenderMetrics> {
+        self.appenders.iter().find(|a| a.0 == name).map(|a| &a.1)
+    }
+}
+
+impl MetricsPrivateExt for Metrics {
+    fn new(submitted: LevelCounts,
+           filtered: LevelCounts,
+           appenders: Vec<(String, AppenderMetrics)>)
+           -> Metrics {
+        Metrics {
+            submitted: submitted,
+            filtered: filtered,
+            appenders: appenders,
+        }
+    }
+}
+
+/// The process wide counts of log events handled by an appender.
+#[derive(Debug, Clone, Default)]
+pub struct AppenderMetrics {
+    written: LevelCounts,
+    filtered: LevelCounts,
+    dropped: LevelCounts,
+    errored: LevelCounts,
+}
+
+impl AppenderMetrics {
+    /// Returns the number of events successfully appended.
+    pub fn written(&self) -> &LevelCounts {
+        &self.written
+    }
+
+    /// Returns the number of events discarded by the appender's level
+    /// threshold or filters.
+    pub fn filtered(&self) -> &LevelCounts {
+        &self.filtered
+    }
+
+    /// Returns the number of events which were lost, either because the
+    /// appender failed to append them or because its queue was full.
+    ///
+    /// Failed events are also counted by `errored`.
+    pub fn dropped(&self) -> &LevelCounts {
+        &self.dropped
+    }
+
+    /// Returns the number of events the appender failed to append.
+    pub fn errored(&self) -> &LevelCounts {
+        &self.errored
+    }
+}
+
+impl AppenderMetricsPrivateExt for AppenderMetrics {
+    fn new(written: LevelCounts,
+           filtered: LevelCounts,
+           dropped: LevelCounts,
+           errored: LevelCounts)
+           -> AppenderMetrics {
+        AppenderMetrics {
+            written: written,
+            filtered: filtered,
+            dropped: dropped,
+            errored: errored,
+        }
+    }
+}
+
+/// Counts of log events by level.
+#[derive(Debug, Clone, Default)]
+pub struct LevelCounts([u64; 5]);
+
+impl LevelCounts {
+    /// Returns the count for the specified level.
+    pub fn get(&self, level: Level) -> u64 {
+        self.0[level as usize - 1]
+    }
+
+    /// Returns the count across all levels.
+    pub fn total(&self) -> u64 {
+        self.0.iter().sum()
+    }
+}
+
+impl LevelCountsPrivateExt for LevelCounts {
+    fn new(counts: [u64; 5]) -> LevelCounts {
+        LevelCounts(counts)
+    }
+}
+
+/// The number of events written to a target at a level over a window.
+#[derive(Debug, Clone)]
+pub struct TargetVolume {
+    target: String,
+    level: Level,
+    count: u64,
+}
+
+impl TargetVolume {
+    /// Returns the target.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the level.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Returns the number of events.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl TargetVolumePrivateExt for TargetVolume {
+    fn new(target: String, level: Level, count: u64) -> TargetVolume {
+        TargetVolume {
+            target: target,
+            level: level,
+            count: count,
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! A view of the active logger configuration.
+//!
+//! A `Topology` can be retrieved through `Handle::topology`, for example to
+//! display how events are routed in an admin interface. Like `Stats`, it is a
+//! snapshot which doesn't change when the logger is reconfigured.
+
+use log::LevelFilter;
+
+use {TopologyPrivateExt, LoggerInfoPrivateExt, AppenderInfoPrivateExt};
+
+/// A snapshot of the loggers and appenders of the active configuration.
+#[derive(Debug, Clone)]
+pub struct Topology {
+    root: LoggerInfo,
+    loggers: Vec<LoggerInfo>,
+    appenders: Vec<AppenderInfo>,
+}
+
+impl Topology {
+    /// Returns the root logger.
+    ///
+    /// Its name is empty.
+    pub fn root(&self) -> &LoggerInfo {
+        &self.root
+    }
+
+    /// Returns the loggers other than the root, sorted by name.
+    pub fn loggers(&self) -> &[LoggerInfo] {
+        &self.loggers
+    }
+
+    /// Returns the appenders, sorted by name.
+    pub fn appenders(&self) -> &[AppenderInfo] {
+        &self.appenders
+    }
+
+    /// Returns the appender with the specified name.
+    pub fn appender(&self, name: &str) -> Option<&AppenderInfo> {
+        self.appenders.iter().find(|a| a.name == name)
+    }
+}
+
+impl TopologyPrivateExt for Topology {
+    fn new(root: LoggerInfo, mut loggers: Vec<LoggerInfo>, mut appenders: Vec<AppenderInfo>)
+           -> Topology {
+        loggers.sort_by(|a, b| a.name.cmp(&b.name));
+        appenders.sort_by(|a, b| a.name.cmp(&b.name));
+        Topology {
+            root: root,
+            loggers: loggers,
+            appenders: appenders,
+        }
+    }
+}
+
+/// A description of a configured logger.
+#[derive(Debug, Clone)]
+pub struct LoggerInfo {
+    name: String,
+    level: LevelFilter,
+    configured_level: LevelFilter,
+    additive: bool,
+    appenders: Vec<String>,
+}
+
+impl LoggerInfo {
+    /// Returns the name of the logger.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the maximum level of events the logger accepts.
+    ///
+    /// This is the configured level unless it is overridden by
+    /// `Handle::set_level`.
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    /// Returns the level the logger was configured with.
+    pub fn configured_level(&self) -> LevelFilter {
+        self.configured_level
+    }
+
+    /// Returns whether the logger also sends events to the appenders of its
+    /// parent.
+    ///
+    /// This is `false` for the root logger.
+    pub fn additive(&self) -> bool {
+        self.additive
+    }
+
+    /// Returns the names of the appenders attached directly to the logger.
+    pub fn appenders(&self) -> &[String] {
+        &self.appenders
+    }
+}
+
+impl LoggerInfoPrivateExt for LoggerInfo {
+    fn new(name: String,
+           level: LevelFilter,
+           configured_level: LevelFilter,
+           additive: bool,
+           appenders: Vec<String>)
+           -> LoggerInfo {
+        LoggerInfo {
+            name: name,
+            level: level,
+            configured_level: configured_level,
+            additive: additive,
+            appenders: appenders,
+        }
+    }
+}
+
+/// A description of a configured appender.
+///
+/// Encoders, filters and the appender itself are described by their `Debug`
+/// representations.
+#[derive(Debug, Clone)]
+pub struct AppenderInfo {
+    name: String,
+    kind: Option<String>,
+    level: LevelFilter,
+    encoder: Option<String>,
+    filters: Vec<String>,
+    description: String,
+}
+
+impl AppenderInfo {
+    /// Returns the name of the appender.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the kind of the appender, if known.
+    ///
+    /// See `AppenderBuilder::kind`.
+    pub fn kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
+
+    /// Returns the maximum level of events the appender accepts.
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    /// Returns a description of the appender's encoder, if it reports one.
+    ///
+    /// See `Append::encoder`.
+    pub fn encoder(&self) -> Option<&str> {
+        self.encoder.as_deref()
+    }
+
+    /// Returns descriptions of the filters attached to the appender.
+    pub fn filters(&self) -> &[String] {
+        &self.filters
+    }
+
+    /// Returns a description of the appender.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl AppenderInfoPrivateExt for AppenderInfo {
+    fn new(name: String,
+           kind: Option<String>,
+           level: LevelFilter,
+           encoder: Option<String>,
+           filters: Vec<String>,
+           description: String)
+           -> AppenderInfo {
+        AppenderInfo {
+            name: name,
+            kind: kind,
+            level: level,
+            encoder: encoder,
+            filters: filters,
+            description: description,
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! A bridge from `tracing` to log4rs.
+//!
+//! `Log4rsLayer` is a `tracing_subscriber::Layer` which logs `tracing`
+//! events through the global log4rs logger, so that they are filtered,
+//! formatted and written by the same configuration as events from the `log`
+//! crate:
+//!
+//! ```ignore
+//! log4rs::init_file("log4rs.yml", Default::default()).unwrap();
+//! tracing::subscriber::set_global_default(log4rs::tracing::subscriber()).unwrap();
+//! ```
+//!
+//! The fields of an event and of the spans it is in are added to the MDC of
+//! the logging thread while the event is logged, where filters and encoders
+//! pick them up as key-value pairs. Fields of inner spans
+//! override those of outer spans, and the event's own fields override both.
+//! The names of the spans, outermost first and separated by `:`, are added
+//! under the `spans` key. An event's `message` field becomes the message of
+//! the record.
+//!
+//! Spans themselves aren't logged. Requires the `tracing_bridge` feature.
+
+use log::{self, Level as LogLevel};
+use log_mdc;
+use std::fmt;
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, Layered, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+
+/// A `Layer` which logs `tracing` events through log4rs.
+#[derive(Debug, Default)]
+pub struct Log4rsLayer(());
+
+impl Log4rsLayer {
+    /// Creates a new `Log4rsLayer`.
+    pub fn new() -> Log4rsLayer {
+        Log4rsLayer(())
+    }
+}
+
+/// Returns a `Subscriber` which logs `tracing` events through log4rs.
+pub fn subscriber() -> Layered<Log4rsLayer, Registry> {
+    Registry::default().with(Log4rsLayer::new())
+}
+
+// the recorded fields of a span
+struct SpanFields(Vec<(String, String)>);
+
+#[derive(Default)]
+struct Visitor {
+    message: Option<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl Visitor {
+    fn insert(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = Some(value);
+            return;
+        }
+        match self.fields.iter_mut().find(|f| f.0 == field.name()) {
+            Some(f) => f.1 = value,
+            None => self.fields.push((field.name().to_owned(), value)),
+        }
+    }
+}
+
+impl Visit for Visitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &fmt::Debug) {
+        self.insert(field, format!("{:?}", value));
+    }
+}
+
+// An event converted to a record.
+struct Converted {
+    level: LogLevel,
+    target: &'static str,
+    module_path: Option<&'static str>,
+    file: Option<&'static str>,
+    line: Option<u32>,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+fn level(level: Level) -> LogLevel {
+    match level {
+        Level::ERROR => LogLevel::Error,
+        Level::WARN => LogLevel::Warn,
+        Level::INFO => LogLevel::Info,
+        Level::DEBUG => LogLevel::Debug,
+        Level::TRACE => LogLevel::Trace,
+    }
+}
+
+fn enabled(metadata: &Metadata) -> bool {
+    level(*metadata.level()) <= log::max_level()
+}
+
+fn convert<S>(event: &Event, ctx: &Context<S>) -> Converted
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    let mut fields = vec![];
+    let mut spans = String::new();
+    if let Some(scope) = ctx.event_scope(event) {
+        for span in scope.from_root() {
+            if !spans.is_empty() {
+                spans.push(':');
+            }
+            spans.push_str(span.name());
+            if let Some(span_fields) = span.extensions().get::<SpanFields>() {
+                fields.extend(span_fields.0.iter().cloned());
+            }
+        }
+    }
+    if !spans.is_empty() {
+        fields.push(("spans".to_owned(), spans));
+    }
+
+    let mut visitor = Visitor::default();
+    event.record(&mut visitor);
+    fields.extend(visitor.fields);
+
+    let metadata = event.metadata();
+    Converted {
+        level: level(*metadata.level()),
+        target: metadata.target(),
+        module_path: metadata.module_path(),
+        file: metadata.file(),
+        line: metadata.line(),
+        message: visitor.message.unwrap_or_default(),
+        fields: fields,
+    }
+}
+
+impl<S> Layer<S> for Log4rsLayer
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    fn enabled(&self, metadata: &Metadata, _: Context<S>) -> bool {
+        // spans are needed for the fields of the events inside them
+        metadata.is_span() || enabled(metadata)
+    }
+
+    fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        let mut visitor = Visitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut extensions = span.extensions_mut();
+        let mut visitor = Visitor::default();
+        if let Some(fields) = extensions.remove::<SpanFields>() {
+            visitor.fields = fields.0;
+        }
+        values.record(&mut visitor);
+        extensions.insert(SpanFields(visitor.fields));
+    }
+
+    fn on_event(&self, event: &Event, ctx: Context<S>) {
+        if !enabled(event.metadata()) {
+            return;
+        }
+        let converted = convert(event, &ctx);
+
+        let previous = converted.fields
+            .iter()
+            .map(|(k, v)| (k.clone(), log_mdc::insert(k.clone(), v.clone())))
+            .collect::<Vec<_>>();
+
+        log::logger().log(&log::Record::builder()
+            .level(converted.level)
+            .target(converted.target)
+            .args(format_args!("{}", converted.message))
+            .module_path_static(converted.module_path)
+            .file_static(converted.file)
+            .line(converted.line)
+            .build());
+
+        // restored in reverse, so that keys inserted twice get their
+        // original values back
+        for (key, value) in previous.into_iter().rev() {
+            match value {
+                Some(value) => {
+                    log_mdc::insert(key, value);
+                }
+                None => {
+                    log_mdc::remove(&key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use tracing_macros;
+
+    use super::*;
+
+    type Events = Arc<Mutex<Vec<(LogLevel, String, Vec<(String, String)>)>>>;
+
+    // records the conversions of the events
+    struct Capture(Log4rsLayer, Events);
+
+    impl<S> Layer<S> for Capture
+        where S: Subscriber + for<'a> LookupSpan<'a>
+    {
+        fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+            self.0.on_new_span(attrs, id, ctx)
+        }
+
+        fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
+            self.0.on_record(id, values, ctx)
+        }
+
+        fn on_event(&self, event: &Event, ctx: Context<S>) {
+            let converted = convert(event, &ctx);
+            let mut events = self.1.lock().unwrap();
+            events.push((converted.level, converted.message, converted.fields));
+        }
+    }
+
+    #[test]
+    fn conversion() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let subscriber = Registry::default().with(Capture(Log4rsLayer::new(), events.clone()));
+        tracing_macros::subscriber::with_default(subscriber, || {
+            let span = tracing_macros::info_span!("request", id = 42, user = "alice");
+            let _entered = span.enter();
+            let span = tracing_macros::info_span!("query", user = "bob");
+            let _entered = span.enter();
+            tracing_macros::warn!(rows = 7, "slow {}", "query");
+        });
+
+        let fields = vec![("id".to_owned(), "42".to_owned()),
+                          ("user".to_owned(), "alice".to_owned()),
+                          ("user".to_owned(), "bob".to_owned()),
+                          ("spans".to_owned(), "request:query".to_owned()),
+                          ("rows".to_owned(), "7".to_owned())];
+        assert_eq!(*events.lock().unwrap(),
+                   vec![(LogLevel::Warn, "slow query".to_owned(), fields)]);
+    }
+}